@@ -0,0 +1,100 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Called from the build script of a binary. The metadata is passed to the compiler as environment
+// variables, read by `build_info!`. Release pipelines set `E4K_BUILD_SIGNING_KEY_PATH` to the PEM
+// release key, the metadata is then signed with it.
+
+use std::{
+    env, fs,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use openssl::pkey::PKey;
+
+use crate::{BuildInfo, UNKNOWN};
+
+const SIGNING_KEY_PATH_ENV_VAR: &str = "E4K_BUILD_SIGNING_KEY_PATH";
+
+pub fn emit() {
+    let git_sha = command_output("git", &["rev-parse", "HEAD"]);
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = command_output(&rustc, &["--version"]);
+
+    // Reproducible builds set the build time themselves.
+    let build_time = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|time| time.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs())
+        });
+
+    // Features enabled on the binary, as cargo passes them to its build script.
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| {
+            key.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+
+    println!(
+        "cargo:rustc-env=E4K_BUILD_GIT_SHA={}",
+        git_sha.as_deref().unwrap_or(UNKNOWN)
+    );
+    println!("cargo:rustc-env=E4K_BUILD_TIME={}", build_time);
+    println!(
+        "cargo:rustc-env=E4K_BUILD_RUSTC_VERSION={}",
+        rustc_version.as_deref().unwrap_or(UNKNOWN)
+    );
+    println!("cargo:rustc-env=E4K_BUILD_FEATURES={}", features.join(","));
+
+    // Signed as `build_info!` reads it back. A release build fails rather than ship unsigned metadata.
+    if let Ok(key_path) = env::var(SIGNING_KEY_PATH_ENV_VAR) {
+        let build_info = BuildInfo::from_env(
+            &env::var("CARGO_PKG_VERSION").unwrap_or_default(),
+            git_sha.as_deref(),
+            Some(&build_time.to_string()),
+            rustc_version.as_deref(),
+            Some(&features.join(",")),
+            None,
+        );
+        let signature = sign(&build_info, &key_path).unwrap_or_else(|err| {
+            panic!("Cannot sign the build metadata with {}: {}", key_path, err)
+        });
+
+        println!("cargo:rustc-env=E4K_BUILD_SIGNATURE={}", signature);
+        println!("cargo:rerun-if-changed={}", key_path);
+    }
+
+    // Run again on a new commit or signing key, other changes do not affect the metadata.
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    println!("cargo:rerun-if-env-changed={}", SIGNING_KEY_PATH_ENV_VAR);
+    if let Some(head) = command_output("git", &["rev-parse", "--git-path", "HEAD"]) {
+        println!("cargo:rerun-if-changed={}", head);
+    }
+    if let Some(reference) = command_output("git", &["symbolic-ref", "-q", "HEAD"]) {
+        if let Some(path) = command_output("git", &["rev-parse", "--git-path", &reference]) {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+}
+
+fn sign(build_info: &BuildInfo, key_path: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let key = PKey::private_key_from_pem(&fs::read(key_path)?)?;
+
+    Ok(build_info.sign(&key)?)
+}
+
+// Builds from a source archive have no git repository, the metadata is then unknown.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let output = String::from_utf8(output.stdout).ok()?;
+    Some(output.trim().to_string())
+}
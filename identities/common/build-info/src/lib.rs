@@ -0,0 +1,219 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::default_trait_access,
+    clippy::let_unit_value,
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+// Build metadata embedded in the binaries. The build script of a binary calls `build_script::emit`
+// to record the metadata at compile time, `build_info!` reads it back in the binary. Crates built
+// without the build script get "unknown" values. Release builds sign the metadata with the release
+// key, so the server can check it before trusting it.
+
+pub mod build_script;
+
+use std::fmt;
+
+use openssl::{
+    error::ErrorStack,
+    hash::MessageDigest,
+    pkey::{HasPublic, PKey, Private},
+    sign::{Signer, Verifier},
+};
+
+pub const UNKNOWN: &str = "unknown";
+
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct BuildInfo {
+    pub version: String,
+    pub git_sha: String,
+    // Seconds since epoch.
+    pub build_time: u64,
+    pub rustc_version: String,
+    pub features: Vec<String>,
+    // Base64 signature of the other fields by the release key. Not set for unsigned builds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl BuildInfo {
+    // Only meant to be called by `build_info!`, with the variables set by the build script.
+    #[doc(hidden)]
+    #[must_use]
+    pub fn from_env(
+        version: &str,
+        git_sha: Option<&str>,
+        build_time: Option<&str>,
+        rustc_version: Option<&str>,
+        features: Option<&str>,
+        signature: Option<&str>,
+    ) -> Self {
+        BuildInfo {
+            version: version.to_string(),
+            git_sha: git_sha.unwrap_or(UNKNOWN).to_string(),
+            build_time: build_time.and_then(|time| time.parse().ok()).unwrap_or(0),
+            rustc_version: rustc_version.unwrap_or(UNKNOWN).to_string(),
+            features: features
+                .unwrap_or_default()
+                .split(',')
+                .filter(|feature| !feature.is_empty())
+                .map(ToString::to_string)
+                .collect(),
+            signature: signature
+                .filter(|signature| !signature.is_empty())
+                .map(ToString::to_string),
+        }
+    }
+
+    // Signature of the metadata with an ECDSA or RSA key, with SHA-256.
+    pub fn sign(&self, key: &PKey<Private>) -> Result<String, ErrorStack> {
+        let mut signer = Signer::new(MessageDigest::sha256(), key)?;
+        signer.update(self.signed_data().as_bytes())?;
+
+        Ok(base64::encode(signer.sign_to_vec()?))
+    }
+
+    // Whether the metadata is signed by the private key of `key`. Unsigned metadata is never verified.
+    #[must_use]
+    pub fn verify<T: HasPublic>(&self, key: &PKey<T>) -> bool {
+        let signature = match self
+            .signature
+            .as_ref()
+            .and_then(|signature| base64::decode(signature).ok())
+        {
+            Some(signature) => signature,
+            None => return false,
+        };
+
+        Verifier::new(MessageDigest::sha256(), key)
+            .and_then(|mut verifier| {
+                verifier.update(self.signed_data().as_bytes())?;
+                verifier.verify(&signature)
+            })
+            .unwrap_or(false)
+    }
+
+    // Every field but the signature, one per line.
+    fn signed_data(&self) -> String {
+        format!(
+            "{}\n{}\n{}\n{}\n{}",
+            self.version,
+            self.git_sha,
+            self.build_time,
+            self.rustc_version,
+            self.features.join(",")
+        )
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "version={} git_sha={} build_time={} rustc_version=\"{}\" features=[{}]",
+            self.version,
+            self.git_sha,
+            self.build_time,
+            self.rustc_version,
+            self.features.join(",")
+        )
+    }
+}
+
+#[macro_export]
+macro_rules! build_info {
+    () => {
+        $crate::BuildInfo::from_env(
+            env!("CARGO_PKG_VERSION"),
+            option_env!("E4K_BUILD_GIT_SHA"),
+            option_env!("E4K_BUILD_TIME"),
+            option_env!("E4K_BUILD_RUSTC_VERSION"),
+            option_env!("E4K_BUILD_FEATURES"),
+            option_env!("E4K_BUILD_SIGNATURE"),
+        )
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::{
+        ec::{EcGroup, EcKey},
+        nid::Nid,
+    };
+
+    use super::*;
+
+    #[test]
+    fn from_env_happy_path() {
+        let build_info = BuildInfo::from_env(
+            "0.1.0",
+            Some("0123abcd"),
+            Some("1650000000"),
+            Some("rustc 1.59.0 (9d1b2106e 2022-02-23)"),
+            Some("chaos,tests"),
+            Some("c2lnbmF0dXJl"),
+        );
+
+        assert_eq!(
+            build_info,
+            BuildInfo {
+                version: "0.1.0".to_string(),
+                git_sha: "0123abcd".to_string(),
+                build_time: 1_650_000_000,
+                rustc_version: "rustc 1.59.0 (9d1b2106e 2022-02-23)".to_string(),
+                features: vec!["chaos".to_string(), "tests".to_string()],
+                signature: Some("c2lnbmF0dXJl".to_string()),
+            }
+        );
+        assert_eq!(
+            build_info.to_string(),
+            "version=0.1.0 git_sha=0123abcd build_time=1650000000 \
+            rustc_version=\"rustc 1.59.0 (9d1b2106e 2022-02-23)\" features=[chaos,tests]"
+        );
+    }
+
+    #[test]
+    fn from_env_without_build_script() {
+        let build_info = build_info!();
+
+        assert_eq!(build_info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(build_info.git_sha, UNKNOWN);
+        assert!(build_info.features.is_empty());
+        assert!(build_info.signature.is_none());
+    }
+
+    fn ec_key() -> PKey<Private> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn sign_verify() {
+        let key = ec_key();
+        let public_key = PKey::public_key_from_pem(&key.public_key_to_pem().unwrap()).unwrap();
+
+        let mut build_info = BuildInfo::from_env("0.1.0", Some("0123abcd"), None, None, None, None);
+        assert!(!build_info.verify(&public_key));
+
+        build_info.signature = Some(build_info.sign(&key).unwrap());
+        assert!(build_info.verify(&public_key));
+        assert!(!build_info.verify(&ec_key()));
+
+        // The signature of a release does not verify another commit or other features.
+        let mut modified = build_info.clone();
+        modified.git_sha = "4567ef01".to_string();
+        assert!(!modified.verify(&public_key));
+        let mut modified = build_info.clone();
+        modified.features.push("chaos".to_string());
+        assert!(!modified.verify(&public_key));
+
+        build_info.signature = Some("not base64".to_string());
+        assert!(!build_info.verify(&public_key));
+    }
+}
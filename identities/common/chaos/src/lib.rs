@@ -0,0 +1,144 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::default_trait_access,
+    clippy::let_unit_value,
+    clippy::missing_errors_doc,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+// Fault injection for resilience tests. Components built with their `chaos` feature wrap their
+// dependencies with a fault layer, the faults are then changed at runtime: through the admin API
+// for the server, through the config file for the agent. Faults are deterministic so tests can
+// predict which call fails.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use parking_lot::RwLock;
+
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct FaultConfig {
+    // Delay added before every call.
+    #[serde(default)]
+    pub latency_ms: u64,
+    // Every n-th call fails, 0 to never fail.
+    #[serde(default)]
+    pub fail_every: u64,
+    // Batch calls fail for every other item instead of failing as a whole.
+    #[serde(default)]
+    pub partial_failure: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Fault injected in {0}")]
+pub struct InjectedFault(pub &'static str);
+
+pub struct Faults {
+    component: &'static str,
+    config: RwLock<FaultConfig>,
+    calls: AtomicU64,
+}
+
+impl Faults {
+    #[must_use]
+    pub fn new(component: &'static str) -> Self {
+        Faults {
+            component,
+            config: RwLock::new(FaultConfig::default()),
+            calls: AtomicU64::new(0),
+        }
+    }
+
+    // Replace the faults. The call count restarts so the n-th call after the change is the first to fail.
+    pub fn set(&self, config: FaultConfig) {
+        *self.config.write() = config;
+        self.calls.store(0, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn get(&self) -> FaultConfig {
+        self.config.read().clone()
+    }
+
+    // Called before forwarding a call: waits for the configured latency and fails every n-th call.
+    pub async fn inject(&self) -> Result<(), InjectedFault> {
+        let config = self.get();
+
+        if config.latency_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(config.latency_ms)).await;
+        }
+
+        let call = self.calls.fetch_add(1, Ordering::Relaxed) + 1;
+        if config.fail_every != 0 && call % config.fail_every == 0 {
+            return Err(self.fault());
+        }
+
+        Ok(())
+    }
+
+    // Whether the item at `index` of a batch call fails.
+    #[must_use]
+    pub fn fail_item(&self, index: usize) -> bool {
+        self.config.read().partial_failure && index % 2 == 1
+    }
+
+    #[must_use]
+    pub fn fault(&self) -> InjectedFault {
+        InjectedFault(self.component)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn inject_fails_every_nth_call() {
+        let faults = Faults::new("test");
+        faults.inject().await.unwrap();
+
+        faults.set(FaultConfig {
+            fail_every: 2,
+            ..Default::default()
+        });
+        faults.inject().await.unwrap();
+        faults.inject().await.unwrap_err();
+        faults.inject().await.unwrap();
+        faults.inject().await.unwrap_err();
+
+        faults.set(FaultConfig::default());
+        faults.inject().await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn inject_latency() {
+        let faults = Faults::new("test");
+        faults.set(FaultConfig {
+            latency_ms: 1000,
+            ..Default::default()
+        });
+
+        let start = tokio::time::Instant::now();
+        faults.inject().await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn fail_item_partial_failure() {
+        let faults = Faults::new("test");
+        assert!(!faults.fail_item(1));
+
+        faults.set(FaultConfig {
+            partial_failure: true,
+            ..Default::default()
+        });
+        let failed = (0..4).filter(|index| faults.fail_item(*index)).count();
+        assert_eq!(failed, 2);
+    }
+}
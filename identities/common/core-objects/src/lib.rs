@@ -10,11 +10,22 @@
     clippy::too_many_lines
 )]
 
-use std::{fmt::Display, time::SystemTime};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    time::SystemTime,
+};
 
 use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+pub mod selector;
+pub mod spiffe_id;
+
+pub use selector::{NodeSelector, WorkloadSelector};
+pub use spiffe_id::SpiffeId;
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
 pub struct RegistrationEntry {
     pub id: String,
     pub other_identities: Vec<IdentityTypes>,
@@ -25,6 +36,22 @@ pub struct RegistrationEntry {
     pub dns_names: Vec<String>,
     pub revision_number: u64,
     pub store_svid: bool,
+    // Agents fetch the SVIDs of these entries for the workloads on their node when they start, so
+    // the first request of a workload does not wait for attestation and issuance.
+    #[serde(default)]
+    pub prefetch: bool,
+    // Lifetime of the JWT-SVIDs of this entry in seconds, capped by the server. The server default when
+    // not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u64>,
+    // Extra claims of the JWT-SVIDs of this entry, e.g. the deployment ring. Entries are only issued when the
+    // server allows all their claims.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom_claims: BTreeMap<String, String>,
+    // Foreign trust domains whose bundles the workloads of this entry get from their agent, as in SPIRE. The
+    // agents only have the bundles of the trust domains the server federates with.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub federates_with: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -47,7 +74,18 @@ pub struct EntryNodeAttestation {
     pub plugin: NodeAttestationPlugin,
 }
 
-#[derive(Debug, Clone, Hash, Serialize, strum_macros::Display)]
+// The string forms of the selector types are parsed back with `FromStr`.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    strum_macros::Display,
+    strum_macros::EnumIter,
+    strum_macros::EnumString,
+)]
 #[strum(serialize_all = "UPPERCASE")]
 pub enum WorkloadSelectorType {
     Namespace,
@@ -56,6 +94,8 @@ pub enum WorkloadSelectorType {
     PodUID,
     NodeName,
     PodLabels,
+    // Only the annotations allowed by the agent configuration.
+    PodAnnotations,
     ContainerName,
     ContainerImage,
     ContainerImageId,
@@ -65,9 +105,26 @@ pub enum WorkloadSelectorType {
     PodImageCount,
     PodInitImages,
     PodInitImageCount,
+    // Process calling the workload API, set by the unix workload attestation.
+    UnixUID,
+    UnixGID,
+    UnixPath,
+    UnixSHA256,
+    // Unit of the process calling the workload API, set by the systemd workload attestation.
+    SystemdUnit,
+    SystemdSlice,
 }
 
-#[derive(Debug, Clone, strum_macros::Display)]
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    strum_macros::Display,
+    strum_macros::EnumIter,
+    strum_macros::EnumString,
+)]
 #[strum(serialize_all = "UPPERCASE")]
 pub enum NodeSelectorType {
     Cluster,
@@ -80,6 +137,21 @@ pub enum NodeSelectorType {
     AgentNodeUID,
     AgentNodeLabels,
     AgentPodLabels,
+    // Reported by the agent, only added when the server is configured for it.
+    AgentBuildVersion,
+    AgentBuildGitSHA,
+    AgentBuildFeature,
+    // Registration of the device of the agent, set by the DPS node attestation.
+    IoTHubName,
+    IoTHubDeviceId,
+    DpsRegistrationId,
+    DpsEnrollmentType,
+    // Certificate of the agent, set by the X.509 proof of possession node attestation.
+    X509PopSubjectCN,
+    X509PopSubject,
+    X509PopSanDNS,
+    X509PopSanURI,
+    X509PopFingerprint,
 }
 
 pub fn build_selector_string<A: ToString, B: Display>(selector: &A, value: B) -> String {
@@ -91,6 +163,8 @@ pub fn build_selector_string<A: ToString, B: Display>(selector: &A, value: B) ->
 pub enum NodeAttestationPlugin {
     Psat,
     Sat,
+    Dps,
+    X509Pop,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -98,6 +172,8 @@ pub enum NodeAttestationPlugin {
 pub enum WorkloadAttestationPlugin {
     K8s,
     Docker,
+    Unix,
+    Systemd,
 }
 
 #[derive(PartialEq, Debug, serde::Deserialize, serde::Serialize, Clone)]
@@ -114,20 +190,76 @@ pub struct JWTSVID {
     pub signature: String,
 }
 
+// Serialized with the header parameter names of RFC 7515, so standard JWT libraries can read the JWT-SVIDs.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct JWTHeader {
+    #[serde(rename = "alg")]
     pub algorithm: KeyType,
+    #[serde(rename = "kid")]
     pub key_id: String,
+    #[serde(rename = "typ")]
     pub jwt_type: JWTType,
 }
 
+// Serialized with the registered claim names of RFC 7519.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct JWTClaims {
+    #[serde(rename = "sub")]
     pub subject: String,
+    #[serde(rename = "aud", deserialize_with = "deserialize_audience")]
     pub audience: Vec<String>,
+    #[serde(rename = "exp")]
     pub expiry: u64,
+    #[serde(rename = "iat")]
     pub issued_at: u64,
+    // Unique id of the token, so the services it is presented to can reject replays.
+    #[serde(rename = "jti", default, skip_serializing_if = "Option::is_none")]
+    pub jwt_id: Option<String>,
+    #[serde(rename = "nbf", default, skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<u64>,
+    // Issuer URL, for the services validating the JWT-SVIDs with OIDC discovery.
+    #[serde(rename = "iss", default, skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    // Private claim, missing from the tokens of other issuers.
+    #[serde(default)]
     pub other_identities: Vec<IdentityTypes>,
+    // Private claim, UID of the pod the SVID was issued to when the agent pins identities to pods.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pod_uid: Option<String>,
+    // The custom claims of the entry, and the other claims of tokens from other issuers.
+    #[serde(flatten)]
+    pub custom_claims: BTreeMap<String, serde_json::Value>,
+}
+
+// Claims set by the server, entries cannot use them as custom claims.
+pub const RESERVED_CLAIMS: &[&str] = &[
+    "sub",
+    "aud",
+    "exp",
+    "iat",
+    "jti",
+    "nbf",
+    "iss",
+    "other_identities",
+    "pod_uid",
+];
+
+// The audience of a token with a single audience may be a string instead of an array (RFC 7519 section 4.1.3).
+fn deserialize_audience<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Audience {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match Audience::deserialize(deserializer)? {
+        Audience::One(audience) => vec![audience],
+        Audience::Many(audiences) => audiences,
+    })
 }
 
 #[derive(PartialEq, Debug, serde::Deserialize, serde::Serialize, Clone)]
@@ -154,15 +286,38 @@ pub enum KeyType {
     PS256,
     PS384,
     PS512,
+    // Ed25519, the only EdDSA curve supported.
+    EdDSA,
 }
 
-impl From<KeyType> for (Kty, Crv) {
-    fn from(key_type: KeyType) -> (Kty, Crv) {
+// RSA keys have no curve.
+impl From<KeyType> for (Kty, Option<Crv>) {
+    fn from(key_type: KeyType) -> (Kty, Option<Crv>) {
         match key_type {
-            KeyType::ES256 => (Kty::EC, Crv::P256),
-            KeyType::ES384 => (Kty::EC, Crv::P384),
-            KeyType::ES512 => (Kty::EC, Crv::P521),
-            _ => unimplemented!(),
+            KeyType::ES256 => (Kty::EC, Some(Crv::P256)),
+            KeyType::ES384 => (Kty::EC, Some(Crv::P384)),
+            KeyType::ES512 => (Kty::EC, Some(Crv::P521)),
+            KeyType::RS256
+            | KeyType::RS384
+            | KeyType::RS512
+            | KeyType::PS256
+            | KeyType::PS384
+            | KeyType::PS512 => (Kty::RSA, None),
+            KeyType::EdDSA => (Kty::OKP, Some(Crv::Ed25519)),
+        }
+    }
+}
+
+impl KeyType {
+    // Length of R and S in the JWS encoding of the ECDSA signatures (RFC 7518 section 3.4), the size of the
+    // curve order. None for the key types that are not ECDSA.
+    #[must_use]
+    pub fn ecdsa_component_len(self) -> Option<usize> {
+        match self {
+            KeyType::ES256 => Some(32),
+            KeyType::ES384 => Some(48),
+            KeyType::ES512 => Some(66),
+            _ => None,
         }
     }
 }
@@ -183,7 +338,14 @@ pub struct JWTSVIDCompact {
     pub issued_at: u64,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
+// The token is a bearer credential, it is wiped from memory with the JWT-SVID.
+impl Drop for JWTSVIDCompact {
+    fn drop(&mut self) {
+        self.token.zeroize();
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
 pub struct TrustBundle {
     pub trust_domain: String,
     pub jwt_key_set: JWKSet,
@@ -197,21 +359,181 @@ pub struct JWKSet {
     pub spiffe_sequence_number: u64,
 }
 
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+pub struct JWKSetVersion {
+    pub version: usize,
+    pub created_at: u64,
+    pub keys: Vec<JWK>,
+}
+
+// Signing keys of the key manager, saved in the catalog so a restarted server keeps signing with the keys
+// it published.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+pub struct KeySlots {
+    pub previous: Option<KeySlot>,
+    pub current: KeySlot,
+    pub next: Option<KeySlot>,
+    // Kids of the last revoked keys, the trust bundle is never rolled back to a version with one of them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub revoked: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+pub struct KeySlot {
+    // Id of the key in the key store.
+    pub id: String,
+    // Key id of the JWK, the thumbprint of the key. Empty in the slots saved before the keys had thumbprints,
+    // their JWK has the key store id.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub kid: String,
+    pub expiry: u64,
+}
+
+// Agents whose attested selectors contain all the selectors of a ban are not attested anymore, e.g. after
+// their node was decommissioned or compromised.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+pub struct AgentBan {
+    pub id: String,
+    pub selectors: BTreeSet<String>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    pub created_at: u64,
+}
+
+impl AgentBan {
+    // A ban without selectors would ban every agent, it matches none instead.
+    #[must_use]
+    pub fn matches(&self, agent_selectors: &BTreeSet<String>) -> bool {
+        !self.selectors.is_empty() && self.selectors.is_subset(agent_selectors)
+    }
+}
+
+// Agent attested by a server, so operators can see which nodes are connected.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+pub struct AttestedAgent {
+    // Derived from the selectors, an agent keeps its id when it attests again.
+    pub id: String,
+    pub selectors: BTreeSet<String>,
+    pub last_seen: u64,
+    // Latest expiry of the JWT-SVIDs issued through the agent, 0 if none were issued.
+    pub svid_expiry: u64,
+}
+
+// SVID issued by a server, as recorded in the audit log. Records are never updated.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+pub struct IssuedSvid {
+    // Issuance time followed by a random part, so the records are ordered by issuance.
+    pub id: String,
+    pub svid_type: KeyUse,
+    pub entry_id: String,
+    pub spiffe_id: String,
+    #[serde(default)]
+    pub audiences: Vec<String>,
+    // Selectors of the entry.
+    pub selectors: Vec<String>,
+    // Id and selectors of the agent the SVID was issued through, as in `AttestedAgent`.
+    pub agent_id: String,
+    pub agent_selectors: BTreeSet<String>,
+    pub issued_at: u64,
+    pub expiry: u64,
+}
+
+// Change made through the admin API, as recorded in the audit log. Records are never updated.
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+pub struct AdminOperation {
+    // Time of the operation followed by a random part, so the records are ordered by time.
+    pub id: String,
+    pub time: u64,
+    // Tenant, SPIFFE ID of the JWT-SVID or credentials of the local process that made the change.
+    pub caller: String,
+    // Name of the API, e.g. "create_registration_entries".
+    pub operation: String,
+    // Entries changed by the operation, the ones that failed are left out.
+    #[serde(default)]
+    pub entries: Vec<EntryChange>,
+    // What the operations that do not change entries did, e.g. the agent that was banned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
+pub struct EntryChange {
+    pub id: String,
+    pub change: EntryChangeKind,
+    // Revision of the entry before an update or a delete.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_revision: Option<u64>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum EntryChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
 pub struct JWK {
+    // Coordinates of EC keys, or the public key of OKP keys in `x`. Empty for RSA keys.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub x: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub y: String,
     pub kty: Kty,
-    pub crv: Crv,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crv: Option<Crv>,
+    // Modulus and exponent of RSA keys, empty for EC keys.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub n: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub e: String,
     pub kid: String,
     #[serde(rename = "use")]
     pub key_use: KeyUse,
 }
 
+impl JWK {
+    // JWK thumbprint of RFC 7638: the base64url SHA-256 of the required members of the key, in lexicographic
+    // order and without whitespace. The same key always has the same thumbprint. None for the symmetric keys
+    // and the keys without a curve, which have no required members to hash.
+    #[must_use]
+    pub fn thumbprint(&self) -> Option<String> {
+        // Serializing a string or a curve does not fail, a failure gives no thumbprint rather than a panic.
+        let json = |value: &str| serde_json::to_string(value).ok();
+        let crv = |crv: &Crv| serde_json::to_string(crv).ok();
+
+        let members = match (&self.kty, &self.crv) {
+            (Kty::EC, Some(curve)) => format!(
+                r#"{{"crv":{},"kty":"EC","x":{},"y":{}}}"#,
+                crv(curve)?,
+                json(&self.x)?,
+                json(&self.y)?
+            ),
+            (Kty::OKP, Some(curve)) => format!(
+                r#"{{"crv":{},"kty":"OKP","x":{}}}"#,
+                crv(curve)?,
+                json(&self.x)?
+            ),
+            (Kty::RSA, _) => format!(
+                r#"{{"e":{},"kty":"RSA","n":{}}}"#,
+                json(&self.e)?,
+                json(&self.n)?
+            ),
+            _ => return None,
+        };
+
+        let digest = openssl::sha::sha256(members.as_bytes());
+        Some(base64::encode_config(digest, base64::URL_SAFE_NO_PAD))
+    }
+}
+
 #[derive(Debug, serde::Deserialize, serde::Serialize, Clone, PartialEq)]
 pub enum Kty {
     EC,
     RSA,
+    // Octet key pairs, for EdDSA.
+    OKP,
     #[serde(rename = "oct")]
     Oct,
 }
@@ -224,8 +546,14 @@ pub enum Crv {
     P384,
     #[serde(rename = "P-521")]
     P521,
+    Ed25519,
 }
 
+// DER encoding of an Ed25519 public key, up to the 32 bytes of the key itself. JWKs only hold the key.
+pub const ED25519_PUBLIC_KEY_DER_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
 #[must_use]
 pub fn get_epoch_time() -> u64 {
     let now = SystemTime::now();
@@ -242,3 +570,96 @@ pub const CONFIG_DEFAULT_PATH: &str = "../../iot-edge-spiffe-server/config/tests
 
 #[cfg(feature = "tests")]
 pub const AGENT_DEFAULT_CONFIG_PATH: &str = "../../iot-edge-spiffe-agent/config/tests/Config.toml";
+
+// Entries of the unit tests. The tests set the fields they check with the struct update syntax, e.g.
+// `RegistrationEntry { admin: true, ..RegistrationEntry::test_entry("id") }`.
+#[cfg(feature = "tests")]
+impl RegistrationEntry {
+    // Node entry attested by SAT without selectors, its SPIFFE ID path is its id.
+    #[must_use]
+    pub fn test_entry(id: &str) -> Self {
+        RegistrationEntry {
+            id: id.to_string(),
+            other_identities: Vec::new(),
+            spiffe_id_path: id.to_string(),
+            attestation_config: AttestationConfig::Node(EntryNodeAttestation {
+                value: Vec::new(),
+                plugin: NodeAttestationPlugin::Sat,
+            }),
+            admin: false,
+            expires_at: 0,
+            dns_names: Vec::new(),
+            revision_number: 0,
+            store_svid: false,
+            prefetch: false,
+            ttl: None,
+            custom_claims: BTreeMap::new(),
+            federates_with: Vec::new(),
+        }
+    }
+
+    // Workload entry attested by K8S under `parent_id`, its SPIFFE ID path is its id.
+    #[must_use]
+    pub fn test_workload_entry(id: &str, parent_id: &str, selectors: Vec<String>) -> Self {
+        RegistrationEntry {
+            attestation_config: AttestationConfig::Workload(EntryWorkloadAttestation {
+                parent_id: parent_id.to_string(),
+                value: selectors,
+                plugin: WorkloadAttestationPlugin::K8s,
+            }),
+            ..RegistrationEntry::test_entry(id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jwk(kty: Kty, crv: Option<Crv>) -> JWK {
+        JWK {
+            x: String::new(),
+            y: String::new(),
+            kty,
+            crv,
+            n: String::new(),
+            e: String::new(),
+            kid: String::new(),
+            key_use: KeyUse::JWTSVID,
+        }
+    }
+
+    #[test]
+    fn thumbprint() {
+        // Example of RFC 7638 section 3.1.
+        let rsa = JWK {
+            n: "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw".to_string(),
+            e: "AQAB".to_string(),
+            kid: "2011-04-29".to_string(),
+            ..jwk(Kty::RSA, None)
+        };
+        assert_eq!(
+            rsa.thumbprint().unwrap(),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+
+        // Example of RFC 8037 appendix A.3.
+        let okp = JWK {
+            x: "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo".to_string(),
+            ..jwk(Kty::OKP, Some(Crv::Ed25519))
+        };
+        assert_eq!(
+            okp.thumbprint().unwrap(),
+            "kPrK_qmxVWaYVA9wwBF6Iuo3vVzz7TxHCTwXBygrS4k"
+        );
+
+        // The kid and the use are not hashed.
+        let mut other = okp.clone();
+        other.kid = "other".to_string();
+        other.key_use = KeyUse::X509SVID;
+        assert_eq!(other.thumbprint(), okp.thumbprint());
+
+        assert_eq!(jwk(Kty::EC, None).thumbprint(), None);
+        assert_eq!(jwk(Kty::Oct, None).thumbprint(), None);
+    }
+}
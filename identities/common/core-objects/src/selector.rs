@@ -0,0 +1,123 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Typed selectors. The entries and the attestation keep them as `<TYPE>:<value>` strings, e.g. `PODNAME:web-0`,
+// the type in upper case. The value is everything after the first colon, it may have colons of its own like the
+// `PODLABELS:app:web` selectors.
+
+use std::{fmt, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{NodeSelectorType, WorkloadSelectorType};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum Error {
+    #[error("Selector {0} is not <TYPE>:<value>")]
+    NoType(String),
+    #[error("Unknown selector type {0}")]
+    UnknownType(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Selector<T> {
+    pub selector_type: T,
+    pub value: String,
+}
+
+pub type WorkloadSelector = Selector<WorkloadSelectorType>;
+pub type NodeSelector = Selector<NodeSelectorType>;
+
+impl<T> Selector<T> {
+    pub fn new(selector_type: T, value: impl Into<String>) -> Self {
+        Selector {
+            selector_type,
+            value: value.into(),
+        }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Selector<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.selector_type, self.value)
+    }
+}
+
+impl<T: FromStr> FromStr for Selector<T> {
+    type Err = Error;
+
+    fn from_str(selector: &str) -> Result<Self, Error> {
+        let (selector_type, value) = selector
+            .split_once(':')
+            .ok_or_else(|| Error::NoType(selector.to_string()))?;
+
+        Ok(Selector {
+            selector_type: selector_type
+                .parse()
+                .map_err(|_| Error::UnknownType(selector_type.to_string()))?,
+            value: value.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use crate::build_selector_string;
+
+    use super::*;
+
+    #[test]
+    fn parse_selectors() {
+        let selector: WorkloadSelector = "PODNAME:web-0".parse().unwrap();
+        assert_eq!(
+            selector,
+            Selector::new(WorkloadSelectorType::PodName, "web-0")
+        );
+
+        let selector: WorkloadSelector = "PODLABELS:app:web".parse().unwrap();
+        assert_eq!(selector.selector_type, WorkloadSelectorType::PodLabels);
+        assert_eq!(selector.value, "app:web");
+        assert_eq!(selector.to_string(), "PODLABELS:app:web");
+
+        let selector: NodeSelector = "AGENTNODENAME:".parse().unwrap();
+        assert_eq!(selector, Selector::new(NodeSelectorType::AgentNodeName, ""));
+
+        // Every type reads back from its string form.
+        for selector_type in WorkloadSelectorType::iter() {
+            let selector = build_selector_string(&selector_type, "value");
+            assert_eq!(
+                selector.parse::<WorkloadSelector>().unwrap(),
+                Selector::new(selector_type, "value")
+            );
+        }
+        for selector_type in NodeSelectorType::iter() {
+            let selector = build_selector_string(&selector_type, "value");
+            assert_eq!(
+                selector.parse::<NodeSelector>().unwrap(),
+                Selector::new(selector_type, "value")
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_selectors() {
+        assert_eq!(
+            "web-0".parse::<WorkloadSelector>().unwrap_err(),
+            Error::NoType("web-0".to_string())
+        );
+        assert_eq!(
+            "PodName:web-0".parse::<WorkloadSelector>().unwrap_err(),
+            Error::UnknownType("PodName".to_string())
+        );
+        // Node selectors are not workload selectors.
+        assert_eq!(
+            "CLUSTER:demo".parse::<WorkloadSelector>().unwrap_err(),
+            Error::UnknownType("CLUSTER".to_string())
+        );
+        assert_eq!(
+            "PODNAME:web-0".parse::<NodeSelector>().unwrap_err(),
+            Error::UnknownType("PODNAME".to_string())
+        );
+    }
+}
@@ -0,0 +1,233 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// SPIFFE ID of the SPIFFE ID specification: spiffe://<trust domain><path>. The scheme and the trust domain are
+// case insensitive and normalized to lower case, the path is kept as is. The trust domain only has lower case
+// letters, digits, dots, dashes and underscores. The path is empty or has segments of letters, digits, dots,
+// dashes and underscores, each after a slash, none empty, "." or "..". Ports, user info, queries, fragments and
+// percent-encoding are not allowed.
+
+use std::{fmt, str::FromStr};
+
+use thiserror::Error;
+
+use crate::SPIFFE_ID_PREFIX;
+
+const MAX_SPIFFE_ID_LENGTH: usize = 2048;
+const MAX_TRUST_DOMAIN_LENGTH: usize = 255;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum Error {
+    #[error("{0} does not start with spiffe://")]
+    Scheme(String),
+    #[error("The trust domain is empty")]
+    EmptyTrustDomain,
+    #[error("The trust domain is longer than 255 characters")]
+    TrustDomainTooLong,
+    #[error("Character {0:?} is not allowed in a trust domain")]
+    TrustDomainCharacter(char),
+    #[error("The path does not start with a slash")]
+    RelativePath,
+    #[error("The path has an empty segment")]
+    EmptySegment,
+    #[error("The path has a \".\" or \"..\" segment")]
+    DotSegment,
+    #[error("Character {0:?} is not allowed in a path")]
+    PathCharacter(char),
+    #[error("The SPIFFE ID is longer than 2048 characters")]
+    TooLong,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SpiffeId {
+    trust_domain: String,
+    // Empty or starting with a slash.
+    path: String,
+}
+
+impl SpiffeId {
+    pub fn new(trust_domain: &str, path: &str) -> Result<Self, Error> {
+        let trust_domain = trust_domain.to_ascii_lowercase();
+        check_trust_domain(&trust_domain)?;
+        if !path.is_empty() {
+            check_path(path.strip_prefix('/').ok_or(Error::RelativePath)?)?;
+        }
+        if SPIFFE_ID_PREFIX.len() + trust_domain.len() + path.len() > MAX_SPIFFE_ID_LENGTH {
+            return Err(Error::TooLong);
+        }
+
+        Ok(SpiffeId {
+            trust_domain,
+            path: path.to_string(),
+        })
+    }
+
+    // SPIFFE ID of a registration entry, its spiffe_id_path has no leading slash. Entries always have a path.
+    pub fn from_entry_path(trust_domain: &str, spiffe_id_path: &str) -> Result<Self, Error> {
+        SpiffeId::new(trust_domain, &format!("/{}", spiffe_id_path))
+    }
+
+    #[must_use]
+    pub fn trust_domain(&self) -> &str {
+        &self.trust_domain
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // Path without its leading slash, as in the spiffe_id_path of the registration entries.
+    #[must_use]
+    pub fn entry_path(&self) -> &str {
+        self.path.strip_prefix('/').unwrap_or_default()
+    }
+}
+
+impl TryFrom<&str> for SpiffeId {
+    type Error = Error;
+
+    fn try_from(spiffe_id: &str) -> Result<Self, Error> {
+        let rest = spiffe_id
+            .get(..SPIFFE_ID_PREFIX.len())
+            .filter(|scheme| scheme.eq_ignore_ascii_case(SPIFFE_ID_PREFIX))
+            .map(|_| &spiffe_id[SPIFFE_ID_PREFIX.len()..])
+            .ok_or_else(|| Error::Scheme(spiffe_id.to_string()))?;
+        let (trust_domain, path) = rest.find('/').map_or((rest, ""), |i| rest.split_at(i));
+
+        SpiffeId::new(trust_domain, path)
+    }
+}
+
+impl FromStr for SpiffeId {
+    type Err = Error;
+
+    fn from_str(spiffe_id: &str) -> Result<Self, Error> {
+        SpiffeId::try_from(spiffe_id)
+    }
+}
+
+impl fmt::Display for SpiffeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", SPIFFE_ID_PREFIX, self.trust_domain, self.path)
+    }
+}
+
+impl From<SpiffeId> for String {
+    fn from(spiffe_id: SpiffeId) -> Self {
+        spiffe_id.to_string()
+    }
+}
+
+// Expects a lower case trust domain.
+fn check_trust_domain(trust_domain: &str) -> Result<(), Error> {
+    if trust_domain.is_empty() {
+        return Err(Error::EmptyTrustDomain);
+    }
+    if trust_domain.len() > MAX_TRUST_DOMAIN_LENGTH {
+        return Err(Error::TrustDomainTooLong);
+    }
+    if let Some(c) = trust_domain
+        .chars()
+        .find(|c| !matches!(c, 'a'..='z' | '0'..='9' | '.' | '-' | '_'))
+    {
+        return Err(Error::TrustDomainCharacter(c));
+    }
+
+    Ok(())
+}
+
+// Path without its leading slash.
+pub fn check_path(path: &str) -> Result<(), Error> {
+    for segment in path.split('/') {
+        match segment {
+            "" => return Err(Error::EmptySegment),
+            "." | ".." => return Err(Error::DotSegment),
+            _ => {}
+        }
+        if let Some(c) = segment
+            .chars()
+            .find(|c| !matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_'))
+        {
+            return Err(Error::PathCharacter(c));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_spiffe_id() {
+        let spiffe_id = SpiffeId::try_from("spiffe://iotedge/ns/default/Web_1").unwrap();
+        assert_eq!(spiffe_id.trust_domain(), "iotedge");
+        assert_eq!(spiffe_id.path(), "/ns/default/Web_1");
+        assert_eq!(spiffe_id.entry_path(), "ns/default/Web_1");
+        assert_eq!(spiffe_id.to_string(), "spiffe://iotedge/ns/default/Web_1");
+
+        let spiffe_id: SpiffeId = "SPIFFE://IoTEdge.Contoso.com".parse().unwrap();
+        assert_eq!(spiffe_id.trust_domain(), "iotedge.contoso.com");
+        assert_eq!(spiffe_id.path(), "");
+        assert_eq!(spiffe_id.to_string(), "spiffe://iotedge.contoso.com");
+
+        assert_eq!(
+            SpiffeId::from_entry_path("IoTEdge", "web").unwrap(),
+            SpiffeId::try_from("spiffe://iotedge/web").unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_spiffe_ids() {
+        let error = |spiffe_id: &str| SpiffeId::try_from(spiffe_id).unwrap_err();
+
+        assert_eq!(
+            error("https://iotedge/web"),
+            Error::Scheme("https://iotedge/web".to_string())
+        );
+        assert_eq!(
+            error("spiffe:/iotedge"),
+            Error::Scheme("spiffe:/iotedge".to_string())
+        );
+        assert_eq!(error("spiffe:///web"), Error::EmptyTrustDomain);
+        assert_eq!(
+            error("spiffe://iotedge:443/web"),
+            Error::TrustDomainCharacter(':')
+        );
+        assert_eq!(
+            error("spiffe://user@iotedge/web"),
+            Error::TrustDomainCharacter('@')
+        );
+        assert_eq!(error("spiffe://iotedge/"), Error::EmptySegment);
+        assert_eq!(error("spiffe://iotedge/web/"), Error::EmptySegment);
+        assert_eq!(error("spiffe://iotedge//web"), Error::EmptySegment);
+        assert_eq!(error("spiffe://iotedge/ns/../web"), Error::DotSegment);
+        assert_eq!(
+            error("spiffe://iotedge/web?query"),
+            Error::PathCharacter('?')
+        );
+        assert_eq!(
+            error("spiffe://iotedge/web#fragment"),
+            Error::PathCharacter('#')
+        );
+        assert_eq!(error("spiffe://iotedge/w%65b"), Error::PathCharacter('%'));
+        assert_eq!(
+            error(&format!("spiffe://{}", "a".repeat(256))),
+            Error::TrustDomainTooLong
+        );
+        assert_eq!(
+            error(&format!("spiffe://iotedge/{}", "a".repeat(2048))),
+            Error::TooLong
+        );
+
+        assert_eq!(
+            SpiffeId::from_entry_path("iotedge", "").unwrap_err(),
+            Error::EmptySegment
+        );
+        assert_eq!(
+            SpiffeId::new("iotedge", "web").unwrap_err(),
+            Error::RelativePath
+        );
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fmt;
+
+use core_objects::SpiffeId;
+
+/// Options controlling how the requested audience is compared with the JWT-SVID audiences.
+#[derive(Clone, Debug, Default)]
+pub struct AudienceOptions {
+    /// Compare audiences byte for byte, even when they parse as SPIFFE IDs.
+    pub exact_match: bool,
+    /// When set, a requested audience that parses as a SPIFFE ID must belong to this trust domain.
+    pub trust_domain: Option<String>,
+}
+
+/// An audience as found in a JWT-SVID. Audiences are arbitrary strings, the ones that parse
+/// as SPIFFE IDs are kept in canonical form so they can be compared safely.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Audience {
+    SPIFFEID(SpiffeId),
+    Other(String),
+}
+
+impl Audience {
+    #[must_use]
+    pub fn parse(audience: &str) -> Self {
+        // Trailing slashes are not part of a SPIFFE ID, they are dropped rather than rejected.
+        SpiffeId::try_from(audience.trim_end_matches('/')).map_or_else(
+            |_| Audience::Other(audience.to_string()),
+            Audience::SPIFFEID,
+        )
+    }
+
+    #[must_use]
+    pub fn trust_domain(&self) -> Option<&str> {
+        match self {
+            Audience::SPIFFEID(spiffe_id) => Some(spiffe_id.trust_domain()),
+            Audience::Other(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Audience {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Audience::SPIFFEID(spiffe_id) => spiffe_id.fmt(f),
+            Audience::Other(audience) => f.write_str(audience),
+        }
+    }
+}
+
+/// Canonical form of an audience: SPIFFE IDs get a lowercase scheme and trust domain and no
+/// trailing slash, any other string is returned unchanged.
+#[must_use]
+pub fn normalize_audience(audience: &str) -> String {
+    Audience::parse(audience).to_string()
+}
+
+/// Whether two audiences designate the same party. SPIFFE IDs are compared in canonical form,
+/// so "spiffe://Example.org/broker/" matches "spiffe://example.org/broker".
+#[must_use]
+pub fn audiences_match(left: &str, right: &str) -> bool {
+    left == right || Audience::parse(left) == Audience::parse(right)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_spiffe_id_audience() {
+        assert_eq!(
+            Audience::parse("SPIFFE://Example.org/broker/"),
+            Audience::SPIFFEID(SpiffeId::new("example.org", "/broker").unwrap())
+        );
+        assert_eq!(
+            Audience::parse("spiffe://example.org"),
+            Audience::SPIFFEID(SpiffeId::new("example.org", "").unwrap())
+        );
+    }
+
+    #[test]
+    fn parse_other_audience() {
+        for audience in [
+            "myaudience",
+            "https://example.org/broker",
+            "spiffe://",
+            "spiffe:///broker",
+            "spiffe://example.org//broker",
+            "spiffe://example.org/../broker",
+            "spiffe://exa mple.org/broker",
+        ] {
+            assert_eq!(
+                Audience::parse(audience),
+                Audience::Other(audience.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(
+            normalize_audience("spiffe://EXAMPLE.org/Broker//"),
+            "spiffe://example.org/Broker"
+        );
+        assert_eq!(normalize_audience("MyAudience/"), "MyAudience/");
+    }
+
+    #[test]
+    fn match_audiences() {
+        assert!(audiences_match(
+            "spiffe://example.org/broker/",
+            "spiffe://Example.org/broker"
+        ));
+        assert!(!audiences_match(
+            "spiffe://example.org/broker",
+            "spiffe://example.org/Broker"
+        ));
+        assert!(!audiences_match(
+            "spiffe://example.org/broker",
+            "spiffe://other.org/broker"
+        ));
+        assert!(audiences_match("myaudience", "myaudience"));
+        assert!(!audiences_match("myaudience/", "myaudience"));
+    }
+}
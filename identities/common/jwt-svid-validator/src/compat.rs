@@ -0,0 +1,151 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// JWT-SVIDs issued before the header and claims used the names of RFC 7515 and RFC 7519 spelled the names out
+// ("algorithm", "subject", ...). They are still accepted until they expire: a header or claims that do not
+// parse with the standard names are parsed again with the old ones. The error of the standard names is
+// returned when neither parse.
+
+use std::collections::BTreeMap;
+
+use core_objects::{IdentityTypes, JWTClaims, JWTHeader, JWTType, KeyType};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct LegacyJWTHeader {
+    algorithm: KeyType,
+    key_id: String,
+    jwt_type: JWTType,
+}
+
+impl From<LegacyJWTHeader> for JWTHeader {
+    fn from(header: LegacyJWTHeader) -> Self {
+        JWTHeader {
+            algorithm: header.algorithm,
+            key_id: header.key_id,
+            jwt_type: header.jwt_type,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LegacyJWTClaims {
+    subject: String,
+    audience: Vec<String>,
+    expiry: u64,
+    issued_at: u64,
+    other_identities: Vec<IdentityTypes>,
+    #[serde(default)]
+    pod_uid: Option<String>,
+}
+
+impl From<LegacyJWTClaims> for JWTClaims {
+    fn from(claims: LegacyJWTClaims) -> Self {
+        JWTClaims {
+            subject: claims.subject,
+            audience: claims.audience,
+            expiry: claims.expiry,
+            issued_at: claims.issued_at,
+            jwt_id: None,
+            not_before: None,
+            issuer: None,
+            other_identities: claims.other_identities,
+            pod_uid: claims.pod_uid,
+            custom_claims: BTreeMap::new(),
+        }
+    }
+}
+
+pub(crate) fn parse_header(header: &str) -> Result<JWTHeader, serde_json::Error> {
+    serde_json::from_str(header).or_else(|err| {
+        serde_json::from_str::<LegacyJWTHeader>(header)
+            .map(Into::into)
+            .map_err(|_| err)
+    })
+}
+
+pub(crate) fn parse_claims(claims: &str) -> Result<JWTClaims, serde_json::Error> {
+    serde_json::from_str(claims).or_else(|err| {
+        serde_json::from_str::<LegacyJWTClaims>(claims)
+            .map(Into::into)
+            .map_err(|_| err)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_standard_names() {
+        let header = parse_header(r#"{"alg":"ES256","kid":"key","typ":"JWT"}"#).unwrap();
+        assert_eq!(header.algorithm, KeyType::ES256);
+        assert_eq!(header.key_id, "key");
+        assert_eq!(header.jwt_type, JWTType::JWT);
+
+        let claims =
+            parse_claims(r#"{"sub":"spiffe://td/path","aud":["a","b"],"exp":10,"iat":1}"#).unwrap();
+        assert_eq!(claims.subject, "spiffe://td/path");
+        assert_eq!(claims.audience, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(claims.expiry, 10);
+        assert_eq!(claims.issued_at, 1);
+        assert!(claims.other_identities.is_empty());
+
+        // A single audience may be a string.
+        let claims =
+            parse_claims(r#"{"sub":"spiffe://td/path","aud":"a","exp":10,"iat":1}"#).unwrap();
+        assert_eq!(claims.audience, vec!["a".to_string()]);
+    }
+
+    #[test]
+    fn parse_legacy_names() {
+        let header =
+            parse_header(r#"{"algorithm":"ES256","key_id":"key","jwt_type":"JWT"}"#).unwrap();
+        assert_eq!(header.algorithm, KeyType::ES256);
+        assert_eq!(header.key_id, "key");
+
+        let claims = parse_claims(
+            r#"{"subject":"spiffe://td/path","audience":["a"],"expiry":10,"issued_at":1,"other_identities":[],"pod_uid":"uid"}"#,
+        )
+        .unwrap();
+        assert_eq!(claims.subject, "spiffe://td/path");
+        assert_eq!(claims.audience, vec!["a".to_string()]);
+        assert_eq!(claims.expiry, 10);
+        assert_eq!(claims.pod_uid.as_deref(), Some("uid"));
+    }
+
+    #[test]
+    fn serialize_standard_names() {
+        let header = JWTHeader {
+            algorithm: KeyType::ES256,
+            key_id: "key".to_string(),
+            jwt_type: JWTType::JWT,
+        };
+        assert_eq!(
+            serde_json::to_string(&header).unwrap(),
+            r#"{"alg":"ES256","kid":"key","typ":"JWT"}"#
+        );
+
+        let claims = JWTClaims {
+            subject: "spiffe://td/path".to_string(),
+            audience: vec!["a".to_string()],
+            expiry: 10,
+            issued_at: 1,
+            jwt_id: None,
+            not_before: None,
+            issuer: None,
+            other_identities: Vec::new(),
+            pod_uid: None,
+            custom_claims: Default::default(),
+        };
+        assert_eq!(
+            serde_json::to_string(&claims).unwrap(),
+            r#"{"sub":"spiffe://td/path","aud":["a"],"exp":10,"iat":1,"other_identities":[]}"#
+        );
+    }
+
+    #[test]
+    fn parse_error() {
+        parse_header(r#"{"alg":"ES256"}"#).unwrap_err();
+        parse_claims(r#"{"subject":"spiffe://td/path"}"#).unwrap_err();
+    }
+}
@@ -22,10 +22,29 @@ pub enum Error {
     InvalidBase64Encoding(DecodeError),
     #[error("Error decoding from base64: {0}")]
     InvalidUTF8Encoding(Utf8Error),
-    #[error("Token is expired: current time {current:?}, expiry time {current:?}")]
+    #[error("Token is expired: current time {current:?}, expiry time {expiry:?}")]
     ExpiredToken { expiry: u64, current: u64 },
+    #[error("Token is not valid yet: current time {current:?}, not before {not_before:?}")]
+    TokenNotYetValid { not_before: u64, current: u64 },
+    #[error("Token is issued in the future: current time {current:?}, issued at {issued_at:?}")]
+    TokenIssuedInFuture { issued_at: u64, current: u64 },
+    #[error("Token expires before it is issued: issued at {issued_at:?}, expiry time {expiry:?}")]
+    InvalidLifetime { issued_at: u64, expiry: u64 },
+    #[error("Token {0:?} was already presented")]
+    TokenReplayed(String),
+    #[error("Too many tokens seen to check replays")]
+    TooManySeenTokens,
     #[error("Identity {0:?} is not in audience field")]
     InvalidAudience(String),
+    #[error("Audience {audience:?} is not in trust domain {trust_domain:?}")]
+    AudienceNotInTrustDomain {
+        audience: String,
+        trust_domain: String,
+    },
+    #[error("Subject {0:?} is not a SPIFFE ID")]
+    SubjectNotSPIFFEID(String),
+    #[error("No trust bundle for trust domain {0:?}")]
+    UnknownTrustDomain(String),
     #[error("Could not find public key kid: ")]
     PublicKeyNotInTrustBundle(String),
     #[error("Cannot convert public key der to openssl public key: {0}")]
@@ -42,6 +61,14 @@ pub enum Error {
     BigNumberFromSlice(ErrorStack),
     #[error("Could not retrieve EC pub key from pub key affine coordinates: {0}")]
     ECKeyFromPubKeyAffineCoordinates(ErrorStack),
+    #[error("Could not build RSA pub key from its modulus and exponent: {0}")]
+    RSAKeyFromPubKeyComponents(ErrorStack),
+    #[error("Error while verifying the signature: {0}")]
+    SignatureVerificationErrorRsa(ErrorStack),
+    #[error("Cannot convert public key der to EdDSA public key: {0}")]
+    CannotConvertDerToEdDSAPublicKey(ErrorStack),
+    #[error("Error while verifying the signature: {0}")]
+    SignatureVerificationErrorEdDSA(ErrorStack),
     #[error("Could decode the base64 encoded coordinates: {0}")]
     Base64DecodeCoordinates(DecodeError),
 }
@@ -9,12 +9,17 @@
     clippy::similar_names,
     clippy::too_many_lines
 )]
+pub mod audience;
+mod compat;
 pub mod error;
+pub mod replay;
 pub mod validate;
 
 #[cfg(feature = "tests")]
 use mockall::automock;
 
+use std::collections::BTreeMap;
+
 use core_objects::{TrustBundle, JWTSVID};
 use error::Error;
 
@@ -22,10 +27,12 @@ use error::Error;
 #[cfg_attr(feature = "tests", automock)]
 #[async_trait::async_trait]
 pub trait JWTSVIDValidator: Send + Sync {
+    // The trust bundles are keyed by trust domain, the own one and the federated ones. A JWT-SVID is checked
+    // with the bundle of the trust domain of its subject.
     async fn validate(
         &self,
         jwt_svid_compact: &str,
-        trust_bundle: &TrustBundle,
+        trust_bundles: &BTreeMap<String, TrustBundle>,
         audience: &str,
     ) -> Result<JWTSVID, Error>;
 }
@@ -0,0 +1,72 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Replay detection of the services JWT-SVIDs are presented to. The validator calls the hook with the jti of every
+// valid token that has one, once the signature, expiry and audience are checked.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::error::Error;
+
+pub trait ReplayCheck: Send + Sync {
+    // Records the token as seen, or fails with `Error::TokenReplayed` if it was already seen. The token does not
+    // need to be remembered after its expiry, it is rejected as expired by then.
+    fn check(&self, jwt_id: &str, expiry: u64, now: u64) -> Result<(), Error>;
+}
+
+// Remembers the ids of the tokens seen by this process until they expire.
+pub struct ReplayCache {
+    max_tokens: usize,
+    // Expiry of the ids of the tokens already seen.
+    seen_tokens: Mutex<HashMap<String, u64>>,
+}
+
+impl ReplayCache {
+    // Bounds the memory taken by the ids, tokens are rejected when the cache is full.
+    #[must_use]
+    pub fn new(max_tokens: usize) -> Self {
+        ReplayCache {
+            max_tokens,
+            seen_tokens: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl ReplayCheck for ReplayCache {
+    fn check(&self, jwt_id: &str, expiry: u64, now: u64) -> Result<(), Error> {
+        let mut seen_tokens = self.seen_tokens.lock().unwrap();
+        seen_tokens.retain(|_, expires_at| *expires_at >= now);
+        if seen_tokens.contains_key(jwt_id) {
+            return Err(Error::TokenReplayed(jwt_id.to_string()));
+        }
+        if seen_tokens.len() >= self.max_tokens {
+            return Err(Error::TooManySeenTokens);
+        }
+        seen_tokens.insert(jwt_id.to_string(), expiry);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::*;
+
+    #[test]
+    fn replay_cache() {
+        let cache = ReplayCache::new(2);
+
+        cache.check("a", 10, 0).unwrap();
+        let error = cache.check("a", 10, 5).unwrap_err();
+        assert_matches!(error, Error::TokenReplayed(_));
+
+        cache.check("b", 20, 5).unwrap();
+        let error = cache.check("c", 20, 5).unwrap_err();
+        assert_matches!(error, Error::TooManySeenTokens);
+
+        // "a" expired, its id is forgotten.
+        cache.check("c", 20, 11).unwrap();
+        cache.check("a", 30, 11).unwrap();
+    }
+}
@@ -1,32 +1,144 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use crate::audience::{audiences_match, Audience, AudienceOptions};
+use crate::compat;
 use crate::error::Error;
+use crate::replay::ReplayCheck;
 use crate::JWTSVIDValidator as JWTSVIDValidatorTrait;
-use core_objects::{get_epoch_time, JWTClaims, JWTHeader, JWTType, KeyType, TrustBundle, JWTSVID};
-use openssl::{bn::BigNum, nid, sha};
+use core_objects::{
+    get_epoch_time, Crv, JWTClaims, JWTType, KeyType, Kty, TrustBundle,
+    ED25519_PUBLIC_KEY_DER_PREFIX, JWTSVID,
+};
+use openssl::{
+    bn::BigNum,
+    ecdsa::EcdsaSig,
+    error::ErrorStack,
+    md::{Md, MdRef},
+    nid,
+    pkey::{PKey, Public},
+    pkey_ctx::PkeyCtx,
+    rsa::{Padding, Rsa},
+    sha,
+    sign::Verifier,
+};
+use std::{collections::BTreeMap, sync::Arc};
+
+// Seconds the clock of the validator may be off from the clock of the server that issued the tokens.
+pub const DEFAULT_LEEWAY: u64 = 60;
+
+pub struct JWTSVIDValidator {
+    audience_options: AudienceOptions,
+    replay_check: Option<Arc<dyn ReplayCheck>>,
+    leeway: u64,
+}
 
-#[derive(Default)]
-pub struct JWTSVIDValidator {}
+impl Default for JWTSVIDValidator {
+    fn default() -> Self {
+        JWTSVIDValidator::new(AudienceOptions::default())
+    }
+}
 
 #[async_trait::async_trait]
 impl JWTSVIDValidatorTrait for JWTSVIDValidator {
     async fn validate(
         &self,
         jwt_svid_compact: &str,
-        trust_bundle: &TrustBundle,
+        trust_bundles: &BTreeMap<String, TrustBundle>,
         audience: &str,
     ) -> Result<JWTSVID, Error> {
         let time = get_epoch_time();
-        self.validate_inner(jwt_svid_compact, trust_bundle, audience, time)
+        self.validate_inner(jwt_svid_compact, trust_bundles, audience, time)
             .await
     }
 }
 
 impl JWTSVIDValidator {
+    #[must_use]
+    pub fn new(audience_options: AudienceOptions) -> Self {
+        JWTSVIDValidator {
+            audience_options,
+            replay_check: None,
+            leeway: DEFAULT_LEEWAY,
+        }
+    }
+
+    #[must_use]
+    pub fn with_leeway(mut self, leeway: u64) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    // Tokens without a jti cannot be checked for replays, they are accepted.
+    #[must_use]
+    pub fn with_replay_check(mut self, replay_check: Arc<dyn ReplayCheck>) -> Self {
+        self.replay_check = Some(replay_check);
+        self
+    }
+
+    // Tokens are valid from their nbf, or their iat when they have none, to their exp. Each bound is widened by
+    // the leeway, the issuing server and the validator do not have the same clock.
+    fn check_times(&self, claims: &JWTClaims, time: u64) -> Result<(), Error> {
+        if claims.expiry < claims.issued_at {
+            return Err(Error::InvalidLifetime {
+                issued_at: claims.issued_at,
+                expiry: claims.expiry,
+            });
+        }
+
+        if claims.expiry.saturating_add(self.leeway) < time {
+            return Err(Error::ExpiredToken {
+                current: time,
+                expiry: claims.expiry,
+            });
+        }
+
+        let latest_time = time.saturating_add(self.leeway);
+        match claims.not_before {
+            Some(not_before) if latest_time < not_before => Err(Error::TokenNotYetValid {
+                current: time,
+                not_before,
+            }),
+            None if latest_time < claims.issued_at => Err(Error::TokenIssuedInFuture {
+                current: time,
+                issued_at: claims.issued_at,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_audience(&self, claims_audiences: &[String], audience: &str) -> Result<(), Error> {
+        if let Some(trust_domain) = &self.audience_options.trust_domain {
+            if let Some(audience_trust_domain) = Audience::parse(audience).trust_domain() {
+                if !audience_trust_domain.eq_ignore_ascii_case(trust_domain) {
+                    return Err(Error::AudienceNotInTrustDomain {
+                        audience: audience.to_string(),
+                        trust_domain: trust_domain.clone(),
+                    });
+                }
+            }
+        }
+
+        let found = if self.audience_options.exact_match {
+            claims_audiences
+                .iter()
+                .any(|claims_audience| claims_audience == audience)
+        } else {
+            claims_audiences
+                .iter()
+                .any(|claims_audience| audiences_match(claims_audience, audience))
+        };
+
+        if found {
+            Ok(())
+        } else {
+            Err(Error::InvalidAudience(audience.to_string()))
+        }
+    }
+
     async fn validate_inner(
         &self,
         jwt_svid_compact: &str,
-        trust_bundle: &TrustBundle,
+        trust_bundles: &BTreeMap<String, TrustBundle>,
         audience: &str,
         time: u64,
     ) -> Result<JWTSVID, Error> {
@@ -38,15 +150,13 @@ impl JWTSVIDValidator {
 
         let data = format!("{}.{}", split[0], split[1]);
 
-        let digest = sha::sha256(data.as_bytes());
-
         let jwtsvid_signature = split[2].to_string();
 
-        let header_compact = base64::decode_config(split[0], base64::STANDARD_NO_PAD)
+        let header_compact = base64::decode_config(split[0], base64::URL_SAFE_NO_PAD)
             .map_err(Error::InvalidBase64Encoding)?;
-        let claim_compact = base64::decode_config(split[1], base64::STANDARD_NO_PAD)
+        let claim_compact = base64::decode_config(split[1], base64::URL_SAFE_NO_PAD)
             .map_err(Error::InvalidBase64Encoding)?;
-        let signature_encrypted = base64::decode_config(split[2], base64::STANDARD_NO_PAD)
+        let signature_encrypted = base64::decode_config(split[2], base64::URL_SAFE_NO_PAD)
             .map_err(Error::InvalidBase64Encoding)?;
 
         let header_compact =
@@ -54,28 +164,29 @@ impl JWTSVIDValidator {
         let claim_compact =
             std::str::from_utf8(&claim_compact).map_err(Error::InvalidUTF8Encoding)?;
 
-        let header: JWTHeader =
-            serde_json::from_str(header_compact).map_err(Error::DeserializeJson)?;
-        let claims: JWTClaims =
-            serde_json::from_str(claim_compact).map_err(Error::DeserializeJson)?;
+        let header = compat::parse_header(header_compact).map_err(Error::DeserializeJson)?;
+        let claims = compat::parse_claims(claim_compact).map_err(Error::DeserializeJson)?;
 
         if JWTType::JWT != header.jwt_type {
             return Err(Error::InvalidJWTType(header.jwt_type));
         }
 
-        // Check token is not expired.
-        if claims.expiry < time {
-            return Err(Error::ExpiredToken {
-                current: time,
-                expiry: claims.expiry,
-            });
-        }
+        self.check_times(&claims, time)?;
+        self.check_audience(&claims.audience, audience)?;
 
-        let _: &String = claims
-            .audience
+        // Another trust domain cannot issue JWT-SVIDs for the subjects of this one: the key must be in the
+        // bundle of the trust domain of the subject.
+        let trust_domain = Audience::parse(&claims.subject)
+            .trust_domain()
+            .map(str::to_string)
+            .ok_or_else(|| Error::SubjectNotSPIFFEID(claims.subject.clone()))?;
+        let trust_bundle = trust_bundles
             .iter()
-            .find(|claims_audience| claims_audience == &audience)
-            .ok_or_else(|| Error::InvalidAudience(audience.to_string()))?;
+            .find(|(bundle_trust_domain, _)| {
+                bundle_trust_domain.eq_ignore_ascii_case(&trust_domain)
+            })
+            .map(|(_, trust_bundle)| trust_bundle)
+            .ok_or(Error::UnknownTrustDomain(trust_domain))?;
 
         let jwk = trust_bundle
             .jwt_key_set
@@ -84,25 +195,37 @@ impl JWTSVIDValidator {
             .find(|jwk| jwk.kid == header.key_id)
             .ok_or_else(|| Error::PublicKeyNotInTrustBundle(header.key_id.clone()))?;
 
-        match header.algorithm {
-            KeyType::ES256 => {
-                let ec_group = openssl::ec::EcGroup::from_curve_name(nid::Nid::X9_62_PRIME256V1)
-                    .map_err(Error::ECGroupFromNID)?;
+        // The algorithm comes from the token, it must match the key it claims to be signed with.
+        let (kty, crv): (Kty, Option<Crv>) = header.algorithm.into();
+        if jwk.kty != kty || jwk.crv != crv {
+            return Err(Error::InvalidAlgorithm(header.algorithm));
+        }
 
-                let x = &base64::decode_config(jwk.x.clone(), base64::STANDARD_NO_PAD)
-                    .map_err(Error::Base64DecodeCoordinates)?;
+        let jwt_svid = match header.algorithm {
+            KeyType::ES256 | KeyType::ES384 | KeyType::ES512 => {
+                let (digest, curve) = match header.algorithm {
+                    KeyType::ES256 => (
+                        sha::sha256(data.as_bytes()).to_vec(),
+                        nid::Nid::X9_62_PRIME256V1,
+                    ),
+                    KeyType::ES384 => (sha::sha384(data.as_bytes()).to_vec(), nid::Nid::SECP384R1),
+                    _ => (sha::sha512(data.as_bytes()).to_vec(), nid::Nid::SECP521R1),
+                };
+
+                let ec_group =
+                    openssl::ec::EcGroup::from_curve_name(curve).map_err(Error::ECGroupFromNID)?;
+
+                let x = &decode_member(&jwk.x).map_err(Error::Base64DecodeCoordinates)?;
                 let x = BigNum::from_slice(x).map_err(Error::BigNumberFromSlice)?;
 
-                let y = &base64::decode_config(jwk.y.clone(), base64::STANDARD_NO_PAD)
-                    .map_err(Error::Base64DecodeCoordinates)?;
+                let y = &decode_member(&jwk.y).map_err(Error::Base64DecodeCoordinates)?;
                 let y = BigNum::from_slice(y).map_err(Error::BigNumberFromSlice)?;
 
                 let public_key =
                     openssl::ec::EcKey::from_public_key_affine_coordinates(&ec_group, &x, &y)
                         .map_err(Error::ECKeyFromPubKeyAffineCoordinates)?;
 
-                let ecda_sign = openssl::ecdsa::EcdsaSig::from_der(&signature_encrypted)
-                    .map_err(Error::CannotConvertSignatureToEcdsaSignature)?;
+                let ecda_sign = ecdsa_signature(header.algorithm, &signature_encrypted)?;
 
                 ecda_sign
                     .verify(&digest, &public_key)
@@ -114,15 +237,123 @@ impl JWTSVIDValidator {
                     })
                     .ok_or(Error::InvalidSignature)
             }
-            _ => Err(Error::InvalidAlgorithm(header.algorithm)),
+            KeyType::RS256
+            | KeyType::RS384
+            | KeyType::RS512
+            | KeyType::PS256
+            | KeyType::PS384
+            | KeyType::PS512 => {
+                let (digest, md) = match header.algorithm {
+                    KeyType::RS256 | KeyType::PS256 => {
+                        (sha::sha256(data.as_bytes()).to_vec(), Md::sha256())
+                    }
+                    KeyType::RS384 | KeyType::PS384 => {
+                        (sha::sha384(data.as_bytes()).to_vec(), Md::sha384())
+                    }
+                    _ => (sha::sha512(data.as_bytes()).to_vec(), Md::sha512()),
+                };
+                let padding = match header.algorithm {
+                    KeyType::PS256 | KeyType::PS384 | KeyType::PS512 => Padding::PKCS1_PSS,
+                    _ => Padding::PKCS1,
+                };
+
+                let n = &decode_member(&jwk.n).map_err(Error::Base64DecodeCoordinates)?;
+                let n = BigNum::from_slice(n).map_err(Error::BigNumberFromSlice)?;
+
+                let e = &decode_member(&jwk.e).map_err(Error::Base64DecodeCoordinates)?;
+                let e = BigNum::from_slice(e).map_err(Error::BigNumberFromSlice)?;
+
+                let public_key = Rsa::from_public_components(n, e)
+                    .and_then(PKey::from_rsa)
+                    .map_err(Error::RSAKeyFromPubKeyComponents)?;
+
+                verify_rsa(&public_key, md, padding, &digest, &signature_encrypted)
+                    .map_err(Error::SignatureVerificationErrorRsa)?
+                    .then(|| JWTSVID {
+                        header,
+                        claims,
+                        signature: jwtsvid_signature,
+                    })
+                    .ok_or(Error::InvalidSignature)
+            }
+            KeyType::EdDSA => {
+                let x = &decode_member(&jwk.x).map_err(Error::Base64DecodeCoordinates)?;
+                let public_key_der = [&ED25519_PUBLIC_KEY_DER_PREFIX[..], x].concat();
+                let public_key = PKey::public_key_from_der(&public_key_der)
+                    .map_err(Error::CannotConvertDerToEdDSAPublicKey)?;
+
+                // EdDSA hashes the message itself.
+                Verifier::new_without_digest(&public_key)
+                    .and_then(|mut verifier| {
+                        verifier.verify_oneshot(&signature_encrypted, data.as_bytes())
+                    })
+                    .map_err(Error::SignatureVerificationErrorEdDSA)?
+                    .then(|| JWTSVID {
+                        header,
+                        claims,
+                        signature: jwtsvid_signature,
+                    })
+                    .ok_or(Error::InvalidSignature)
+            }
+        }?;
+
+        // Only once the token is known to be genuine, so forged tokens cannot fill the replay cache.
+        if let (Some(replay_check), Some(jwt_id)) = (&self.replay_check, &jwt_svid.claims.jwt_id) {
+            // The token is accepted until the end of the leeway, it must be remembered as long.
+            let expiry = jwt_svid.claims.expiry.saturating_add(self.leeway);
+            replay_check.check(jwt_id, expiry, time)?;
+        }
+
+        Ok(jwt_svid)
+    }
+}
+
+// JWS encodes the signatures as R and S concatenated, each the size of the curve order. Servers signed with DER
+// encoded signatures before, they are accepted until those tokens expire. DER signatures have a different length
+// but for the odd one with very short R and S.
+fn ecdsa_signature(key_type: KeyType, signature: &[u8]) -> Result<EcdsaSig, Error> {
+    match key_type.ecdsa_component_len() {
+        Some(component_len) if signature.len() == 2 * component_len => {
+            let r = BigNum::from_slice(&signature[..component_len])
+                .map_err(Error::BigNumberFromSlice)?;
+            let s = BigNum::from_slice(&signature[component_len..])
+                .map_err(Error::BigNumberFromSlice)?;
+
+            EcdsaSig::from_private_components(r, s)
+                .map_err(Error::CannotConvertSignatureToEcdsaSignature)
         }
+        _ => EcdsaSig::from_der(signature).map_err(Error::CannotConvertSignatureToEcdsaSignature),
     }
 }
 
+fn verify_rsa(
+    public_key: &PKey<Public>,
+    md: &MdRef,
+    padding: Padding,
+    digest: &[u8],
+    signature: &[u8],
+) -> Result<bool, ErrorStack> {
+    let mut ctx = PkeyCtx::new(public_key)?;
+    ctx.verify_init()?;
+    ctx.set_signature_md(md)?;
+    ctx.set_rsa_padding(padding)?;
+
+    ctx.verify(digest, signature)
+}
+
+// The members of the JWKs are base64url. Keys saved before are standard base64, both are unpadded and only the
+// two last characters of the alphabets differ.
+fn decode_member(member: &str) -> Result<Vec<u8>, base64::DecodeError> {
+    base64::decode_config(
+        member.replace('+', "-").replace('/', "_"),
+        base64::URL_SAFE_NO_PAD,
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use catalog::inmemory;
-    use core_objects::{CONFIG_DEFAULT_PATH, SPIFFE_ID_PREFIX};
+    use core_objects::{JWTHeader, CONFIG_DEFAULT_PATH, SPIFFE_ID_PREFIX};
     use key_manager::KeyManager;
     use key_store::disk;
     use matches::assert_matches;
@@ -131,6 +362,8 @@ mod tests {
     use svid_factory::{JWTSVIDParams, SVIDFactory};
     use trust_bundle_builder::TrustBundleBuilder;
 
+    use crate::replay::ReplayCache;
+
     use super::*;
 
     async fn init(
@@ -138,13 +371,49 @@ mod tests {
     ) -> (
         JWTSVIDValidator,
         SVIDFactory,
-        TrustBundle,
+        BTreeMap<String, TrustBundle>,
+        Config,
+        Arc<KeyManager>,
+    ) {
+        init_with_key_type(dir, KeyType::ES256).await
+    }
+
+    async fn init_with_key_type(
+        dir: &tempfile::TempDir,
+        key_type: KeyType,
+    ) -> (
+        JWTSVIDValidator,
+        SVIDFactory,
+        BTreeMap<String, TrustBundle>,
         Config,
         Arc<KeyManager>,
     ) {
         let mut config = Config::load_config(CONFIG_DEFAULT_PATH).unwrap();
+        config.jwt.key_type = key_type;
+        let (svid_factory, trust_bundle, key_manager) = init_trust_domain(dir, &config).await;
+
+        let svid_validator = JWTSVIDValidator::default();
+        let trust_bundles = BTreeMap::from([(config.trust_domain.clone(), trust_bundle)]);
+
+        (
+            svid_validator,
+            svid_factory,
+            trust_bundles,
+            config,
+            key_manager,
+        )
+    }
+
+    async fn init_trust_domain(
+        dir: &tempfile::TempDir,
+        config: &Config,
+    ) -> (SVIDFactory, TrustBundle, Arc<KeyManager>) {
+        let mut config = config.clone();
         let key_base_path = dir.path().to_str().unwrap().to_string();
-        let key_plugin = KeyStoreConfigDisk { key_base_path };
+        let key_plugin = KeyStoreConfigDisk {
+            key_base_path,
+            encryption: None,
+        };
 
         // Change key disk plugin path to write in tempdir
         config.key_store = KeyStoreConfig::Disk(key_plugin.clone());
@@ -152,59 +421,209 @@ mod tests {
         config.jwt.key_ttl = 10;
 
         let catalog = Arc::new(inmemory::Catalog::new());
-        let key_store = Arc::new(disk::KeyStore::new(&key_plugin));
+        let key_store = Arc::new(disk::KeyStore::new(&key_plugin).unwrap());
 
+        // The tokens of the factory are issued now, the key must not expire before.
         let key_manager = Arc::new(
-            KeyManager::new(&config, catalog.clone(), key_store.clone(), 0)
-                .await
-                .unwrap(),
+            KeyManager::new(
+                &config,
+                catalog.clone(),
+                key_store.clone(),
+                get_epoch_time(),
+            )
+            .await
+            .unwrap(),
         );
         let svid_factory = SVIDFactory::new(key_manager.clone(), &config);
 
-        let svid_validator = JWTSVIDValidator::default();
-
         let trust_bundle = TrustBundleBuilder::new(&config, catalog)
             .build_trust_bundle(true, true)
             .await
             .unwrap();
 
-        (
-            svid_validator,
-            svid_factory,
-            trust_bundle,
-            config,
-            key_manager,
-        )
+        (svid_factory, trust_bundle, key_manager)
     }
 
     #[tokio::test]
     async fn validate_happy_path() {
         let tmp = tempfile::tempdir().unwrap();
-        let (svid_validator, svid_factory, trust_bundle, _config, _key_manager) = init(&tmp).await;
+        let (svid_validator, svid_factory, trust_bundles, _config, _key_manager) = init(&tmp).await;
+
+        let jwt_svid_params = JWTSVIDParams {
+            spiffe_id_path: "path".to_string(),
+            audiences: vec!["myaudience".to_string()],
+            other_identities: Vec::new(),
+            pod_uid: None,
+            ttl: None,
+            custom_claims: Default::default(),
+        };
+
+        let jwt_svid = svid_factory.create_jwt_svid(jwt_svid_params).await.unwrap();
+
+        svid_validator
+            .validate_inner(
+                &jwt_svid.token,
+                &trust_bundles,
+                "myaudience",
+                jwt_svid.issued_at,
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn validate_federated() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (svid_validator, svid_factory, mut trust_bundles, config, _key_manager) =
+            init(&tmp).await;
+
+        let federated_tmp = tempfile::tempdir().unwrap();
+        let mut federated_config = config.clone();
+        federated_config.trust_domain = "federated.org".to_string();
+        let (federated_svid_factory, federated_trust_bundle, _federated_key_manager) =
+            init_trust_domain(&federated_tmp, &federated_config).await;
+
+        let jwt_svid_params = JWTSVIDParams {
+            spiffe_id_path: "path".to_string(),
+            audiences: vec!["myaudience".to_string()],
+            other_identities: Vec::new(),
+            pod_uid: None,
+            ttl: None,
+            custom_claims: Default::default(),
+        };
+        let jwt_svid = federated_svid_factory
+            .create_jwt_svid(jwt_svid_params.clone())
+            .await
+            .unwrap();
+
+        let error = svid_validator
+            .validate_inner(
+                &jwt_svid.token,
+                &trust_bundles,
+                "myaudience",
+                jwt_svid.issued_at,
+            )
+            .await
+            .unwrap_err();
+        assert_matches!(error, Error::UnknownTrustDomain(trust_domain) if trust_domain == "federated.org");
+
+        trust_bundles.insert("federated.org".to_string(), federated_trust_bundle);
+        let validated = svid_validator
+            .validate_inner(
+                &jwt_svid.token,
+                &trust_bundles,
+                "myaudience",
+                jwt_svid.issued_at,
+            )
+            .await
+            .unwrap();
+        assert_eq!(validated.claims.subject, "spiffe://federated.org/path");
+
+        // The tokens of the own trust domain are still checked with its own bundle.
+        let jwt_svid = svid_factory.create_jwt_svid(jwt_svid_params).await.unwrap();
+        svid_validator
+            .validate_inner(
+                &jwt_svid.token,
+                &trust_bundles,
+                "myaudience",
+                jwt_svid.issued_at,
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn validate_der_ecdsa_signature() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (svid_validator, svid_factory, trust_bundles, _config, _key_manager) = init(&tmp).await;
 
         let jwt_svid_params = JWTSVIDParams {
             spiffe_id_path: "path".to_string(),
             audiences: vec!["myaudience".to_string()],
             other_identities: Vec::new(),
+            pod_uid: None,
+            ttl: None,
+            custom_claims: Default::default(),
         };
 
         let jwt_svid = svid_factory.create_jwt_svid(jwt_svid_params).await.unwrap();
+        let segments = jwt_svid.token.split('.').collect::<Vec<&str>>();
+
+        // Re-encode the signature in DER, like the servers did before they followed JWS.
+        let signature = base64::decode_config(segments[2], base64::URL_SAFE_NO_PAD).unwrap();
+        assert_eq!(signature.len(), 64);
+        let signature = EcdsaSig::from_private_components(
+            BigNum::from_slice(&signature[..32]).unwrap(),
+            BigNum::from_slice(&signature[32..]).unwrap(),
+        )
+        .unwrap();
+        let signature = base64::encode_config(signature.to_der().unwrap(), base64::URL_SAFE_NO_PAD);
+        let token = format!("{}.{}.{}", segments[0], segments[1], signature);
 
         svid_validator
-            .validate_inner(&jwt_svid.token, &trust_bundle, "myaudience", 0)
+            .validate_inner(&token, &trust_bundles, "myaudience", jwt_svid.issued_at)
             .await
             .unwrap();
     }
 
+    #[tokio::test]
+    async fn validate_key_types_happy_path() {
+        for key_type in [
+            KeyType::ES384,
+            KeyType::ES512,
+            KeyType::RS256,
+            KeyType::PS384,
+            KeyType::RS512,
+            KeyType::EdDSA,
+        ] {
+            let tmp = tempfile::tempdir().unwrap();
+            let (svid_validator, svid_factory, trust_bundles, _config, _key_manager) =
+                init_with_key_type(&tmp, key_type).await;
+
+            let jwt_svid_params = JWTSVIDParams {
+                spiffe_id_path: "path".to_string(),
+                audiences: vec!["myaudience".to_string()],
+                other_identities: Vec::new(),
+                pod_uid: None,
+                ttl: None,
+                custom_claims: Default::default(),
+            };
+
+            let jwt_svid = svid_factory.create_jwt_svid(jwt_svid_params).await.unwrap();
+
+            let jwt_svid = svid_validator
+                .validate_inner(
+                    &jwt_svid.token,
+                    &trust_bundles,
+                    "myaudience",
+                    jwt_svid.issued_at,
+                )
+                .await
+                .unwrap();
+            assert_eq!(jwt_svid.header.algorithm, key_type);
+        }
+    }
+
+    #[test]
+    fn decode_member_both_alphabets() {
+        let member = [0xfb, 0xff, 0xbf];
+        assert_eq!(decode_member("-_-_").unwrap(), member);
+        // Keys saved before the members were base64url.
+        assert_eq!(decode_member("+/+/").unwrap(), member);
+    }
+
     #[tokio::test]
     async fn validate_invalid_signature() {
         let tmp = tempfile::tempdir().unwrap();
-        let (svid_validator, svid_factory, trust_bundle, _config, _key_manager) = init(&tmp).await;
+        let (svid_validator, svid_factory, trust_bundles, _config, _key_manager) = init(&tmp).await;
 
         let jwt_svid_params = JWTSVIDParams {
             spiffe_id_path: "path".to_string(),
             audiences: vec!["myaudience".to_string()],
             other_identities: Vec::new(),
+            pod_uid: None,
+            ttl: None,
+            custom_claims: Default::default(),
         };
 
         // Get token from a valid jwt
@@ -219,15 +638,19 @@ mod tests {
             spiffe_id_path: "hack".to_string(),
             audiences: vec!["myaudience".to_string()],
             other_identities: Vec::new(),
+            pod_uid: None,
+            ttl: None,
+            custom_claims: Default::default(),
         };
 
         let jwt_svid = svid_factory.create_jwt_svid(jwt_svid_params).await.unwrap();
+        let issued_at = jwt_svid.issued_at;
         let jwt_svid = jwt_svid.token.split('.').collect::<Vec<&str>>();
 
         let jwt_svid = format!("{}.{}.{}", jwt_svid[0], jwt_svid[1], token);
         // Try to valida the signature taken from a valid token and applied to a new token with "hack" as destination.
         let error = svid_validator
-            .validate_inner(&jwt_svid, &trust_bundle, "myaudience", 0)
+            .validate_inner(&jwt_svid, &trust_bundles, "myaudience", issued_at)
             .await
             .unwrap_err();
 
@@ -237,14 +660,14 @@ mod tests {
     #[tokio::test]
     async fn validate_invalid_token() {
         let tmp = tempfile::tempdir().unwrap();
-        let (svid_validator, _svid_factory, trust_bundle, config, _key_manager) = init(&tmp).await;
+        let (svid_validator, _svid_factory, trust_bundles, config, _key_manager) = init(&tmp).await;
         let audience_spiffe_id = format!(
             "{}{}/{}",
             SPIFFE_ID_PREFIX, &config.trust_domain, "myaudience"
         );
 
         let error = svid_validator
-            .validate_inner("dummy", &trust_bundle, &audience_spiffe_id.to_string(), 0)
+            .validate_inner("dummy", &trust_bundles, &audience_spiffe_id.to_string(), 0)
             .await
             .unwrap_err();
 
@@ -253,7 +676,7 @@ mod tests {
         let error = svid_validator
             .validate_inner(
                 "header.claim.token",
-                &trust_bundle,
+                &trust_bundles,
                 &audience_spiffe_id.to_string(),
                 0,
             )
@@ -266,7 +689,7 @@ mod tests {
         let token = base64::encode("dummy");
         let token = format!("{}.{}.{}", header, claim, token);
         let error = svid_validator
-            .validate_inner(&token, &trust_bundle, &audience_spiffe_id.to_string(), 0)
+            .validate_inner(&token, &trust_bundles, &audience_spiffe_id.to_string(), 0)
             .await
             .unwrap_err();
         assert_matches!(error, Error::DeserializeJson(_));
@@ -275,18 +698,37 @@ mod tests {
     #[tokio::test]
     async fn validate_expired() {
         let tmp = tempfile::tempdir().unwrap();
-        let (svid_validator, svid_factory, trust_bundle, _config, _key_manager) = init(&tmp).await;
+        let (svid_validator, svid_factory, trust_bundles, _config, _key_manager) = init(&tmp).await;
 
         let jwt_svid_params = JWTSVIDParams {
             spiffe_id_path: "path".to_string(),
             audiences: vec!["myaudience".to_string()],
             other_identities: Vec::new(),
+            pod_uid: None,
+            ttl: None,
+            custom_claims: Default::default(),
         };
 
         let jwt_svid = svid_factory.create_jwt_svid(jwt_svid_params).await.unwrap();
 
+        // Still valid during the leeway.
+        svid_validator
+            .validate_inner(
+                &jwt_svid.token,
+                &trust_bundles,
+                "myaudience",
+                jwt_svid.expiry + DEFAULT_LEEWAY,
+            )
+            .await
+            .unwrap();
+
         let error = svid_validator
-            .validate_inner(&jwt_svid.token, &trust_bundle, "myaudience", 12)
+            .validate_inner(
+                &jwt_svid.token,
+                &trust_bundles,
+                "myaudience",
+                jwt_svid.expiry + DEFAULT_LEEWAY + 1,
+            )
             .await
             .unwrap_err();
         assert_matches!(
@@ -298,30 +740,260 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn validate_not_yet_valid() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (svid_validator, _svid_factory, trust_bundles, config, key_manager) = init(&tmp).await;
+        let slots = &*key_manager.slots.read().await;
+        let jwt_key = &slots.current_jwt_key;
+
+        let header = JWTHeader {
+            algorithm: key_manager.jwt_key_type,
+            key_id: jwt_key.kid.clone(),
+            jwt_type: JWTType::JWT,
+        };
+        let claims = JWTClaims {
+            expiry: 200,
+            issued_at: 100,
+            not_before: Some(100),
+            ..claims(
+                format!("{}{}/path", SPIFFE_ID_PREFIX, config.trust_domain),
+                "myaudience".to_string(),
+            )
+        };
+        let token = encode_token(&header, &claims);
+
+        let error = svid_validator
+            .validate_inner(&token, &trust_bundles, "myaudience", 0)
+            .await
+            .unwrap_err();
+        assert_matches!(
+            error,
+            Error::TokenNotYetValid {
+                not_before: 100,
+                current: 0
+            }
+        );
+
+        // Without a nbf, the token is not valid before its iat.
+        let claims = JWTClaims {
+            not_before: None,
+            ..claims
+        };
+        let error = svid_validator
+            .validate_inner(
+                &encode_token(&header, &claims),
+                &trust_bundles,
+                "myaudience",
+                0,
+            )
+            .await
+            .unwrap_err();
+        assert_matches!(
+            error,
+            Error::TokenIssuedInFuture {
+                issued_at: 100,
+                current: 0
+            }
+        );
+
+        // Within the leeway, the times are accepted and the dummy signature is checked.
+        let error = svid_validator
+            .validate_inner(&token, &trust_bundles, "myaudience", 100 - DEFAULT_LEEWAY)
+            .await
+            .unwrap_err();
+        assert_matches!(error, Error::CannotConvertSignatureToEcdsaSignature(_));
+
+        let svid_validator = svid_validator.with_leeway(0);
+        let error = svid_validator
+            .validate_inner(&token, &trust_bundles, "myaudience", 99)
+            .await
+            .unwrap_err();
+        assert_matches!(error, Error::TokenNotYetValid { .. });
+    }
+
+    #[tokio::test]
+    async fn validate_invalid_lifetime() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (svid_validator, _svid_factory, trust_bundles, config, key_manager) = init(&tmp).await;
+        let slots = &*key_manager.slots.read().await;
+        let jwt_key = &slots.current_jwt_key;
+
+        let header = JWTHeader {
+            algorithm: key_manager.jwt_key_type,
+            key_id: jwt_key.kid.clone(),
+            jwt_type: JWTType::JWT,
+        };
+        let claims = JWTClaims {
+            issued_at: 20,
+            ..claims(
+                format!("{}{}/path", SPIFFE_ID_PREFIX, config.trust_domain),
+                "myaudience".to_string(),
+            )
+        };
+
+        let error = svid_validator
+            .validate_inner(
+                &encode_token(&header, &claims),
+                &trust_bundles,
+                "myaudience",
+                10,
+            )
+            .await
+            .unwrap_err();
+        assert_matches!(
+            error,
+            Error::InvalidLifetime {
+                issued_at: 20,
+                expiry: 10
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn validate_replayed() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (svid_validator, svid_factory, trust_bundles, _config, _key_manager) = init(&tmp).await;
+        let svid_validator = svid_validator.with_replay_check(Arc::new(ReplayCache::new(10)));
+
+        let jwt_svid_params = JWTSVIDParams {
+            spiffe_id_path: "path".to_string(),
+            audiences: vec!["myaudience".to_string()],
+            other_identities: Vec::new(),
+            pod_uid: None,
+            ttl: None,
+            custom_claims: Default::default(),
+        };
+
+        let jwt_svid = svid_factory
+            .create_jwt_svid(jwt_svid_params.clone())
+            .await
+            .unwrap();
+        let validated = svid_validator
+            .validate_inner(
+                &jwt_svid.token,
+                &trust_bundles,
+                "myaudience",
+                jwt_svid.issued_at,
+            )
+            .await
+            .unwrap();
+        assert!(validated.claims.jwt_id.is_some());
+
+        let error = svid_validator
+            .validate_inner(
+                &jwt_svid.token,
+                &trust_bundles,
+                "myaudience",
+                jwt_svid.issued_at,
+            )
+            .await
+            .unwrap_err();
+        assert_matches!(error, Error::TokenReplayed(_));
+
+        // Another token of the same workload has its own jti.
+        let jwt_svid = svid_factory.create_jwt_svid(jwt_svid_params).await.unwrap();
+        svid_validator
+            .validate_inner(
+                &jwt_svid.token,
+                &trust_bundles,
+                "myaudience",
+                jwt_svid.issued_at,
+            )
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn validate_jwt_invalid_audience() {
         let tmp = tempfile::tempdir().unwrap();
-        let (svid_validator, svid_factory, trust_bundle, _config, _key_manager) = init(&tmp).await;
+        let (svid_validator, svid_factory, trust_bundles, _config, _key_manager) = init(&tmp).await;
 
         let jwt_svid_params = JWTSVIDParams {
             spiffe_id_path: "path".to_string(),
             audiences: vec!["myaudience".to_string()],
             other_identities: Vec::new(),
+            pod_uid: None,
+            ttl: None,
+            custom_claims: Default::default(),
+        };
+
+        let jwt_svid = svid_factory.create_jwt_svid(jwt_svid_params).await.unwrap();
+
+        let error = svid_validator
+            .validate_inner(
+                &jwt_svid.token,
+                &trust_bundles,
+                "wrongaudience",
+                jwt_svid.issued_at,
+            )
+            .await
+            .unwrap_err();
+        assert_matches!(error, Error::InvalidAudience(_));
+    }
+
+    #[tokio::test]
+    async fn validate_jwt_spiffe_id_audience() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (svid_validator, svid_factory, trust_bundles, config, _key_manager) = init(&tmp).await;
+        let audience_spiffe_id = format!("{}{}/broker", SPIFFE_ID_PREFIX, config.trust_domain);
+
+        let jwt_svid_params = JWTSVIDParams {
+            spiffe_id_path: "path".to_string(),
+            audiences: vec![format!("{}/", audience_spiffe_id)],
+            other_identities: Vec::new(),
+            pod_uid: None,
+            ttl: None,
+            custom_claims: Default::default(),
         };
 
         let jwt_svid = svid_factory.create_jwt_svid(jwt_svid_params).await.unwrap();
 
+        svid_validator
+            .validate_inner(
+                &jwt_svid.token,
+                &trust_bundles,
+                &audience_spiffe_id,
+                jwt_svid.issued_at,
+            )
+            .await
+            .unwrap();
+
+        let svid_validator = JWTSVIDValidator::new(AudienceOptions {
+            exact_match: true,
+            ..Default::default()
+        });
         let error = svid_validator
-            .validate_inner(&jwt_svid.token, &trust_bundle, "wrongaudience", 0)
+            .validate_inner(
+                &jwt_svid.token,
+                &trust_bundles,
+                &audience_spiffe_id,
+                jwt_svid.issued_at,
+            )
             .await
             .unwrap_err();
         assert_matches!(error, Error::InvalidAudience(_));
+
+        let svid_validator = JWTSVIDValidator::new(AudienceOptions {
+            trust_domain: Some("other.org".to_string()),
+            ..Default::default()
+        });
+        let error = svid_validator
+            .validate_inner(
+                &jwt_svid.token,
+                &trust_bundles,
+                &audience_spiffe_id,
+                jwt_svid.issued_at,
+            )
+            .await
+            .unwrap_err();
+        assert_matches!(error, Error::AudienceNotInTrustDomain { .. });
     }
 
     #[tokio::test]
     async fn validate_jwt_invalid_algorithm() {
         let tmp = tempfile::tempdir().unwrap();
-        let (svid_validator, _svid_factory, trust_bundle, config, key_manager) = init(&tmp).await;
+        let (svid_validator, _svid_factory, trust_bundles, config, key_manager) = init(&tmp).await;
         let slots = &*key_manager.slots.read().await;
         let jwt_key = &slots.current_jwt_key;
 
@@ -332,15 +1004,15 @@ mod tests {
         );
 
         let header = JWTHeader {
-            algorithm: KeyType::PS512, //unimplemented algorithm
-            key_id: jwt_key.id.clone(),
+            algorithm: KeyType::ES384, // does not match the ES256 key of the trust bundle
+            key_id: jwt_key.kid.clone(),
             jwt_type: JWTType::JWT,
         };
 
         let token = get_token(&header, spiffe_id.clone(), audience_spiffe_id.clone());
 
         let error = svid_validator
-            .validate_inner(&token, &trust_bundle, &audience_spiffe_id, 0)
+            .validate_inner(&token, &trust_bundles, &audience_spiffe_id, 0)
             .await
             .unwrap_err();
         assert_matches!(error, Error::InvalidAlgorithm(_));
@@ -349,7 +1021,7 @@ mod tests {
     #[tokio::test]
     async fn validate_jwt_invalid_kid() {
         let tmp = tempfile::tempdir().unwrap();
-        let (svid_validator, _svid_factory, trust_bundle, config, key_manager) = init(&tmp).await;
+        let (svid_validator, _svid_factory, trust_bundles, config, key_manager) = init(&tmp).await;
         let _slots = &*key_manager.slots.read().await;
 
         let spiffe_id = format!("{}{}/{}", SPIFFE_ID_PREFIX, config.trust_domain, "path");
@@ -367,7 +1039,7 @@ mod tests {
         let token = get_token(&header, spiffe_id.clone(), audience_spiffe_id.clone());
 
         let error = svid_validator
-            .validate_inner(&token, &trust_bundle, &audience_spiffe_id, 0)
+            .validate_inner(&token, &trust_bundles, &audience_spiffe_id, 0)
             .await
             .unwrap_err();
         assert_matches!(error, Error::PublicKeyNotInTrustBundle(_));
@@ -376,7 +1048,7 @@ mod tests {
     #[tokio::test]
     async fn validate_jwt_invalid_jwt_type() {
         let tmp = tempfile::tempdir().unwrap();
-        let (svid_validator, _svid_factory, trust_bundle, config, key_manager) = init(&tmp).await;
+        let (svid_validator, _svid_factory, trust_bundles, config, key_manager) = init(&tmp).await;
         let slots = &*key_manager.slots.read().await;
         let jwt_key = &slots.current_jwt_key;
 
@@ -388,38 +1060,50 @@ mod tests {
 
         let header = JWTHeader {
             algorithm: key_manager.jwt_key_type,
-            key_id: jwt_key.id.clone(),
+            key_id: jwt_key.kid.clone(),
             jwt_type: JWTType::JOSE,
         };
 
         let token = get_token(&header, spiffe_id.clone(), audience_spiffe_id.clone());
 
         let error = svid_validator
-            .validate_inner(&token, &trust_bundle, &audience_spiffe_id, 0)
+            .validate_inner(&token, &trust_bundles, &audience_spiffe_id, 0)
             .await
             .unwrap_err();
         assert_matches!(error, Error::InvalidJWTType(_));
     }
 
-    fn get_token(header: &JWTHeader, spiffe_id: String, audience_spiffe_id: String) -> String {
-        let claims = JWTClaims {
+    fn claims(spiffe_id: String, audience_spiffe_id: String) -> JWTClaims {
+        JWTClaims {
             subject: spiffe_id,
             audience: vec![audience_spiffe_id],
             expiry: 10,
             issued_at: 0,
+            jwt_id: None,
+            not_before: None,
+            issuer: None,
             other_identities: Vec::new(),
-        };
+            pod_uid: None,
+            custom_claims: Default::default(),
+        }
+    }
+
+    fn get_token(header: &JWTHeader, spiffe_id: String, audience_spiffe_id: String) -> String {
+        encode_token(header, &claims(spiffe_id, audience_spiffe_id))
+    }
 
+    // With a dummy signature.
+    fn encode_token(header: &JWTHeader, claims: &JWTClaims) -> String {
         let header_compact = serde_json::to_string(header).unwrap();
         let header_compact =
-            base64::encode_config(header_compact.as_bytes(), base64::STANDARD_NO_PAD);
+            base64::encode_config(header_compact.as_bytes(), base64::URL_SAFE_NO_PAD);
 
-        let claims_compact = serde_json::to_string(&claims).unwrap();
+        let claims_compact = serde_json::to_string(claims).unwrap();
         let claims_compact =
-            base64::encode_config(claims_compact.as_bytes(), base64::STANDARD_NO_PAD);
+            base64::encode_config(claims_compact.as_bytes(), base64::URL_SAFE_NO_PAD);
 
         let dummy_signature =
-            base64::encode_config("dummysignature".as_bytes(), base64::STANDARD_NO_PAD);
+            base64::encode_config("dummysignature".as_bytes(), base64::URL_SAFE_NO_PAD);
 
         format!("{}.{}.{}", header_compact, claims_compact, dummy_signature)
     }
@@ -0,0 +1,191 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::default_trait_access,
+    clippy::let_unit_value,
+    clippy::missing_errors_doc,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+// Logger of the server and the agent. The text format is the one of the other IoT Edge services. The JSON
+// format writes one object per line for log collectors such as Azure Monitor or ELK, with the key-values
+// of the records as fields:
+// `log::info!(spiffe_id = spiffe_id.as_str(), entry_id = entry.id.as_str(); "...")`.
+// The fields used across the components are `trust_domain`, `spiffe_id`, `entry_id` and `request_id`.
+// Both formats take the level filters from `AZIOT_LOG_LEVEL`.
+
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    time::SystemTime,
+};
+
+use log::{
+    kv::{self, Key, Source, Value, Visitor},
+    LevelFilter, Log, Metadata, Record, SetLoggerError,
+};
+
+const LOG_LEVEL_ENV_VAR: &str = "AZIOT_LOG_LEVEL";
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        LogFormat::Text
+    }
+}
+
+pub fn try_init(format: LogFormat, trust_domain: Option<&str>) -> Result<(), SetLoggerError> {
+    match format {
+        LogFormat::Text => logger::try_init(),
+        LogFormat::Json => {
+            let mut filter = env_logger::filter::Builder::new();
+            filter.filter_level(LevelFilter::Info);
+            if let Ok(filters) = std::env::var(LOG_LEVEL_ENV_VAR) {
+                filter.parse(&filters);
+            }
+
+            let logger = JsonLogger {
+                filter: filter.build(),
+                trust_domain: trust_domain.map(ToString::to_string),
+            };
+            log::set_max_level(logger.filter.filter());
+            log::set_boxed_logger(Box::new(logger))
+        }
+    }
+}
+
+struct JsonLogger {
+    filter: env_logger::filter::Filter,
+    // Added to every record, the logs of several trust domains are often collected together.
+    trust_domain: Option<String>,
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.filter.matches(record) {
+            return;
+        }
+
+        let line = format_record(record, self.trust_domain.as_deref(), SystemTime::now());
+
+        // Same as the text logger, a log that cannot be written is dropped.
+        let stderr = io::stderr();
+        writeln!(stderr.lock(), "{}", line).ok();
+    }
+
+    fn flush(&self) {
+        io::stderr().flush().ok();
+    }
+}
+
+#[derive(serde::Serialize)]
+struct JsonRecord<'a> {
+    timestamp: String,
+    level: &'a str,
+    target: &'a str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trust_domain: Option<&'a str>,
+    #[serde(flatten)]
+    fields: BTreeMap<String, String>,
+}
+
+// The fields keep the order of the keys, so the lines are stable for a given record.
+#[derive(Default)]
+struct Fields(BTreeMap<String, String>);
+
+impl<'kvs> Visitor<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.insert(key.as_str().to_string(), value.to_string());
+
+        Ok(())
+    }
+}
+
+fn format_record(record: &Record<'_>, trust_domain: Option<&str>, now: SystemTime) -> String {
+    let mut fields = Fields::default();
+    record.key_values().visit(&mut fields).ok();
+
+    let json_record = JsonRecord {
+        timestamp: humantime::format_rfc3339_millis(now).to_string(),
+        level: record.level().as_str(),
+        target: record.target(),
+        message: record.args().to_string(),
+        trust_domain,
+        fields: fields.0,
+    };
+
+    serde_json::to_string(&json_record).unwrap_or_else(|_| {
+        "{\"level\":\"ERROR\",\"message\":\"Could not serialize a log record\"}".to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use log::Level;
+
+    use super::*;
+
+    #[test]
+    fn format_record_with_fields() {
+        let fields: &[(&str, &dyn kv::ToValue)] = &[
+            ("spiffe_id", &"spiffe://iotedge/workload"),
+            ("entry_id", &"entry"),
+        ];
+        let now = SystemTime::UNIX_EPOCH + Duration::from_millis(1_500);
+
+        let line = format_record(
+            &Record::builder()
+                .args(format_args!("Issued {} JWT-SVIDs", 2))
+                .level(Level::Info)
+                .target("audit")
+                .key_values(&fields)
+                .build(),
+            Some("iotedge"),
+            now,
+        );
+
+        assert_eq!(
+            line,
+            "{\"timestamp\":\"1970-01-01T00:00:01.500Z\",\"level\":\"INFO\",\"target\":\"audit\",\
+             \"message\":\"Issued 2 JWT-SVIDs\",\"trust_domain\":\"iotedge\",\
+             \"entry_id\":\"entry\",\"spiffe_id\":\"spiffe://iotedge/workload\"}"
+        );
+    }
+
+    #[test]
+    fn format_record_without_trust_domain() {
+        let now = SystemTime::UNIX_EPOCH;
+
+        let line = format_record(
+            &Record::builder()
+                .args(format_args!("Starting"))
+                .level(Level::Warn)
+                .target("serverd")
+                .build(),
+            None,
+            now,
+        );
+
+        assert_eq!(
+            line,
+            "{\"timestamp\":\"1970-01-01T00:00:00.000Z\",\"level\":\"WARN\",\"target\":\"serverd\",\
+             \"message\":\"Starting\"}"
+        );
+    }
+}
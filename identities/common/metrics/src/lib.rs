@@ -0,0 +1,277 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::default_trait_access,
+    clippy::let_unit_value,
+    clippy::missing_errors_doc,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+// Metrics of the server and the agent in the Prometheus text format. The components own their metrics
+// and register them at startup, the registry renders them when `/metrics` is scraped. Metrics already
+// counted by a component, e.g. the key rotations, are registered as functions read at scrape time
+// instead of being counted twice.
+
+use std::{
+    fmt::Write,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+pub mod server;
+
+// Buckets of the latency histograms, in seconds.
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct MetricsConfig {
+    // Address of the `/metrics` endpoint, e.g. "0.0.0.0:9090".
+    pub address: SocketAddr,
+}
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    pub fn inc_by(&self, value: u64) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicU64);
+
+impl Gauge {
+    pub fn set(&self, value: u64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// Durations, counted in the first bucket they fit in. The sum is kept in microseconds.
+#[derive(Debug)]
+pub struct Histogram {
+    buckets: Vec<f64>,
+    counts: Vec<AtomicU64>,
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    #[must_use]
+    pub fn new(buckets: &[f64]) -> Self {
+        Histogram {
+            buckets: buckets.to_vec(),
+            counts: buckets.iter().map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        if let Some(index) = self.buckets.iter().position(|bucket| secs <= *bucket) {
+            self.counts[index].fetch_add(1, Ordering::Relaxed);
+        }
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(
+            u64::try_from(duration.as_micros()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    fn render(&self, name: &str, out: &mut String) {
+        let mut cumulative = 0;
+        for (bucket, count) in self.buckets.iter().zip(&self.counts) {
+            cumulative += count.load(Ordering::Relaxed);
+            writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bucket, cumulative).ok();
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count()).ok();
+        writeln!(out, "{}_sum {}", name, sum).ok();
+        writeln!(out, "{}_count {}", name, self.count()).ok();
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram::new(LATENCY_BUCKETS)
+    }
+}
+
+enum Metric {
+    Counter(Arc<Counter>),
+    Gauge(Arc<Gauge>),
+    Histogram(Arc<Histogram>),
+    CounterFn(Box<dyn Fn() -> u64 + Send + Sync>),
+    GaugeFn(Box<dyn Fn() -> u64 + Send + Sync>),
+}
+
+struct Entry {
+    name: String,
+    help: String,
+    metric: Metric,
+}
+
+// Metrics are registered before the endpoint is started, the registry is then shared read only.
+#[derive(Default)]
+pub struct Registry {
+    entries: Vec<Entry>,
+}
+
+impl Registry {
+    pub fn counter(&mut self, name: &str, help: &str) -> Arc<Counter> {
+        let counter = Arc::new(Counter::default());
+        self.register(name, help, Metric::Counter(counter.clone()));
+
+        counter
+    }
+
+    pub fn gauge(&mut self, name: &str, help: &str) -> Arc<Gauge> {
+        let gauge = Arc::new(Gauge::default());
+        self.register(name, help, Metric::Gauge(gauge.clone()));
+
+        gauge
+    }
+
+    pub fn histogram(&mut self, name: &str, help: &str, buckets: &[f64]) -> Arc<Histogram> {
+        let histogram = Arc::new(Histogram::new(buckets));
+        self.register(name, help, Metric::Histogram(histogram.clone()));
+
+        histogram
+    }
+
+    pub fn counter_fn(
+        &mut self,
+        name: &str,
+        help: &str,
+        counter: impl Fn() -> u64 + Send + Sync + 'static,
+    ) {
+        self.register(name, help, Metric::CounterFn(Box::new(counter)));
+    }
+
+    pub fn gauge_fn(
+        &mut self,
+        name: &str,
+        help: &str,
+        gauge: impl Fn() -> u64 + Send + Sync + 'static,
+    ) {
+        self.register(name, help, Metric::GaugeFn(Box::new(gauge)));
+    }
+
+    fn register(&mut self, name: &str, help: &str, metric: Metric) {
+        self.entries.push(Entry {
+            name: name.to_string(),
+            help: help.to_string(),
+            metric,
+        });
+    }
+
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        for entry in &self.entries {
+            let metric_type = match entry.metric {
+                Metric::Counter(_) | Metric::CounterFn(_) => "counter",
+                Metric::Gauge(_) | Metric::GaugeFn(_) => "gauge",
+                Metric::Histogram(_) => "histogram",
+            };
+            writeln!(out, "# HELP {} {}", entry.name, entry.help).ok();
+            writeln!(out, "# TYPE {} {}", entry.name, metric_type).ok();
+
+            let value = match &entry.metric {
+                Metric::Counter(counter) => counter.get(),
+                Metric::Gauge(gauge) => gauge.get(),
+                Metric::CounterFn(counter) => counter(),
+                Metric::GaugeFn(gauge) => gauge(),
+                Metric::Histogram(histogram) => {
+                    histogram.render(&entry.name, &mut out);
+                    continue;
+                }
+            };
+            writeln!(out, "{} {}", entry.name, value).ok();
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_text_format() {
+        let mut registry = Registry::default();
+        let counter = registry.counter("e4k_requests_total", "Requests.");
+        let gauge = registry.gauge("e4k_entries", "Entries.");
+        registry.counter_fn("e4k_rotations_total", "Rotations.", || 7);
+        counter.inc();
+        counter.inc_by(2);
+        gauge.set(5);
+
+        assert_eq!(
+            registry.render(),
+            "# HELP e4k_requests_total Requests.\n\
+             # TYPE e4k_requests_total counter\n\
+             e4k_requests_total 3\n\
+             # HELP e4k_entries Entries.\n\
+             # TYPE e4k_entries gauge\n\
+             e4k_entries 5\n\
+             # HELP e4k_rotations_total Rotations.\n\
+             # TYPE e4k_rotations_total counter\n\
+             e4k_rotations_total 7\n"
+        );
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let mut registry = Registry::default();
+        let histogram = registry.histogram("e4k_latency_seconds", "Latency.", &[0.1, 1.0]);
+        histogram.observe(Duration::from_millis(50));
+        histogram.observe(Duration::from_millis(500));
+        histogram.observe(Duration::from_secs(2));
+
+        assert_eq!(
+            registry.render(),
+            "# HELP e4k_latency_seconds Latency.\n\
+             # TYPE e4k_latency_seconds histogram\n\
+             e4k_latency_seconds_bucket{le=\"0.1\"} 1\n\
+             e4k_latency_seconds_bucket{le=\"1\"} 2\n\
+             e4k_latency_seconds_bucket{le=\"+Inf\"} 3\n\
+             e4k_latency_seconds_sum 2.55\n\
+             e4k_latency_seconds_count 3\n"
+        );
+    }
+}
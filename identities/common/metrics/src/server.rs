@@ -0,0 +1,57 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// `/metrics` endpoint scraped by Prometheus, on its own TCP listener.
+
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use hyper::{
+    header::{HeaderValue, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use log::{error, info};
+use tokio::task::JoinHandle;
+
+use crate::Registry;
+
+const METRICS_PATH: &str = "/metrics";
+const TEXT_FORMAT: &str = "text/plain; version=0.0.4";
+
+pub fn start(address: SocketAddr, registry: Arc<Registry>) -> Result<JoinHandle<()>, hyper::Error> {
+    let make_service = make_service_fn(move |_| {
+        let registry = registry.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let registry = registry.clone();
+
+                async move { Ok::<_, Infallible>(handle(&request, &registry)) }
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&address)?.serve(make_service);
+
+    info!("Starting metrics endpoint on {}", address);
+    Ok(tokio::spawn(async move {
+        if let Err(err) = server.await {
+            error!("Error serving the metrics endpoint {}", err);
+        }
+    }))
+}
+
+fn handle(request: &Request<Body>, registry: &Registry) -> Response<Body> {
+    if request.method() != Method::GET || request.uri().path() != METRICS_PATH {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NOT_FOUND;
+
+        return response;
+    }
+
+    let mut response = Response::new(Body::from(registry.render()));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(TEXT_FORMAT));
+
+    response
+}
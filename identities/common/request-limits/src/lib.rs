@@ -0,0 +1,235 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::default_trait_access,
+    clippy::let_unit_value,
+    clippy::missing_errors_doc,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+// Limits on the size of the request bodies and on the time taken to process a request. A request
+// over a limit gets a structured error back instead of being buffered or processed without end.
+// Endpoints are split in classes so batch endpoints can take larger bodies than the others.
+
+pub mod service;
+
+use std::time::Duration;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use http::{header, HeaderValue, Response, StatusCode};
+use hyper::{body::HttpBody, Body};
+
+const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+const DEFAULT_TIMEOUT_MS: u64 = 30_000;
+const BATCH_MAX_BODY_BYTES: usize = 64 * 1024 * 1024;
+const BATCH_TIMEOUT_MS: u64 = 120_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Limits {
+    pub max_body_bytes: usize,
+    pub timeout_ms: u64,
+}
+
+impl Limits {
+    #[must_use]
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_ms)
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            timeout_ms: DEFAULT_TIMEOUT_MS,
+        }
+    }
+}
+
+fn default_batch_limits() -> Limits {
+    Limits {
+        max_body_bytes: BATCH_MAX_BODY_BYTES,
+        timeout_ms: BATCH_TIMEOUT_MS,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EndpointClass {
+    Default,
+    // Endpoints taking a list of items, like the creation of registration entries.
+    Batch,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct EndpointLimits {
+    #[serde(default)]
+    pub default: Limits,
+    #[serde(default = "default_batch_limits")]
+    pub batch: Limits,
+}
+
+impl EndpointLimits {
+    #[must_use]
+    pub fn get(&self, class: EndpointClass) -> Limits {
+        match class {
+            EndpointClass::Default => self.default,
+            EndpointClass::Batch => self.batch,
+        }
+    }
+}
+
+impl Default for EndpointLimits {
+    fn default() -> Self {
+        EndpointLimits {
+            default: Limits::default(),
+            batch: default_batch_limits(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Request body is larger than the limit of {0} bytes")]
+    TooLarge(usize),
+    #[error("Request was not processed within the limit of {0} ms")]
+    Timeout(u64),
+    #[error("Could not read the request body: {0}")]
+    Body(hyper::Error),
+}
+
+// Body of the responses to requests over a limit. The message is in the same field as the other
+// errors of the APIs, the limit that was hit is added for the callers that split their requests.
+#[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ErrorBody {
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_body_bytes: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+}
+
+impl Error {
+    #[must_use]
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            Error::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::Timeout(_) => StatusCode::REQUEST_TIMEOUT,
+            Error::Body(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    #[must_use]
+    pub fn error_body(&self) -> ErrorBody {
+        let (max_body_bytes, timeout_ms) = match self {
+            Error::TooLarge(max_body_bytes) => (Some(*max_body_bytes), None),
+            Error::Timeout(timeout_ms) => (None, Some(*timeout_ms)),
+            Error::Body(_) => (None, None),
+        };
+
+        ErrorBody {
+            message: self.to_string(),
+            max_body_bytes,
+            timeout_ms,
+        }
+    }
+
+    #[must_use]
+    pub fn to_response(&self) -> Response<Body> {
+        // Serializing a struct of strings and integers cannot fail.
+        let body = serde_json::to_string(&self.error_body()).unwrap_or_default();
+
+        let mut response = Response::new(Body::from(body));
+        *response.status_mut() = self.status_code();
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+
+        response
+    }
+}
+
+// Reads the whole body, stops as soon as it is over the limit so a large body is never buffered.
+pub async fn read_body(mut body: Body, max_body_bytes: usize) -> Result<Bytes, Error> {
+    // The length is known upfront when the content length is set, the body is not read at all then.
+    let length = usize::try_from(body.size_hint().lower()).unwrap_or(usize::MAX);
+    if length > max_body_bytes {
+        return Err(Error::TooLarge(max_body_bytes));
+    }
+
+    let mut buffer = BytesMut::with_capacity(length);
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(Error::Body)?;
+
+        if buffer.len() + chunk.len() > max_body_bytes {
+            return Err(Error::TooLarge(max_body_bytes));
+        }
+        buffer.put(chunk);
+    }
+
+    Ok(buffer.freeze())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matches::assert_matches;
+
+    #[tokio::test]
+    async fn read_body_happy_path() {
+        let body = read_body(Body::from("0123456789"), 10).await.unwrap();
+
+        assert_eq!(body, Bytes::from("0123456789"));
+    }
+
+    #[tokio::test]
+    async fn read_body_too_large() {
+        let error = read_body(Body::from("0123456789"), 9).await.unwrap_err();
+
+        assert_matches!(error, Error::TooLarge(9));
+    }
+
+    #[tokio::test]
+    async fn read_body_too_large_without_length() {
+        // Streamed bodies have no length, they are stopped once the limit is reached.
+        let chunks: Vec<Result<_, std::io::Error>> = vec![Ok("01234"), Ok("56789")];
+        let body = Body::wrap_stream(futures_util::stream::iter(chunks));
+
+        let error = read_body(body, 9).await.unwrap_err();
+
+        assert_matches!(error, Error::TooLarge(9));
+    }
+
+    #[test]
+    fn error_response() {
+        let response = Error::TooLarge(10).to_response();
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            Error::Timeout(10).error_body(),
+            ErrorBody {
+                message: "Request was not processed within the limit of 10 ms".to_string(),
+                max_body_bytes: None,
+                timeout_ms: Some(10),
+            }
+        );
+    }
+
+    #[test]
+    fn batch_limits_default() {
+        let limits: EndpointLimits = toml::from_str(
+            "
+            [default]
+            max_body_bytes = 10
+            timeout_ms = 100
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(limits.get(EndpointClass::Default).max_body_bytes, 10);
+        assert_eq!(limits.get(EndpointClass::Batch), default_batch_limits());
+    }
+}
@@ -0,0 +1,174 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Wraps the service of an HTTP API: the body is read up to the limit of the endpoint class before
+// the request is handed to the API, and the whole request must be processed before the deadline.
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use http::{Method, Request, Response};
+use hyper::Body;
+use tokio::time::{self, Instant};
+use tower_service::Service;
+
+use crate::{read_body, EndpointClass, EndpointLimits, Error};
+
+#[derive(Clone)]
+pub struct LimitedService<S> {
+    inner: S,
+    limits: EndpointLimits,
+    classify: fn(&Method, &str) -> EndpointClass,
+}
+
+impl<S> LimitedService<S> {
+    // `classify` gets the method and the path of each request.
+    #[must_use]
+    pub fn new(
+        inner: S,
+        limits: EndpointLimits,
+        classify: fn(&Method, &str) -> EndpointClass,
+    ) -> Self {
+        LimitedService {
+            inner,
+            limits,
+            classify,
+        }
+    }
+}
+
+impl<S> Service<Request<Body>> for LimitedService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+{
+    type Response = Response<Body>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<Body>, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let limits = self
+            .limits
+            .get((self.classify)(req.method(), req.uri().path()));
+
+        // The service polled ready handles this request, the clone is kept for the next one.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            let deadline = Instant::now() + limits.timeout();
+            let timeout = || Ok(Error::Timeout(limits.timeout_ms).to_response());
+
+            let (parts, body) = req.into_parts();
+            let read = read_body(body, limits.max_body_bytes);
+            let body = match time::timeout_at(deadline, read).await {
+                Ok(Ok(body)) => body,
+                Ok(Err(err)) => return Ok(err.to_response()),
+                Err(_) => return timeout(),
+            };
+
+            // Requests stopped at the deadline are dropped where they are: a batch that is not
+            // transactional may be partially applied.
+            let req = Request::from_parts(parts, Body::from(body));
+            match time::timeout_at(deadline, inner.call(req)).await {
+                Ok(response) => response,
+                Err(_) => timeout(),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use http::StatusCode;
+    use hyper::service::service_fn;
+
+    use super::*;
+    use crate::{ErrorBody, Limits};
+
+    fn limits() -> EndpointLimits {
+        EndpointLimits {
+            default: Limits {
+                max_body_bytes: 4,
+                timeout_ms: 1000,
+            },
+            batch: Limits {
+                max_body_bytes: 16,
+                timeout_ms: 1000,
+            },
+        }
+    }
+
+    fn classify(method: &Method, path: &str) -> EndpointClass {
+        if method == Method::POST && path == "/batch" {
+            EndpointClass::Batch
+        } else {
+            EndpointClass::Default
+        }
+    }
+
+    async fn echo(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        if req.uri().path() == "/slow" {
+            time::sleep(Duration::from_secs(5)).await;
+        }
+
+        Ok(Response::new(req.into_body()))
+    }
+
+    async fn call(path: &str, body: &'static str) -> Response<Body> {
+        let mut service = LimitedService::new(service_fn(echo), limits(), classify);
+        let req = Request::post(path).body(Body::from(body)).unwrap();
+
+        service.call(req).await.unwrap()
+    }
+
+    async fn error_body(response: Response<Body>) -> ErrorBody {
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn body_under_limit() {
+        let response = call("/", "0123").await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body, "0123");
+    }
+
+    #[tokio::test]
+    async fn body_over_limit() {
+        let response = call("/", "01234").await;
+
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(error_body(response).await.max_body_bytes, Some(4));
+    }
+
+    #[tokio::test]
+    async fn body_under_batch_limit() {
+        let response = call("/batch", "01234").await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn request_over_deadline() {
+        let response = call("/slow", "").await;
+
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+        assert_eq!(error_body(response).await.timeout_ms, Some(1000));
+    }
+}
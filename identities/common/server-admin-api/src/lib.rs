@@ -44,6 +44,9 @@ pub mod create_registration_entries {
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
     pub struct Request {
         pub entries: Vec<RegistrationEntry>,
+        // Apply all the entries or none of them. Not supported by every catalog backend.
+        #[serde(default)]
+        pub transactional: bool,
     }
 
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -60,6 +63,8 @@ pub mod update_registration_entries {
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
     pub struct Request {
         pub entries: Vec<RegistrationEntry>,
+        #[serde(default)]
+        pub transactional: bool,
     }
 
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -71,9 +76,15 @@ pub mod update_registration_entries {
 pub mod list_all {
     use core_objects::RegistrationEntry;
 
+    #[derive(Default)]
     pub struct Params {
         pub page_size: u32,
         pub page_token: Option<String>,
+        // Only the entries matching all the filters that are set are listed.
+        pub parent_id: Option<String>,
+        pub selector: Option<String>,
+        pub spiffe_id_path_prefix: Option<String>,
+        pub plugin: Option<String>,
     }
 
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -105,6 +116,8 @@ pub mod delete_registration_entries {
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
     pub struct Request {
         pub ids: Vec<String>,
+        #[serde(default)]
+        pub transactional: bool,
     }
 
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -113,11 +126,342 @@ pub mod delete_registration_entries {
     }
 }
 
+pub mod apply_entries {
+    use core_objects::RegistrationEntry;
+
+    use crate::operation;
+
+    // The entries of the catalog are made the same as `entries`: the missing entries are created, the different
+    // ones updated and the others deleted. With `spiffe_id_path_prefix`, only the entries under the prefix are
+    // managed. Revision numbers are ignored.
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Request {
+        pub entries: Vec<RegistrationEntry>,
+        #[serde(default)]
+        pub spiffe_id_path_prefix: Option<String>,
+        // Only compute the changes, without applying them.
+        #[serde(default)]
+        pub dry_run: bool,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub create: Vec<String>,
+        pub update: Vec<String>,
+        pub delete: Vec<String>,
+        pub results: Result<(), Vec<operation::Error>>,
+    }
+}
+
+pub mod export_snapshot {
+    use core_objects::RegistrationEntry;
+
+    // Version of the snapshot format, bumped when a snapshot cannot be imported by an older server.
+    pub const SNAPSHOT_VERSION: u32 = 1;
+
+    // The exported snapshot is the body to import it again.
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub version: u32,
+        pub created_at: u64,
+        pub entries: Vec<RegistrationEntry>,
+    }
+}
+
+pub mod import_snapshot {
+    use crate::operation;
+
+    pub type Request = crate::export_snapshot::Response;
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub results: Result<(), Vec<operation::Error>>,
+    }
+}
+
+pub mod get_trust_bundle {
+    use core_objects::{TrustBundle, JWK};
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub trust_bundle: TrustBundle,
+        // The JWT keys of the trust bundle as a plain JWK set, for the JWT libraries that only take that.
+        pub jwks: JWKS,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct JWKS {
+        pub keys: Vec<JWK>,
+    }
+}
+
+pub mod get_trust_bundle_history {
+    use core_objects::JWKSetVersion;
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub versions: Vec<JWKSetVersion>,
+    }
+}
+
+pub mod rollback_trust_bundle {
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Request {
+        pub version: usize,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub version: usize,
+    }
+}
+
+pub mod revoke_signing_key {
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub revoked_key_id: String,
+        pub current_key_id: String,
+        // Version of the trust bundle without the revoked key.
+        pub version: usize,
+    }
+}
+
+pub mod get_info {
+    use build_info::BuildInfo;
+
+    #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub server_version: String,
+        pub trust_domain: String,
+        pub catalog: Backend,
+        pub key_store: Backend,
+        // Older servers do not send this field.
+        #[serde(default)]
+        pub build: Option<BuildInfo>,
+    }
+
+    #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Backend {
+        #[serde(rename = "type")]
+        pub backend_type: String,
+        pub location: Option<String>,
+        pub version: Option<String>,
+    }
+}
+
+pub mod get_health {
+    use crate::get_info;
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub healthy: bool,
+        pub error: Option<String>,
+        // Expired entries deleted from the catalog since the server started.
+        #[serde(default)]
+        pub pruned_entries: u64,
+        // Older servers do not send this field.
+        #[serde(default)]
+        pub key_rotation: KeyRotation,
+        #[serde(flatten)]
+        pub info: get_info::Response,
+    }
+
+    // Periodic checks of the signing keys since the server started, a check rotates the keys when due.
+    #[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+    pub struct KeyRotation {
+        pub checks: u64,
+        pub failures: u64,
+        pub consecutive_failures: u64,
+        pub rotations: u64,
+        // Seconds since epoch, 0 until the first one.
+        pub last_success_at: u64,
+        pub last_rotation_at: u64,
+    }
+}
+
+#[cfg(feature = "chaos")]
+pub mod faults {
+    use chaos::FaultConfig;
+
+    // Faults injected by a server built with the `chaos` feature. The same body is used to set them.
+    #[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+    pub struct Faults {
+        #[serde(default)]
+        pub catalog: FaultConfig,
+        #[serde(default)]
+        pub key_store: FaultConfig,
+    }
+}
+
+pub mod ban_agent {
+    use std::collections::BTreeSet;
+
+    use core_objects::AgentBan;
+
+    // An agent is banned when its node selectors include all the selectors of the ban.
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Request {
+        pub selectors: BTreeSet<String>,
+        #[serde(default)]
+        pub reason: Option<String>,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub ban: AgentBan,
+    }
+}
+
+pub mod list_agent_bans {
+    use core_objects::AgentBan;
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub bans: Vec<AgentBan>,
+    }
+}
+
+pub mod unban_agent {
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Request {
+        pub id: String,
+    }
+}
+
+pub mod list_attested_agents {
+    use core_objects::AttestedAgent;
+
+    pub struct Params {
+        pub page_size: u32,
+        pub page_token: Option<String>,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub agents: Vec<AttestedAgent>,
+        pub next_page_token: Option<String>,
+    }
+}
+
+pub mod list_issued_svids {
+    use core_objects::IssuedSvid;
+
+    // The SVIDs match all the filters that are set. `since` and `until` are epoch times, `until` is excluded.
+    #[derive(Default)]
+    pub struct Params {
+        pub page_size: u32,
+        pub page_token: Option<String>,
+        pub spiffe_id: Option<String>,
+        pub entry_id: Option<String>,
+        pub agent_id: Option<String>,
+        pub since: Option<u64>,
+        pub until: Option<u64>,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub svids: Vec<IssuedSvid>,
+        pub next_page_token: Option<String>,
+    }
+}
+
+pub mod list_admin_operations {
+    use core_objects::AdminOperation;
+
+    // The operations match all the filters that are set. `since` and `until` are epoch times, `until` is excluded.
+    #[derive(Default)]
+    pub struct Params {
+        pub page_size: u32,
+        pub page_token: Option<String>,
+        pub caller: Option<String>,
+        pub entry_id: Option<String>,
+        pub since: Option<u64>,
+        pub until: Option<u64>,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub operations: Vec<AdminOperation>,
+        pub next_page_token: Option<String>,
+    }
+}
+
+// Entries in the format of the `-data` file of `spire-server entry create`, to migrate from SPIRE.
+pub mod spire_entries {
+    use crate::operation;
+
+    // The export is the body to import the entries again, into E4K or SPIRE.
+    #[derive(Debug, Default, serde::Deserialize, serde::Serialize)]
+    pub struct Entries {
+        pub entries: Vec<Entry>,
+        // Entries that cannot be expressed as SPIRE entries, left out of the export.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub skipped: Vec<operation::Error>,
+    }
+
+    #[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+    pub struct Entry {
+        // SPIRE generates the id when it is not set, E4K uses a new uuid.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub entry_id: Option<String>,
+        pub spiffe_id: String,
+        pub parent_id: String,
+        pub selectors: Vec<Selector>,
+        #[serde(default)]
+        pub dns_names: Vec<String>,
+        #[serde(default)]
+        pub admin: bool,
+        #[serde(default)]
+        pub expires_at: u64,
+        // Not supported by E4K, ignored on import.
+        #[serde(default)]
+        pub x509_svid_ttl: u32,
+        // The TTL of the entry, 0 for the default of the server.
+        #[serde(default)]
+        pub jwt_svid_ttl: u32,
+        #[serde(default)]
+        pub downstream: bool,
+        #[serde(default)]
+        pub federates_with: Vec<String>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+    pub struct Selector {
+        #[serde(rename = "type")]
+        pub selector_type: String,
+        pub value: String,
+    }
+
+    #[derive(Debug, serde::Deserialize, serde::Serialize)]
+    pub struct ImportResponse {
+        pub results: Result<(), Vec<operation::Error>>,
+    }
+}
+
 pub mod operation {
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
     pub struct Error {
         pub id: String,
         pub error: String,
+        #[serde(default)]
+        pub kind: ErrorKind,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+    #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+    pub enum ErrorKind {
+        Other,
+        // The entry was modified since the revision number sent in the request.
+        // Get the entry again and retry the update.
+        RevisionConflict,
+        // The entry is outside of the scope of the tenant of the caller.
+        Forbidden,
+    }
+
+    impl Default for ErrorKind {
+        fn default() -> Self {
+            ErrorKind::Other
+        }
     }
 
     impl From<(String, Box<dyn std::error::Error + Send>)> for Error {
@@ -125,6 +469,7 @@ pub mod operation {
             Self {
                 id: error.0,
                 error: error.1.to_string(),
+                kind: ErrorKind::Other,
             }
         }
     }
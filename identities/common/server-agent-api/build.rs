@@ -0,0 +1,19 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::default_trait_access,
+    clippy::let_unit_value,
+    clippy::missing_errors_doc,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+fn main() {
+    println!("cargo:rerun-if-changed=proto/server_agent.proto");
+
+    tonic_build::configure()
+        .compile(&["proto/server_agent.proto"], &["proto"])
+        .unwrap();
+}
@@ -0,0 +1,342 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Conversions between the messages of the gRPC variant of the API and the types of the HTTP API, so the
+// server and the agent handle the same types whatever the protocol.
+
+use build_info::BuildInfo;
+use core_objects::JWTSVIDCompact;
+
+use crate::{
+    create_workload_jwts::{self, DeniedIdentity, DenyReason},
+    get_attestation_nonce, get_trust_bundle,
+};
+
+pub mod generated {
+    #![allow(
+        clippy::doc_markdown,
+        clippy::must_use_candidate,
+        clippy::wildcard_imports
+    )]
+
+    tonic::include_proto!("server_agent");
+}
+
+pub use generated::create_workload_jwts_request::Credential;
+
+fn non_empty(value: String) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+impl From<BuildInfo> for generated::BuildInfo {
+    fn from(build_info: BuildInfo) -> Self {
+        generated::BuildInfo {
+            version: build_info.version,
+            git_sha: build_info.git_sha,
+            build_time: build_info.build_time,
+            rustc_version: build_info.rustc_version,
+            features: build_info.features,
+            signature: build_info.signature.unwrap_or_default(),
+        }
+    }
+}
+
+impl From<generated::BuildInfo> for BuildInfo {
+    fn from(build_info: generated::BuildInfo) -> Self {
+        BuildInfo {
+            version: build_info.version,
+            git_sha: build_info.git_sha,
+            build_time: build_info.build_time,
+            rustc_version: build_info.rustc_version,
+            features: build_info.features,
+            signature: non_empty(build_info.signature),
+        }
+    }
+}
+
+impl From<JWTSVIDCompact> for generated::JwtSvid {
+    fn from(mut jwt_svid: JWTSVIDCompact) -> Self {
+        // The fields are taken, JWTSVIDCompact wipes its token on drop.
+        generated::JwtSvid {
+            token: std::mem::take(&mut jwt_svid.token),
+            spiffe_id: std::mem::take(&mut jwt_svid.spiffe_id),
+            expiry: jwt_svid.expiry,
+            issued_at: jwt_svid.issued_at,
+        }
+    }
+}
+
+impl From<generated::JwtSvid> for JWTSVIDCompact {
+    fn from(jwt_svid: generated::JwtSvid) -> Self {
+        JWTSVIDCompact {
+            token: jwt_svid.token,
+            spiffe_id: jwt_svid.spiffe_id,
+            expiry: jwt_svid.expiry,
+            issued_at: jwt_svid.issued_at,
+        }
+    }
+}
+
+impl From<DenyReason> for generated::DenyReason {
+    fn from(reason: DenyReason) -> Self {
+        match reason {
+            DenyReason::PolicyDenied => generated::DenyReason::PolicyDenied,
+            DenyReason::QuotaExceeded => generated::DenyReason::QuotaExceeded,
+            DenyReason::AdminDisabled => generated::DenyReason::AdminDisabled,
+        }
+    }
+}
+
+impl From<generated::DenyReason> for DenyReason {
+    fn from(reason: generated::DenyReason) -> Self {
+        match reason {
+            generated::DenyReason::PolicyDenied => DenyReason::PolicyDenied,
+            generated::DenyReason::QuotaExceeded => DenyReason::QuotaExceeded,
+            generated::DenyReason::AdminDisabled => DenyReason::AdminDisabled,
+        }
+    }
+}
+
+impl From<create_workload_jwts::Response> for generated::CreateWorkloadJwtsResponse {
+    fn from(response: create_workload_jwts::Response) -> Self {
+        generated::CreateWorkloadJwtsResponse {
+            jwt_svids: response.jwt_svids.into_iter().map(Into::into).collect(),
+            denied: response
+                .denied
+                .into_iter()
+                .map(|denied| generated::DeniedIdentity {
+                    spiffe_id: denied.spiffe_id,
+                    reason: generated::DenyReason::from(denied.reason).into(),
+                })
+                .collect(),
+            federates_with: response.federates_with.into_iter().collect(),
+        }
+    }
+}
+
+impl From<generated::CreateWorkloadJwtsResponse> for create_workload_jwts::Response {
+    fn from(response: generated::CreateWorkloadJwtsResponse) -> Self {
+        create_workload_jwts::Response {
+            jwt_svids: response.jwt_svids.into_iter().map(Into::into).collect(),
+            denied: response
+                .denied
+                .into_iter()
+                .map(|denied| DeniedIdentity {
+                    reason: denied.reason().into(),
+                    spiffe_id: denied.spiffe_id,
+                })
+                .collect(),
+            federates_with: response.federates_with.into_iter().collect(),
+        }
+    }
+}
+
+// The attestation token of the request is replaced by `credential`, an agent with an attestation
+// session sends its session instead.
+#[must_use]
+pub fn to_grpc_request(
+    request: create_workload_jwts::Request,
+    credential: Credential,
+) -> generated::CreateWorkloadJwtsRequest {
+    generated::CreateWorkloadJwtsRequest {
+        credential: Some(credential),
+        workload_spiffe_id: request.workload_spiffe_id.unwrap_or_default(),
+        audiences: request.audiences,
+        selectors: request.selectors.into_iter().collect(),
+        pod_uid: request.pod_uid.unwrap_or_default(),
+        agent_build: request.agent_build.map(Into::into),
+        prefetch_only: request.prefetch_only,
+        bundles_only: request.bundles_only,
+    }
+}
+
+// The attestation token of the returned request is empty when the agent sent a session.
+#[must_use]
+pub fn from_grpc_request(
+    request: generated::CreateWorkloadJwtsRequest,
+) -> (Option<Credential>, create_workload_jwts::Request) {
+    let attestation_token = match &request.credential {
+        Some(Credential::AttestationToken(attestation_token)) => attestation_token.clone(),
+        _ => String::new(),
+    };
+
+    let converted = create_workload_jwts::Request {
+        attestation_token,
+        workload_spiffe_id: non_empty(request.workload_spiffe_id),
+        audiences: request.audiences,
+        selectors: request.selectors.into_iter().collect(),
+        pod_uid: non_empty(request.pod_uid),
+        agent_build: request.agent_build.map(Into::into),
+        prefetch_only: request.prefetch_only,
+        bundles_only: request.bundles_only,
+    };
+
+    (request.credential, converted)
+}
+
+impl From<get_trust_bundle::Params> for generated::GetTrustBundleRequest {
+    fn from(params: get_trust_bundle::Params) -> Self {
+        generated::GetTrustBundleRequest {
+            jwt_keys: params.jwt_keys,
+            x509_cas: params.x509_cas,
+        }
+    }
+}
+
+impl From<generated::GetTrustBundleRequest> for get_trust_bundle::Params {
+    fn from(request: generated::GetTrustBundleRequest) -> Self {
+        get_trust_bundle::Params {
+            jwt_keys: request.jwt_keys,
+            x509_cas: request.x509_cas,
+        }
+    }
+}
+
+impl From<get_attestation_nonce::Response> for generated::GetAttestationNonceResponse {
+    fn from(response: get_attestation_nonce::Response) -> Self {
+        generated::GetAttestationNonceResponse {
+            nonce: response.nonce,
+            expires_at: response.expires_at,
+        }
+    }
+}
+
+impl From<generated::GetAttestationNonceResponse> for get_attestation_nonce::Response {
+    fn from(response: generated::GetAttestationNonceResponse) -> Self {
+        get_attestation_nonce::Response {
+            nonce: response.nonce,
+            expires_at: response.expires_at,
+        }
+    }
+}
+
+pub fn to_grpc_trust_bundle(
+    response: &get_trust_bundle::Response,
+) -> Result<generated::GetTrustBundleResponse, serde_json::Error> {
+    let federated_bundles = if response.federated_bundles.is_empty() {
+        Vec::new()
+    } else {
+        serde_json::to_vec(&response.federated_bundles)?
+    };
+
+    Ok(generated::GetTrustBundleResponse {
+        trust_bundle: serde_json::to_vec(&response.trust_bundle)?,
+        federated_bundles,
+    })
+}
+
+// Servers without federation do not send the federated bundles.
+pub fn from_grpc_trust_bundle(
+    response: &generated::GetTrustBundleResponse,
+) -> Result<get_trust_bundle::Response, serde_json::Error> {
+    let federated_bundles = if response.federated_bundles.is_empty() {
+        Vec::new()
+    } else {
+        serde_json::from_slice(&response.federated_bundles)?
+    };
+
+    Ok(get_trust_bundle::Response {
+        trust_bundle: serde_json::from_slice(&response.trust_bundle)?,
+        federated_bundles,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use core_objects::{JWKSet, TrustBundle};
+
+    use super::*;
+
+    #[test]
+    fn create_workload_jwts_round_trip() {
+        let request = create_workload_jwts::Request {
+            attestation_token: "token".to_string(),
+            workload_spiffe_id: None,
+            audiences: vec!["audience".to_string()],
+            selectors: BTreeSet::from(["NAMESPACE:default".to_string()]),
+            pod_uid: Some("pod_uid".to_string()),
+            agent_build: None,
+            prefetch_only: true,
+            bundles_only: true,
+        };
+
+        let grpc_request = to_grpc_request(
+            request.clone(),
+            Credential::SessionId("session".to_string()),
+        );
+        let (credential, converted) = from_grpc_request(grpc_request);
+        assert_eq!(
+            credential,
+            Some(Credential::SessionId("session".to_string()))
+        );
+        assert_eq!(converted.attestation_token, "");
+        assert_eq!(converted.workload_spiffe_id, None);
+        assert_eq!(converted.selectors, request.selectors);
+        assert_eq!(converted.pod_uid, request.pod_uid);
+        assert!(converted.prefetch_only);
+        assert!(converted.bundles_only);
+
+        let response = create_workload_jwts::Response {
+            jwt_svids: vec![JWTSVIDCompact {
+                token: "token".to_string(),
+                spiffe_id: "spiffe_id".to_string(),
+                expiry: 2,
+                issued_at: 1,
+            }],
+            denied: vec![DeniedIdentity {
+                spiffe_id: "denied".to_string(),
+                reason: DenyReason::QuotaExceeded,
+            }],
+            federates_with: BTreeSet::from(["cloud.contoso.com".to_string()]),
+        };
+        let expected_jwt_svids = response.jwt_svids.clone();
+        let expected_denied = response.denied.clone();
+        let expected_federates_with = response.federates_with.clone();
+
+        let converted = create_workload_jwts::Response::from(
+            generated::CreateWorkloadJwtsResponse::from(response),
+        );
+        assert_eq!(converted.jwt_svids, expected_jwt_svids);
+        assert_eq!(converted.denied, expected_denied);
+        assert_eq!(converted.federates_with, expected_federates_with);
+    }
+
+    #[test]
+    fn trust_bundle_round_trip() {
+        let trust_bundle = |trust_domain: &str| TrustBundle {
+            trust_domain: trust_domain.to_string(),
+            jwt_key_set: JWKSet {
+                keys: Vec::new(),
+                spiffe_refresh_hint: 300,
+                spiffe_sequence_number: 1,
+            },
+            x509_key_set: JWKSet {
+                keys: Vec::new(),
+                spiffe_refresh_hint: 300,
+                spiffe_sequence_number: 1,
+            },
+        };
+
+        let response = get_trust_bundle::Response {
+            trust_bundle: trust_bundle("iotedge"),
+            federated_bundles: Vec::new(),
+        };
+        let grpc_response = to_grpc_trust_bundle(&response).unwrap();
+        assert!(grpc_response.federated_bundles.is_empty());
+        let converted = from_grpc_trust_bundle(&grpc_response).unwrap();
+        assert_eq!(converted.trust_bundle, response.trust_bundle);
+        assert!(converted.federated_bundles.is_empty());
+
+        let response = get_trust_bundle::Response {
+            trust_bundle: trust_bundle("iotedge"),
+            federated_bundles: vec![trust_bundle("cloud.contoso.com")],
+        };
+        let converted = from_grpc_trust_bundle(&to_grpc_trust_bundle(&response).unwrap()).unwrap();
+        assert_eq!(converted.federated_bundles, response.federated_bundles);
+    }
+}
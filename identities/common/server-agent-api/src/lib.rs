@@ -10,6 +10,8 @@
     clippy::too_many_lines
 )]
 
+pub mod grpc;
+
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
 pub enum ApiVersion {
     V2022_06_01,
@@ -37,6 +39,7 @@ impl std::str::FromStr for ApiVersion {
 pub mod create_workload_jwts {
     use std::collections::BTreeSet;
 
+    use build_info::BuildInfo;
     use core_objects::JWTSVIDCompact;
 
     #[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
@@ -45,11 +48,60 @@ pub mod create_workload_jwts {
         pub workload_spiffe_id: Option<String>,
         pub audiences: Vec<String>,
         pub selectors: BTreeSet<String>,
+        // Attested pod UID, embedded in the JWT-SVIDs when the agent pins identities to pods.
+        // Older agents do not send this field.
+        #[serde(default)]
+        pub pod_uid: Option<String>,
+        // Build of the agent, as reported by the agent itself. Older agents do not send this field.
+        #[serde(default)]
+        pub agent_build: Option<BuildInfo>,
+        // Only issue the entries marked for prefetch, set by the agent warming up its cache at
+        // startup. Older agents do not send this field.
+        #[serde(default)]
+        pub prefetch_only: bool,
+        // Only match the entries of the workload, no JWT-SVID is issued. Set by the agent to get the federated
+        // trust domains of a workload that only fetches bundles. Older agents do not send this field.
+        #[serde(default)]
+        pub bundles_only: bool,
     }
 
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
     pub struct Response {
         pub jwt_svids: Vec<JWTSVIDCompact>,
+        // Entries that matched the workload but were not issued. Older servers
+        // do not send this field.
+        #[serde(default)]
+        pub denied: Vec<DeniedIdentity>,
+        // Trust domains the matched entries federate with, the workload gets their bundles. Older servers do not
+        // send this field.
+        #[serde(default)]
+        pub federates_with: BTreeSet<String>,
+    }
+
+    #[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+    pub struct DeniedIdentity {
+        pub spiffe_id: String,
+        pub reason: DenyReason,
+    }
+
+    // Sanitized reason sent back to the agent. The details of the denial only go
+    // to the server audit log.
+    #[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+    #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+    pub enum DenyReason {
+        PolicyDenied,
+        QuotaExceeded,
+        AdminDisabled,
+    }
+
+    impl std::fmt::Display for DenyReason {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(match self {
+                DenyReason::PolicyDenied => "POLICY_DENIED",
+                DenyReason::QuotaExceeded => "QUOTA_EXCEEDED",
+                DenyReason::AdminDisabled => "ADMIN_DISABLED",
+            })
+        }
     }
 }
 
@@ -64,5 +116,88 @@ pub mod get_trust_bundle {
     #[derive(Debug, serde::Deserialize, serde::Serialize)]
     pub struct Response {
         pub trust_bundle: TrustBundle,
+        // Bundles of the federated trust domains, only sent with the JWT keys.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub federated_bundles: Vec<TrustBundle>,
+    }
+}
+
+pub mod get_attestation_nonce {
+    #[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+    pub struct Response {
+        pub nonce: String,
+        // Seconds since epoch. The nonce must be used before then.
+        pub expires_at: u64,
+    }
+}
+
+// Attestation token of the agents on Azure IoT devices provisioned by DPS, sent as the JSON string of a `Token`.
+pub mod dps_attestation {
+    #[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    pub enum Token {
+        // Signed with the key of the device, the group key of its enrollment derives it.
+        SymmetricKey {
+            registration_id: String,
+            expiry: u64,
+            // Base64 HMAC-SHA256 of the signed data.
+            signature: String,
+        },
+        // Signed with the private key of the device certificate.
+        X509 {
+            registration_id: String,
+            expiry: u64,
+            // Base64 DER certificates, the device certificate first.
+            certificate_chain: Vec<String>,
+            // Base64 SHA256 signature of the signed data.
+            signature: String,
+        },
+    }
+
+    impl Token {
+        #[must_use]
+        pub fn registration_id(&self) -> &str {
+            match self {
+                Token::SymmetricKey {
+                    registration_id, ..
+                }
+                | Token::X509 {
+                    registration_id, ..
+                } => registration_id,
+            }
+        }
+
+        #[must_use]
+        pub fn expiry(&self) -> u64 {
+            match self {
+                Token::SymmetricKey { expiry, .. } | Token::X509 { expiry, .. } => *expiry,
+            }
+        }
+    }
+
+    // Same resource as the DPS registration SAS tokens, so a token is only valid for one ID scope.
+    #[must_use]
+    pub fn signed_data(id_scope: &str, registration_id: &str, expiry: u64) -> String {
+        format!("{}/registrations/{}\n{}", id_scope, registration_id, expiry)
+    }
+}
+
+// Attestation token of the agents proving the possession of the private key of a pre-provisioned certificate,
+// sent as the JSON string of a `Token`.
+pub mod x509pop_attestation {
+    #[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+    pub struct Token {
+        // Issued by the server, a nonce is only accepted once.
+        pub nonce: String,
+        // Base64 DER certificates, the agent certificate first.
+        pub certificate_chain: Vec<String>,
+        // Base64 SHA256 signature of the signed data.
+        pub signature: String,
+    }
+
+    // Prefixed so the signature cannot be reused as the signature of anything else.
+    #[must_use]
+    pub fn signed_data(nonce: &str) -> String {
+        format!("x509pop\n{}", nonce)
     }
 }
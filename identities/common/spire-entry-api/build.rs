@@ -0,0 +1,27 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::default_trait_access,
+    clippy::let_unit_value,
+    clippy::missing_errors_doc,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+fn main() {
+    println!("cargo:rerun-if-changed=proto");
+
+    // Only the server side is served by E4K.
+    tonic_build::configure()
+        .build_client(false)
+        .compile(
+            &[
+                "proto/spire/api/types/types.proto",
+                "proto/spire/api/server/entry/v1/entry.proto",
+            ],
+            &["proto"],
+        )
+        .unwrap();
+}
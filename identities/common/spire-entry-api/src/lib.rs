@@ -0,0 +1,42 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::default_trait_access,
+    clippy::let_unit_value,
+    clippy::missing_errors_doc,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+// The modules follow the packages of the protos, the generated code refers to the types by their relative path.
+pub mod spire {
+    pub mod api {
+        pub mod types {
+            #![allow(
+                clippy::doc_markdown,
+                clippy::must_use_candidate,
+                clippy::wildcard_imports
+            )]
+
+            tonic::include_proto!("spire.api.types");
+        }
+
+        pub mod server {
+            pub mod entry {
+                pub mod v1 {
+                    #![allow(
+                        clippy::doc_markdown,
+                        clippy::must_use_candidate,
+                        clippy::wildcard_imports
+                    )]
+
+                    tonic::include_proto!("spire.api.server.entry.v1");
+                }
+            }
+        }
+    }
+}
+
+pub use spire::api::{server::entry::v1 as entry, types};
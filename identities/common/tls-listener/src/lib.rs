@@ -0,0 +1,183 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::default_trait_access,
+    clippy::let_unit_value,
+    clippy::missing_errors_doc,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+// TCP listeners served over TLS: the admin TLS listener, the server-agent API, the bundle endpoint, the OIDC
+// discovery endpoint and the admission webhook of the registrar. Connections are accepted in a loop that
+// survives accept errors, and each handshake runs in its own task with a deadline, so a slow or idle client
+// holds neither the listener nor a connection.
+
+use std::{future::Future, net::SocketAddr, pin::Pin, time::Duration};
+
+use openssl::{
+    error::ErrorStack,
+    ssl::{Ssl, SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod},
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time,
+};
+use tokio_openssl::SslStream;
+
+// Accepting fails while the server is out of file descriptors, this keeps it from spinning.
+pub const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+// Clients that do not finish their handshake in time are disconnected, so they do not hold a connection open.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Acceptor with the certificate chain and the private key of the server, `configure` sets the other options,
+// e.g. the client CAs.
+pub fn acceptor(
+    cert_path: &str,
+    key_path: &str,
+    configure: impl FnOnce(&mut SslAcceptorBuilder) -> Result<(), ErrorStack>,
+) -> Result<SslAcceptor, ErrorStack> {
+    let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server())?;
+    builder.set_certificate_chain_file(cert_path)?;
+    builder.set_private_key_file(key_path, SslFiletype::PEM)?;
+    builder.check_private_key()?;
+    configure(&mut builder)?;
+
+    Ok(builder.build())
+}
+
+// Serves each connection whose handshake succeeds in its own task. `name` is the listener in the logs.
+pub async fn serve<F, Fut>(
+    listener: TcpListener,
+    acceptor: SslAcceptor,
+    name: &'static str,
+    serve_connection: F,
+) where
+    F: Fn(SslStream<TcpStream>, SocketAddr) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    loop {
+        let (stream, remote) = accept(&listener, name).await;
+        let acceptor = acceptor.clone();
+        let serve_connection = serve_connection.clone();
+
+        tokio::spawn(async move {
+            if let Some(stream) = handshake(&acceptor, stream, remote).await {
+                serve_connection(stream, remote).await;
+            }
+        });
+    }
+}
+
+// Next connection of the listener, accept errors are logged and retried.
+pub async fn accept(listener: &TcpListener, name: &str) -> (TcpStream, SocketAddr) {
+    loop {
+        match listener.accept().await {
+            Ok(accepted) => return accepted,
+            Err(err) => {
+                log::error!("Error accepting a connection to the {}: {}", name, err);
+                time::sleep(ACCEPT_RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+// None when the handshake fails or does not finish before `HANDSHAKE_TIMEOUT`, the connection is then closed.
+pub async fn handshake(
+    acceptor: &SslAcceptor,
+    stream: TcpStream,
+    remote: SocketAddr,
+) -> Option<SslStream<TcpStream>> {
+    let ssl = match Ssl::new(acceptor.context()) {
+        Ok(ssl) => ssl,
+        Err(err) => {
+            log::error!("Error creating the TLS session of {}: {}", remote, err);
+            return None;
+        }
+    };
+    let mut stream = match SslStream::new(ssl, stream) {
+        Ok(stream) => stream,
+        Err(err) => {
+            log::error!("Error creating the TLS stream of {}: {}", remote, err);
+            return None;
+        }
+    };
+
+    match time::timeout(HANDSHAKE_TIMEOUT, Pin::new(&mut stream).accept()).await {
+        Ok(Ok(())) => Some(stream),
+        Ok(Err(err)) => {
+            log::warn!("TLS handshake with {} failed: {}", remote, err);
+            None
+        }
+        Err(_) => {
+            log::warn!("TLS handshake with {} timed out", remote);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::{
+        asn1::Asn1Time,
+        ec::{EcGroup, EcKey},
+        hash::MessageDigest,
+        nid::Nid,
+        pkey::PKey,
+        x509::X509,
+    };
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    fn self_signed_acceptor() -> SslAcceptor {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+
+        let mut builder = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server()).unwrap();
+        builder.set_certificate(&cert.build()).unwrap();
+        builder.set_private_key(&key).unwrap();
+
+        builder.build()
+    }
+
+    #[tokio::test]
+    async fn handshake_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, remote) = accept(&listener, "test listener").await;
+
+        // The client never sends its hello.
+        time::pause();
+        assert!(handshake(&self_signed_acceptor(), stream, remote)
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn handshake_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, remote) = accept(&listener, "test listener").await;
+
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+        assert!(handshake(&self_signed_acceptor(), stream, remote)
+            .await
+            .is_none());
+    }
+}
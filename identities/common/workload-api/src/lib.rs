@@ -34,6 +34,30 @@ use generated::{
     JwtsvidRequest, JwtsvidResponse, ValidateJwtsvidRequest, ValidateJwtsvidResponse,
 };
 
+// Metadata the SPIFFE workload API requires on every request, set to "true". Requests without it are
+// rejected, so that a workload API cannot be reached by a client not built for it, e.g. through a proxy.
+pub const SECURITY_HEADER: &str = "workload.spiffe.io";
+
+// Wraps a message in a request to the workload API, with the security header.
+#[must_use]
+pub fn request<T>(message: T) -> tonic::Request<T> {
+    let mut request = tonic::Request::new(message);
+    request.metadata_mut().insert(
+        SECURITY_HEADER,
+        tonic::metadata::MetadataValue::from_static("true"),
+    );
+
+    request
+}
+
+#[must_use]
+pub fn has_security_header<T>(request: &tonic::Request<T>) -> bool {
+    request
+        .metadata()
+        .get(SECURITY_HEADER)
+        .map_or(false, |value| value.as_bytes() == b"true")
+}
+
 #[cfg_attr(feature = "tests", mockall::automock)]
 #[async_trait::async_trait]
 pub trait WorkloadAPIClient: Send {
@@ -59,20 +83,20 @@ impl WorkloadAPIClient for SpiffeWorkloadApiClient<tonic::transport::Channel> {
         &mut self,
         request: JwtsvidRequest,
     ) -> Result<tonic::Response<JwtsvidResponse>, tonic::Status> {
-        self.fetch_jwtsvid(request).await
+        self.fetch_jwtsvid(crate::request(request)).await
     }
 
     async fn fetch_jwt_bundles(
         &mut self,
         request: JwtBundlesRequest,
     ) -> Result<tonic::Response<tonic::codec::Streaming<JwtBundlesResponse>>, tonic::Status> {
-        self.fetch_jwt_bundles(request).await
+        self.fetch_jwt_bundles(crate::request(request)).await
     }
 
     async fn validate_jwtsvid(
         &mut self,
         request: ValidateJwtsvidRequest,
     ) -> Result<tonic::Response<ValidateJwtsvidResponse>, tonic::Status> {
-        self.validate_jwtsvid(request).await
+        self.validate_jwtsvid(crate::request(request)).await
     }
 }
@@ -0,0 +1,551 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Commands calling the admin API of the server over its socket. The responses are printed as tables, or as the
+// JSON bodies of the admin API so they can be edited and sent back, e.g. with `entry update --file`.
+
+use std::{collections::BTreeSet, fmt::Display, fs, io};
+
+use core_objects::{
+    AttestationConfig, AttestedAgent, EntryNodeAttestation, EntryWorkloadAttestation,
+    RegistrationEntry, JWK,
+};
+use http_common::{ErrorBody, HttpRequest};
+use serde::Serialize;
+use server_admin_api::{
+    ban_agent, create_registration_entries, delete_registration_entries, get_trust_bundle,
+    list_all, list_attested_agents, operation, unban_agent, update_registration_entries,
+    ApiVersion,
+};
+use thiserror::Error;
+
+use crate::args::{AdminCommand, AdminOptions, EntryFilter, NewEntries, Output};
+
+// The host is ignored, requests go to the admin API socket.
+pub(crate) const ADMIN_BASE_URL: &str = "http://spiffeserver.sock";
+
+const PAGE_SIZE: u32 = 100;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Cannot connect to the admin API socket: {0}")]
+    Connect(String),
+    #[error("Admin API request failed: {0}")]
+    Request(io::Error),
+    #[error("Cannot read {0}: {1}")]
+    ReadFile(String, io::Error),
+    #[error("Invalid entries in {0}: {1}")]
+    InvalidFile(String, serde_json::Error),
+    #[error("Unknown attestation plugin {0}")]
+    UnknownPlugin(String),
+    #[error("{0} entries failed")]
+    EntriesFailed(usize),
+}
+
+pub(crate) fn admin_connector(socket: &str) -> Result<http_common::Connector, String> {
+    let socket_url =
+        url::Url::parse(&format!("unix://{}", socket)).map_err(|err| err.to_string())?;
+
+    http_common::Connector::new(&socket_url).map_err(|err| err.to_string())
+}
+
+pub async fn run(options: &AdminOptions, command: AdminCommand) -> Result<(), Error> {
+    let connector = admin_connector(&options.server_socket).map_err(Error::Connect)?;
+
+    match command {
+        AdminCommand::EntryList(filter) => {
+            let entries = list_entries(connector, &filter).await?;
+            print_output(options.output, &entries, || entries_table(&entries));
+        }
+        AdminCommand::EntryCreate(new_entries) => {
+            let entries = match new_entries {
+                NewEntries::File(file) => read_entries(&file)?,
+                NewEntries::Entry {
+                    id,
+                    spiffe_id_path,
+                    plugin,
+                    parent_id,
+                    selectors,
+                    dns_names,
+                    admin,
+                } => vec![RegistrationEntry {
+                    id,
+                    other_identities: Vec::new(),
+                    spiffe_id_path,
+                    attestation_config: attestation_config(&plugin, parent_id, selectors)?,
+                    admin,
+                    expires_at: 0,
+                    dns_names,
+                    revision_number: 0,
+                    store_svid: false,
+                    prefetch: false,
+                    ttl: None,
+                    custom_claims: Default::default(),
+                    federates_with: Vec::new(),
+                }],
+            };
+            let ids = entries.iter().map(|entry| entry.id.clone()).collect();
+            let body = create_registration_entries::Request {
+                entries,
+                transactional: false,
+            };
+
+            let request = HttpRequest::post(connector, &uri("/entries"), Some(body));
+            let response: create_registration_entries::Response = request
+                .json_response()
+                .await
+                .and_then(|response| {
+                    response.parse::<_, ErrorBody<'_>>(&[hyper::StatusCode::CREATED])
+                })
+                .map_err(Error::Request)?;
+
+            print_results(options.output, "created", ids, response.results)?;
+        }
+        AdminCommand::EntryUpdate { file } => {
+            let entries = read_entries(&file)?;
+            let ids = entries.iter().map(|entry| entry.id.clone()).collect();
+            let body = update_registration_entries::Request {
+                entries,
+                transactional: false,
+            };
+
+            let request = HttpRequest::put(connector, &uri("/entries"), Some(body));
+            let response: update_registration_entries::Response = request
+                .json_response()
+                .await
+                .and_then(|response| response.parse_expect_ok::<_, ErrorBody<'_>>())
+                .map_err(Error::Request)?;
+
+            print_results(options.output, "updated", ids, response.results)?;
+        }
+        AdminCommand::EntryDelete { ids } => {
+            let body = delete_registration_entries::Request {
+                ids: ids.clone(),
+                transactional: false,
+            };
+
+            let request = HttpRequest::delete(connector, &uri("/entries"), Some(body));
+            let response: delete_registration_entries::Response = request
+                .json_response()
+                .await
+                .and_then(|response| response.parse_expect_ok::<_, ErrorBody<'_>>())
+                .map_err(Error::Request)?;
+
+            print_results(options.output, "deleted", ids, response.results)?;
+        }
+        AdminCommand::BundleShow => {
+            let request: HttpRequest<(), _> = HttpRequest::get(connector, &uri("/trust-bundle"));
+            let response: get_trust_bundle::Response = request
+                .json_response()
+                .await
+                .and_then(|response| response.parse_expect_ok::<_, ErrorBody<'_>>())
+                .map_err(Error::Request)?;
+
+            print_output(options.output, &response, || bundle_table(&response));
+        }
+        AdminCommand::AgentList => {
+            let agents = list_agents(connector).await?;
+
+            print_output(options.output, &agents, || {
+                let mut table = Table::new(&["ID", "LAST SEEN", "SVID EXPIRY", "SELECTORS"]);
+                for agent in &agents {
+                    table.row(vec![
+                        agent.id.clone(),
+                        agent.last_seen.to_string(),
+                        agent.svid_expiry.to_string(),
+                        join(&agent.selectors),
+                    ]);
+                }
+                table
+            });
+        }
+        AdminCommand::AgentBan { selectors, reason } => {
+            let body = ban_agent::Request {
+                selectors: selectors.into_iter().collect(),
+                reason,
+            };
+
+            let request = HttpRequest::post(connector, &uri("/agent-bans"), Some(body));
+            let response: ban_agent::Response = request
+                .json_response()
+                .await
+                .and_then(|response| {
+                    response.parse::<_, ErrorBody<'_>>(&[hyper::StatusCode::CREATED])
+                })
+                .map_err(Error::Request)?;
+
+            print_output(options.output, &response.ban, || {
+                let mut table = Table::new(&["ID", "SELECTORS", "REASON"]);
+                table.row(vec![
+                    response.ban.id.clone(),
+                    join(&response.ban.selectors),
+                    response.ban.reason.clone().unwrap_or_default(),
+                ]);
+                table
+            });
+        }
+        AdminCommand::AgentUnban { id } => {
+            let body = unban_agent::Request { id: id.clone() };
+
+            let request = HttpRequest::delete(connector, &uri("/agent-bans"), Some(body));
+            request
+                .no_content_response()
+                .await
+                .map_err(Error::Request)?;
+
+            if options.output == Output::Table {
+                println!("Removed agent ban {}", id);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn uri(path: &str) -> String {
+    format!(
+        "{}{}?api-version={}",
+        ADMIN_BASE_URL,
+        path,
+        ApiVersion::V2022_06_01
+    )
+}
+
+async fn list_entries(
+    connector: http_common::Connector,
+    filter: &EntryFilter,
+) -> Result<Vec<RegistrationEntry>, Error> {
+    let mut entries = Vec::new();
+    let mut page_token = None;
+
+    loop {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        query.append_pair("page_size", &PAGE_SIZE.to_string());
+        let optional = [
+            ("page_token", &page_token),
+            ("parent_id", &filter.parent_id),
+            ("selector", &filter.selector),
+            ("spiffe_id_path_prefix", &filter.spiffe_id_path_prefix),
+            ("plugin", &filter.plugin),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                query.append_pair(key, value);
+            }
+        }
+
+        let uri = format!("{}&{}", uri("/entries"), query.finish());
+        let request: HttpRequest<(), _> = HttpRequest::get(connector.clone(), &uri);
+        let mut response: list_all::Response = request
+            .json_response()
+            .await
+            .and_then(|response| response.parse_expect_ok::<_, ErrorBody<'_>>())
+            .map_err(Error::Request)?;
+
+        entries.append(&mut response.entries);
+        page_token = response.next_page_token;
+        if page_token.is_none() {
+            return Ok(entries);
+        }
+    }
+}
+
+async fn list_agents(connector: http_common::Connector) -> Result<Vec<AttestedAgent>, Error> {
+    let mut agents = Vec::new();
+    let mut page_token: Option<String> = None;
+
+    loop {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        query.append_pair("page_size", &PAGE_SIZE.to_string());
+        if let Some(page_token) = &page_token {
+            query.append_pair("page_token", page_token);
+        }
+
+        let uri = format!("{}&{}", uri("/agents"), query.finish());
+        let request: HttpRequest<(), _> = HttpRequest::get(connector.clone(), &uri);
+        let mut response: list_attested_agents::Response = request
+            .json_response()
+            .await
+            .and_then(|response| response.parse_expect_ok::<_, ErrorBody<'_>>())
+            .map_err(Error::Request)?;
+
+        agents.append(&mut response.agents);
+        page_token = response.next_page_token;
+        if page_token.is_none() {
+            return Ok(agents);
+        }
+    }
+}
+
+fn read_entries(file: &str) -> Result<Vec<RegistrationEntry>, Error> {
+    let entries = fs::read(file).map_err(|err| Error::ReadFile(file.to_string(), err))?;
+
+    serde_json::from_slice(&entries).map_err(|err| Error::InvalidFile(file.to_string(), err))
+}
+
+fn attestation_config(
+    plugin: &str,
+    parent_id: Option<String>,
+    value: Vec<String>,
+) -> Result<AttestationConfig, Error> {
+    let plugin_name = || serde_json::Value::String(plugin.to_string());
+    let unknown_plugin = |_| Error::UnknownPlugin(plugin.to_string());
+
+    match parent_id {
+        Some(parent_id) => Ok(AttestationConfig::Workload(EntryWorkloadAttestation {
+            parent_id,
+            value,
+            plugin: serde_json::from_value(plugin_name()).map_err(unknown_plugin)?,
+        })),
+        None => Ok(AttestationConfig::Node(EntryNodeAttestation {
+            value,
+            plugin: serde_json::from_value(plugin_name()).map_err(unknown_plugin)?,
+        })),
+    }
+}
+
+// The JSON output is the response of the admin API, the errors of the failed entries are printed in both formats.
+fn print_results(
+    output: Output,
+    action: &str,
+    ids: Vec<String>,
+    results: Result<(), Vec<operation::Error>>,
+) -> Result<(), Error> {
+    let errors = results.err().unwrap_or_default();
+
+    match output {
+        Output::Json => print_json(&errors),
+        Output::Table => {
+            let failed: BTreeSet<&str> = errors.iter().map(|error| error.id.as_str()).collect();
+            let mut table = Table::new(&["ID", "RESULT"]);
+            for id in ids {
+                if !failed.contains(id.as_str()) {
+                    table.row(vec![id, action.to_string()]);
+                }
+            }
+            for error in &errors {
+                table.row(vec![error.id.clone(), error.error.clone()]);
+            }
+            print!("{}", table);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::EntriesFailed(errors.len()))
+    }
+}
+
+fn print_output<T, F>(output: Output, value: &T, table: F)
+where
+    T: Serialize,
+    F: FnOnce() -> Table,
+{
+    match output {
+        Output::Json => print_json(value),
+        Output::Table => print!("{}", table()),
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{}", json),
+        Err(err) => eprintln!("Cannot serialize the response: {}", err),
+    }
+}
+
+fn entries_table(entries: &[RegistrationEntry]) -> Table {
+    let mut table = Table::new(&[
+        "ID",
+        "SPIFFE ID PATH",
+        "PLUGIN",
+        "PARENT",
+        "SELECTORS",
+        "ADMIN",
+    ]);
+
+    for entry in entries {
+        let (plugin, parent_id, selectors) = match &entry.attestation_config {
+            AttestationConfig::Node(attestation) => {
+                (name(&attestation.plugin), String::new(), &attestation.value)
+            }
+            AttestationConfig::Workload(attestation) => (
+                name(&attestation.plugin),
+                attestation.parent_id.clone(),
+                &attestation.value,
+            ),
+        };
+
+        table.row(vec![
+            entry.id.clone(),
+            entry.spiffe_id_path.clone(),
+            plugin,
+            parent_id,
+            join(selectors),
+            entry.admin.to_string(),
+        ]);
+    }
+
+    table
+}
+
+fn bundle_table(response: &get_trust_bundle::Response) -> Table {
+    let trust_bundle = &response.trust_bundle;
+    let mut table = Table::new(&[
+        "TRUST DOMAIN",
+        "USE",
+        "KID",
+        "KTY",
+        "SEQUENCE",
+        "REFRESH HINT",
+    ]);
+
+    let key_sets = [
+        ("jwt-svid", &trust_bundle.jwt_key_set),
+        ("x509-svid", &trust_bundle.x509_key_set),
+    ];
+    for (key_use, key_set) in key_sets {
+        for JWK { kid, kty, .. } in &key_set.keys {
+            table.row(vec![
+                trust_bundle.trust_domain.clone(),
+                key_use.to_string(),
+                kid.clone(),
+                name(kty),
+                key_set.spiffe_sequence_number.to_string(),
+                key_set.spiffe_refresh_hint.to_string(),
+            ]);
+        }
+    }
+
+    table
+}
+
+// Name of an enum value as it is serialized.
+fn name<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|name| name.as_str().map(ToString::to_string))
+        .unwrap_or_default()
+}
+
+fn join<'a, I, T>(values: I) -> String
+where
+    I: IntoIterator<Item = &'a T>,
+    T: Display + 'a,
+{
+    values
+        .into_iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// Columns are aligned on the widest cell.
+struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn new(header: &[&str]) -> Self {
+        Table {
+            header: header.iter().map(ToString::to_string).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    fn row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+}
+
+impl Display for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut widths: Vec<usize> = self.header.iter().map(String::len).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        for row in std::iter::once(&self.header).chain(&self.rows) {
+            let line = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join("  ");
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core_objects::{NodeAttestationPlugin, WorkloadAttestationPlugin};
+
+    use super::*;
+
+    #[test]
+    fn display_entries_table() {
+        let entry = |id: &str, attestation_config: AttestationConfig| RegistrationEntry {
+            spiffe_id_path: format!("path/{}", id),
+            attestation_config,
+            ..RegistrationEntry::test_entry(id)
+        };
+        let entries = vec![
+            entry(
+                "node",
+                attestation_config("PSAT", None, vec!["CLUSTER:demo".to_string()]).unwrap(),
+            ),
+            entry(
+                "web",
+                attestation_config(
+                    "K8S",
+                    Some("node".to_string()),
+                    vec![
+                        "NAMESPACE:default".to_string(),
+                        "SERVICEACCOUNT:web".to_string(),
+                    ],
+                )
+                .unwrap(),
+            ),
+        ];
+
+        assert_eq!(
+            entries_table(&entries).to_string(),
+            "\
+ID    SPIFFE ID PATH  PLUGIN  PARENT  SELECTORS                             ADMIN
+node  path/node       PSAT            CLUSTER:demo                          false
+web   path/web        K8S     node    NAMESPACE:default,SERVICEACCOUNT:web  false
+"
+        );
+    }
+
+    #[test]
+    fn parse_plugin() {
+        let config = attestation_config("SAT", None, Vec::new()).unwrap();
+        assert!(matches!(
+            config,
+            AttestationConfig::Node(EntryNodeAttestation {
+                plugin: NodeAttestationPlugin::Sat,
+                ..
+            })
+        ));
+
+        let config = attestation_config("UNIX", Some("node".to_string()), Vec::new()).unwrap();
+        assert!(matches!(
+            config,
+            AttestationConfig::Workload(EntryWorkloadAttestation {
+                plugin: WorkloadAttestationPlugin::Unix,
+                ..
+            })
+        ));
+
+        // Node plugins are not workload plugins.
+        attestation_config("PSAT", Some("node".to_string()), Vec::new()).unwrap_err();
+        attestation_config("psat", None, Vec::new()).unwrap_err();
+    }
+}
@@ -0,0 +1,508 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+pub const DEFAULT_SERVER_SOCKET: &str = "/run/iotedge/sockets/api.sock";
+pub const DEFAULT_AGENT_SOCKET: &str = "/run/iotedge/sockets/workloadapi.sock";
+pub const DEFAULT_CANARY_AUDIENCE: &str = "e4k-doctor";
+
+pub const USAGE: &str = "\
+Usage: e4k <command> [options]
+
+Commands:
+    doctor                 Check a running deployment end to end and print a report
+    entry list             List the registration entries
+    entry create           Create a registration entry, or the entries of --file
+    entry update           Update the entries of --file, e.g. edited from `entry list --output json`
+    entry delete <id>...   Delete registration entries
+    bundle show            Show the trust bundle of the server
+    agent list             List the attested agents
+    agent ban              Ban the agents with all the --selector node selectors
+    agent unban <id>       Remove an agent ban
+    help                   Print this message
+
+Options of doctor:
+    --server-socket <path>       Admin API socket of the server
+                                 [default: /run/iotedge/sockets/api.sock]
+    --agent-socket <path>        Workload API socket of an agent, repeat it to check a sample
+                                 of agents [default: /run/iotedge/sockets/workloadapi.sock]
+    --canary-spiffe-id <id>      SPIFFE ID of the canary entry used for the test issuance
+    --canary-audience <audience> Audience of the test JWT-SVID [default: e4k-doctor]
+
+Options of the entry, bundle and agent commands:
+    --server-socket <path>       Admin API socket of the server
+                                 [default: /run/iotedge/sockets/api.sock]
+    --output <table|json>        Output format [default: table]
+
+Options of entry list:
+    --parent-id <id>             Only the workload entries of this node entry
+    --selector <selector>        Only the entries with this selector, e.g. NAMESPACE:default
+    --spiffe-id-path-prefix <p>  Only the entries whose SPIFFE ID path starts with this prefix
+    --plugin <plugin>            Only the entries of this attestation plugin, e.g. PSAT or K8S
+
+Options of entry create:
+    --file <path>                JSON list of entries to create, instead of the options below
+    --id <id>                    Id of the entry
+    --spiffe-id-path <path>      SPIFFE ID path of the entry, without the trust domain
+    --plugin <plugin>            Attestation plugin, e.g. PSAT for a node entry or K8S for a workload entry
+    --parent-id <id>             Node entry of a workload entry
+    --selector <selector>        Selector of the entry, repeat it for more selectors
+    --dns-name <name>            DNS name of the entry, repeat it for more names
+    --admin                      The entry can call the admin API
+
+Options of entry update:
+    --file <path>                JSON list of entries to update, with their current revision number
+
+Options of agent ban:
+    --selector <selector>        Node selector of the agents to ban, e.g. AGENTNODENAME:node1
+    --reason <reason>            Why the agents are banned";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Help,
+    Doctor(DoctorOptions),
+    Admin(AdminOptions, AdminCommand),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DoctorOptions {
+    pub server_socket: String,
+    pub agent_sockets: Vec<String>,
+    // The test issuance and the bundle freshness checks are skipped without a canary entry.
+    pub canary_spiffe_id: Option<String>,
+    pub canary_audience: String,
+}
+
+// Options of the commands calling the admin API.
+#[derive(Debug, PartialEq)]
+pub struct AdminOptions {
+    pub server_socket: String,
+    pub output: Output,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Output {
+    Table,
+    Json,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AdminCommand {
+    EntryList(EntryFilter),
+    EntryCreate(NewEntries),
+    EntryUpdate {
+        file: String,
+    },
+    EntryDelete {
+        ids: Vec<String>,
+    },
+    BundleShow,
+    AgentList,
+    AgentBan {
+        selectors: Vec<String>,
+        reason: Option<String>,
+    },
+    AgentUnban {
+        id: String,
+    },
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct EntryFilter {
+    pub parent_id: Option<String>,
+    pub selector: Option<String>,
+    pub spiffe_id_path_prefix: Option<String>,
+    pub plugin: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum NewEntries {
+    File(String),
+    // A node entry without a parent, a workload entry with one.
+    Entry {
+        id: String,
+        spiffe_id_path: String,
+        plugin: String,
+        parent_id: Option<String>,
+        selectors: Vec<String>,
+        dns_names: Vec<String>,
+        admin: bool,
+    },
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum Error {
+    #[error("Missing command")]
+    MissingCommand,
+    #[error("Unknown command {0}")]
+    UnknownCommand(String),
+    #[error("Unknown option {0}")]
+    UnknownOption(String),
+    #[error("Missing value for option {0}")]
+    MissingValue(String),
+    #[error("Missing option {0}")]
+    MissingOption(&'static str),
+    #[error("Option {0} is given more than once")]
+    RepeatedOption(String),
+    #[error("Invalid output format {0}, expected table or json")]
+    InvalidOutput(String),
+    #[error("Unexpected argument {0}")]
+    UnexpectedArgument(String),
+    #[error("Missing argument {0}")]
+    MissingArgument(&'static str),
+}
+
+pub fn parse(args: &[String]) -> Result<Command, Error> {
+    let (command, options) = args.split_first().ok_or(Error::MissingCommand)?;
+
+    match command.as_str() {
+        "help" | "--help" | "-h" => Ok(Command::Help),
+        "doctor" => parse_doctor(options).map(Command::Doctor),
+        "entry" | "bundle" | "agent" => parse_admin(command, options),
+        _ => Err(Error::UnknownCommand(command.clone())),
+    }
+}
+
+fn parse_doctor(args: &[String]) -> Result<DoctorOptions, Error> {
+    let mut server_socket = DEFAULT_SERVER_SOCKET.to_string();
+    let mut agent_sockets = Vec::new();
+    let mut canary_spiffe_id = None;
+    let mut canary_audience = DEFAULT_CANARY_AUDIENCE.to_string();
+
+    let mut args = args.iter();
+    while let Some(option) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| Error::MissingValue(option.clone()))?
+            .clone();
+
+        match option.as_str() {
+            "--server-socket" => server_socket = value,
+            "--agent-socket" => agent_sockets.push(value),
+            "--canary-spiffe-id" => canary_spiffe_id = Some(value),
+            "--canary-audience" => canary_audience = value,
+            _ => return Err(Error::UnknownOption(option.clone())),
+        }
+    }
+
+    if agent_sockets.is_empty() {
+        agent_sockets.push(DEFAULT_AGENT_SOCKET.to_string());
+    }
+
+    Ok(DoctorOptions {
+        server_socket,
+        agent_sockets,
+        canary_spiffe_id,
+        canary_audience,
+    })
+}
+
+fn parse_admin(group: &str, args: &[String]) -> Result<Command, Error> {
+    let (action, args) = args.split_first().ok_or(Error::MissingCommand)?;
+    let mut args = Args::parse(args)?;
+
+    let options = AdminOptions {
+        server_socket: args
+            .one("--server-socket")?
+            .unwrap_or_else(|| DEFAULT_SERVER_SOCKET.to_string()),
+        output: match args.one("--output")?.as_deref() {
+            None | Some("table") => Output::Table,
+            Some("json") => Output::Json,
+            Some(output) => return Err(Error::InvalidOutput(output.to_string())),
+        },
+    };
+
+    let command = match (group, action.as_str()) {
+        ("entry", "list") => AdminCommand::EntryList(EntryFilter {
+            parent_id: args.one("--parent-id")?,
+            selector: args.one("--selector")?,
+            spiffe_id_path_prefix: args.one("--spiffe-id-path-prefix")?,
+            plugin: args.one("--plugin")?,
+        }),
+        ("entry", "create") => match args.one("--file")? {
+            Some(file) => AdminCommand::EntryCreate(NewEntries::File(file)),
+            None => AdminCommand::EntryCreate(NewEntries::Entry {
+                id: args.required("--id")?,
+                spiffe_id_path: args.required("--spiffe-id-path")?,
+                plugin: args.required("--plugin")?,
+                parent_id: args.one("--parent-id")?,
+                selectors: args.all("--selector"),
+                dns_names: args.all("--dns-name"),
+                admin: args.flag("--admin"),
+            }),
+        },
+        ("entry", "update") => AdminCommand::EntryUpdate {
+            file: args.required("--file")?,
+        },
+        ("entry", "delete") => {
+            let ids = args.positional();
+            if ids.is_empty() {
+                return Err(Error::MissingArgument("<id>"));
+            }
+
+            AdminCommand::EntryDelete { ids }
+        }
+        ("bundle", "show") => AdminCommand::BundleShow,
+        ("agent", "list") => AdminCommand::AgentList,
+        ("agent", "ban") => {
+            let selectors = args.all("--selector");
+            if selectors.is_empty() {
+                return Err(Error::MissingOption("--selector"));
+            }
+
+            AdminCommand::AgentBan {
+                selectors,
+                reason: args.one("--reason")?,
+            }
+        }
+        ("agent", "unban") => match args.positional().as_slice() {
+            [id] => AdminCommand::AgentUnban { id: id.clone() },
+            [] => return Err(Error::MissingArgument("<id>")),
+            [_, other, ..] => return Err(Error::UnexpectedArgument(other.clone())),
+        },
+        _ => return Err(Error::UnknownCommand(format!("{} {}", group, action))),
+    };
+
+    args.finish()?;
+
+    Ok(Command::Admin(options, command))
+}
+
+// Options of an admin command. The commands take the options they know, the ones left are unknown.
+#[derive(Default)]
+struct Args {
+    options: BTreeMap<String, Vec<String>>,
+    flags: Vec<String>,
+    positional: Vec<String>,
+}
+
+// Options without a value.
+const FLAGS: &[&str] = &["--admin"];
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, Error> {
+        let mut parsed = Args::default();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if FLAGS.contains(&arg.as_str()) {
+                parsed.flags.push(arg.clone());
+            } else if arg.starts_with("--") {
+                let value = args
+                    .next()
+                    .ok_or_else(|| Error::MissingValue(arg.clone()))?
+                    .clone();
+                parsed.options.entry(arg.clone()).or_default().push(value);
+            } else {
+                parsed.positional.push(arg.clone());
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    fn one(&mut self, name: &str) -> Result<Option<String>, Error> {
+        let mut values = self.all(name);
+        if values.len() > 1 {
+            return Err(Error::RepeatedOption(name.to_string()));
+        }
+
+        Ok(values.pop())
+    }
+
+    fn required(&mut self, name: &'static str) -> Result<String, Error> {
+        self.one(name)?.ok_or(Error::MissingOption(name))
+    }
+
+    fn all(&mut self, name: &str) -> Vec<String> {
+        self.options.remove(name).unwrap_or_default()
+    }
+
+    fn flag(&mut self, name: &str) -> bool {
+        let len = self.flags.len();
+        self.flags.retain(|flag| flag != name);
+
+        self.flags.len() != len
+    }
+
+    fn positional(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.positional)
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        if let Some(option) = self.options.keys().chain(&self.flags).next() {
+            return Err(Error::UnknownOption(option.clone()));
+        }
+        if let Some(arg) = self.positional.into_iter().next() {
+            return Err(Error::UnexpectedArgument(arg));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn parse_doctor_defaults() {
+        let command = parse(&args(&["doctor"])).unwrap();
+
+        assert_eq!(
+            command,
+            Command::Doctor(DoctorOptions {
+                server_socket: DEFAULT_SERVER_SOCKET.to_string(),
+                agent_sockets: vec![DEFAULT_AGENT_SOCKET.to_string()],
+                canary_spiffe_id: None,
+                canary_audience: DEFAULT_CANARY_AUDIENCE.to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_doctor_options() {
+        let command = parse(&args(&[
+            "doctor",
+            "--agent-socket",
+            "agent1.sock",
+            "--agent-socket",
+            "agent2.sock",
+            "--canary-spiffe-id",
+            "spiffe://iotedge/canary",
+        ]))
+        .unwrap();
+
+        let options = match command {
+            Command::Doctor(options) => options,
+            _ => panic!("Expected doctor command"),
+        };
+        assert_eq!(options.agent_sockets, vec!["agent1.sock", "agent2.sock"]);
+        assert_eq!(
+            options.canary_spiffe_id,
+            Some("spiffe://iotedge/canary".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_entry_create() {
+        let command = parse(&args(&[
+            "entry",
+            "create",
+            "--id",
+            "web",
+            "--spiffe-id-path",
+            "web",
+            "--plugin",
+            "K8S",
+            "--parent-id",
+            "node",
+            "--selector",
+            "NAMESPACE:default",
+            "--selector",
+            "SERVICEACCOUNT:web",
+            "--admin",
+            "--output",
+            "json",
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            command,
+            Command::Admin(
+                AdminOptions {
+                    server_socket: DEFAULT_SERVER_SOCKET.to_string(),
+                    output: Output::Json,
+                },
+                AdminCommand::EntryCreate(NewEntries::Entry {
+                    id: "web".to_string(),
+                    spiffe_id_path: "web".to_string(),
+                    plugin: "K8S".to_string(),
+                    parent_id: Some("node".to_string()),
+                    selectors: args(&["NAMESPACE:default", "SERVICEACCOUNT:web"]),
+                    dns_names: Vec::new(),
+                    admin: true,
+                })
+            )
+        );
+    }
+
+    #[test]
+    fn parse_admin_commands() {
+        let command = |list: &[&str]| match parse(&args(list)).unwrap() {
+            Command::Admin(_, command) => command,
+            _ => panic!("Expected admin command"),
+        };
+
+        assert_eq!(
+            command(&["entry", "delete", "id1", "id2"]),
+            AdminCommand::EntryDelete {
+                ids: args(&["id1", "id2"])
+            }
+        );
+        assert_eq!(
+            command(&["entry", "list", "--plugin", "PSAT"]),
+            AdminCommand::EntryList(EntryFilter {
+                plugin: Some("PSAT".to_string()),
+                ..Default::default()
+            })
+        );
+        assert_eq!(command(&["bundle", "show"]), AdminCommand::BundleShow);
+        assert_eq!(
+            command(&["agent", "ban", "--selector", "AGENTNODENAME:node1"]),
+            AdminCommand::AgentBan {
+                selectors: args(&["AGENTNODENAME:node1"]),
+                reason: None,
+            }
+        );
+        assert_eq!(
+            command(&["agent", "unban", "ban1"]),
+            AdminCommand::AgentUnban {
+                id: "ban1".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse(&[]).unwrap_err(), Error::MissingCommand);
+        assert_eq!(
+            parse(&args(&["fix"])).unwrap_err(),
+            Error::UnknownCommand("fix".to_string())
+        );
+        assert_eq!(
+            parse(&args(&["doctor", "--verbose", "1"])).unwrap_err(),
+            Error::UnknownOption("--verbose".to_string())
+        );
+        assert_eq!(
+            parse(&args(&["doctor", "--server-socket"])).unwrap_err(),
+            Error::MissingValue("--server-socket".to_string())
+        );
+        assert_eq!(
+            parse(&args(&["entry", "show"])).unwrap_err(),
+            Error::UnknownCommand("entry show".to_string())
+        );
+        assert_eq!(
+            parse(&args(&["entry", "create", "--id", "web"])).unwrap_err(),
+            Error::MissingOption("--spiffe-id-path")
+        );
+        assert_eq!(
+            parse(&args(&["entry", "list", "--admin"])).unwrap_err(),
+            Error::UnknownOption("--admin".to_string())
+        );
+        assert_eq!(
+            parse(&args(&["bundle", "show", "--output", "yaml"])).unwrap_err(),
+            Error::InvalidOutput("yaml".to_string())
+        );
+        assert_eq!(
+            parse(&args(&["agent", "ban"])).unwrap_err(),
+            Error::MissingOption("--selector")
+        );
+    }
+}
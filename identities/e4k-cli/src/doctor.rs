@@ -0,0 +1,373 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// End to end diagnostics of a running deployment. Every check adds a line to the report, a failed
+// check does not stop the others so the report shows everything that is wrong at once.
+//
+// The agents are reached through their workload API socket, the only interface they expose. The
+// test issuance only works if the canary entry matches the workload running the doctor. Agents
+// answer FetchJWTBundles with the bundle of the server, but validate JWT-SVIDs with the bundle they
+// cached, so validating the canary JWT-SVID on each agent checks the freshness of its bundle.
+
+use std::{collections::BTreeSet, io, time::Duration};
+
+use core_objects::JWKSet;
+use http_common::{ErrorBody, HttpRequest};
+use server_admin_api::{get_health, get_trust_bundle_history, ApiVersion};
+use tokio::{net::UnixStream, time};
+use tonic::{
+    transport::{Channel, Endpoint, Uri},
+    Code,
+};
+use tower::service_fn;
+use workload_api::generated::{
+    spiffe_workload_api_client::SpiffeWorkloadApiClient, JwtBundlesRequest, JwtsvidRequest,
+    ValidateJwtsvidRequest,
+};
+
+use crate::{
+    admin::{admin_connector, ADMIN_BASE_URL},
+    args::DoctorOptions,
+    report::Report,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const HINT_SERVER_UNREACHABLE: &str =
+    "Check the server is running and that --server-socket is the \
+    `socket_path` of the server config.";
+const HINT_SERVER_UNHEALTHY: &str =
+    "A backend of the server is unreachable, check the catalog and \
+    key store settings and the server logs.";
+const HINT_NO_TRUST_BUNDLE: &str =
+    "The server has not published a trust bundle yet, check the key \
+    manager errors in the server logs.";
+const HINT_AGENT_UNREACHABLE: &str = "Check the agent runs on this node and that the socket is \
+    mounted in the pod running the doctor.";
+const HINT_AGENT_SERVER: &str = "The agent cannot get the trust bundle from the server, check \
+    `server_config` in the agent config and the network policies between agent and server.";
+const HINT_BUNDLE_MISMATCH: &str = "The agent and the server do not agree on the trust domain or \
+    the keys, check `trust_domain` in both configs. Keys can also differ for a few seconds during \
+    a rotation.";
+const HINT_NO_CANARY: &str =
+    "Create a canary entry whose workload selectors match the pod running \
+    the doctor, and pass its SPIFFE ID with --canary-spiffe-id.";
+const HINT_ISSUANCE_DENIED: &str = "The issuance policy of the server denied the canary entry, \
+    check the server audit log.";
+const HINT_ISSUANCE_FAILED: &str = "Check the agent logs, the workload or node attestation failed \
+    or the server could not sign the JWT-SVID.";
+const HINT_STALE_BUNDLE: &str = "The agent validated the canary JWT-SVID with a stale trust \
+    bundle. It is refreshed every `spiffe_refresh_hint` seconds, check the trust bundle refresh \
+    errors in the agent logs or restart the agent.";
+
+// Trust domain and key ids of the bundle published by the server.
+struct ServerBundle {
+    trust_domain: String,
+    kids: BTreeSet<String>,
+}
+
+pub async fn run(options: &DoctorOptions) -> Report {
+    let mut report = Report::default();
+
+    let server_bundle = check_server(&options.server_socket, &mut report).await;
+
+    let mut canary_svid = None;
+    for (index, socket) in options.agent_sockets.iter().enumerate() {
+        let mut client = match connect_agent(socket).await {
+            Ok(client) => {
+                report.ok(format!("Agent socket {}", socket), "reachable");
+                client
+            }
+            Err(err) => {
+                report.error(
+                    format!("Agent socket {}", socket),
+                    err,
+                    HINT_AGENT_UNREACHABLE,
+                );
+                continue;
+            }
+        };
+
+        check_agent_bundle(&mut client, socket, server_bundle.as_ref(), &mut report).await;
+
+        // One issuance is enough, the same JWT-SVID is then validated by every agent.
+        if index == 0 {
+            canary_svid = issue_canary(&mut client, options, &mut report).await;
+        }
+
+        match &canary_svid {
+            Some(canary_svid) => {
+                check_bundle_freshness(&mut client, socket, canary_svid, options, &mut report)
+                    .await;
+            }
+            None => report.skipped(
+                format!("Trust bundle freshness {}", socket),
+                "no canary JWT-SVID",
+            ),
+        }
+    }
+
+    report
+}
+
+async fn check_server(socket: &str, report: &mut Report) -> Option<ServerBundle> {
+    let connector = match admin_connector(socket) {
+        Ok(connector) => connector,
+        Err(err) => {
+            report.error("Server health", err, HINT_SERVER_UNREACHABLE);
+            return None;
+        }
+    };
+
+    let health = match get_health(connector.clone()).await {
+        Ok(health) => health,
+        Err(err) => {
+            report.error("Server health", err.to_string(), HINT_SERVER_UNREACHABLE);
+            return None;
+        }
+    };
+
+    if health.healthy {
+        report.ok(
+            "Server health",
+            format!(
+                "server {}, trust domain {}, catalog {}, key store {}",
+                health.info.server_version,
+                health.info.trust_domain,
+                health.info.catalog.backend_type,
+                health.info.key_store.backend_type
+            ),
+        );
+    } else {
+        report.error(
+            "Server health",
+            health.error.unwrap_or_else(|| "unhealthy".to_string()),
+            HINT_SERVER_UNHEALTHY,
+        );
+    }
+
+    let history = match get_trust_bundle_history(connector).await {
+        Ok(history) => history,
+        Err(err) => {
+            report.error(
+                "Server trust bundle",
+                err.to_string(),
+                HINT_SERVER_UNHEALTHY,
+            );
+            return None;
+        }
+    };
+
+    // Most recent version first.
+    let current = match history.versions.into_iter().next() {
+        Some(current) => current,
+        None => {
+            report.error("Server trust bundle", "no version", HINT_NO_TRUST_BUNDLE);
+            return None;
+        }
+    };
+    report.ok(
+        "Server trust bundle",
+        format!(
+            "version {} with {} keys",
+            current.version,
+            current.keys.len()
+        ),
+    );
+
+    Some(ServerBundle {
+        trust_domain: health.info.trust_domain,
+        kids: current.keys.into_iter().map(|jwk| jwk.kid).collect(),
+    })
+}
+
+// The health is returned with both status codes, the body tells which backend is failing.
+async fn get_health(connector: http_common::Connector) -> io::Result<get_health::Response> {
+    let uri = format!(
+        "{}/health?api-version={}",
+        ADMIN_BASE_URL,
+        ApiVersion::V2022_06_01
+    );
+    let request: HttpRequest<(), _> = HttpRequest::get(connector, &uri);
+
+    let response = request.json_response().await?;
+    response.parse::<get_health::Response, ErrorBody<'_>>(&[
+        hyper::StatusCode::OK,
+        hyper::StatusCode::SERVICE_UNAVAILABLE,
+    ])
+}
+
+async fn get_trust_bundle_history(
+    connector: http_common::Connector,
+) -> io::Result<get_trust_bundle_history::Response> {
+    let uri = format!(
+        "{}/trust-bundle/history?api-version={}",
+        ADMIN_BASE_URL,
+        ApiVersion::V2022_06_01
+    );
+    let request: HttpRequest<(), _> = HttpRequest::get(connector, &uri);
+
+    let response = request.json_response().await?;
+    response.parse_expect_ok::<get_trust_bundle_history::Response, ErrorBody<'_>>()
+}
+
+async fn connect_agent(socket: &str) -> Result<SpiffeWorkloadApiClient<Channel>, String> {
+    let socket = socket.to_string();
+
+    // The uri is ignored, the connector always connects to the socket.
+    let channel = Endpoint::try_from("http://[::]:50051")
+        .map_err(|err| err.to_string())?
+        .connect_timeout(TIMEOUT)
+        .connect_with_connector(service_fn(move |_: Uri| {
+            UnixStream::connect(socket.clone())
+        }))
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(SpiffeWorkloadApiClient::new(channel))
+}
+
+async fn check_agent_bundle(
+    client: &mut SpiffeWorkloadApiClient<Channel>,
+    socket: &str,
+    server_bundle: Option<&ServerBundle>,
+    report: &mut Report,
+) {
+    let name = format!("Agent trust bundle {}", socket);
+
+    let bundles = match fetch_jwt_bundles(client).await {
+        Ok(bundles) => bundles,
+        Err(err) => {
+            report.error(name, err, HINT_AGENT_SERVER);
+            return;
+        }
+    };
+
+    let server_bundle = match server_bundle {
+        Some(server_bundle) => server_bundle,
+        None => {
+            report.skipped(name, "the server trust bundle is unknown");
+            return;
+        }
+    };
+
+    let kids = bundles
+        .iter()
+        .find(|(trust_domain, _)| trust_domain == &server_bundle.trust_domain)
+        .map(|(_, jwk_set)| {
+            jwk_set
+                .keys
+                .iter()
+                .map(|jwk| jwk.kid.clone())
+                .collect::<BTreeSet<_>>()
+        });
+
+    match kids {
+        Some(kids) if kids == server_bundle.kids => {
+            report.ok(name, format!("{} keys, same as the server", kids.len()));
+        }
+        Some(_) => report.warning(name, "keys differ from the server", HINT_BUNDLE_MISMATCH),
+        None => report.error(
+            name,
+            format!("no bundle for trust domain {}", server_bundle.trust_domain),
+            HINT_BUNDLE_MISMATCH,
+        ),
+    }
+}
+
+async fn fetch_jwt_bundles(
+    client: &mut SpiffeWorkloadApiClient<Channel>,
+) -> Result<Vec<(String, JWKSet)>, String> {
+    let request = workload_api::request(JwtBundlesRequest::default());
+    let mut response = time::timeout(TIMEOUT, client.fetch_jwt_bundles(request))
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|status| status.message().to_string())?;
+
+    let message = time::timeout(TIMEOUT, response.get_mut().message())
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|status| status.message().to_string())?
+        .ok_or_else(|| "the stream ended without a bundle".to_string())?;
+
+    message
+        .bundles
+        .into_iter()
+        .map(|(trust_domain, jwk_set)| {
+            let jwk_set: JWKSet =
+                serde_json::from_slice(&jwk_set).map_err(|err| err.to_string())?;
+
+            Ok((trust_domain, jwk_set))
+        })
+        .collect()
+}
+
+async fn issue_canary(
+    client: &mut SpiffeWorkloadApiClient<Channel>,
+    options: &DoctorOptions,
+    report: &mut Report,
+) -> Option<String> {
+    let canary_spiffe_id = match &options.canary_spiffe_id {
+        Some(canary_spiffe_id) => canary_spiffe_id,
+        None => {
+            report.skipped("Test issuance", "no canary entry, see --canary-spiffe-id");
+            return None;
+        }
+    };
+
+    let request = JwtsvidRequest {
+        audience: vec![options.canary_audience.clone()],
+        spiffe_id: canary_spiffe_id.clone(),
+    };
+    let request = workload_api::request(request);
+    let response = match time::timeout(TIMEOUT, client.fetch_jwtsvid(request)).await {
+        Ok(Ok(response)) => response.into_inner(),
+        Ok(Err(status)) if status.code() == Code::PermissionDenied => {
+            report.error("Test issuance", status.message(), HINT_ISSUANCE_DENIED);
+            return None;
+        }
+        Ok(Err(status)) => {
+            report.error("Test issuance", status.message(), HINT_ISSUANCE_FAILED);
+            return None;
+        }
+        Err(err) => {
+            report.error("Test issuance", err.to_string(), HINT_ISSUANCE_FAILED);
+            return None;
+        }
+    };
+
+    match response.svids.into_iter().next() {
+        Some(svid) => {
+            report.ok("Test issuance", format!("issued {}", svid.spiffe_id));
+            Some(svid.svid)
+        }
+        None => {
+            report.error(
+                "Test issuance",
+                format!("no entry matched {}", canary_spiffe_id),
+                HINT_NO_CANARY,
+            );
+            None
+        }
+    }
+}
+
+async fn check_bundle_freshness(
+    client: &mut SpiffeWorkloadApiClient<Channel>,
+    socket: &str,
+    canary_svid: &str,
+    options: &DoctorOptions,
+    report: &mut Report,
+) {
+    let name = format!("Trust bundle freshness {}", socket);
+
+    let request = ValidateJwtsvidRequest {
+        audience: options.canary_audience.clone(),
+        svid: canary_svid.to_string(),
+    };
+    let request = workload_api::request(request);
+    match time::timeout(TIMEOUT, client.validate_jwtsvid(request)).await {
+        Ok(Ok(_)) => report.ok(name, "validated the canary JWT-SVID"),
+        Ok(Err(status)) => report.error(name, status.message(), HINT_STALE_BUNDLE),
+        Err(err) => report.error(name, err.to_string(), HINT_AGENT_UNREACHABLE),
+    }
+}
@@ -0,0 +1,51 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::default_trait_access,
+    clippy::let_unit_value,
+    clippy::missing_errors_doc,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+mod admin;
+mod args;
+mod doctor;
+mod report;
+
+use std::process;
+
+use args::{Command, USAGE};
+
+#[tokio::main]
+async fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+
+    let command = match args::parse(&args) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    match command {
+        Command::Help => println!("{}", USAGE),
+        Command::Doctor(options) => {
+            let report = doctor::run(&options).await;
+            print!("{}", report);
+
+            if report.has_errors() {
+                process::exit(1);
+            }
+        }
+        Command::Admin(options, command) => {
+            if let Err(err) = admin::run(&options, command).await {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Ok,
+    Warning,
+    Error,
+    Skipped,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Ok => "OK",
+            Status::Warning => "WARNING",
+            Status::Error => "ERROR",
+            Status::Skipped => "SKIPPED",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+    // How to fix the problem, only set for warnings and errors.
+    pub hint: Option<&'static str>,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    checks: Vec<Check>,
+}
+
+impl Report {
+    pub fn ok(&mut self, name: impl Into<String>, detail: impl Into<String>) {
+        self.push(name.into(), Status::Ok, detail.into(), None);
+    }
+
+    pub fn warning(
+        &mut self,
+        name: impl Into<String>,
+        detail: impl Into<String>,
+        hint: &'static str,
+    ) {
+        self.push(name.into(), Status::Warning, detail.into(), Some(hint));
+    }
+
+    pub fn error(
+        &mut self,
+        name: impl Into<String>,
+        detail: impl Into<String>,
+        hint: &'static str,
+    ) {
+        self.push(name.into(), Status::Error, detail.into(), Some(hint));
+    }
+
+    pub fn skipped(&mut self, name: impl Into<String>, detail: impl Into<String>) {
+        self.push(name.into(), Status::Skipped, detail.into(), None);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.count(Status::Error) > 0
+    }
+
+    fn push(&mut self, name: String, status: Status, detail: String, hint: Option<&'static str>) {
+        self.checks.push(Check {
+            name,
+            status,
+            detail,
+            hint,
+        });
+    }
+
+    fn count(&self, status: Status) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| check.status == status)
+            .count()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            writeln!(
+                f,
+                "{:<9} {}: {}",
+                format!("[{}]", check.status),
+                check.name,
+                check.detail
+            )?;
+            if let Some(hint) = check.hint {
+                writeln!(f, "{:<9} hint: {}", "", hint)?;
+            }
+        }
+
+        writeln!(
+            f,
+            "\n{} checks: {} ok, {} warnings, {} errors, {} skipped",
+            self.checks.len(),
+            self.count(Status::Ok),
+            self.count(Status::Warning),
+            self.count(Status::Error),
+            self.count(Status::Skipped)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_report() {
+        let mut report = Report::default();
+        report.ok("Server health", "healthy");
+        report.error(
+            "Agent socket a.sock",
+            "connection refused",
+            "Start the agent",
+        );
+        report.skipped("Test issuance", "no canary entry");
+
+        assert!(report.has_errors());
+        assert_eq!(
+            report.to_string(),
+            "\
+[OK]      Server health: healthy
+[ERROR]   Agent socket a.sock: connection refused
+          hint: Start the agent
+[SKIPPED] Test issuance: no canary entry
+
+3 checks: 1 ok, 0 warnings, 1 errors, 1 skipped
+"
+        );
+    }
+
+    #[test]
+    fn warnings_are_not_errors() {
+        let mut report = Report::default();
+        report.warning("Trust bundle", "no version", "Wait for the first rotation");
+
+        assert!(!report.has_errors());
+    }
+}
@@ -0,0 +1,360 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Admission webhook of the registrar, served over TLS to the API server. Pods annotated with a SPIFFE ID path
+// outside of the prefix of their namespace are rejected when they are created or updated. The entries of a
+// pod are applied when it is bound to its node, the workload entry needs the node entry as parent, so they
+// are in the server before the containers of the pod start.
+
+use std::{convert::Infallible, io, sync::Arc};
+
+use hyper::{server::conn::Http, service::service_fn, Body, Method, Request, Response, StatusCode};
+use k8s_openapi::api::core::v1::{Binding, Pod};
+use kube::core::{
+    admission::{AdmissionRequest, AdmissionResponse, AdmissionReview},
+    DynamicObject,
+};
+use log::{error, info, warn};
+use openssl::ssl::SslAcceptor;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_openssl::SslStream;
+
+use crate::{config::AdmissionConfig, entries::NODE_PATH_PREFIX, registrar::Registrar};
+
+const ADMISSION_PATH: &str = "/admit";
+
+pub async fn start(registrar: Arc<Registrar>, config: &AdmissionConfig) -> io::Result<()> {
+    let acceptor = acceptor(config)?;
+    let listener = TcpListener::bind((config.bind_address.as_str(), config.bind_port)).await?;
+    info!(
+        "Serving the admission webhook on {}:{}",
+        config.bind_address, config.bind_port
+    );
+
+    tokio::spawn(serve(
+        listener,
+        acceptor,
+        registrar,
+        Arc::new(config.clone()),
+    ));
+
+    Ok(())
+}
+
+fn acceptor(config: &AdmissionConfig) -> io::Result<SslAcceptor> {
+    tls_listener::acceptor(&config.cert_path, &config.key_path, |_| Ok(())).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid admission webhook configuration: {}", err),
+        )
+    })
+}
+
+async fn serve(
+    listener: TcpListener,
+    acceptor: SslAcceptor,
+    registrar: Arc<Registrar>,
+    config: Arc<AdmissionConfig>,
+) {
+    tls_listener::serve(listener, acceptor, "admission webhook", move |stream, _| {
+        serve_connection(stream, registrar.clone(), config.clone())
+    })
+    .await;
+}
+
+async fn serve_connection(
+    stream: SslStream<TcpStream>,
+    registrar: Arc<Registrar>,
+    config: Arc<AdmissionConfig>,
+) {
+    let service = service_fn(move |request| handle(registrar.clone(), config.clone(), request));
+    if let Err(err) = Http::new().serve_connection(stream, service).await {
+        error!("Error serving the admission webhook: {}", err);
+    }
+}
+
+async fn handle(
+    registrar: Arc<Registrar>,
+    config: Arc<AdmissionConfig>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::POST || request.uri().path() != ADMISSION_PATH {
+        return Ok(response(StatusCode::NOT_FOUND, Body::empty()));
+    }
+
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body,
+        Err(err) => return Ok(response(StatusCode::BAD_REQUEST, err.to_string().into())),
+    };
+    let review: AdmissionReview<DynamicObject> = match serde_json::from_slice(&body) {
+        Ok(review) => review,
+        Err(err) => return Ok(response(StatusCode::BAD_REQUEST, err.to_string().into())),
+    };
+    let request: AdmissionRequest<DynamicObject> = match review.try_into() {
+        Ok(request) => request,
+        Err(err) => return Ok(response(StatusCode::BAD_REQUEST, err.to_string().into())),
+    };
+
+    let review = admit(&registrar, &config, &request).await.into_review();
+    let body = serde_json::to_vec(&review).expect("cannot fail to serialize an admission review");
+
+    Ok(response(StatusCode::OK, body.into()))
+}
+
+fn response(status: StatusCode, body: Body) -> Response<Body> {
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    response
+}
+
+async fn admit(
+    registrar: &Registrar,
+    config: &AdmissionConfig,
+    request: &AdmissionRequest<DynamicObject>,
+) -> AdmissionResponse {
+    let response = AdmissionResponse::from(request);
+    let (namespace, object) = match (request.namespace.as_deref(), &request.object) {
+        (Some(namespace), Some(object)) => (namespace, object),
+        _ => return response,
+    };
+
+    match request.kind.kind.as_str() {
+        "Pod" => match from_object::<Pod>(object) {
+            Ok(pod) => match check_policy(registrar, config, namespace, &pod) {
+                Ok(()) => response,
+                Err(reason) => response.deny(reason),
+            },
+            Err(err) => AdmissionResponse::invalid(err),
+        },
+        // Creation of the pods/binding subresource by the scheduler. The binding is always allowed, a pod
+        // whose entries could not be applied gets them from the next apply of the watch.
+        "Binding" => {
+            match from_object::<Binding>(object) {
+                Ok(binding) => {
+                    if let Some(node_name) = binding.target.name {
+                        provision(registrar, namespace, &request.name, node_name).await;
+                    }
+                }
+                Err(err) => warn!(
+                    "Invalid binding of pod {}/{}: {}",
+                    namespace, request.name, err
+                ),
+            }
+
+            response
+        }
+        _ => response,
+    }
+}
+
+fn from_object<T: serde::de::DeserializeOwned>(
+    object: &DynamicObject,
+) -> Result<T, serde_json::Error> {
+    serde_json::to_value(object).and_then(serde_json::from_value)
+}
+
+// The SPIFFE ID path requested with the annotation must be under the prefix of the namespace of the pod. The
+// paths of the template are set by the registrar and are not checked.
+fn check_policy(
+    registrar: &Registrar,
+    config: &AdmissionConfig,
+    namespace: &str,
+    pod: &Pod,
+) -> Result<(), String> {
+    let path = match pod
+        .metadata
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(&registrar.config.annotation))
+    {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let namespace_prefix = config
+        .namespace_path_prefix
+        .replace("{namespace}", namespace);
+    if path.starts_with(NODE_PATH_PREFIX) {
+        Err(format!(
+            "SPIFFE ID path {} is reserved for the node entries",
+            path
+        ))
+    } else if !path.starts_with(&namespace_prefix)
+        || path
+            .split('/')
+            .any(|segment| segment == "." || segment == "..")
+    {
+        Err(format!(
+            "SPIFFE ID path {} of annotation {} is not under {} for the pods of namespace {}",
+            path, registrar.config.annotation, namespace_prefix, namespace
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+async fn provision(registrar: &Registrar, namespace: &str, pod_name: &str, node_name: String) {
+    let mut pod = match registrar.cache.find(namespace, pod_name) {
+        Some(pod) => pod,
+        None => {
+            warn!(
+                "Pod {}/{} bound to node {} is not known yet, its entries are applied by the watch",
+                namespace, pod_name, node_name
+            );
+            return;
+        }
+    };
+    pod.spec.get_or_insert_with(Default::default).node_name = Some(node_name);
+
+    if let Err(err) = registrar.apply(Some(pod)).await {
+        error!(
+            "Cannot apply the entries of pod {}/{}: {}",
+            namespace, pod_name, err
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream;
+    use kube::runtime::watcher::Event;
+    use serde_json::json;
+    use spiffe_server_admin_client::SpiffeFakeConnector;
+    use tokio::sync::Notify;
+
+    use crate::pods::{self, PodCache};
+
+    use super::*;
+
+    fn admission_config() -> AdmissionConfig {
+        toml::from_str(
+            r#"
+            cert_path = "/mnt/certs/tls.crt"
+            key_path = "/mnt/certs/tls.key"
+            "#,
+        )
+        .unwrap()
+    }
+
+    async fn registrar(pods: Vec<Pod>) -> (Registrar, Arc<SpiffeFakeConnector>) {
+        let config = toml::from_str(
+            r#"
+            server_socket_path = "/run/iotedge/sockets/api.sock"
+            cluster_name = "demo"
+            spiffe_id_path_prefix = "k8s/"
+            "#,
+        )
+        .unwrap();
+        let cache = Arc::new(PodCache::default());
+        pods::run(
+            cache.clone(),
+            Arc::new(Notify::new()),
+            stream::iter(vec![Ok(Event::Restarted(pods))]),
+        )
+        .await;
+        let connector = Arc::new(SpiffeFakeConnector::default());
+
+        (
+            Registrar::new(config, None, cache, connector.clone()),
+            connector,
+        )
+    }
+
+    fn request(
+        kind: &str,
+        name: &str,
+        object: serde_json::Value,
+    ) -> AdmissionRequest<DynamicObject> {
+        let review: AdmissionReview<DynamicObject> = serde_json::from_value(json!({
+            "apiVersion": "admission.k8s.io/v1",
+            "kind": "AdmissionReview",
+            "request": {
+                "uid": "705ab4f5-6393-11e8-b7cc-42010a800002",
+                "kind": { "group": "", "version": "v1", "kind": kind },
+                "resource": { "group": "", "version": "v1", "resource": "pods" },
+                "name": name,
+                "namespace": "default",
+                "operation": "CREATE",
+                "userInfo": {},
+                "object": object,
+                "dryRun": false,
+            },
+        }))
+        .unwrap();
+
+        review.try_into().unwrap()
+    }
+
+    fn pod_request(annotation: Option<&str>) -> AdmissionRequest<DynamicObject> {
+        let annotations = annotation
+            .map(|path| json!({ "e4k.azure.com/spiffe-id-path": path }))
+            .unwrap_or_else(|| json!({}));
+
+        request(
+            "Pod",
+            "web-0",
+            json!({
+                "apiVersion": "v1",
+                "kind": "Pod",
+                "metadata": { "name": "web-0", "namespace": "default", "annotations": annotations },
+                "spec": { "containers": [] },
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn admit_pods_of_namespace_policy() {
+        let (registrar, _) = registrar(Vec::new()).await;
+        let config = admission_config();
+
+        for (annotation, allowed) in [
+            (None, true),
+            (Some("ns/default/web"), true),
+            (Some("ns/other/web"), false),
+            (Some("ns/default/../other/web"), false),
+            (Some("node/node1"), false),
+        ] {
+            let response = admit(&registrar, &config, &pod_request(annotation)).await;
+            assert_eq!(response.allowed, allowed, "{:?}", annotation);
+        }
+    }
+
+    #[tokio::test]
+    async fn admit_binding_applies_entries() {
+        let pod = serde_json::from_value(json!({
+            "apiVersion": "v1",
+            "kind": "Pod",
+            "metadata": {
+                "name": "web-0",
+                "namespace": "default",
+                "uid": "0b1e3a4c-8b0d-4a0a-9a4e-6c8f1e2b3d4f",
+                "annotations": { "e4k.azure.com/spiffe-id-path": "ns/default/web" },
+            },
+            "spec": { "containers": [] },
+        }))
+        .unwrap();
+        let (registrar, connector) = registrar(vec![pod]).await;
+
+        let binding = request(
+            "Binding",
+            "web-0",
+            json!({
+                "apiVersion": "v1",
+                "kind": "Binding",
+                "metadata": { "name": "web-0", "namespace": "default" },
+                "target": { "kind": "Node", "name": "node1" },
+            }),
+        );
+        let response = admit(&registrar, &admission_config(), &binding).await;
+
+        assert!(response.allowed);
+        let mut paths: Vec<_> = connector
+            .current_identities
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.spiffe_id_path.clone())
+            .collect();
+        paths.sort();
+        assert_eq!(paths, vec!["k8s/node/node1", "k8s/ns/default/web"]);
+    }
+}
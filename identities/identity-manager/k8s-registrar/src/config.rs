@@ -0,0 +1,77 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeSet;
+
+use logging::LogFormat;
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Config {
+    // Admin socket of the server.
+    pub server_socket_path: String,
+    // Cluster name of the PSAT node attestation of the server, set on the node entries.
+    pub cluster_name: String,
+    // The entries of the registrar are under this prefix. The registrar owns the prefix: the other entries
+    // under it are deleted.
+    pub spiffe_id_path_prefix: String,
+    // SPIFFE ID path of the pods, after the prefix. Only the annotated pods are registered when not set.
+    #[serde(default)]
+    pub spiffe_id_template: Option<String>,
+    // Annotation of the pods setting their SPIFFE ID path after the prefix, it takes precedence over the
+    // template.
+    #[serde(default = "default_annotation")]
+    pub annotation: String,
+    // Namespaces of the registered pods, all of them when empty.
+    #[serde(default)]
+    pub namespaces: BTreeSet<String>,
+    #[serde(default = "default_ignored_namespaces")]
+    pub ignored_namespaces: BTreeSet<String>,
+    // The entries are applied after every change of the pods, and at this interval in case an apply failed
+    // or the entries were changed by someone else.
+    #[serde(default = "default_resync_interval_secs")]
+    pub resync_interval_secs: u64,
+    #[serde(default)]
+    pub log_format: LogFormat,
+    // Admission webhook, not served when not set.
+    #[serde(default)]
+    pub admission: Option<AdmissionConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct AdmissionConfig {
+    #[serde(default = "default_admission_bind_address")]
+    pub bind_address: String,
+    #[serde(default = "default_admission_bind_port")]
+    pub bind_port: u16,
+    // Serving certificate chain and key of the webhook, the API server verifies them with the CA bundle of
+    // the webhook configuration.
+    pub cert_path: String,
+    pub key_path: String,
+    // SPIFFE ID paths allowed in the annotation of the pods of a namespace start with this prefix, after the
+    // prefix of the registrar. `{namespace}` is replaced by the namespace of the pod.
+    #[serde(default = "default_namespace_path_prefix")]
+    pub namespace_path_prefix: String,
+}
+
+fn default_annotation() -> String {
+    "e4k.azure.com/spiffe-id-path".to_string()
+}
+
+fn default_ignored_namespaces() -> BTreeSet<String> {
+    BTreeSet::from(["kube-system".to_string()])
+}
+
+fn default_resync_interval_secs() -> u64 {
+    300
+}
+
+fn default_admission_bind_address() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_admission_bind_port() -> u16 {
+    8443
+}
+
+fn default_namespace_path_prefix() -> String {
+    "ns/{namespace}/".to_string()
+}
@@ -0,0 +1,481 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Entries of the pods. A registered pod gets a workload entry with the SPIFFE ID of its annotation or of the
+// template, under the node entry of its node. The pods with the same SPIFFE ID on a node share an entry, the
+// ones of a deployment usually do.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use core_objects::{
+    build_selector_string, AttestationConfig, EntryNodeAttestation, EntryWorkloadAttestation,
+    NodeAttestationPlugin, NodeSelectorType, RegistrationEntry, WorkloadAttestationPlugin,
+    WorkloadSelectorType,
+};
+use k8s_openapi::api::core::v1::Pod;
+use thiserror::Error;
+
+use crate::config::Config;
+
+const ENTRY_ID_PREFIX: &str = "k8s-registrar";
+// The k8s catalog stores the entries as resources named by their id.
+const MAX_ENTRY_ID_LEN: usize = 253;
+// SPIFFE ID paths of the node entries, after the prefix. A pod with one of them would get the identity of
+// the agents.
+pub const NODE_PATH_PREFIX: &str = "node/";
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TemplateError {
+    #[error("Placeholder is not closed in template {0}")]
+    Unclosed(String),
+    #[error("Unknown placeholder {{{0}}}")]
+    UnknownPlaceholder(String),
+    #[error("Pod has no label {0}")]
+    MissingLabel(String),
+    #[error("SPIFFE ID path {0} is reserved for the node entries")]
+    ReservedPath(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Namespace,
+    ServiceAccount,
+    PodName,
+    Label(String),
+}
+
+// `{namespace}`, `{service_account}`, `{pod_name}` and `{label:<key>}` are replaced by the ones of the pod.
+// The selectors of the entries pin every placeholder of the template.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, TemplateError> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| TemplateError::Unclosed(template.to_string()))?;
+            let placeholder = &rest[start + 1..start + end];
+            parts.push(match placeholder {
+                "namespace" => Part::Namespace,
+                "service_account" => Part::ServiceAccount,
+                "pod_name" => Part::PodName,
+                _ => match placeholder.strip_prefix("label:") {
+                    Some(key) => Part::Label(key.to_string()),
+                    None => return Err(TemplateError::UnknownPlaceholder(placeholder.to_string())),
+                },
+            });
+
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+
+        Ok(Template { parts })
+    }
+
+    // SPIFFE ID path of the pod with the selectors of its placeholders.
+    fn render(&self, pod: &PodInfo<'_>) -> Result<(String, BTreeSet<String>), TemplateError> {
+        let mut path = String::new();
+        let mut selectors = BTreeSet::new();
+
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => path.push_str(text),
+                // Always in the selectors of the entries.
+                Part::Namespace => path.push_str(pod.namespace),
+                Part::ServiceAccount => path.push_str(pod.service_account),
+                Part::PodName => {
+                    path.push_str(pod.name);
+                    selectors.insert(build_selector_string(
+                        &WorkloadSelectorType::PodName,
+                        pod.name,
+                    ));
+                }
+                Part::Label(key) => {
+                    let value = pod
+                        .labels
+                        .and_then(|labels| labels.get(key))
+                        .ok_or_else(|| TemplateError::MissingLabel(key.clone()))?;
+                    path.push_str(value);
+                    selectors.insert(build_selector_string(
+                        &WorkloadSelectorType::PodLabels,
+                        format!("{}:{}", key, value),
+                    ));
+                }
+            }
+        }
+
+        Ok((path, selectors))
+    }
+}
+
+struct PodInfo<'a> {
+    namespace: &'a str,
+    name: &'a str,
+    service_account: &'a str,
+    node_name: &'a str,
+    labels: Option<&'a BTreeMap<String, String>>,
+}
+
+impl<'a> PodInfo<'a> {
+    // Pods that are not scheduled yet or that are done do not need an identity.
+    fn new(pod: &'a Pod) -> Option<Self> {
+        let spec = pod.spec.as_ref()?;
+        let phase = pod
+            .status
+            .as_ref()
+            .and_then(|status| status.phase.as_deref());
+        if matches!(phase, Some("Succeeded" | "Failed")) {
+            return None;
+        }
+
+        Some(PodInfo {
+            namespace: pod.metadata.namespace.as_deref()?,
+            name: pod.metadata.name.as_deref()?,
+            service_account: spec.service_account_name.as_deref().unwrap_or("default"),
+            node_name: spec.node_name.as_deref()?,
+            labels: pod.metadata.labels.as_ref(),
+        })
+    }
+}
+
+pub fn desired_entries<'a>(
+    config: &Config,
+    template: Option<&Template>,
+    pods: impl IntoIterator<Item = &'a Pod>,
+) -> Vec<RegistrationEntry> {
+    let mut entries: BTreeMap<String, RegistrationEntry> = BTreeMap::new();
+
+    for pod in pods {
+        let info = match PodInfo::new(pod) {
+            Some(info) => info,
+            None => continue,
+        };
+        if (!config.namespaces.is_empty() && !config.namespaces.contains(info.namespace))
+            || config.ignored_namespaces.contains(info.namespace)
+        {
+            continue;
+        }
+
+        let (path, mut selectors) = match workload_path(config, template, pod, &info) {
+            Some(Ok(workload)) => workload,
+            Some(Err(err)) => {
+                log::warn!(
+                    "Cannot register pod {}/{}: {}",
+                    info.namespace,
+                    info.name,
+                    err
+                );
+                continue;
+            }
+            None => continue,
+        };
+        selectors.insert(build_selector_string(
+            &WorkloadSelectorType::Namespace,
+            info.namespace,
+        ));
+        selectors.insert(build_selector_string(
+            &WorkloadSelectorType::ServiceAccount,
+            info.service_account,
+        ));
+
+        let node = node_entry(config, info.node_name);
+        let workload = entry(
+            entry_id(&["workload", info.node_name, &path]),
+            format!("{}{}", config.spiffe_id_path_prefix, path),
+            AttestationConfig::Workload(EntryWorkloadAttestation {
+                parent_id: node.id.clone(),
+                value: selectors.into_iter().collect(),
+                plugin: WorkloadAttestationPlugin::K8s,
+            }),
+        );
+
+        match entries.get(&workload.id) {
+            Some(existing) if existing.attestation_config != workload.attestation_config => {
+                log::warn!(
+                    "Pod {}/{} has the SPIFFE ID path {} of another pod with other selectors, it is not registered",
+                    info.namespace,
+                    info.name,
+                    path
+                );
+            }
+            _ => {
+                entries.insert(node.id.clone(), node);
+                entries.insert(workload.id.clone(), workload);
+            }
+        }
+    }
+
+    entries.into_values().collect()
+}
+
+// None when the pod is neither annotated nor covered by a template.
+fn workload_path(
+    config: &Config,
+    template: Option<&Template>,
+    pod: &Pod,
+    info: &PodInfo<'_>,
+) -> Option<Result<(String, BTreeSet<String>), TemplateError>> {
+    let annotation = pod
+        .metadata
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(&config.annotation));
+
+    let workload = match (annotation, template) {
+        // The path of an annotation is only the one of the pod.
+        (Some(path), _) => Ok((
+            path.clone(),
+            BTreeSet::from([build_selector_string(
+                &WorkloadSelectorType::PodName,
+                info.name,
+            )]),
+        )),
+        (None, Some(template)) => template.render(info),
+        (None, None) => return None,
+    };
+
+    Some(workload.and_then(|(path, selectors)| {
+        if path.starts_with(NODE_PATH_PREFIX) {
+            Err(TemplateError::ReservedPath(path))
+        } else {
+            Ok((path, selectors))
+        }
+    }))
+}
+
+fn node_entry(config: &Config, node_name: &str) -> RegistrationEntry {
+    entry(
+        entry_id(&["node", node_name]),
+        format!(
+            "{}{}{}",
+            config.spiffe_id_path_prefix, NODE_PATH_PREFIX, node_name
+        ),
+        AttestationConfig::Node(EntryNodeAttestation {
+            value: vec![
+                build_selector_string(&NodeSelectorType::Cluster, &config.cluster_name),
+                build_selector_string(&NodeSelectorType::AgentNodeName, node_name),
+            ],
+            plugin: NodeAttestationPlugin::Psat,
+        }),
+    )
+}
+
+fn entry(
+    id: String,
+    spiffe_id_path: String,
+    attestation_config: AttestationConfig,
+) -> RegistrationEntry {
+    RegistrationEntry {
+        id,
+        other_identities: Vec::new(),
+        spiffe_id_path,
+        attestation_config,
+        admin: false,
+        expires_at: 0,
+        dns_names: Vec::new(),
+        revision_number: 0,
+        store_svid: false,
+        prefetch: false,
+        ttl: None,
+        custom_claims: Default::default(),
+        federates_with: Vec::new(),
+    }
+}
+
+// Same for the same parts, so the entries of the pods are found again. Only the characters of kubernetes
+// resource names are kept.
+fn entry_id(parts: &[&str]) -> String {
+    let id: String = std::iter::once(ENTRY_ID_PREFIX)
+        .chain(parts.iter().copied())
+        .collect::<Vec<_>>()
+        .join(".")
+        .to_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .take(MAX_ENTRY_ID_LEN)
+        .collect();
+
+    id.trim_end_matches(|c| c == '-' || c == '.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::{
+        api::core::v1::{PodSpec, PodStatus},
+        apimachinery::pkg::apis::meta::v1::ObjectMeta,
+    };
+
+    use super::*;
+
+    fn config() -> Config {
+        toml::from_str(
+            r#"
+            server_socket_path = "/run/iotedge/sockets/api.sock"
+            cluster_name = "demo"
+            spiffe_id_path_prefix = "k8s/"
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn pod(name: &str, node_name: &str, labels: &[(&str, &str)]) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some("default".to_string()),
+                labels: Some(
+                    labels
+                        .iter()
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                        .collect(),
+                ),
+                ..Default::default()
+            },
+            spec: Some(PodSpec {
+                service_account_name: Some("web".to_string()),
+                node_name: Some(node_name.to_string()),
+                ..Default::default()
+            }),
+            status: None,
+        }
+    }
+
+    #[test]
+    fn parse_template() {
+        let template = Template::parse("ns/{namespace}/app/{label:app}").unwrap();
+        assert_eq!(
+            template.parts,
+            vec![
+                Part::Text("ns/".to_string()),
+                Part::Namespace,
+                Part::Text("/app/".to_string()),
+                Part::Label("app".to_string()),
+            ]
+        );
+
+        assert_eq!(
+            Template::parse("ns/{namespace").unwrap_err(),
+            TemplateError::Unclosed("ns/{namespace".to_string())
+        );
+        assert_eq!(
+            Template::parse("{node}").unwrap_err(),
+            TemplateError::UnknownPlaceholder("node".to_string())
+        );
+    }
+
+    #[test]
+    fn entries_of_template() {
+        let config = config();
+        let template = Template::parse("ns/{namespace}/app/{label:app}").unwrap();
+        let pods = vec![
+            pod("web-1", "node1", &[("app", "web")]),
+            pod("web-2", "node1", &[("app", "web")]),
+            pod("web-3", "node2", &[("app", "web")]),
+            // Not registered, the label of the template is missing.
+            pod("other", "node1", &[]),
+        ];
+
+        let entries = desired_entries(&config, Some(&template), &pods);
+        let ids: Vec<&str> = entries.iter().map(|entry| entry.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "k8s-registrar.node.node1",
+                "k8s-registrar.node.node2",
+                "k8s-registrar.workload.node1.ns-default-app-web",
+                "k8s-registrar.workload.node2.ns-default-app-web",
+            ]
+        );
+
+        let workload = &entries[2];
+        assert_eq!(workload.spiffe_id_path, "k8s/ns/default/app/web");
+        assert_eq!(
+            workload.attestation_config,
+            AttestationConfig::Workload(EntryWorkloadAttestation {
+                parent_id: "k8s-registrar.node.node1".to_string(),
+                value: vec![
+                    "NAMESPACE:default".to_string(),
+                    "PODLABELS:app:web".to_string(),
+                    "SERVICEACCOUNT:web".to_string(),
+                ],
+                plugin: WorkloadAttestationPlugin::K8s,
+            })
+        );
+        assert_eq!(
+            entries[0].attestation_config,
+            AttestationConfig::Node(EntryNodeAttestation {
+                value: vec![
+                    "CLUSTER:demo".to_string(),
+                    "AGENTNODENAME:node1".to_string()
+                ],
+                plugin: NodeAttestationPlugin::Psat,
+            })
+        );
+    }
+
+    #[test]
+    fn entries_of_annotations() {
+        let mut config = config();
+        config.ignored_namespaces.insert("default".to_string());
+        let mut annotated = pod("db-0", "node1", &[]);
+        annotated.metadata.annotations = Some(BTreeMap::from([(
+            config.annotation.clone(),
+            "db".to_string(),
+        )]));
+        let mut done = annotated.clone();
+        done.status = Some(PodStatus {
+            phase: Some("Succeeded".to_string()),
+            ..Default::default()
+        });
+
+        // Pods of the ignored namespaces are not registered.
+        assert!(desired_entries(&config, None, [&annotated]).is_empty());
+
+        config.ignored_namespaces.clear();
+        let entries = desired_entries(&config, None, [&annotated, &pod("web-1", "node1", &[])]);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].spiffe_id_path, "k8s/db");
+        assert_eq!(
+            entries[1].attestation_config,
+            AttestationConfig::Workload(EntryWorkloadAttestation {
+                parent_id: "k8s-registrar.node.node1".to_string(),
+                value: vec![
+                    "NAMESPACE:default".to_string(),
+                    "PODNAME:db-0".to_string(),
+                    "SERVICEACCOUNT:web".to_string(),
+                ],
+                plugin: WorkloadAttestationPlugin::K8s,
+            })
+        );
+
+        // Pods that are done are not registered.
+        assert!(desired_entries(&config, None, [&done]).is_empty());
+
+        // Nor the ones taking the SPIFFE ID of a node.
+        annotated
+            .metadata
+            .annotations
+            .as_mut()
+            .unwrap()
+            .insert(config.annotation.clone(), "node/node1".to_string());
+        assert!(desired_entries(&config, None, [&annotated]).is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::default_trait_access,
+    clippy::let_unit_value,
+    clippy::missing_errors_doc,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+// Registers the pods of the cluster in the server: the entries of the pods are computed from their
+// annotations or from a template, and applied under the SPIFFE ID path prefix of the registrar after every
+// change of the pods. The optional admission webhook rejects the pods requesting a SPIFFE ID outside of their
+// namespace and applies the entries of a pod when it is bound to its node.
+
+use std::{sync::Arc, time::Duration};
+
+use k8s_openapi::api::core::v1::Pod;
+use kube::{api::ListParams, runtime::watcher::watcher, Api, Client};
+use log::{error, info};
+use spiffe_server_admin_client::SpiffeHttpClient;
+use tokio::{sync::Notify, time};
+
+use config::Config;
+use entries::Template;
+use pods::PodCache;
+use registrar::Registrar;
+
+mod admission;
+mod config;
+mod entries;
+mod pods;
+mod registrar;
+
+const CONFIG_DEFAULT_PATH: &str = "/mnt/config/registrar.toml";
+// Changes of the pods coming in a burst, e.g. during a rollout, are applied together.
+const APPLY_DEBOUNCE: Duration = Duration::from_secs(2);
+
+#[tokio::main]
+async fn main() {
+    let path = std::env::var("CONFIG_PATH").unwrap_or_else(|_| CONFIG_DEFAULT_PATH.to_string());
+
+    let config = match read_config(&path) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Cannot read config {}: {}", path, err);
+            std::process::exit(1);
+        }
+    };
+
+    logging::try_init(config.log_format, None).expect("cannot fail to initialize global logger");
+
+    if let Err(err) = main_inner(config).await {
+        error!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn read_config(path: &str) -> Result<Config, Box<dyn std::error::Error>> {
+    let config = std::fs::read_to_string(path)?;
+
+    Ok(toml::from_str(&config)?)
+}
+
+async fn main_inner(config: Config) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let template = config
+        .spiffe_id_template
+        .as_deref()
+        .map(Template::parse)
+        .transpose()?;
+    let connector = SpiffeHttpClient::new(&config.server_socket_path)?;
+    let client = Client::try_default().await?;
+
+    let cache = Arc::new(PodCache::default());
+    let changed = Arc::new(Notify::new());
+    let pods: Api<Pod> = Api::all(client);
+    tokio::spawn(pods::run(
+        cache.clone(),
+        changed.clone(),
+        watcher(pods, ListParams::default()),
+    ));
+
+    let resync_interval = Duration::from_secs(config.resync_interval_secs);
+    info!(
+        "Registering the pods under {} with the server at {}",
+        config.spiffe_id_path_prefix, config.server_socket_path
+    );
+
+    let admission_config = config.admission.clone();
+    let registrar = Arc::new(Registrar::new(config, template, cache, Arc::new(connector)));
+    if let Some(admission_config) = admission_config {
+        admission::start(registrar.clone(), &admission_config).await?;
+    }
+
+    loop {
+        let _changed = time::timeout(resync_interval, changed.notified()).await;
+        time::sleep(APPLY_DEBOUNCE).await;
+
+        if let Err(err) = registrar.apply(None).await {
+            error!("Cannot apply the entries of the pods: {}", err);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Pods of the cluster, kept up to date by a watch on the API server and keyed by pod UID. Every change of
+// the pods is notified, so the entries are applied again.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use futures_util::{pin_mut, Stream, StreamExt};
+use k8s_openapi::api::core::v1::Pod;
+use kube::runtime::watcher::{self, Event};
+use log::{error, info};
+use tokio::{sync::Notify, time};
+
+// The watcher lists the pods again after an error, this keeps it from hammering the API server.
+const WATCH_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+pub struct PodCache {
+    // None until the first list of the watch, the entries of all the pods would be deleted otherwise.
+    pods: RwLock<Option<HashMap<String, Pod>>>,
+}
+
+impl PodCache {
+    // None when the pods were not listed yet.
+    pub fn list(&self) -> Option<Vec<Pod>> {
+        self.pods
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|pods| pods.values().cloned().collect())
+    }
+
+    pub fn find(&self, namespace: &str, name: &str) -> Option<Pod> {
+        self.pods.read().unwrap().as_ref().and_then(|pods| {
+            pods.values()
+                .find(|pod| {
+                    pod.metadata.namespace.as_deref() == Some(namespace)
+                        && pod.metadata.name.as_deref() == Some(name)
+                })
+                .cloned()
+        })
+    }
+
+    fn apply(&self, event: Event<Pod>) {
+        let mut pods = self.pods.write().unwrap();
+
+        match event {
+            Event::Applied(pod) => {
+                if let (Some(pods), Some(uid)) = (pods.as_mut(), pod.metadata.uid.clone()) {
+                    pods.insert(uid, pod);
+                }
+            }
+            Event::Deleted(pod) => {
+                if let (Some(pods), Some(uid)) = (pods.as_mut(), &pod.metadata.uid) {
+                    pods.remove(uid);
+                }
+            }
+            Event::Restarted(list) => {
+                *pods = Some(
+                    list.into_iter()
+                        .filter_map(|pod| pod.metadata.uid.clone().map(|uid| (uid, pod)))
+                        .collect(),
+                );
+            }
+        }
+    }
+}
+
+pub async fn run<S>(cache: Arc<PodCache>, changed: Arc<Notify>, events: S)
+where
+    S: Stream<Item = Result<Event<Pod>, watcher::Error>>,
+{
+    info!("Starting pod watch");
+
+    pin_mut!(events);
+    while let Some(event) = events.next().await {
+        match event {
+            Ok(event) => {
+                cache.apply(event);
+                changed.notify_one();
+            }
+            Err(err) => {
+                error!(
+                    "Error while watching pods, retrying in {:?}: {}",
+                    WATCH_RETRY_DELAY, err
+                );
+                time::sleep(WATCH_RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+    use super::*;
+
+    fn pod(uid: &str) -> Pod {
+        Pod {
+            metadata: ObjectMeta {
+                uid: Some(uid.to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn run_notifies_changes() {
+        let cache = Arc::new(PodCache::default());
+        let changed = Arc::new(Notify::new());
+        let events = stream::iter(vec![
+            // Events before the first list are ignored, the list has them anyway.
+            Ok(Event::Applied(pod("a"))),
+            Ok(Event::Restarted(vec![pod("b"), pod("c")])),
+            Ok(Event::Deleted(pod("b"))),
+        ]);
+
+        run(cache.clone(), changed.clone(), events).await;
+
+        changed.notified().await;
+        let pods = cache.list().unwrap();
+        assert_eq!(pods.len(), 1);
+        assert_eq!(pods[0].metadata.uid.as_deref(), Some("c"));
+    }
+}
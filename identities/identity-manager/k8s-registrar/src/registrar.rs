@@ -0,0 +1,65 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Applies the entries of the cached pods. The applies of the watch loop and of the admission webhook are
+// serialized, each one replaces all the entries under the prefix.
+
+use std::sync::Arc;
+
+use k8s_openapi::api::core::v1::Pod;
+use log::info;
+use spiffe_server_admin_client::SpiffeConnector;
+use tokio::sync::Mutex;
+
+use crate::{config::Config, entries, entries::Template, pods::PodCache};
+
+pub struct Registrar {
+    pub config: Config,
+    pub template: Option<Template>,
+    pub cache: Arc<PodCache>,
+    connector: Arc<dyn SpiffeConnector + Send + Sync>,
+    apply_lock: Mutex<()>,
+}
+
+impl Registrar {
+    pub fn new(
+        config: Config,
+        template: Option<Template>,
+        cache: Arc<PodCache>,
+        connector: Arc<dyn SpiffeConnector + Send + Sync>,
+    ) -> Self {
+        Registrar {
+            config,
+            template,
+            cache,
+            connector,
+            apply_lock: Mutex::new(()),
+        }
+    }
+
+    // `bound` replaces the cached pod with the same UID, the watch may not have seen its binding yet.
+    // Nothing is applied before the first list of the pods.
+    pub async fn apply(
+        &self,
+        bound: Option<Pod>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let _lock = self.apply_lock.lock().await;
+
+        let mut pods = match self.cache.list() {
+            Some(pods) => pods,
+            None => return Ok(()),
+        };
+        if let Some(bound) = bound {
+            pods.retain(|pod| pod.metadata.uid != bound.metadata.uid);
+            pods.push(bound);
+        }
+
+        let entries = entries::desired_entries(&self.config, self.template.as_ref(), &pods);
+        let count = entries.len();
+        self.connector
+            .apply_identities(entries, self.config.spiffe_id_path_prefix.clone())
+            .await?;
+        info!("Applied {} entries for {} pods", count, pods.len());
+
+        Ok(())
+    }
+}
@@ -9,4 +9,10 @@ pub trait SpiffeConnector {
     async fn get_identities(&self) -> Result<Vec<RegistrationEntry>>;
     async fn create_identities(&self, identities_to_create: Vec<RegistrationEntry>) -> Result<()>;
     async fn delete_identities(&self, identities_to_delete: Vec<String>) -> Result<()>;
+    // Makes the identities under the SPIFFE ID path prefix the same as `identities`.
+    async fn apply_identities(
+        &self,
+        identities: Vec<RegistrationEntry>,
+        spiffe_id_path_prefix: String,
+    ) -> Result<()>;
 }
@@ -45,4 +45,17 @@ impl SpiffeConnector for SpiffeFakeConnector {
 
         Ok(())
     }
+
+    async fn apply_identities(
+        &self,
+        identities: Vec<RegistrationEntry>,
+        spiffe_id_path_prefix: String,
+    ) -> Result<()> {
+        let mut current_identities = self.current_identities.lock().unwrap();
+
+        current_identities.retain(|i| !i.spiffe_id_path.starts_with(&spiffe_id_path_prefix));
+        current_identities.extend(identities);
+
+        Ok(())
+    }
 }
@@ -7,6 +7,7 @@ pub use super::SpiffeConnector;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync + 'static>>;
 const BASE_URL: &str = "https://spiffieserver.sock/entries?api-version=2022-06-01";
+const APPLY_URL: &str = "https://spiffieserver.sock/entries/apply?api-version=2022-06-01";
 
 pub struct SpiffeHttpClient {
     connector: http_common::Connector,
@@ -60,6 +61,7 @@ impl SpiffeConnector for SpiffeHttpClient {
     async fn create_identities(&self, identities_to_create: Vec<RegistrationEntry>) -> Result<()> {
         let body = server_admin_api::update_registration_entries::Request {
             entries: identities_to_create,
+            transactional: false,
         };
 
         let request = HttpRequest::post(self.connector.clone(), BASE_URL, Some(body));
@@ -75,6 +77,7 @@ impl SpiffeConnector for SpiffeHttpClient {
     async fn delete_identities(&self, identities_to_delete: Vec<String>) -> Result<()> {
         let body = server_admin_api::delete_registration_entries::Request {
             ids: identities_to_delete,
+            transactional: false,
         };
 
         let request = HttpRequest::delete(self.connector.clone(), BASE_URL, Some(body));
@@ -84,4 +87,31 @@ impl SpiffeConnector for SpiffeHttpClient {
 
         Ok(())
     }
+
+    async fn apply_identities(
+        &self,
+        identities: Vec<RegistrationEntry>,
+        spiffe_id_path_prefix: String,
+    ) -> Result<()> {
+        let body = server_admin_api::apply_entries::Request {
+            entries: identities,
+            spiffe_id_path_prefix: Some(spiffe_id_path_prefix),
+            dry_run: false,
+        };
+
+        let request = HttpRequest::post(self.connector.clone(), APPLY_URL, Some(body));
+        let response = request.json_response().await?;
+        let response: server_admin_api::apply_entries::Response =
+            response.parse_expect_ok::<_, ErrorBody<'_>>()?;
+
+        if let Err(errors) = response.results {
+            let errors: Vec<String> = errors
+                .into_iter()
+                .map(|error| format!("{}: {}", error.id, error.error))
+                .collect();
+            return Err(format!("Could not apply identities: {}", errors.join(", ")).into());
+        }
+
+        Ok(())
+    }
 }
@@ -50,6 +50,10 @@ impl Reconciler {
                 dns_names: vec!["mydns".to_string()],
                 revision_number: 1,
                 store_svid: true,
+                prefetch: false,
+                ttl: None,
+                custom_claims: Default::default(),
+                federates_with: Vec::new(),
             };
 
             if let Some(actual_entry) = existing_identities.remove(&config_entry.id) {
@@ -144,18 +148,12 @@ mod tests {
     #[tokio::test]
     async fn test_remove_entry() {
         let existing_entry = RegistrationEntry {
-            id: "2".to_string(),
             spiffe_id_path: "test2".to_string(),
             attestation_config: AttestationConfig::Node(EntryNodeAttestation {
                 value: Vec::new(),
                 plugin: NodeAttestationPlugin::Psat,
             }),
-            other_identities: Default::default(),
-            admin: Default::default(),
-            expires_at: Default::default(),
-            dns_names: Default::default(),
-            revision_number: Default::default(),
-            store_svid: Default::default(),
+            ..RegistrationEntry::test_entry("2")
         };
 
         let fake_connector = SpiffeFakeConnector {
@@ -181,18 +179,13 @@ mod tests {
     #[tokio::test]
     async fn test_modify_entry() {
         let existing_entry = RegistrationEntry {
-            id: "3".to_string(),
             spiffe_id_path: "test3".to_string(),
             attestation_config: AttestationConfig::Node(EntryNodeAttestation {
                 value: Vec::new(),
                 plugin: NodeAttestationPlugin::Psat,
             }),
-            other_identities: Default::default(),
-            admin: Default::default(),
-            expires_at: Default::default(),
-            dns_names: Default::default(),
             revision_number: 5,
-            store_svid: Default::default(),
+            ..RegistrationEntry::test_entry("3")
         };
 
         let fake_connector = SpiffeFakeConnector {
@@ -0,0 +1,60 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::time::{Duration, SystemTime};
+
+use agent_config::Config;
+use log::{error, info};
+use tokio::{
+    fs,
+    time::{self, Interval},
+};
+
+use crate::error::Error;
+
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+// Poll the config file and reload it when its modification time changes.
+pub struct ConfigWatcher {
+    path: String,
+    modified: Option<SystemTime>,
+    interval: Interval,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: &str) -> Self {
+        let modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        ConfigWatcher {
+            path: path.to_string(),
+            modified,
+            interval: time::interval(CONFIG_POLL_INTERVAL),
+        }
+    }
+
+    // Resolves with the new config once the file changed and parses. A config that fails to parse
+    // is logged and skipped, the agent keeps running with the previous one.
+    pub async fn changed(&mut self) -> Config {
+        loop {
+            self.interval.tick().await;
+
+            let modified = fs::metadata(&self.path)
+                .await
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            if modified == self.modified {
+                continue;
+            }
+            self.modified = modified;
+
+            match Config::load_config(&self.path) {
+                Ok(config) => {
+                    info!("Reloaded config from {}", self.path);
+                    return config;
+                }
+                Err(err) => error!("{}", Error::ParsingConfig(err)),
+            }
+        }
+    }
+}
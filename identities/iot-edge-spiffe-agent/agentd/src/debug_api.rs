@@ -0,0 +1,132 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Debug endpoint of the agent on a local socket, for field debugging of the devices. `GET /debug` returns
+// the workloads attested recently, the JWT-SVIDs issued to them that did not expire yet, the last
+// attestation errors and the state of the trust bundle as JSON. The socket is only accessible to the user
+// of the agent, the selectors and errors it lists are not for the workloads to read.
+
+use std::{
+    convert::Infallible, fs::Permissions, os::unix::fs::PermissionsExt, sync::Arc, time::Duration,
+};
+
+use core_objects::get_epoch_time;
+use hyper::{
+    header::{HeaderValue, CONTENT_TYPE},
+    server::conn::Http,
+    service::service_fn,
+    Body, Method, Request, Response, StatusCode,
+};
+use log::{error, info};
+use tokio::{fs, net::UnixListener, time};
+use trust_bundle_manager::TrustBundleManager;
+use workload_api_server::debug::{DebugSnapshot, DebugState};
+
+use crate::error::Error;
+
+const SOCKET_PERMISSIONS: u32 = 0o600;
+const DEBUG_PATH: &str = "/debug";
+// Accepting fails while the agent is out of file descriptors, this keeps it from spinning.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+#[derive(serde::Serialize)]
+struct DebugResponse {
+    #[serde(flatten)]
+    snapshot: DebugSnapshot,
+    trust_bundle: TrustBundleState,
+}
+
+#[derive(serde::Serialize)]
+struct TrustBundleState {
+    trust_domain: String,
+    sequence_number: u64,
+    refresh_hint: u64,
+    // Seconds since epoch, 0 until the first refresh.
+    last_refresh_at: u64,
+}
+
+pub async fn start(
+    socket_path: &str,
+    debug_state: Arc<DebugState>,
+    trust_bundle_manager: Arc<TrustBundleManager>,
+) -> Result<(), Error> {
+    fs::remove_file(socket_path).await.ok();
+    let listener = UnixListener::bind(socket_path).map_err(Error::BindingDebugListener)?;
+    fs::set_permissions(socket_path, Permissions::from_mode(SOCKET_PERMISSIONS))
+        .await
+        .map_err(Error::BindingDebugListener)?;
+
+    info!("Starting debug endpoint on {}", socket_path);
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    error!("Error accepting a connection to the debug endpoint {}", err);
+                    time::sleep(ACCEPT_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+
+            let debug_state = debug_state.clone();
+            let trust_bundle_manager = trust_bundle_manager.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |request| {
+                    handle(request, debug_state.clone(), trust_bundle_manager.clone())
+                });
+
+                if let Err(err) = Http::new()
+                    .http1_only(true)
+                    .serve_connection(stream, service)
+                    .await
+                {
+                    error!("Error serving the debug endpoint {}", err);
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+async fn handle(
+    request: Request<Body>,
+    debug_state: Arc<DebugState>,
+    trust_bundle_manager: Arc<TrustBundleManager>,
+) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::GET || request.uri().path() != DEBUG_PATH {
+        return Ok(status_response(StatusCode::NOT_FOUND));
+    }
+
+    let trust_bundle = trust_bundle_manager.get_cached_trust_bundle().await;
+    let response = DebugResponse {
+        snapshot: debug_state.snapshot(get_epoch_time()),
+        trust_bundle: TrustBundleState {
+            trust_domain: trust_bundle.trust_domain,
+            sequence_number: trust_bundle.jwt_key_set.spiffe_sequence_number,
+            refresh_hint: trust_bundle.jwt_key_set.spiffe_refresh_hint,
+            last_refresh_at: trust_bundle_manager.last_refresh_at(),
+        },
+    };
+
+    let body = match serde_json::to_vec(&response) {
+        Ok(body) => body,
+        Err(err) => {
+            error!("Error serializing the debug response {}", err);
+            return Ok(status_response(StatusCode::INTERNAL_SERVER_ERROR));
+        }
+    };
+
+    let mut response = Response::new(Body::from(body));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+
+    Ok(response)
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+
+    response
+}
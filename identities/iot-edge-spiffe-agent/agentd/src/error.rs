@@ -8,4 +8,24 @@ pub enum Error {
     ParsingConfig(std::io::Error),
     #[error("Error Creating server client {0}")]
     CreatingServerclient(Box<dyn std::error::Error + Send>),
+    #[error("Invalid SPIFFE_ENDPOINT_SOCKET {0}, expected a unix socket URI")]
+    InvalidEndpointSocket(String),
+    #[error("Error binding the workload API socket {0}")]
+    BindingListener(std::io::Error),
+    #[error("Error setting the permissions of the workload API socket {0}")]
+    SettingSocketPermissions(std::io::Error),
+    #[error("The TCP listener serves workloads without attesting them, set `insecure = true` to enable it")]
+    InsecureTcpListener,
+    #[error("Error binding the workload API TCP listener {0}")]
+    BindingTcpListener(Box<dyn std::error::Error + Send + Sync>),
+    #[error("Error binding the health service {0}")]
+    BindingHealthListener(Box<dyn std::error::Error + Send + Sync>),
+    #[error("Error binding the debug endpoint {0}")]
+    BindingDebugListener(std::io::Error),
+    #[error("Error binding the metrics endpoint {0}")]
+    BindingMetricsListener(hyper::Error),
+    #[error("Error serving the workload API {0}")]
+    ServingWorkloadAPI(tonic::transport::Error),
+    #[error("Workload API listener task failed {0}")]
+    ListenerTask(tokio::task::JoinError),
 }
@@ -0,0 +1,54 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Readiness of the agent on the standard gRPC health service, for the gRPC probes of kubernetes. The
+// health service has its own TCP listener, probes cannot reach the workload API socket. The agent is
+// NOT_SERVING until it got its initial trust bundle and was attested, then SERVING.
+
+use std::net::SocketAddr;
+
+use log::{error, info};
+use tonic::transport::{server::TcpIncoming, Server};
+use tonic_health::{server::HealthReporter, ServingStatus};
+use workload_api::generated::spiffe_workload_api_server::SpiffeWorkloadApiServer;
+use workload_api_server::WorkloadAPIServer;
+
+use crate::error::Error;
+
+// Name of the overall health of the agent in the health protocol.
+const AGENT_SERVICE: &str = "";
+
+pub async fn start(address: SocketAddr) -> Result<HealthReporter, Error> {
+    let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
+    health_reporter
+        .set_service_status(AGENT_SERVICE, ServingStatus::NotServing)
+        .await;
+    health_reporter
+        .set_not_serving::<SpiffeWorkloadApiServer<WorkloadAPIServer>>()
+        .await;
+
+    let incoming = TcpIncoming::new(address, true, None).map_err(Error::BindingHealthListener)?;
+
+    info!("Starting health service on {}", address);
+    tokio::spawn(async move {
+        let result = Server::builder()
+            .add_service(health_service)
+            .serve_with_incoming(incoming)
+            .await;
+        if let Err(err) = result {
+            error!("Error serving the health service {}", err);
+        }
+    });
+
+    Ok(health_reporter)
+}
+
+pub async fn set_ready(health_reporter: &mut HealthReporter) {
+    health_reporter
+        .set_service_status(AGENT_SERVICE, ServingStatus::Serving)
+        .await;
+    health_reporter
+        .set_serving::<SpiffeWorkloadApiServer<WorkloadAPIServer>>()
+        .await;
+
+    info!("Agent is ready");
+}
@@ -0,0 +1,240 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::{
+    fs::Permissions,
+    os::unix::{fs::PermissionsExt, io::FromRawFd},
+    time::Duration,
+};
+
+use agent_config::{TcpListenerConfig, WorkloadSocketConfig, ABSTRACT_SOCKET_PREFIX};
+use futures_util::TryFutureExt;
+use log::{error, info, warn};
+use nix::{
+    sys::socket::{self, AddressFamily, SockAddr, SockFlag, SockType, UnixAddr},
+    unistd::{self, Gid, Uid},
+};
+use request_limits::Limits;
+use tokio::{fs, net::UnixListener, sync::watch, task::JoinHandle, time};
+use tonic::transport::{server::TcpIncoming, Server};
+use workload_api::generated::spiffe_workload_api_server::SpiffeWorkloadApiServer;
+use workload_api_server::{
+    limits::RequestLimitsLayer,
+    tcp::TcpSelectors,
+    unix_stream::{self, PeerAllowlist},
+    WorkloadAPIServer,
+};
+
+use crate::error::Error;
+
+// Time given to in-flight requests to complete once a listener is replaced.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+// Same backlog as the listeners of the standard library.
+const LISTEN_BACKLOG: usize = 128;
+
+pub struct WorkloadListener {
+    socket_path: String,
+    socket_config: WorkloadSocketConfig,
+    shutdown_signal_tx: watch::Sender<bool>,
+    handle: JoinHandle<Result<(), tonic::transport::Error>>,
+}
+
+impl WorkloadListener {
+    pub async fn start(
+        socket_path: &str,
+        socket_config: WorkloadSocketConfig,
+        peer_allowlist: PeerAllowlist,
+        workload_api_server: WorkloadAPIServer,
+        request_limits: Limits,
+    ) -> Result<Self, Error> {
+        let uds = if let Some(name) = socket_path.strip_prefix(ABSTRACT_SOCKET_PREFIX) {
+            bind_abstract(name).map_err(Error::BindingListener)?
+        } else {
+            let _result = fs::remove_file(socket_path).await;
+            UnixListener::bind(socket_path).map_err(Error::BindingListener)?
+        };
+        set_socket_permissions(socket_path, socket_config)
+            .map_err(Error::SettingSocketPermissions)?;
+
+        let uds_stream = async_stream::stream! {
+            loop {
+                let item = uds.accept().map_ok(|(st, _)| unix_stream::UnixStream(st)).await;
+
+                // Dropping the stream closes the connection.
+                if let Ok(stream) = &item {
+                    let peer_cred = stream.0.peer_cred().ok().map(|cred| (cred.uid(), cred.gid()));
+                    if !peer_allowlist.allows(peer_cred) {
+                        warn!("Rejected connection to the workload API from {:?}", peer_cred);
+                        continue;
+                    }
+                }
+
+                yield item;
+            }
+        };
+
+        let (shutdown_signal_tx, shutdown_signal_rx) = watch::channel(false);
+        let workload_api_server =
+            workload_api_server.with_shutdown_signal(shutdown_signal_rx.clone());
+
+        info!("Starting workload API server on {}", socket_path);
+
+        // Once the shutdown signal is set the server stops accepting connections, sends GOAWAY on the open
+        // ones and waits for in-flight requests. Open streams are closed by the workload API server itself.
+        let handle = tokio::spawn(
+            Server::builder()
+                .layer(RequestLimitsLayer::new(request_limits))
+                .add_service(SpiffeWorkloadApiServer::new(workload_api_server))
+                .serve_with_incoming_shutdown(uds_stream, wait_for_shutdown(shutdown_signal_rx)),
+        );
+
+        Ok(WorkloadListener {
+            socket_path: socket_path.to_string(),
+            socket_config,
+            shutdown_signal_tx,
+            handle,
+        })
+    }
+
+    pub fn socket_path(&self) -> &str {
+        &self.socket_path
+    }
+
+    pub fn socket_config(&self) -> WorkloadSocketConfig {
+        self.socket_config
+    }
+
+    // The workloads stay connected, only the next connections see the new permissions.
+    pub fn set_socket_config(&mut self, socket_config: WorkloadSocketConfig) -> Result<(), Error> {
+        set_socket_permissions(&self.socket_path, socket_config)
+            .map_err(Error::SettingSocketPermissions)?;
+        self.socket_config = socket_config;
+
+        Ok(())
+    }
+
+    // Resolves when the server stops on its own, which only happens on error.
+    pub async fn stopped(&mut self) -> Result<(), Error> {
+        (&mut self.handle)
+            .await
+            .map_err(Error::ListenerTask)?
+            .map_err(Error::ServingWorkloadAPI)
+    }
+
+    pub async fn shutdown(self) {
+        info!("Draining workload API listener on {}", self.socket_path);
+
+        let _result = self.shutdown_signal_tx.send(true);
+
+        let mut handle = self.handle;
+        match time::timeout(DRAIN_TIMEOUT, &mut handle).await {
+            Ok(Ok(Ok(()))) => {}
+            Ok(Ok(Err(err))) => error!("{}", Error::ServingWorkloadAPI(err)),
+            Ok(Err(err)) => error!("{}", Error::ListenerTask(err)),
+            Err(_) => {
+                warn!(
+                    "Workload API listener on {} did not drain in time, closing remaining connections",
+                    self.socket_path
+                );
+                handle.abort();
+            }
+        }
+
+        // Abstract sockets go away with the listener.
+        if !self.socket_path.starts_with(ABSTRACT_SOCKET_PREFIX) {
+            let _result = fs::remove_file(&self.socket_path).await;
+        }
+    }
+}
+
+// Tokio only binds sockets with a path.
+fn bind_abstract(name: &str) -> Result<UnixListener, std::io::Error> {
+    let fd = socket::socket(
+        AddressFamily::Unix,
+        SockType::Stream,
+        SockFlag::SOCK_CLOEXEC | SockFlag::SOCK_NONBLOCK,
+        None,
+    )?;
+    // The listener owns the socket right away so it is closed on errors. The descriptor was just
+    // created and is not owned by anything else.
+    let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) };
+
+    let address = UnixAddr::new_abstract(name.as_bytes())?;
+    socket::bind(fd, &SockAddr::Unix(address))?;
+    socket::listen(fd, LISTEN_BACKLOG)?;
+
+    UnixListener::from_std(listener)
+}
+
+fn set_socket_permissions(
+    socket_path: &str,
+    socket_config: WorkloadSocketConfig,
+) -> Result<(), std::io::Error> {
+    // Abstract sockets have no file, any process of the network namespace of the agent can connect.
+    if socket_path.starts_with(ABSTRACT_SOCKET_PREFIX) {
+        if socket_config != WorkloadSocketConfig::default() {
+            warn!(
+                "Ignoring the permissions of abstract socket {}, abstract sockets have none",
+                socket_path
+            );
+        }
+
+        return Ok(());
+    }
+
+    if socket_config.uid.is_some() || socket_config.gid.is_some() {
+        unistd::chown(
+            socket_path,
+            socket_config.uid.map(Uid::from_raw),
+            socket_config.gid.map(Gid::from_raw),
+        )?;
+    }
+    if let Some(mode) = socket_config.mode {
+        std::fs::set_permissions(socket_path, Permissions::from_mode(mode))?;
+    }
+
+    Ok(())
+}
+
+// The TCP listener is never replaced, it runs until the agent stops.
+pub fn start_tcp_listener(
+    config: &TcpListenerConfig,
+    workload_api_server: WorkloadAPIServer,
+    request_limits: Limits,
+) -> Result<(), Error> {
+    if !config.insecure {
+        return Err(Error::InsecureTcpListener);
+    }
+
+    let incoming =
+        TcpIncoming::new(config.address, true, None).map_err(Error::BindingTcpListener)?;
+    let workload_api_server = workload_api_server.with_tcp_selectors(TcpSelectors::new(
+        config.peer_selectors.clone(),
+        config.selectors.clone(),
+    ));
+
+    warn!(
+        "Starting workload API server on TCP {}, its workloads are not attested",
+        config.address
+    );
+
+    let server = Server::builder()
+        .layer(RequestLimitsLayer::new(request_limits))
+        .add_service(SpiffeWorkloadApiServer::new(workload_api_server))
+        .serve_with_incoming(incoming);
+
+    tokio::spawn(async move {
+        if let Err(err) = server.await {
+            error!("{}", Error::ServingWorkloadAPI(err));
+        }
+    });
+
+    Ok(())
+}
+
+async fn wait_for_shutdown(mut shutdown_signal_rx: watch::Receiver<bool>) {
+    while !*shutdown_signal_rx.borrow() {
+        if shutdown_signal_rx.changed().await.is_err() {
+            return;
+        }
+    }
+}
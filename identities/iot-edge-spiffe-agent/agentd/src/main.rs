@@ -10,35 +10,67 @@
     clippy::too_many_lines
 )]
 
+mod config_watcher;
+mod debug_api;
 mod error;
-use agent_config::Config;
+mod health;
+mod listener;
+
+use agent_config::{Config, ServerProtocol, SPIFFE_ENDPOINT_SOCKET_ENV_VAR};
+use build_info::build_info;
+#[cfg(feature = "chaos")]
+use chaos_hooks::Faults;
+use config_watcher::ConfigWatcher;
 use error::Error;
-use futures_util::{future, pin_mut, TryFutureExt};
-use jwt_svid_validator::validate;
+use futures_util::{future, pin_mut};
+use jwt_svid_validator::{audience::AudienceOptions, validate};
 #[cfg(not(any(test, feature = "tests")))]
 use kube::Client;
+use listener::{start_tcp_listener, WorkloadListener};
+#[cfg(feature = "chaos")]
+use log::warn;
 use log::{error, info};
+use logging::LogFormat;
+use metrics::Registry;
 #[cfg(any(test, feature = "tests"))]
 use mock_kube::Client;
-use node_attestation_agent::NodeAttestatorFactory;
+use node_attestation_agent::{reattestation, NodeAttestatorFactory};
 use spiffe_server_client::ServerClientFactory;
-use std::{env, error::Error as StdError, sync::Arc, time::Duration};
-use tokio::{fs, net::UnixListener, sync::Notify, task::JoinHandle, time};
-use tonic::transport::Server;
-use trust_bundle_manager::TrustBundleManager;
-use workload_api::generated::spiffe_workload_api_server::SpiffeWorkloadApiServer;
-use workload_api_server::{unix_stream, WorkloadAPIServer};
+use std::{env, error::Error as StdError, io, path::PathBuf, sync::Arc, time::Duration};
+use tokio::{
+    sync::{oneshot, Notify},
+    task::JoinHandle,
+    time,
+};
+use trust_bundle_manager::{refresh, TrustBundleManager};
+use workload_api_server::{
+    debug::DebugState,
+    metrics::Metrics,
+    rate_limit::{RateLimiter, RateLimits},
+    streams::{StreamLimits, StreamMetrics},
+    unix_stream::PeerAllowlist,
+    warm_up::PrefetchCache,
+    WorkloadAPIServer,
+};
 use workload_attestation::WorkloadAttestatorFactory;
 
 const CONFIG_DEFAULT_PATH: &str = "/mnt/config/Config.toml";
 const NODE_NAME_ENV_VAR: &str = "NODE_NAME";
+const STREAM_METRICS_LOG_PERIOD_SEC: u64 = 300;
+const TRUST_BUNDLE_WATCH_RETRY_SEC: u64 = 5;
 
 #[tokio::main]
 async fn main() {
-    logger::try_init()
+    // Loaded first for the log format, a config that cannot be loaded is reported in the text format.
+    let config = Config::load_config(CONFIG_DEFAULT_PATH);
+    let (log_format, trust_domain) = match &config {
+        Ok(config) => (config.log_format, Some(config.trust_domain.as_str())),
+        Err(_) => (LogFormat::Text, None),
+    };
+    logging::try_init(log_format, trust_domain)
         .expect("cannot fail to initialize global logger from the process entrypoint");
 
-    if let Err(err) = main_inner().await {
+    if let Err(err) = main_inner(config).await {
         error!("{}", err);
 
         let mut source = std::error::Error::source(&*err);
@@ -51,10 +83,18 @@ async fn main() {
     }
 }
 
-async fn main_inner() -> Result<(), Box<dyn StdError>> {
+async fn main_inner(config: Result<Config, io::Error>) -> Result<(), Box<dyn StdError>> {
+    let build = build_info!();
     info!("Starting IoTEdge SPIFFE Agent");
+    info!("build {}", build);
+
+    let config = config.map_err(Error::ParsingConfig)?;
 
-    let config = Config::load_config(CONFIG_DEFAULT_PATH).map_err(Error::ParsingConfig)?;
+    // Started first so the probes see the agent is not ready yet while it starts.
+    let health_reporter = match &config.health {
+        Some(health) => Some(health::start(health.address).await?),
+        None => None,
+    };
 
     let node_name = env::var(NODE_NAME_ENV_VAR)?;
 
@@ -63,94 +103,343 @@ async fn main_inner() -> Result<(), Box<dyn StdError>> {
     let server_api_client =
         ServerClientFactory::get(&config.server_config).map_err(Error::CreatingServerclient)?;
 
-    let node_attestation = NodeAttestatorFactory::get(&config.node_attestation_config);
+    // Resilience test builds wrap the server client with the faults of the config file.
+    #[cfg(feature = "chaos")]
+    let server_client_faults = Arc::new(Faults::new("server client"));
+    #[cfg(feature = "chaos")]
+    let server_api_client: Arc<dyn spiffe_server_client::Client> = {
+        warn!("Fault injection is enabled, do not use this build in production");
+        server_client_faults.set(config.chaos.clone());
+
+        Arc::new(spiffe_server_client::fault_injection::Client::new(
+            server_api_client,
+            server_client_faults.clone(),
+        ))
+    };
+
+    let node_attestation = NodeAttestatorFactory::get(
+        &config.node_attestation_config,
+        server_api_client.clone(),
+        kube_client.clone(),
+    );
+
+    // Over gRPC, the attestation session is renewed before it expires instead of by the next request.
+    // Other protocols stop after the first attestation.
+    let (attested_tx, attested_rx) = oneshot::channel();
+    tokio::spawn(reattestation::run(
+        node_attestation.clone(),
+        server_api_client.clone(),
+        attested_tx,
+    ));
 
     let workload_attestation =
         WorkloadAttestatorFactory::get(&config.workload_attestation_config, node_name, kube_client);
 
-    let trust_bundle = TrustBundleManager::get_init_trust_bundle(
+    let init_trust_bundle = TrustBundleManager::get_init_trust_bundle(
         server_api_client.clone(),
         &config.trust_bundle_config,
     )
     .await?;
-    let jwt_trust_bundle_refresh_hint = trust_bundle.jwt_key_set.spiffe_refresh_hint;
-    let trust_bundle_manager = Arc::new(TrustBundleManager::new(
-        server_api_client.clone(),
-        trust_bundle,
-    ));
+    let trust_bundle_manager = Arc::new(
+        TrustBundleManager::new(server_api_client.clone(), init_trust_bundle.trust_bundle)
+            .with_federated_bundles(init_trust_bundle.federated_bundles)
+            .with_cache_path(
+                config
+                    .trust_bundle_config
+                    .cache_path
+                    .as_ref()
+                    .map(PathBuf::from),
+            ),
+    );
     let (trust_bundle_manager_handle, trust_bundle_manager_shutdown_signal_tx) =
-        start_refresh_trust_bundle_task(
+        start_refresh_trust_bundle_task(trust_bundle_manager.clone()).await;
+    // The gRPC server sends the new trust bundles as soon as it has them, the periodic refresh stays
+    // as a fallback while the watch is reopened.
+    if config.server_config.protocol == ServerProtocol::Grpc {
+        tokio::spawn(watch_trust_bundle(trust_bundle_manager.clone()));
+    }
+
+    // SPIFFE ID audiences outside of the agent trust domain are never valid for its workloads.
+    let jwt_svid_validator = Arc::new(validate::JWTSVIDValidator::new(AudienceOptions {
+        trust_domain: Some(config.trust_domain.clone()),
+        ..Default::default()
+    }));
+
+    // A new server is built for each listener so each one is drained independently.
+    let pod_identity_pinning = config.pod_identity_pinning;
+    let jwt_svid_cache = config.jwt_svid_cache;
+    // The JWT-SVIDs prefetched at startup are shared by all the listeners.
+    let prefetch_cache = Arc::new(PrefetchCache::default());
+    let stream_limits = StreamLimits {
+        max_lifetime: config
+            .stream_limits
+            .max_lifetime_secs
+            .map(Duration::from_secs),
+        idle_timeout: config
+            .stream_limits
+            .idle_timeout_secs
+            .map(Duration::from_secs),
+    };
+    let stream_metrics = Arc::new(StreamMetrics::default());
+    tokio::spawn(log_stream_metrics(stream_metrics.clone()));
+    // The buckets of the workloads are shared by all the listeners.
+    let rate_limiter = config.rate_limit.map(|rate_limit| {
+        Arc::new(RateLimiter::new(RateLimits {
+            requests_per_sec: rate_limit.requests_per_sec,
+            burst: rate_limit.burst.unwrap_or(rate_limit.requests_per_sec),
+        }))
+    });
+    // The attestations of every listener are listed together.
+    let debug_state = match &config.debug {
+        Some(debug) => {
+            let debug_state = Arc::new(DebugState::default());
+            debug_api::start(
+                &debug.socket_path,
+                debug_state.clone(),
+                trust_bundle_manager.clone(),
+            )
+            .await?;
+
+            Some(debug_state)
+        }
+        None => None,
+    };
+    // The requests of every listener are counted together.
+    let workload_api_metrics = match config.metrics {
+        Some(metrics_config) => {
+            let mut registry = Registry::default();
+            let workload_api_metrics = Arc::new(Metrics::new(&mut registry));
+            register_agent_metrics(&mut registry, &stream_metrics, &trust_bundle_manager);
+            metrics::server::start(metrics_config.address, Arc::new(registry))
+                .map_err(Error::BindingMetricsListener)?;
+
+            workload_api_metrics
+        }
+        None => Arc::new(Metrics::default()),
+    };
+    let new_workload_api_server = move || {
+        let workload_api_server = WorkloadAPIServer::new(
+            server_api_client.clone(),
+            workload_attestation.clone(),
+            node_attestation.clone(),
             trust_bundle_manager.clone(),
-            jwt_trust_bundle_refresh_hint,
+            jwt_svid_validator.clone(),
         )
-        .await;
+        .with_pod_identity_pinning(pod_identity_pinning)
+        .with_jwt_svid_caching(jwt_svid_cache)
+        .with_agent_build(build.clone())
+        .with_prefetch_cache(prefetch_cache.clone())
+        .with_stream_limits(stream_limits)
+        .with_stream_metrics(stream_metrics.clone())
+        .with_metrics(workload_api_metrics.clone());
 
-    let jwt_svid_validator = Arc::new(validate::JWTSVIDValidator::default());
+        let workload_api_server = match &rate_limiter {
+            Some(rate_limiter) => workload_api_server.with_rate_limiter(rate_limiter.clone()),
+            None => workload_api_server,
+        };
 
-    let uds_stream = {
-        let _result = fs::remove_file(config.socket_path.clone()).await;
-        let uds = UnixListener::bind(config.socket_path)?;
+        match &debug_state {
+            Some(debug_state) => workload_api_server.with_debug_state(debug_state.clone()),
+            None => workload_api_server,
+        }
+    };
 
-        async_stream::stream! {
-            loop {
-                let item = uds.accept().map_ok(|(st, _)| unix_stream::UnixStream(st)).await;
+    // The standard variable of the workload API wins over the config, so the workloads and the agent
+    // agree on the socket.
+    let endpoint_socket = match env::var(SPIFFE_ENDPOINT_SOCKET_ENV_VAR) {
+        Ok(endpoint) => Some(
+            agent_config::parse_endpoint_socket(&endpoint)
+                .ok_or(Error::InvalidEndpointSocket(endpoint))?,
+        ),
+        Err(_) => None,
+    };
+    let socket_path = |config: &Config| {
+        endpoint_socket
+            .clone()
+            .unwrap_or_else(|| config.socket_path.clone())
+    };
 
-                yield item;
+    let mut listener = WorkloadListener::start(
+        &socket_path(&config),
+        config.workload_socket,
+        peer_allowlist(&config),
+        new_workload_api_server(),
+        config.request_limits,
+    )
+    .await?;
+
+    // The initial trust bundle was fetched before starting the listener, the agent is ready once it is
+    // attested too.
+    if let Some(mut health_reporter) = health_reporter {
+        tokio::spawn(async move {
+            if attested_rx.await.is_ok() {
+                health::set_ready(&mut health_reporter).await;
             }
-        }
-    };
+        });
+    }
+
+    if let Some(tcp_listener) = &config.tcp_listener {
+        start_tcp_listener(
+            tcp_listener,
+            new_workload_api_server(),
+            config.request_limits,
+        )?;
+    }
 
-    info!("Starting workload API server");
+    // Warm up in the background, workloads can already be served while their JWT-SVIDs are fetched.
+    if let Some(warm_up) = config.warm_up.clone() {
+        let workload_api_server = new_workload_api_server();
+        tokio::spawn(async move {
+            let prefetched = workload_api_server
+                .warm_up(&warm_up.audiences, warm_up.concurrency)
+                .await;
+            info!("Prefetched JWT-SVIDs of {} workloads", prefetched);
+        });
+    }
 
-    Server::builder()
-        .add_service(SpiffeWorkloadApiServer::new(WorkloadAPIServer::new(
-            server_api_client,
-            workload_attestation,
-            node_attestation,
-            trust_bundle_manager,
-            jwt_svid_validator,
-        )))
-        .serve_with_incoming(uds_stream)
-        .await?;
+    let mut config_watcher = ConfigWatcher::new(CONFIG_DEFAULT_PATH);
+
+    let result = loop {
+        let new_config = {
+            let wait_config = config_watcher.changed();
+            let wait_listener = listener.stopped();
+
+            pin_mut!(wait_config);
+            pin_mut!(wait_listener);
+
+            match future::select(wait_config, wait_listener).await {
+                future::Either::Left((new_config, _)) => new_config,
+                future::Either::Right((result, _)) => break result,
+            }
+        };
+
+        #[cfg(feature = "chaos")]
+        server_client_faults.set(new_config.chaos.clone());
+
+        // Only the listener settings (and the injected faults) are applied on the fly, other settings
+        // need a restart of the agent.
+        let new_socket_path = socket_path(&new_config);
+        if new_socket_path == listener.socket_path() {
+            if new_config.workload_socket != listener.socket_config() {
+                if let Err(err) = listener.set_socket_config(new_config.workload_socket) {
+                    error!("Keeping the permissions of {}: {}", new_socket_path, err);
+                }
+            }
+            continue;
+        }
+
+        // Open the new socket before draining the old one so workloads can always connect.
+        let new_listener = WorkloadListener::start(
+            &new_socket_path,
+            new_config.workload_socket,
+            peer_allowlist(&new_config),
+            new_workload_api_server(),
+            new_config.request_limits,
+        )
+        .await;
+        match new_listener {
+            Ok(new_listener) => {
+                let old_listener = std::mem::replace(&mut listener, new_listener);
+                tokio::spawn(old_listener.shutdown());
+            }
+            Err(err) => error!(
+                "Keeping workload API listener on {}: {}",
+                listener.socket_path(),
+                err
+            ),
+        }
+    };
 
     trust_bundle_manager_shutdown_signal_tx.notify_one();
     let _wait = trust_bundle_manager_handle.await;
 
+    result?;
+
     Ok(())
 }
 
+fn peer_allowlist(config: &Config) -> PeerAllowlist {
+    PeerAllowlist::new(
+        config.workload_socket_allowlist.uids.clone(),
+        config.workload_socket_allowlist.gids.clone(),
+    )
+}
+
+// Read from the stream metrics and the trust bundle manager, which already keep them.
+fn register_agent_metrics(
+    registry: &mut Registry,
+    stream_metrics: &Arc<StreamMetrics>,
+    trust_bundle_manager: &Arc<TrustBundleManager>,
+) {
+    let active_streams = stream_metrics.clone();
+    registry.gauge_fn(
+        "e4k_agent_workload_api_active_streams",
+        "Streams open on the workload API.",
+        move || active_streams.snapshot().active,
+    );
+
+    let opened_streams = stream_metrics.clone();
+    registry.counter_fn(
+        "e4k_agent_workload_api_opened_streams_total",
+        "Streams opened on the workload API.",
+        move || opened_streams.snapshot().opened,
+    );
+
+    let trust_bundle_manager = trust_bundle_manager.clone();
+    registry.gauge_fn(
+        "e4k_agent_trust_bundle_last_refresh_timestamp_seconds",
+        "Time of the last trust bundle refresh, in seconds since epoch.",
+        move || trust_bundle_manager.last_refresh_at(),
+    );
+}
+
+async fn log_stream_metrics(stream_metrics: Arc<StreamMetrics>) {
+    let mut interval = time::interval(Duration::from_secs(STREAM_METRICS_LOG_PERIOD_SEC));
+    let mut last_snapshot = stream_metrics.snapshot();
+
+    loop {
+        interval.tick().await;
+
+        // Nothing happened since the last report.
+        let snapshot = stream_metrics.snapshot();
+        if snapshot == last_snapshot {
+            continue;
+        }
+
+        info!(
+            "Workload API streams: {} active, {} opened, closed by age {:?}, {} closed at max lifetime, {} closed when idle",
+            snapshot.active,
+            snapshot.opened,
+            snapshot.age_counts,
+            snapshot.max_lifetime_terminations,
+            snapshot.idle_terminations
+        );
+        last_snapshot = snapshot;
+    }
+}
+
+async fn watch_trust_bundle(trust_bundle_manager: Arc<TrustBundleManager>) {
+    info!("Starting Trust Bundle manager watch task");
+
+    loop {
+        match trust_bundle_manager.watch_trust_bundle().await {
+            Ok(()) => info!("Trust bundle watch closed by the server"),
+            Err(err) => error!("{}", err),
+        }
+
+        time::sleep(Duration::from_secs(TRUST_BUNDLE_WATCH_RETRY_SEC)).await;
+    }
+}
+
 async fn start_refresh_trust_bundle_task(
     trust_bundle_manager: Arc<TrustBundleManager>,
-    refresh_period_sec: u64,
 ) -> (JoinHandle<()>, Arc<Notify>) {
     let trust_bundle_manager_shutdown_signal_rx = Arc::new(Notify::new());
     let trust_bundle_manager_shutdown_signal_tx = trust_bundle_manager_shutdown_signal_rx.clone();
-    let trust_bundle_manager_handle = tokio::spawn(async move {
-        info!("Starting Trust Bundle manager refresh task");
-        let mut interval = time::interval(Duration::from_secs(refresh_period_sec));
-
-        loop {
-            let wait_shutdown = trust_bundle_manager_shutdown_signal_rx.notified();
-            let wait_tick = interval.tick();
-
-            pin_mut!(wait_shutdown);
-            pin_mut!(wait_tick);
-
-            match future::select(wait_shutdown, wait_tick).await {
-                future::Either::Left(_) => {
-                    info!("Closing key manager task");
-                    break;
-                }
-                future::Either::Right(_) => {
-                    if let Err(err) = trust_bundle_manager.refresh_trust_bundle().await {
-                        error!("{}", err);
-                    } else {
-                        info!("Fetch new trust bundle");
-                    }
-                }
-            };
-        }
-    });
+    let trust_bundle_manager_handle = tokio::spawn(refresh::run(
+        trust_bundle_manager,
+        trust_bundle_manager_shutdown_signal_rx,
+    ));
 
     (
         trust_bundle_manager_handle,
@@ -10,7 +10,22 @@
     clippy::too_many_lines
 )]
 
-use std::{fs, io, path::Path};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    net::{IpAddr, SocketAddr},
+    path::Path,
+};
+
+#[cfg(feature = "chaos")]
+use chaos::FaultConfig;
+use request_limits::Limits;
+
+// Standard variable of the SPIFFE workload API. When it is set, the agent serves the workload API on
+// its socket instead of `socket_path`, so SPIFFE client libraries find the agent without any setting.
+pub const SPIFFE_ENDPOINT_SOCKET_ENV_VAR: &str = "SPIFFE_ENDPOINT_SOCKET";
+// Sockets named with this prefix are bound in the abstract namespace, e.g. "@spiffe-agent".
+pub const ABSTRACT_SOCKET_PREFIX: char = '@';
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct Config {
@@ -34,6 +49,60 @@ pub struct Config {
         default = "default_workload_attestation_config"
     )]
     pub workload_attestation_config: WorkloadAttestationConfig,
+    // Permissions of the workload API socket. Applied when the listener is started, and on the socket
+    // in place when they are reloaded.
+    #[serde(default, alias = "workload-socket")]
+    pub workload_socket: WorkloadSocketConfig,
+    // Users and groups allowed to connect to the workload API socket. Applied when the listener is
+    // started, like the request limits.
+    #[serde(default, alias = "workload-socket-allowlist")]
+    pub workload_socket_allowlist: WorkloadSocketAllowlistConfig,
+    // Serve the workload API on a TCP port as well, for runtimes that cannot mount the socket in their
+    // containers. Disabled when not set, changing it needs a restart of the agent.
+    #[serde(default, alias = "tcp-listener")]
+    pub tcp_listener: Option<TcpListenerConfig>,
+    // Bind the JWT-SVIDs to the UID of the pod they are issued to. Cached JWT-SVIDs are only returned
+    // to a process after checking it still belongs to that pod.
+    #[serde(default)]
+    pub pod_identity_pinning: bool,
+    // Cache the JWT-SVIDs issued to each workload process until half of their lifetime. JWT-SVIDs with a
+    // jti claim are never cached. Enabled when not set.
+    #[serde(default = "default_jwt_svid_cache", alias = "jwt-svid-cache")]
+    pub jwt_svid_cache: bool,
+    // Size and processing time limits of the requests to the workload API. Applied when the listener
+    // is started, like the socket path.
+    #[serde(default, alias = "request-limits")]
+    pub request_limits: Limits,
+    // Standard gRPC health service of the agent, for the readiness probes. Disabled when not set.
+    #[serde(default)]
+    pub health: Option<HealthConfig>,
+    // Rate limit of the `FetchJWTSVID` requests of each workload process. Not limited when not set,
+    // changing it needs a restart of the agent.
+    #[serde(default, alias = "rate-limit")]
+    pub rate_limit: Option<RateLimitConfig>,
+    // Lifetime of the streams opened on the workload API. Changing it needs a restart of the agent.
+    #[serde(default, alias = "stream-limits")]
+    pub stream_limits: StreamLimitsConfig,
+    // Fetch the JWT-SVIDs of the entries marked for prefetch for the workloads already running on the
+    // node when the agent starts. Disabled when not set.
+    #[serde(default, alias = "warm-up")]
+    pub warm_up: Option<WarmUpConfig>,
+    // Local socket listing what the agent did recently, for debugging devices in the field. Disabled
+    // when not set, changing it needs a restart of the agent.
+    #[serde(default)]
+    pub debug: Option<DebugConfig>,
+    // Prometheus `/metrics` endpoint of the agent. Disabled when not set, changing it needs a restart of
+    // the agent.
+    #[serde(default)]
+    pub metrics: Option<metrics::MetricsConfig>,
+    // Format of the logs, "text" or "json" for log collectors. Changing it needs a restart of the agent.
+    #[serde(default, alias = "log-format")]
+    pub log_format: logging::LogFormat,
+    // Faults injected in the calls to the server, only applied by agents built with the `chaos` feature.
+    // They are reloaded with the config file.
+    #[cfg(feature = "chaos")]
+    #[serde(default)]
+    pub chaos: FaultConfig,
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
@@ -41,30 +110,94 @@ pub struct Config {
 pub enum NodeAttestationConfig {
     Sat(NodeAttestationConfigK8s),
     Psat(NodeAttestationConfigK8s),
+    Dps(NodeAttestationConfigDps),
+    X509Pop(NodeAttestationConfigX509Pop),
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct NodeAttestationConfigK8s {
     #[serde(default = "default_token_path")]
     pub token_path: String,
+    // Request a new token from the API server for every attestation instead of reading `token_path`, for
+    // servers rejecting replayed tokens. Read from the file when not set.
+    #[serde(default, alias = "token-request")]
+    pub token_request: Option<TokenRequestConfig>,
+}
+
+// Tokens bound to the pod of the agent, requested for its service account. The pod is read from the
+// `POD_NAME`, `POD_UID`, `POD_NAMESPACE` and `SERVICE_ACCOUNT_NAME` environment variables.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct TokenRequestConfig {
+    pub audience: String,
+    // The API server does not issue tokens valid for less than 10 minutes.
+    #[serde(default = "default_token_request_expiration_secs")]
+    pub expiration_secs: u64,
+}
+
+// Agents on Azure IoT devices, attested with the credentials of the DPS enrollment of the device.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct NodeAttestationConfigDps {
+    pub id_scope: String,
+    pub registration_id: String,
+    pub credentials: DpsCredentials,
+    #[serde(default = "default_dps_token_ttl_secs")]
+    pub token_ttl_secs: u64,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DpsCredentials {
+    // File holding the base64 key of the device, derived from the group key for group enrollments.
+    SymmetricKey {
+        key_path: String,
+    },
+    // PEM files of the device certificate, followed by its intermediates, and of its private key.
+    X509 {
+        cert_chain_path: String,
+        private_key_path: String,
+    },
+}
+
+// Agents with a pre-provisioned certificate, proving they hold its private key by signing a nonce of the
+// server.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct NodeAttestationConfigX509Pop {
+    // PEM file of the agent certificate, followed by its intermediates.
+    pub cert_chain_path: String,
+    pub private_key_path: String,
+}
+
+fn default_dps_token_ttl_secs() -> u64 {
+    300
 }
 
 fn default_node_attestation_config() -> NodeAttestationConfig {
     let config = NodeAttestationConfigK8s {
         token_path: default_token_path(),
+        token_request: None,
     };
 
     NodeAttestationConfig::Psat(config)
 }
 
+fn default_jwt_svid_cache() -> bool {
+    true
+}
+
 fn default_token_path() -> String {
     "/var/run/secrets/tokens/iotedge-spiffe-agent".to_string()
 }
 
+fn default_token_request_expiration_secs() -> u64 {
+    600
+}
+
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 #[serde(tag = "type", content = "content", rename_all = "UPPERCASE")]
 pub enum WorkloadAttestationConfig {
     K8s(WorkloadAttestationConfigK8s),
+    Unix(WorkloadAttestationConfigUnix),
+    Systemd(WorkloadAttestationConfigSystemd),
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
@@ -73,12 +206,28 @@ pub struct WorkloadAttestationConfigK8s {
     pub max_poll_attempt: usize,
     #[serde(default = "default_poll_retry_interval_ms")]
     pub poll_retry_interval_ms: u64,
+    // Attestations of a container are reused for this time, or until its pod changes. Not cached when 0.
+    #[serde(default = "default_attestation_cache_ttl_secs")]
+    pub attestation_cache_ttl_secs: u64,
+    // Annotations of the pod turned into selectors. Annotations are not selectors unless listed here.
+    #[serde(default)]
+    pub allowed_pod_annotation_keys: BTreeSet<String>,
 }
 
+// Workloads attested by their process only: UID, GID, executable path and hash.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct WorkloadAttestationConfigUnix {}
+
+// Workloads attested by the systemd unit of their process, for the daemons of the host.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct WorkloadAttestationConfigSystemd {}
+
 fn default_workload_attestation_config() -> WorkloadAttestationConfig {
     let config = WorkloadAttestationConfigK8s {
         max_poll_attempt: default_max_poll_attempt(),
         poll_retry_interval_ms: default_poll_retry_interval_ms(),
+        attestation_cache_ttl_secs: default_attestation_cache_ttl_secs(),
+        allowed_pod_annotation_keys: BTreeSet::new(),
     };
 
     WorkloadAttestationConfig::K8s(config)
@@ -92,18 +241,106 @@ fn default_poll_retry_interval_ms() -> u64 {
     500
 }
 
+fn default_attestation_cache_ttl_secs() -> u64 {
+    30
+}
+
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct StreamLimitsConfig {
+    // Streams are closed after this time so the workloads reconnect and are attested again. Not
+    // limited when not set.
+    #[serde(default)]
+    pub max_lifetime_secs: Option<u64>,
+    // Streams without any update for this time are closed. Not limited when not set.
+    #[serde(default)]
+    pub idle_timeout_secs: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct WorkloadSocketConfig {
+    // Mode of the socket file, e.g. 0o770. Left to the umask of the agent when not set.
+    #[serde(default)]
+    pub mode: Option<u32>,
+    // Owner and group of the socket file. Left to the user of the agent when not set.
+    #[serde(default)]
+    pub uid: Option<u32>,
+    #[serde(default)]
+    pub gid: Option<u32>,
+}
+
+// Workloads connecting over TCP cannot be attested, any process that can reach the port from an address
+// gets the identities of the selectors of that address.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct TcpListenerConfig {
+    pub address: SocketAddr,
+    // Must be set to acknowledge that the workloads are not attested, the agent does not start otherwise.
+    #[serde(default)]
+    pub insecure: bool,
+    // Selectors of the workloads connecting from these peer addresses.
+    #[serde(default)]
+    pub peer_selectors: BTreeMap<IpAddr, BTreeSet<String>>,
+    // Selectors of the workloads connecting from any other address. They get no identity when empty.
+    #[serde(default)]
+    pub selectors: BTreeSet<String>,
+}
+
+// A process is allowed when its UID or its GID is listed, every process is when both are empty.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct WorkloadSocketAllowlistConfig {
+    #[serde(default)]
+    pub uids: BTreeSet<u32>,
+    #[serde(default)]
+    pub gids: BTreeSet<u32>,
+}
+
+#[derive(Clone, Copy, Debug, serde::Deserialize, serde::Serialize)]
+pub struct HealthConfig {
+    pub address: SocketAddr,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct DebugConfig {
+    pub socket_path: String,
+}
+
+#[derive(Clone, Copy, Debug, serde::Deserialize, serde::Serialize)]
+pub struct RateLimitConfig {
+    pub requests_per_sec: u32,
+    // Requests a workload can send at once after being idle. Defaults to `requests_per_sec`.
+    #[serde(default)]
+    pub burst: Option<u32>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct WarmUpConfig {
+    // Audiences the JWT-SVIDs are fetched for. A prefetched JWT-SVID is only returned to a workload
+    // requesting exactly these audiences.
+    pub audiences: Vec<String>,
+    // Number of workloads fetched at the same time, to bound the load on the server at startup.
+    #[serde(default = "default_warm_up_concurrency")]
+    pub concurrency: usize,
+}
+
+fn default_warm_up_concurrency() -> usize {
+    4
+}
+
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct TrustBundleManagerConfig {
     #[serde(default = "default_max_retry")]
     pub max_retry: usize,
     #[serde(default = "default_wait_retry_sec")]
     pub wait_retry_sec: u64,
+    // File keeping the last trust bundle received, loaded when the server cannot be reached at startup.
+    #[serde(default)]
+    pub cache_path: Option<String>,
 }
 
 fn default_trust_bundle_manager_config() -> TrustBundleManagerConfig {
     TrustBundleManagerConfig {
         max_retry: default_max_retry(),
         wait_retry_sec: default_wait_retry_sec(),
+        cache_path: None,
     }
 }
 
@@ -118,7 +355,44 @@ fn default_wait_retry_sec() -> u64 {
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct ServerConfig {
     pub address: String,
+    // Port of the server-agent API of `protocol`, the server serves gRPC on its own port.
     pub port: u16,
+    #[serde(default)]
+    pub protocol: ServerProtocol,
+    // Connect to the server over TLS, the server must then be configured with TLS too.
+    #[serde(default)]
+    pub tls: Option<ServerTlsConfig>,
+}
+
+// The certificate of the server is checked with the PEM bundle of CAs in `ca_path`, for the name in `address`.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ServerTlsConfig {
+    pub ca_path: String,
+    // Sent to the servers requiring a client certificate.
+    #[serde(default)]
+    pub client_certificate: Option<ClientCertificateConfig>,
+}
+
+// PEM certificate chain of the agent, then its private key.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ClientCertificateConfig {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerProtocol {
+    Http,
+    // Attests the agent once per session instead of on every request, and receives the trust bundle
+    // updates as soon as the server has them.
+    Grpc,
+}
+
+impl Default for ServerProtocol {
+    fn default() -> Self {
+        ServerProtocol::Http
+    }
 }
 
 impl Config {
@@ -131,6 +405,23 @@ impl Config {
     }
 }
 
+// Socket path of a SPIFFE_ENDPOINT_SOCKET URI, "unix:///path" or "unix:/path". Abstract sockets are
+// named after the prefix, e.g. "unix:@spiffe-agent". Only unix sockets are served by the agent.
+#[must_use]
+pub fn parse_endpoint_socket(endpoint: &str) -> Option<String> {
+    let path = endpoint.strip_prefix("unix:")?;
+    // The authority of "unix:///path" is empty, any other authority is not a local socket.
+    let path = path.strip_prefix("//").unwrap_or(path);
+
+    let is_valid =
+        path.starts_with('/') || (path.starts_with(ABSTRACT_SOCKET_PREFIX) && path.len() > 1);
+    if !is_valid || path.contains(&['?', '#'][..]) {
+        return None;
+    }
+
+    Some(path.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use std::{fs::File, io::Read};
@@ -157,4 +448,27 @@ mod tests {
             let _config: Config = toml::from_slice(&buf).unwrap();
         }
     }
+
+    #[test]
+    fn parse_endpoint_socket_uris() {
+        assert_eq!(
+            parse_endpoint_socket("unix:///run/iotedge/sockets/workloadapi.sock").unwrap(),
+            "/run/iotedge/sockets/workloadapi.sock"
+        );
+        assert_eq!(
+            parse_endpoint_socket("unix:/run/workloadapi.sock").unwrap(),
+            "/run/workloadapi.sock"
+        );
+        assert_eq!(
+            parse_endpoint_socket("unix:@spiffe-agent").unwrap(),
+            "@spiffe-agent"
+        );
+
+        assert!(parse_endpoint_socket("/run/workloadapi.sock").is_none());
+        assert!(parse_endpoint_socket("tcp://127.0.0.1:8080").is_none());
+        assert!(parse_endpoint_socket("unix://host/run/workloadapi.sock").is_none());
+        assert!(parse_endpoint_socket("unix:relative.sock").is_none());
+        assert!(parse_endpoint_socket("unix:@").is_none());
+        assert!(parse_endpoint_socket("unix:///run/workloadapi.sock?query").is_none());
+    }
 }
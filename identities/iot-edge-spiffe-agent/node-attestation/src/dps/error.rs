@@ -0,0 +1,17 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Unable to read the DPS credentials {0}")]
+    UnableToReadCredentials(std::io::Error),
+    #[error("Invalid symmetric key {0}")]
+    InvalidSymmetricKey(base64::DecodeError),
+    #[error("Invalid certificate or private key {0}")]
+    InvalidCertificate(openssl::error::ErrorStack),
+    #[error("Failed to sign the attestation token {0}")]
+    Signing(openssl::error::ErrorStack),
+    #[error("Failed to serialize the attestation token {0}")]
+    SerializingToken(serde_json::Error),
+}
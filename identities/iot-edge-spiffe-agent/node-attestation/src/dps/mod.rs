@@ -0,0 +1,227 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// The token is signed with the credentials of the DPS enrollment of the device. A new token is signed for
+// every attestation, so the server only accepts it for a short time.
+
+pub mod error;
+
+use std::fs;
+
+use agent_config::{DpsCredentials, NodeAttestationConfigDps};
+use core_objects::get_epoch_time;
+use openssl::{
+    hash::MessageDigest,
+    pkey::{PKey, Private},
+    sign::Signer,
+    x509::X509,
+};
+use server_agent_api::dps_attestation::{signed_data, Token};
+use zeroize::Zeroizing;
+
+use crate::NodeAttestation as NodeAttestationTrait;
+
+use error::Error;
+
+pub struct NodeAttestation {
+    config: NodeAttestationConfigDps,
+}
+
+impl NodeAttestation {
+    #[must_use]
+    pub fn new(config: &NodeAttestationConfigDps) -> Self {
+        NodeAttestation {
+            config: config.clone(),
+        }
+    }
+
+    fn create_token(&self) -> Result<String, Error> {
+        let registration_id = self.config.registration_id.clone();
+        let expiry = get_epoch_time() + self.config.token_ttl_secs;
+        let data = signed_data(&self.config.id_scope, &registration_id, expiry);
+
+        let token = match &self.config.credentials {
+            DpsCredentials::SymmetricKey { key_path } => {
+                // The key is wiped from memory once the token is signed.
+                let key = Zeroizing::new(
+                    fs::read_to_string(key_path).map_err(Error::UnableToReadCredentials)?,
+                );
+                let key =
+                    Zeroizing::new(base64::decode(key.trim()).map_err(Error::InvalidSymmetricKey)?);
+                let key = PKey::hmac(&key).map_err(Error::Signing)?;
+
+                Token::SymmetricKey {
+                    registration_id,
+                    expiry,
+                    signature: sign(&key, data.as_bytes())?,
+                }
+            }
+            DpsCredentials::X509 {
+                cert_chain_path,
+                private_key_path,
+            } => {
+                let cert_chain =
+                    fs::read(cert_chain_path).map_err(Error::UnableToReadCredentials)?;
+                let certificate_chain = X509::stack_from_pem(&cert_chain)
+                    .map_err(Error::InvalidCertificate)?
+                    .iter()
+                    .map(|cert| cert.to_der().map(base64::encode))
+                    .collect::<Result<_, _>>()
+                    .map_err(Error::InvalidCertificate)?;
+
+                let private_key = Zeroizing::new(
+                    fs::read(private_key_path).map_err(Error::UnableToReadCredentials)?,
+                );
+                let private_key =
+                    PKey::private_key_from_pem(&private_key).map_err(Error::InvalidCertificate)?;
+
+                Token::X509 {
+                    registration_id,
+                    expiry,
+                    certificate_chain,
+                    signature: sign(&private_key, data.as_bytes())?,
+                }
+            }
+        };
+
+        serde_json::to_string(&token).map_err(Error::SerializingToken)
+    }
+}
+
+// HMAC-SHA256 for the symmetric keys, SHA256 signature for the private keys.
+fn sign(key: &PKey<Private>, data: &[u8]) -> Result<String, Error> {
+    let mut signer = Signer::new(MessageDigest::sha256(), key).map_err(Error::Signing)?;
+    signer.update(data).map_err(Error::Signing)?;
+
+    signer
+        .sign_to_vec()
+        .map(base64::encode)
+        .map_err(Error::Signing)
+}
+
+#[async_trait::async_trait]
+impl NodeAttestationTrait for NodeAttestation {
+    async fn get_attestation_token(&self) -> Result<String, Box<dyn std::error::Error + Send>> {
+        self.create_token().map_err(|err| Box::new(err) as _)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+    use openssl::{
+        asn1::Asn1Time,
+        ec::{EcGroup, EcKey},
+        nid::Nid,
+        sign::Verifier,
+        x509::X509NameBuilder,
+    };
+
+    use super::*;
+
+    fn config(credentials: DpsCredentials) -> NodeAttestationConfigDps {
+        NodeAttestationConfigDps {
+            id_scope: "0ne00000000".to_string(),
+            registration_id: "device-registration".to_string(),
+            credentials,
+            token_ttl_secs: 300,
+        }
+    }
+
+    #[tokio::test]
+    async fn symmetric_key_token_happy_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("device_key");
+        fs::write(&key_path, base64::encode("device key")).unwrap();
+
+        let node_attestation = NodeAttestation::new(&config(DpsCredentials::SymmetricKey {
+            key_path: key_path.to_str().unwrap().to_string(),
+        }));
+        let token = node_attestation.get_attestation_token().await.unwrap();
+
+        let (expiry, signature) = match serde_json::from_str(&token).unwrap() {
+            Token::SymmetricKey {
+                registration_id,
+                expiry,
+                signature,
+            } => {
+                assert_eq!(registration_id, "device-registration");
+                (expiry, signature)
+            }
+            Token::X509 { .. } => panic!("Unexpected token type"),
+        };
+        assert!(expiry > get_epoch_time());
+
+        let key = PKey::hmac(b"device key").unwrap();
+        let data = signed_data("0ne00000000", "device-registration", expiry);
+        assert_eq!(sign(&key, data.as_bytes()).unwrap(), signature);
+    }
+
+    #[tokio::test]
+    async fn x509_token_happy_path() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let private_key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, "device-registration")
+            .unwrap();
+        let name = name.build();
+        let mut cert = X509::builder().unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&private_key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&private_key, MessageDigest::sha256()).unwrap();
+        let cert = cert.build();
+
+        let cert_chain_path = dir.path().join("device_cert.pem");
+        fs::write(&cert_chain_path, cert.to_pem().unwrap()).unwrap();
+        let private_key_path = dir.path().join("device_key.pem");
+        fs::write(
+            &private_key_path,
+            private_key.private_key_to_pem_pkcs8().unwrap(),
+        )
+        .unwrap();
+
+        let node_attestation = NodeAttestation::new(&config(DpsCredentials::X509 {
+            cert_chain_path: cert_chain_path.to_str().unwrap().to_string(),
+            private_key_path: private_key_path.to_str().unwrap().to_string(),
+        }));
+        let token = node_attestation.get_attestation_token().await.unwrap();
+
+        let (expiry, certificate_chain, signature) = match serde_json::from_str(&token).unwrap() {
+            Token::X509 {
+                expiry,
+                certificate_chain,
+                signature,
+                ..
+            } => (expiry, certificate_chain, signature),
+            Token::SymmetricKey { .. } => panic!("Unexpected token type"),
+        };
+        assert_eq!(
+            certificate_chain,
+            vec![base64::encode(cert.to_der().unwrap())]
+        );
+
+        let data = signed_data("0ne00000000", "device-registration", expiry);
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &private_key).unwrap();
+        verifier.update(data.as_bytes()).unwrap();
+        assert!(verifier
+            .verify(&base64::decode(signature).unwrap())
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn read_credentials_error() {
+        let node_attestation = NodeAttestation::new(&config(DpsCredentials::SymmetricKey {
+            key_path: "/nonexistent/device_key".to_string(),
+        }));
+
+        let error = node_attestation.create_token().unwrap_err();
+
+        assert_matches!(error, Error::UnableToReadCredentials(_));
+    }
+}
@@ -1,9 +1,18 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use k8s_openapi::RequestError;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Unable to read the Service Account Token {0}")]
     UnableToReadToken(std::io::Error),
+    #[error("Unable to read the environment variable {0} of the agent pod {1}")]
+    MissingPodEnvVar(&'static str, std::env::VarError),
+    #[error("Error while creating token request {0}")]
+    TokenRequestRequest(RequestError),
+    #[error("Error while calling token request API {0}")]
+    K8sTokenRequestAPI(kube::Error),
+    #[error("Error while reading response from kube API, missing token request status")]
+    MissingTokenRequestStatus,
 }
@@ -2,29 +2,91 @@
 
 pub mod error;
 
-use std::{fs, path};
+use std::{env, fs, path};
 
-use agent_config::NodeAttestationConfigK8s;
+use agent_config::{NodeAttestationConfigK8s, TokenRequestConfig};
+use k8s_openapi::api::authentication::v1::{BoundObjectReference, TokenRequest};
+
+#[cfg(not(any(test, feature = "tests")))]
+use kube::Client;
+#[cfg(any(test, feature = "tests"))]
+use mock_kube::Client;
 
 use crate::NodeAttestation as NodeAttestationTrait;
 
 use error::Error;
 
+// Set from the pod spec with the downward API, the requested tokens are bound to this pod.
+const POD_NAME_ENV_VAR: &str = "POD_NAME";
+const POD_UID_ENV_VAR: &str = "POD_UID";
+const POD_NAMESPACE_ENV_VAR: &str = "POD_NAMESPACE";
+const SERVICE_ACCOUNT_NAME_ENV_VAR: &str = "SERVICE_ACCOUNT_NAME";
+
 pub struct NodeAttestation {
     token_path: path::PathBuf,
+    token_request: Option<TokenRequestConfig>,
+    client: Client,
 }
 
 impl NodeAttestation {
     #[must_use]
-    pub fn new(config: &NodeAttestationConfigK8s) -> Self {
+    pub fn new(config: &NodeAttestationConfigK8s, client: Client) -> Self {
         let token_path = path::Path::new(&config.token_path).to_path_buf();
-        NodeAttestation { token_path }
+        NodeAttestation {
+            token_path,
+            token_request: config.token_request.clone(),
+            client,
+        }
+    }
+
+    // New token of the service account of the agent, bound to its pod. The server sees a different token for
+    // every attestation, so it can reject the ones presented again.
+    async fn request_token(&self, config: &TokenRequestConfig) -> Result<String, Error> {
+        let mut body = TokenRequest::default();
+        body.spec.audiences = vec![config.audience.clone()];
+        body.spec.expiration_seconds =
+            Some(i64::try_from(config.expiration_secs).unwrap_or(i64::MAX));
+        body.spec.bound_object_ref = Some(BoundObjectReference {
+            api_version: Some("v1".to_string()),
+            kind: Some("Pod".to_string()),
+            name: Some(pod_env_var(POD_NAME_ENV_VAR)?),
+            uid: Some(pod_env_var(POD_UID_ENV_VAR)?),
+        });
+
+        let (req, _) = TokenRequest::create_namespaced_service_account_token(
+            &pod_env_var(SERVICE_ACCOUNT_NAME_ENV_VAR)?,
+            &pod_env_var(POD_NAMESPACE_ENV_VAR)?,
+            &body,
+            Default::default(),
+        )
+        .map_err(Error::TokenRequestRequest)?;
+
+        let resp = self
+            .client
+            .request::<TokenRequest>(req)
+            .await
+            .map_err(Error::K8sTokenRequestAPI)?;
+
+        let status = resp.status.ok_or(Error::MissingTokenRequestStatus)?;
+
+        Ok(status.token)
     }
 }
 
+fn pod_env_var(name: &'static str) -> Result<String, Error> {
+    env::var(name).map_err(|err| Error::MissingPodEnvVar(name, err))
+}
+
 #[async_trait::async_trait]
 impl NodeAttestationTrait for NodeAttestation {
     async fn get_attestation_token(&self) -> Result<String, Box<dyn std::error::Error + Send>> {
+        if let Some(token_request) = &self.token_request {
+            return self
+                .request_token(token_request)
+                .await
+                .map_err(|err| Box::new(err) as _);
+        }
+
         let token = fs::read_to_string(&self.token_path)
             .map_err(|err| Box::new(Error::UnableToReadToken(err)) as _)?;
 
@@ -41,8 +103,10 @@ mod tests {
     use crate::NodeAttestation as NodeAttestationTrait;
     use agent_config::Config;
     use agent_config::NodeAttestationConfig::Psat;
+    use agent_config::{NodeAttestationConfigK8s, TokenRequestConfig};
     use core_objects::AGENT_DEFAULT_CONFIG_PATH;
     use matches::assert_matches;
+    use mock_kube::{get_token_request, Client};
 
     fn init_tests() -> (Config, impl AsRef<std::path::Path>) {
         let dir = tempfile::tempdir().unwrap();
@@ -68,7 +132,7 @@ mod tests {
 
         config.token_path = token_path.to_str().unwrap().to_string();
 
-        let node_attestation = NodeAttestation::new(config);
+        let node_attestation = NodeAttestation::new(config, Client::try_default().await.unwrap());
 
         let token = node_attestation.get_attestation_token().await.unwrap();
 
@@ -85,7 +149,7 @@ mod tests {
             panic!("Unexpected attestation type");
         };
 
-        let node_attestation = NodeAttestation::new(config);
+        let node_attestation = NodeAttestation::new(config, Client::try_default().await.unwrap());
 
         let error = *node_attestation
             .get_attestation_token()
@@ -96,4 +160,65 @@ mod tests {
 
         assert_matches!(error, Error::UnableToReadToken(_));
     }
+
+    fn token_request_config(config: &mut Config) -> &mut NodeAttestationConfigK8s {
+        let config = if let Psat(config) = &mut config.node_attestation_config {
+            config
+        } else {
+            panic!("Unexpected attestation type");
+        };
+
+        config.token_request = Some(TokenRequestConfig {
+            audience: "iotedge-spiffe-server".to_string(),
+            expiration_secs: 600,
+        });
+        std::env::set_var(super::POD_NAME_ENV_VAR, "pod_name");
+        std::env::set_var(super::POD_UID_ENV_VAR, mock_kube::POD_UID);
+        std::env::set_var(super::POD_NAMESPACE_ENV_VAR, "namespace");
+        std::env::set_var(super::SERVICE_ACCOUNT_NAME_ENV_VAR, "iotedge-spiffe-agent");
+
+        config
+    }
+
+    #[tokio::test]
+    async fn attest_agent_token_request_happy_path() {
+        let (mut config, _base_path) = init_tests();
+        let config = token_request_config(&mut config);
+
+        let mut client = Client::try_default().await.unwrap();
+        client
+            .queue_response(get_token_request("first token"))
+            .await;
+        client
+            .queue_response(get_token_request("second token"))
+            .await;
+        let node_attestation = NodeAttestation::new(config, client);
+
+        // The token file is not read, every attestation gets a new token.
+        let token = node_attestation.get_attestation_token().await.unwrap();
+        assert_eq!(token, "first token");
+        let token = node_attestation.get_attestation_token().await.unwrap();
+        assert_eq!(token, "second token");
+    }
+
+    #[tokio::test]
+    async fn attest_agent_token_request_missing_status_error() {
+        let (mut config, _base_path) = init_tests();
+        let config = token_request_config(&mut config);
+
+        let mut client = Client::try_default().await.unwrap();
+        let mut token_request = get_token_request("token");
+        token_request.status = None;
+        client.queue_response(token_request).await;
+        let node_attestation = NodeAttestation::new(config, client);
+
+        let error = *node_attestation
+            .get_attestation_token()
+            .await
+            .unwrap_err()
+            .downcast::<Error>()
+            .unwrap();
+
+        assert_matches!(error, Error::MissingTokenRequestStatus);
+    }
 }
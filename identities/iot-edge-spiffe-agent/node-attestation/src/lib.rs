@@ -11,11 +11,18 @@
     clippy::missing_panics_doc
 )]
 
+pub mod dps;
 pub mod k8s;
+pub mod reattestation;
+pub mod x509pop;
 
 use std::sync::Arc;
 
 use agent_config::NodeAttestationConfig;
+#[cfg(not(any(test, feature = "tests")))]
+use kube::Client;
+#[cfg(any(test, feature = "tests"))]
+use mock_kube::Client;
 #[cfg(feature = "tests")]
 use mockall::automock;
 
@@ -23,10 +30,18 @@ pub struct NodeAttestatorFactory {}
 
 impl NodeAttestatorFactory {
     #[must_use]
-    pub fn get(config: &NodeAttestationConfig) -> Arc<dyn NodeAttestation> {
+    pub fn get(
+        config: &NodeAttestationConfig,
+        server_client: Arc<dyn spiffe_server_client::Client>,
+        kube_client: Client,
+    ) -> Arc<dyn NodeAttestation> {
         match config {
             NodeAttestationConfig::Sat(config) | NodeAttestationConfig::Psat(config) => {
-                Arc::new(k8s::NodeAttestation::new(config))
+                Arc::new(k8s::NodeAttestation::new(config, kube_client))
+            }
+            NodeAttestationConfig::Dps(config) => Arc::new(dps::NodeAttestation::new(config)),
+            NodeAttestationConfig::X509Pop(config) => {
+                Arc::new(x509pop::NodeAttestation::new(config, server_client))
             }
         }
     }
@@ -0,0 +1,184 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Periodic re-attestation of the agent. The attestation session is renewed with a token read again from
+// its source, e.g. the projected token rotated by the kubelet, before the session expires. Without it, the
+// session is only renewed by the next request after it got close to expiry. Failed renewals are retried
+// with an exponential backoff. The first attestation also tells whether the agent is ready to serve.
+
+use std::{sync::Arc, time::Duration};
+
+use core_objects::get_epoch_time;
+use log::{error, info};
+use spiffe_server_client::Client;
+use tokio::sync::oneshot;
+use zeroize::Zeroizing;
+
+use crate::NodeAttestation;
+
+pub const MIN_RETRY_DELAY: Duration = Duration::from_secs(1);
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+// Renewals are never closer than this, even for short sessions.
+const MIN_RENEWAL_DELAY: Duration = Duration::from_secs(5);
+
+// `attested` is notified once the agent was attested for the first time. Protocols without sessions
+// only check the attestation token can be read.
+pub async fn run(
+    node_attestation: Arc<dyn NodeAttestation>,
+    server_client: Arc<dyn Client>,
+    attested: oneshot::Sender<()>,
+) {
+    info!("Starting agent re-attestation task");
+
+    let mut attested = Some(attested);
+    let mut retry_delay = MIN_RETRY_DELAY;
+
+    loop {
+        let result = renew(node_attestation.as_ref(), server_client.as_ref()).await;
+        if result.is_ok() {
+            if let Some(attested) = attested.take() {
+                attested.send(()).ok();
+            }
+        }
+
+        let delay = match result {
+            Ok(Some(expires_at)) => {
+                retry_delay = MIN_RETRY_DELAY;
+                renewal_delay(expires_at, get_epoch_time())
+            }
+            Ok(None) => {
+                info!("The server protocol has no attestation session to renew");
+                return;
+            }
+            Err(err) => {
+                error!(
+                    "Could not renew the attestation session, retrying in {:?}: {}",
+                    retry_delay, err
+                );
+                let delay = retry_delay;
+                retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+                delay
+            }
+        };
+
+        tokio::time::sleep(delay).await;
+    }
+}
+
+// Returns the new expiry of the session.
+async fn renew(
+    node_attestation: &dyn NodeAttestation,
+    server_client: &dyn Client,
+) -> Result<Option<u64>, Box<dyn std::error::Error + Send>> {
+    let attestation_token = Zeroizing::new(node_attestation.get_attestation_token().await?);
+
+    server_client.attest(&attestation_token).await
+}
+
+// Renews halfway to the expiry, so a failed renewal still leaves time for the retries.
+fn renewal_delay(expires_at: u64, now: u64) -> Duration {
+    let remaining = Duration::from_secs(expires_at.saturating_sub(now));
+
+    (remaining / 2).max(MIN_RENEWAL_DELAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use agent_config::NodeAttestationConfigK8s;
+    use mock_kube::Client;
+    use spiffe_server_client::MockClient;
+
+    use super::*;
+    use crate::k8s;
+
+    #[test]
+    fn renewal_delay_halfway() {
+        assert_eq!(renewal_delay(400, 100), Duration::from_secs(150));
+        assert_eq!(renewal_delay(106, 100), MIN_RENEWAL_DELAY);
+        assert_eq!(renewal_delay(50, 100), MIN_RENEWAL_DELAY);
+    }
+
+    #[tokio::test]
+    async fn renew_reads_token_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let token_path = dir.path().join("token");
+        let node_attestation = k8s::NodeAttestation::new(
+            &NodeAttestationConfigK8s {
+                token_path: token_path.to_str().unwrap().to_string(),
+                token_request: None,
+            },
+            Client::try_default().await.unwrap(),
+        );
+
+        let mut server_client = MockClient::new();
+        server_client
+            .expect_attest()
+            .withf(|attestation_token| attestation_token == "first token")
+            .times(1)
+            .return_once(|_| Ok(Some(100)));
+        server_client
+            .expect_attest()
+            .withf(|attestation_token| attestation_token == "rotated token")
+            .times(1)
+            .return_once(|_| Ok(Some(200)));
+
+        fs::write(&token_path, "first token").unwrap();
+        let expires_at = renew(&node_attestation, &server_client).await.unwrap();
+        assert_eq!(expires_at, Some(100));
+
+        fs::write(&token_path, "rotated token").unwrap();
+        let expires_at = renew(&node_attestation, &server_client).await.unwrap();
+        assert_eq!(expires_at, Some(200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn run_notifies_attested() {
+        let dir = tempfile::tempdir().unwrap();
+        let token_path = dir.path().join("token");
+        let node_attestation = k8s::NodeAttestation::new(
+            &NodeAttestationConfigK8s {
+                token_path: token_path.to_str().unwrap().to_string(),
+                token_request: None,
+            },
+            Client::try_default().await.unwrap(),
+        );
+
+        let mut server_client = MockClient::new();
+        server_client
+            .expect_attest()
+            .times(1)
+            .return_once(|_| Ok(None));
+
+        let (attested_tx, mut attested_rx) = oneshot::channel();
+        let run = tokio::spawn(run(
+            Arc::new(node_attestation),
+            Arc::new(server_client),
+            attested_tx,
+        ));
+
+        // Not attested while the token is missing.
+        tokio::time::sleep(MIN_RETRY_DELAY / 2).await;
+        assert!(attested_rx.try_recv().is_err());
+
+        fs::write(&token_path, "token").unwrap();
+        run.await.unwrap();
+        attested_rx.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn renew_token_error() {
+        let node_attestation = k8s::NodeAttestation::new(
+            &NodeAttestationConfigK8s {
+                token_path: "/nonexistent/token".to_string(),
+                token_request: None,
+            },
+            Client::try_default().await.unwrap(),
+        );
+
+        // The server is not called without a token.
+        let server_client = MockClient::new();
+
+        renew(&node_attestation, &server_client).await.unwrap_err();
+    }
+}
@@ -0,0 +1,17 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Unable to read the agent certificate or private key {0}")]
+    UnableToReadCredentials(std::io::Error),
+    #[error("Invalid certificate or private key {0}")]
+    InvalidCertificate(openssl::error::ErrorStack),
+    #[error("Unable to get an attestation nonce from the server {0}")]
+    GetAttestationNonce(Box<dyn std::error::Error + Send>),
+    #[error("Failed to sign the attestation token {0}")]
+    Signing(openssl::error::ErrorStack),
+    #[error("Failed to serialize the attestation token {0}")]
+    SerializingToken(serde_json::Error),
+}
@@ -0,0 +1,201 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// The agent proves it holds the private key of its pre-provisioned certificate by signing a nonce of the
+// server. A nonce can only be used once, so a new one is requested for every token.
+
+pub mod error;
+
+use std::{fs, sync::Arc};
+
+use agent_config::NodeAttestationConfigX509Pop;
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer, x509::X509};
+use server_agent_api::x509pop_attestation::{signed_data, Token};
+use spiffe_server_client::Client;
+use zeroize::Zeroizing;
+
+use crate::NodeAttestation as NodeAttestationTrait;
+
+use error::Error;
+
+pub struct NodeAttestation {
+    config: NodeAttestationConfigX509Pop,
+    server_client: Arc<dyn Client>,
+}
+
+impl NodeAttestation {
+    #[must_use]
+    pub fn new(config: &NodeAttestationConfigX509Pop, server_client: Arc<dyn Client>) -> Self {
+        NodeAttestation {
+            config: config.clone(),
+            server_client,
+        }
+    }
+
+    async fn create_token(&self) -> Result<String, Error> {
+        // Read before asking for a nonce, so a missing file does not waste one.
+        let cert_chain =
+            fs::read(&self.config.cert_chain_path).map_err(Error::UnableToReadCredentials)?;
+        let certificate_chain = X509::stack_from_pem(&cert_chain)
+            .map_err(Error::InvalidCertificate)?
+            .iter()
+            .map(|cert| cert.to_der().map(base64::encode))
+            .collect::<Result<_, _>>()
+            .map_err(Error::InvalidCertificate)?;
+
+        // Wiped from memory once the token is created.
+        let private_key = Zeroizing::new(
+            fs::read(&self.config.private_key_path).map_err(Error::UnableToReadCredentials)?,
+        );
+        let private_key =
+            PKey::private_key_from_pem(&private_key).map_err(Error::InvalidCertificate)?;
+
+        let nonce = self
+            .server_client
+            .get_attestation_nonce()
+            .await
+            .map_err(Error::GetAttestationNonce)?
+            .nonce;
+
+        let mut signer =
+            Signer::new(MessageDigest::sha256(), &private_key).map_err(Error::Signing)?;
+        signer
+            .update(signed_data(&nonce).as_bytes())
+            .map_err(Error::Signing)?;
+        let signature = signer
+            .sign_to_vec()
+            .map(base64::encode)
+            .map_err(Error::Signing)?;
+
+        let token = Token {
+            nonce,
+            certificate_chain,
+            signature,
+        };
+
+        serde_json::to_string(&token).map_err(Error::SerializingToken)
+    }
+}
+
+#[async_trait::async_trait]
+impl NodeAttestationTrait for NodeAttestation {
+    async fn get_attestation_token(&self) -> Result<String, Box<dyn std::error::Error + Send>> {
+        self.create_token().await.map_err(|err| Box::new(err) as _)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use matches::assert_matches;
+    use openssl::{
+        asn1::Asn1Time,
+        ec::{EcGroup, EcKey},
+        nid::Nid,
+        pkey::Private,
+        sign::Verifier,
+        x509::X509NameBuilder,
+    };
+    use server_agent_api::get_attestation_nonce;
+    use spiffe_server_client::MockClient;
+
+    use super::*;
+
+    // Writes a self-signed certificate and its private key.
+    fn init(base_path: &Path) -> (NodeAttestationConfigX509Pop, X509, PKey<Private>) {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let private_key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, "agent").unwrap();
+        let name = name.build();
+        let mut cert = X509::builder().unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&private_key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&private_key, MessageDigest::sha256()).unwrap();
+        let cert = cert.build();
+
+        let cert_chain_path = base_path.join("agent_cert.pem");
+        fs::write(&cert_chain_path, cert.to_pem().unwrap()).unwrap();
+        let private_key_path = base_path.join("agent_key.pem");
+        fs::write(
+            &private_key_path,
+            private_key.private_key_to_pem_pkcs8().unwrap(),
+        )
+        .unwrap();
+
+        let config = NodeAttestationConfigX509Pop {
+            cert_chain_path: cert_chain_path.to_str().unwrap().to_string(),
+            private_key_path: private_key_path.to_str().unwrap().to_string(),
+        };
+
+        (config, cert, private_key)
+    }
+
+    #[tokio::test]
+    async fn token_happy_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, cert, private_key) = init(dir.path());
+
+        let mut server_client = MockClient::new();
+        server_client
+            .expect_get_attestation_nonce()
+            .times(1)
+            .return_once(|| {
+                Ok(get_attestation_nonce::Response {
+                    nonce: "nonce".to_string(),
+                    expires_at: u64::MAX,
+                })
+            });
+
+        let node_attestation = NodeAttestation::new(&config, Arc::new(server_client));
+        let token = node_attestation.get_attestation_token().await.unwrap();
+        let token: Token = serde_json::from_str(&token).unwrap();
+
+        assert_eq!(token.nonce, "nonce");
+        assert_eq!(
+            token.certificate_chain,
+            vec![base64::encode(cert.to_der().unwrap())]
+        );
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &private_key).unwrap();
+        verifier.update(signed_data("nonce").as_bytes()).unwrap();
+        assert!(verifier
+            .verify(&base64::decode(token.signature).unwrap())
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn get_attestation_nonce_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let (config, _cert, _private_key) = init(dir.path());
+
+        let mut server_client = MockClient::new();
+        server_client
+            .expect_get_attestation_nonce()
+            .return_once(|| Err(Box::new(std::io::Error::from(std::io::ErrorKind::Other)) as _));
+
+        let node_attestation = NodeAttestation::new(&config, Arc::new(server_client));
+        let error = node_attestation.create_token().await.unwrap_err();
+
+        assert_matches!(error, Error::GetAttestationNonce(_));
+    }
+
+    #[tokio::test]
+    async fn read_credentials_error() {
+        let config = NodeAttestationConfigX509Pop {
+            cert_chain_path: "/nonexistent/agent_cert.pem".to_string(),
+            private_key_path: "/nonexistent/agent_key.pem".to_string(),
+        };
+
+        // The server is not asked for a nonce.
+        let server_client = MockClient::new();
+        let node_attestation = NodeAttestation::new(&config, Arc::new(server_client));
+        let error = node_attestation.create_token().await.unwrap_err();
+
+        assert_matches!(error, Error::UnableToReadCredentials(_));
+    }
+}
@@ -0,0 +1,95 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Server client wrapper injecting the faults set in the agent config, e.g. to test the retries of the
+// trust bundle manager when the server is slow or unreachable. Only built with the `chaos` feature.
+
+use std::sync::Arc;
+
+use ::chaos::Faults;
+use server_agent_api::{create_workload_jwts, get_attestation_nonce, get_trust_bundle};
+
+use crate::{Client as ClientTrait, TrustBundleUpdates};
+
+pub struct Client {
+    client: Arc<dyn ClientTrait>,
+    faults: Arc<Faults>,
+}
+
+impl Client {
+    #[must_use]
+    pub fn new(client: Arc<dyn ClientTrait>, faults: Arc<Faults>) -> Self {
+        Client { client, faults }
+    }
+}
+
+#[async_trait::async_trait]
+impl ClientTrait for Client {
+    async fn create_workload_jwts(
+        &self,
+        request: create_workload_jwts::Request,
+    ) -> Result<create_workload_jwts::Response, Box<dyn std::error::Error + Send>> {
+        self.faults
+            .inject()
+            .await
+            .map_err(|err| Box::new(err) as _)?;
+
+        let mut response = self.client.create_workload_jwts(request).await?;
+
+        // A partial failure drops every other JWT-SVID, as if the server could not issue them.
+        let mut index = 0;
+        response.jwt_svids.retain(|_| {
+            index += 1;
+            !self.faults.fail_item(index - 1)
+        });
+
+        Ok(response)
+    }
+
+    async fn get_trust_bundle(
+        &self,
+        params: get_trust_bundle::Params,
+    ) -> Result<get_trust_bundle::Response, Box<dyn std::error::Error + Send>> {
+        self.faults
+            .inject()
+            .await
+            .map_err(|err| Box::new(err) as _)?;
+
+        self.client.get_trust_bundle(params).await
+    }
+
+    // Only the opening of the watch is faulty, the updates are sent as they are.
+    async fn watch_trust_bundle(
+        &self,
+        params: get_trust_bundle::Params,
+    ) -> Result<TrustBundleUpdates, Box<dyn std::error::Error + Send>> {
+        self.faults
+            .inject()
+            .await
+            .map_err(|err| Box::new(err) as _)?;
+
+        self.client.watch_trust_bundle(params).await
+    }
+
+    async fn attest(
+        &self,
+        attestation_token: &str,
+    ) -> Result<Option<u64>, Box<dyn std::error::Error + Send>> {
+        self.faults
+            .inject()
+            .await
+            .map_err(|err| Box::new(err) as _)?;
+
+        self.client.attest(attestation_token).await
+    }
+
+    async fn get_attestation_nonce(
+        &self,
+    ) -> Result<get_attestation_nonce::Response, Box<dyn std::error::Error + Send>> {
+        self.faults
+            .inject()
+            .await
+            .map_err(|err| Box::new(err) as _)?;
+
+        self.client.get_attestation_nonce().await
+    }
+}
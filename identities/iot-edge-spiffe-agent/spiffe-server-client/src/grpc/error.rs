@@ -0,0 +1,27 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use openssl::error::ErrorStack;
+use thiserror::Error;
+use tonic::Status;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Could not parse server address {0}")]
+    InvalidAddress(String),
+    #[error("Could not create the TLS connector {0}")]
+    TlsConnector(ErrorStack),
+    #[error("Error while opening or renewing the attestation session {0}")]
+    Attest(Status),
+    #[error("The server closed the attestation session")]
+    SessionClosed,
+    #[error("Error while creating workload jwt-svids {0}")]
+    CreateWorkloadJWTs(Status),
+    #[error("Error while getting trust bundle from server {0}")]
+    GetTrustBundle(Status),
+    #[error("Error while watching the trust bundle of the server {0}")]
+    WatchTrustBundle(Status),
+    #[error("Error while getting an attestation nonce from server {0}")]
+    GetAttestationNonce(Status),
+    #[error("Error while deserializing the trust bundle sent by the server {0}")]
+    DeserializingTrustBundle(serde_json::Error),
+}
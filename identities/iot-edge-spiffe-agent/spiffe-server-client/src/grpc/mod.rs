@@ -0,0 +1,283 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Client of the gRPC variant of the server-agent API. The agent is attested once to open a session, then
+// sends its session instead of its token until the session is about to expire, when the token of the next
+// request renews it. A request is sent with its token when no session could be opened.
+
+pub mod error;
+
+use agent_config::ServerConfig;
+use core_objects::get_epoch_time;
+use error::Error;
+use futures_util::StreamExt;
+use server_agent_api::{
+    create_workload_jwts, get_attestation_nonce, get_trust_bundle,
+    grpc::{
+        self,
+        generated::{
+            server_agent_client::ServerAgentClient, AttestRequest, AttestResponse,
+            GetAttestationNonceRequest, GetTrustBundleRequest, GetTrustBundleResponse,
+        },
+        Credential,
+    },
+};
+use tokio::sync::{mpsc, Mutex};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{transport::Channel, Code, Status, Streaming};
+
+use crate::{tls, Client as ClientTrait, TrustBundleUpdates};
+
+// A session closer than this to its expiry is renewed before being used.
+const SESSION_RENEW_MARGIN_SECS: u64 = 30;
+
+pub struct Client {
+    client: ServerAgentClient<Channel>,
+    session: Mutex<Option<Session>>,
+}
+
+struct Session {
+    id: String,
+    expires_at: u64,
+    // The server ends the session when this side of the stream is dropped.
+    tokens: mpsc::Sender<AttestRequest>,
+    responses: Streaming<AttestResponse>,
+}
+
+impl Session {
+    async fn open(
+        client: &ServerAgentClient<Channel>,
+        attestation_token: &str,
+    ) -> Result<Self, Error> {
+        let (tokens, requests) = mpsc::channel(1);
+        // Queued before the stream is opened, the server answers once the agent is attested.
+        send_token(&tokens, attestation_token).await?;
+
+        let mut responses = client
+            .clone()
+            .attest(ReceiverStream::new(requests))
+            .await
+            .map_err(Error::Attest)?
+            .into_inner();
+        let response = next_response(&mut responses).await?;
+
+        Ok(Session {
+            id: response.session_id,
+            expires_at: response.expires_at,
+            tokens,
+            responses,
+        })
+    }
+
+    async fn renew(&mut self, attestation_token: &str) -> Result<(), Error> {
+        send_token(&self.tokens, attestation_token).await?;
+        let response = next_response(&mut self.responses).await?;
+
+        self.id = response.session_id;
+        self.expires_at = response.expires_at;
+
+        Ok(())
+    }
+}
+
+async fn send_token(
+    tokens: &mpsc::Sender<AttestRequest>,
+    attestation_token: &str,
+) -> Result<(), Error> {
+    tokens
+        .send(AttestRequest {
+            attestation_token: attestation_token.to_string(),
+        })
+        .await
+        .map_err(|_| Error::SessionClosed)
+}
+
+async fn next_response(responses: &mut Streaming<AttestResponse>) -> Result<AttestResponse, Error> {
+    responses
+        .message()
+        .await
+        .map_err(Error::Attest)?
+        .ok_or(Error::SessionClosed)
+}
+
+fn to_trust_bundle(
+    update: Result<GetTrustBundleResponse, Status>,
+) -> Result<get_trust_bundle::Response, Box<dyn std::error::Error + Send>> {
+    let update = update.map_err(|status| Box::new(Error::WatchTrustBundle(status)) as _)?;
+
+    grpc::from_grpc_trust_bundle(&update)
+        .map_err(|err| Box::new(Error::DeserializingTrustBundle(err)) as _)
+}
+
+impl Client {
+    pub fn new(server_config: &ServerConfig) -> Result<Self, Error> {
+        let scheme = if server_config.tls.is_some() {
+            "https"
+        } else {
+            "http"
+        };
+        let endpoint = Channel::from_shared(format!(
+            "{}://{}:{}",
+            scheme, server_config.address, server_config.port
+        ))
+        .map_err(|err| Error::InvalidAddress(err.to_string()))?;
+
+        // Connects on the first request, like the HTTP client.
+        let channel = match &server_config.tls {
+            Some(tls_config) => {
+                let connector = tls::connector(tls_config).map_err(Error::TlsConnector)?;
+                endpoint.connect_with_connector_lazy(connector)
+            }
+            None => endpoint.connect_lazy(),
+        };
+
+        Ok(Self {
+            client: ServerAgentClient::new(channel),
+            session: Mutex::new(None),
+        })
+    }
+
+    // Returns the session to send instead of the token, opened or renewed with the token if needed.
+    async fn session_id(&self, attestation_token: &str) -> Result<String, Error> {
+        let mut session = self.session.lock().await;
+
+        if let Some(current) = session.as_mut() {
+            if current.expires_at > get_epoch_time() + SESSION_RENEW_MARGIN_SECS {
+                return Ok(current.id.clone());
+            }
+            if current.renew(attestation_token).await.is_ok() {
+                return Ok(current.id.clone());
+            }
+        }
+
+        *session = None;
+        let opened = Session::open(&self.client, attestation_token).await?;
+        let id = opened.id.clone();
+        *session = Some(opened);
+
+        Ok(id)
+    }
+
+    // Renews the session with the token whatever its expiry, or opens a new one. Returns the new expiry.
+    async fn renew_session(&self, attestation_token: &str) -> Result<u64, Error> {
+        let mut session = self.session.lock().await;
+
+        if let Some(current) = session.as_mut() {
+            if current.renew(attestation_token).await.is_ok() {
+                return Ok(current.expires_at);
+            }
+        }
+
+        *session = None;
+        let opened = Session::open(&self.client, attestation_token).await?;
+        let expires_at = opened.expires_at;
+        *session = Some(opened);
+
+        Ok(expires_at)
+    }
+}
+
+#[async_trait::async_trait]
+impl ClientTrait for Client {
+    async fn create_workload_jwts(
+        &self,
+        request: create_workload_jwts::Request,
+    ) -> Result<create_workload_jwts::Response, Box<dyn std::error::Error + Send>> {
+        let attestation_token = request.attestation_token.clone();
+
+        let response = match self.session_id(&attestation_token).await {
+            Ok(session_id) => {
+                let session_request =
+                    grpc::to_grpc_request(request.clone(), Credential::SessionId(session_id));
+
+                match self
+                    .client
+                    .clone()
+                    .create_workload_jwts(session_request)
+                    .await
+                {
+                    // The server lost the session, e.g. it restarted. The request is sent again with
+                    // its token.
+                    Err(status) if status.code() == Code::Unauthenticated => {
+                        *self.session.lock().await = None;
+                        None
+                    }
+                    response => Some(response),
+                }
+            }
+            Err(err) => {
+                log::warn!(
+                    "Could not open an attestation session, sending the attestation token: {}",
+                    err
+                );
+                None
+            }
+        };
+
+        let response = match response {
+            Some(response) => response,
+            None => {
+                let token_request =
+                    grpc::to_grpc_request(request, Credential::AttestationToken(attestation_token));
+                self.client
+                    .clone()
+                    .create_workload_jwts(token_request)
+                    .await
+            }
+        };
+
+        response
+            .map(|response| response.into_inner().into())
+            .map_err(|status| Box::new(Error::CreateWorkloadJWTs(status)) as _)
+    }
+
+    async fn get_trust_bundle(
+        &self,
+        params: get_trust_bundle::Params,
+    ) -> Result<get_trust_bundle::Response, Box<dyn std::error::Error + Send>> {
+        let response = self
+            .client
+            .clone()
+            .get_trust_bundle(GetTrustBundleRequest::from(params))
+            .await
+            .map_err(|status| Box::new(Error::GetTrustBundle(status)) as _)?;
+
+        grpc::from_grpc_trust_bundle(&response.into_inner())
+            .map_err(|err| Box::new(Error::DeserializingTrustBundle(err)) as _)
+    }
+
+    async fn watch_trust_bundle(
+        &self,
+        params: get_trust_bundle::Params,
+    ) -> Result<TrustBundleUpdates, Box<dyn std::error::Error + Send>> {
+        let updates = self
+            .client
+            .clone()
+            .watch_trust_bundle(GetTrustBundleRequest::from(params))
+            .await
+            .map_err(|status| Box::new(Error::WatchTrustBundle(status)) as _)?
+            .into_inner();
+
+        Ok(updates.map(to_trust_bundle).boxed())
+    }
+
+    async fn attest(
+        &self,
+        attestation_token: &str,
+    ) -> Result<Option<u64>, Box<dyn std::error::Error + Send>> {
+        self.renew_session(attestation_token)
+            .await
+            .map(Some)
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn get_attestation_nonce(
+        &self,
+    ) -> Result<get_attestation_nonce::Response, Box<dyn std::error::Error + Send>> {
+        self.client
+            .clone()
+            .get_attestation_nonce(GetAttestationNonceRequest {})
+            .await
+            .map(|response| response.into_inner().into())
+            .map_err(|status| Box::new(Error::GetAttestationNonce(status)) as _)
+    }
+}
@@ -3,6 +3,7 @@
 use std::io;
 
 use http_common::ConnectorError;
+use openssl::error::ErrorStack;
 use thiserror::Error;
 use url::ParseError;
 
@@ -12,14 +13,22 @@ pub enum Error {
     InvalidAddress(ParseError),
     #[error("Could create connector with given address {0}")]
     Connector(String),
+    #[error("Could not create the TLS connector {0}")]
+    TlsConnector(ErrorStack),
     #[error("Error while creating workload jwt-svids {0}")]
     CreateWorkloadJWTs(io::Error),
     #[error("Error while getting trust bundle from server {0}")]
     GetTrustBundle(io::Error),
+    #[error("Error while getting an attestation nonce from server {0}")]
+    GetAttestationNonce(io::Error),
     #[error("Error while deserializing response from create_workload_jwts request {0}")]
     DeserializingCreateWorkloadJWTsResponse(io::Error),
     #[error("Error while deserializing response from get_trust_bundle request {0}")]
     DeserializingGetTrustBundleResponse(io::Error),
+    #[error("Error while deserializing response from get_attestation_nonce request {0}")]
+    DeserializingGetAttestationNonceResponse(io::Error),
+    #[error("The HTTP server-agent API cannot watch the trust bundle, use gRPC")]
+    WatchTrustBundleUnsupported,
 }
 
 impl From<ConnectorError> for Error {
@@ -2,19 +2,30 @@
 
 pub mod error;
 
-use crate::Client as ClientTrait;
+use std::io;
+
+use crate::{tls, Client as ClientTrait, TrustBundleUpdates};
 
 use agent_config::ServerConfig;
 use error::Error;
 use http_common::{Connector, ErrorBody, HttpRequest};
-use server_agent_api::{create_workload_jwts, get_trust_bundle, ApiVersion};
+use hyper::{client::HttpConnector, header, Body, Method, Request, StatusCode};
+use hyper_openssl::HttpsConnector;
+use serde::{de::DeserializeOwned, Serialize};
+use server_agent_api::{create_workload_jwts, get_attestation_nonce, get_trust_bundle, ApiVersion};
 use url::Url;
 
 pub struct Client {
-    connector: http_common::Connector,
+    transport: Transport,
     address_url: Url,
 }
 
+// The connector of http-common has no TLS, the requests over TLS are sent with a hyper client instead.
+enum Transport {
+    Plain(Connector),
+    Tls(hyper::Client<HttpsConnector<HttpConnector>, Body>),
+}
+
 #[must_use]
 pub fn create_workload_jwts_uri() -> String {
     format!("workload-jwts?api-version={}", ApiVersion::V2022_06_01)
@@ -25,23 +36,92 @@ pub fn get_trust_bundle_uri() -> String {
     format!("trust-bundle?api-version={}", ApiVersion::V2022_06_01)
 }
 
+#[must_use]
+pub fn get_attestation_nonce_uri() -> String {
+    format!("attestation-nonce?api-version={}", ApiVersion::V2022_06_01)
+}
+
 impl Client {
     pub fn new(server_config: &ServerConfig) -> Result<Self, Error> {
+        let scheme = if server_config.tls.is_some() {
+            "https"
+        } else {
+            "http"
+        };
         let address_url = url::Url::parse(&format!(
-            "http://{}:{}",
-            server_config.address, server_config.port
+            "{}://{}:{}",
+            scheme, server_config.address, server_config.port
         ))
         .map_err(Error::InvalidAddress)?;
 
-        let connector = Connector::new(&address_url).map_err(Error::from)?;
+        let transport = match &server_config.tls {
+            Some(tls_config) => {
+                let connector = tls::connector(tls_config).map_err(Error::TlsConnector)?;
+                Transport::Tls(hyper::Client::builder().build(connector))
+            }
+            None => Transport::Plain(Connector::new(&address_url).map_err(Error::from)?),
+        };
 
         Ok(Self {
-            connector,
+            transport,
             address_url,
         })
     }
 }
 
+// The server answers the requests of the server-agent API with 201 and a JSON body.
+async fn send_tls<TRequest, TResponse>(
+    client: &hyper::Client<HttpsConnector<HttpConnector>, Body>,
+    method: Method,
+    uri: &str,
+    attestation_token: Option<&str>,
+    body: Option<&TRequest>,
+) -> io::Result<TResponse>
+where
+    TRequest: Serialize,
+    TResponse: DeserializeOwned,
+{
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(attestation_token) = attestation_token {
+        request = request.header(
+            header::AUTHORIZATION,
+            format!("Bearer {}", attestation_token),
+        );
+    }
+    let request = match body {
+        Some(body) => {
+            let body = serde_json::to_vec(body)?;
+            request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+        }
+        None => request.body(Body::empty()),
+    }
+    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    let response = client
+        .request(request)
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+
+    if status != StatusCode::CREATED {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "The server answered {}: {}",
+                status,
+                String::from_utf8_lossy(&body)
+            ),
+        ));
+    }
+
+    serde_json::from_slice(&body).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
 #[async_trait::async_trait]
 impl ClientTrait for Client {
     async fn create_workload_jwts(
@@ -49,7 +129,23 @@ impl ClientTrait for Client {
         request: create_workload_jwts::Request,
     ) -> Result<create_workload_jwts::Response, Box<dyn std::error::Error + Send>> {
         let address_url = format!("{}{}", self.address_url, &create_workload_jwts_uri(),);
-        let request = HttpRequest::post(self.connector.clone(), &address_url, Some(request));
+        let connector = match &self.transport {
+            Transport::Plain(connector) => connector.clone(),
+            Transport::Tls(client) => {
+                // Also sent in the header, the server authenticates the agent before handling the request.
+                let attestation_token = request.attestation_token.clone();
+                return send_tls(
+                    client,
+                    Method::POST,
+                    &address_url,
+                    Some(&attestation_token),
+                    Some(&request),
+                )
+                .await
+                .map_err(|err| Box::new(Error::CreateWorkloadJWTs(err)) as _);
+            }
+        };
+        let request = HttpRequest::post(connector, &address_url, Some(request));
 
         let response = request
             .json_response()
@@ -72,7 +168,15 @@ impl ClientTrait for Client {
             params.jwt_keys,
             params.x509_cas,
         );
-        let request: HttpRequest<(), _> = HttpRequest::get(self.connector.clone(), &address_url);
+        let connector = match &self.transport {
+            Transport::Plain(connector) => connector.clone(),
+            Transport::Tls(client) => {
+                return send_tls(client, Method::GET, &address_url, None, None::<&()>)
+                    .await
+                    .map_err(|err| Box::new(Error::GetTrustBundle(err)) as _);
+            }
+        };
+        let request: HttpRequest<(), _> = HttpRequest::get(connector, &address_url);
 
         let response = request
             .json_response()
@@ -83,4 +187,43 @@ impl ClientTrait for Client {
             .parse::<get_trust_bundle::Response, ErrorBody<'_>>(&[hyper::StatusCode::CREATED])
             .map_err(|err| Box::new(Error::DeserializingGetTrustBundleResponse(err)) as _)
     }
+
+    async fn watch_trust_bundle(
+        &self,
+        _params: get_trust_bundle::Params,
+    ) -> Result<TrustBundleUpdates, Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::WatchTrustBundleUnsupported))
+    }
+
+    // Every request is attested with its token, there is no session.
+    async fn attest(
+        &self,
+        _attestation_token: &str,
+    ) -> Result<Option<u64>, Box<dyn std::error::Error + Send>> {
+        Ok(None)
+    }
+
+    async fn get_attestation_nonce(
+        &self,
+    ) -> Result<get_attestation_nonce::Response, Box<dyn std::error::Error + Send>> {
+        let address_url = format!("{}{}", self.address_url, &get_attestation_nonce_uri());
+        let connector = match &self.transport {
+            Transport::Plain(connector) => connector.clone(),
+            Transport::Tls(client) => {
+                return send_tls(client, Method::POST, &address_url, None, None::<&()>)
+                    .await
+                    .map_err(|err| Box::new(Error::GetAttestationNonce(err)) as _);
+            }
+        };
+        let request: HttpRequest<(), _> = HttpRequest::post(connector, &address_url, None);
+
+        let response = request
+            .json_response()
+            .await
+            .map_err(|err| Box::new(Error::GetAttestationNonce(err)) as _)?;
+
+        response
+            .parse::<get_attestation_nonce::Response, ErrorBody<'_>>(&[hyper::StatusCode::CREATED])
+            .map_err(|err| Box::new(Error::DeserializingGetAttestationNonceResponse(err)) as _)
+    }
 }
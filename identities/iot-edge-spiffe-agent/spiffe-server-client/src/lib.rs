@@ -11,15 +11,23 @@
     clippy::missing_panics_doc
 )]
 
+#[cfg(feature = "chaos")]
+pub mod fault_injection;
+pub mod grpc;
 pub mod http;
+mod tls;
 
 use std::sync::Arc;
 
 #[cfg(feature = "tests")]
 use mockall::automock;
 
-use agent_config::ServerConfig;
-use server_agent_api::{create_workload_jwts, get_trust_bundle};
+use agent_config::{ServerConfig, ServerProtocol};
+use futures_util::stream::BoxStream;
+use server_agent_api::{create_workload_jwts, get_attestation_nonce, get_trust_bundle};
+
+pub type TrustBundleUpdates =
+    BoxStream<'static, Result<get_trust_bundle::Response, Box<dyn std::error::Error + Send>>>;
 
 pub struct ServerClientFactory {}
 
@@ -27,9 +35,16 @@ impl ServerClientFactory {
     pub fn get(
         server_config: &ServerConfig,
     ) -> Result<Arc<dyn Client>, Box<dyn std::error::Error + Send>> {
-        let http_client = http::Client::new(server_config).map_err(|err| Box::new(err) as _)?;
+        let client: Arc<dyn Client> = match server_config.protocol {
+            ServerProtocol::Http => {
+                Arc::new(http::Client::new(server_config).map_err(|err| Box::new(err) as _)?)
+            }
+            ServerProtocol::Grpc => {
+                Arc::new(grpc::Client::new(server_config).map_err(|err| Box::new(err) as _)?)
+            }
+        };
 
-        Ok(Arc::new(http_client))
+        Ok(client)
     }
 }
 
@@ -45,4 +60,23 @@ pub trait Client: Sync + Send {
         &self,
         params: get_trust_bundle::Params,
     ) -> Result<get_trust_bundle::Response, Box<dyn std::error::Error + Send>>;
+
+    /// Stream of the trust bundles sent by the server, starting with the current one. Only the gRPC
+    /// client supports it.
+    async fn watch_trust_bundle(
+        &self,
+        params: get_trust_bundle::Params,
+    ) -> Result<TrustBundleUpdates, Box<dyn std::error::Error + Send>>;
+
+    /// Opens or renews the attestation session of the agent with a new token. Returns the expiry of the
+    /// session, None when the protocol has no sessions. Only the gRPC client has sessions.
+    async fn attest(
+        &self,
+        attestation_token: &str,
+    ) -> Result<Option<u64>, Box<dyn std::error::Error + Send>>;
+
+    /// Nonce to sign in the next attestation token, for the attestations using nonces.
+    async fn get_attestation_nonce(
+        &self,
+    ) -> Result<get_attestation_nonce::Response, Box<dyn std::error::Error + Send>>;
 }
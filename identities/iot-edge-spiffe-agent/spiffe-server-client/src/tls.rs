@@ -0,0 +1,28 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Connector to the server-agent API over TLS, for the HTTP and the gRPC clients.
+
+use agent_config::ServerTlsConfig;
+use hyper::client::HttpConnector;
+use hyper_openssl::HttpsConnector;
+use openssl::{
+    error::ErrorStack,
+    ssl::{SslConnector, SslFiletype, SslMethod},
+};
+
+pub(crate) fn connector(
+    config: &ServerTlsConfig,
+) -> Result<HttpsConnector<HttpConnector>, ErrorStack> {
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+
+    let mut builder = SslConnector::builder(SslMethod::tls_client())?;
+    builder.set_ca_file(&config.ca_path)?;
+    if let Some(client_certificate) = &config.client_certificate {
+        builder.set_certificate_chain_file(&client_certificate.cert_path)?;
+        builder.set_private_key_file(&client_certificate.key_path, SslFiletype::PEM)?;
+        builder.check_private_key()?;
+    }
+
+    HttpsConnector::with_connector(http, builder)
+}
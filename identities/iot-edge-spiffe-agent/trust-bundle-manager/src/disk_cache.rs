@@ -0,0 +1,72 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Copy of the last trust bundle received, kept on disk so an agent restarted while the server cannot be
+// reached still validates the JWT-SVIDs of its workloads. The file is written next to the cache then
+// renamed, a crash never leaves a partial trust bundle behind.
+
+use std::path::Path;
+
+use core_objects::TrustBundle;
+use tokio::fs;
+
+use crate::error::Error;
+
+pub async fn load(path: &Path) -> Result<TrustBundle, Error> {
+    let bytes = fs::read(path).await.map_err(Error::ReadCache)?;
+
+    serde_json::from_slice(&bytes).map_err(Error::InvalidCache)
+}
+
+pub async fn save(path: &Path, trust_bundle: &TrustBundle) -> Result<(), Error> {
+    let bytes = serde_json::to_vec(trust_bundle).map_err(|err| Error::WriteCache(err.into()))?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(Error::WriteCache)?;
+    }
+
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, bytes)
+        .await
+        .map_err(Error::WriteCache)?;
+    fs::rename(&temp_path, path)
+        .await
+        .map_err(Error::WriteCache)
+}
+
+#[cfg(test)]
+mod tests {
+    use core_objects::JWKSet;
+    use matches::assert_matches;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn save_then_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cache").join("trust_bundle.json");
+        let trust_bundle = TrustBundle {
+            trust_domain: "trust_domain".to_string(),
+            jwt_key_set: JWKSet {
+                keys: Vec::new(),
+                spiffe_refresh_hint: 30,
+                spiffe_sequence_number: 1,
+            },
+            x509_key_set: JWKSet {
+                keys: Vec::new(),
+                spiffe_refresh_hint: 0,
+                spiffe_sequence_number: 0,
+            },
+        };
+
+        assert_matches!(load(&path).await, Err(Error::ReadCache(_)));
+
+        save(&path, &trust_bundle).await.unwrap();
+        assert_eq!(load(&path).await.unwrap(), trust_bundle);
+        assert!(!path.with_extension("tmp").exists());
+
+        std::fs::write(&path, "{").unwrap();
+        assert_matches!(load(&path).await, Err(Error::InvalidCache(_)));
+    }
+}
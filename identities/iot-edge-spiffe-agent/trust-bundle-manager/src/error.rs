@@ -8,4 +8,10 @@ pub enum Error {
     InitTrustBundle(Box<dyn std::error::Error + Send>),
     #[error("Could not refresh the trust bundle")]
     TrustBundle(Box<dyn std::error::Error + Send>),
+    #[error("Could not read the trust bundle cached on disk: {0}")]
+    ReadCache(std::io::Error),
+    #[error("Could not parse the trust bundle cached on disk: {0}")]
+    InvalidCache(serde_json::Error),
+    #[error("Could not write the trust bundle cache on disk: {0}")]
+    WriteCache(std::io::Error),
 }
@@ -11,37 +11,89 @@
     clippy::missing_panics_doc
 )]
 
+pub mod disk_cache;
 pub mod error;
+pub mod refresh;
 
-use std::{sync::Arc, time::Duration};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use agent_config::TrustBundleManagerConfig;
-use core_objects::TrustBundle;
+use core_objects::{get_epoch_time, TrustBundle};
 use error::Error;
+use futures_util::StreamExt;
 use log::{info, warn};
 use server_agent_api::get_trust_bundle;
 use spiffe_server_client::Client;
-use tokio::{sync::RwLock, time::sleep};
+use tokio::{
+    sync::{broadcast, RwLock},
+    time::sleep,
+};
+
+// Rotations are rare, subscribers only lag behind when they stall.
+const UPDATES_CAPACITY: usize = 16;
 
 pub struct TrustBundleManager {
     trust_bundle: RwLock<TrustBundle>,
+    // Bundles of the trust domains the server federates with. Not saved to the disk cache, they are empty until
+    // the server is reached.
+    federated_bundles: RwLock<Vec<TrustBundle>>,
     spiffe_server_client: Arc<dyn Client>,
+    updates: broadcast::Sender<TrustBundle>,
+    // Every new trust bundle is saved there, see `disk_cache`.
+    cache_path: Option<PathBuf>,
+    // Seconds since epoch, 0 until the first refresh or watch update.
+    last_refresh_at: AtomicU64,
 }
 
 impl TrustBundleManager {
     #[must_use]
     pub fn new(spiffe_server_client: Arc<dyn Client>, init_trust_bundle: TrustBundle) -> Self {
+        let (updates, _) = broadcast::channel(UPDATES_CAPACITY);
+
         TrustBundleManager {
             trust_bundle: RwLock::new(init_trust_bundle),
+            federated_bundles: RwLock::new(Vec::new()),
             spiffe_server_client,
+            updates,
+            cache_path: None,
+            last_refresh_at: AtomicU64::new(0),
         }
     }
 
+    #[must_use]
+    pub fn with_cache_path(mut self, cache_path: Option<PathBuf>) -> Self {
+        self.cache_path = cache_path;
+
+        self
+    }
+
+    #[must_use]
+    pub fn with_federated_bundles(mut self, federated_bundles: Vec<TrustBundle>) -> Self {
+        self.federated_bundles = RwLock::new(federated_bundles);
+
+        self
+    }
+
+    // Receives the trust bundle every time it or the federated bundles change, e.g. when the server rotates its
+    // keys. A lagging receiver should read the cached trust bundle instead of the updates it missed.
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<TrustBundle> {
+        self.updates.subscribe()
+    }
+
     pub async fn get_init_trust_bundle(
         spiffe_server_client: Arc<dyn Client>,
         config: &TrustBundleManagerConfig,
-    ) -> Result<TrustBundle, Error> {
+    ) -> Result<get_trust_bundle::Response, Error> {
         info!("Getting first trust bundle");
+        let cache_path = config.cache_path.as_ref().map(PathBuf::from);
         let mut retry = 0;
 
         loop {
@@ -53,9 +105,38 @@ impl TrustBundleManager {
             let trust_bundle = spiffe_server_client.get_trust_bundle(params).await;
 
             match trust_bundle {
-                Ok(trust_bundle) => return Ok(trust_bundle.trust_bundle),
+                Ok(trust_bundle) => {
+                    if let Some(cache_path) = &cache_path {
+                        if let Err(err) =
+                            disk_cache::save(cache_path, &trust_bundle.trust_bundle).await
+                        {
+                            warn!("{}", err);
+                        }
+                    }
+
+                    return Ok(trust_bundle);
+                }
                 Err(err) => {
                     if retry >= config.max_retry {
+                        // The server may be down for a while at the edge, the last trust bundle is
+                        // better than none until the refresh gets a new one.
+                        if let Some(cache_path) = &cache_path {
+                            match disk_cache::load(cache_path).await {
+                                Ok(trust_bundle) => {
+                                    warn!(
+                                        "Failed to get trust bundle {:?}, using the trust bundle cached in {}",
+                                        err,
+                                        cache_path.display()
+                                    );
+                                    return Ok(get_trust_bundle::Response {
+                                        trust_bundle,
+                                        federated_bundles: Vec::new(),
+                                    });
+                                }
+                                Err(cache_err) => warn!("{}", cache_err),
+                            }
+                        }
+
                         return Err(Error::InitTrustBundle(err));
                     }
                     retry += 1;
@@ -76,20 +157,101 @@ impl TrustBundleManager {
             x509_cas: false,
         };
 
-        let trust_bundle = &mut *self.trust_bundle.write().await;
-        *trust_bundle = self
+        let response = self
             .spiffe_server_client
             .get_trust_bundle(params)
             .await
-            .map_err(Error::TrustBundle)?
-            .trust_bundle;
+            .map_err(Error::TrustBundle)?;
+        self.set_trust_bundle(response).await;
+        self.last_refresh_at
+            .store(get_epoch_time(), Ordering::Relaxed);
 
         Ok(())
     }
 
+    // Refreshes the trust bundle unless it was received less than `min_interval` seconds ago, or another caller
+    // is refreshing it. For the refreshes the workloads can trigger, so they cannot flood the server. Returns
+    // whether the trust bundle was refreshed.
+    pub async fn refresh_trust_bundle_throttled(&self, min_interval: u64) -> Result<bool, Error> {
+        let now = get_epoch_time();
+        let last_refresh_at = self.last_refresh_at();
+        if now < last_refresh_at.saturating_add(min_interval) {
+            return Ok(false);
+        }
+        // A failed refresh is throttled too, a server that is down is not asked again right away.
+        if self
+            .last_refresh_at
+            .compare_exchange(last_refresh_at, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return Ok(false);
+        }
+
+        self.refresh_trust_bundle().await?;
+
+        Ok(true)
+    }
+
+    // Caches the trust bundles sent by the server until it closes the watch or the watch fails.
+    pub async fn watch_trust_bundle(&self) -> Result<(), Error> {
+        let params = get_trust_bundle::Params {
+            jwt_keys: true,
+            x509_cas: false,
+        };
+
+        let mut updates = self
+            .spiffe_server_client
+            .watch_trust_bundle(params)
+            .await
+            .map_err(Error::TrustBundle)?;
+
+        while let Some(update) = updates.next().await {
+            let response = update.map_err(Error::TrustBundle)?;
+            self.set_trust_bundle(response).await;
+            self.last_refresh_at
+                .store(get_epoch_time(), Ordering::Relaxed);
+            info!("Received new trust bundle");
+        }
+
+        Ok(())
+    }
+
+    async fn set_trust_bundle(&self, response: get_trust_bundle::Response) {
+        let mut cached = self.trust_bundle.write().await;
+        let mut cached_federated_bundles = self.federated_bundles.write().await;
+        if *cached == response.trust_bundle
+            && *cached_federated_bundles == response.federated_bundles
+        {
+            return;
+        }
+
+        if *cached != response.trust_bundle {
+            if let Some(cache_path) = &self.cache_path {
+                if let Err(err) = disk_cache::save(cache_path, &response.trust_bundle).await {
+                    warn!("{}", err);
+                }
+            }
+        }
+
+        *cached = response.trust_bundle.clone();
+        *cached_federated_bundles = response.federated_bundles;
+        // Fails when nobody is subscribed, which is fine.
+        self.updates.send(response.trust_bundle).ok();
+    }
+
     pub async fn get_cached_trust_bundle(&self) -> TrustBundle {
         self.trust_bundle.read().await.clone()
     }
+
+    pub async fn get_cached_federated_bundles(&self) -> Vec<TrustBundle> {
+        self.federated_bundles.read().await.clone()
+    }
+
+    // Last time the trust bundle was received from the server, changed or not.
+    #[must_use]
+    pub fn last_refresh_at(&self) -> u64 {
+        self.last_refresh_at.load(Ordering::Relaxed)
+    }
 }
 
 #[cfg(test)]
@@ -99,11 +261,12 @@ mod tests {
 
     use agent_config::TrustBundleManagerConfig;
     use core_objects::{Crv, JWKSet, KeyUse, Kty, TrustBundle, JWK};
+    use futures_util::{stream, StreamExt};
     use matches::assert_matches;
     use server_agent_api::get_trust_bundle;
     use spiffe_server_client::MockClient;
 
-    use crate::{error::Error, TrustBundleManager};
+    use crate::{disk_cache, error::Error, TrustBundleManager};
 
     #[tokio::test]
     async fn get_init_trust_bundle_happy_path() {
@@ -114,18 +277,21 @@ mod tests {
         let config = TrustBundleManagerConfig {
             max_retry: 3,
             wait_retry_sec: 0,
+            cache_path: None,
         };
 
         mock_client.expect_get_trust_bundle().return_once(|_| {
             Ok(get_trust_bundle::Response {
                 trust_bundle: get_trust_bundle(),
+                federated_bundles: Vec::new(),
             })
         });
 
         let trust_bundle =
             TrustBundleManager::get_init_trust_bundle(Arc::new(mock_client), &config)
                 .await
-                .unwrap();
+                .unwrap()
+                .trust_bundle;
 
         assert_eq!(
             trust_bundle.trust_domain,
@@ -149,6 +315,7 @@ mod tests {
         let config = TrustBundleManagerConfig {
             max_retry: 3,
             wait_retry_sec: 0,
+            cache_path: None,
         };
 
         mock_client
@@ -167,6 +334,104 @@ mod tests {
         assert_matches!(error, Error::InitTrustBundle(_));
     }
 
+    #[tokio::test]
+    async fn get_init_trust_bundle_from_disk_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = TrustBundleManagerConfig {
+            max_retry: 0,
+            wait_retry_sec: 0,
+            cache_path: Some(dir.path().join("trust_bundle.json").display().to_string()),
+        };
+
+        // Nothing cached yet.
+        let mut mock_client = MockClient::new();
+        mock_client.expect_get_trust_bundle().return_once(|_| {
+            Err(Box::new(
+                spiffe_server_client::http::error::Error::Connector("dummy".to_string()),
+            ))
+        });
+        let error = TrustBundleManager::get_init_trust_bundle(Arc::new(mock_client), &config)
+            .await
+            .unwrap_err();
+        assert_matches!(error, Error::InitTrustBundle(_));
+
+        let mut mock_client = MockClient::new();
+        mock_client.expect_get_trust_bundle().return_once(|_| {
+            Ok(get_trust_bundle::Response {
+                trust_bundle: get_trust_bundle(),
+                federated_bundles: Vec::new(),
+            })
+        });
+        TrustBundleManager::get_init_trust_bundle(Arc::new(mock_client), &config)
+            .await
+            .unwrap();
+
+        let mut mock_client = MockClient::new();
+        mock_client.expect_get_trust_bundle().return_once(|_| {
+            Err(Box::new(
+                spiffe_server_client::http::error::Error::Connector("dummy".to_string()),
+            ))
+        });
+        let response = TrustBundleManager::get_init_trust_bundle(Arc::new(mock_client), &config)
+            .await
+            .unwrap();
+        assert_eq!(response.trust_bundle, get_trust_bundle());
+        assert!(response.federated_bundles.is_empty());
+    }
+
+    #[tokio::test]
+    async fn refresh_trust_bundle_saves_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_path = dir.path().join("trust_bundle.json");
+        let mut mock_client = MockClient::new();
+
+        let mut rotated_trust_bundle = get_trust_bundle();
+        rotated_trust_bundle.jwt_key_set.keys[0].kid = "rotated".to_string();
+        let rotated_trust_bundle_copy = rotated_trust_bundle.clone();
+        mock_client.expect_get_trust_bundle().return_once(move |_| {
+            Ok(get_trust_bundle::Response {
+                trust_bundle: rotated_trust_bundle_copy,
+                federated_bundles: Vec::new(),
+            })
+        });
+
+        let trust_bundle_manager =
+            TrustBundleManager::new(Arc::new(mock_client), get_trust_bundle())
+                .with_cache_path(Some(cache_path.clone()));
+        trust_bundle_manager.refresh_trust_bundle().await.unwrap();
+
+        assert_eq!(
+            disk_cache::load(&cache_path).await.unwrap(),
+            rotated_trust_bundle
+        );
+    }
+
+    #[tokio::test]
+    async fn refresh_trust_bundle_throttled() {
+        let mut mock_client = MockClient::new();
+        mock_client
+            .expect_get_trust_bundle()
+            .times(1)
+            .returning(|_| {
+                Ok(get_trust_bundle::Response {
+                    trust_bundle: get_trust_bundle(),
+                    federated_bundles: Vec::new(),
+                })
+            });
+
+        let trust_bundle_manager =
+            TrustBundleManager::new(Arc::new(mock_client), get_trust_bundle());
+        assert!(trust_bundle_manager
+            .refresh_trust_bundle_throttled(60)
+            .await
+            .unwrap());
+        // Refreshed less than a minute ago, the server is not called again.
+        assert!(!trust_bundle_manager
+            .refresh_trust_bundle_throttled(60)
+            .await
+            .unwrap());
+    }
+
     #[tokio::test]
     async fn refresh_trust_bundle_error_path() {
         let mut mock_client = MockClient::new();
@@ -203,6 +468,7 @@ mod tests {
         mock_client.expect_get_trust_bundle().return_once(move |_| {
             Ok(get_trust_bundle::Response {
                 trust_bundle: expected_trust_bundle_copy,
+                federated_bundles: Vec::new(),
             })
         });
 
@@ -215,8 +481,11 @@ mod tests {
             expected_trust_bundle1.jwt_key_set.keys[0].x
         );
 
+        assert_eq!(trust_bundle_manager.last_refresh_at(), 0);
+
         // Refresh trust bundle
         trust_bundle_manager.refresh_trust_bundle().await.unwrap();
+        assert_ne!(trust_bundle_manager.last_refresh_at(), 0);
         // Get new trust bundle
         let trust_bundle = trust_bundle_manager.get_cached_trust_bundle().await;
         // key should now match 1234
@@ -226,12 +495,113 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn refresh_trust_bundle_notifies_changes() {
+        let mut mock_client = MockClient::new();
+
+        let mut rotated_trust_bundle = get_trust_bundle();
+        rotated_trust_bundle.jwt_key_set.keys[0].kid = "rotated".to_string();
+        // Popped from the end, the same trust bundle first.
+        let mut responses = vec![rotated_trust_bundle.clone(), get_trust_bundle()];
+        mock_client
+            .expect_get_trust_bundle()
+            .times(2)
+            .returning(move |_| {
+                Ok(get_trust_bundle::Response {
+                    trust_bundle: responses.pop().unwrap(),
+                    federated_bundles: Vec::new(),
+                })
+            });
+
+        let trust_bundle_manager =
+            TrustBundleManager::new(Arc::new(mock_client), get_trust_bundle());
+        let mut updates = trust_bundle_manager.subscribe();
+
+        // Same trust bundle, nothing to notify.
+        trust_bundle_manager.refresh_trust_bundle().await.unwrap();
+        assert!(updates.try_recv().is_err());
+
+        trust_bundle_manager.refresh_trust_bundle().await.unwrap();
+        assert_eq!(updates.try_recv().unwrap(), rotated_trust_bundle);
+    }
+
+    #[tokio::test]
+    async fn refresh_trust_bundle_caches_federated_bundles() {
+        let mut mock_client = MockClient::new();
+
+        let mut federated_bundle = get_trust_bundle();
+        federated_bundle.trust_domain = "cloud.contoso.com".to_string();
+        let federated_bundle_copy = federated_bundle.clone();
+        mock_client.expect_get_trust_bundle().return_once(move |_| {
+            Ok(get_trust_bundle::Response {
+                trust_bundle: get_trust_bundle(),
+                federated_bundles: vec![federated_bundle_copy],
+            })
+        });
+
+        let trust_bundle_manager =
+            TrustBundleManager::new(Arc::new(mock_client), get_trust_bundle());
+        let mut updates = trust_bundle_manager.subscribe();
+        assert!(trust_bundle_manager
+            .get_cached_federated_bundles()
+            .await
+            .is_empty());
+
+        // Only the federated bundles changed, the subscribers are notified too.
+        trust_bundle_manager.refresh_trust_bundle().await.unwrap();
+        assert_eq!(updates.try_recv().unwrap(), get_trust_bundle());
+        assert_eq!(
+            trust_bundle_manager.get_cached_federated_bundles().await,
+            vec![federated_bundle]
+        );
+    }
+
+    #[tokio::test]
+    async fn watch_trust_bundle_caches_updates() {
+        let mut mock_client = MockClient::new();
+
+        let mut expected_trust_bundle = get_trust_bundle();
+        expected_trust_bundle.jwt_key_set.keys[0].x = "1234".to_string();
+        let expected_trust_bundle_copy = expected_trust_bundle.clone();
+        mock_client
+            .expect_watch_trust_bundle()
+            .return_once(move |_| {
+                let updates: Vec<Result<_, Box<dyn std::error::Error + Send>>> = vec![
+                    Ok(get_trust_bundle::Response {
+                        trust_bundle: expected_trust_bundle_copy,
+                        federated_bundles: Vec::new(),
+                    }),
+                    Err(
+                        Box::new(spiffe_server_client::http::error::Error::Connector(
+                            "dummy".to_string(),
+                        )) as _,
+                    ),
+                ];
+                Ok(stream::iter(updates).boxed())
+            });
+
+        let trust_bundle_manager =
+            TrustBundleManager::new(Arc::new(mock_client), get_trust_bundle());
+
+        let error = trust_bundle_manager.watch_trust_bundle().await.unwrap_err();
+        assert_matches!(error, Error::TrustBundle(_));
+
+        // The updates received before the watch failed are kept.
+        let trust_bundle = trust_bundle_manager.get_cached_trust_bundle().await;
+        assert_eq!(
+            trust_bundle.jwt_key_set.keys[0].x,
+            expected_trust_bundle.jwt_key_set.keys[0].x
+        );
+    }
+
     fn get_trust_bundle() -> TrustBundle {
         let jwk = JWK {
             x: "MjE2NDE3NTMwMTgxMjY5Njc2MTE3MzAwODU4NjY4Mjg2MDU4MTQ2OTY3ODY0MjU2MDA1MzI0NTA0ODQyNTcxMTcyMzI4NjM1MjgxMjM".to_string(),
             y: "MzU1NjA3MjI0Mjc5MzAxMjYzMzkxNDg5NjAxMDA2NjMzNDE1NTA2MzQzMTQ5MDIxNzQxNTI0MDMyMzk0ODA1NjM2NjE0MTU0NjMyNzI".to_string(),
             kty: Kty::EC,
-            crv: Crv::P256,
+            crv: Some(Crv::P256),
+            n: String::new(),
+            e: String::new(),
             kid: "kid".to_string(),
             key_use: KeyUse::JWTSVID,
         };
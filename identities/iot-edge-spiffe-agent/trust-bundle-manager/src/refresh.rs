@@ -0,0 +1,179 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Periodic refresh of the cached trust bundle. The period is the refresh hint of the JWT key set, read
+// again before every refresh so a new hint from the server is followed. A random jitter takes up to a
+// tenth off every delay so the agents of a cluster do not all refresh at once, and failed refreshes are
+// retried after 1 second, then with a delay doubling up to 1 minute.
+
+use std::{sync::Arc, time::Duration};
+
+use futures_util::{future, pin_mut};
+use log::{error, info};
+use tokio::{sync::Notify, time};
+
+use crate::TrustBundleManager;
+
+// Period used when the server sends no refresh hint.
+pub const DEFAULT_REFRESH_PERIOD: Duration = Duration::from_secs(300);
+pub const MIN_RETRY_DELAY: Duration = Duration::from_secs(1);
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+// Largest fraction of a delay taken off by the jitter.
+const MAX_JITTER: f64 = 0.1;
+
+// Time to wait before the next refresh. `jitter` is the fraction of MAX_JITTER taken off, between 0 and 1.
+// Retries never wait longer than the refresh period.
+#[must_use]
+pub fn next_delay(refresh_hint_secs: u64, consecutive_failures: u32, jitter: f64) -> Duration {
+    let period = if refresh_hint_secs == 0 {
+        DEFAULT_REFRESH_PERIOD
+    } else {
+        Duration::from_secs(refresh_hint_secs)
+    };
+
+    let delay = if consecutive_failures == 0 {
+        period
+    } else {
+        let exponent = (consecutive_failures - 1).min(16);
+        MIN_RETRY_DELAY
+            .checked_mul(1 << exponent)
+            .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY))
+            .min(period)
+    };
+
+    let jitter = if jitter > 0.0 { jitter.min(1.0) } else { 0.0 };
+
+    delay - delay.mul_f64(MAX_JITTER * jitter)
+}
+
+#[must_use]
+pub fn random_jitter() -> f64 {
+    rand::random()
+}
+
+// Refreshes the trust bundle until `shutdown` is notified.
+pub async fn run(trust_bundle_manager: Arc<TrustBundleManager>, shutdown: Arc<Notify>) {
+    info!("Starting Trust Bundle manager refresh task");
+    let mut consecutive_failures = 0;
+
+    loop {
+        let refresh_hint = trust_bundle_manager
+            .get_cached_trust_bundle()
+            .await
+            .jwt_key_set
+            .spiffe_refresh_hint;
+        let delay = next_delay(refresh_hint, consecutive_failures, random_jitter());
+
+        let wait_shutdown = shutdown.notified();
+        let wait_tick = time::sleep(delay);
+
+        pin_mut!(wait_shutdown);
+        pin_mut!(wait_tick);
+
+        if let future::Either::Left(_) = future::select(wait_shutdown, wait_tick).await {
+            info!("Closing Trust Bundle manager refresh task");
+            break;
+        }
+
+        match trust_bundle_manager.refresh_trust_bundle().await {
+            Ok(()) => {
+                consecutive_failures = 0;
+                info!("Fetch new trust bundle");
+            }
+            Err(err) => {
+                consecutive_failures = consecutive_failures.saturating_add(1);
+                error!("{}, {} consecutive failures", err, consecutive_failures);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core_objects::{JWKSet, TrustBundle};
+    use server_agent_api::get_trust_bundle;
+    use spiffe_server_client::MockClient;
+
+    use super::*;
+
+    #[test]
+    fn next_delay_follows_refresh_hint() {
+        assert_eq!(next_delay(30, 0, 0.0), Duration::from_secs(30));
+        assert_eq!(next_delay(0, 0, 0.0), DEFAULT_REFRESH_PERIOD);
+        assert_eq!(next_delay(30, 0, 1.0), Duration::from_secs(27));
+        assert_eq!(next_delay(30, 0, 2.0), Duration::from_secs(27));
+        assert_eq!(next_delay(30, 0, -1.0), Duration::from_secs(30));
+
+        let jitter = random_jitter();
+        assert!((0.0..=1.0).contains(&jitter));
+    }
+
+    #[test]
+    fn next_delay_backs_off() {
+        assert_eq!(next_delay(3600, 1, 0.0), MIN_RETRY_DELAY);
+        assert_eq!(next_delay(3600, 2, 0.0), MIN_RETRY_DELAY * 2);
+        assert_eq!(next_delay(3600, 4, 0.0), MIN_RETRY_DELAY * 8);
+        assert_eq!(next_delay(3600, 10, 0.0), MAX_RETRY_DELAY);
+        assert_eq!(next_delay(3600, u32::MAX, 0.0), MAX_RETRY_DELAY);
+
+        // Retries are not slower than the refreshes.
+        assert_eq!(next_delay(5, 10, 0.0), Duration::from_secs(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn run_retries_failed_refresh() {
+        let mut mock_client = MockClient::new();
+        let shutdown = Arc::new(Notify::new());
+
+        let mut refreshed_trust_bundle = trust_bundle();
+        refreshed_trust_bundle.jwt_key_set.spiffe_sequence_number = 1;
+        let refreshed_trust_bundle_copy = refreshed_trust_bundle.clone();
+        let shutdown_copy = shutdown.clone();
+        let mut failed = false;
+        mock_client
+            .expect_get_trust_bundle()
+            .times(2)
+            .returning(move |_| {
+                if !failed {
+                    failed = true;
+                    return Err(Box::new(
+                        spiffe_server_client::http::error::Error::Connector("dummy".to_string()),
+                    ));
+                }
+
+                // The task stops before its next refresh.
+                shutdown_copy.notify_one();
+                Ok(get_trust_bundle::Response {
+                    trust_bundle: refreshed_trust_bundle_copy.clone(),
+                    federated_bundles: Vec::new(),
+                })
+            });
+
+        let trust_bundle_manager = Arc::new(TrustBundleManager::new(
+            Arc::new(mock_client),
+            trust_bundle(),
+        ));
+
+        run(trust_bundle_manager.clone(), shutdown).await;
+
+        assert_eq!(
+            trust_bundle_manager.get_cached_trust_bundle().await,
+            refreshed_trust_bundle
+        );
+    }
+
+    fn trust_bundle() -> TrustBundle {
+        TrustBundle {
+            trust_domain: "trust_domain".to_string(),
+            jwt_key_set: JWKSet {
+                keys: Vec::new(),
+                spiffe_refresh_hint: 30,
+                spiffe_sequence_number: 0,
+            },
+            x509_key_set: JWKSet {
+                keys: Vec::new(),
+                spiffe_refresh_hint: 0,
+                spiffe_sequence_number: 0,
+            },
+        }
+    }
+}
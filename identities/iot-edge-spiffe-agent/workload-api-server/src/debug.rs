@@ -0,0 +1,182 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// What the agent did recently, listed by its debug endpoint for field debugging of the devices: the
+// workloads it attested, the JWT-SVIDs issued to them that did not expire yet, and the last attestation
+// errors. Shared by the servers of every listener of the agent. Only the last entries are kept, so busy
+// nodes do not grow the state without bounds.
+
+use std::{
+    collections::{BTreeSet, VecDeque},
+    sync::Mutex,
+};
+
+const MAX_WORKLOADS: usize = 256;
+const MAX_JWT_SVIDS: usize = 256;
+const MAX_ATTESTATION_ERRORS: usize = 32;
+
+// Times are in seconds since epoch.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct AttestedWorkload {
+    pub pid: u32,
+    pub uid: u32,
+    pub pod_uid: Option<String>,
+    pub selectors: BTreeSet<String>,
+    pub attested_at: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct IssuedJWTSVID {
+    pub spiffe_id: String,
+    pub audiences: Vec<String>,
+    // Selectors of the workload it was issued to.
+    pub selectors: BTreeSet<String>,
+    pub issued_at: u64,
+    pub expires_at: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct AttestationError {
+    pub pid: u32,
+    pub error: String,
+    pub failed_at: u64,
+}
+
+// Oldest entries first.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize)]
+pub struct DebugSnapshot {
+    pub workloads: Vec<AttestedWorkload>,
+    pub jwt_svids: Vec<IssuedJWTSVID>,
+    pub attestation_errors: Vec<AttestationError>,
+}
+
+#[derive(Default)]
+struct Entries {
+    workloads: VecDeque<AttestedWorkload>,
+    jwt_svids: VecDeque<IssuedJWTSVID>,
+    attestation_errors: VecDeque<AttestationError>,
+}
+
+#[derive(Default)]
+pub struct DebugState {
+    entries: Mutex<Entries>,
+}
+
+impl DebugState {
+    // A process attested again replaces its previous attestation.
+    pub(crate) fn attested(&self, workload: AttestedWorkload) {
+        let mut entries = self.entries.lock().unwrap();
+
+        entries
+            .workloads
+            .retain(|attested| attested.pid != workload.pid);
+        push_bounded(&mut entries.workloads, workload, MAX_WORKLOADS);
+    }
+
+    pub(crate) fn issued(&self, jwt_svid: IssuedJWTSVID) {
+        let mut entries = self.entries.lock().unwrap();
+
+        push_bounded(&mut entries.jwt_svids, jwt_svid, MAX_JWT_SVIDS);
+    }
+
+    pub(crate) fn attestation_failed(&self, error: AttestationError) {
+        let mut entries = self.entries.lock().unwrap();
+
+        push_bounded(
+            &mut entries.attestation_errors,
+            error,
+            MAX_ATTESTATION_ERRORS,
+        );
+    }
+
+    // The expired JWT-SVIDs are dropped along the way.
+    #[must_use]
+    pub fn snapshot(&self, now: u64) -> DebugSnapshot {
+        let mut entries = self.entries.lock().unwrap();
+
+        entries
+            .jwt_svids
+            .retain(|jwt_svid| jwt_svid.expires_at > now);
+
+        DebugSnapshot {
+            workloads: entries.workloads.iter().cloned().collect(),
+            jwt_svids: entries.jwt_svids.iter().cloned().collect(),
+            attestation_errors: entries.attestation_errors.iter().cloned().collect(),
+        }
+    }
+}
+
+fn push_bounded<T>(entries: &mut VecDeque<T>, entry: T, max: usize) {
+    if entries.len() >= max {
+        entries.pop_front();
+    }
+
+    entries.push_back(entry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workload(pid: u32, attested_at: u64) -> AttestedWorkload {
+        AttestedWorkload {
+            pid,
+            uid: 0,
+            pod_uid: None,
+            selectors: BTreeSet::new(),
+            attested_at,
+        }
+    }
+
+    fn jwt_svid(spiffe_id: &str, expires_at: u64) -> IssuedJWTSVID {
+        IssuedJWTSVID {
+            spiffe_id: spiffe_id.to_string(),
+            audiences: vec!["audience".to_string()],
+            selectors: BTreeSet::new(),
+            issued_at: 0,
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn snapshot_drops_expired_jwt_svids() {
+        let debug_state = DebugState::default();
+        debug_state.issued(jwt_svid("spiffe://iotedge/a", 100));
+        debug_state.issued(jwt_svid("spiffe://iotedge/b", 200));
+
+        assert_eq!(debug_state.snapshot(99).jwt_svids.len(), 2);
+        assert_eq!(
+            debug_state.snapshot(100).jwt_svids,
+            vec![jwt_svid("spiffe://iotedge/b", 200)]
+        );
+    }
+
+    #[test]
+    fn attested_keeps_last_attestation_of_each_process() {
+        let debug_state = DebugState::default();
+        debug_state.attested(workload(1, 10));
+        debug_state.attested(workload(2, 10));
+        debug_state.attested(workload(1, 20));
+
+        assert_eq!(
+            debug_state.snapshot(0).workloads,
+            vec![workload(2, 10), workload(1, 20)]
+        );
+    }
+
+    #[test]
+    fn entries_are_bounded() {
+        let debug_state = DebugState::default();
+        for pid in 0..=u32::try_from(MAX_ATTESTATION_ERRORS).unwrap() {
+            debug_state.attestation_failed(AttestationError {
+                pid,
+                error: "error".to_string(),
+                failed_at: 0,
+            });
+        }
+
+        let attestation_errors = debug_state.snapshot(0).attestation_errors;
+        assert_eq!(attestation_errors.len(), MAX_ATTESTATION_ERRORS);
+        // The oldest error was dropped.
+        assert_eq!(attestation_errors[0].pid, 1);
+    }
+}
@@ -1,7 +1,8 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use std::num::TryFromIntError;
+use std::{net::IpAddr, num::TryFromIntError};
 
+use server_agent_api::create_workload_jwts::DeniedIdentity;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -24,10 +25,57 @@ pub enum Error {
     ValidateJWTSVIDs(jwt_svid_validator::error::Error),
     #[error("Error could not serialize identity {0}")]
     SerdeSerializeIdentity(serde_json::Error),
+    #[error("Workload API listener is restarting, reconnect to the workload API socket")]
+    ListenerClosing,
+    #[error("Stream reached its maximum lifetime, reconnect to be attested again")]
+    StreamLifetimeExceeded,
+    #[error("Stream was idle for too long, reconnect to receive updates")]
+    StreamIdle,
+    #[error("Matching entries exist but issuance was denied: {}", format_denied(.0))]
+    IssuanceDenied(Vec<DeniedIdentity>),
+    #[error("Missing security header {}", workload_api::SECURITY_HEADER)]
+    MissingSecurityHeader,
+    #[error("At least one audience is required")]
+    MissingAudience,
+    #[error("No identity matches the workload")]
+    NoIdentity,
+    #[error("No selectors are configured for TCP peer {0}")]
+    UnknownTcpPeer(IpAddr),
+    #[error("Too many requests from the workload, the limit is {0} requests per second")]
+    RateLimited(u32),
 }
 
+fn format_denied(denied: &[DeniedIdentity]) -> String {
+    denied
+        .iter()
+        .map(|denied| format!("{} ({})", denied.spiffe_id, denied.reason))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Codes of the SPIFFE workload API: clients retry on UNAVAILABLE, and treat PERMISSION_DENIED as the
+// workload having no identity.
 impl From<Error> for tonic::Status {
     fn from(error: Error) -> Self {
-        tonic::Status::unknown(format!("{}", error))
+        match error {
+            Error::ListenerClosing
+            | Error::StreamLifetimeExceeded
+            | Error::StreamIdle
+            | Error::TrustBundleResponse(_)
+            | Error::WorkloadAttestation(_)
+            | Error::NodeAttestation(_)
+            | Error::CreateJWTSVIDs(_) => tonic::Status::unavailable(format!("{}", error)),
+            Error::IssuanceDenied(_) | Error::NoIdentity | Error::UnknownTcpPeer(_) => {
+                tonic::Status::permission_denied(format!("{}", error))
+            }
+            Error::MissingSecurityHeader | Error::MissingAudience | Error::ValidateJWTSVIDs(_) => {
+                tonic::Status::invalid_argument(format!("{}", error))
+            }
+            Error::RateLimited(_) => tonic::Status::resource_exhausted(format!("{}", error)),
+            Error::SerdeConvertToVec(_)
+            | Error::UdsClientPID
+            | Error::NegativePID(_)
+            | Error::SerdeSerializeIdentity(_) => tonic::Status::internal(format!("{}", error)),
+        }
     }
 }
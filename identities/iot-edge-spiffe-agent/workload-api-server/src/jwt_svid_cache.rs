@@ -0,0 +1,298 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// JWT-SVIDs issued to a process, cached until half of their lifetime so the same request does not go
+// through the kubernetes API and the server again. With pod identity pinning, they are looked up before
+// the attestation: PIDs are recycled when pods restart, a cached token is only returned once the process
+// is checked to still belong to the pod it was issued to.
+// Without pinning, they are looked up once the workload is attested, by caller along with its pod and
+// selectors. A recycled PID only gets them back when it has the same identity, and a JWT-SVID is never
+// handed to another process or TCP peer.
+// JWT-SVIDs with a jti claim are never cached: the services they are presented to may reject a jti they
+// already saw, each request needs a new one.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    hash::Hash,
+    sync::Mutex,
+};
+
+use core_objects::JWTSVIDCompact;
+use workload_api::generated::Jwtsvid;
+use zeroize::Zeroize;
+
+use crate::rate_limit::CallerKey;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pid: u32,
+    spiffe_id: String,
+    audiences: Vec<String>,
+}
+
+impl CacheKey {
+    #[must_use]
+    pub fn new(pid: u32, spiffe_id: &str, audiences: &[String]) -> Self {
+        CacheKey {
+            pid,
+            spiffe_id: spiffe_id.to_string(),
+            audiences: audiences.to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct WorkloadCacheKey {
+    caller: CallerKey,
+    pod_uid: Option<String>,
+    selectors: BTreeSet<String>,
+    spiffe_id: String,
+    audiences: Vec<String>,
+}
+
+impl WorkloadCacheKey {
+    #[must_use]
+    pub(crate) fn new(
+        caller: CallerKey,
+        pod_uid: Option<String>,
+        selectors: &BTreeSet<String>,
+        spiffe_id: &str,
+        audiences: &[String],
+    ) -> Self {
+        WorkloadCacheKey {
+            caller,
+            pod_uid,
+            selectors: selectors.clone(),
+            spiffe_id: spiffe_id.to_string(),
+            audiences: audiences.to_vec(),
+        }
+    }
+}
+
+// Claims of a JWT-SVID looked at before caching it, the token was just received from the server and is not
+// validated.
+#[derive(serde::Deserialize)]
+struct JWTIDClaim {
+    jti: Option<String>,
+}
+
+fn has_jwt_id(token: &str) -> bool {
+    token
+        .split('.')
+        .nth(1)
+        .and_then(|claims| base64::decode_config(claims, base64::URL_SAFE_NO_PAD).ok())
+        .and_then(|claims| serde_json::from_slice::<JWTIDClaim>(&claims).ok())
+        .map_or(false, |claims| claims.jti.is_some())
+}
+
+struct CachedJWTSVIDs {
+    pod_uid: String,
+    svids: Vec<Jwtsvid>,
+    refresh_at: u64,
+}
+
+// The tokens stay in the cache for half of their lifetime, they are wiped from memory when evicted instead of
+// being left behind in freed memory.
+impl Drop for CachedJWTSVIDs {
+    fn drop(&mut self) {
+        for svid in &mut self.svids {
+            svid.svid.zeroize();
+        }
+    }
+}
+
+// Also keyed by container for the JWT-SVIDs prefetched at startup, see `warm_up`.
+pub struct JWTSVIDCache<K = CacheKey> {
+    entries: Mutex<HashMap<K, CachedJWTSVIDs>>,
+}
+
+impl<K> Default for JWTSVIDCache<K> {
+    fn default() -> Self {
+        JWTSVIDCache {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K: Eq + Hash> JWTSVIDCache<K> {
+    // Returns the pod UID the SVIDs were issued to, along with the SVIDs.
+    pub fn get(&self, key: &K, now: u64) -> Option<(String, Vec<Jwtsvid>)> {
+        let mut entries = self.entries.lock().unwrap();
+
+        match entries.get(key) {
+            Some(cached) if cached.refresh_at > now => {
+                Some((cached.pod_uid.clone(), cached.svids.clone()))
+            }
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(
+        &self,
+        key: K,
+        pod_uid: String,
+        svids: Vec<Jwtsvid>,
+        jwt_svids: &[JWTSVIDCompact],
+        now: u64,
+    ) {
+        let refresh_at = jwt_svids
+            .iter()
+            .map(|jwt_svid| {
+                jwt_svid.issued_at + jwt_svid.expiry.saturating_sub(jwt_svid.issued_at) / 2
+            })
+            .min();
+
+        // Nothing was issued, there is nothing to cache.
+        let refresh_at = match refresh_at {
+            Some(refresh_at) => refresh_at,
+            None => return,
+        };
+        if jwt_svids.iter().any(|jwt_svid| has_jwt_id(&jwt_svid.token)) {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        // The PIDs of processes that are gone are never requested again, drop them with the
+        // expired entries.
+        entries.retain(|_, cached| cached.refresh_at > now);
+        entries.insert(
+            key,
+            CachedJWTSVIDs {
+                pod_uid,
+                svids,
+                refresh_at,
+            },
+        );
+    }
+
+    pub fn remove(&self, key: &K) {
+        let mut entries = self.entries.lock().unwrap();
+
+        entries.remove(key);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let entries = self.entries.lock().unwrap();
+
+        entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jwt_svid(issued_at: u64, expiry: u64) -> JWTSVIDCompact {
+        JWTSVIDCompact {
+            token: "token".to_string(),
+            spiffe_id: "spiffe_id".to_string(),
+            expiry,
+            issued_at,
+        }
+    }
+
+    #[test]
+    fn get_until_half_lifetime() {
+        let cache = JWTSVIDCache::default();
+        let key = CacheKey::new(1, "", &["audience".to_string()]);
+
+        cache.insert(
+            key.clone(),
+            "pod_uid".to_string(),
+            vec![Jwtsvid::default()],
+            &[jwt_svid(100, 200), jwt_svid(100, 300)],
+            100,
+        );
+
+        let (pod_uid, svids) = cache.get(&key, 149).unwrap();
+        assert_eq!(pod_uid, "pod_uid");
+        assert_eq!(svids.len(), 1);
+
+        assert!(cache.get(&key, 150).is_none());
+        assert!(cache.get(&key, 100).is_none());
+    }
+
+    #[test]
+    fn jwt_id_not_cached() {
+        let cache = JWTSVIDCache::default();
+        let key = CacheKey::new(1, "", &["audience".to_string()]);
+        let claims = base64::encode_config(
+            r#"{"sub":"spiffe://trust_domain/path","jti":"id"}"#,
+            base64::URL_SAFE_NO_PAD,
+        );
+        let mut jwt_svid = jwt_svid(100, 200);
+        jwt_svid.token = format!("header.{}.signature", claims);
+
+        cache.insert(
+            key.clone(),
+            "pod_uid".to_string(),
+            vec![Jwtsvid::default()],
+            &[jwt_svid],
+            100,
+        );
+        assert!(cache.get(&key, 100).is_none());
+    }
+
+    #[test]
+    fn get_other_pid() {
+        let cache = JWTSVIDCache::default();
+        let key = CacheKey::new(1, "", &["audience".to_string()]);
+
+        cache.insert(
+            key,
+            "pod_uid".to_string(),
+            vec![Jwtsvid::default()],
+            &[jwt_svid(100, 200)],
+            100,
+        );
+
+        let other_key = CacheKey::new(2, "", &["audience".to_string()]);
+        assert!(cache.get(&other_key, 100).is_none());
+    }
+
+    #[test]
+    fn get_other_caller() {
+        let cache = JWTSVIDCache::default();
+        let selectors = vec!["UNIXUID:1000".to_string()].into_iter().collect();
+        let key = WorkloadCacheKey::new(
+            CallerKey::Process { pid: 1, uid: 1000 },
+            None,
+            &selectors,
+            "",
+            &["audience".to_string()],
+        );
+
+        cache.insert(
+            key.clone(),
+            String::new(),
+            vec![Jwtsvid::default()],
+            &[jwt_svid(100, 200)],
+            100,
+        );
+        assert!(cache.get(&key, 100).is_some());
+
+        // Same selectors, but another process: the JWT-SVIDs are not shared.
+        let other_process = WorkloadCacheKey::new(
+            CallerKey::Process { pid: 2, uid: 1000 },
+            None,
+            &selectors,
+            "",
+            &["audience".to_string()],
+        );
+        assert!(cache.get(&other_process, 100).is_none());
+
+        // Same process, but its PID was recycled by a workload with other selectors.
+        let other_selectors = vec!["UNIXUID:1001".to_string()].into_iter().collect();
+        let other_workload = WorkloadCacheKey::new(
+            CallerKey::Process { pid: 1, uid: 1000 },
+            None,
+            &other_selectors,
+            "",
+            &["audience".to_string()],
+        );
+        assert!(cache.get(&other_workload, 100).is_none());
+    }
+}
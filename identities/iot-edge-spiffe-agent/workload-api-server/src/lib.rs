@@ -10,40 +10,79 @@
     clippy::too_many_lines
 )]
 
+pub mod debug;
 mod error;
+mod jwt_svid_cache;
+pub mod limits;
+pub mod metrics;
+pub mod rate_limit;
+pub mod streams;
+pub mod tcp;
 pub mod unix_stream;
+pub mod warm_up;
 
+use build_info::BuildInfo;
 use core::pin::Pin;
+use core_objects::{get_epoch_time, TrustBundle, JWTSVID};
+use debug::{AttestationError, AttestedWorkload, DebugState, IssuedJWTSVID};
 use error::Error;
-use futures_util::Stream;
+use futures_util::{future, pin_mut, Stream, StreamExt};
+use jwt_svid_cache::{CacheKey, JWTSVIDCache, WorkloadCacheKey};
 use jwt_svid_validator::JWTSVIDValidator;
-use log::{debug, info};
+use log::{debug, info, warn};
 use node_attestation_agent::NodeAttestation;
+use rate_limit::{CallerKey, RateLimiter};
 use server_agent_api::{create_workload_jwts, get_trust_bundle};
 use spiffe_server_client::Client;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    net::IpAddr,
+    sync::Arc,
+    time::Instant,
+};
+use streams::{StreamLimits, StreamMetrics};
+use tcp::TcpSelectors;
+use tokio::sync::{broadcast, watch};
 use tonic::{Request, Response};
 use trust_bundle_manager::TrustBundleManager;
+use warm_up::PrefetchCache;
 use workload_api::generated::{
     spiffe_workload_api_server::SpiffeWorkloadApi, JwtBundlesRequest, JwtBundlesResponse, Jwtsvid,
     JwtsvidRequest, JwtsvidResponse, ValidateJwtsvidRequest, ValidateJwtsvidResponse,
     X509svidRequest, X509svidResponse,
 };
-use workload_attestation::WorkloadAttestation;
+use workload_attestation::{Caller, WorkloadAttestation, WorkloadAttributes};
+use zeroize::Zeroizing;
 
-use crate::unix_stream::UdsConnectInfo;
+use crate::{metrics::Metrics, unix_stream::UdsConnectInfo};
 
 type X509ResponseStream =
     Pin<Box<dyn Stream<Item = Result<X509svidResponse, tonic::Status>> + Send>>;
 type JWTResponseStream =
     Pin<Box<dyn Stream<Item = Result<JwtBundlesResponse, tonic::Status>> + Send>>;
 
+// Seconds between the refreshes of the trust bundle triggered by JWT-SVIDs signed with an unknown key.
+const UNKNOWN_KID_REFRESH_INTERVAL: u64 = 5;
+
 pub struct WorkloadAPIServer {
     spiffe_server_client: Arc<dyn Client>,
     workload_attestation: Arc<dyn WorkloadAttestation>,
     node_attestation: Arc<dyn NodeAttestation>,
     trust_bundle_manager: Arc<TrustBundleManager>,
     jwt_svid_validator: Arc<dyn JWTSVIDValidator>,
+    shutdown_signal: watch::Receiver<bool>,
+    pod_identity_pinning: bool,
+    jwt_svid_caching: bool,
+    jwt_svid_cache: JWTSVIDCache,
+    workload_jwt_svid_cache: JWTSVIDCache<WorkloadCacheKey>,
+    prefetch_cache: Arc<PrefetchCache>,
+    stream_limits: StreamLimits,
+    stream_metrics: Arc<StreamMetrics>,
+    metrics: Arc<Metrics>,
+    agent_build: Option<BuildInfo>,
+    tcp_selectors: TcpSelectors,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    debug_state: Option<Arc<DebugState>>,
 }
 
 impl WorkloadAPIServer {
@@ -55,34 +94,425 @@ impl WorkloadAPIServer {
         trust_bundle_manager: Arc<TrustBundleManager>,
         jwt_svid_validator: Arc<dyn JWTSVIDValidator>,
     ) -> Self {
+        // Without a shutdown signal the sender is dropped right away and the listener is never drained.
+        let (_, shutdown_signal) = watch::channel(false);
+
         Self {
             spiffe_server_client,
             workload_attestation,
             node_attestation,
             trust_bundle_manager,
             jwt_svid_validator,
+            shutdown_signal,
+            pod_identity_pinning: false,
+            jwt_svid_caching: true,
+            jwt_svid_cache: JWTSVIDCache::default(),
+            workload_jwt_svid_cache: JWTSVIDCache::default(),
+            prefetch_cache: Arc::new(PrefetchCache::default()),
+            stream_limits: StreamLimits::default(),
+            stream_metrics: Arc::new(StreamMetrics::default()),
+            metrics: Arc::new(Metrics::default()),
+            agent_build: None,
+            tcp_selectors: TcpSelectors::default(),
+            rate_limiter: None,
+            debug_state: None,
+        }
+    }
+
+    // Set to true when the listener serving this server is being replaced. New requests are refused
+    // and open streams are closed with UNAVAILABLE so clients reconnect on the new socket.
+    #[must_use]
+    pub fn with_shutdown_signal(mut self, shutdown_signal: watch::Receiver<bool>) -> Self {
+        self.shutdown_signal = shutdown_signal;
+
+        self
+    }
+
+    // Embed the pod UID in the issued JWT-SVIDs, and cache them for the calling process. A cached
+    // JWT-SVID is only returned while the process still belongs to the same pod.
+    #[must_use]
+    pub fn with_pod_identity_pinning(mut self, pod_identity_pinning: bool) -> Self {
+        self.pod_identity_pinning = pod_identity_pinning;
+
+        self
+    }
+
+    // Cache the JWT-SVIDs issued to each process until half of their lifetime, see `jwt_svid_cache`. When
+    // disabled, every request gets new JWT-SVIDs from the server. Enabled by default.
+    #[must_use]
+    pub fn with_jwt_svid_caching(mut self, jwt_svid_caching: bool) -> Self {
+        self.jwt_svid_caching = jwt_svid_caching;
+
+        self
+    }
+
+    // Report the build of the agent to the server along with each request, the server can turn it
+    // into selectors of the agent.
+    #[must_use]
+    pub fn with_agent_build(mut self, agent_build: BuildInfo) -> Self {
+        self.agent_build = Some(agent_build);
+
+        self
+    }
+
+    // JWT-SVIDs fetched by `warm_up`, shared by the servers of every listener of the agent.
+    #[must_use]
+    pub fn with_prefetch_cache(mut self, prefetch_cache: Arc<PrefetchCache>) -> Self {
+        self.prefetch_cache = prefetch_cache;
+
+        self
+    }
+
+    // Close the streams opened by workloads after a maximum lifetime or when they are idle, see `streams`.
+    #[must_use]
+    pub fn with_stream_limits(mut self, stream_limits: StreamLimits) -> Self {
+        self.stream_limits = stream_limits;
+
+        self
+    }
+
+    // Metrics of the streams, shared by the servers of every listener of the agent.
+    #[must_use]
+    pub fn with_stream_metrics(mut self, stream_metrics: Arc<StreamMetrics>) -> Self {
+        self.stream_metrics = stream_metrics;
+
+        self
+    }
+
+    // Metrics of the requests, registered by the agent for its metrics endpoint, see `metrics`.
+    #[must_use]
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+
+        self
+    }
+
+    // Selectors of the workloads connecting over TCP, see `tcp`. Without them, TCP peers get no identity.
+    #[must_use]
+    pub fn with_tcp_selectors(mut self, tcp_selectors: TcpSelectors) -> Self {
+        self.tcp_selectors = tcp_selectors;
+
+        self
+    }
+
+    // Rate limit of `FetchJWTSVID` per caller, see `rate_limit`. Shared by the servers of every listener
+    // of the agent. Not limited when not set.
+    #[must_use]
+    pub fn with_rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+
+        self
+    }
+
+    // Record the attestations and the issued JWT-SVIDs for the debug endpoint of the agent, see `debug`.
+    // Shared by the servers of every listener of the agent.
+    #[must_use]
+    pub fn with_debug_state(mut self, debug_state: Arc<DebugState>) -> Self {
+        self.debug_state = Some(debug_state);
+
+        self
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        *self.shutdown_signal.borrow()
+    }
+
+    async fn get_cached_jwtsvids(&self, cache_key: &CacheKey, pid: u32) -> Option<Vec<Jwtsvid>> {
+        let (pod_uid, svids) = self.jwt_svid_cache.get(cache_key, get_epoch_time())?;
+
+        match self.workload_attestation.get_pod_uid(pid).await {
+            Ok(current_pod_uid) if current_pod_uid == pod_uid => Some(svids),
+            Ok(current_pod_uid) => {
+                info!(
+                    "Process {} moved from pod {} to pod {}, attesting it again",
+                    pid, pod_uid, current_pod_uid
+                );
+                self.jwt_svid_cache.remove(cache_key);
+                None
+            }
+            Err(err) => {
+                warn!("Could not check the pod of process {}: {}", pid, err);
+                self.jwt_svid_cache.remove(cache_key);
+                None
+            }
+        }
+    }
+
+    async fn validate(
+        &self,
+        jwt_svid_compact: &str,
+        audience: &str,
+    ) -> Result<JWTSVID, jwt_svid_validator::error::Error> {
+        let trust_bundle = self.trust_bundle_manager.get_cached_trust_bundle().await;
+        let mut trust_bundles: BTreeMap<_, _> = self
+            .trust_bundle_manager
+            .get_cached_federated_bundles()
+            .await
+            .into_iter()
+            .map(|federated_bundle| (federated_bundle.trust_domain.clone(), federated_bundle))
+            .collect();
+        // Inserted last, a federated bundle never replaces the bundle of the agent trust domain.
+        trust_bundles.insert(trust_bundle.trust_domain.clone(), trust_bundle);
+
+        self.jwt_svid_validator
+            .validate(jwt_svid_compact, &trust_bundles, audience)
+            .await
+    }
+
+    fn check_rate_limit(&self, caller: CallerKey) -> Result<(), Error> {
+        match &self.rate_limiter {
+            Some(rate_limiter) if !rate_limiter.check(caller, Instant::now()) => {
+                debug!("Rate limit exceeded by {:?}", caller);
+                Err(Error::RateLimited(rate_limiter.limits().requests_per_sec))
+            }
+            _ => Ok(()),
         }
     }
 
     async fn fetch_jwtsvid_inner(
         &self,
         request: Request<JwtsvidRequest>,
-        pid: u32,
+        caller: Caller,
     ) -> Result<Response<JwtsvidResponse>, tonic::Status> {
+        let pid = caller.pid;
+        self.check_rate_limit(CallerKey::Process {
+            pid,
+            uid: caller.uid,
+        })?;
         let jwt_svid_request = request.into_inner();
         debug!("Request: {:?}", jwt_svid_request);
 
-        let workload_attributes = self
-            .workload_attestation
-            .attest_workload(pid)
+        if jwt_svid_request.audience.is_empty() {
+            return Err(Error::MissingAudience.into());
+        }
+
+        if let Some(svids) = self
+            .get_prefetched_jwtsvids(pid, &jwt_svid_request.spiffe_id, &jwt_svid_request.audience)
+            .await
+        {
+            return Ok(Response::new(JwtsvidResponse { svids }));
+        }
+
+        let cache_key = if self.jwt_svid_caching && self.pod_identity_pinning {
+            let cache_key =
+                CacheKey::new(pid, &jwt_svid_request.spiffe_id, &jwt_svid_request.audience);
+            if let Some(svids) = self.get_cached_jwtsvids(&cache_key, pid).await {
+                return Ok(Response::new(JwtsvidResponse { svids }));
+            }
+
+            Some(cache_key)
+        } else {
+            None
+        };
+
+        let uid = caller.uid;
+        let workload_attributes = match self.workload_attestation.attest_workload(caller).await {
+            Ok(workload_attributes) => workload_attributes,
+            Err(err) => {
+                self.metrics.workload_attestation_failures.inc();
+                if let Some(debug_state) = &self.debug_state {
+                    debug_state.attestation_failed(AttestationError {
+                        pid,
+                        error: err.to_string(),
+                        failed_at: get_epoch_time(),
+                    });
+                }
+
+                return Err(Error::WorkloadAttestation(err).into());
+            }
+        };
+
+        if let Some(debug_state) = &self.debug_state {
+            debug_state.attested(AttestedWorkload {
+                pid,
+                uid,
+                pod_uid: workload_attributes.pod_uid.clone(),
+                selectors: workload_attributes.selectors.clone(),
+                attested_at: get_epoch_time(),
+            });
+        }
+
+        self.issue_jwtsvids(
+            jwt_svid_request,
+            workload_attributes,
+            CallerKey::Process { pid, uid },
+            cache_key,
+        )
+        .await
+    }
+
+    async fn fetch_jwtsvid_tcp(
+        &self,
+        request: Request<JwtsvidRequest>,
+        peer: IpAddr,
+    ) -> Result<Response<JwtsvidResponse>, tonic::Status> {
+        self.check_rate_limit(CallerKey::Peer(peer))?;
+
+        let jwt_svid_request = request.into_inner();
+        debug!("Request from TCP peer {}: {:?}", peer, jwt_svid_request);
+
+        if jwt_svid_request.audience.is_empty() {
+            return Err(Error::MissingAudience.into());
+        }
+
+        let selectors = self
+            .tcp_selectors
+            .get(peer)
+            .ok_or(Error::UnknownTcpPeer(peer))?;
+        let workload_attributes = WorkloadAttributes {
+            selectors,
+            pod_uid: None,
+        };
+
+        self.issue_jwtsvids(
+            jwt_svid_request,
+            workload_attributes,
+            CallerKey::Peer(peer),
+            None,
+        )
+        .await
+    }
+
+    async fn fetch_jwt_bundles_inner(
+        &self,
+        peer: Option<IpAddr>,
+        caller: Result<Caller, Error>,
+    ) -> Result<Response<JWTResponseStream>, tonic::Status> {
+        // Subscribed first, so a rotation while the first response is fetched is not missed.
+        let mut updates = self.trust_bundle_manager.subscribe();
+
+        let response = self
+            .spiffe_server_client
+            .get_trust_bundle(get_trust_bundle::Params {
+                jwt_keys: true,
+                x509_cas: false,
+            })
             .await
-            .map_err(Error::WorkloadAttestation)?;
+            .map_err(Error::TrustBundleResponse)?;
+
+        // The entries of the workload are matched once, when the stream opens. A workload gets the bundles of new
+        // trust domains of its entries when it opens a new stream.
+        let federates_with = if response.federated_bundles.is_empty() {
+            BTreeSet::new()
+        } else {
+            self.get_federates_with(peer, caller).await?
+        };
+
+        let trust_bundle_response = jwt_bundles_response(
+            response.trust_bundle,
+            response.federated_bundles,
+            &federates_with,
+        )?;
+
+        // The stream stays open and sends the bundles again every time the agent sees them change.
+        let trust_bundle_manager = self.trust_bundle_manager.clone();
+        let stream: JWTResponseStream = Box::pin(async_stream::stream! {
+            yield Ok(trust_bundle_response);
+
+            loop {
+                let trust_bundle = match updates.recv().await {
+                    Ok(trust_bundle) => trust_bundle,
+                    // Only the last trust bundle matters.
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        trust_bundle_manager.get_cached_trust_bundle().await
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let federated_bundles = trust_bundle_manager.get_cached_federated_bundles().await;
+
+                info!("Sending rotated trust bundle");
+                yield jwt_bundles_response(trust_bundle, federated_bundles, &federates_with)
+                    .map_err(tonic::Status::from);
+            }
+        }) as _;
+        let stream = streams::with_limits(stream, self.stream_limits, self.stream_metrics.clone());
+        let stream = until_shutdown(stream, self.shutdown_signal.clone());
+
+        Ok(Response::new(stream))
+    }
+
+    // Trust domains of the bundles the workload gets, from the entries it matches. No JWT-SVID is issued.
+    async fn get_federates_with(
+        &self,
+        peer: Option<IpAddr>,
+        caller: Result<Caller, Error>,
+    ) -> Result<BTreeSet<String>, Error> {
+        let selectors = if let Some(peer) = peer {
+            self.tcp_selectors
+                .get(peer)
+                .ok_or(Error::UnknownTcpPeer(peer))?
+        } else {
+            match self.workload_attestation.attest_workload(caller?).await {
+                Ok(workload_attributes) => workload_attributes.selectors,
+                Err(err) => {
+                    self.metrics.workload_attestation_failures.inc();
+                    return Err(Error::WorkloadAttestation(err));
+                }
+            }
+        };
 
-        let attestation_token = self
-            .node_attestation
-            .get_attestation_token()
+        let attestation_token = Zeroizing::new(
+            self.node_attestation
+                .get_attestation_token()
+                .await
+                .map_err(Error::NodeAttestation)?,
+        );
+
+        let request = create_workload_jwts::Request {
+            workload_spiffe_id: None,
+            audiences: Vec::new(),
+            selectors,
+            attestation_token: attestation_token.to_string(),
+            pod_uid: None,
+            agent_build: self.agent_build.clone(),
+            prefetch_only: false,
+            bundles_only: true,
+        };
+        let response = self
+            .spiffe_server_client
+            .create_workload_jwts(request)
             .await
-            .map_err(Error::NodeAttestation)?;
+            .map_err(Error::CreateJWTSVIDs)?;
+
+        Ok(response.federates_with)
+    }
+
+    // Fetches the JWT-SVIDs of the workload from the server, or from the JWT-SVIDs cached for the caller.
+    async fn issue_jwtsvids(
+        &self,
+        jwt_svid_request: JwtsvidRequest,
+        workload_attributes: WorkloadAttributes,
+        caller: CallerKey,
+        cache_key: Option<CacheKey>,
+    ) -> Result<Response<JwtsvidResponse>, tonic::Status> {
+        // Pinned JWT-SVIDs were looked up before the attestation, by process and checked against its pod.
+        let workload_cache_key = if self.jwt_svid_caching && !self.pod_identity_pinning {
+            let workload_cache_key = WorkloadCacheKey::new(
+                caller,
+                workload_attributes.pod_uid.clone(),
+                &workload_attributes.selectors,
+                &jwt_svid_request.spiffe_id,
+                &jwt_svid_request.audience,
+            );
+            if let Some((_pod_uid, svids)) = self
+                .workload_jwt_svid_cache
+                .get(&workload_cache_key, get_epoch_time())
+            {
+                debug!("Returning cached JWT-SVIDs for {:?}", caller);
+                return Ok(Response::new(JwtsvidResponse { svids }));
+            }
+
+            Some(workload_cache_key)
+        } else {
+            None
+        };
+
+        let attestation_token = Zeroizing::new(
+            self.node_attestation
+                .get_attestation_token()
+                .await
+                .map_err(Error::NodeAttestation)?,
+        );
 
         let workload_spiffe_id = if jwt_svid_request.spiffe_id.is_empty() {
             None
@@ -90,26 +520,108 @@ impl WorkloadAPIServer {
             Some(jwt_svid_request.spiffe_id.clone())
         };
 
+        let pod_uid = if self.pod_identity_pinning {
+            workload_attributes.pod_uid
+        } else {
+            None
+        };
+
+        // Kept for the debug endpoint, the request takes them.
+        let debug_selectors = self
+            .debug_state
+            .as_ref()
+            .map(|_| workload_attributes.selectors.clone());
+        let debug_audiences = self
+            .debug_state
+            .as_ref()
+            .map(|_| jwt_svid_request.audience.clone());
+
         let request = create_workload_jwts::Request {
             workload_spiffe_id,
             audiences: jwt_svid_request.audience,
             selectors: workload_attributes.selectors,
-            attestation_token,
+            attestation_token: attestation_token.to_string(),
+            pod_uid: pod_uid.clone(),
+            agent_build: self.agent_build.clone(),
+            prefetch_only: false,
+            bundles_only: false,
         };
 
-        let svids: Vec<Jwtsvid> = self
+        let started_at = Instant::now();
+        let jwts_response = self
             .spiffe_server_client
             .create_workload_jwts(request)
             .await
-            .map_err(Error::CreateJWTSVIDs)?
+            .map_err(Error::CreateJWTSVIDs)?;
+        self.metrics
+            .create_jwt_svids_duration
+            .observe(started_at.elapsed());
+
+        for denied in &jwts_response.denied {
+            warn!(
+                spiffe_id = denied.spiffe_id.as_str();
+                "Server denied JWT-SVID for {}: {}",
+                denied.spiffe_id,
+                denied.reason
+            );
+        }
+
+        // Tell the workload an entry exists but could not be issued, rather than
+        // returning an empty list that looks like no entry matched.
+        if jwts_response.jwt_svids.is_empty() && !jwts_response.denied.is_empty() {
+            return Err(Error::IssuanceDenied(jwts_response.denied).into());
+        }
+        if jwts_response.jwt_svids.is_empty() {
+            return Err(Error::NoIdentity.into());
+        }
+
+        self.metrics
+            .jwt_svids_issued
+            .inc_by(jwts_response.jwt_svids.len() as u64);
+        if let (Some(debug_state), Some(selectors), Some(audiences)) =
+            (&self.debug_state, debug_selectors, debug_audiences)
+        {
+            for jwt_svid in &jwts_response.jwt_svids {
+                debug_state.issued(IssuedJWTSVID {
+                    spiffe_id: jwt_svid.spiffe_id.to_string(),
+                    audiences: audiences.clone(),
+                    selectors: selectors.clone(),
+                    issued_at: jwt_svid.issued_at,
+                    expires_at: jwt_svid.expiry,
+                });
+            }
+        }
+
+        let svids: Vec<Jwtsvid> = jwts_response
             .jwt_svids
-            .into_iter()
+            .iter()
             .map(|jwt_svid| Jwtsvid {
                 spiffe_id: jwt_svid.spiffe_id.to_string(),
-                svid: jwt_svid.token,
+                svid: jwt_svid.token.clone(),
             })
             .collect();
 
+        if let (Some(cache_key), Some(pod_uid)) = (cache_key, pod_uid) {
+            self.jwt_svid_cache.insert(
+                cache_key,
+                pod_uid,
+                svids.clone(),
+                &jwts_response.jwt_svids,
+                get_epoch_time(),
+            );
+        }
+
+        if let Some(workload_cache_key) = workload_cache_key {
+            // The pod is part of the key, there is nothing to check before returning them.
+            self.workload_jwt_svid_cache.insert(
+                workload_cache_key,
+                String::new(),
+                svids.clone(),
+                &jwts_response.jwt_svids,
+                get_epoch_time(),
+            );
+        }
+
         let response = Response::new(JwtsvidResponse { svids });
 
         Ok(response)
@@ -124,73 +636,71 @@ impl SpiffeWorkloadApi for WorkloadAPIServer {
     ) -> Result<Response<JwtsvidResponse>, tonic::Status> {
         info!("Received for new jwt");
 
-        let pid = request
-            .extensions()
-            .get::<UdsConnectInfo>()
-            .ok_or(Error::UdsClientPID)?
-            .peer_cred
-            .ok_or(Error::UdsClientPID)?
-            .pid()
-            .ok_or(Error::UdsClientPID)?
-            .try_into()
-            .map_err(Error::NegativePID)?;
+        if !workload_api::has_security_header(&request) {
+            return Err(Error::MissingSecurityHeader.into());
+        }
+
+        // Only set for the connections of the TCP listener.
+        if let Some(remote_addr) = request.remote_addr() {
+            return self.fetch_jwtsvid_tcp(request, remote_addr.ip()).await;
+        }
+
+        let caller = uds_caller(&request)?;
 
         // Create inner to avoid dependency with pid which is very hard to mock
-        self.fetch_jwtsvid_inner(request, pid).await
+        self.fetch_jwtsvid_inner(request, caller).await
     }
 
     async fn fetch_jwt_bundles(
         &self,
-        _request: Request<JwtBundlesRequest>,
+        request: Request<JwtBundlesRequest>,
     ) -> Result<Response<Self::FetchJWTBundlesStream>, tonic::Status> {
         info!("Received request for trust bundle");
 
-        let mut bundles_map = HashMap::new();
-
-        let trust_bundle = self
-            .spiffe_server_client
-            .get_trust_bundle(get_trust_bundle::Params {
-                jwt_keys: true,
-                x509_cas: false,
-            })
-            .await
-            .map_err(Error::TrustBundleResponse)?
-            .trust_bundle;
-
-        let jwk_set =
-            serde_json::to_vec(&trust_bundle.jwt_key_set).map_err(Error::SerdeConvertToVec)?;
-
-        bundles_map.insert(trust_bundle.trust_domain, jwk_set);
-
-        let trust_bundle_response = JwtBundlesResponse {
-            bundles: bundles_map,
-        };
+        if !workload_api::has_security_header(&request) {
+            return Err(Error::MissingSecurityHeader.into());
+        }
+        if self.is_shutting_down() {
+            return Err(Error::ListenerClosing.into());
+        }
 
-        let stream: Self::FetchJWTBundlesStream = Box::pin(async_stream::stream! {
-                yield Ok(trust_bundle_response)
-        }) as _;
+        // Only set for the connections of the TCP listener. The caller is only needed with federated bundles.
+        let peer = request.remote_addr().map(|remote_addr| remote_addr.ip());
+        let caller = uds_caller(&request);
 
-        return Ok(Response::new(Box::pin(stream) as _));
+        self.fetch_jwt_bundles_inner(peer, caller).await
     }
 
     async fn validate_jwtsvid(
         &self,
         request: Request<ValidateJwtsvidRequest>,
     ) -> Result<Response<ValidateJwtsvidResponse>, tonic::Status> {
+        if !workload_api::has_security_header(&request) {
+            return Err(Error::MissingSecurityHeader.into());
+        }
         let request = request.into_inner();
 
         info!("Received request for to validate jwt svid");
         debug!("SVID: {:?}, Audience: {}", request.svid, request.audience);
-        let trust_bundle = self.trust_bundle_manager.get_cached_trust_bundle().await;
-
         let audience = request.audience;
         let jwt_svid_compact = request.svid;
 
-        let jwt_svid = self
-            .jwt_svid_validator
-            .validate(&jwt_svid_compact, &trust_bundle, &audience)
-            .await
-            .map_err(Error::ValidateJWTSVIDs)?;
+        let mut result = self.validate(&jwt_svid_compact, &audience).await;
+        // The server may have just rotated its keys, the new key is not in the cached trust bundle yet. The
+        // token is validated once more with a fresh trust bundle.
+        if let Err(jwt_svid_validator::error::Error::PublicKeyNotInTrustBundle(kid)) = &result {
+            info!("Key {} is not in the trust bundle, refreshing it", kid);
+            match self
+                .trust_bundle_manager
+                .refresh_trust_bundle_throttled(UNKNOWN_KID_REFRESH_INTERVAL)
+                .await
+            {
+                Ok(true) => result = self.validate(&jwt_svid_compact, &audience).await,
+                Ok(false) => {}
+                Err(err) => warn!("Could not refresh the trust bundle: {}", err),
+            }
+        }
+        let jwt_svid = result.map_err(Error::ValidateJWTSVIDs)?;
 
         let claims_struct =
             serde_json::from_str(&serde_json::to_string(&jwt_svid.claims).unwrap()).unwrap();
@@ -213,26 +723,119 @@ impl SpiffeWorkloadApi for WorkloadAPIServer {
     type FetchJWTBundlesStream = JWTResponseStream;
 }
 
+// Peer of a connection of the unix socket listeners.
+fn uds_caller<T>(request: &Request<T>) -> Result<Caller, Error> {
+    let peer_cred = request
+        .extensions()
+        .get::<UdsConnectInfo>()
+        .ok_or(Error::UdsClientPID)?
+        .peer_cred
+        .ok_or(Error::UdsClientPID)?;
+
+    Ok(Caller {
+        pid: peer_cred
+            .pid()
+            .ok_or(Error::UdsClientPID)?
+            .try_into()
+            .map_err(Error::NegativePID)?,
+        uid: peer_cred.uid(),
+        gid: peer_cred.gid(),
+    })
+}
+
+// The bundles of the federated trust domains the workload is entitled to are sent with the bundle of the agent
+// trust domain, which is never replaced by one of them.
+fn jwt_bundles_response(
+    trust_bundle: TrustBundle,
+    federated_bundles: Vec<TrustBundle>,
+    federates_with: &BTreeSet<String>,
+) -> Result<JwtBundlesResponse, Error> {
+    let federated_bundles = federated_bundles
+        .into_iter()
+        .filter(|federated_bundle| federates_with.contains(&federated_bundle.trust_domain));
+
+    let mut bundles = HashMap::new();
+    for trust_bundle in federated_bundles.chain([trust_bundle]) {
+        let jwk_set =
+            serde_json::to_vec(&trust_bundle.jwt_key_set).map_err(Error::SerdeConvertToVec)?;
+        bundles.insert(trust_bundle.trust_domain, jwk_set);
+    }
+
+    Ok(JwtBundlesResponse { bundles })
+}
+
+// Forward the stream until the listener starts draining, then end it with UNAVAILABLE. Graceful shutdown
+// of the listener waits for every open stream, so long lived streams must be closed for the drain to finish.
+fn until_shutdown<T: Send + 'static>(
+    stream: Pin<Box<dyn Stream<Item = Result<T, tonic::Status>> + Send>>,
+    shutdown_signal: watch::Receiver<bool>,
+) -> Pin<Box<dyn Stream<Item = Result<T, tonic::Status>> + Send>> {
+    Box::pin(async_stream::stream! {
+        let mut stream = stream;
+        let shutdown = wait_for_shutdown(shutdown_signal);
+        pin_mut!(shutdown);
+
+        loop {
+            match future::select(stream.next(), shutdown.as_mut()).await {
+                future::Either::Left((Some(item), _)) => yield item,
+                future::Either::Left((None, _)) => break,
+                future::Either::Right(_) => {
+                    yield Err(Error::ListenerClosing.into());
+                    break;
+                }
+            }
+        }
+    })
+}
+
+async fn wait_for_shutdown(mut shutdown_signal: watch::Receiver<bool>) {
+    while !*shutdown_signal.borrow() {
+        // The sender is gone without ever signaling, the listener is never drained.
+        if shutdown_signal.changed().await.is_err() {
+            future::pending::<()>().await;
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::WorkloadAPIServer;
+    use crate::{
+        debug::DebugState,
+        metrics::Metrics,
+        rate_limit::{RateLimiter, RateLimits},
+        tcp::TcpSelectors,
+        until_shutdown, WorkloadAPIServer,
+    };
     use core_objects::{
-        Crv, JWKSet, JWTClaims, JWTHeader, JWTSVIDCompact, JWTType, KeyType, KeyUse, Kty,
-        TrustBundle, JWK, JWTSVID,
+        get_epoch_time, Crv, JWKSet, JWTClaims, JWTHeader, JWTSVIDCompact, JWTType, KeyType,
+        KeyUse, Kty, TrustBundle, JWK, JWTSVID,
     };
     use futures_util::StreamExt;
     use jwt_svid_validator::MockJWTSVIDValidator;
     use node_attestation_agent::MockNodeAttestation;
     use server_agent_api::{create_workload_jwts, get_trust_bundle};
     use spiffe_server_client::MockClient;
-    use std::{collections::BTreeSet, io::ErrorKind, sync::Arc};
-    use tonic::Request;
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        io::ErrorKind,
+        net::IpAddr,
+        sync::Arc,
+    };
+    use tokio::sync::watch;
+    use tonic::Code;
     use trust_bundle_manager::TrustBundleManager;
     use workload_api::generated::{
         spiffe_workload_api_server::SpiffeWorkloadApi, JwtBundlesRequest, JwtsvidRequest,
         ValidateJwtsvidRequest,
     };
-    use workload_attestation::{MockWorkloadAttestation, WorkloadAttributes};
+    use workload_attestation::{Caller, MockWorkloadAttestation, WorkloadAttributes, WorkloadId};
+
+    fn jwtsvid_request() -> JwtsvidRequest {
+        JwtsvidRequest {
+            spiffe_id: String::new(),
+            audience: vec!["audience".to_string()],
+        }
+    }
 
     fn init() -> (
         MockClient,
@@ -250,7 +853,9 @@ mod tests {
             x: "MjE2NDE3NTMwMTgxMjY5Njc2MTE3MzAwODU4NjY4Mjg2MDU4MTQ2OTY3ODY0MjU2MDA1MzI0NTA0ODQyNTcxMTcyMzI4NjM1MjgxMjM".to_string(),
             y: "MzU1NjA3MjI0Mjc5MzAxMjYzMzkxNDg5NjAxMDA2NjMzNDE1NTA2MzQzMTQ5MDIxNzQxNTI0MDMyMzk0ODA1NjM2NjE0MTU0NjMyNzI".to_string(),
             kty: Kty::EC,
-            crv: Crv::P256,
+            crv: Some(Crv::P256),
+            n: String::new(),
+            e: String::new(),
             kid: "kid".to_string(),
             key_use: KeyUse::JWTSVID,
         };
@@ -288,7 +893,7 @@ mod tests {
             trust_bundle,
         ) = init();
 
-        let request = Request::new(ValidateJwtsvidRequest::default());
+        let request = workload_api::request(ValidateJwtsvidRequest::default());
 
         let mock_client = Arc::new(mock_client);
         let trust_bundle_manager = TrustBundleManager::new(mock_client.clone(), trust_bundle);
@@ -304,7 +909,12 @@ mod tests {
             audience: vec!["audience".to_string()],
             expiry: 10,
             issued_at: 0,
+            jwt_id: None,
+            not_before: None,
+            issuer: None,
             other_identities: Vec::new(),
+            pod_uid: None,
+            custom_claims: Default::default(),
         };
         mock_jwt_svid_validator.expect_validate().return_once({
             let claims = claims.clone();
@@ -341,6 +951,88 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn validate_jwt_refreshes_unknown_kid() {
+        let (
+            mut mock_client,
+            mock_workload_attestation,
+            mock_node_attestation,
+            mut mock_jwt_svid_validator,
+            trust_bundle,
+        ) = init();
+
+        let mut rotated_trust_bundle = trust_bundle.clone();
+        rotated_trust_bundle.jwt_key_set.keys[0].kid = "rotated".to_string();
+        mock_client.expect_get_trust_bundle().times(1).return_once({
+            let rotated_trust_bundle = rotated_trust_bundle.clone();
+
+            move |_| {
+                Ok(get_trust_bundle::Response {
+                    trust_bundle: rotated_trust_bundle,
+                    federated_bundles: Vec::new(),
+                })
+            }
+        });
+
+        let claims = JWTClaims {
+            subject: "subject".to_string(),
+            audience: vec!["audience".to_string()],
+            expiry: 10,
+            issued_at: 0,
+            jwt_id: None,
+            not_before: None,
+            issuer: None,
+            other_identities: Vec::new(),
+            pod_uid: None,
+            custom_claims: Default::default(),
+        };
+        // The token is signed with the rotated key, only found in the refreshed trust bundle.
+        mock_jwt_svid_validator
+            .expect_validate()
+            .times(2)
+            .returning(move |_, trust_bundles, _| {
+                let keys = &trust_bundles["trust_domain"].jwt_key_set.keys;
+                if keys[0].kid == "rotated" {
+                    Ok(JWTSVID {
+                        header: JWTHeader {
+                            algorithm: KeyType::ES256,
+                            key_id: "rotated".to_string(),
+                            jwt_type: JWTType::JWT,
+                        },
+                        claims: claims.clone(),
+                        signature: "dummy".to_string(),
+                    })
+                } else {
+                    Err(jwt_svid_validator::error::Error::PublicKeyNotInTrustBundle(
+                        "rotated".to_string(),
+                    ))
+                }
+            });
+
+        let mock_client = Arc::new(mock_client);
+        let trust_bundle_manager =
+            Arc::new(TrustBundleManager::new(mock_client.clone(), trust_bundle));
+        let workload_server = WorkloadAPIServer::new(
+            mock_client,
+            Arc::new(mock_workload_attestation),
+            Arc::new(mock_node_attestation),
+            trust_bundle_manager.clone(),
+            Arc::new(mock_jwt_svid_validator),
+        );
+
+        let request = workload_api::request(ValidateJwtsvidRequest::default());
+        let response = workload_server
+            .validate_jwtsvid(request)
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.spiffe_id, "subject");
+        assert_eq!(
+            trust_bundle_manager.get_cached_trust_bundle().await,
+            rotated_trust_bundle
+        );
+    }
+
     #[tokio::test]
     #[allow(clippy::cast_precision_loss)]
     async fn validate_jwt_error_validation() {
@@ -352,7 +1044,7 @@ mod tests {
             trust_bundle,
         ) = init();
 
-        let request = Request::new(ValidateJwtsvidRequest::default());
+        let request = workload_api::request(ValidateJwtsvidRequest::default());
 
         let mock_client = Arc::new(mock_client);
         let trust_bundle_manager = TrustBundleManager::new(mock_client.clone(), trust_bundle);
@@ -391,7 +1083,9 @@ mod tests {
                 x: "xxx".to_string(),
                 y: "yyy".to_string(),
                 kty: Kty::EC,
-                crv: Crv::P256,
+                crv: Some(Crv::P256),
+                n: String::new(),
+                e: String::new(),
                 kid: "132".to_string(),
                 key_use: KeyUse::JWTSVID,
             }],
@@ -411,6 +1105,7 @@ mod tests {
                         spiffe_sequence_number: 0,
                     },
                 },
+                federated_bundles: Vec::new(),
             })
         });
 
@@ -425,7 +1120,7 @@ mod tests {
             Arc::new(mock_jwt_svid_validator),
         );
 
-        let request = Request::new(JwtBundlesRequest::default());
+        let request = workload_api::request(JwtBundlesRequest::default());
         let mut stream = workload_server
             .fetch_jwt_bundles(request)
             .await
@@ -446,7 +1141,122 @@ mod tests {
         assert_eq!(jwk_set_resp, jwk_set);
     }
 
-    #[tokio::test]
+    #[test]
+    fn jwt_bundles_response_with_federated_bundles() {
+        let trust_bundle = |trust_domain: &str, kid: &str| TrustBundle {
+            trust_domain: trust_domain.to_string(),
+            jwt_key_set: JWKSet {
+                keys: vec![JWK {
+                    x: "xxx".to_string(),
+                    y: "yyy".to_string(),
+                    kty: Kty::EC,
+                    crv: Some(Crv::P256),
+                    n: String::new(),
+                    e: String::new(),
+                    kid: kid.to_string(),
+                    key_use: KeyUse::JWTSVID,
+                }],
+                spiffe_refresh_hint: 0,
+                spiffe_sequence_number: 0,
+            },
+            x509_key_set: JWKSet {
+                keys: Vec::new(),
+                spiffe_refresh_hint: 0,
+                spiffe_sequence_number: 0,
+            },
+        };
+
+        let response = jwt_bundles_response(
+            trust_bundle("dummy", "local"),
+            vec![
+                trust_bundle("cloud.contoso.com", "federated"),
+                trust_bundle("fabrikam.com", "not entitled"),
+                trust_bundle("dummy", "impostor"),
+            ],
+            &BTreeSet::from(["cloud.contoso.com".to_string(), "dummy".to_string()]),
+        )
+        .unwrap();
+
+        assert_eq!(response.bundles.len(), 2);
+        let jwk_set: JWKSet = serde_json::from_slice(&response.bundles["dummy"]).unwrap();
+        assert_eq!(jwk_set.keys[0].kid, "local");
+        let jwk_set: JWKSet =
+            serde_json::from_slice(&response.bundles["cloud.contoso.com"]).unwrap();
+        assert_eq!(jwk_set.keys[0].kid, "federated");
+    }
+
+    #[tokio::test]
+    async fn fetch_jwt_bundles_entitled_federated_bundles() {
+        let (
+            mut mock_client,
+            mut mock_workload_attestation,
+            mut mock_node_attestation,
+            mock_jwt_svid_validator,
+            trust_bundle,
+        ) = init();
+
+        let federated_bundle = |trust_domain: &str| TrustBundle {
+            trust_domain: trust_domain.to_string(),
+            ..trust_bundle.clone()
+        };
+        let federated_bundles = vec![
+            federated_bundle("cloud.contoso.com"),
+            federated_bundle("fabrikam.com"),
+        ];
+        let closure_trust_bundle = trust_bundle.clone();
+        mock_client.expect_get_trust_bundle().return_once(move |_| {
+            Ok(get_trust_bundle::Response {
+                trust_bundle: closure_trust_bundle,
+                federated_bundles,
+            })
+        });
+        mock_client
+            .expect_create_workload_jwts()
+            .withf(|request| request.bundles_only && request.selectors.contains("selector"))
+            .return_once(move |_| {
+                Ok(create_workload_jwts::Response {
+                    jwt_svids: Vec::new(),
+                    denied: Vec::new(),
+                    federates_with: BTreeSet::from(["cloud.contoso.com".to_string()]),
+                })
+            });
+        mock_workload_attestation
+            .expect_attest_workload()
+            .return_once(move |_| {
+                Ok(WorkloadAttributes {
+                    selectors: BTreeSet::from(["selector".to_string()]),
+                    pod_uid: None,
+                })
+            });
+        mock_node_attestation
+            .expect_get_attestation_token()
+            .return_once(move || Ok("".to_string()));
+
+        let mock_client = Arc::new(mock_client);
+        let trust_bundle_manager =
+            TrustBundleManager::new(mock_client.clone(), trust_bundle.clone());
+
+        let workload_server = WorkloadAPIServer::new(
+            mock_client,
+            Arc::new(mock_workload_attestation),
+            Arc::new(mock_node_attestation),
+            Arc::new(trust_bundle_manager),
+            Arc::new(mock_jwt_svid_validator),
+        );
+
+        let mut stream = workload_server
+            .fetch_jwt_bundles_inner(None, Ok(Caller::default()))
+            .await
+            .unwrap()
+            .into_inner();
+        let bundles = stream.next().await.unwrap().unwrap().bundles;
+
+        assert_eq!(bundles.len(), 2);
+        assert!(bundles.contains_key(&trust_bundle.trust_domain));
+        assert!(bundles.contains_key("cloud.contoso.com"));
+    }
+
+    #[tokio::test]
     async fn fetch_jwt_bundles_no_server_response() {
         let (
             mut mock_client,
@@ -474,7 +1284,7 @@ mod tests {
             Arc::new(mock_jwt_svid_validator),
         );
 
-        let request = Request::new(JwtBundlesRequest::default());
+        let request = workload_api::request(JwtBundlesRequest::default());
         // Unwrap error doesn't work because the debug trait is missing.
         assert!(
             workload_server.fetch_jwt_bundles(request).await.is_err(),
@@ -482,6 +1292,58 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn fetch_jwt_bundles_sends_rotated_bundle() {
+        let (
+            mut mock_client,
+            mock_workload_attestation,
+            mock_node_attestation,
+            mock_jwt_svid_validator,
+            trust_bundle,
+        ) = init();
+
+        let mut rotated_trust_bundle = trust_bundle.clone();
+        rotated_trust_bundle.jwt_key_set.keys[0].kid = "rotated".to_string();
+        // Popped from the end, the first response of the stream then the refresh.
+        let mut responses = vec![rotated_trust_bundle.clone(), trust_bundle.clone()];
+        mock_client
+            .expect_get_trust_bundle()
+            .times(2)
+            .returning(move |_| {
+                Ok(get_trust_bundle::Response {
+                    trust_bundle: responses.pop().unwrap(),
+                    federated_bundles: Vec::new(),
+                })
+            });
+
+        let mock_client = Arc::new(mock_client);
+        let trust_bundle_manager =
+            Arc::new(TrustBundleManager::new(mock_client.clone(), trust_bundle));
+
+        let workload_server = WorkloadAPIServer::new(
+            mock_client,
+            Arc::new(mock_workload_attestation),
+            Arc::new(mock_node_attestation),
+            trust_bundle_manager.clone(),
+            Arc::new(mock_jwt_svid_validator),
+        );
+
+        let request = workload_api::request(JwtBundlesRequest::default());
+        let mut stream = workload_server
+            .fetch_jwt_bundles(request)
+            .await
+            .unwrap()
+            .into_inner();
+        stream.next().await.unwrap().unwrap();
+
+        trust_bundle_manager.refresh_trust_bundle().await.unwrap();
+
+        let bundles = stream.next().await.unwrap().unwrap().bundles;
+        let jwk_set: JWKSet =
+            serde_json::from_slice(&bundles[&rotated_trust_bundle.trust_domain]).unwrap();
+        assert_eq!(jwk_set, rotated_trust_bundle.jwt_key_set);
+    }
+
     #[tokio::test]
     async fn fetch_jwtsvid_happy_path() {
         let (
@@ -505,6 +1367,8 @@ mod tests {
                         expiry: 0,
                         issued_at: 0,
                     }],
+                    denied: Vec::new(),
+                    federates_with: Default::default(),
                 })
             });
         mock_workload_attestation
@@ -512,6 +1376,7 @@ mod tests {
             .return_once(move |_| {
                 Ok(WorkloadAttributes {
                     selectors: BTreeSet::new(),
+                    pod_uid: None,
                 })
             });
 
@@ -529,10 +1394,10 @@ mod tests {
             Arc::new(trust_bundle_manager),
             Arc::new(mock_jwt_svid_validator),
         );
-        let request = Request::new(JwtsvidRequest::default());
+        let request = workload_api::request(jwtsvid_request());
 
         let response = workload_server
-            .fetch_jwtsvid_inner(request, 0)
+            .fetch_jwtsvid_inner(request, Caller::default())
             .await
             .unwrap()
             .into_inner();
@@ -546,23 +1411,45 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn fetch_jwtsvid_error_workload_attestation() {
+    async fn fetch_jwtsvid_sends_audiences() {
         let (
             mut mock_client,
-            mock_workload_attestation,
-            mock_node_attestation,
+            mut mock_workload_attestation,
+            mut mock_node_attestation,
             mock_jwt_svid_validator,
             trust_bundle,
         ) = init();
 
+        let audiences = vec![
+            "spiffe://trust_domain/mqttbroker".to_string(),
+            "other".to_string(),
+        ];
+
+        let audiences_tmp = audiences.clone();
         mock_client
             .expect_create_workload_jwts()
-            .return_once(move |_| {
-                // Use full name here to avoid name collision
-                Err(Box::new(
-                    spiffe_server_client::http::error::Error::Connector("dummy".to_string()),
-                ))
+            .times(1)
+            .return_once(move |req| {
+                assert_eq!(req.audiences, audiences_tmp);
+
+                Ok(create_workload_jwts::Response {
+                    jwt_svids: vec![JWTSVIDCompact {
+                        token: "token".to_string(),
+                        spiffe_id: "trust_domain/path".to_string(),
+                        expiry: 0,
+                        issued_at: 0,
+                    }],
+                    denied: Vec::new(),
+                    federates_with: Default::default(),
+                })
             });
+        mock_workload_attestation
+            .expect_attest_workload()
+            .return_once(move |_| Ok(WorkloadAttributes::default()));
+
+        mock_node_attestation
+            .expect_get_attestation_token()
+            .return_once(move || Ok("".to_string()));
 
         let mock_client = Arc::new(mock_client);
         let trust_bundle_manager = TrustBundleManager::new(mock_client.clone(), trust_bundle);
@@ -574,56 +1461,207 @@ mod tests {
             Arc::new(trust_bundle_manager),
             Arc::new(mock_jwt_svid_validator),
         );
+        let request = workload_api::request(JwtsvidRequest {
+            audience: audiences,
+            spiffe_id: String::new(),
+        });
 
-        let request = Request::new(JwtsvidRequest::default());
-        // Unwrap error doesn't work because the debug trait is missing.
-        assert!(
-            workload_server.fetch_jwtsvid(request).await.is_err(),
-            "Expected an error"
-        );
+        let response = workload_server
+            .fetch_jwtsvid_inner(request, Caller::default())
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(1, response.svids.len());
     }
 
     #[tokio::test]
-    async fn fetch_jwtsvid_error_agent_attestation() {
+    async fn fetch_jwtsvid_tcp_peer_selectors() {
         let (
             mut mock_client,
-            mut mock_workload_attestation,
+            mock_workload_attestation,
             mut mock_node_attestation,
             mock_jwt_svid_validator,
             trust_bundle,
         ) = init();
 
-        let spiffe_id = "trust_domain/path".to_string();
+        let peer: IpAddr = "172.17.0.5".parse().unwrap();
+        let mut selectors = BTreeSet::new();
+        selectors.insert("PODLABELS:app:legacy".to_string());
+        let mut peers = BTreeMap::new();
+        peers.insert(peer, selectors.clone());
 
-        let spiffe_id_tmp = spiffe_id.clone();
+        // TCP peers are never attested.
+        mock_client
+            .expect_create_workload_jwts()
+            .times(1)
+            .return_once(move |req| {
+                assert_eq!(req.selectors, selectors);
+
+                Ok(create_workload_jwts::Response {
+                    jwt_svids: vec![JWTSVIDCompact {
+                        token: "token".to_string(),
+                        spiffe_id: "trust_domain/path".to_string(),
+                        expiry: 0,
+                        issued_at: 0,
+                    }],
+                    denied: Vec::new(),
+                    federates_with: Default::default(),
+                })
+            });
+        mock_node_attestation
+            .expect_get_attestation_token()
+            .return_once(move || Ok("".to_string()));
+
+        let mock_client = Arc::new(mock_client);
+        let trust_bundle_manager = TrustBundleManager::new(mock_client.clone(), trust_bundle);
+
+        let workload_server = WorkloadAPIServer::new(
+            mock_client,
+            Arc::new(mock_workload_attestation),
+            Arc::new(mock_node_attestation),
+            Arc::new(trust_bundle_manager),
+            Arc::new(mock_jwt_svid_validator),
+        )
+        .with_tcp_selectors(TcpSelectors::new(peers, BTreeSet::new()));
+
+        let response = workload_server
+            .fetch_jwtsvid_tcp(workload_api::request(jwtsvid_request()), peer)
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(1, response.svids.len());
+
+        // Without default selectors, other peers get no identity.
+        let status = match workload_server
+            .fetch_jwtsvid_tcp(
+                workload_api::request(jwtsvid_request()),
+                "172.17.0.6".parse().unwrap(),
+            )
+            .await
+        {
+            Ok(_) => panic!("Expected an error"),
+            Err(status) => status,
+        };
+        assert_eq!(status.code(), Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn fetch_jwtsvid_rate_limited() {
+        let (
+            mut mock_client,
+            mut mock_workload_attestation,
+            mut mock_node_attestation,
+            mock_jwt_svid_validator,
+            trust_bundle,
+        ) = init();
+
+        // Only the first request reaches the server.
         mock_client
             .expect_create_workload_jwts()
+            .times(1)
             .return_once(move |_| {
                 Ok(create_workload_jwts::Response {
                     jwt_svids: vec![JWTSVIDCompact {
                         token: "token".to_string(),
-                        spiffe_id: spiffe_id_tmp,
+                        spiffe_id: "trust_domain/path".to_string(),
                         expiry: 0,
                         issued_at: 0,
                     }],
+                    denied: Vec::new(),
+                    federates_with: Default::default(),
                 })
             });
         mock_workload_attestation
             .expect_attest_workload()
+            .times(1)
+            .return_once(move |_| Ok(WorkloadAttributes::default()));
+        mock_node_attestation
+            .expect_get_attestation_token()
+            .return_once(move || Ok("".to_string()));
+
+        let mock_client = Arc::new(mock_client);
+        let trust_bundle_manager = TrustBundleManager::new(mock_client.clone(), trust_bundle);
+
+        let workload_server = WorkloadAPIServer::new(
+            mock_client,
+            Arc::new(mock_workload_attestation),
+            Arc::new(mock_node_attestation),
+            Arc::new(trust_bundle_manager),
+            Arc::new(mock_jwt_svid_validator),
+        )
+        .with_rate_limiter(Arc::new(RateLimiter::new(RateLimits {
+            requests_per_sec: 1,
+            burst: 1,
+        })));
+
+        workload_server
+            .fetch_jwtsvid_inner(workload_api::request(jwtsvid_request()), Caller::default())
+            .await
+            .unwrap();
+
+        let status = match workload_server
+            .fetch_jwtsvid_inner(workload_api::request(jwtsvid_request()), Caller::default())
+            .await
+        {
+            Ok(_) => panic!("Expected an error"),
+            Err(status) => status,
+        };
+        assert_eq!(status.code(), Code::ResourceExhausted);
+    }
+
+    #[tokio::test]
+    async fn fetch_jwtsvid_records_debug_state_and_metrics() {
+        let (
+            mut mock_client,
+            mut mock_workload_attestation,
+            mut mock_node_attestation,
+            mock_jwt_svid_validator,
+            trust_bundle,
+        ) = init();
+
+        let now = get_epoch_time();
+        mock_client
+            .expect_create_workload_jwts()
             .return_once(move |_| {
+                Ok(create_workload_jwts::Response {
+                    jwt_svids: vec![JWTSVIDCompact {
+                        token: "token".to_string(),
+                        spiffe_id: "trust_domain/path".to_string(),
+                        expiry: now + 3600,
+                        issued_at: now,
+                    }],
+                    denied: Vec::new(),
+                    federates_with: Default::default(),
+                })
+            });
+        let mut attested = false;
+        mock_workload_attestation
+            .expect_attest_workload()
+            .times(2)
+            .returning(move |_| {
+                if attested {
+                    return Ok(WorkloadAttributes {
+                        selectors: BTreeSet::from(["PODLABELS:app:test".to_string()]),
+                        pod_uid: None,
+                    });
+                }
+
+                attested = true;
                 Err(Box::new(
                     node_attestation_agent::k8s::error::Error::UnableToReadToken(
                         std::io::Error::new(ErrorKind::Other, "dummy"),
                     ),
                 ))
             });
-
         mock_node_attestation
             .expect_get_attestation_token()
             .return_once(move || Ok("".to_string()));
 
         let mock_client = Arc::new(mock_client);
         let trust_bundle_manager = TrustBundleManager::new(mock_client.clone(), trust_bundle);
+        let debug_state = Arc::new(DebugState::default());
+        let metrics = Arc::new(Metrics::default());
 
         let workload_server = WorkloadAPIServer::new(
             mock_client,
@@ -631,12 +1669,590 @@ mod tests {
             Arc::new(mock_node_attestation),
             Arc::new(trust_bundle_manager),
             Arc::new(mock_jwt_svid_validator),
+        )
+        .with_debug_state(debug_state.clone())
+        .with_metrics(metrics.clone());
+
+        let caller = Caller {
+            pid: 42,
+            uid: 1000,
+            gid: 1000,
+        };
+        assert!(workload_server
+            .fetch_jwtsvid_inner(workload_api::request(jwtsvid_request()), caller)
+            .await
+            .is_err());
+        workload_server
+            .fetch_jwtsvid_inner(workload_api::request(jwtsvid_request()), caller)
+            .await
+            .unwrap();
+
+        let snapshot = debug_state.snapshot(now);
+        assert_eq!(snapshot.attestation_errors.len(), 1);
+        assert_eq!(snapshot.attestation_errors[0].pid, 42);
+        assert_eq!(snapshot.workloads.len(), 1);
+        assert_eq!(snapshot.workloads[0].uid, 1000);
+        assert_eq!(snapshot.jwt_svids.len(), 1);
+        assert_eq!(snapshot.jwt_svids[0].spiffe_id, "trust_domain/path");
+        assert_eq!(
+            snapshot.jwt_svids[0].audiences,
+            vec!["audience".to_string()]
         );
-        let request = Request::new(JwtsvidRequest::default());
-        // Unwrap error doesn't work because the debug trait is missing.
-        assert!(
-            workload_server.fetch_jwtsvid(request).await.is_err(),
-            "Expected an error"
+        assert_eq!(snapshot.jwt_svids[0].expires_at, now + 3600);
+        assert_eq!(
+            snapshot.jwt_svids[0].selectors,
+            BTreeSet::from(["PODLABELS:app:test".to_string()])
         );
+
+        assert_eq!(metrics.workload_attestation_failures.get(), 1);
+        assert_eq!(metrics.jwt_svids_issued.get(), 1);
+        assert_eq!(metrics.create_jwt_svids_duration.count(), 1);
+    }
+
+    fn caching_workload_server(
+        jwt_svid_caching: bool,
+        server_calls: usize,
+        attestations: usize,
+    ) -> WorkloadAPIServer {
+        let (
+            mut mock_client,
+            mut mock_workload_attestation,
+            mut mock_node_attestation,
+            mock_jwt_svid_validator,
+            trust_bundle,
+        ) = init();
+
+        let now = get_epoch_time();
+        let mut issued = 0;
+        mock_client
+            .expect_create_workload_jwts()
+            .times(server_calls)
+            .returning(move |_| {
+                issued += 1;
+
+                Ok(create_workload_jwts::Response {
+                    jwt_svids: vec![JWTSVIDCompact {
+                        token: format!("token{}", issued),
+                        spiffe_id: "trust_domain/path".to_string(),
+                        expiry: now + 3600,
+                        issued_at: now,
+                    }],
+                    denied: Vec::new(),
+                    federates_with: Default::default(),
+                })
+            });
+        mock_workload_attestation
+            .expect_attest_workload()
+            .times(attestations)
+            .returning(|_| {
+                Ok(WorkloadAttributes {
+                    selectors: vec!["UNIXUID:1000".to_string()].into_iter().collect(),
+                    pod_uid: None,
+                })
+            });
+        mock_node_attestation
+            .expect_get_attestation_token()
+            .times(server_calls)
+            .returning(|| Ok(String::new()));
+
+        let mock_client = Arc::new(mock_client);
+        let trust_bundle_manager = TrustBundleManager::new(mock_client.clone(), trust_bundle);
+
+        WorkloadAPIServer::new(
+            mock_client,
+            Arc::new(mock_workload_attestation),
+            Arc::new(mock_node_attestation),
+            Arc::new(trust_bundle_manager),
+            Arc::new(mock_jwt_svid_validator),
+        )
+        .with_jwt_svid_caching(jwt_svid_caching)
+    }
+
+    async fn fetch_jwtsvid_token(workload_server: &WorkloadAPIServer, pid: u32) -> String {
+        let request = workload_api::request(JwtsvidRequest {
+            spiffe_id: "trust_domain/path".to_string(),
+            audience: vec!["audience".to_string()],
+        });
+        let caller = Caller {
+            pid,
+            ..Default::default()
+        };
+
+        let svids = workload_server
+            .fetch_jwtsvid_inner(request, caller)
+            .await
+            .unwrap()
+            .into_inner()
+            .svids;
+        assert_eq!(svids.len(), 1);
+
+        svids[0].svid.clone()
+    }
+
+    #[tokio::test]
+    async fn fetch_jwtsvid_cached_by_process() {
+        let workload_server = caching_workload_server(true, 2, 3);
+
+        // The second request of the process is answered from the cache, without going to the server. Another
+        // process with the same selectors gets its own JWT-SVID.
+        assert_eq!(fetch_jwtsvid_token(&workload_server, 1).await, "token1");
+        assert_eq!(fetch_jwtsvid_token(&workload_server, 1).await, "token1");
+        assert_eq!(fetch_jwtsvid_token(&workload_server, 2).await, "token2");
+    }
+
+    #[tokio::test]
+    async fn fetch_jwtsvid_caching_disabled() {
+        let workload_server = caching_workload_server(false, 2, 2);
+
+        assert_eq!(fetch_jwtsvid_token(&workload_server, 1).await, "token1");
+        assert_eq!(fetch_jwtsvid_token(&workload_server, 1).await, "token2");
+    }
+
+    #[tokio::test]
+    async fn fetch_jwtsvid_pinned_to_pod() {
+        let (
+            mut mock_client,
+            mut mock_workload_attestation,
+            mut mock_node_attestation,
+            mock_jwt_svid_validator,
+            trust_bundle,
+        ) = init();
+
+        let now = get_epoch_time();
+        mock_client
+            .expect_create_workload_jwts()
+            .times(2)
+            .returning(move |req| {
+                assert_eq!(req.pod_uid, Some("pod_uid".to_string()));
+
+                Ok(create_workload_jwts::Response {
+                    jwt_svids: vec![JWTSVIDCompact {
+                        token: "token".to_string(),
+                        spiffe_id: "trust_domain/path".to_string(),
+                        expiry: now + 3600,
+                        issued_at: now,
+                    }],
+                    denied: Vec::new(),
+                    federates_with: Default::default(),
+                })
+            });
+        mock_workload_attestation
+            .expect_attest_workload()
+            .times(2)
+            .returning(move |_| {
+                Ok(WorkloadAttributes {
+                    selectors: BTreeSet::new(),
+                    pod_uid: Some("pod_uid".to_string()),
+                })
+            });
+        // The process is first still in the same pod, then the PID is reused by another pod.
+        let mut pod_uids = vec!["other_pod_uid", "pod_uid"];
+        mock_workload_attestation
+            .expect_get_pod_uid()
+            .times(2)
+            .returning(move |_| Ok(pod_uids.pop().unwrap().to_string()));
+
+        mock_node_attestation
+            .expect_get_attestation_token()
+            .times(2)
+            .returning(move || Ok("".to_string()));
+
+        let mock_client = Arc::new(mock_client);
+        let trust_bundle_manager = TrustBundleManager::new(mock_client.clone(), trust_bundle);
+
+        let workload_server = WorkloadAPIServer::new(
+            mock_client,
+            Arc::new(mock_workload_attestation),
+            Arc::new(mock_node_attestation),
+            Arc::new(trust_bundle_manager),
+            Arc::new(mock_jwt_svid_validator),
+        )
+        .with_pod_identity_pinning(true);
+
+        // Attested and issued, then returned from the cache, then attested and issued again.
+        for _ in 0..3 {
+            let request = workload_api::request(jwtsvid_request());
+            let response = workload_server
+                .fetch_jwtsvid_inner(request, Caller::default())
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!("token", response.svids[0].svid);
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_jwtsvid_prefetched() {
+        let (
+            mut mock_client,
+            mut mock_workload_attestation,
+            mut mock_node_attestation,
+            mock_jwt_svid_validator,
+            trust_bundle,
+        ) = init();
+
+        let workload_id = WorkloadId {
+            pod_uid: "pod_uid".to_string(),
+            container_id: "container_id".to_string(),
+        };
+        let audiences = vec!["audience".to_string()];
+
+        let now = get_epoch_time();
+        mock_client
+            .expect_create_workload_jwts()
+            .return_once(move |req| {
+                assert!(req.prefetch_only);
+
+                Ok(create_workload_jwts::Response {
+                    jwt_svids: vec![JWTSVIDCompact {
+                        token: "token".to_string(),
+                        spiffe_id: "trust_domain/path".to_string(),
+                        expiry: now + 3600,
+                        issued_at: now,
+                    }],
+                    denied: Vec::new(),
+                    federates_with: Default::default(),
+                })
+            });
+        mock_workload_attestation
+            .expect_attest_node_workloads()
+            .return_once({
+                let workload_id = workload_id.clone();
+
+                move || Ok(vec![(workload_id, WorkloadAttributes::default())])
+            });
+        // The workload is not attested again, only its container is looked up.
+        mock_workload_attestation
+            .expect_get_workload_id()
+            .return_once(move |_| Ok(workload_id));
+        mock_node_attestation
+            .expect_get_attestation_token()
+            .return_once(move || Ok("".to_string()));
+
+        let mock_client = Arc::new(mock_client);
+        let trust_bundle_manager = TrustBundleManager::new(mock_client.clone(), trust_bundle);
+
+        let workload_server = WorkloadAPIServer::new(
+            mock_client,
+            Arc::new(mock_workload_attestation),
+            Arc::new(mock_node_attestation),
+            Arc::new(trust_bundle_manager),
+            Arc::new(mock_jwt_svid_validator),
+        );
+
+        assert_eq!(workload_server.warm_up(&audiences, 4).await, 1);
+
+        let request = workload_api::request(JwtsvidRequest {
+            spiffe_id: "trust_domain/path".to_string(),
+            audience: audiences,
+        });
+        let response = workload_server
+            .fetch_jwtsvid_inner(request, Caller::default())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!("token", response.svids[0].svid);
+    }
+
+    #[tokio::test]
+    async fn fetch_jwtsvid_denied_by_server() {
+        let (
+            mut mock_client,
+            mut mock_workload_attestation,
+            mut mock_node_attestation,
+            mock_jwt_svid_validator,
+            trust_bundle,
+        ) = init();
+
+        mock_client
+            .expect_create_workload_jwts()
+            .return_once(move |_| {
+                Ok(create_workload_jwts::Response {
+                    jwt_svids: Vec::new(),
+                    denied: vec![create_workload_jwts::DeniedIdentity {
+                        spiffe_id: "trust_domain/path".to_string(),
+                        reason: create_workload_jwts::DenyReason::QuotaExceeded,
+                    }],
+                    federates_with: Default::default(),
+                })
+            });
+        mock_workload_attestation
+            .expect_attest_workload()
+            .return_once(move |_| {
+                Ok(WorkloadAttributes {
+                    selectors: BTreeSet::new(),
+                    pod_uid: None,
+                })
+            });
+
+        mock_node_attestation
+            .expect_get_attestation_token()
+            .return_once(move || Ok("".to_string()));
+
+        let mock_client = Arc::new(mock_client);
+        let trust_bundle_manager = TrustBundleManager::new(mock_client.clone(), trust_bundle);
+
+        let workload_server = WorkloadAPIServer::new(
+            mock_client,
+            Arc::new(mock_workload_attestation),
+            Arc::new(mock_node_attestation),
+            Arc::new(trust_bundle_manager),
+            Arc::new(mock_jwt_svid_validator),
+        );
+        let request = workload_api::request(jwtsvid_request());
+
+        let status = workload_server
+            .fetch_jwtsvid_inner(request, Caller::default())
+            .await
+            .unwrap_err();
+
+        assert_eq!(tonic::Code::PermissionDenied, status.code());
+        assert!(status.message().contains("QUOTA_EXCEEDED"));
+    }
+
+    #[tokio::test]
+    async fn fetch_jwtsvid_no_identity() {
+        let (
+            mut mock_client,
+            mut mock_workload_attestation,
+            mut mock_node_attestation,
+            mock_jwt_svid_validator,
+            trust_bundle,
+        ) = init();
+
+        mock_client
+            .expect_create_workload_jwts()
+            .return_once(move |_| {
+                Ok(create_workload_jwts::Response {
+                    jwt_svids: Vec::new(),
+                    denied: Vec::new(),
+                    federates_with: Default::default(),
+                })
+            });
+        mock_workload_attestation
+            .expect_attest_workload()
+            .return_once(move |_| Ok(WorkloadAttributes::default()));
+        mock_node_attestation
+            .expect_get_attestation_token()
+            .return_once(move || Ok("".to_string()));
+
+        let mock_client = Arc::new(mock_client);
+        let trust_bundle_manager = TrustBundleManager::new(mock_client.clone(), trust_bundle);
+
+        let workload_server = WorkloadAPIServer::new(
+            mock_client,
+            Arc::new(mock_workload_attestation),
+            Arc::new(mock_node_attestation),
+            Arc::new(trust_bundle_manager),
+            Arc::new(mock_jwt_svid_validator),
+        );
+
+        let status = workload_server
+            .fetch_jwtsvid_inner(workload_api::request(jwtsvid_request()), Caller::default())
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn fetch_jwtsvid_invalid_requests() {
+        let (
+            mock_client,
+            mock_workload_attestation,
+            mock_node_attestation,
+            mock_jwt_svid_validator,
+            trust_bundle,
+        ) = init();
+
+        // Nothing is attested or issued.
+        let mock_client = Arc::new(mock_client);
+        let trust_bundle_manager = TrustBundleManager::new(mock_client.clone(), trust_bundle);
+
+        let workload_server = WorkloadAPIServer::new(
+            mock_client,
+            Arc::new(mock_workload_attestation),
+            Arc::new(mock_node_attestation),
+            Arc::new(trust_bundle_manager),
+            Arc::new(mock_jwt_svid_validator),
+        );
+
+        let request = workload_api::request(JwtsvidRequest::default());
+        let status = workload_server
+            .fetch_jwtsvid_inner(request, Caller::default())
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let request = tonic::Request::new(jwtsvid_request());
+        let status = match workload_server.fetch_jwtsvid(request).await {
+            Ok(_) => panic!("Expected an error"),
+            Err(status) => status,
+        };
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let request = tonic::Request::new(JwtBundlesRequest::default());
+        let status = match workload_server.fetch_jwt_bundles(request).await {
+            Ok(_) => panic!("Expected an error"),
+            Err(status) => status,
+        };
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn fetch_jwtsvid_error_workload_attestation() {
+        let (
+            mut mock_client,
+            mock_workload_attestation,
+            mock_node_attestation,
+            mock_jwt_svid_validator,
+            trust_bundle,
+        ) = init();
+
+        mock_client
+            .expect_create_workload_jwts()
+            .return_once(move |_| {
+                // Use full name here to avoid name collision
+                Err(Box::new(
+                    spiffe_server_client::http::error::Error::Connector("dummy".to_string()),
+                ))
+            });
+
+        let mock_client = Arc::new(mock_client);
+        let trust_bundle_manager = TrustBundleManager::new(mock_client.clone(), trust_bundle);
+
+        let workload_server = WorkloadAPIServer::new(
+            mock_client,
+            Arc::new(mock_workload_attestation),
+            Arc::new(mock_node_attestation),
+            Arc::new(trust_bundle_manager),
+            Arc::new(mock_jwt_svid_validator),
+        );
+
+        let request = workload_api::request(jwtsvid_request());
+        // Unwrap error doesn't work because the debug trait is missing.
+        assert!(
+            workload_server.fetch_jwtsvid(request).await.is_err(),
+            "Expected an error"
+        );
+    }
+
+    #[tokio::test]
+    async fn fetch_jwtsvid_error_agent_attestation() {
+        let (
+            mut mock_client,
+            mut mock_workload_attestation,
+            mut mock_node_attestation,
+            mock_jwt_svid_validator,
+            trust_bundle,
+        ) = init();
+
+        let spiffe_id = "trust_domain/path".to_string();
+
+        let spiffe_id_tmp = spiffe_id.clone();
+        mock_client
+            .expect_create_workload_jwts()
+            .return_once(move |_| {
+                Ok(create_workload_jwts::Response {
+                    jwt_svids: vec![JWTSVIDCompact {
+                        token: "token".to_string(),
+                        spiffe_id: spiffe_id_tmp,
+                        expiry: 0,
+                        issued_at: 0,
+                    }],
+                    denied: Vec::new(),
+                    federates_with: Default::default(),
+                })
+            });
+        mock_workload_attestation
+            .expect_attest_workload()
+            .return_once(move |_| {
+                Err(Box::new(
+                    node_attestation_agent::k8s::error::Error::UnableToReadToken(
+                        std::io::Error::new(ErrorKind::Other, "dummy"),
+                    ),
+                ))
+            });
+
+        mock_node_attestation
+            .expect_get_attestation_token()
+            .return_once(move || Ok("".to_string()));
+
+        let mock_client = Arc::new(mock_client);
+        let trust_bundle_manager = TrustBundleManager::new(mock_client.clone(), trust_bundle);
+
+        let workload_server = WorkloadAPIServer::new(
+            mock_client,
+            Arc::new(mock_workload_attestation),
+            Arc::new(mock_node_attestation),
+            Arc::new(trust_bundle_manager),
+            Arc::new(mock_jwt_svid_validator),
+        );
+        let request = workload_api::request(jwtsvid_request());
+        // Unwrap error doesn't work because the debug trait is missing.
+        assert!(
+            workload_server.fetch_jwtsvid(request).await.is_err(),
+            "Expected an error"
+        );
+    }
+
+    #[tokio::test]
+    async fn fetch_jwt_bundles_listener_closing() {
+        let (
+            mock_client,
+            mock_workload_attestation,
+            mock_node_attestation,
+            mock_jwt_svid_validator,
+            trust_bundle,
+        ) = init();
+
+        let mock_client = Arc::new(mock_client);
+        let trust_bundle_manager = TrustBundleManager::new(mock_client.clone(), trust_bundle);
+        let (_shutdown_signal_tx, shutdown_signal_rx) = watch::channel(true);
+
+        let workload_server = WorkloadAPIServer::new(
+            mock_client,
+            Arc::new(mock_workload_attestation),
+            Arc::new(mock_node_attestation),
+            Arc::new(trust_bundle_manager),
+            Arc::new(mock_jwt_svid_validator),
+        )
+        .with_shutdown_signal(shutdown_signal_rx);
+
+        let request = workload_api::request(JwtBundlesRequest::default());
+        let status = match workload_server.fetch_jwt_bundles(request).await {
+            Ok(_) => panic!("Expected an error"),
+            Err(status) => status,
+        };
+        assert_eq!(status.code(), Code::Unavailable);
+    }
+
+    #[tokio::test]
+    async fn until_shutdown_closes_open_stream() {
+        let (shutdown_signal_tx, shutdown_signal_rx) = watch::channel(false);
+
+        let stream = futures_util::stream::iter(vec![Ok::<_, tonic::Status>(1)])
+            .chain(futures_util::stream::pending());
+        let mut stream = until_shutdown(Box::pin(stream), shutdown_signal_rx);
+
+        assert_eq!(stream.next().await.unwrap().unwrap(), 1);
+
+        shutdown_signal_tx.send(true).unwrap();
+
+        let status = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn until_shutdown_without_signal_forwards_stream() {
+        let (_, shutdown_signal_rx) = watch::channel(false);
+
+        let stream = futures_util::stream::iter(vec![Ok::<_, tonic::Status>(1), Ok(2)]);
+        let items = until_shutdown(Box::pin(stream), shutdown_signal_rx)
+            .map(Result::unwrap)
+            .collect::<Vec<i32>>()
+            .await;
+
+        assert_eq!(items, vec![1, 2]);
     }
 }
@@ -0,0 +1,152 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Limits of the requests to the workload API. A message is read up to the size limit before it
+// reaches the server, and the server must answer before the deadline. Only the time to open a
+// stream counts: the updates sent on it afterwards are not bound by the deadline.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use http::{Request, Response};
+use hyper::Body;
+use request_limits::{read_body, Error, Limits};
+use tokio::time::{self, Instant};
+use tonic::{body::BoxBody, Status};
+use tower_layer::Layer;
+use tower_service::Service;
+
+#[derive(Clone)]
+pub struct RequestLimitsLayer {
+    limits: Limits,
+}
+
+impl RequestLimitsLayer {
+    #[must_use]
+    pub fn new(limits: Limits) -> Self {
+        RequestLimitsLayer { limits }
+    }
+}
+
+impl<S> Layer<S> for RequestLimitsLayer {
+    type Service = RequestLimitsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestLimitsService {
+            inner,
+            limits: self.limits,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestLimitsService<S> {
+    inner: S,
+    limits: Limits,
+}
+
+impl<S> Service<Request<Body>> for RequestLimitsService<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Send,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<BoxBody>, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let limits = self.limits;
+
+        // The service polled ready handles this request, the clone is kept for the next one.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            let deadline = Instant::now() + limits.timeout();
+            let timeout = || Ok(to_status(&Error::Timeout(limits.timeout_ms)).to_http());
+
+            let (parts, body) = req.into_parts();
+            let read = read_body(body, limits.max_body_bytes);
+            let body = match time::timeout_at(deadline, read).await {
+                Ok(Ok(body)) => body,
+                Ok(Err(err)) => return Ok(to_status(&err).to_http()),
+                Err(_) => return timeout(),
+            };
+
+            let req = Request::from_parts(parts, Body::from(body));
+            match time::timeout_at(deadline, inner.call(req)).await {
+                Ok(response) => response,
+                Err(_) => timeout(),
+            }
+        })
+    }
+}
+
+fn to_status(err: &Error) -> Status {
+    match err {
+        Error::TooLarge(_) => Status::resource_exhausted(err.to_string()),
+        Error::Timeout(_) => Status::deadline_exceeded(err.to_string()),
+        Error::Body(_) => Status::invalid_argument(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, time::Duration};
+
+    use hyper::service::service_fn;
+    use tonic::{body::empty_body, Code};
+
+    use super::*;
+
+    async fn server(req: Request<Body>) -> Result<Response<BoxBody>, Infallible> {
+        if req.uri().path() == "/slow" {
+            time::sleep(Duration::from_secs(5)).await;
+        }
+
+        Ok(Response::new(empty_body()))
+    }
+
+    async fn call(path: &str, body: &'static str) -> Response<BoxBody> {
+        let limits = Limits {
+            max_body_bytes: 4,
+            timeout_ms: 1000,
+        };
+        let mut service = RequestLimitsLayer::new(limits).layer(service_fn(server));
+        let req = Request::post(path).body(Body::from(body)).unwrap();
+
+        service.call(req).await.unwrap()
+    }
+
+    fn code(response: &Response<BoxBody>) -> Code {
+        Status::from_header_map(response.headers()).map_or(Code::Ok, |status| status.code())
+    }
+
+    #[tokio::test]
+    async fn message_under_limit() {
+        let response = call("/", "0123").await;
+
+        assert_eq!(code(&response), Code::Ok);
+    }
+
+    #[tokio::test]
+    async fn message_over_limit() {
+        let response = call("/", "01234").await;
+
+        assert_eq!(code(&response), Code::ResourceExhausted);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn request_over_deadline() {
+        let response = call("/slow", "").await;
+
+        assert_eq!(code(&response), Code::DeadlineExceeded);
+    }
+}
@@ -0,0 +1,36 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Metrics of the requests of the workloads, shared by the servers of every listener of the agent. The
+// default metrics are not registered, for tests and agents without a metrics endpoint.
+
+use std::sync::Arc;
+
+use metrics::{Counter, Histogram, Registry, LATENCY_BUCKETS};
+
+#[derive(Default)]
+pub struct Metrics {
+    pub(crate) jwt_svids_issued: Arc<Counter>,
+    pub(crate) workload_attestation_failures: Arc<Counter>,
+    pub(crate) create_jwt_svids_duration: Arc<Histogram>,
+}
+
+impl Metrics {
+    #[must_use]
+    pub fn new(registry: &mut Registry) -> Self {
+        Metrics {
+            jwt_svids_issued: registry.counter(
+                "e4k_agent_jwt_svids_issued_total",
+                "JWT-SVIDs fetched from the server for the workloads.",
+            ),
+            workload_attestation_failures: registry.counter(
+                "e4k_agent_workload_attestation_failures_total",
+                "Workloads that failed attestation.",
+            ),
+            create_jwt_svids_duration: registry.histogram(
+                "e4k_agent_create_jwt_svids_duration_seconds",
+                "Time for the server to issue the JWT-SVIDs of a request.",
+                LATENCY_BUCKETS,
+            ),
+        }
+    }
+}
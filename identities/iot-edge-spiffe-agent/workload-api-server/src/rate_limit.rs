@@ -0,0 +1,139 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Rate limit of the `FetchJWTSVID` requests of each caller, so a workload sending requests in a loop
+// cannot use up the signing capacity of the server for the other workloads. Every caller gets a token
+// bucket: it is refilled at `requests_per_sec` and holds up to `burst` requests. Callers are processes,
+// keyed by PID and UID, or peer addresses for the TCP listener.
+
+use std::{collections::HashMap, net::IpAddr, sync::Mutex, time::Instant};
+
+// Full buckets are the same as no bucket, they are dropped past this number of callers so the buckets
+// of the processes that exited are forgotten.
+const CLEANUP_BUCKETS: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimits {
+    pub requests_per_sec: u32,
+    pub burst: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum CallerKey {
+    Process { pid: u32, uid: u32 },
+    Peer(IpAddr),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limits: RateLimits, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * f64::from(limits.requests_per_sec))
+            .min(f64::from(limits.burst));
+        self.updated = now;
+    }
+}
+
+pub struct RateLimiter {
+    limits: RateLimits,
+    buckets: Mutex<HashMap<CallerKey, Bucket>>,
+}
+
+impl RateLimiter {
+    #[must_use]
+    pub fn new(limits: RateLimits) -> Self {
+        RateLimiter {
+            limits,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[must_use]
+    pub fn limits(&self) -> RateLimits {
+        self.limits
+    }
+
+    // Takes a token from the bucket of the caller, false when it is empty.
+    pub(crate) fn check(&self, caller: CallerKey, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= CLEANUP_BUCKETS {
+            let limits = self.limits;
+            buckets.retain(|_, bucket| {
+                bucket.refill(limits, now);
+                bucket.tokens < f64::from(limits.burst)
+            });
+        }
+
+        let bucket = buckets.entry(caller).or_insert(Bucket {
+            tokens: f64::from(self.limits.burst),
+            updated: now,
+        });
+        bucket.refill(self.limits, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const CALLER: CallerKey = CallerKey::Process { pid: 1, uid: 1000 };
+
+    #[test]
+    fn check_burst_then_rate() {
+        let rate_limiter = RateLimiter::new(RateLimits {
+            requests_per_sec: 2,
+            burst: 3,
+        });
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(rate_limiter.check(CALLER, now));
+        }
+        assert!(!rate_limiter.check(CALLER, now));
+
+        // Other callers have their own bucket.
+        assert!(rate_limiter.check(CallerKey::Process { pid: 2, uid: 1000 }, now));
+        assert!(rate_limiter.check(CallerKey::Process { pid: 1, uid: 0 }, now));
+
+        let now = now + Duration::from_millis(500);
+        assert!(rate_limiter.check(CALLER, now));
+        assert!(!rate_limiter.check(CALLER, now));
+
+        // The bucket never holds more than the burst.
+        let now = now + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(rate_limiter.check(CALLER, now));
+        }
+        assert!(!rate_limiter.check(CALLER, now));
+    }
+
+    #[test]
+    fn check_cleans_up_full_buckets() {
+        let rate_limiter = RateLimiter::new(RateLimits {
+            requests_per_sec: 1,
+            burst: 1,
+        });
+        let now = Instant::now();
+
+        for pid in 0..u32::try_from(CLEANUP_BUCKETS).unwrap() {
+            assert!(rate_limiter.check(CallerKey::Process { pid, uid: 0 }, now));
+        }
+
+        let now = now + Duration::from_secs(1);
+        assert!(rate_limiter.check(CALLER, now));
+        assert_eq!(rate_limiter.buckets.lock().unwrap().len(), 1);
+    }
+}
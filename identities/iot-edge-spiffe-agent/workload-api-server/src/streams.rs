@@ -0,0 +1,237 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Limits of the streams opened on the workload API. A workload is only attested when it opens a
+// stream, the updates sent on it afterwards are trusted for as long as it stays open. Closing streams
+// after a maximum lifetime forces the workload to reconnect and be attested again, and closing the idle
+// ones frees the connections of workloads that stopped listening.
+
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures_util::{future, pin_mut, Stream, StreamExt};
+use tokio::time::{self, Instant};
+
+use crate::error::Error;
+
+// Upper bounds of the buckets of the age of the closed streams, the last bucket counts the older ones.
+pub const STREAM_AGE_BUCKETS_SECS: [u64; 5] = [1, 10, 60, 600, 3600];
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, tonic::Status>> + Send>>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StreamLimits {
+    // Not limited when not set.
+    pub max_lifetime: Option<Duration>,
+    // Time without any update sent on the stream, not limited when not set.
+    pub idle_timeout: Option<Duration>,
+}
+
+#[derive(Default, Debug)]
+pub struct StreamMetrics {
+    opened: AtomicU64,
+    active: AtomicU64,
+    age_counts: [AtomicU64; STREAM_AGE_BUCKETS_SECS.len() + 1],
+    max_lifetime_terminations: AtomicU64,
+    idle_terminations: AtomicU64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StreamMetricsSnapshot {
+    pub opened: u64,
+    pub active: u64,
+    // Number of closed streams per bucket of `STREAM_AGE_BUCKETS_SECS`.
+    pub age_counts: [u64; STREAM_AGE_BUCKETS_SECS.len() + 1],
+    pub max_lifetime_terminations: u64,
+    pub idle_terminations: u64,
+}
+
+impl StreamMetrics {
+    fn open(self: &Arc<Self>) -> OpenStream {
+        self.opened.fetch_add(1, Ordering::Relaxed);
+        self.active.fetch_add(1, Ordering::Relaxed);
+
+        OpenStream {
+            metrics: self.clone(),
+            opened_at: Instant::now(),
+        }
+    }
+
+    fn close(&self, age: Duration) {
+        let bucket = STREAM_AGE_BUCKETS_SECS
+            .iter()
+            .position(|upper_bound| age <= Duration::from_secs(*upper_bound))
+            .unwrap_or(STREAM_AGE_BUCKETS_SECS.len());
+
+        self.age_counts[bucket].fetch_add(1, Ordering::Relaxed);
+        self.active.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn snapshot(&self) -> StreamMetricsSnapshot {
+        let mut age_counts = [0; STREAM_AGE_BUCKETS_SECS.len() + 1];
+        for (count, age_count) in age_counts.iter_mut().zip(&self.age_counts) {
+            *count = age_count.load(Ordering::Relaxed);
+        }
+
+        StreamMetricsSnapshot {
+            opened: self.opened.load(Ordering::Relaxed),
+            active: self.active.load(Ordering::Relaxed),
+            age_counts,
+            max_lifetime_terminations: self.max_lifetime_terminations.load(Ordering::Relaxed),
+            idle_terminations: self.idle_terminations.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// Records the age of the stream when it is dropped, whether it ended, was closed by the limits or the
+// client went away.
+struct OpenStream {
+    metrics: Arc<StreamMetrics>,
+    opened_at: Instant,
+}
+
+impl Drop for OpenStream {
+    fn drop(&mut self) {
+        self.metrics.close(self.opened_at.elapsed());
+    }
+}
+
+// Forward the stream until one of the limits is reached, then end it with UNAVAILABLE so the client
+// reconnects.
+pub(crate) fn with_limits<T: Send + 'static>(
+    stream: ResponseStream<T>,
+    limits: StreamLimits,
+    metrics: Arc<StreamMetrics>,
+) -> ResponseStream<T> {
+    Box::pin(async_stream::stream! {
+        let mut stream = stream;
+        let open_stream = metrics.open();
+        let lifetime = sleep_for(limits.max_lifetime);
+        pin_mut!(lifetime);
+
+        loop {
+            let idle = sleep_for(limits.idle_timeout);
+            pin_mut!(idle);
+
+            match future::select(stream.next(), future::select(lifetime.as_mut(), idle)).await {
+                future::Either::Left((Some(item), _)) => yield item,
+                future::Either::Left((None, _)) => break,
+                future::Either::Right((future::Either::Left(_), _)) => {
+                    metrics.max_lifetime_terminations.fetch_add(1, Ordering::Relaxed);
+                    yield Err(Error::StreamLifetimeExceeded.into());
+                    break;
+                }
+                future::Either::Right((future::Either::Right(_), _)) => {
+                    metrics.idle_terminations.fetch_add(1, Ordering::Relaxed);
+                    yield Err(Error::StreamIdle.into());
+                    break;
+                }
+            }
+        }
+
+        drop(open_stream);
+    })
+}
+
+async fn sleep_for(duration: Option<Duration>) {
+    match duration {
+        Some(duration) => time::sleep(duration).await,
+        None => future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream;
+    use tonic::Code;
+
+    use super::*;
+
+    fn pending_after(items: Vec<u32>) -> ResponseStream<u32> {
+        Box::pin(stream::iter(items.into_iter().map(Ok)).chain(stream::pending()))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn with_limits_closes_stream_after_max_lifetime() {
+        let metrics = Arc::new(StreamMetrics::default());
+        let limits = StreamLimits {
+            max_lifetime: Some(Duration::from_secs(120)),
+            idle_timeout: None,
+        };
+        let mut stream = with_limits(pending_after(vec![1]), limits, metrics.clone());
+
+        assert_eq!(stream.next().await.unwrap().unwrap(), 1);
+        assert_eq!(metrics.snapshot().active, 1);
+
+        let status = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+        assert!(stream.next().await.is_none());
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.opened, 1);
+        assert_eq!(snapshot.active, 0);
+        assert_eq!(snapshot.max_lifetime_terminations, 1);
+        assert_eq!(snapshot.idle_terminations, 0);
+        assert_eq!(snapshot.age_counts, [0, 0, 0, 1, 0, 0]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn with_limits_closes_idle_stream() {
+        let metrics = Arc::new(StreamMetrics::default());
+        let limits = StreamLimits {
+            max_lifetime: Some(Duration::from_secs(3600)),
+            idle_timeout: Some(Duration::from_secs(5)),
+        };
+        let mut stream = with_limits(pending_after(vec![1, 2]), limits, metrics.clone());
+
+        assert_eq!(stream.next().await.unwrap().unwrap(), 1);
+        assert_eq!(stream.next().await.unwrap().unwrap(), 2);
+        let status = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+        assert!(stream.next().await.is_none());
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.idle_terminations, 1);
+        assert_eq!(snapshot.max_lifetime_terminations, 0);
+        assert_eq!(snapshot.age_counts, [0, 1, 0, 0, 0, 0]);
+    }
+
+    #[tokio::test]
+    async fn with_limits_records_dropped_stream() {
+        let metrics = Arc::new(StreamMetrics::default());
+        let items = with_limits(
+            Box::pin(stream::iter(vec![Ok(1), Ok(2)])),
+            StreamLimits::default(),
+            metrics.clone(),
+        )
+        .map(Result::unwrap)
+        .collect::<Vec<u32>>()
+        .await;
+        assert_eq!(items, vec![1, 2]);
+
+        // The client goes away before the end of the stream.
+        let mut stream = with_limits(
+            pending_after(vec![1]),
+            StreamLimits::default(),
+            metrics.clone(),
+        );
+        assert_eq!(stream.next().await.unwrap().unwrap(), 1);
+        assert_eq!(metrics.snapshot().active, 1);
+        drop(stream);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.opened, 2);
+        assert_eq!(snapshot.active, 0);
+        assert_eq!(snapshot.age_counts, [2, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            snapshot.max_lifetime_terminations + snapshot.idle_terminations,
+            0
+        );
+    }
+}
@@ -0,0 +1,59 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Workloads connecting over TCP, for runtimes that cannot mount the workload API socket in their
+// containers. The kernel gives no process for a TCP connection, so these workloads are not attested:
+// their selectors come from the config, per peer IP address or shared by any other peer. Any process
+// that can reach the port from an address gets the identities of its selectors.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::IpAddr,
+};
+
+#[derive(Clone, Debug, Default)]
+pub struct TcpSelectors {
+    peers: BTreeMap<IpAddr, BTreeSet<String>>,
+    // Selectors of the peers without their own, they are refused when empty.
+    default: BTreeSet<String>,
+}
+
+impl TcpSelectors {
+    #[must_use]
+    pub fn new(peers: BTreeMap<IpAddr, BTreeSet<String>>, default: BTreeSet<String>) -> Self {
+        TcpSelectors { peers, default }
+    }
+
+    pub(crate) fn get(&self, peer: IpAddr) -> Option<BTreeSet<String>> {
+        match self.peers.get(&peer) {
+            Some(selectors) => Some(selectors.clone()),
+            None if self.default.is_empty() => None,
+            None => Some(self.default.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_peer_or_default() {
+        let peer: IpAddr = "172.17.0.5".parse().unwrap();
+        let mut peers = BTreeMap::new();
+        peers.insert(peer, BTreeSet::from(["PODLABELS:app:legacy".to_string()]));
+        let default = BTreeSet::from(["PODLABELS:app:other".to_string()]);
+
+        let selectors = TcpSelectors::new(peers.clone(), default.clone());
+        assert_eq!(
+            selectors.get(peer).unwrap(),
+            BTreeSet::from(["PODLABELS:app:legacy".to_string()])
+        );
+        assert_eq!(
+            selectors.get("172.17.0.6".parse().unwrap()).unwrap(),
+            default
+        );
+
+        let selectors = TcpSelectors::new(peers, BTreeSet::new());
+        assert!(selectors.get("172.17.0.6".parse().unwrap()).is_none());
+    }
+}
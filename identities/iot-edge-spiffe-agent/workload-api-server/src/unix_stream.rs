@@ -3,6 +3,7 @@
 // This file won't be necessary once this the new version of tonic is released.
 // This file is a copy past from tonic example for UDS: https://github.com/hyperium/tonic/blob/v0.6.2/examples/src/uds/server.rs#L70
 use std::{
+    collections::BTreeSet,
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
@@ -31,6 +32,35 @@ pub struct UdsConnectInfo {
     pub peer_cred: Option<tokio::net::unix::UCred>,
 }
 
+// Users and groups allowed to connect to the workload API socket, checked when a connection is accepted
+// so the other processes of a shared node never get to the attestation. A process is allowed when its
+// UID or its GID is listed. Every process is allowed when both lists are empty.
+#[derive(Clone, Debug, Default)]
+pub struct PeerAllowlist {
+    uids: BTreeSet<u32>,
+    gids: BTreeSet<u32>,
+}
+
+impl PeerAllowlist {
+    #[must_use]
+    pub fn new(uids: BTreeSet<u32>, gids: BTreeSet<u32>) -> Self {
+        PeerAllowlist { uids, gids }
+    }
+
+    // Connections without credentials are only allowed when every process is.
+    #[must_use]
+    pub fn allows(&self, peer_cred: Option<(u32, u32)>) -> bool {
+        if self.uids.is_empty() && self.gids.is_empty() {
+            return true;
+        }
+
+        match peer_cred {
+            Some((uid, gid)) => self.uids.contains(&uid) || self.gids.contains(&gid),
+            None => false,
+        }
+    }
+}
+
 impl AsyncRead for UnixStream {
     fn poll_read(
         mut self: Pin<&mut Self>,
@@ -58,3 +88,21 @@ impl AsyncWrite for UnixStream {
         Pin::new(&mut self.0).poll_shutdown(cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peer_allowlist() {
+        let allowlist = PeerAllowlist::default();
+        assert!(allowlist.allows(Some((1000, 1000))));
+        assert!(allowlist.allows(None));
+
+        let allowlist = PeerAllowlist::new(BTreeSet::from([0]), BTreeSet::from([1001]));
+        assert!(allowlist.allows(Some((0, 0))));
+        assert!(allowlist.allows(Some((1000, 1001))));
+        assert!(!allowlist.allows(Some((1000, 1000))));
+        assert!(!allowlist.allows(None));
+    }
+}
@@ -0,0 +1,186 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// JWT-SVIDs fetched when the agent starts for the workloads already running on the node, so their
+// first request does not wait for attestation and issuance. Only the entries marked for prefetch
+// are fetched. They are cached per container, a process is matched to its container through its
+// cgroups without listing the pods of the node.
+
+use core_objects::get_epoch_time;
+use futures_util::{stream, StreamExt};
+use log::{info, warn};
+use server_agent_api::create_workload_jwts;
+use workload_api::generated::Jwtsvid;
+use workload_attestation::{WorkloadAttributes, WorkloadId};
+use zeroize::Zeroizing;
+
+use crate::{jwt_svid_cache::JWTSVIDCache, WorkloadAPIServer};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct PrefetchKey {
+    workload_id: WorkloadId,
+    audiences: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct PrefetchCache {
+    jwt_svids: JWTSVIDCache<PrefetchKey>,
+}
+
+impl PrefetchCache {
+    // Prefetched JWT-SVIDs are only returned for requests naming their SPIFFE ID: a request without
+    // one expects the JWT-SVIDs of all the entries of the workload, not only the prefetched ones.
+    fn get(
+        &self,
+        workload_id: WorkloadId,
+        audiences: &[String],
+        spiffe_id: &str,
+        now: u64,
+    ) -> Option<Vec<Jwtsvid>> {
+        let key = PrefetchKey {
+            workload_id,
+            audiences: audiences.to_vec(),
+        };
+        let (_pod_uid, svids) = self.jwt_svids.get(&key, now)?;
+
+        let svids: Vec<Jwtsvid> = svids
+            .into_iter()
+            .filter(|svid| svid.spiffe_id == spiffe_id)
+            .collect();
+
+        if svids.is_empty() {
+            None
+        } else {
+            Some(svids)
+        }
+    }
+}
+
+impl WorkloadAPIServer {
+    // Attests the workloads running on the node and fetches their JWT-SVIDs for `audiences`,
+    // `concurrency` workloads at a time. Returns the number of workloads that got JWT-SVIDs.
+    pub async fn warm_up(&self, audiences: &[String], concurrency: usize) -> usize {
+        let workloads = match self.workload_attestation.attest_node_workloads().await {
+            Ok(workloads) => workloads,
+            Err(err) => {
+                warn!(
+                    "Could not list the workloads of the node to warm up: {}",
+                    err
+                );
+                return 0;
+            }
+        };
+
+        let attestation_token = match self.node_attestation.get_attestation_token().await {
+            Ok(attestation_token) => Zeroizing::new(attestation_token),
+            Err(err) => {
+                warn!("Could not get an attestation token to warm up: {}", err);
+                return 0;
+            }
+        };
+
+        info!("Warming up the JWT-SVIDs of {} workloads", workloads.len());
+        stream::iter(workloads)
+            .map(|(workload_id, workload_attributes)| {
+                self.prefetch_jwtsvids(
+                    workload_id,
+                    workload_attributes,
+                    audiences,
+                    &attestation_token,
+                )
+            })
+            .buffer_unordered(concurrency.max(1))
+            .filter(|prefetched| futures_util::future::ready(*prefetched))
+            .count()
+            .await
+    }
+
+    async fn prefetch_jwtsvids(
+        &self,
+        workload_id: WorkloadId,
+        workload_attributes: WorkloadAttributes,
+        audiences: &[String],
+        attestation_token: &str,
+    ) -> bool {
+        let pod_uid = if self.pod_identity_pinning {
+            workload_attributes.pod_uid
+        } else {
+            None
+        };
+
+        let request = create_workload_jwts::Request {
+            workload_spiffe_id: None,
+            audiences: audiences.to_vec(),
+            selectors: workload_attributes.selectors,
+            attestation_token: attestation_token.to_string(),
+            pod_uid,
+            agent_build: self.agent_build.clone(),
+            prefetch_only: true,
+            bundles_only: false,
+        };
+
+        let jwts_response = match self
+            .spiffe_server_client
+            .create_workload_jwts(request)
+            .await
+        {
+            Ok(jwts_response) => jwts_response,
+            Err(err) => {
+                warn!(
+                    "Could not prefetch JWT-SVIDs of container {} in pod {}: {}",
+                    workload_id.container_id, workload_id.pod_uid, err
+                );
+                return false;
+            }
+        };
+
+        if jwts_response.jwt_svids.is_empty() {
+            return false;
+        }
+
+        let svids = jwts_response
+            .jwt_svids
+            .iter()
+            .map(|jwt_svid| Jwtsvid {
+                spiffe_id: jwt_svid.spiffe_id.to_string(),
+                svid: jwt_svid.token.clone(),
+            })
+            .collect();
+
+        let pod_uid = workload_id.pod_uid.clone();
+        let key = PrefetchKey {
+            workload_id,
+            audiences: audiences.to_vec(),
+        };
+        self.prefetch_cache.jwt_svids.insert(
+            key,
+            pod_uid,
+            svids,
+            &jwts_response.jwt_svids,
+            get_epoch_time(),
+        );
+
+        true
+    }
+
+    pub(crate) async fn get_prefetched_jwtsvids(
+        &self,
+        pid: u32,
+        spiffe_id: &str,
+        audiences: &[String],
+    ) -> Option<Vec<Jwtsvid>> {
+        if spiffe_id.is_empty() || self.prefetch_cache.jwt_svids.is_empty() {
+            return None;
+        }
+
+        let workload_id = match self.workload_attestation.get_workload_id(pid).await {
+            Ok(workload_id) => workload_id,
+            Err(err) => {
+                warn!("Could not find the container of process {}: {}", pid, err);
+                return None;
+            }
+        };
+
+        self.prefetch_cache
+            .get(workload_id, audiences, spiffe_id, get_epoch_time())
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Attestations of the containers, reused by the next requests of the same container instead of going
+// through the pod lookup again. The processes are still mapped to their container from their cgroup on
+// every request, so a recycled PID never gets the attestation of another container. The entries of a
+// pod are dropped whenever the pod watch reports it changed or got deleted, its selectors may differ.
+
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::{WorkloadAttributes, WorkloadId};
+
+struct CachedAttestation {
+    attributes: WorkloadAttributes,
+    expires_at: u64,
+}
+
+pub(super) struct AttestationCache {
+    entries: Mutex<HashMap<WorkloadId, CachedAttestation>>,
+    ttl_secs: u64,
+}
+
+impl AttestationCache {
+    pub(super) fn new(ttl_secs: u64) -> Self {
+        AttestationCache {
+            entries: Mutex::new(HashMap::new()),
+            ttl_secs,
+        }
+    }
+
+    pub(super) fn get(&self, workload_id: &WorkloadId, now: u64) -> Option<WorkloadAttributes> {
+        let mut entries = self.entries.lock().unwrap();
+
+        match entries.get(workload_id) {
+            Some(cached) if cached.expires_at > now => Some(cached.attributes.clone()),
+            Some(_) => {
+                entries.remove(workload_id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub(super) fn insert(&self, workload_id: WorkloadId, attributes: WorkloadAttributes, now: u64) {
+        if self.ttl_secs == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        // Containers that are gone are never requested again, drop them with the expired entries.
+        entries.retain(|_, cached| cached.expires_at > now);
+        entries.insert(
+            workload_id,
+            CachedAttestation {
+                attributes,
+                expires_at: now + self.ttl_secs,
+            },
+        );
+    }
+
+    pub(super) fn remove_pod(&self, pod_uid: &str) {
+        let mut entries = self.entries.lock().unwrap();
+
+        entries.retain(|workload_id, _| workload_id.pod_uid != pod_uid);
+    }
+
+    pub(super) fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+
+        entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workload_id(pod_uid: &str, container_id: &str) -> WorkloadId {
+        WorkloadId {
+            pod_uid: pod_uid.to_string(),
+            container_id: container_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn get_until_expiry() {
+        let cache = AttestationCache::new(10);
+        cache.insert(workload_id("pod", "a"), WorkloadAttributes::default(), 100);
+
+        assert!(cache.get(&workload_id("pod", "a"), 109).is_some());
+        assert!(cache.get(&workload_id("pod", "b"), 109).is_none());
+        assert!(cache.get(&workload_id("pod", "a"), 110).is_none());
+    }
+
+    #[test]
+    fn remove_pod() {
+        let cache = AttestationCache::new(10);
+        cache.insert(workload_id("pod1", "a"), WorkloadAttributes::default(), 100);
+        cache.insert(workload_id("pod1", "b"), WorkloadAttributes::default(), 100);
+        cache.insert(workload_id("pod2", "c"), WorkloadAttributes::default(), 100);
+
+        cache.remove_pod("pod1");
+
+        assert!(cache.get(&workload_id("pod1", "a"), 100).is_none());
+        assert!(cache.get(&workload_id("pod1", "b"), 100).is_none());
+        assert!(cache.get(&workload_id("pod2", "c"), 100).is_some());
+    }
+
+    #[test]
+    fn disabled() {
+        let cache = AttestationCache::new(0);
+        cache.insert(workload_id("pod", "a"), WorkloadAttributes::default(), 100);
+
+        assert!(cache.get(&workload_id("pod", "a"), 100).is_none());
+    }
+}
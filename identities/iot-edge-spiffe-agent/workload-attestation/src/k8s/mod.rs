@@ -6,45 +6,55 @@
 //! podname, poduid, node name, etc...
 //! How it works:
 //! The workloads reach the workload API through Unix Domain Socket (UDS). From the UDS we get the PID.
-//! With the PID we get the cgroups. We regex the cgroups to get the pod uid and the container id.
+//! With the PID we get the cgroups. We parse the cgroup path to get the pod uid and the container id.
 //! Then we call kubernetes API to get the list of all the pod inside the node and we match the pod with the uid.
 //! Once we find the pod we extract all the data (selectors)
+//! The attestations are cached per container, see `attestation_cache`.
+//! The pods are looked up in a cache kept up to date by a watch. The pods are listed from the API only
+//! when the cache does not have the container yet, e.g. before the kubelet reports it started.
 
+mod attestation_cache;
 pub mod error;
+mod pod_cache;
 
 use agent_config::WorkloadAttestationConfigK8s;
 use cgroups_rs::cgroup;
-use core_objects::{build_selector_string, WorkloadSelectorType};
-use k8s_openapi::{
-    api::core::v1::{ContainerStatus, Pod},
-    url::Url,
-};
+use core_objects::{build_selector_string, get_epoch_time, WorkloadSelectorType};
+use k8s_openapi::api::core::v1::{ContainerStatus, Pod};
 use log::{debug, info};
 use regex::Regex;
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
+    future::Future,
+    sync::Arc,
     time::Duration,
 };
 use tokio::time;
 
 use crate::k8s::error::MissingField;
-use crate::WorkloadAttributes;
+use crate::{Caller, WorkloadAttributes, WorkloadId};
 
 use super::WorkloadAttestation as WorkloadAttestationTrait;
 
 #[cfg(not(any(test, feature = "tests")))]
-use kube::{Api, Client};
+use kube::{runtime::watcher::watcher, Api, Client};
 #[cfg(any(test, feature = "tests"))]
-use mock_kube::{Api, Client};
+use mock_kube::{watcher, Api, Client};
 
 use kube::{api::ListParams, core::ObjectList};
 
+use attestation_cache::AttestationCache;
 use error::Error;
+use pod_cache::PodCache;
 
+// Controller of the cgroup path with cgroups v1. With cgroups v2, the unified hierarchy has no
+// controller.
 const PID_CGROUP: &str = "pids";
+const UNIFIED_CGROUP: &str = "";
 
-// Regex taken from spire: https://github.com/spiffe/spire/blob/9fab47f081ca94517c1e0ac166f4afb2929f8ee8/pkg/agent/plugin/workloadattestor/k8s/k8s.go#L579
-const REGEX_GET_UID: &str = "[[:punct:]]pod([[:xdigit:]]{8}[[:punct:]][[:xdigit:]]{4}[[:punct:]][[:xdigit:]]{4}[[:punct:]][[:xdigit:]]{4}[[:punct:]][[:xdigit:]]{12})[[:punct:]](?:[[:^punct:]]+[[:punct:]])*([[:^punct:]]+)$";
+// Cgroup of the pod, "pod<uid>" with the cgroupfs driver and "kubepods-<qos>-pod<uid>.slice" with the
+// systemd driver, where the dashes of the uid are underscores.
+const REGEX_POD_CGROUP: &str = "^(?:.*[-_])?pod([[:xdigit:]]{8}[-_][[:xdigit:]]{4}[-_][[:xdigit:]]{4}[-_][[:xdigit:]]{4}[-_][[:xdigit:]]{12})(?:\\.slice)?$";
 
 #[derive(Clone, Debug, Default)]
 struct SelectorInfo {
@@ -54,6 +64,7 @@ struct SelectorInfo {
     container_image: String,
     node_name: String,
     pod_labels: BTreeMap<String, String>,
+    pod_annotations: BTreeMap<String, String>,
     pod_owner: BTreeSet<String>,
     pod_owner_uid: BTreeSet<String>,
     pod_uid: String,
@@ -73,7 +84,10 @@ struct ContainerIdentifiers {
 pub struct WorkloadAttestation {
     node_name: String,
     client: Client,
-    regex_get_uid: Regex,
+    pod_cache: Arc<PodCache>,
+    attestation_cache: Arc<AttestationCache>,
+    allowed_pod_annotation_keys: BTreeSet<String>,
+    regex_pod_cgroup: Regex,
     max_poll_attempt: usize,
     poll_retry_interval_ms: u64,
 }
@@ -81,17 +95,33 @@ pub struct WorkloadAttestation {
 impl WorkloadAttestation {
     #[must_use]
     pub fn new(config: &WorkloadAttestationConfigK8s, node_name: String, client: Client) -> Self {
-        let regex_get_uid = Regex::new(REGEX_GET_UID).unwrap();
+        let regex_pod_cgroup = Regex::new(REGEX_POD_CGROUP).unwrap();
 
         WorkloadAttestation {
             node_name,
             client,
-            regex_get_uid,
+            pod_cache: Arc::new(PodCache::default()),
+            attestation_cache: Arc::new(AttestationCache::new(config.attestation_cache_ttl_secs)),
+            allowed_pod_annotation_keys: config.allowed_pod_annotation_keys.clone(),
+            regex_pod_cgroup,
             max_poll_attempt: config.max_poll_attempt,
             poll_retry_interval_ms: config.poll_retry_interval_ms,
         }
     }
 
+    // Keeps the pod cache up to date, to be spawned along with the attestation.
+    pub fn watch_pods(&self) -> impl Future<Output = ()> + Send + 'static {
+        let pods: Api<Pod> = Api::default_namespaced(self.client.clone());
+        let mut list_param = ListParams::default();
+        list_param.field_selector = Some(format!("spec.nodeName={}", self.node_name));
+
+        pod_cache::run(
+            self.pod_cache.clone(),
+            self.attestation_cache.clone(),
+            watcher(pods, list_param),
+        )
+    }
+
     // For unit test, remove dependency to cgroup call.
     fn get_container_id_and_pod_uid_from_cgroup(
         &self,
@@ -99,18 +129,41 @@ impl WorkloadAttestation {
     ) -> Result<(String, String), Error> {
         let path = cgroups
             .get(PID_CGROUP)
-            .ok_or(Error::NoPIDcgroup)?
-            .trim_end_matches(".scope");
-        let captures = self
-            .regex_get_uid
-            .captures(path)
-            .ok_or_else(|| Error::ExtractPodUIDandContainerID(path.to_string()))?;
+            .or_else(|| cgroups.get(UNIFIED_CGROUP))
+            .ok_or(Error::NoPIDcgroup)?;
 
-        let pod_uid = canonicalize_pod_uid(&captures[1]);
+        self.parse_cgroup_path(path)
+            .ok_or_else(|| Error::ExtractPodUIDandContainerID(path.to_string()))
+    }
 
-        let container_id = captures[2].to_string();
+    // The container cgroup is right below the pod cgroup, whatever the cgroups above them, e.g. with
+    // Docker in Docker. The runtimes may create sub-cgroups below the container cgroup, like crun with
+    // cgroups v2. The container cgroup is named after the container id, with the prefix and suffix of
+    // the runtime with the systemd driver: "docker-<id>.scope", "cri-containerd-<id>.scope" or
+    // "crio-<id>.scope".
+    fn parse_cgroup_path(&self, path: &str) -> Option<(String, String)> {
+        let mut components = path.split('/').filter(|component| !component.is_empty());
+
+        let pod_uid = components.by_ref().find_map(|component| {
+            self.regex_pod_cgroup
+                .captures(component)
+                .map(|captures| canonicalize_pod_uid(&captures[1]))
+        })?;
+
+        let container_id = components
+            .next()?
+            .trim_end_matches(".scope")
+            .rsplit('-')
+            .next()?;
+        if container_id.is_empty()
+            || !container_id
+                .chars()
+                .all(|character| character.is_ascii_alphanumeric())
+        {
+            return None;
+        }
 
-        Ok((container_id, pod_uid))
+        Some((container_id.to_string(), pod_uid))
     }
 
     async fn get_pod_list(&self) -> Result<ObjectList<Pod>, Error> {
@@ -132,6 +185,12 @@ impl WorkloadAttestation {
         container_id: &str,
         pod_uid: &str,
     ) -> Result<(Pod, ContainerIdentifiers), Error> {
+        if let Some(pod) = self.pod_cache.get(pod_uid) {
+            if let Some(container_identifiers) = is_container_ready_in_pod(&pod, container_id) {
+                return Ok((pod, container_identifiers));
+            }
+        }
+
         let mut attempt = 0;
 
         loop {
@@ -172,12 +231,87 @@ impl WorkloadAttestation {
         cgroups: HashMap<String, String>,
     ) -> Result<WorkloadAttributes, Error> {
         let (container_id, pod_uid) = self.get_container_id_and_pod_uid_from_cgroup(&cgroups)?;
+        let workload_id = WorkloadId {
+            pod_uid,
+            container_id,
+        };
+
+        if let Some(workload_attributes) =
+            self.attestation_cache.get(&workload_id, get_epoch_time())
+        {
+            debug!("Using the cached attestation of {:?}", workload_id);
+            return Ok(workload_attributes);
+        }
+
+        let (pod, container_identifier) = self
+            .get_pod(&workload_id.container_id, &workload_id.pod_uid)
+            .await?;
+
+        let selector_info =
+            get_selector_info(pod, container_identifier, &self.allowed_pod_annotation_keys)?;
+        let workload_attributes = get_workload_attributes_from_select_info(&selector_info);
 
-        let (pod, container_identifier) = self.get_pod(&container_id, &pod_uid).await?;
+        self.attestation_cache
+            .insert(workload_id, workload_attributes.clone(), get_epoch_time());
+
+        Ok(workload_attributes)
+    }
+
+    async fn attest_node_workloads_inner(
+        &self,
+    ) -> Result<Vec<(WorkloadId, WorkloadAttributes)>, Error> {
+        let pod_list = match self.pod_cache.list() {
+            Some(pod_list) => pod_list,
+            None => self.get_pod_list().await?.items,
+        };
 
-        let selector_info = get_selector_info(pod, container_identifier)?;
+        let mut workloads = Vec::new();
+        for pod in pod_list {
+            let container_statuses = pod
+                .status
+                .as_ref()
+                .and_then(|status| status.container_statuses.clone())
+                .unwrap_or_default();
+
+            for container_status in container_statuses {
+                // Containers not started yet are attested on their first request instead.
+                let container_id = if let Some(container_id) = get_container_id(&container_status) {
+                    container_id
+                } else {
+                    continue;
+                };
+
+                let container_identifiers = ContainerIdentifiers {
+                    name: container_status.name.clone(),
+                    image: container_status.image.clone(),
+                };
+                let selector_info = match get_selector_info(
+                    pod.clone(),
+                    container_identifiers,
+                    &self.allowed_pod_annotation_keys,
+                ) {
+                    Ok(selector_info) => selector_info,
+                    Err(err) => {
+                        debug!("Skipping container {}: {}", container_id, err);
+                        continue;
+                    }
+                };
+
+                let workload_id = WorkloadId {
+                    pod_uid: selector_info.pod_uid.clone(),
+                    container_id,
+                };
+                let workload_attributes = get_workload_attributes_from_select_info(&selector_info);
+                self.attestation_cache.insert(
+                    workload_id.clone(),
+                    workload_attributes.clone(),
+                    get_epoch_time(),
+                );
+                workloads.push((workload_id, workload_attributes));
+            }
+        }
 
-        Ok(get_workload_attributes_from_select_info(&selector_info))
+        Ok(workloads)
     }
 }
 
@@ -185,15 +319,49 @@ impl WorkloadAttestation {
 impl WorkloadAttestationTrait for WorkloadAttestation {
     async fn attest_workload(
         &self,
-        pid: u32,
+        caller: Caller,
     ) -> Result<WorkloadAttributes, Box<dyn std::error::Error + Send>> {
-        let cgroups =
-            cgroup::get_cgroups_relative_paths_by_pid(pid).map_err(|err| Box::new(err) as _)?;
+        let cgroups = cgroup::get_cgroups_relative_paths_by_pid(caller.pid)
+            .map_err(|err| Box::new(err) as _)?;
         // For unit test, we remove dependency to cgroup call.
         self.attest_workload_inner(cgroups)
             .await
             .map_err(|err| Box::new(err) as _)
     }
+
+    async fn get_pod_uid(&self, pid: u32) -> Result<String, Box<dyn std::error::Error + Send>> {
+        let cgroups =
+            cgroup::get_cgroups_relative_paths_by_pid(pid).map_err(|err| Box::new(err) as _)?;
+        let (_container_id, pod_uid) = self
+            .get_container_id_and_pod_uid_from_cgroup(&cgroups)
+            .map_err(|err| Box::new(err) as _)?;
+
+        Ok(pod_uid)
+    }
+
+    async fn get_workload_id(
+        &self,
+        pid: u32,
+    ) -> Result<WorkloadId, Box<dyn std::error::Error + Send>> {
+        let cgroups =
+            cgroup::get_cgroups_relative_paths_by_pid(pid).map_err(|err| Box::new(err) as _)?;
+        let (container_id, pod_uid) = self
+            .get_container_id_and_pod_uid_from_cgroup(&cgroups)
+            .map_err(|err| Box::new(err) as _)?;
+
+        Ok(WorkloadId {
+            pod_uid,
+            container_id,
+        })
+    }
+
+    async fn attest_node_workloads(
+        &self,
+    ) -> Result<Vec<(WorkloadId, WorkloadAttributes)>, Box<dyn std::error::Error + Send>> {
+        self.attest_node_workloads_inner()
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
 }
 
 // canonicalizePodUID converts a Pod UID, as represented in a cgroup path, into
@@ -252,6 +420,11 @@ fn get_workload_attributes_from_select_info(selector_info: &SelectorInfo) -> Wor
         &selector_info.pod_labels,
         &WorkloadSelectorType::PodLabels,
     );
+    push_map_into_selectors(
+        &mut selectors,
+        &selector_info.pod_annotations,
+        &WorkloadSelectorType::PodAnnotations,
+    );
     push_set_into_selectors(
         &mut selectors,
         &selector_info.pod_owner,
@@ -279,7 +452,10 @@ fn get_workload_attributes_from_select_info(selector_info: &SelectorInfo) -> Wor
     );
     debug!("Found the following selectors for workload {:?}", selectors);
 
-    WorkloadAttributes { selectors }
+    WorkloadAttributes {
+        selectors,
+        pod_uid: Some(selector_info.pod_uid.clone()),
+    }
 }
 
 fn push_map_into_selectors<'a, A>(
@@ -312,6 +488,7 @@ fn push_set_into_selectors<'a, A>(
 fn get_selector_info(
     pod: Pod,
     container_identifiers: ContainerIdentifiers,
+    allowed_pod_annotation_keys: &BTreeSet<String>,
 ) -> Result<SelectorInfo, Error> {
     let pod_spec = pod.spec.ok_or(Error::MissingField(MissingField::PodSpec))?;
 
@@ -338,6 +515,14 @@ fn get_selector_info(
             .metadata
             .labels
             .ok_or(Error::MissingField(MissingField::PodLabels))?,
+        // Unlike labels, pods often have no annotations at all.
+        pod_annotations: pod
+            .metadata
+            .annotations
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(key, _)| allowed_pod_annotation_keys.contains(key))
+            .collect(),
         node_name: pod_spec
             .node_name
             .ok_or(Error::MissingField(MissingField::NodeName))?,
@@ -383,24 +568,17 @@ fn get_selector_info(
 }
 
 fn container_status_match_container_id(status: &ContainerStatus, container_id: &str) -> bool {
-    let status_container_id_url = if let Some(status_container_id_url) = &status.container_id {
-        status_container_id_url
-    } else {
-        return false;
-    };
+    get_container_id(status).map_or(false, |status_container_id| {
+        status_container_id == container_id
+    })
+}
 
-    let status_container_id_url =
-        if let Ok(status_container_id_url) = Url::parse(status_container_id_url) {
-            status_container_id_url
-        } else {
-            return false;
-        };
+// The status holds the id with the runtime as scheme, like docker://<id>, containerd://<id> or
+// cri-o://<id>.
+fn get_container_id(status: &ContainerStatus) -> Option<String> {
+    let (_runtime, container_id) = status.container_id.as_ref()?.split_once("://")?;
 
-    if let Some(host) = status_container_id_url.host() {
-        host.to_string() == container_id
-    } else {
-        false
-    }
+    (!container_id.is_empty()).then(|| container_id.to_string())
 }
 
 fn is_container_ready_in_pod(pod: &Pod, container_id: &str) -> Option<ContainerIdentifiers> {
@@ -472,7 +650,7 @@ fn get_container_identitifiers(
 
 #[cfg(test)]
 mod tests {
-    use kube::core::ListMeta;
+    use kube::{core::ListMeta, runtime::watcher::Event};
     use matches::assert_matches;
     use mock_kube::{get_pods, CONTAINER_ID, INIT_CONTAINER_ID, POD_UID};
 
@@ -482,6 +660,8 @@ mod tests {
         let workload_attestation_config = WorkloadAttestationConfigK8s {
             max_poll_attempt: 2,
             poll_retry_interval_ms: 0,
+            attestation_cache_ttl_secs: 30,
+            allowed_pod_annotation_keys: BTreeSet::new(),
         };
 
         let client = Client::try_default().await.unwrap();
@@ -509,11 +689,15 @@ mod tests {
         cgroups.insert("pids".to_string(), path);
 
         workload_attestation.client.queue_response(pod_list).await;
-        let workload_selectors = workload_attestation
+        let workload_attributes = workload_attestation
             .attest_workload_inner(cgroups)
             .await
-            .unwrap()
-            .selectors;
+            .unwrap();
+        assert_eq!(
+            workload_attributes.pod_uid,
+            Some("75dbabec-9510-11ec-b909-0242ac120002".to_string())
+        );
+        let workload_selectors = workload_attributes.selectors;
 
         let namespace = build_selector_string(&WorkloadSelectorType::Namespace, "namespace");
         assert!(workload_selectors.contains(&namespace));
@@ -566,6 +750,80 @@ mod tests {
         assert!(workload_selectors.contains(&init_image_count));
     }
 
+    #[tokio::test]
+    async fn attest_workload_inner_cached() {
+        let mut workload_attestation = init_selector_test().await;
+
+        let pod_list = ObjectList {
+            metadata: ListMeta::default(),
+            items: vec![get_pods()],
+        };
+        workload_attestation.client.queue_response(pod_list).await;
+
+        let mut cgroups = HashMap::new();
+        let path = format!("/kubepods/besteffort/pod{}/{}", POD_UID, CONTAINER_ID);
+        cgroups.insert("pids".to_string(), path);
+
+        let workload_attributes = workload_attestation
+            .attest_workload_inner(cgroups.clone())
+            .await
+            .unwrap();
+
+        // Only one response was queued, the second attestation does not list the pods.
+        let cached_workload_attributes = workload_attestation
+            .attest_workload_inner(cgroups.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            cached_workload_attributes.selectors,
+            workload_attributes.selectors
+        );
+
+        // Once the pod changes, it is looked up again.
+        workload_attestation.attestation_cache.remove_pod(POD_UID);
+        workload_attestation.client.queue_response("dummy").await;
+        let error = workload_attestation
+            .attest_workload_inner(cgroups)
+            .await
+            .unwrap_err();
+        assert_matches!(error, Error::ListingPods { .. });
+    }
+
+    #[tokio::test]
+    async fn attest_node_workloads_inner_happy_path() {
+        let mut workload_attestation = init_selector_test().await;
+
+        // Pods missing fields are skipped
+        let mut pod1 = get_pods();
+        let pod2 = get_pods();
+        pod1.spec = None;
+
+        let pod_list = ObjectList {
+            metadata: ListMeta::default(),
+            items: vec![pod1, pod2],
+        };
+
+        workload_attestation.client.queue_response(pod_list).await;
+        let workloads = workload_attestation
+            .attest_node_workloads_inner()
+            .await
+            .unwrap();
+
+        // Init containers are done by the time workloads run, only the containers are attested.
+        assert_eq!(workloads.len(), 1);
+        let (workload_id, workload_attributes) = &workloads[0];
+        assert_eq!(
+            workload_id,
+            &WorkloadId {
+                pod_uid: POD_UID.to_string(),
+                container_id: CONTAINER_ID.to_string(),
+            }
+        );
+        let container_name =
+            build_selector_string(&WorkloadSelectorType::ContainerName, "container_name");
+        assert!(workload_attributes.selectors.contains(&container_name));
+    }
+
     #[test]
     fn get_container_identitifiers_no_match() {
         let container_status = ContainerStatus {
@@ -721,6 +979,106 @@ mod tests {
         assert_eq!(pod_uid, POD_UID);
     }
 
+    // Cgroup paths of the container processes, by runtime and cgroup driver.
+    #[tokio::test]
+    async fn get_container_id_and_pod_uid_from_cgroup_fixtures() {
+        let workload_attestation = init_selector_test().await;
+        let pod_uid_underscores = POD_UID.replace('-', "_");
+
+        let fixtures = [
+            // Docker, cgroups v1 with the cgroupfs driver.
+            (
+                PID_CGROUP,
+                format!("/kubepods/besteffort/pod{}/{}", POD_UID, CONTAINER_ID),
+            ),
+            // Docker, cgroups v1 with the systemd driver.
+            (
+                PID_CGROUP,
+                format!(
+                    "/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod{}.slice/docker-{}.scope",
+                    pod_uid_underscores, CONTAINER_ID
+                ),
+            ),
+            // Containerd, cgroups v2 with the systemd driver.
+            (
+                UNIFIED_CGROUP,
+                format!(
+                    "/kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-pod{}.slice/cri-containerd-{}.scope",
+                    pod_uid_underscores, CONTAINER_ID
+                ),
+            ),
+            // Containerd, cgroups v2 with the cgroupfs driver.
+            (
+                UNIFIED_CGROUP,
+                format!("/kubepods/burstable/pod{}/{}", POD_UID, CONTAINER_ID),
+            ),
+            // CRI-O with crun, cgroups v2 with the systemd driver and a sub-cgroup of the container.
+            (
+                UNIFIED_CGROUP,
+                format!(
+                    "/kubepods.slice/kubepods-pod{}.slice/crio-{}.scope/container",
+                    pod_uid_underscores, CONTAINER_ID
+                ),
+            ),
+            // Cgroups v2 seen from the cgroup namespace of another container.
+            (
+                UNIFIED_CGROUP,
+                format!(
+                    "/../../kubepods-besteffort-pod{}.slice/cri-containerd-{}.scope",
+                    pod_uid_underscores, CONTAINER_ID
+                ),
+            ),
+        ];
+
+        for (controller, path) in fixtures {
+            let mut cgroups = HashMap::new();
+            cgroups.insert(controller.to_string(), path.clone());
+
+            let (container_id, pod_uid) = workload_attestation
+                .get_container_id_and_pod_uid_from_cgroup(&cgroups)
+                .unwrap_or_else(|err| panic!("{}: {}", path, err));
+            assert_eq!(container_id, CONTAINER_ID, "{}", path);
+            assert_eq!(pod_uid, POD_UID, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn get_container_id_and_pod_uid_from_cgroup_not_in_container() {
+        let workload_attestation = init_selector_test().await;
+
+        for path in [
+            "/system.slice/containerd.service",
+            "/kubepods.slice/kubepods-besteffort.slice",
+            &format!("/kubepods/besteffort/pod{}", POD_UID),
+        ] {
+            let mut cgroups = HashMap::new();
+            cgroups.insert(UNIFIED_CGROUP.to_string(), path.to_string());
+
+            let error = workload_attestation
+                .get_container_id_and_pod_uid_from_cgroup(&cgroups)
+                .unwrap_err();
+            assert_matches!(error, Error::ExtractPodUIDandContainerID(_));
+        }
+    }
+
+    #[test]
+    fn get_container_id_runtimes() {
+        for runtime in ["docker", "containerd", "cri-o"] {
+            let status = ContainerStatus {
+                container_id: Some(format!("{}://{}", runtime, CONTAINER_ID)),
+                ..Default::default()
+            };
+            assert_eq!(get_container_id(&status), Some(CONTAINER_ID.to_string()));
+        }
+
+        let status = ContainerStatus {
+            container_id: Some("containerd://".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(get_container_id(&status), None);
+        assert_eq!(get_container_id(&ContainerStatus::default()), None);
+    }
+
     #[tokio::test]
     async fn get_container_id_and_pod_uid_from_cgroup_error_no_pid_cgroup() {
         let workload_attestation = init_selector_test().await;
@@ -774,6 +1132,58 @@ mod tests {
         assert_eq!(container_identifiers.image, "image");
     }
 
+    #[tokio::test]
+    async fn get_pod_from_cache() {
+        let workload_attestation = init_selector_test().await;
+        workload_attestation
+            .pod_cache
+            .apply(Event::Restarted(vec![get_pods()]));
+
+        // No response queued, the pods are not listed.
+        let (pod, container_identifiers) = workload_attestation
+            .get_pod(CONTAINER_ID, POD_UID)
+            .await
+            .unwrap();
+        assert_eq!(pod, get_pods());
+        assert_eq!(container_identifiers.name, "container_name");
+    }
+
+    #[tokio::test]
+    async fn get_pod_cache_miss() {
+        let mut workload_attestation = init_selector_test().await;
+        workload_attestation
+            .pod_cache
+            .apply(Event::Restarted(vec![]));
+
+        let pod_list = ObjectList {
+            metadata: ListMeta::default(),
+            items: vec![get_pods()],
+        };
+        workload_attestation.client.queue_response(pod_list).await;
+
+        let (pod, _container_identifiers) = workload_attestation
+            .get_pod(CONTAINER_ID, POD_UID)
+            .await
+            .unwrap();
+        assert_eq!(pod, get_pods());
+    }
+
+    #[tokio::test]
+    async fn attest_node_workloads_inner_from_cache() {
+        let workload_attestation = init_selector_test().await;
+        workload_attestation
+            .pod_cache
+            .apply(Event::Restarted(vec![get_pods()]));
+
+        let workloads = workload_attestation
+            .attest_node_workloads_inner()
+            .await
+            .unwrap();
+
+        assert_eq!(workloads.len(), 1);
+        assert_eq!(workloads[0].0.container_id, CONTAINER_ID);
+    }
+
     #[tokio::test]
     async fn get_pod_error_listing_pods() {
         let mut workload_attestation = init_selector_test().await;
@@ -841,7 +1251,36 @@ mod tests {
         let pod = get_pods();
 
         // No need to test the return. Already tested in main function happy path.
-        get_selector_info(pod, container_identifiers).unwrap();
+        get_selector_info(pod, container_identifiers, &BTreeSet::new()).unwrap();
+    }
+
+    #[test]
+    fn get_selector_info_allowed_annotations() {
+        let container_identifiers = ContainerIdentifiers {
+            name: "name".to_string(),
+            image: "image".to_string(),
+        };
+
+        let mut pod = get_pods();
+        let mut annotations = BTreeMap::new();
+        annotations.insert("intent".to_string(), "telemetry".to_string());
+        annotations.insert("other".to_string(), "value".to_string());
+        pod.metadata.annotations = Some(annotations);
+
+        let allowed_pod_annotation_keys = vec!["intent".to_string(), "missing".to_string()]
+            .into_iter()
+            .collect();
+        let selector_info =
+            get_selector_info(pod, container_identifiers, &allowed_pod_annotation_keys).unwrap();
+        assert_eq!(selector_info.pod_annotations.len(), 1);
+
+        let workload_attributes = get_workload_attributes_from_select_info(&selector_info);
+        let annotation =
+            build_selector_string(&WorkloadSelectorType::PodAnnotations, "intent:telemetry");
+        assert!(workload_attributes.selectors.contains(&annotation));
+        assert!(!workload_attributes
+            .selectors
+            .contains("PODANNOTATIONS:other:value"));
     }
 
     #[test]
@@ -855,7 +1294,7 @@ mod tests {
         pod.spec = None;
 
         // No need to test the return. Already tested in main function happy path.
-        let error = get_selector_info(pod, container_identifiers).unwrap_err();
+        let error = get_selector_info(pod, container_identifiers, &BTreeSet::new()).unwrap_err();
         if let Error::MissingField(error) = error {
             assert_matches!(error, MissingField::PodSpec);
         } else {
@@ -874,7 +1313,7 @@ mod tests {
         pod.status = None;
 
         // No need to test the return. Already tested in main function happy path.
-        let error = get_selector_info(pod, container_identifiers).unwrap_err();
+        let error = get_selector_info(pod, container_identifiers, &BTreeSet::new()).unwrap_err();
         if let Error::MissingField(error) = error {
             assert_matches!(error, MissingField::Status);
         } else {
@@ -893,7 +1332,7 @@ mod tests {
         pod.metadata.name = None;
 
         // No need to test the return. Already tested in main function happy path.
-        let error = get_selector_info(pod, container_identifiers).unwrap_err();
+        let error = get_selector_info(pod, container_identifiers, &BTreeSet::new()).unwrap_err();
         if let Error::MissingField(error) = error {
             assert_matches!(error, MissingField::PodName);
         } else {
@@ -912,7 +1351,7 @@ mod tests {
         pod.metadata.uid = None;
 
         // No need to test the return. Already tested in main function happy path.
-        let error = get_selector_info(pod, container_identifiers).unwrap_err();
+        let error = get_selector_info(pod, container_identifiers, &BTreeSet::new()).unwrap_err();
         if let Error::MissingField(error) = error {
             assert_matches!(error, MissingField::PodUid);
         } else {
@@ -931,7 +1370,7 @@ mod tests {
         pod.metadata.namespace = None;
 
         // No need to test the return. Already tested in main function happy path.
-        let error = get_selector_info(pod, container_identifiers).unwrap_err();
+        let error = get_selector_info(pod, container_identifiers, &BTreeSet::new()).unwrap_err();
         if let Error::MissingField(error) = error {
             assert_matches!(error, MissingField::Namespace);
         } else {
@@ -950,7 +1389,7 @@ mod tests {
         pod.metadata.labels = None;
 
         // No need to test the return. Already tested in main function happy path.
-        let error = get_selector_info(pod, container_identifiers).unwrap_err();
+        let error = get_selector_info(pod, container_identifiers, &BTreeSet::new()).unwrap_err();
         if let Error::MissingField(error) = error {
             assert_matches!(error, MissingField::PodLabels);
         } else {
@@ -971,7 +1410,7 @@ mod tests {
         }
 
         // No need to test the return. Already tested in main function happy path.
-        let error = get_selector_info(pod, container_identifiers).unwrap_err();
+        let error = get_selector_info(pod, container_identifiers, &BTreeSet::new()).unwrap_err();
         if let Error::MissingField(error) = error {
             assert_matches!(error, MissingField::NodeName);
         } else {
@@ -992,7 +1431,7 @@ mod tests {
         }
 
         // No need to test the return. Already tested in main function happy path.
-        let error = get_selector_info(pod, container_identifiers).unwrap_err();
+        let error = get_selector_info(pod, container_identifiers, &BTreeSet::new()).unwrap_err();
         if let Error::MissingField(error) = error {
             assert_matches!(error, MissingField::ServiceAccountName);
         } else {
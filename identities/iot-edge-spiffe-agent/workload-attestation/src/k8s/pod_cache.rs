@@ -0,0 +1,179 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Pods of the node, kept up to date by a watch on the API server and keyed by pod UID. Attestations look
+// the pod up locally instead of listing every pod of the node.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use futures_util::{pin_mut, Stream, StreamExt};
+use k8s_openapi::api::core::v1::Pod;
+use kube::runtime::watcher::{self, Event};
+use log::{error, info};
+use tokio::time;
+
+use super::attestation_cache::AttestationCache;
+
+// The watcher lists the pods again after an error, this keeps it from hammering the API server.
+const WATCH_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+pub(super) struct PodCache {
+    // None until the first list of the watch.
+    pods: RwLock<Option<HashMap<String, Pod>>>,
+}
+
+impl PodCache {
+    pub(super) fn get(&self, pod_uid: &str) -> Option<Pod> {
+        self.pods
+            .read()
+            .unwrap()
+            .as_ref()
+            .and_then(|pods| pods.get(pod_uid).cloned())
+    }
+
+    // None when the pods were not listed yet.
+    pub(super) fn list(&self) -> Option<Vec<Pod>> {
+        self.pods
+            .read()
+            .unwrap()
+            .as_ref()
+            .map(|pods| pods.values().cloned().collect())
+    }
+
+    pub(super) fn apply(&self, event: Event<Pod>) {
+        let mut pods = self.pods.write().unwrap();
+
+        match event {
+            Event::Applied(pod) => {
+                if let (Some(pods), Some(uid)) = (pods.as_mut(), pod.metadata.uid.clone()) {
+                    pods.insert(uid, pod);
+                }
+            }
+            Event::Deleted(pod) => {
+                if let (Some(pods), Some(uid)) = (pods.as_mut(), &pod.metadata.uid) {
+                    pods.remove(uid);
+                }
+            }
+            Event::Restarted(list) => {
+                *pods = Some(
+                    list.into_iter()
+                        .filter_map(|pod| pod.metadata.uid.clone().map(|uid| (uid, pod)))
+                        .collect(),
+                );
+            }
+        }
+    }
+}
+
+// The attestations of the pods that changed are dropped along the way.
+pub(super) async fn run<S>(
+    cache: Arc<PodCache>,
+    attestation_cache: Arc<AttestationCache>,
+    events: S,
+) where
+    S: Stream<Item = Result<Event<Pod>, watcher::Error>>,
+{
+    info!("Starting pod watch");
+
+    pin_mut!(events);
+    while let Some(event) = events.next().await {
+        match event {
+            Ok(event) => {
+                match &event {
+                    Event::Applied(pod) | Event::Deleted(pod) => {
+                        if let Some(uid) = &pod.metadata.uid {
+                            attestation_cache.remove_pod(uid);
+                        }
+                    }
+                    Event::Restarted(_) => attestation_cache.clear(),
+                }
+
+                cache.apply(event);
+            }
+            Err(err) => {
+                error!(
+                    "Error while watching pods, retrying in {:?}: {}",
+                    WATCH_RETRY_DELAY, err
+                );
+                time::sleep(WATCH_RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream;
+    use mock_kube::{get_pods, CONTAINER_ID, POD_UID};
+
+    use super::*;
+    use crate::{WorkloadAttributes, WorkloadId};
+
+    fn pod(uid: &str) -> Pod {
+        let mut pod = get_pods();
+        pod.metadata.uid = Some(uid.to_string());
+
+        pod
+    }
+
+    #[test]
+    fn apply_events() {
+        let cache = PodCache::default();
+
+        // Events before the first list are ignored, the list has them anyway.
+        cache.apply(Event::Applied(pod("a")));
+        assert!(cache.get("a").is_none());
+        assert!(cache.list().is_none());
+
+        cache.apply(Event::Restarted(vec![pod("a"), pod("b")]));
+        assert!(cache.get("a").is_some());
+        assert_eq!(cache.list().unwrap().len(), 2);
+
+        cache.apply(Event::Applied(pod("c")));
+        cache.apply(Event::Deleted(pod("a")));
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("c").is_some());
+
+        // Pods deleted while the watch was down are dropped by the next list.
+        cache.apply(Event::Restarted(vec![pod("c")]));
+        assert!(cache.get("b").is_none());
+        assert_eq!(cache.list().unwrap().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn run_after_watch_error() {
+        let cache = Arc::new(PodCache::default());
+        let events = stream::iter(vec![
+            Err(watcher::Error::TooManyObjects),
+            Ok(Event::Restarted(vec![get_pods()])),
+        ]);
+
+        run(cache.clone(), Arc::new(AttestationCache::new(10)), events).await;
+
+        assert!(cache.get(POD_UID).is_some());
+    }
+
+    #[tokio::test]
+    async fn run_invalidates_attestations() {
+        let cache = Arc::new(PodCache::default());
+        let attestation_cache = Arc::new(AttestationCache::new(10));
+        let workload_id = WorkloadId {
+            pod_uid: POD_UID.to_string(),
+            container_id: CONTAINER_ID.to_string(),
+        };
+        let events = stream::iter(vec![
+            Ok(Event::Restarted(vec![get_pods()])),
+            Ok(Event::Deleted(get_pods())),
+        ]);
+        attestation_cache.insert(workload_id.clone(), WorkloadAttributes::default(), 0);
+
+        run(cache.clone(), attestation_cache.clone(), events).await;
+
+        assert!(attestation_cache.get(&workload_id, 0).is_none());
+        assert!(cache.get(POD_UID).is_none());
+    }
+}
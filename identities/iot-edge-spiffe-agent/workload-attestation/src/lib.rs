@@ -12,6 +12,8 @@
 )]
 
 pub mod k8s;
+pub mod systemd;
+pub mod unix;
 
 use agent_config::WorkloadAttestationConfig;
 
@@ -27,6 +29,24 @@ use std::{collections::BTreeSet, sync::Arc};
 #[derive(Clone, Debug, Default)]
 pub struct WorkloadAttributes {
     pub selectors: BTreeSet<String>,
+    pub pod_uid: Option<String>,
+}
+
+// Process calling the workload API, with the credentials captured by the kernel when it connected to
+// the socket.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Caller {
+    pub pid: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+// Container a workload runs in. Container ids change when a container restarts, so the same id is
+// never reused by another workload.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct WorkloadId {
+    pub pod_uid: String,
+    pub container_id: String,
 }
 
 pub struct WorkloadAttestatorFactory {}
@@ -40,7 +60,16 @@ impl WorkloadAttestatorFactory {
     ) -> Arc<dyn WorkloadAttestation> {
         match config {
             WorkloadAttestationConfig::K8s(config) => {
-                Arc::new(k8s::WorkloadAttestation::new(config, node_name, client))
+                let workload_attestation = k8s::WorkloadAttestation::new(config, node_name, client);
+                tokio::spawn(workload_attestation.watch_pods());
+
+                Arc::new(workload_attestation)
+            }
+            WorkloadAttestationConfig::Unix(config) => {
+                Arc::new(unix::WorkloadAttestation::new(config))
+            }
+            WorkloadAttestationConfig::Systemd(config) => {
+                Arc::new(systemd::WorkloadAttestation::new(config))
             }
         }
     }
@@ -51,6 +80,22 @@ impl WorkloadAttestatorFactory {
 pub trait WorkloadAttestation: Sync + Send {
     async fn attest_workload(
         &self,
-        pid: u32,
+        caller: Caller,
     ) -> Result<WorkloadAttributes, Box<dyn std::error::Error + Send>>;
+
+    // Only looks up the pod of the process, without attesting it again. Used to check a process
+    // still belongs to the same pod, PIDs are recycled when pods restart.
+    async fn get_pod_uid(&self, pid: u32) -> Result<String, Box<dyn std::error::Error + Send>>;
+
+    // Like `get_pod_uid`, along with the container of the process.
+    async fn get_workload_id(
+        &self,
+        pid: u32,
+    ) -> Result<WorkloadId, Box<dyn std::error::Error + Send>>;
+
+    // Attests every running container of the node, without a process to start from. Used to warm up
+    // the agent when it starts.
+    async fn attest_node_workloads(
+        &self,
+    ) -> Result<Vec<(WorkloadId, WorkloadAttributes)>, Box<dyn std::error::Error + Send>>;
 }
@@ -0,0 +1,14 @@
+// Copyright (c) Microsoft. All rights reserved.
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Could not read the cgroups of process {0}: {1}")]
+    ReadingCgroups(u32, std::io::Error),
+    #[error("No systemd cgroup for process {0}")]
+    NoSystemdCgroup(u32),
+    #[error("Process {0} is not in a systemd service or scope, cgroup {1}")]
+    NoUnit(u32, String),
+    #[error("Systemd workloads do not run in pods")]
+    NoPod,
+}
@@ -0,0 +1,215 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Systemd workload attestation.
+//!
+//! Host daemons are identified by the systemd unit they run in. Systemd puts every unit in its own
+//! cgroup, named after the unit and nested in the cgroups of its slices, e.g.
+//! `/system.slice/mosquitto.service`. The unit and slice of the process are read from its cgroup in
+//! /proc: the systemd hierarchy with cgroups v1, the unified hierarchy with cgroups v2.
+
+pub mod error;
+
+use std::collections::BTreeSet;
+
+use agent_config::WorkloadAttestationConfigSystemd;
+use core_objects::{build_selector_string, WorkloadSelectorType};
+use log::{debug, info};
+
+use crate::{Caller, WorkloadAttributes, WorkloadId};
+
+use super::WorkloadAttestation as WorkloadAttestationTrait;
+
+use error::Error;
+
+// Hierarchy of systemd in /proc/<pid>/cgroup, "1:name=systemd:/system.slice/foo.service" with
+// cgroups v1 and "0::/system.slice/foo.service" with cgroups v2.
+const SYSTEMD_HIERARCHY_V1: &str = "name=systemd";
+const SYSTEMD_HIERARCHY_V2: &str = "";
+// Units processes run in. A service may create sub-cgroups below its own.
+const UNIT_SUFFIXES: [&str; 2] = [".service", ".scope"];
+const SLICE_SUFFIX: &str = ".slice";
+
+#[derive(Clone, Debug, PartialEq)]
+struct Unit {
+    name: String,
+    // The innermost slice of the unit, "-.slice" for the root slice.
+    slice: String,
+}
+
+pub struct WorkloadAttestation {}
+
+impl WorkloadAttestation {
+    #[must_use]
+    pub fn new(_config: &WorkloadAttestationConfigSystemd) -> Self {
+        WorkloadAttestation {}
+    }
+
+    fn attest_workload_inner(pid: u32, cgroups: &str) -> Result<WorkloadAttributes, Error> {
+        let unit = get_unit(pid, cgroups)?;
+
+        let mut selectors = BTreeSet::new();
+        selectors.insert(build_selector_string(
+            &WorkloadSelectorType::SystemdUnit,
+            &unit.name,
+        ));
+        selectors.insert(build_selector_string(
+            &WorkloadSelectorType::SystemdSlice,
+            &unit.slice,
+        ));
+
+        info!(
+            "Process {} of unit {} was attested successfully",
+            pid, unit.name
+        );
+        debug!("Found the following selectors for workload {:?}", selectors);
+
+        Ok(WorkloadAttributes {
+            selectors,
+            pod_uid: None,
+        })
+    }
+}
+
+fn get_unit(pid: u32, cgroups: &str) -> Result<Unit, Error> {
+    // With the hybrid layout, both hierarchies are there and the systemd one is used.
+    let mut cgroup_v2 = None;
+    let mut cgroup_v1 = None;
+    for line in cgroups.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (hierarchy, path) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(_), Some(hierarchy), Some(path)) => (hierarchy, path),
+            _ => continue,
+        };
+
+        match hierarchy {
+            SYSTEMD_HIERARCHY_V1 => cgroup_v1 = Some(path),
+            SYSTEMD_HIERARCHY_V2 => cgroup_v2 = Some(path),
+            _ => (),
+        }
+    }
+    let cgroup = cgroup_v1.or(cgroup_v2).ok_or(Error::NoSystemdCgroup(pid))?;
+
+    let components: Vec<&str> = cgroup
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .collect();
+    let unit_index = components
+        .iter()
+        .rposition(|component| {
+            UNIT_SUFFIXES
+                .iter()
+                .any(|suffix| component.ends_with(suffix))
+        })
+        .ok_or_else(|| Error::NoUnit(pid, cgroup.to_string()))?;
+
+    let slice = components[..unit_index]
+        .iter()
+        .rev()
+        .find(|component| component.ends_with(SLICE_SUFFIX))
+        .copied()
+        .unwrap_or("-.slice");
+
+    Ok(Unit {
+        name: components[unit_index].to_string(),
+        slice: slice.to_string(),
+    })
+}
+
+#[async_trait::async_trait]
+impl WorkloadAttestationTrait for WorkloadAttestation {
+    async fn attest_workload(
+        &self,
+        caller: Caller,
+    ) -> Result<WorkloadAttributes, Box<dyn std::error::Error + Send>> {
+        let cgroups = std::fs::read_to_string(format!("/proc/{}/cgroup", caller.pid))
+            .map_err(|err| Box::new(Error::ReadingCgroups(caller.pid, err)) as _)?;
+        // For unit test, we remove dependency to /proc.
+        Self::attest_workload_inner(caller.pid, &cgroups).map_err(|err| Box::new(err) as _)
+    }
+
+    async fn get_pod_uid(&self, _pid: u32) -> Result<String, Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::NoPod))
+    }
+
+    async fn get_workload_id(
+        &self,
+        _pid: u32,
+    ) -> Result<WorkloadId, Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::NoPod))
+    }
+
+    // Units are only known once their processes call the workload API, there is nothing to warm up.
+    async fn attest_node_workloads(
+        &self,
+    ) -> Result<Vec<(WorkloadId, WorkloadAttributes)>, Box<dyn std::error::Error + Send>> {
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::*;
+
+    #[test]
+    fn attest_workload_happy_path() {
+        let attributes =
+            WorkloadAttestation::attest_workload_inner(1, "0::/system.slice/mosquitto.service\n")
+                .unwrap();
+
+        assert!(attributes
+            .selectors
+            .contains("SYSTEMDUNIT:mosquitto.service"));
+        assert!(attributes.selectors.contains("SYSTEMDSLICE:system.slice"));
+        assert_eq!(attributes.pod_uid, None);
+    }
+
+    #[test]
+    fn get_unit_cgroups_v1() {
+        let cgroups = "12:pids:/system.slice/aziot-edged.service\n\
+                       1:name=systemd:/system.slice/aziot-edged.service\n\
+                       0::/system.slice/aziot-edged.service\n";
+
+        let unit = get_unit(1, cgroups).unwrap();
+
+        assert_eq!(
+            unit,
+            Unit {
+                name: "aziot-edged.service".to_string(),
+                slice: "system.slice".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn get_unit_nested() {
+        // Sub-cgroup created by the service.
+        let unit = get_unit(1, "0::/system.slice/containerd.service/init\n").unwrap();
+        assert_eq!(unit.name, "containerd.service");
+        assert_eq!(unit.slice, "system.slice");
+
+        // Scope of a user session.
+        let unit = get_unit(
+            1,
+            "0::/user.slice/user-1000.slice/user@1000.service/app.slice/app-monitor.scope\n",
+        )
+        .unwrap();
+        assert_eq!(unit.name, "app-monitor.scope");
+        assert_eq!(unit.slice, "app.slice");
+
+        // Unit of the root slice.
+        let unit = get_unit(1, "0::/init.scope\n").unwrap();
+        assert_eq!(unit.name, "init.scope");
+        assert_eq!(unit.slice, "-.slice");
+    }
+
+    #[test]
+    fn get_unit_errors() {
+        let error = get_unit(1, "0::/system.slice\n").unwrap_err();
+        assert_matches!(error, Error::NoUnit(1, _));
+
+        let error = get_unit(1, "12:pids:/system.slice/foo.service\n").unwrap_err();
+        assert_matches!(error, Error::NoSystemdCgroup(1));
+    }
+}
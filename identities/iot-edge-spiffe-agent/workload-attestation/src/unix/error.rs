@@ -0,0 +1,12 @@
+// Copyright (c) Microsoft. All rights reserved.
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Could not read the executable path of process {0}: {1}")]
+    ExecutablePath(u32, std::io::Error),
+    #[error("Could not hash the executable of process {0}: {1}")]
+    ExecutableHash(u32, std::io::Error),
+    #[error("Unix workloads do not run in pods")]
+    NoPod,
+}
@@ -0,0 +1,175 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+//! Unix workload attestation.
+//!
+//! The workloads are identified by their process only: the UID and GID captured by the kernel when the
+//! process connected to the workload API socket, and the path and SHA-256 of its executable read from
+//! /proc. The executable is hashed through /proc/<pid>/exe, so the hash is the one of the binary the
+//! process runs even when the file at its path was replaced since.
+
+pub mod error;
+
+use std::{collections::BTreeSet, fs::File, io::Read, path::PathBuf};
+
+use agent_config::WorkloadAttestationConfigUnix;
+use core_objects::{build_selector_string, WorkloadSelectorType};
+use log::{debug, info};
+use openssl::sha::Sha256;
+
+use crate::{Caller, WorkloadAttributes, WorkloadId};
+
+use super::WorkloadAttestation as WorkloadAttestationTrait;
+
+use error::Error;
+
+const READ_BUFFER_BYTES: usize = 64 * 1024;
+
+pub struct WorkloadAttestation {}
+
+impl WorkloadAttestation {
+    #[must_use]
+    pub fn new(_config: &WorkloadAttestationConfigUnix) -> Self {
+        WorkloadAttestation {}
+    }
+
+    fn attest_workload_inner(caller: Caller) -> Result<WorkloadAttributes, Error> {
+        let exe = PathBuf::from(format!("/proc/{}/exe", caller.pid));
+
+        let path =
+            std::fs::read_link(&exe).map_err(|err| Error::ExecutablePath(caller.pid, err))?;
+        let sha256 = hash_file(&exe).map_err(|err| Error::ExecutableHash(caller.pid, err))?;
+
+        let mut selectors = BTreeSet::new();
+        selectors.insert(build_selector_string(
+            &WorkloadSelectorType::UnixUID,
+            caller.uid,
+        ));
+        selectors.insert(build_selector_string(
+            &WorkloadSelectorType::UnixGID,
+            caller.gid,
+        ));
+        selectors.insert(build_selector_string(
+            &WorkloadSelectorType::UnixPath,
+            path.display(),
+        ));
+        selectors.insert(build_selector_string(
+            &WorkloadSelectorType::UnixSHA256,
+            sha256,
+        ));
+
+        info!(
+            "Process {} running {} was attested successfully",
+            caller.pid,
+            path.display()
+        );
+        debug!("Found the following selectors for workload {:?}", selectors);
+
+        Ok(WorkloadAttributes {
+            selectors,
+            pod_uid: None,
+        })
+    }
+}
+
+// Hex SHA-256 of the file.
+fn hash_file(path: &std::path::Path) -> Result<String, std::io::Error> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; READ_BUFFER_BYTES];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher
+        .finish()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+#[async_trait::async_trait]
+impl WorkloadAttestationTrait for WorkloadAttestation {
+    async fn attest_workload(
+        &self,
+        caller: Caller,
+    ) -> Result<WorkloadAttributes, Box<dyn std::error::Error + Send>> {
+        Self::attest_workload_inner(caller).map_err(|err| Box::new(err) as _)
+    }
+
+    async fn get_pod_uid(&self, _pid: u32) -> Result<String, Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::NoPod))
+    }
+
+    async fn get_workload_id(
+        &self,
+        _pid: u32,
+    ) -> Result<WorkloadId, Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::NoPod))
+    }
+
+    // Processes are only known once they call the workload API, there is nothing to warm up.
+    async fn attest_node_workloads(
+        &self,
+    ) -> Result<Vec<(WorkloadId, WorkloadAttributes)>, Box<dyn std::error::Error + Send>> {
+        Ok(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn attest_workload_happy_path() {
+        let caller = Caller {
+            pid: std::process::id(),
+            uid: 1000,
+            gid: 1001,
+        };
+
+        let attributes = WorkloadAttestation::new(&WorkloadAttestationConfigUnix {})
+            .attest_workload(caller)
+            .await
+            .unwrap();
+
+        let exe = std::env::current_exe().unwrap();
+        let sha256 = hash_file(&exe).unwrap();
+        let selectors = attributes.selectors;
+        assert!(selectors.contains("UNIXUID:1000"));
+        assert!(selectors.contains("UNIXGID:1001"));
+        assert!(selectors.contains(&format!("UNIXPATH:{}", exe.display())));
+        assert!(selectors.contains(&format!("UNIXSHA256:{}", sha256)));
+        assert_eq!(attributes.pod_uid, None);
+    }
+
+    #[test]
+    fn attest_workload_unknown_process() {
+        let caller = Caller {
+            pid: u32::MAX,
+            ..Default::default()
+        };
+
+        let error = WorkloadAttestation::attest_workload_inner(caller).unwrap_err();
+
+        assert_matches!(error, Error::ExecutablePath(_, _));
+    }
+
+    #[test]
+    fn hash_file_sha256() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("binary");
+        std::fs::write(&path, "abc").unwrap();
+
+        assert_eq!(
+            hash_file(&path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
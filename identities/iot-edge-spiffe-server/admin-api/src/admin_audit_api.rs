@@ -0,0 +1,227 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeSet, HashMap};
+
+use catalog::{AdminAudit, AdminOperationFilter};
+use core_objects::{get_epoch_time, AdminOperation, EntryChange, EntryChangeKind};
+use server_admin_api::{list_admin_operations, operation};
+
+use crate::{error::Error, tenancy::Caller, Api};
+
+impl Api {
+    pub async fn list_admin_operations(
+        &self,
+        params: list_admin_operations::Params,
+    ) -> Result<list_admin_operations::Response, Error> {
+        let admin_audit = self.admin_audit.as_ref().ok_or(Error::AdminAuditDisabled)?;
+        let page_size: usize = params
+            .page_size
+            .try_into()
+            .map_err(|err| Error::InvalidPageSize(Box::new(err)))?;
+
+        let filter = AdminOperationFilter {
+            caller: params.caller,
+            entry_id: params.entry_id,
+            since: params.since,
+            until: params.until,
+        };
+        let (operations, next_page_token) = admin_audit
+            .list(&filter, params.page_token, page_size)
+            .await
+            .map_err(|err| Error::ListAdminOperations(err))?;
+
+        Ok(list_admin_operations::Response {
+            operations,
+            next_page_token,
+        })
+    }
+
+    // Operations are logged whether the audit log is enabled or not, operations that changed nothing are
+    // neither. The change is already made: a record that cannot be written is logged as an error, the change
+    // is not undone.
+    pub(crate) async fn audit(
+        &self,
+        caller: &Caller,
+        operation: &str,
+        entries: Vec<EntryChange>,
+        detail: Option<String>,
+    ) {
+        if entries.is_empty() && detail.is_none() {
+            return;
+        }
+
+        let time = get_epoch_time();
+        let operation = AdminOperation {
+            id: AdminAudit::record_id(time),
+            time,
+            caller: caller.as_str().to_string(),
+            operation: operation.to_string(),
+            entries,
+            detail,
+        };
+
+        log::info!(
+            target: "audit",
+            operation_id = operation.id.as_str(), caller = operation.caller.as_str();
+            "Admin operation {} changed {} entries{}",
+            operation.operation,
+            operation.entries.len(),
+            operation
+                .detail
+                .as_ref()
+                .map(|detail| format!(": {}", detail))
+                .unwrap_or_default()
+        );
+
+        if let Some(admin_audit) = &self.admin_audit {
+            let id = operation.id.clone();
+            if let Err(err) = admin_audit.record(operation).await {
+                log::error!(
+                    "Cannot record admin operation {} in the audit log: {}",
+                    id,
+                    err
+                );
+            }
+        }
+    }
+
+    // Revisions of the stored entries, so the audit log has the revision an update or a delete replaced. They
+    // are only read when the audit log is enabled.
+    pub(crate) async fn revisions(&self, ids: &[String]) -> HashMap<String, u64> {
+        if self.admin_audit.is_none() {
+            return HashMap::new();
+        }
+
+        self.catalog
+            .batch_get(ids)
+            .await
+            .into_iter()
+            .filter_map(|(id, result)| result.ok().map(|entry| (id, entry.revision_number)))
+            .collect()
+    }
+}
+
+// Changes of the entries that did not fail.
+pub(crate) fn entry_changes(
+    change: EntryChangeKind,
+    ids: impl IntoIterator<Item = String>,
+    failed: &BTreeSet<&str>,
+    revisions: &HashMap<String, u64>,
+) -> Vec<EntryChange> {
+    ids.into_iter()
+        .filter(|id| !failed.contains(id.as_str()))
+        .map(|id| EntryChange {
+            previous_revision: revisions.get(&id).copied(),
+            id,
+            change,
+        })
+        .collect()
+}
+
+pub(crate) fn failed_ids(results: &Result<(), Vec<operation::Error>>) -> BTreeSet<&str> {
+    match results {
+        Ok(()) => BTreeSet::new(),
+        Err(errors) => errors.iter().map(|error| error.id.as_str()).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use catalog::{AgentBans, EntryPruner};
+    use server_config::{AuditLogConfig, CatalogConfig, KeyStoreConfig};
+
+    use crate::{
+        info_api::{catalog_backend, key_store_backend},
+        test_key_manager, test_trust_bundle_builder,
+    };
+
+    use super::*;
+
+    async fn init(admin_audit: Option<Arc<AdminAudit>>) -> Api {
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+
+        Api {
+            catalog: catalog.clone(),
+            trust_bundle_builder: test_trust_bundle_builder(catalog.clone()),
+            entry_pruner: Arc::new(EntryPruner::new(catalog.clone())),
+            agent_bans: Arc::new(AgentBans::new(catalog)),
+            key_manager: test_key_manager(Arc::new(catalog::inmemory::Catalog::new())).await,
+            svid_audit: None,
+            admin_audit,
+            trust_domain: "trust_domain".to_string(),
+            catalog_backend: catalog_backend(&CatalogConfig::Memory),
+            key_store_backend: key_store_backend(&KeyStoreConfig::Memory()),
+            faults: None,
+            build: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn audit_records_changes() {
+        let admin_audit = Arc::new(AdminAudit::new(
+            &AuditLogConfig::Catalog,
+            Arc::new(catalog::inmemory::Catalog::new()),
+        ));
+        let api = init(Some(admin_audit)).await;
+        let caller = Caller::default();
+
+        let failed = BTreeSet::from(["failed"]);
+        let revisions = HashMap::from([("deleted".to_string(), 3)]);
+        let entries = entry_changes(
+            EntryChangeKind::Deleted,
+            vec!["deleted".to_string(), "failed".to_string()],
+            &failed,
+            &revisions,
+        );
+        api.audit(&caller, "delete_registration_entries", entries, None)
+            .await;
+        // Nothing changed, nothing is recorded.
+        api.audit(&caller, "delete_registration_entries", Vec::new(), None)
+            .await;
+
+        let res = api
+            .list_admin_operations(list_admin_operations::Params {
+                page_size: 10,
+                entry_id: Some("deleted".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(res.operations.len(), 1);
+        assert_eq!(res.operations[0].caller, "local");
+        assert_eq!(
+            res.operations[0].entries,
+            vec![EntryChange {
+                id: "deleted".to_string(),
+                change: EntryChangeKind::Deleted,
+                previous_revision: Some(3),
+            }]
+        );
+
+        let res = api
+            .list_admin_operations(list_admin_operations::Params {
+                page_size: 10,
+                entry_id: Some("failed".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(res.operations.is_empty());
+    }
+
+    #[tokio::test]
+    async fn list_admin_operations_disabled() {
+        let api = init(None).await;
+
+        let error = api
+            .list_admin_operations(list_admin_operations::Params {
+                page_size: 10,
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::AdminAuditDisabled));
+    }
+}
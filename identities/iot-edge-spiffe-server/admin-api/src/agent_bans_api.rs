@@ -0,0 +1,198 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use core_objects::{get_epoch_time, AgentBan, NodeSelector};
+use server_admin_api::{ban_agent, list_agent_bans, unban_agent};
+
+use crate::{error::Error, tenancy::Caller, Api};
+
+impl Api {
+    // The ban applies to the agents attested by this replica right away, and to the other replicas
+    // when they refresh their bans.
+    pub async fn ban_agent(
+        &self,
+        req: ban_agent::Request,
+        caller: &Caller,
+    ) -> Result<ban_agent::Response, Error> {
+        if req.selectors.is_empty() {
+            return Err(Error::EmptyAgentBan);
+        }
+        // Agents are banned by the selectors of their node attestation.
+        for selector in &req.selectors {
+            selector
+                .parse::<NodeSelector>()
+                .map_err(|err| Error::InvalidAgentBanSelector(selector.clone(), err))?;
+        }
+
+        let ban = AgentBan {
+            id: uuid::Uuid::new_v4().to_string(),
+            selectors: req.selectors,
+            reason: req.reason,
+            created_at: get_epoch_time(),
+        };
+        self.agent_bans
+            .ban(ban.clone())
+            .await
+            .map_err(|err| Error::AgentBans(err))?;
+
+        log::warn!(
+            target: "audit",
+            ban_id = ban.id.as_str();
+            "Banned agents with selectors {:?}: {}",
+            ban.selectors,
+            ban.reason.as_deref().unwrap_or("no reason given")
+        );
+
+        let detail = format!(
+            "Ban {} of agents with selectors {:?}",
+            ban.id, ban.selectors
+        );
+        self.audit(caller, "ban_agent", Vec::new(), Some(detail))
+            .await;
+
+        Ok(ban_agent::Response { ban })
+    }
+
+    pub async fn list_agent_bans(&self) -> Result<list_agent_bans::Response, Error> {
+        let bans = self
+            .catalog
+            .get_agent_bans()
+            .await
+            .map_err(|err| Error::AgentBans(err))?;
+
+        Ok(list_agent_bans::Response { bans })
+    }
+
+    pub async fn unban_agent(
+        &self,
+        req: unban_agent::Request,
+        caller: &Caller,
+    ) -> Result<(), Error> {
+        let removed = self
+            .agent_bans
+            .unban(&req.id)
+            .await
+            .map_err(|err| Error::AgentBans(err))?;
+
+        if !removed {
+            return Err(Error::AgentBanNotFound(req.id));
+        }
+
+        log::warn!(target: "audit", ban_id = req.id.as_str(); "Removed agent ban {}", req.id);
+
+        let detail = format!("Removed ban {}", req.id);
+        self.audit(caller, "unban_agent", Vec::new(), Some(detail))
+            .await;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, sync::Arc};
+
+    use catalog::{AgentBans, EntryPruner};
+    use matches::assert_matches;
+    use server_config::{CatalogConfig, KeyStoreConfig};
+
+    use crate::{
+        info_api::{catalog_backend, key_store_backend},
+        test_key_manager, test_trust_bundle_builder,
+    };
+
+    use super::*;
+
+    async fn init() -> Api {
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+
+        Api {
+            catalog: catalog.clone(),
+            trust_bundle_builder: test_trust_bundle_builder(catalog.clone()),
+            entry_pruner: Arc::new(EntryPruner::new(catalog.clone())),
+            agent_bans: Arc::new(AgentBans::new(catalog)),
+            key_manager: test_key_manager(Arc::new(catalog::inmemory::Catalog::new())).await,
+            svid_audit: None,
+            admin_audit: None,
+            trust_domain: "trust_domain".to_string(),
+            catalog_backend: catalog_backend(&CatalogConfig::Memory),
+            key_store_backend: key_store_backend(&KeyStoreConfig::Memory()),
+            faults: None,
+            build: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn ban_and_unban_agent() {
+        let api = init().await;
+        let agent = BTreeSet::from([
+            "AGENTNODENAME:node1".to_string(),
+            "CLUSTER:cluster".to_string(),
+        ]);
+
+        let res = api
+            .ban_agent(
+                ban_agent::Request {
+                    selectors: BTreeSet::from(["AGENTNODENAME:node1".to_string()]),
+                    reason: Some("decommissioned".to_string()),
+                },
+                &Caller::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(api.agent_bans.banned(&agent), Some(res.ban.clone()));
+
+        let bans = api.list_agent_bans().await.unwrap().bans;
+        assert_eq!(bans, vec![res.ban.clone()]);
+
+        api.unban_agent(
+            unban_agent::Request {
+                id: res.ban.id.clone(),
+            },
+            &Caller::default(),
+        )
+        .await
+        .unwrap();
+        assert!(api.agent_bans.banned(&agent).is_none());
+
+        let error = api
+            .unban_agent(unban_agent::Request { id: res.ban.id }, &Caller::default())
+            .await
+            .unwrap_err();
+        assert_matches!(error, Error::AgentBanNotFound(_));
+    }
+
+    #[tokio::test]
+    async fn ban_agent_without_selectors() {
+        let api = init().await;
+
+        let error = api
+            .ban_agent(
+                ban_agent::Request {
+                    selectors: BTreeSet::new(),
+                    reason: None,
+                },
+                &Caller::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_matches!(error, Error::EmptyAgentBan);
+    }
+
+    #[tokio::test]
+    async fn ban_agent_invalid_selector() {
+        let api = init().await;
+
+        let error = api
+            .ban_agent(
+                ban_agent::Request {
+                    selectors: BTreeSet::from(["PODNAME:node1".to_string()]),
+                    reason: None,
+                },
+                &Caller::default(),
+            )
+            .await
+            .unwrap_err();
+        assert_matches!(error, Error::InvalidAgentBanSelector(_, _));
+        assert!(api.list_agent_bans().await.unwrap().bans.is_empty());
+    }
+}
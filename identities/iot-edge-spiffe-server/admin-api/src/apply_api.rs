@@ -0,0 +1,271 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeMap, HashMap};
+
+use core_objects::{EntryChangeKind, RegistrationEntry};
+use server_admin_api::{apply_entries, operation};
+
+use crate::{
+    admin_audit_api::{entry_changes, failed_ids},
+    entries_api::update_error,
+    error::Error,
+    tenancy::Caller,
+    Api,
+};
+
+impl Api {
+    // Entries changed between the diff and the writes fail with a revision conflict instead of being
+    // overwritten, the apply can then be retried.
+    pub async fn apply_entries(
+        &self,
+        req: apply_entries::Request,
+        caller: &Caller,
+    ) -> Result<apply_entries::Response, Error> {
+        let prefix = req.spiffe_id_path_prefix.unwrap_or_default();
+
+        let mut desired = BTreeMap::new();
+        for entry in req.entries {
+            if !entry.spiffe_id_path.starts_with(&prefix) {
+                return Err(Error::EntryOutsideApplyScope(entry.id));
+            }
+            self.check_entry(&entry)
+                .map_err(|err| Error::InvalidEntry(entry.id.clone(), err))?;
+            if desired.contains_key(&entry.id) {
+                return Err(Error::DuplicateEntry(entry.id));
+            }
+
+            desired.insert(entry.id.clone(), entry);
+        }
+
+        let current: BTreeMap<String, RegistrationEntry> = self
+            .catalog
+            .export_snapshot()
+            .await
+            .map_err(|err| Error::ApplyEntries(err))?
+            .into_iter()
+            .filter(|entry| entry.spiffe_id_path.starts_with(&prefix))
+            .map(|entry| (entry.id.clone(), entry))
+            .collect();
+
+        let deletes: Vec<String> = current
+            .keys()
+            .filter(|id| !desired.contains_key(*id))
+            .cloned()
+            .collect();
+
+        let mut creates = Vec::new();
+        let mut updates = Vec::new();
+        for (id, mut entry) in desired {
+            match current.get(&id) {
+                None => creates.push(entry),
+                Some(current_entry) => {
+                    entry.revision_number = current_entry.revision_number;
+                    if entry != *current_entry {
+                        updates.push(entry);
+                    }
+                }
+            }
+        }
+
+        let mut res = apply_entries::Response {
+            create: creates.iter().map(|entry| entry.id.clone()).collect(),
+            update: updates.iter().map(|entry| entry.id.clone()).collect(),
+            delete: deletes.clone(),
+            results: Ok(()),
+        };
+
+        log::info!(
+            "Applying entries{}: {} to create, {} to update, {} to delete",
+            if req.dry_run { " (dry run)" } else { "" },
+            res.create.len(),
+            res.update.len(),
+            res.delete.len()
+        );
+
+        if req.dry_run {
+            return Ok(res);
+        }
+
+        let mut errors = Vec::new();
+        if let Err(err) = self.catalog.batch_create(creates).await {
+            errors.extend(err.into_iter().map(operation::Error::from));
+        }
+        if let Err(err) = self.catalog.batch_update(updates).await {
+            errors.extend(err.into_iter().map(update_error));
+        }
+        if let Err(err) = self.catalog.batch_delete(&deletes).await {
+            errors.extend(err.into_iter().map(operation::Error::from));
+        }
+
+        if !errors.is_empty() {
+            res.results = Err(errors);
+        }
+
+        let revisions: HashMap<String, u64> = current
+            .iter()
+            .map(|(id, entry)| (id.clone(), entry.revision_number))
+            .collect();
+        let failed = failed_ids(&res.results);
+        let mut changes = entry_changes(
+            EntryChangeKind::Created,
+            res.create.clone(),
+            &failed,
+            &revisions,
+        );
+        changes.extend(entry_changes(
+            EntryChangeKind::Updated,
+            res.update.clone(),
+            &failed,
+            &revisions,
+        ));
+        changes.extend(entry_changes(
+            EntryChangeKind::Deleted,
+            res.delete.clone(),
+            &failed,
+            &revisions,
+        ));
+        self.audit(caller, "apply_entries", changes, None).await;
+
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use catalog::{AgentBans, Entries, EntryPruner};
+    use server_config::{CatalogConfig, KeyStoreConfig};
+
+    use crate::{
+        info_api::{catalog_backend, key_store_backend},
+        test_key_manager, test_trust_bundle_builder,
+    };
+
+    use super::*;
+
+    async fn init() -> (Api, Arc<catalog::inmemory::Catalog>) {
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+
+        let api = Api {
+            catalog: catalog.clone(),
+            trust_bundle_builder: test_trust_bundle_builder(catalog.clone()),
+            entry_pruner: Arc::new(EntryPruner::new(catalog.clone())),
+            agent_bans: Arc::new(AgentBans::new(catalog.clone())),
+            key_manager: test_key_manager(Arc::new(catalog::inmemory::Catalog::new())).await,
+            svid_audit: None,
+            admin_audit: None,
+            trust_domain: "trust_domain".to_string(),
+            catalog_backend: catalog_backend(&CatalogConfig::Memory),
+            key_store_backend: key_store_backend(&KeyStoreConfig::Memory()),
+            faults: None,
+            build: Default::default(),
+        };
+
+        (api, catalog)
+    }
+
+    fn entry(id: &str, spiffe_id_path: &str) -> RegistrationEntry {
+        RegistrationEntry {
+            spiffe_id_path: spiffe_id_path.to_string(),
+            ..RegistrationEntry::test_entry(id)
+        }
+    }
+
+    fn request(entries: Vec<RegistrationEntry>, dry_run: bool) -> apply_entries::Request {
+        apply_entries::Request {
+            entries,
+            spiffe_id_path_prefix: Some("apps/".to_string()),
+            dry_run,
+        }
+    }
+
+    #[tokio::test]
+    async fn apply_entries_diff() {
+        let (api, catalog) = init().await;
+        catalog
+            .batch_create(vec![
+                entry("same", "apps/same"),
+                entry("changed", "apps/changed"),
+                entry("removed", "apps/removed"),
+                entry("unmanaged", "infra/unmanaged"),
+            ])
+            .await
+            .unwrap();
+        // The revision number of the catalog is not part of the desired entries.
+        catalog
+            .batch_update(vec![entry("same", "apps/same")])
+            .await
+            .unwrap();
+
+        let mut changed = entry("changed", "apps/changed");
+        changed.admin = true;
+        let desired = vec![
+            entry("same", "apps/same"),
+            changed,
+            entry("new", "apps/new"),
+        ];
+
+        let res = api
+            .apply_entries(request(desired.clone(), true), &Caller::default())
+            .await
+            .unwrap();
+        assert_eq!(res.create, vec!["new"]);
+        assert_eq!(res.update, vec!["changed"]);
+        assert_eq!(res.delete, vec!["removed"]);
+        let (entries, _page_token) = catalog.list_all(None, 10).await.unwrap();
+        assert_eq!(entries.len(), 4);
+
+        let res = api
+            .apply_entries(request(desired.clone(), false), &Caller::default())
+            .await
+            .unwrap();
+        res.results.unwrap();
+        let (entries, _page_token) = catalog.list_all(None, 10).await.unwrap();
+        let mut ids: Vec<&str> = entries.iter().map(|entry| entry.id.as_str()).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, vec!["changed", "new", "same", "unmanaged"]);
+
+        // Applying the same entries again changes nothing.
+        let res = api
+            .apply_entries(request(desired, false), &Caller::default())
+            .await
+            .unwrap();
+        assert!(res.create.is_empty() && res.update.is_empty() && res.delete.is_empty());
+    }
+
+    #[tokio::test]
+    async fn apply_entries_invalid_request() {
+        let (api, _catalog) = init().await;
+
+        let error = api
+            .apply_entries(
+                request(vec![entry("other", "infra/other")], false),
+                &Caller::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::EntryOutsideApplyScope(_)));
+
+        let error = api
+            .apply_entries(
+                request(
+                    vec![entry("dup", "apps/dup"), entry("dup", "apps/dup2")],
+                    false,
+                ),
+                &Caller::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::DuplicateEntry(_)));
+
+        let error = api
+            .apply_entries(
+                request(vec![entry("dot", "apps/../infra")], false),
+                &Caller::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::InvalidEntry(_, _)));
+    }
+}
@@ -0,0 +1,95 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use server_admin_api::list_attested_agents;
+
+use crate::{error::Error, Api};
+
+impl Api {
+    pub async fn list_attested_agents(
+        &self,
+        params: list_attested_agents::Params,
+    ) -> Result<list_attested_agents::Response, Error> {
+        let page_size: usize = params
+            .page_size
+            .try_into()
+            .map_err(|err| Error::InvalidPageSize(Box::new(err)))?;
+
+        let (agents, next_page_token) = self
+            .catalog
+            .list_attested_agents(params.page_token, page_size)
+            .await
+            .map_err(|err| Error::ListAttestedAgents(err))?;
+
+        Ok(list_attested_agents::Response {
+            agents,
+            next_page_token,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, sync::Arc};
+
+    use catalog::{AgentBans, AttestedAgents, EntryPruner};
+    use server_config::{CatalogConfig, KeyStoreConfig};
+
+    use crate::{
+        info_api::{catalog_backend, key_store_backend},
+        test_key_manager, test_trust_bundle_builder,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn list_attested_agents_pages() {
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+        let attested_agents = AttestedAgents::new(catalog.clone());
+        for node in ["node1", "node2", "node3"] {
+            let selectors = BTreeSet::from([format!("AGENTNODENAME:{}", node)]);
+            attested_agents.record(&selectors, 0, 1000).await.unwrap();
+        }
+
+        let api = Api {
+            catalog: catalog.clone(),
+            trust_bundle_builder: test_trust_bundle_builder(catalog.clone()),
+            entry_pruner: Arc::new(EntryPruner::new(catalog.clone())),
+            agent_bans: Arc::new(AgentBans::new(catalog)),
+            key_manager: test_key_manager(Arc::new(catalog::inmemory::Catalog::new())).await,
+            svid_audit: None,
+            admin_audit: None,
+            trust_domain: "trust_domain".to_string(),
+            catalog_backend: catalog_backend(&CatalogConfig::Memory),
+            key_store_backend: key_store_backend(&KeyStoreConfig::Memory()),
+            faults: None,
+            build: Default::default(),
+        };
+
+        let res = api
+            .list_attested_agents(list_attested_agents::Params {
+                page_size: 2,
+                page_token: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(res.agents.len(), 2);
+        assert_eq!(res.agents[0].last_seen, 1000);
+
+        let res = api
+            .list_attested_agents(list_attested_agents::Params {
+                page_size: 2,
+                page_token: res.next_page_token,
+            })
+            .await
+            .unwrap();
+        assert_eq!(res.agents.len(), 1);
+        assert!(res.next_page_token.is_none());
+
+        api.list_attested_agents(list_attested_agents::Params {
+            page_size: 0,
+            page_token: None,
+        })
+        .await
+        .unwrap_err();
+    }
+}
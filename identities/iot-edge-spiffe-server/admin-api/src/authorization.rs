@@ -0,0 +1,278 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Authorization of the callers of the admin API without a tenant token. The socket is served by its own
+// listener so the credentials of the calling process are known: a caller is allowed when it runs as one of
+// the allowed UIDs or GIDs, or when it presents the JWT-SVID of an admin entry. Anything else is forbidden.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    sync::Arc,
+};
+
+use catalog::{scan_entries, Catalog};
+use core_objects::{RegistrationEntry, SpiffeId};
+use futures_util::{future, TryStreamExt};
+use jwt_svid_validator::JWTSVIDValidator;
+use server_config::AdminAuthorizationConfig;
+use thiserror::Error;
+use tokio::net::UnixListener;
+use trust_bundle_builder::TrustBundleBuilder;
+
+const ADMIN_ENTRY_SCAN_PAGE_SIZE: usize = 100;
+
+#[derive(Error, Debug)]
+pub(crate) enum Error {
+    #[error("JWT-SVIDs are not accepted by the admin API")]
+    JWTSVIDNotAccepted,
+    #[error("Cannot build the trust bundle: {0}")]
+    TrustBundle(trust_bundle_builder::error::Error),
+    #[error("Invalid JWT-SVID: {0}")]
+    InvalidJWTSVID(jwt_svid_validator::error::Error),
+    #[error("Cannot look up the admin entries: {0}")]
+    Catalog(Box<dyn std::error::Error + Send>),
+    #[error("{0} is not the SPIFFE ID of an admin entry")]
+    NotAdmin(String),
+}
+
+// UID and GID of the process on the other end of the socket.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct PeerCredentials {
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+}
+
+// Where the requests of a connection come from, set by the listener that accepted it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Connection {
+    // Local process on the admin socket, with its credentials when they could be read.
+    Unix(Option<PeerCredentials>),
+    // Remote client of the TLS listener, `client_certificate` when it presented a certificate of the client
+    // CAs. Clients without one need a token.
+    Tls { client_certificate: bool },
+}
+
+pub(crate) struct Authorization {
+    uids: BTreeSet<u32>,
+    gids: BTreeSet<u32>,
+    jwt_svid_audience: Option<String>,
+    catalog: Arc<dyn Catalog>,
+    trust_bundle_builder: Arc<TrustBundleBuilder>,
+    jwt_svid_validator: Arc<dyn JWTSVIDValidator>,
+    // Lower case, as in the parsed SPIFFE IDs.
+    trust_domain: String,
+}
+
+impl Authorization {
+    pub(crate) fn new(
+        config: &AdminAuthorizationConfig,
+        trust_domain: &str,
+        catalog: Arc<dyn Catalog>,
+        trust_bundle_builder: Arc<TrustBundleBuilder>,
+        jwt_svid_validator: Arc<dyn JWTSVIDValidator>,
+    ) -> Self {
+        Authorization {
+            uids: config.uids.clone(),
+            gids: config.gids.clone(),
+            jwt_svid_audience: config.jwt_svid_audience.clone(),
+            catalog,
+            trust_bundle_builder,
+            jwt_svid_validator,
+            trust_domain: trust_domain.to_ascii_lowercase(),
+        }
+    }
+
+    // Callers without credentials are never allowed.
+    pub(crate) fn allows_peer(&self, peer: Option<PeerCredentials>) -> bool {
+        match peer {
+            Some(peer) => self.uids.contains(&peer.uid) || self.gids.contains(&peer.gid),
+            None => false,
+        }
+    }
+
+    // The JWT-SVID must be valid for the audience of the admin API, and its SPIFFE ID must be the one of an
+    // entry marked `admin`. Returns the SPIFFE ID.
+    pub(crate) async fn check_jwt_svid(&self, jwt_svid: &str) -> Result<String, Error> {
+        let audience = self
+            .jwt_svid_audience
+            .as_ref()
+            .ok_or(Error::JWTSVIDNotAccepted)?;
+
+        let trust_bundle = self
+            .trust_bundle_builder
+            .build_trust_bundle(true, false)
+            .await
+            .map_err(Error::TrustBundle)?;
+        let trust_bundles = BTreeMap::from([(trust_bundle.trust_domain.clone(), trust_bundle)]);
+        let jwt_svid = self
+            .jwt_svid_validator
+            .validate(jwt_svid, &trust_bundles, audience)
+            .await
+            .map_err(Error::InvalidJWTSVID)?;
+
+        let subject = jwt_svid.claims.subject;
+        let spiffe_id = match SpiffeId::try_from(subject.as_str()) {
+            Ok(spiffe_id) if spiffe_id.trust_domain() == self.trust_domain => spiffe_id,
+            _ => return Err(Error::NotAdmin(subject)),
+        };
+        let spiffe_id_path = spiffe_id.entry_path();
+
+        let admin_entries: Vec<RegistrationEntry> =
+            scan_entries(&*self.catalog, ADMIN_ENTRY_SCAN_PAGE_SIZE)
+                .try_filter(|entry| {
+                    future::ready(entry.admin && entry.spiffe_id_path == spiffe_id_path)
+                })
+                .try_collect()
+                .await
+                .map_err(Error::Catalog)?;
+
+        if admin_entries.is_empty() {
+            return Err(Error::NotAdmin(subject));
+        }
+
+        Ok(subject)
+    }
+}
+
+pub(crate) fn bind(socket_path: &str, permissions: u32) -> io::Result<UnixListener> {
+    match fs::remove_file(socket_path) {
+        Ok(()) => (),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+        Err(err) => return Err(err),
+    }
+
+    let listener = UnixListener::bind(Path::new(socket_path))?;
+    fs::set_permissions(socket_path, fs::Permissions::from_mode(permissions))?;
+
+    Ok(listener)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use core_objects::{JWTClaims, JWTHeader, JWTType, KeyType, CONFIG_DEFAULT_PATH, JWTSVID};
+    use jwt_svid_validator::MockJWTSVIDValidator;
+    use matches::assert_matches;
+    use server_config::Config;
+
+    use crate::test_key_manager;
+
+    use super::*;
+
+    pub(crate) fn config() -> AdminAuthorizationConfig {
+        AdminAuthorizationConfig {
+            uids: BTreeSet::from([0]),
+            gids: BTreeSet::from([1000]),
+            jwt_svid_audience: Some("admin-api".to_string()),
+        }
+    }
+
+    fn entry(id: &str, admin: bool) -> RegistrationEntry {
+        RegistrationEntry {
+            admin,
+            ..RegistrationEntry::test_workload_entry(id, "parent", Vec::new())
+        }
+    }
+
+    pub(crate) async fn init(subject: &str, config: &AdminAuthorizationConfig) -> Authorization {
+        let catalog: Arc<dyn Catalog> = Arc::new(catalog::inmemory::Catalog::new());
+        // Creates the signing keys of the trust bundle.
+        test_key_manager(catalog.clone()).await;
+        catalog
+            .batch_create(vec![entry("admin", true), entry("workload", false)])
+            .await
+            .unwrap();
+
+        let server_config = Config::load_config(CONFIG_DEFAULT_PATH).unwrap();
+        let trust_bundle_builder = TrustBundleBuilder::new(&server_config, catalog.clone());
+
+        let mut jwt_svid_validator = MockJWTSVIDValidator::new();
+        let subject = subject.to_string();
+        jwt_svid_validator
+            .expect_validate()
+            .returning(move |_, _, audience| {
+                assert_eq!(audience, "admin-api");
+
+                Ok(JWTSVID {
+                    header: JWTHeader {
+                        algorithm: KeyType::ES256,
+                        key_id: "kid".to_string(),
+                        jwt_type: JWTType::JOSE,
+                    },
+                    claims: JWTClaims {
+                        subject: subject.clone(),
+                        audience: vec![audience.to_string()],
+                        expiry: u64::MAX,
+                        issued_at: 0,
+                        jwt_id: None,
+                        not_before: None,
+                        issuer: None,
+                        other_identities: Vec::new(),
+                        pod_uid: None,
+                        custom_claims: Default::default(),
+                    },
+                    signature: "dummy".to_string(),
+                })
+            });
+
+        Authorization::new(
+            config,
+            "trust_domain",
+            catalog,
+            trust_bundle_builder,
+            Arc::new(jwt_svid_validator),
+        )
+    }
+
+    #[tokio::test]
+    async fn allows_listed_peers() {
+        let authorization = init("spiffe://trust_domain/admin", &config()).await;
+
+        assert!(authorization.allows_peer(Some(PeerCredentials { uid: 0, gid: 5 })));
+        assert!(authorization.allows_peer(Some(PeerCredentials { uid: 5, gid: 1000 })));
+        assert!(!authorization.allows_peer(Some(PeerCredentials { uid: 5, gid: 5 })));
+        assert!(!authorization.allows_peer(None));
+    }
+
+    #[tokio::test]
+    async fn check_jwt_svid_of_admin_entry() {
+        let authorization = init("spiffe://trust_domain/admin", &config()).await;
+        assert_eq!(
+            authorization.check_jwt_svid("jwt").await.unwrap(),
+            "spiffe://trust_domain/admin"
+        );
+
+        let authorization = init("spiffe://trust_domain/workload", &config()).await;
+        assert_matches!(
+            authorization.check_jwt_svid("jwt").await,
+            Err(Error::NotAdmin(_))
+        );
+
+        // The trust domain is case insensitive.
+        let authorization = init("spiffe://Trust_Domain/admin", &config()).await;
+        authorization.check_jwt_svid("jwt").await.unwrap();
+
+        let authorization = init("spiffe://trust_domain/admin/", &config()).await;
+        assert_matches!(
+            authorization.check_jwt_svid("jwt").await,
+            Err(Error::NotAdmin(_))
+        );
+
+        let authorization = init("spiffe://other_domain/admin", &config()).await;
+        assert_matches!(
+            authorization.check_jwt_svid("jwt").await,
+            Err(Error::NotAdmin(_))
+        );
+
+        let config = AdminAuthorizationConfig {
+            jwt_svid_audience: None,
+            ..config()
+        };
+        let authorization = init("spiffe://trust_domain/admin", &config).await;
+        assert_matches!(
+            authorization.check_jwt_svid("jwt").await,
+            Err(Error::JWTSVIDNotAccepted)
+        );
+    }
+}
@@ -1,48 +1,131 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use crate::{error::Error, Api};
+use std::collections::HashMap;
+
+use catalog::EntryFilter;
+use core_objects::{
+    selector, spiffe_id, AttestationConfig, EntryChangeKind, NodeSelector, RegistrationEntry,
+    SpiffeId, WorkloadSelector,
+};
+use thiserror::Error;
+
+use crate::{
+    admin_audit_api::{entry_changes, failed_ids},
+    error::Error,
+    tenancy::{Caller, Scope},
+    Api,
+};
 use server_admin_api::{
     create_registration_entries, delete_registration_entries, list_all, operation,
     select_get_registration_entries, update_registration_entries,
 };
 
+// Entries are checked before they are written, the catalog stores whatever it is given.
+#[derive(Debug, Error)]
+pub enum InvalidEntry {
+    #[error("Invalid SPIFFE ID path {0}: {1}")]
+    SpiffeIdPath(String, spiffe_id::Error),
+    #[error("Invalid selector {0}: {1}")]
+    Selector(String, selector::Error),
+}
+
 impl Api {
-    pub async fn create_registration_entries(
+    pub(crate) async fn create_registration_entries(
         &self,
         req: create_registration_entries::Request,
+        scope: &Scope,
+        caller: &Caller,
     ) -> create_registration_entries::Response {
-        let results = self
-            .catalog
-            .batch_create(req.entries)
-            .await
-            .map_err(|err| err.into_iter().map(operation::Error::from).collect());
+        let (entries, rejected) = self.partition_entries(req.entries, scope);
+        if req.transactional && !rejected.is_empty() {
+            return create_registration_entries::Response {
+                results: Err(rejected),
+            };
+        }
 
-        create_registration_entries::Response { results }
+        let ids: Vec<String> = entries.iter().map(|entry| entry.id.clone()).collect();
+        let results = if req.transactional {
+            self.catalog.batch_create_transactional(entries).await
+        } else {
+            self.catalog.batch_create(entries).await
+        };
+        let results = results.map_err(|err| err.into_iter().map(operation::Error::from).collect());
+
+        let changes = entry_changes(
+            EntryChangeKind::Created,
+            ids,
+            &failed_ids(&results),
+            &HashMap::new(),
+        );
+        self.audit(caller, "create_registration_entries", changes, None)
+            .await;
+
+        create_registration_entries::Response {
+            results: with_rejected(results, rejected),
+        }
     }
 
-    pub async fn update_registration_entries(
+    pub(crate) async fn update_registration_entries(
         &self,
         req: update_registration_entries::Request,
+        scope: &Scope,
+        caller: &Caller,
     ) -> update_registration_entries::Response {
-        let results = self
-            .catalog
-            .batch_update(req.entries)
-            .await
-            .map_err(|err| err.into_iter().map(operation::Error::from).collect());
+        // Both the entry as it is and as it will be must be in the scope, so a tenant can neither take
+        // over an entry nor move one out of its namespaces.
+        let (entries, mut rejected) = self.partition_entries(req.entries, scope);
+        let ids: Vec<String> = entries.iter().map(|entry| entry.id.clone()).collect();
+        let forbidden_ids = self.forbidden_ids(&ids, scope).await;
+        let entries: Vec<RegistrationEntry> = entries
+            .into_iter()
+            .filter(|entry| !forbidden_ids.contains(&entry.id))
+            .collect();
+        rejected.extend(forbidden_ids.into_iter().map(|id| scope.forbidden(id)));
+
+        if req.transactional && !rejected.is_empty() {
+            return update_registration_entries::Response {
+                results: Err(rejected),
+            };
+        }
 
-        update_registration_entries::Response { results }
+        let ids: Vec<String> = entries.iter().map(|entry| entry.id.clone()).collect();
+        let revisions = self.revisions(&ids).await;
+        let results = if req.transactional {
+            self.catalog.batch_update_transactional(entries).await
+        } else {
+            self.catalog.batch_update(entries).await
+        };
+        let results = results.map_err(|err| err.into_iter().map(update_error).collect());
+
+        let changes = entry_changes(
+            EntryChangeKind::Updated,
+            ids,
+            &failed_ids(&results),
+            &revisions,
+        );
+        self.audit(caller, "update_registration_entries", changes, None)
+            .await;
+
+        update_registration_entries::Response {
+            results: with_rejected(results, rejected),
+        }
     }
 
-    pub async fn select_list_registration_entries(
+    pub(crate) async fn select_list_registration_entries(
         &self,
         req: select_get_registration_entries::Request,
+        scope: &Scope,
     ) -> select_get_registration_entries::Response {
         let mut results = Vec::new();
 
         let catalog_results = self.catalog.batch_get(&req.ids).await;
 
         for (id, result) in catalog_results {
-            let result = result.map_err(|err| operation::Error::from((id, err)));
+            let result = match result {
+                Ok(entry) if !scope.allows(&entry) => Err(scope.forbidden(id)),
+                Ok(entry) => Ok(entry),
+                Err(err) => Err(operation::Error::from((id, err))),
+            };
 
             results.push(result);
         }
@@ -50,75 +133,229 @@ impl Api {
         select_get_registration_entries::Response { results }
     }
 
-    pub async fn list_all(&self, params: list_all::Params) -> Result<list_all::Response, Error> {
+    // Entries outside of the scope are left out of the page, so a page may be shorter than its size. The
+    // filters are applied by the catalog.
+    pub(crate) async fn list_all(
+        &self,
+        params: list_all::Params,
+        scope: &Scope,
+    ) -> Result<list_all::Response, Error> {
         let page_size: usize = params
             .page_size
             .try_into()
             .map_err(|err| Error::InvalidPageSize(Box::new(err)))?;
 
+        let plugin = params
+            .plugin
+            .map(|plugin| plugin.parse())
+            .transpose()
+            .map_err(|err| Error::InvalidFilter(Box::new(err)))?;
+        let filter = EntryFilter {
+            parent_id: params.parent_id,
+            selector: params.selector,
+            spiffe_id_path_prefix: params.spiffe_id_path_prefix,
+            plugin,
+        };
+
         let (entries, next_page_token) = self
             .catalog
-            .list_all(params.page_token, page_size)
+            .list_filtered(&filter, params.page_token, page_size)
             .await
             .map_err(|err| Error::ListEntry(err))?;
 
         let response = list_all::Response {
-            entries,
+            entries: entries
+                .into_iter()
+                .filter(|entry| scope.allows(entry))
+                .collect(),
             next_page_token,
         };
 
         Ok(response)
     }
 
-    pub async fn delete_registration_entries(
+    pub(crate) async fn delete_registration_entries(
         &self,
         req: delete_registration_entries::Request,
+        scope: &Scope,
+        caller: &Caller,
     ) -> delete_registration_entries::Response {
-        let results = self
-            .catalog
-            .batch_delete(&req.ids)
+        let forbidden_ids = self.forbidden_ids(&req.ids, scope).await;
+        let ids: Vec<String> = req
+            .ids
+            .into_iter()
+            .filter(|id| !forbidden_ids.contains(id))
+            .collect();
+        let forbidden: Vec<operation::Error> = forbidden_ids
+            .into_iter()
+            .map(|id| scope.forbidden(id))
+            .collect();
+
+        if req.transactional && !forbidden.is_empty() {
+            return delete_registration_entries::Response {
+                results: Err(forbidden),
+            };
+        }
+
+        let revisions = self.revisions(&ids).await;
+        let results = if req.transactional {
+            self.catalog.batch_delete_transactional(&ids).await
+        } else {
+            self.catalog.batch_delete(&ids).await
+        };
+        let results = results.map_err(|err| err.into_iter().map(operation::Error::from).collect());
+
+        let changes = entry_changes(
+            EntryChangeKind::Deleted,
+            ids,
+            &failed_ids(&results),
+            &revisions,
+        );
+        self.audit(caller, "delete_registration_entries", changes, None)
+            .await;
+
+        delete_registration_entries::Response {
+            results: with_rejected(results, forbidden),
+        }
+    }
+
+    // Entries outside of the scope, or that are not valid, are rejected.
+    fn partition_entries(
+        &self,
+        entries: Vec<RegistrationEntry>,
+        scope: &Scope,
+    ) -> (Vec<RegistrationEntry>, Vec<operation::Error>) {
+        let mut allowed = Vec::with_capacity(entries.len());
+        let mut rejected = Vec::new();
+        for entry in entries {
+            if !scope.allows(&entry) {
+                rejected.push(scope.forbidden(entry.id));
+            } else if let Err(err) = self.check_entry(&entry) {
+                rejected.push(operation::Error {
+                    id: entry.id,
+                    error: err.to_string(),
+                    kind: operation::ErrorKind::Other,
+                });
+            } else {
+                allowed.push(entry);
+            }
+        }
+
+        (allowed, rejected)
+    }
+
+    // The selectors must be of the plugin type of the entry: workload selectors for workload entries, node
+    // selectors for node entries. Entries with other selectors would never match.
+    pub(crate) fn check_entry(&self, entry: &RegistrationEntry) -> Result<(), InvalidEntry> {
+        SpiffeId::from_entry_path(&self.trust_domain, &entry.spiffe_id_path)
+            .map_err(|err| InvalidEntry::SpiffeIdPath(entry.spiffe_id_path.clone(), err))?;
+
+        let invalid = match &entry.attestation_config {
+            AttestationConfig::Workload(attestation) => {
+                attestation.value.iter().find_map(|selector| {
+                    let err = selector.parse::<WorkloadSelector>().err()?;
+                    Some((selector, err))
+                })
+            }
+            AttestationConfig::Node(attestation) => attestation.value.iter().find_map(|selector| {
+                let err = selector.parse::<NodeSelector>().err()?;
+                Some((selector, err))
+            }),
+        };
+
+        match invalid {
+            Some((selector, err)) => Err(InvalidEntry::Selector(selector.clone(), err)),
+            None => Ok(()),
+        }
+    }
+
+    // Ids of the stored entries outside of the scope. Entries that cannot be read are left to the
+    // operation itself to report.
+    async fn forbidden_ids(&self, ids: &[String], scope: &Scope) -> Vec<String> {
+        if let Scope::Admin = scope {
+            return Vec::new();
+        }
+
+        self.catalog
+            .batch_get(ids)
             .await
-            .map_err(|err| err.into_iter().map(operation::Error::from).collect());
+            .into_iter()
+            .filter_map(|(id, result)| match result {
+                Ok(entry) if !scope.allows(&entry) => Some(id),
+                _ => None,
+            })
+            .collect()
+    }
+}
 
-        delete_registration_entries::Response { results }
+fn with_rejected(
+    results: Result<(), Vec<operation::Error>>,
+    rejected: Vec<operation::Error>,
+) -> Result<(), Vec<operation::Error>> {
+    if rejected.is_empty() {
+        return results;
     }
+
+    let mut errors = results.err().unwrap_or_default();
+    errors.extend(rejected);
+
+    Err(errors)
+}
+
+// Conflicts are reported with their own kind so the caller knows it can get the entry again and retry.
+pub(crate) fn update_error(error: (String, Box<dyn std::error::Error + Send>)) -> operation::Error {
+    let is_conflict = matches!(
+        error.1.downcast_ref::<catalog::Error>(),
+        Some(catalog::Error::RevisionConflict { .. })
+    );
+
+    let mut error = operation::Error::from(error);
+    if is_conflict {
+        error.kind = operation::ErrorKind::RevisionConflict;
+    }
+
+    error
 }
 
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
 
+    use catalog::{AgentBans, EntryPruner};
     use core_objects::{
         build_selector_string, AttestationConfig, EntryNodeAttestation, NodeAttestationPlugin,
-        NodeSelectorType, RegistrationEntry,
+        NodeSelectorType, RegistrationEntry, WorkloadSelectorType,
     };
+    use matches::assert_matches;
+    use server_config::{CatalogConfig, KeyStoreConfig};
 
-    use crate::Api;
+    use crate::{
+        info_api::{catalog_backend, key_store_backend},
+        tenancy::{tests::tenant_config, Tenant},
+        test_key_manager, test_trust_bundle_builder, Api,
+    };
 
     use super::*;
 
-    fn init() -> (Api, Vec<RegistrationEntry>) {
+    async fn init() -> (Api, Vec<RegistrationEntry>) {
         let catalog = Arc::new(catalog::inmemory::Catalog::new());
 
-        let api = Api { catalog };
-
-        let entry = RegistrationEntry {
-            id: String::from("id"),
-            other_identities: Vec::new(),
-            spiffe_id_path: "path".to_string(),
-            attestation_config: AttestationConfig::Node(EntryNodeAttestation {
-                value: vec![
-                    build_selector_string(&NodeSelectorType::Cluster, "selector1"),
-                    build_selector_string(&NodeSelectorType::AgentNameSpace, "selector2"),
-                ],
-                plugin: NodeAttestationPlugin::Sat,
-            }),
-            admin: false,
-            expires_at: 0,
-            dns_names: Vec::new(),
-            revision_number: 0,
-            store_svid: false,
+        let api = Api {
+            catalog: catalog.clone(),
+            trust_bundle_builder: test_trust_bundle_builder(catalog.clone()),
+            entry_pruner: Arc::new(EntryPruner::new(catalog.clone())),
+            agent_bans: Arc::new(AgentBans::new(catalog)),
+            key_manager: test_key_manager(Arc::new(catalog::inmemory::Catalog::new())).await,
+            svid_audit: None,
+            admin_audit: None,
+            trust_domain: "trust_domain".to_string(),
+            catalog_backend: catalog_backend(&CatalogConfig::Memory),
+            key_store_backend: key_store_backend(&KeyStoreConfig::Memory()),
+            faults: None,
+            build: Default::default(),
         };
+
+        let entry = node_entry("id");
         let entries = vec![entry];
 
         (api, entries)
@@ -126,27 +363,38 @@ mod tests {
 
     #[tokio::test]
     pub async fn create_registration_entries_test_happy_path() {
-        let (api, entries) = init();
+        let (api, entries) = init().await;
 
-        let req = create_registration_entries::Request { entries };
+        let req = create_registration_entries::Request {
+            entries,
 
-        api.create_registration_entries(req).await.results.unwrap();
+            transactional: false,
+        };
+
+        api.create_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await
+            .results
+            .unwrap();
     }
 
     #[tokio::test]
     pub async fn create_registration_entries_test_error_path() {
-        let (api, entries) = init();
+        let (api, entries) = init().await;
 
         let req = create_registration_entries::Request {
             entries: entries.clone(),
+            transactional: false,
         };
-        let _res = api.create_registration_entries(req).await;
+        let _res = api
+            .create_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await;
 
         let req = create_registration_entries::Request {
             entries: entries.clone(),
+            transactional: false,
         };
         let res = api
-            .create_registration_entries(req)
+            .create_registration_entries(req, &Scope::Admin, &Caller::default())
             .await
             .results
             .unwrap_err();
@@ -156,29 +404,144 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    pub async fn create_registration_entries_invalid_spiffe_id() {
+        let (api, mut entries) = init().await;
+        let mut invalid = entries[0].clone();
+        invalid.id = "invalid".to_string();
+        invalid.spiffe_id_path = "ns/../path".to_string();
+        entries.push(invalid);
+
+        let req = create_registration_entries::Request {
+            entries: entries.clone(),
+            transactional: true,
+        };
+        let errors = api
+            .create_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await
+            .results
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].id, "invalid");
+        assert!(api.catalog.batch_get(&["id".to_string()]).await[0]
+            .1
+            .is_err());
+
+        let req = create_registration_entries::Request {
+            entries,
+            transactional: false,
+        };
+        let errors = api
+            .create_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await
+            .results
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].id, "invalid");
+        assert!(api.catalog.batch_get(&["id".to_string()]).await[0]
+            .1
+            .is_ok());
+    }
+
+    #[tokio::test]
+    pub async fn check_entry_selectors() {
+        let (api, entries) = init().await;
+        api.check_entry(&entries[0]).unwrap();
+        api.check_entry(&workload_entry("workload", "default"))
+            .unwrap();
+
+        // A node selector on a workload entry.
+        let mut entry = workload_entry("workload", "default");
+        if let AttestationConfig::Workload(attestation) = &mut entry.attestation_config {
+            attestation.value.push("CLUSTER:demo".to_string());
+        }
+        assert_matches!(
+            api.check_entry(&entry).unwrap_err(),
+            InvalidEntry::Selector(selector, selector::Error::UnknownType(_)) if selector == "CLUSTER:demo"
+        );
+
+        let mut entry = entries[0].clone();
+        if let AttestationConfig::Node(attestation) = &mut entry.attestation_config {
+            attestation.value = vec!["demo".to_string()];
+        }
+        assert_matches!(
+            api.check_entry(&entry).unwrap_err(),
+            InvalidEntry::Selector(_, selector::Error::NoType(_))
+        );
+    }
+
     #[tokio::test]
     pub async fn update_registration_entries_test_happy_path() {
-        let (api, entries) = init();
+        let (api, entries) = init().await;
 
         let req = create_registration_entries::Request {
             entries: entries.clone(),
+            transactional: false,
         };
-        let _res = api.create_registration_entries(req).await;
+        let _res = api
+            .create_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await;
 
         let req = update_registration_entries::Request {
             entries: entries.clone(),
+            transactional: false,
         };
-        api.update_registration_entries(req).await.results.unwrap();
+        api.update_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await
+            .results
+            .unwrap();
+    }
+
+    #[tokio::test]
+    pub async fn update_registration_entries_test_revision_conflict() {
+        let (api, entries) = init().await;
+
+        let req = create_registration_entries::Request {
+            entries: entries.clone(),
+            transactional: false,
+        };
+        api.create_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await
+            .results
+            .unwrap();
+
+        let req = update_registration_entries::Request {
+            entries: entries.clone(),
+            transactional: false,
+        };
+        api.update_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await
+            .results
+            .unwrap();
+
+        // The entries still carry the revision they were created with.
+        let req = update_registration_entries::Request {
+            entries,
+            transactional: false,
+        };
+        let res = api
+            .update_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await
+            .results
+            .unwrap_err();
+        for res in res {
+            assert_eq!(res.id, "id".to_string());
+            assert_eq!(res.kind, operation::ErrorKind::RevisionConflict);
+        }
     }
 
     #[tokio::test]
     pub async fn update_registration_entries_test_error_path() {
-        let (api, entries) = init();
+        let (api, entries) = init().await;
 
-        let req = update_registration_entries::Request { entries };
+        let req = update_registration_entries::Request {
+            entries,
+
+            transactional: false,
+        };
 
         let res = api
-            .update_registration_entries(req)
+            .update_registration_entries(req, &Scope::Admin, &Caller::default())
             .await
             .results
             .unwrap_err();
@@ -189,33 +552,52 @@ mod tests {
 
     #[tokio::test]
     pub async fn delete_registration_entries_test_happy_path() {
-        let (api, entries) = init();
+        let (api, entries) = init().await;
 
         let mut ids = Vec::new();
         for entry in &entries {
             ids.push(entry.id.clone());
         }
-        let req = create_registration_entries::Request { entries };
+        let req = create_registration_entries::Request {
+            entries,
+            transactional: false,
+        };
 
-        let _res = api.create_registration_entries(req).await;
-        let req = delete_registration_entries::Request { ids };
-        api.delete_registration_entries(req).await.results.unwrap();
+        let _res = api
+            .create_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await;
+        let req = delete_registration_entries::Request {
+            ids,
+            transactional: false,
+        };
+        api.delete_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await
+            .results
+            .unwrap();
     }
 
     #[tokio::test]
     pub async fn delete_registration_entries_test_error_path() {
-        let (api, entries) = init();
+        let (api, entries) = init().await;
 
         let mut ids = Vec::new();
         for _entry in &entries {
             ids.push("dummy".to_string());
         }
-        let req = create_registration_entries::Request { entries };
+        let req = create_registration_entries::Request {
+            entries,
+            transactional: false,
+        };
 
-        let _res = api.create_registration_entries(req).await;
-        let req = delete_registration_entries::Request { ids };
+        let _res = api
+            .create_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await;
+        let req = delete_registration_entries::Request {
+            ids,
+            transactional: false,
+        };
         let res = api
-            .delete_registration_entries(req)
+            .delete_registration_entries(req, &Scope::Admin, &Caller::default())
             .await
             .results
             .unwrap_err();
@@ -226,39 +608,60 @@ mod tests {
     }
 
     #[tokio::test]
-    pub async fn list_registration_entries_test_happy_path() {
-        let (api, mut entries) = init();
+    pub async fn delete_registration_entries_test_transactional() {
+        let (api, entries) = init().await;
 
-        let entry2 = RegistrationEntry {
-            id: String::from("id2"),
-            other_identities: Vec::new(),
-            spiffe_id_path: "path".to_string(),
-            attestation_config: AttestationConfig::Node(EntryNodeAttestation {
-                value: vec![
-                    build_selector_string(&NodeSelectorType::Cluster, "selector1"),
-                    build_selector_string(&NodeSelectorType::AgentNameSpace, "selector2"),
-                ],
-                plugin: NodeAttestationPlugin::Sat,
-            }),
-            admin: false,
-            expires_at: 0,
-            dns_names: Vec::new(),
-            revision_number: 0,
-            store_svid: false,
+        let req = create_registration_entries::Request {
+            entries,
+            transactional: true,
         };
+        api.create_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await
+            .results
+            .unwrap();
+
+        let req = delete_registration_entries::Request {
+            ids: vec!["id".to_string(), "dummy".to_string()],
+            transactional: true,
+        };
+        let res = api
+            .delete_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await
+            .results
+            .unwrap_err();
+        assert_eq!(res.len(), 2);
+
+        let req = select_get_registration_entries::Request {
+            ids: vec!["id".to_string()],
+        };
+        let res = api
+            .select_list_registration_entries(req, &Scope::Admin)
+            .await;
+        assert!(res.results[0].is_ok());
+    }
+
+    #[tokio::test]
+    pub async fn list_registration_entries_test_happy_path() {
+        let (api, mut entries) = init().await;
+
+        let entry2 = node_entry("id2");
         entries.push(entry2);
 
         let req = create_registration_entries::Request {
             entries: entries.clone(),
+            transactional: false,
         };
-        let _res = api.create_registration_entries(req).await;
+        let _res = api
+            .create_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await;
 
         let req = list_all::Params {
             page_size: 1,
             page_token: None,
+            ..Default::default()
         };
 
-        let res = api.list_all(req).await.unwrap();
+        let res = api.list_all(req, &Scope::Admin).await.unwrap();
         assert_eq!(res.entries[0].id, "id", "Invalid entry");
         assert_eq!(res.entries.len(), 1);
         assert_eq!(res.next_page_token, Some("id2".to_string()));
@@ -266,8 +669,9 @@ mod tests {
         let req = list_all::Params {
             page_size: 1,
             page_token: Some("id2".to_string()),
+            ..Default::default()
         };
-        let res = api.list_all(req).await.unwrap();
+        let res = api.list_all(req, &Scope::Admin).await.unwrap();
         assert_eq!(res.entries[0].id, "id2", "Invalid entry");
         assert_eq!(res.entries.len(), 1);
         assert_eq!(res.next_page_token, None);
@@ -275,77 +679,58 @@ mod tests {
         let req = list_all::Params {
             page_size: 1,
             page_token: Some("j".to_string()),
+            ..Default::default()
         };
-        let res = api.list_all(req).await.unwrap();
+        let res = api.list_all(req, &Scope::Admin).await.unwrap();
         assert_eq!(res.entries.len(), 0);
         assert_eq!(res.next_page_token, None);
     }
 
     #[tokio::test]
     pub async fn list_registration_entries_test_error_path() {
-        let (api, mut entries) = init();
+        let (api, mut entries) = init().await;
 
-        let entry2 = RegistrationEntry {
-            id: String::from("id2"),
-            other_identities: Vec::new(),
-            spiffe_id_path: "path".to_string(),
-            attestation_config: AttestationConfig::Node(EntryNodeAttestation {
-                value: vec![
-                    build_selector_string(&NodeSelectorType::Cluster, "selector1"),
-                    build_selector_string(&NodeSelectorType::AgentNameSpace, "selector2"),
-                ],
-                plugin: NodeAttestationPlugin::Sat,
-            }),
-            admin: false,
-            expires_at: 0,
-            dns_names: Vec::new(),
-            revision_number: 0,
-            store_svid: false,
-        };
+        let entry2 = node_entry("id2");
         entries.push(entry2);
 
         let req = create_registration_entries::Request {
             entries: entries.clone(),
+            transactional: false,
         };
-        let _res = api.create_registration_entries(req).await;
+        let _res = api
+            .create_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await;
 
         let req = list_all::Params {
             page_size: 0,
             page_token: None,
+            ..Default::default()
         };
-        let _res = api.list_all(req).await.unwrap_err();
+        let _res = api.list_all(req, &Scope::Admin).await.unwrap_err();
     }
 
     #[tokio::test]
     pub async fn select_list_registration_entries_test_happy_path() {
-        let (api, mut entries) = init();
+        let (api, mut entries) = init().await;
 
-        let entry2 = RegistrationEntry {
-            id: String::from("id2"),
-            other_identities: Vec::new(),
-            spiffe_id_path: "path".to_string(),
-            attestation_config: AttestationConfig::Node(EntryNodeAttestation {
-                value: vec![
-                    build_selector_string(&NodeSelectorType::Cluster, "selector1"),
-                    build_selector_string(&NodeSelectorType::AgentNameSpace, "selector2"),
-                ],
-                plugin: NodeAttestationPlugin::Sat,
-            }),
-            admin: false,
-            expires_at: 0,
-            dns_names: Vec::new(),
-            revision_number: 0,
-            store_svid: false,
-        };
+        let entry2 = node_entry("id2");
         entries.push(entry2);
 
-        let req = create_registration_entries::Request { entries };
+        let req = create_registration_entries::Request {
+            entries,
+
+            transactional: false,
+        };
 
-        let _res = api.create_registration_entries(req).await;
+        let _res = api
+            .create_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await;
 
         let ids = vec!["id".to_string(), "id2".to_string()];
         let req = select_get_registration_entries::Request { ids };
-        let res = api.select_list_registration_entries(req).await;
+        let res = api
+            .select_list_registration_entries(req, &Scope::Admin)
+            .await;
         let results = res.results;
 
         assert_eq!(2, results.len());
@@ -355,8 +740,233 @@ mod tests {
 
         let ids = vec!["id".to_string()];
         let req = select_get_registration_entries::Request { ids };
-        let res = api.select_list_registration_entries(req).await;
+        let res = api
+            .select_list_registration_entries(req, &Scope::Admin)
+            .await;
         let results = res.results;
         assert_eq!(1, results.len());
     }
+
+    fn node_entry(id: &str) -> RegistrationEntry {
+        RegistrationEntry {
+            attestation_config: AttestationConfig::Node(EntryNodeAttestation {
+                value: vec![
+                    build_selector_string(&NodeSelectorType::Cluster, "selector1"),
+                    build_selector_string(&NodeSelectorType::AgentNameSpace, "selector2"),
+                ],
+                plugin: NodeAttestationPlugin::Sat,
+            }),
+            ..RegistrationEntry::test_entry(id)
+        }
+    }
+
+    // The SPIFFE ID path is below the namespace, as the paths of the tenants of `tenant_config`.
+    fn workload_entry(id: &str, namespace: &str) -> RegistrationEntry {
+        RegistrationEntry {
+            spiffe_id_path: format!("{}/{}", namespace, id),
+            ..RegistrationEntry::test_workload_entry(
+                id,
+                "id",
+                vec![build_selector_string(
+                    &WorkloadSelectorType::Namespace,
+                    namespace,
+                )],
+            )
+        }
+    }
+
+    #[tokio::test]
+    pub async fn tenant_scope_test() {
+        let (api, mut entries) = init().await;
+        entries.push(workload_entry("team-b", "team-b"));
+        let req = create_registration_entries::Request {
+            entries,
+            transactional: false,
+        };
+        api.create_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await
+            .results
+            .unwrap();
+
+        let scope = Scope::Tenant(Arc::new(Tenant::new(&tenant_config("team-a", &["team-a"]))));
+
+        // Only the entries of the namespaces of the tenant are created.
+        let req = create_registration_entries::Request {
+            entries: vec![
+                workload_entry("team-a", "team-a"),
+                workload_entry("other", "team-b"),
+            ],
+            transactional: false,
+        };
+        let errors = api
+            .create_registration_entries(req, &scope, &Caller::default())
+            .await
+            .results
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].id, "other");
+        assert_eq!(errors[0].kind, operation::ErrorKind::Forbidden);
+
+        // Nor can the tenant create an entry of its namespaces with the SPIFFE ID, the federated trust domains
+        // or the DNS names of another tenant.
+        let mut spiffe_id_path = workload_entry("path", "team-a");
+        spiffe_id_path.spiffe_id_path = "team-b/path".to_string();
+        let mut federates_with = workload_entry("federates_with", "team-a");
+        federates_with.federates_with = vec!["partner.example.org".to_string()];
+        let mut dns_names = workload_entry("dns_names", "team-a");
+        dns_names.dns_names = vec!["team-b.example.org".to_string()];
+        let req = create_registration_entries::Request {
+            entries: vec![spiffe_id_path, federates_with, dns_names],
+            transactional: false,
+        };
+        let errors = api
+            .create_registration_entries(req, &scope, &Caller::default())
+            .await
+            .results
+            .unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert!(errors
+            .iter()
+            .all(|error| error.kind == operation::ErrorKind::Forbidden));
+
+        // Nothing is created when a transactional batch has a forbidden entry.
+        let req = create_registration_entries::Request {
+            entries: vec![
+                workload_entry("team-a2", "team-a"),
+                workload_entry("other", "team-b"),
+            ],
+            transactional: true,
+        };
+        api.create_registration_entries(req, &scope, &Caller::default())
+            .await
+            .results
+            .unwrap_err();
+
+        let params = list_all::Params {
+            page_size: 10,
+            page_token: None,
+            ..Default::default()
+        };
+        let res = api.list_all(params, &scope).await.unwrap();
+        let ids: Vec<&str> = res.entries.iter().map(|entry| entry.id.as_str()).collect();
+        assert_eq!(ids, vec!["team-a"]);
+
+        let req = select_get_registration_entries::Request {
+            ids: vec!["team-a".to_string(), "team-b".to_string()],
+        };
+        let res = api.select_list_registration_entries(req, &scope).await;
+        assert!(res.results[0].is_ok());
+        assert_eq!(
+            res.results[1].as_ref().unwrap_err().kind,
+            operation::ErrorKind::Forbidden
+        );
+
+        // An entry of the tenant cannot be moved to another namespace, nor can another entry be taken over.
+        let req = update_registration_entries::Request {
+            entries: vec![
+                workload_entry("team-a", "team-b"),
+                workload_entry("team-b", "team-a"),
+            ],
+            transactional: false,
+        };
+        let errors = api
+            .update_registration_entries(req, &scope, &Caller::default())
+            .await
+            .results
+            .unwrap_err();
+        assert_eq!(errors.len(), 2);
+
+        let req = delete_registration_entries::Request {
+            ids: vec!["team-a".to_string(), "team-b".to_string()],
+            transactional: false,
+        };
+        let errors = api
+            .delete_registration_entries(req, &scope, &Caller::default())
+            .await
+            .results
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].id, "team-b");
+
+        let res = api
+            .select_list_registration_entries(
+                select_get_registration_entries::Request {
+                    ids: vec!["team-a".to_string(), "team-b".to_string()],
+                },
+                &Scope::Admin,
+            )
+            .await;
+        assert!(res.results[0].is_err());
+        assert!(res.results[1].is_ok());
+    }
+
+    #[tokio::test]
+    pub async fn list_registration_entries_filtered() {
+        let (api, mut entries) = init().await;
+        entries.push(workload_entry("team-a", "team-a"));
+        let mut entry = workload_entry("team-b", "team-b");
+        entry.spiffe_id_path = "team-b/path".to_string();
+        entries.push(entry);
+
+        let req = create_registration_entries::Request {
+            entries,
+            transactional: false,
+        };
+        api.create_registration_entries(req, &Scope::Admin, &Caller::default())
+            .await
+            .results
+            .unwrap();
+
+        let api = &api;
+        let list = move |params: list_all::Params| async move {
+            let res = api.list_all(params, &Scope::Admin).await.unwrap();
+            res.entries
+                .into_iter()
+                .map(|entry| entry.id)
+                .collect::<Vec<_>>()
+        };
+
+        let ids = list(list_all::Params {
+            page_size: 10,
+            parent_id: Some("id".to_string()),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(ids, vec!["team-a", "team-b"]);
+
+        let ids = list(list_all::Params {
+            page_size: 10,
+            selector: Some(build_selector_string(
+                &WorkloadSelectorType::Namespace,
+                "team-b",
+            )),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(ids, vec!["team-b"]);
+
+        let ids = list(list_all::Params {
+            page_size: 10,
+            spiffe_id_path_prefix: Some("team-b/".to_string()),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(ids, vec!["team-b"]);
+
+        let ids = list(list_all::Params {
+            page_size: 10,
+            plugin: Some("SAT".to_string()),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(ids, vec!["id"]);
+
+        let params = list_all::Params {
+            page_size: 10,
+            plugin: Some("UNKNOWN".to_string()),
+            ..Default::default()
+        };
+        let err = api.list_all(params, &Scope::Admin).await.unwrap_err();
+        assert!(matches!(err, Error::InvalidFilter(_)));
+    }
 }
@@ -8,4 +8,50 @@ pub enum Error {
     ListEntry(#[from] Box<dyn std::error::Error>),
     #[error("Invalid page size {0}")]
     InvalidPageSize(Box<dyn std::error::Error>),
+    #[error("Invalid entry filter: {0}")]
+    InvalidFilter(Box<dyn std::error::Error>),
+    #[error("Cannot build trust bundle: {0}")]
+    TrustBundle(Box<dyn std::error::Error>),
+    #[error("Cannot get trust bundle history: {0}")]
+    TrustBundleHistory(Box<dyn std::error::Error>),
+    #[error("Cannot roll back trust bundle: {0}")]
+    TrustBundleRollback(Box<dyn std::error::Error>),
+    #[error("Cannot revoke signing key: {0}")]
+    RevokeSigningKey(Box<dyn std::error::Error>),
+    #[error("Cannot export snapshot: {0}")]
+    ExportSnapshot(Box<dyn std::error::Error>),
+    #[error("Unsupported snapshot version {0}")]
+    UnsupportedSnapshotVersion(u32),
+    #[error("An agent ban needs at least one selector")]
+    EmptyAgentBan,
+    #[error("Invalid agent ban selector {0}: {1}")]
+    InvalidAgentBanSelector(String, core_objects::selector::Error),
+    #[error("Agent ban {0} does not exist")]
+    AgentBanNotFound(String),
+    #[error("Cannot update agent bans: {0}")]
+    AgentBans(Box<dyn std::error::Error>),
+    #[error("Cannot list attested agents: {0}")]
+    ListAttestedAgents(Box<dyn std::error::Error>),
+    #[error("The SVID audit log is not enabled")]
+    SvidAuditDisabled,
+    #[error("Cannot list issued SVIDs: {0}")]
+    ListIssuedSvids(Box<dyn std::error::Error>),
+    #[error("The admin audit log is not enabled")]
+    AdminAuditDisabled,
+    #[error("Cannot list admin operations: {0}")]
+    ListAdminOperations(Box<dyn std::error::Error>),
+    #[error("Cannot export SPIRE entries: {0}")]
+    ExportSpireEntries(Box<dyn std::error::Error>),
+    #[error("Cannot apply entries: {0}")]
+    ApplyEntries(Box<dyn std::error::Error>),
+    #[error("Entry {0} is given more than once")]
+    DuplicateEntry(String),
+    #[error("Entry {0} is outside of the SPIFFE ID path prefix of the apply")]
+    EntryOutsideApplyScope(String),
+    #[error("Entry {0} is not valid: {1}")]
+    InvalidEntry(String, crate::entries_api::InvalidEntry),
+    #[error("Fault injection is not enabled in this build")]
+    FaultInjectionDisabled,
+    #[error("Cannot reach catalog backend: {0}")]
+    CatalogBackend(Box<dyn std::error::Error>),
 }
@@ -0,0 +1,100 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use server_admin_api::faults::Faults;
+
+use crate::{error::Error, Api};
+
+impl Api {
+    pub fn get_faults(&self) -> Result<Faults, Error> {
+        let faults = self.faults.as_ref().ok_or(Error::FaultInjectionDisabled)?;
+
+        Ok(Faults {
+            catalog: faults.catalog.get(),
+            key_store: faults.key_store.get(),
+        })
+    }
+
+    // Replace the injected faults, the default config of a component stops injecting faults in it.
+    pub fn set_faults(&self, req: Faults) -> Result<Faults, Error> {
+        let faults = self.faults.as_ref().ok_or(Error::FaultInjectionDisabled)?;
+
+        log::warn!(
+            "Injecting faults: catalog {:?}, key store {:?}",
+            req.catalog,
+            req.key_store
+        );
+
+        faults.catalog.set(req.catalog);
+        faults.key_store.set(req.key_store);
+
+        self.get_faults()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use catalog::{AgentBans, EntryPruner};
+    use chaos_hooks::{FaultConfig, Faults as ComponentFaults};
+    use server_config::{CatalogConfig, KeyStoreConfig};
+
+    use crate::{
+        info_api::{catalog_backend, key_store_backend},
+        test_key_manager, test_trust_bundle_builder, ServerFaults,
+    };
+
+    use super::*;
+
+    async fn init(faults: Option<ServerFaults>) -> Api {
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+
+        Api {
+            catalog: catalog.clone(),
+            trust_bundle_builder: test_trust_bundle_builder(catalog.clone()),
+            entry_pruner: Arc::new(EntryPruner::new(catalog.clone())),
+            agent_bans: Arc::new(AgentBans::new(catalog)),
+            key_manager: test_key_manager(Arc::new(catalog::inmemory::Catalog::new())).await,
+            svid_audit: None,
+            admin_audit: None,
+            trust_domain: "trust_domain".to_string(),
+            catalog_backend: catalog_backend(&CatalogConfig::Memory),
+            key_store_backend: key_store_backend(&KeyStoreConfig::Memory()),
+            faults,
+            build: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn set_faults_happy_path() {
+        let catalog_faults = Arc::new(ComponentFaults::new("catalog"));
+        let faults = ServerFaults {
+            catalog: catalog_faults.clone(),
+            key_store: Arc::new(ComponentFaults::new("key store")),
+        };
+        let api = init(Some(faults)).await;
+
+        let catalog_config = FaultConfig {
+            fail_every: 1,
+            ..Default::default()
+        };
+        let res = api
+            .set_faults(Faults {
+                catalog: catalog_config.clone(),
+                key_store: FaultConfig::default(),
+            })
+            .unwrap();
+        assert_eq!(res.catalog, catalog_config);
+        assert_eq!(catalog_faults.get(), catalog_config);
+
+        catalog_faults.inject().await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn set_faults_disabled() {
+        let api = init(None).await;
+
+        let error = api.set_faults(Faults::default()).unwrap_err();
+        assert!(matches!(error, Error::FaultInjectionDisabled));
+    }
+}
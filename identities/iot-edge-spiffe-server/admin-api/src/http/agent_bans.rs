@@ -0,0 +1,116 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Bans of agents (GET to list them, POST to ban agents, DELETE to remove a ban).
+
+use std::borrow::Cow;
+
+use crate::{error::Error, tenancy::Caller, Api};
+use http::{Extensions, StatusCode};
+use http_common::{server, DynRangeBounds};
+use serde::de::IgnoredAny;
+use server_admin_api::{ban_agent, unban_agent, ApiVersion};
+
+use super::uri;
+
+pub(super) struct Route {
+    api: Api,
+    caller: Caller,
+}
+
+#[async_trait::async_trait]
+impl server::Route for Route {
+    type ApiVersion = ApiVersion;
+    type Service = super::Service;
+    type DeleteBody = unban_agent::Request;
+    type PostBody = ban_agent::Request;
+    type PutBody = IgnoredAny;
+
+    fn api_version() -> &'static dyn DynRangeBounds<Self::ApiVersion> {
+        &((ApiVersion::V2022_06_01)..)
+    }
+
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(Cow<'_, str>, Cow<'_, str>)],
+        extensions: &Extensions,
+    ) -> Option<Self> {
+        if path != uri::AGENT_BANS {
+            return None;
+        }
+
+        Some(Route {
+            api: service.api.clone(),
+            // Set by the tenant service, the route is not served without it.
+            caller: extensions.get::<Caller>()?.clone(),
+        })
+    }
+
+    async fn get(self) -> server::RouteResponse {
+        let res = self
+            .api
+            .list_agent_bans()
+            .await
+            .map_err(|err| server::Error {
+                status_code: StatusCode::INTERNAL_SERVER_ERROR,
+                message: err.to_string().into(),
+            })?;
+
+        let res = server::response::json(StatusCode::OK, &res);
+
+        Ok(res)
+    }
+
+    async fn post(self, body: Option<Self::PostBody>) -> server::RouteResponse {
+        let body = body.ok_or_else(|| server::Error {
+            status_code: StatusCode::BAD_REQUEST,
+            message: "missing request body".into(),
+        })?;
+
+        let res = self
+            .api
+            .ban_agent(body, &self.caller)
+            .await
+            .map_err(|err| {
+                let status_code = match err {
+                    Error::EmptyAgentBan | Error::InvalidAgentBanSelector(_, _) => {
+                        StatusCode::BAD_REQUEST
+                    }
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+
+                server::Error {
+                    status_code,
+                    message: err.to_string().into(),
+                }
+            })?;
+
+        let res = server::response::json(StatusCode::CREATED, &res);
+
+        Ok(res)
+    }
+
+    async fn delete(self, body: Option<Self::DeleteBody>) -> server::RouteResponse {
+        let body = body.ok_or_else(|| server::Error {
+            status_code: StatusCode::BAD_REQUEST,
+            message: "missing request body".into(),
+        })?;
+
+        self.api
+            .unban_agent(body, &self.caller)
+            .await
+            .map_err(|err| {
+                let status_code = match err {
+                    Error::AgentBanNotFound(_) => StatusCode::NOT_FOUND,
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                };
+
+                server::Error {
+                    status_code,
+                    message: err.to_string().into(),
+                }
+            })?;
+
+        Ok(server::response::no_content())
+    }
+}
@@ -0,0 +1,73 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Declarative management of the entries (POST): the catalog is made the same as the entries of the request.
+
+use std::borrow::Cow;
+
+use crate::{error::Error, tenancy::Caller, Api};
+use http::{Extensions, StatusCode};
+use http_common::{server, DynRangeBounds};
+use serde::de::IgnoredAny;
+use server_admin_api::{apply_entries, ApiVersion};
+
+use super::uri;
+
+pub(super) struct Route {
+    api: Api,
+    caller: Caller,
+}
+
+#[async_trait::async_trait]
+impl server::Route for Route {
+    type ApiVersion = ApiVersion;
+    type Service = super::Service;
+    type DeleteBody = IgnoredAny;
+    type PostBody = apply_entries::Request;
+    type PutBody = IgnoredAny;
+
+    fn api_version() -> &'static dyn DynRangeBounds<Self::ApiVersion> {
+        &((ApiVersion::V2022_06_01)..)
+    }
+
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(Cow<'_, str>, Cow<'_, str>)],
+        extensions: &Extensions,
+    ) -> Option<Self> {
+        if path != uri::APPLY_ENTRIES {
+            return None;
+        }
+
+        Some(Route {
+            api: service.api.clone(),
+            // Set by the tenant service, the route is not served without it.
+            caller: extensions.get::<Caller>()?.clone(),
+        })
+    }
+
+    async fn post(self, body: Option<Self::PostBody>) -> server::RouteResponse {
+        let body = body.ok_or_else(|| server::Error {
+            status_code: StatusCode::BAD_REQUEST,
+            message: "missing request body".into(),
+        })?;
+
+        let res = self
+            .api
+            .apply_entries(body, &self.caller)
+            .await
+            .map_err(|err| server::Error {
+                status_code: match err {
+                    Error::DuplicateEntry(_)
+                    | Error::EntryOutsideApplyScope(_)
+                    | Error::InvalidEntry(_, _) => StatusCode::BAD_REQUEST,
+                    _ => StatusCode::INTERNAL_SERVER_ERROR,
+                },
+                message: format!("Error processing apply entries request: {}", err).into(),
+            })?;
+
+        let res = server::response::json(StatusCode::OK, &res);
+
+        Ok(res)
+    }
+}
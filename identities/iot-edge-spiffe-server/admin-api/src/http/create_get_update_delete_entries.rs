@@ -5,7 +5,10 @@
 
 use std::borrow::Cow;
 
-use crate::Api;
+use crate::{
+    tenancy::{Caller, Scope},
+    Api,
+};
 use http::{Extensions, StatusCode};
 use http_common::{server, DynRangeBounds};
 use server_admin_api::{
@@ -18,7 +21,10 @@ use super::uri;
 pub(super) struct Route {
     page_size: Option<String>,
     page_token: Option<String>,
+    filter: Filter,
     api: Api,
+    scope: Scope,
+    caller: Caller,
 }
 
 #[async_trait::async_trait]
@@ -37,7 +43,7 @@ impl server::Route for Route {
         service: &Self::Service,
         path: &str,
         query: &[(Cow<'_, str>, Cow<'_, str>)],
-        _extensions: &Extensions,
+        extensions: &Extensions,
     ) -> Option<Self> {
         if path != uri::CREATE_DELETE_UPDATE_REGISTRATION_ENTRIES {
             return None;
@@ -45,11 +51,16 @@ impl server::Route for Route {
 
         let mut page_size: Option<String> = None;
         let mut page_token: Option<String> = None;
+        let mut filter = Filter::default();
 
         for q in query.iter() {
             match &q.0 as &str {
                 "page_size" => page_size = Some(q.1.to_string()),
                 "page_token" => page_token = Some(q.1.to_string()),
+                "parent_id" => filter.parent_id = Some(q.1.to_string()),
+                "selector" => filter.selector = Some(q.1.to_string()),
+                "spiffe_id_path_prefix" => filter.spiffe_id_path_prefix = Some(q.1.to_string()),
+                "plugin" => filter.plugin = Some(q.1.to_string()),
                 _ => {}
             }
         }
@@ -57,7 +68,11 @@ impl server::Route for Route {
         Some(Route {
             page_size,
             page_token,
+            filter,
             api: service.api.clone(),
+            // Set by the tenant service, the route is not served without them.
+            scope: extensions.get::<Scope>()?.clone(),
+            caller: extensions.get::<Caller>()?.clone(),
         })
     }
 
@@ -77,9 +92,13 @@ impl server::Route for Route {
         let params = list_all::Params {
             page_size,
             page_token: self.page_token,
+            parent_id: self.filter.parent_id,
+            selector: self.filter.selector,
+            spiffe_id_path_prefix: self.filter.spiffe_id_path_prefix,
+            plugin: self.filter.plugin,
         };
 
-        let res = self.api.list_all(params).await;
+        let res = self.api.list_all(params, &self.scope).await;
         let res = match res {
             Ok(res) => res,
             Err(err) => {
@@ -101,7 +120,10 @@ impl server::Route for Route {
             message: "missing request body".into(),
         })?;
 
-        let res = self.api.delete_registration_entries(body).await;
+        let res = self
+            .api
+            .delete_registration_entries(body, &self.scope, &self.caller)
+            .await;
 
         let res = server::response::json(StatusCode::OK, &res);
 
@@ -114,7 +136,10 @@ impl server::Route for Route {
             message: "missing request body".into(),
         })?;
 
-        let res = self.api.create_registration_entries(body).await;
+        let res = self
+            .api
+            .create_registration_entries(body, &self.scope, &self.caller)
+            .await;
 
         let res = server::response::json(StatusCode::CREATED, &res);
 
@@ -122,10 +147,22 @@ impl server::Route for Route {
     }
 
     async fn put(self, body: Self::PutBody) -> server::RouteResponse {
-        let res = self.api.update_registration_entries(body).await;
+        let res = self
+            .api
+            .update_registration_entries(body, &self.scope, &self.caller)
+            .await;
 
         let res = server::response::json(StatusCode::OK, &res);
 
         Ok(res)
     }
 }
+
+// Query parameters filtering the listed entries, see `list_all::Params`.
+#[derive(Default)]
+struct Filter {
+    parent_id: Option<String>,
+    selector: Option<String>,
+    spiffe_id_path_prefix: Option<String>,
+    plugin: Option<String>,
+}
@@ -0,0 +1,68 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Faults injected in the catalog and the key store (GET to read them, PUT to change them).
+// Only served by a server built with the `chaos` feature.
+
+use std::borrow::Cow;
+
+use crate::Api;
+use http::{Extensions, StatusCode};
+use http_common::{server, DynRangeBounds};
+use serde::de::IgnoredAny;
+use server_admin_api::{faults, ApiVersion};
+
+use super::uri;
+
+pub(super) struct Route {
+    api: Api,
+}
+
+#[async_trait::async_trait]
+impl server::Route for Route {
+    type ApiVersion = ApiVersion;
+    type Service = super::Service;
+    type DeleteBody = IgnoredAny;
+    type PostBody = IgnoredAny;
+    type PutBody = faults::Faults;
+
+    fn api_version() -> &'static dyn DynRangeBounds<Self::ApiVersion> {
+        &((ApiVersion::V2022_06_01)..)
+    }
+
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(Cow<'_, str>, Cow<'_, str>)],
+        _extensions: &Extensions,
+    ) -> Option<Self> {
+        if path != uri::FAULTS || service.api.faults.is_none() {
+            return None;
+        }
+
+        Some(Route {
+            api: service.api.clone(),
+        })
+    }
+
+    async fn get(self) -> server::RouteResponse {
+        let res = self.api.get_faults().map_err(|err| server::Error {
+            status_code: StatusCode::NOT_FOUND,
+            message: err.to_string().into(),
+        })?;
+
+        let res = server::response::json(StatusCode::OK, &res);
+
+        Ok(res)
+    }
+
+    async fn put(self, body: Self::PutBody) -> server::RouteResponse {
+        let res = self.api.set_faults(body).map_err(|err| server::Error {
+            status_code: StatusCode::NOT_FOUND,
+            message: err.to_string().into(),
+        })?;
+
+        let res = server::response::json(StatusCode::OK, &res);
+
+        Ok(res)
+    }
+}
@@ -1,5 +1,5 @@
 // Copyright (c) Microsoft. All rights reserved.
-use crate::Api;
+use crate::{tenancy::Scope, Api};
 use http::{Extensions, StatusCode};
 use http_common::{server, DynRangeBounds};
 use serde::de::IgnoredAny;
@@ -10,6 +10,7 @@ use super::uri;
 
 pub(super) struct Route {
     api: Api,
+    scope: Scope,
 }
 
 #[async_trait::async_trait]
@@ -28,13 +29,15 @@ impl server::Route for Route {
         service: &Self::Service,
         path: &str,
         _query: &[(Cow<'_, str>, Cow<'_, str>)],
-        _extensions: &Extensions,
+        extensions: &Extensions,
     ) -> Option<Self> {
         if path != uri::SELECT_GET_REGISTRATION_ENTRIES {
             return None;
         }
         Some(Route {
             api: service.api.clone(),
+            // Set by the tenant service, the route is not served without it.
+            scope: extensions.get::<Scope>()?.clone(),
         })
     }
 
@@ -44,7 +47,10 @@ impl server::Route for Route {
             message: "missing request body".into(),
         })?;
 
-        let res = self.api.select_list_registration_entries(body).await;
+        let res = self
+            .api
+            .select_list_registration_entries(body, &self.scope)
+            .await;
 
         let res = server::response::json(StatusCode::OK, &res);
 
@@ -0,0 +1,60 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Current trust bundle of the server, with its JWT keys as a plain JWK set.
+
+use std::borrow::Cow;
+
+use crate::Api;
+use http::{Extensions, StatusCode};
+use http_common::{server, DynRangeBounds};
+use serde::de::IgnoredAny;
+use server_admin_api::ApiVersion;
+
+use super::uri;
+
+pub(super) struct Route {
+    api: Api,
+}
+
+#[async_trait::async_trait]
+impl server::Route for Route {
+    type ApiVersion = ApiVersion;
+    type Service = super::Service;
+    type DeleteBody = IgnoredAny;
+    type PostBody = IgnoredAny;
+    type PutBody = IgnoredAny;
+
+    fn api_version() -> &'static dyn DynRangeBounds<Self::ApiVersion> {
+        &((ApiVersion::V2022_06_01)..)
+    }
+
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(Cow<'_, str>, Cow<'_, str>)],
+        _extensions: &Extensions,
+    ) -> Option<Self> {
+        if path != uri::TRUST_BUNDLE {
+            return None;
+        }
+
+        Some(Route {
+            api: service.api.clone(),
+        })
+    }
+
+    async fn get(self) -> server::RouteResponse {
+        let res = self
+            .api
+            .get_trust_bundle()
+            .await
+            .map_err(|err| server::Error {
+                status_code: StatusCode::INTERNAL_SERVER_ERROR,
+                message: format!("Error processing trust bundle request: {}", err).into(),
+            })?;
+
+        let res = server::response::json(StatusCode::OK, &res);
+
+        Ok(res)
+    }
+}
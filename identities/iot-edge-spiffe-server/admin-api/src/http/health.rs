@@ -0,0 +1,58 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Health of the server. The body is returned in both cases so the backends can be checked even when unhealthy.
+
+use std::borrow::Cow;
+
+use crate::Api;
+use http::{Extensions, StatusCode};
+use http_common::{server, DynRangeBounds};
+use serde::de::IgnoredAny;
+use server_admin_api::ApiVersion;
+
+use super::uri;
+
+pub(super) struct Route {
+    api: Api,
+}
+
+#[async_trait::async_trait]
+impl server::Route for Route {
+    type ApiVersion = ApiVersion;
+    type Service = super::Service;
+    type DeleteBody = IgnoredAny;
+    type PostBody = IgnoredAny;
+    type PutBody = IgnoredAny;
+
+    fn api_version() -> &'static dyn DynRangeBounds<Self::ApiVersion> {
+        &((ApiVersion::V2022_06_01)..)
+    }
+
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(Cow<'_, str>, Cow<'_, str>)],
+        _extensions: &Extensions,
+    ) -> Option<Self> {
+        if path != uri::HEALTH {
+            return None;
+        }
+
+        Some(Route {
+            api: service.api.clone(),
+        })
+    }
+
+    async fn get(self) -> server::RouteResponse {
+        let res = self.api.get_health().await;
+
+        let status_code = if res.healthy {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        let res = server::response::json(status_code, &res);
+
+        Ok(res)
+    }
+}
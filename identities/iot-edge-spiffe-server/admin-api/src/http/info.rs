@@ -0,0 +1,56 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Backends and trust domain of the server, so operators can check the configuration of a fleet remotely.
+
+use std::borrow::Cow;
+
+use crate::Api;
+use http::{Extensions, StatusCode};
+use http_common::{server, DynRangeBounds};
+use serde::de::IgnoredAny;
+use server_admin_api::ApiVersion;
+
+use super::uri;
+
+pub(super) struct Route {
+    api: Api,
+}
+
+#[async_trait::async_trait]
+impl server::Route for Route {
+    type ApiVersion = ApiVersion;
+    type Service = super::Service;
+    type DeleteBody = IgnoredAny;
+    type PostBody = IgnoredAny;
+    type PutBody = IgnoredAny;
+
+    fn api_version() -> &'static dyn DynRangeBounds<Self::ApiVersion> {
+        &((ApiVersion::V2022_06_01)..)
+    }
+
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(Cow<'_, str>, Cow<'_, str>)],
+        _extensions: &Extensions,
+    ) -> Option<Self> {
+        if path != uri::INFO {
+            return None;
+        }
+
+        Some(Route {
+            api: service.api.clone(),
+        })
+    }
+
+    async fn get(self) -> server::RouteResponse {
+        let res = self.api.get_info().await.map_err(|err| server::Error {
+            status_code: StatusCode::SERVICE_UNAVAILABLE,
+            message: format!("Error processing info request: {}", err).into(),
+        })?;
+
+        let res = server::response::json(StatusCode::OK, &res);
+
+        Ok(res)
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Changes recorded in the admin audit log, to trace who changed an entry.
+
+use std::borrow::Cow;
+
+use crate::Api;
+use http::{Extensions, StatusCode};
+use http_common::{server, DynRangeBounds};
+use serde::de::IgnoredAny;
+use server_admin_api::{list_admin_operations, ApiVersion};
+
+use super::{list_issued_svids::parse_time, uri};
+
+pub(super) struct Route {
+    api: Api,
+    page_size: Option<String>,
+    page_token: Option<String>,
+    caller: Option<String>,
+    entry_id: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl server::Route for Route {
+    type ApiVersion = ApiVersion;
+    type Service = super::Service;
+    type DeleteBody = IgnoredAny;
+    type PostBody = IgnoredAny;
+    type PutBody = IgnoredAny;
+
+    fn api_version() -> &'static dyn DynRangeBounds<Self::ApiVersion> {
+        &((ApiVersion::V2022_06_01)..)
+    }
+
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        query: &[(Cow<'_, str>, Cow<'_, str>)],
+        _extensions: &Extensions,
+    ) -> Option<Self> {
+        if path != uri::ADMIN_OPERATIONS {
+            return None;
+        }
+
+        let mut route = Route {
+            api: service.api.clone(),
+            page_size: None,
+            page_token: None,
+            caller: None,
+            entry_id: None,
+            since: None,
+            until: None,
+        };
+
+        for q in query.iter() {
+            let value = Some(q.1.to_string());
+            match &q.0 as &str {
+                "page_size" => route.page_size = value,
+                "page_token" => route.page_token = value,
+                "caller" => route.caller = value,
+                "entry_id" => route.entry_id = value,
+                "since" => route.since = value,
+                "until" => route.until = value,
+                _ => {}
+            }
+        }
+
+        Some(route)
+    }
+
+    async fn get(self) -> server::RouteResponse {
+        let page_size = self
+            .page_size
+            .ok_or(server::Error {
+                status_code: StatusCode::BAD_REQUEST,
+                message: "Please provide the page size parameter".into(),
+            })?
+            .parse::<u32>()
+            .map_err(|_| server::Error {
+                status_code: StatusCode::BAD_REQUEST,
+                message: "Could not convert page size to u32".into(),
+            })?;
+
+        let params = list_admin_operations::Params {
+            page_size,
+            page_token: self.page_token,
+            caller: self.caller,
+            entry_id: self.entry_id,
+            since: parse_time("since", self.since)?,
+            until: parse_time("until", self.until)?,
+        };
+
+        let res = self
+            .api
+            .list_admin_operations(params)
+            .await
+            .map_err(|err| server::Error {
+                status_code: match err {
+                    crate::error::Error::AdminAuditDisabled => StatusCode::NOT_FOUND,
+                    _ => StatusCode::BAD_REQUEST,
+                },
+                message: format!("Error listing admin operations: {}", err).into(),
+            })?;
+
+        let res = server::response::json(StatusCode::OK, &res);
+
+        Ok(res)
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Agents attested by the servers, with the time they were last seen.
+
+use std::borrow::Cow;
+
+use crate::Api;
+use http::{Extensions, StatusCode};
+use http_common::{server, DynRangeBounds};
+use serde::de::IgnoredAny;
+use server_admin_api::{list_attested_agents, ApiVersion};
+
+use super::uri;
+
+pub(super) struct Route {
+    api: Api,
+    page_size: Option<String>,
+    page_token: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl server::Route for Route {
+    type ApiVersion = ApiVersion;
+    type Service = super::Service;
+    type DeleteBody = IgnoredAny;
+    type PostBody = IgnoredAny;
+    type PutBody = IgnoredAny;
+
+    fn api_version() -> &'static dyn DynRangeBounds<Self::ApiVersion> {
+        &((ApiVersion::V2022_06_01)..)
+    }
+
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        query: &[(Cow<'_, str>, Cow<'_, str>)],
+        _extensions: &Extensions,
+    ) -> Option<Self> {
+        if path != uri::ATTESTED_AGENTS {
+            return None;
+        }
+
+        let mut page_size: Option<String> = None;
+        let mut page_token: Option<String> = None;
+
+        for q in query.iter() {
+            match &q.0 as &str {
+                "page_size" => page_size = Some(q.1.to_string()),
+                "page_token" => page_token = Some(q.1.to_string()),
+                _ => {}
+            }
+        }
+
+        Some(Route {
+            api: service.api.clone(),
+            page_size,
+            page_token,
+        })
+    }
+
+    async fn get(self) -> server::RouteResponse {
+        let page_size = self
+            .page_size
+            .ok_or(server::Error {
+                status_code: StatusCode::BAD_REQUEST,
+                message: "Please provide the page size parameter".into(),
+            })?
+            .parse::<u32>()
+            .map_err(|_| server::Error {
+                status_code: StatusCode::BAD_REQUEST,
+                message: "Could not convert page size to u32".into(),
+            })?;
+
+        let params = list_attested_agents::Params {
+            page_size,
+            page_token: self.page_token,
+        };
+
+        let res = self
+            .api
+            .list_attested_agents(params)
+            .await
+            .map_err(|err| server::Error {
+                status_code: StatusCode::BAD_REQUEST,
+                message: format!("Error listing attested agents: {}", err).into(),
+            })?;
+
+        let res = server::response::json(StatusCode::OK, &res);
+
+        Ok(res)
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// SVIDs recorded in the audit log, for security reviews.
+
+use std::borrow::Cow;
+
+use crate::Api;
+use http::{Extensions, StatusCode};
+use http_common::{server, DynRangeBounds};
+use serde::de::IgnoredAny;
+use server_admin_api::{list_issued_svids, ApiVersion};
+
+use super::uri;
+
+pub(super) struct Route {
+    api: Api,
+    page_size: Option<String>,
+    page_token: Option<String>,
+    spiffe_id: Option<String>,
+    entry_id: Option<String>,
+    agent_id: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl server::Route for Route {
+    type ApiVersion = ApiVersion;
+    type Service = super::Service;
+    type DeleteBody = IgnoredAny;
+    type PostBody = IgnoredAny;
+    type PutBody = IgnoredAny;
+
+    fn api_version() -> &'static dyn DynRangeBounds<Self::ApiVersion> {
+        &((ApiVersion::V2022_06_01)..)
+    }
+
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        query: &[(Cow<'_, str>, Cow<'_, str>)],
+        _extensions: &Extensions,
+    ) -> Option<Self> {
+        if path != uri::ISSUED_SVIDS {
+            return None;
+        }
+
+        let mut route = Route {
+            api: service.api.clone(),
+            page_size: None,
+            page_token: None,
+            spiffe_id: None,
+            entry_id: None,
+            agent_id: None,
+            since: None,
+            until: None,
+        };
+
+        for q in query.iter() {
+            let value = Some(q.1.to_string());
+            match &q.0 as &str {
+                "page_size" => route.page_size = value,
+                "page_token" => route.page_token = value,
+                "spiffe_id" => route.spiffe_id = value,
+                "entry_id" => route.entry_id = value,
+                "agent_id" => route.agent_id = value,
+                "since" => route.since = value,
+                "until" => route.until = value,
+                _ => {}
+            }
+        }
+
+        Some(route)
+    }
+
+    async fn get(self) -> server::RouteResponse {
+        let page_size = self
+            .page_size
+            .ok_or(server::Error {
+                status_code: StatusCode::BAD_REQUEST,
+                message: "Please provide the page size parameter".into(),
+            })?
+            .parse::<u32>()
+            .map_err(|_| server::Error {
+                status_code: StatusCode::BAD_REQUEST,
+                message: "Could not convert page size to u32".into(),
+            })?;
+
+        let params = list_issued_svids::Params {
+            page_size,
+            page_token: self.page_token,
+            spiffe_id: self.spiffe_id,
+            entry_id: self.entry_id,
+            agent_id: self.agent_id,
+            since: parse_time("since", self.since)?,
+            until: parse_time("until", self.until)?,
+        };
+
+        let res = self
+            .api
+            .list_issued_svids(params)
+            .await
+            .map_err(|err| server::Error {
+                status_code: match err {
+                    crate::error::Error::SvidAuditDisabled => StatusCode::NOT_FOUND,
+                    _ => StatusCode::BAD_REQUEST,
+                },
+                message: format!("Error listing issued SVIDs: {}", err).into(),
+            })?;
+
+        let res = server::response::json(StatusCode::OK, &res);
+
+        Ok(res)
+    }
+}
+
+pub(super) fn parse_time(name: &str, value: Option<String>) -> Result<Option<u64>, server::Error> {
+    value
+        .map(|value| {
+            value.parse::<u64>().map_err(|_| server::Error {
+                status_code: StatusCode::BAD_REQUEST,
+                message: format!("Could not convert {} to an epoch time", name).into(),
+            })
+        })
+        .transpose()
+}
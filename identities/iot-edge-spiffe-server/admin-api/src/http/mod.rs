@@ -1,27 +1,95 @@
 // Copyright (c) Microsoft. All rights reserved.
 
 use crate::Api;
+use http::Method;
 use http_common::make_service;
+use request_limits::EndpointClass;
 use server_admin_api::ApiVersion;
 
+mod agent_bans;
+mod apply_entries;
 mod create_get_update_delete_entries;
+#[cfg(feature = "chaos")]
+mod faults;
 mod get_select_entries;
+mod get_trust_bundle;
+mod health;
+mod info;
+mod list_admin_operations;
+mod list_attested_agents;
+mod list_issued_svids;
+mod revoke_signing_key;
+mod snapshot;
+mod spire_entries;
+mod trust_bundle;
 
 #[derive(Clone)]
 pub struct Service {
     pub(crate) api: Api,
 }
 
-make_service! {
-    service: Service,
-    api_version: ApiVersion,
-    routes: [
-        create_get_update_delete_entries::Route,
-        get_select_entries::Route,
-    ],
+// The faults endpoint is only served by the servers built with the `chaos` feature.
+macro_rules! admin_service {
+    ($($routes:tt)*) => {
+        make_service! {
+            service: Service,
+            api_version: ApiVersion,
+            routes: [
+                agent_bans::Route,
+                apply_entries::Route,
+                create_get_update_delete_entries::Route,
+                get_select_entries::Route,
+                get_trust_bundle::Route,
+                health::Route,
+                info::Route,
+                list_admin_operations::Route,
+                list_attested_agents::Route,
+                list_issued_svids::Route,
+                revoke_signing_key::Route,
+                snapshot::Route,
+                spire_entries::Route,
+                trust_bundle::Route,
+                $($routes)*
+            ],
+        }
+    };
+}
+
+#[cfg(feature = "chaos")]
+admin_service!(faults::Route,);
+#[cfg(not(feature = "chaos"))]
+admin_service!();
+
+// Entry writes, entry lookups, applies, snapshot and SPIRE entry imports take whole lists of entries.
+pub(crate) fn endpoint_class(method: &Method, path: &str) -> EndpointClass {
+    if method == Method::GET {
+        return EndpointClass::Default;
+    }
+
+    match path {
+        uri::CREATE_DELETE_UPDATE_REGISTRATION_ENTRIES
+        | uri::SELECT_GET_REGISTRATION_ENTRIES
+        | uri::APPLY_ENTRIES
+        | uri::SNAPSHOT
+        | uri::SPIRE_ENTRIES => EndpointClass::Batch,
+        _ => EndpointClass::Default,
+    }
 }
 
 pub mod uri {
     pub const CREATE_DELETE_UPDATE_REGISTRATION_ENTRIES: &str = "/entries";
     pub const SELECT_GET_REGISTRATION_ENTRIES: &str = "/select-list-entries";
+    pub const APPLY_ENTRIES: &str = "/entries/apply";
+    pub const TRUST_BUNDLE: &str = "/trust-bundle";
+    pub const TRUST_BUNDLE_HISTORY: &str = "/trust-bundle/history";
+    pub const REVOKE_SIGNING_KEY: &str = "/trust-bundle/revoke-signing-key";
+    pub const INFO: &str = "/info";
+    pub const HEALTH: &str = "/health";
+    pub const SNAPSHOT: &str = "/snapshot";
+    pub const FAULTS: &str = "/faults";
+    pub const AGENT_BANS: &str = "/agent-bans";
+    pub const ATTESTED_AGENTS: &str = "/agents";
+    pub const ISSUED_SVIDS: &str = "/svids";
+    pub const ADMIN_OPERATIONS: &str = "/admin-operations";
+    pub const SPIRE_ENTRIES: &str = "/spire/entries";
 }
@@ -0,0 +1,63 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Emergency replacement of the signing key (POST), when the current key may be compromised.
+
+use std::borrow::Cow;
+
+use crate::{tenancy::Caller, Api};
+use http::{Extensions, StatusCode};
+use http_common::{server, DynRangeBounds};
+use serde::de::IgnoredAny;
+use server_admin_api::ApiVersion;
+
+use super::uri;
+
+pub(super) struct Route {
+    api: Api,
+    caller: Caller,
+}
+
+#[async_trait::async_trait]
+impl server::Route for Route {
+    type ApiVersion = ApiVersion;
+    type Service = super::Service;
+    type DeleteBody = IgnoredAny;
+    type PostBody = IgnoredAny;
+    type PutBody = IgnoredAny;
+
+    fn api_version() -> &'static dyn DynRangeBounds<Self::ApiVersion> {
+        &((ApiVersion::V2022_06_01)..)
+    }
+
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(Cow<'_, str>, Cow<'_, str>)],
+        extensions: &Extensions,
+    ) -> Option<Self> {
+        if path != uri::REVOKE_SIGNING_KEY {
+            return None;
+        }
+
+        Some(Route {
+            api: service.api.clone(),
+            // Set by the tenant service, the route is not served without it.
+            caller: extensions.get::<Caller>()?.clone(),
+        })
+    }
+
+    async fn post(self, _body: Option<Self::PostBody>) -> server::RouteResponse {
+        let res = self
+            .api
+            .revoke_signing_key(&self.caller)
+            .await
+            .map_err(|err| server::Error {
+                status_code: StatusCode::INTERNAL_SERVER_ERROR,
+                message: format!("Error processing signing key revocation request: {}", err).into(),
+            })?;
+
+        let res = server::response::json(StatusCode::OK, &res);
+
+        Ok(res)
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Export (GET) and import (POST) of a snapshot of all the registration entries.
+
+use std::borrow::Cow;
+
+use crate::{tenancy::Caller, Api};
+use http::{Extensions, StatusCode};
+use http_common::{server, DynRangeBounds};
+use serde::de::IgnoredAny;
+use server_admin_api::{import_snapshot, ApiVersion};
+
+use super::uri;
+
+pub(super) struct Route {
+    api: Api,
+    caller: Caller,
+}
+
+#[async_trait::async_trait]
+impl server::Route for Route {
+    type ApiVersion = ApiVersion;
+    type Service = super::Service;
+    type DeleteBody = IgnoredAny;
+    type PostBody = import_snapshot::Request;
+    type PutBody = IgnoredAny;
+
+    fn api_version() -> &'static dyn DynRangeBounds<Self::ApiVersion> {
+        &((ApiVersion::V2022_06_01)..)
+    }
+
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(Cow<'_, str>, Cow<'_, str>)],
+        extensions: &Extensions,
+    ) -> Option<Self> {
+        if path != uri::SNAPSHOT {
+            return None;
+        }
+
+        Some(Route {
+            api: service.api.clone(),
+            // Set by the tenant service, the route is not served without it.
+            caller: extensions.get::<Caller>()?.clone(),
+        })
+    }
+
+    async fn get(self) -> server::RouteResponse {
+        let res = self
+            .api
+            .export_snapshot()
+            .await
+            .map_err(|err| server::Error {
+                status_code: StatusCode::INTERNAL_SERVER_ERROR,
+                message: format!("Error processing export snapshot request: {}", err).into(),
+            })?;
+
+        let res = server::response::json(StatusCode::OK, &res);
+
+        Ok(res)
+    }
+
+    async fn post(self, body: Option<Self::PostBody>) -> server::RouteResponse {
+        let body = body.ok_or_else(|| server::Error {
+            status_code: StatusCode::BAD_REQUEST,
+            message: "missing request body".into(),
+        })?;
+
+        let res = self
+            .api
+            .import_snapshot(body, &self.caller)
+            .await
+            .map_err(|err| server::Error {
+                status_code: StatusCode::BAD_REQUEST,
+                message: format!("Error processing import snapshot request: {}", err).into(),
+            })?;
+
+        let res = server::response::json(StatusCode::CREATED, &res);
+
+        Ok(res)
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Export (GET) and import (POST) of the registration entries in the JSON entry format of SPIRE.
+
+use std::borrow::Cow;
+
+use crate::{tenancy::Caller, Api};
+use http::{Extensions, StatusCode};
+use http_common::{server, DynRangeBounds};
+use serde::de::IgnoredAny;
+use server_admin_api::{spire_entries, ApiVersion};
+
+use super::uri;
+
+pub(super) struct Route {
+    api: Api,
+    caller: Caller,
+}
+
+#[async_trait::async_trait]
+impl server::Route for Route {
+    type ApiVersion = ApiVersion;
+    type Service = super::Service;
+    type DeleteBody = IgnoredAny;
+    type PostBody = spire_entries::Entries;
+    type PutBody = IgnoredAny;
+
+    fn api_version() -> &'static dyn DynRangeBounds<Self::ApiVersion> {
+        &((ApiVersion::V2022_06_01)..)
+    }
+
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(Cow<'_, str>, Cow<'_, str>)],
+        extensions: &Extensions,
+    ) -> Option<Self> {
+        if path != uri::SPIRE_ENTRIES {
+            return None;
+        }
+
+        Some(Route {
+            api: service.api.clone(),
+            // Set by the tenant service, the route is not served without it.
+            caller: extensions.get::<Caller>()?.clone(),
+        })
+    }
+
+    async fn get(self) -> server::RouteResponse {
+        let res = self
+            .api
+            .export_spire_entries()
+            .await
+            .map_err(|err| server::Error {
+                status_code: StatusCode::INTERNAL_SERVER_ERROR,
+                message: format!("Error processing export SPIRE entries request: {}", err).into(),
+            })?;
+
+        let res = server::response::json(StatusCode::OK, &res);
+
+        Ok(res)
+    }
+
+    async fn post(self, body: Option<Self::PostBody>) -> server::RouteResponse {
+        let body = body.ok_or_else(|| server::Error {
+            status_code: StatusCode::BAD_REQUEST,
+            message: "missing request body".into(),
+        })?;
+
+        let res = self
+            .api
+            .import_spire_entries(body, &self.caller)
+            .await
+            .map_err(|err| server::Error {
+                status_code: StatusCode::BAD_REQUEST,
+                message: format!("Error processing import SPIRE entries request: {}", err).into(),
+            })?;
+
+        let res = server::response::json(StatusCode::CREATED, &res);
+
+        Ok(res)
+    }
+}
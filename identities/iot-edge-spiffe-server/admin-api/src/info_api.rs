@@ -0,0 +1,164 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use crate::{error::Error, Api};
+use server_admin_api::{get_health, get_info};
+use server_config::{CatalogConfig, KeyStoreConfig};
+
+const SERVER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+impl Api {
+    // The catalog version is read from the backend on each call, so a failure means the catalog is unreachable.
+    pub async fn get_info(&self) -> Result<get_info::Response, Error> {
+        let mut catalog = self.catalog_backend.clone();
+        catalog.version = self
+            .catalog
+            .backend_version()
+            .await
+            .map_err(|err| Error::CatalogBackend(err))?;
+
+        Ok(self.info(catalog))
+    }
+
+    pub async fn get_health(&self) -> get_health::Response {
+        match self.get_info().await {
+            Ok(info) => get_health::Response {
+                healthy: true,
+                error: None,
+                pruned_entries: self.entry_pruner.pruned_entries(),
+                key_rotation: self.key_rotation(),
+                info,
+            },
+            Err(err) => get_health::Response {
+                healthy: false,
+                error: Some(err.to_string()),
+                pruned_entries: self.entry_pruner.pruned_entries(),
+                key_rotation: self.key_rotation(),
+                info: self.info(self.catalog_backend.clone()),
+            },
+        }
+    }
+
+    fn key_rotation(&self) -> get_health::KeyRotation {
+        let metrics = self.key_manager.rotation_metrics.snapshot();
+
+        get_health::KeyRotation {
+            checks: metrics.checks,
+            failures: metrics.failures,
+            consecutive_failures: metrics.consecutive_failures,
+            rotations: metrics.rotations,
+            last_success_at: metrics.last_success_at,
+            last_rotation_at: metrics.last_rotation_at,
+        }
+    }
+
+    fn info(&self, catalog: get_info::Backend) -> get_info::Response {
+        get_info::Response {
+            server_version: SERVER_VERSION.to_string(),
+            trust_domain: self.trust_domain.clone(),
+            catalog,
+            key_store: self.key_store_backend.clone(),
+            build: Some(self.build.clone()),
+        }
+    }
+}
+
+// Passwords are never part of the location.
+#[must_use]
+pub fn catalog_backend(config: &CatalogConfig) -> get_info::Backend {
+    let (backend_type, location) = match config {
+        CatalogConfig::Disk => ("disk", None),
+        CatalogConfig::Memory => ("memory", None),
+        CatalogConfig::Postgres(config) => (
+            "postgres",
+            Some(format!("{}:{}/{}", config.host, config.port, config.dbname)),
+        ),
+        CatalogConfig::Etcd(config) => (
+            "etcd",
+            Some(format!(
+                "{}{}",
+                config.endpoints.join(","),
+                config.key_prefix
+            )),
+        ),
+        CatalogConfig::K8s(config) => ("k8s", Some(config.namespace.clone())),
+    };
+
+    get_info::Backend {
+        backend_type: backend_type.to_string(),
+        location,
+        version: None,
+    }
+}
+
+#[must_use]
+pub fn key_store_backend(config: &KeyStoreConfig) -> get_info::Backend {
+    let (backend_type, location) = match config {
+        KeyStoreConfig::Disk(config) => ("disk", Some(config.key_base_path.clone())),
+        KeyStoreConfig::Memory() => ("memory", None),
+        KeyStoreConfig::Tpm(config) => ("tpm", Some(config.tcti.clone())),
+    };
+
+    get_info::Backend {
+        backend_type: backend_type.to_string(),
+        location,
+        version: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use catalog::{AgentBans, EntryPruner};
+    use server_config::{CatalogConfigPostgres, PostgresSslMode};
+
+    use crate::{test_key_manager, test_trust_bundle_builder};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn get_health_memory_backends() {
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+        let api = Api {
+            catalog: catalog.clone(),
+            trust_bundle_builder: test_trust_bundle_builder(catalog.clone()),
+            entry_pruner: Arc::new(EntryPruner::new(catalog.clone())),
+            agent_bans: Arc::new(AgentBans::new(catalog)),
+            key_manager: test_key_manager(Arc::new(catalog::inmemory::Catalog::new())).await,
+            svid_audit: None,
+            admin_audit: None,
+            trust_domain: "trust_domain".to_string(),
+            catalog_backend: catalog_backend(&CatalogConfig::Memory),
+            key_store_backend: key_store_backend(&KeyStoreConfig::Memory()),
+            faults: None,
+            build: Default::default(),
+        };
+
+        let health = api.get_health().await;
+        assert!(health.healthy);
+        assert!(health.error.is_none());
+        assert_eq!(health.info.trust_domain, "trust_domain");
+        assert_eq!(health.info.catalog.backend_type, "memory");
+        assert_eq!(health.info.catalog.version, None);
+        assert_eq!(health.info.key_store.backend_type, "memory");
+        assert_eq!(health.pruned_entries, 0);
+        assert_eq!(health.info.build, Some(Default::default()));
+    }
+
+    #[test]
+    fn catalog_backend_hides_password() {
+        let backend = catalog_backend(&CatalogConfig::Postgres(CatalogConfigPostgres {
+            host: "db".to_string(),
+            port: 5432,
+            user: "user".to_string(),
+            password: Some("secret".to_string()),
+            dbname: "spiffe".to_string(),
+            max_connections: 16,
+            ssl_mode: PostgresSslMode::Require,
+            ssl_ca_path: None,
+        }));
+
+        assert_eq!(backend.backend_type, "postgres");
+        assert_eq!(backend.location.unwrap(), "db:5432/spiffe");
+    }
+}
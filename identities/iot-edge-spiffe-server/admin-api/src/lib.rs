@@ -10,49 +10,202 @@
     clippy::too_many_lines
 )]
 
-use catalog::Catalog;
-use http_common::Connector;
+use authorization::{Authorization, Connection, PeerCredentials};
+use build_info::BuildInfo;
+use catalog::{AdminAudit, AgentBans, Catalog, EntryPruner, SvidAudit};
+#[cfg(feature = "chaos")]
+use chaos_hooks::Faults;
+use hyper::server::conn::Http;
+use jwt_svid_validator::{audience::AudienceOptions, validate::JWTSVIDValidator};
+use key_manager::KeyManager;
+use request_limits::service::LimitedService;
+use server_admin_api::get_info;
 use server_config::Config;
-use std::{io, path::Path, sync::Arc};
-use tokio::task::JoinHandle;
+use std::{io, sync::Arc};
+use tenancy::{TenantService, Tenants};
+use tokio::{task::JoinHandle, time};
+use trust_bundle_builder::TrustBundleBuilder;
 
+mod admin_audit_api;
+pub mod agent_bans_api;
+pub mod apply_api;
+pub mod attested_agents_api;
+mod authorization;
 pub mod entries_api;
 mod error;
+#[cfg(feature = "chaos")]
+pub mod faults_api;
 mod http;
+pub mod info_api;
+pub mod snapshot_api;
+pub mod spire_api;
+mod spire_grpc;
+pub mod svid_audit_api;
+mod tenancy;
+mod tls;
+pub mod trust_bundle_api;
 
 const SOCKET_DEFAULT_PERMISSION: u32 = 0o660;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn start_admin_api(
     config: &Config,
     catalog: Arc<dyn Catalog>,
+    entry_pruner: Arc<EntryPruner>,
+    agent_bans: Arc<AgentBans>,
+    key_manager: Arc<KeyManager>,
+    svid_audit: Option<Arc<SvidAudit>>,
+    admin_audit: Option<Arc<AdminAudit>>,
+    faults: Option<ServerFaults>,
+    build: BuildInfo,
 ) -> Result<JoinHandle<Result<(), std::io::Error>>, io::Error> {
-    let api = Api { catalog };
+    let trust_bundle_builder = TrustBundleBuilder::new(config, catalog.clone());
 
-    let service = http::Service { api: api.clone() };
+    let authorization = config.admin_authorization.as_ref().map(|authorization| {
+        let jwt_svid_validator = JWTSVIDValidator::new(AudienceOptions {
+            exact_match: true,
+            trust_domain: Some(config.trust_domain.clone()),
+        });
 
-    let connector = Connector::Unix {
-        socket_path: Path::new(&config.socket_path).into(),
+        Arc::new(Authorization::new(
+            authorization,
+            &config.trust_domain,
+            catalog.clone(),
+            trust_bundle_builder.clone(),
+            Arc::new(jwt_svid_validator),
+        ))
+    });
+
+    let api = Api {
+        catalog,
+        trust_bundle_builder,
+        entry_pruner,
+        agent_bans,
+        key_manager,
+        svid_audit,
+        admin_audit,
+        trust_domain: config.trust_domain.clone(),
+        catalog_backend: info_api::catalog_backend(&config.catalog),
+        key_store_backend: info_api::key_store_backend(&config.key_store),
+        faults,
+        build,
     };
 
-    let mut incoming = connector.incoming(SOCKET_DEFAULT_PERMISSION, None).await?;
+    if let Some(socket_path) = &config.admin_grpc_socket_path {
+        spire_grpc::start(
+            api.clone(),
+            authorization.clone(),
+            socket_path,
+            config.request_limits.batch.timeout(),
+        )?;
+    }
 
-    Ok(tokio::spawn(async move {
-        // Channel to gracefully shut down the server. It's currently not used.
-        let (_shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+    let tenants = Tenants::load(&config.admin_tenants)?;
+
+    // Callers are authenticated before their request body is read.
+    let service = TenantService::new(
+        LimitedService::new(
+            http::Service { api: api.clone() },
+            config.request_limits.clone(),
+            http::endpoint_class,
+        ),
+        tenants,
+    )
+    .with_authorization(authorization);
+
+    let listener = authorization::bind(&config.socket_path, SOCKET_DEFAULT_PERMISSION)?;
+
+    if let Some(tls_listener) = &config.admin_tls_listener {
+        let acceptor = tls::acceptor(tls_listener)?;
+        let tcp_listener = tls::bind(tls_listener).await?;
+        log::info!(
+            "Starting admin TLS listener on {}:{}",
+            tls_listener.bind_address,
+            tls_listener.bind_port
+        );
+        tokio::spawn(tls::serve(tcp_listener, acceptor, service.clone()));
+    }
 
+    Ok(tokio::spawn(async move {
         log::info!("Starting admin server");
-        let res = incoming.serve(service, shutdown_rx).await;
-        if let Err(err) = res {
-            log::error!("Closing admin server: {:?}", err);
-        } else {
-            log::info!("Closing admin server");
-        };
-
-        Ok(())
+
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    log::error!("Error accepting a connection to the admin server: {}", err);
+                    time::sleep(tls_listener::ACCEPT_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+
+            let peer = stream.peer_cred().ok().map(|cred| PeerCredentials {
+                uid: cred.uid(),
+                gid: cred.gid(),
+            });
+            let service = service.clone().with_connection(Connection::Unix(peer));
+
+            tokio::spawn(async move {
+                if let Err(err) = Http::new()
+                    .http1_only(true)
+                    .serve_connection(stream, service)
+                    .await
+                {
+                    log::error!("Error serving the admin server: {}", err);
+                }
+            });
+        }
     }))
 }
 
 #[derive(Clone)]
 struct Api {
     catalog: Arc<dyn Catalog>,
+    trust_bundle_builder: Arc<TrustBundleBuilder>,
+    entry_pruner: Arc<EntryPruner>,
+    agent_bans: Arc<AgentBans>,
+    key_manager: Arc<KeyManager>,
+    // The issued SVIDs are only listed when the audit log is enabled.
+    svid_audit: Option<Arc<SvidAudit>>,
+    // The changes are recorded and listed when the audit log is enabled, they are logged either way.
+    admin_audit: Option<Arc<AdminAudit>>,
+    trust_domain: String,
+    catalog_backend: get_info::Backend,
+    key_store_backend: get_info::Backend,
+    #[cfg_attr(not(feature = "chaos"), allow(dead_code))]
+    faults: Option<ServerFaults>,
+    build: BuildInfo,
+}
+
+// Fault layers of the catalog and the key store. Only set when the server is built with the `chaos` feature,
+// the faults endpoint is not served otherwise.
+#[cfg(feature = "chaos")]
+#[derive(Clone)]
+pub struct ServerFaults {
+    pub catalog: Arc<Faults>,
+    pub key_store: Arc<Faults>,
+}
+
+// Without the `chaos` feature there are no fault layers, the faults are always `None`.
+#[cfg(not(feature = "chaos"))]
+#[derive(Clone)]
+pub enum ServerFaults {}
+
+#[cfg(test)]
+async fn test_key_manager(catalog: Arc<dyn Catalog>) -> Arc<KeyManager> {
+    let config = Config::load_config(core_objects::CONFIG_DEFAULT_PATH).unwrap();
+    let key_store = Arc::new(key_store::inmemory::KeyStore::new());
+
+    Arc::new(
+        KeyManager::new(&config, catalog, key_store, 0)
+            .await
+            .unwrap(),
+    )
+}
+
+#[cfg(test)]
+fn test_trust_bundle_builder(catalog: Arc<dyn Catalog>) -> Arc<TrustBundleBuilder> {
+    let config = Config::load_config(core_objects::CONFIG_DEFAULT_PATH).unwrap();
+
+    TrustBundleBuilder::new(&config, catalog)
 }
@@ -0,0 +1,148 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+
+use core_objects::{get_epoch_time, EntryChangeKind};
+use server_admin_api::{
+    export_snapshot::{self, SNAPSHOT_VERSION},
+    import_snapshot, operation,
+};
+
+use crate::{
+    admin_audit_api::{entry_changes, failed_ids},
+    error::Error,
+    tenancy::Caller,
+    Api,
+};
+
+impl Api {
+    pub async fn export_snapshot(&self) -> Result<export_snapshot::Response, Error> {
+        let entries = self
+            .catalog
+            .export_snapshot()
+            .await
+            .map_err(|err| Error::ExportSnapshot(err))?;
+
+        log::info!("Exported snapshot of {} entries", entries.len());
+
+        Ok(export_snapshot::Response {
+            version: SNAPSHOT_VERSION,
+            created_at: get_epoch_time(),
+            entries,
+        })
+    }
+
+    // Restore the entries of a snapshot, usually into a new server. Entries that already exist are
+    // left untouched and reported in the results.
+    pub async fn import_snapshot(
+        &self,
+        req: import_snapshot::Request,
+        caller: &Caller,
+    ) -> Result<import_snapshot::Response, Error> {
+        if req.version > SNAPSHOT_VERSION {
+            return Err(Error::UnsupportedSnapshotVersion(req.version));
+        }
+
+        log::info!(
+            "Importing snapshot of {} entries created at {}",
+            req.entries.len(),
+            req.created_at
+        );
+
+        let ids: Vec<String> = req.entries.iter().map(|entry| entry.id.clone()).collect();
+        let results = self
+            .catalog
+            .import_snapshot(req.entries)
+            .await
+            .map_err(|err| err.into_iter().map(operation::Error::from).collect());
+
+        let changes = entry_changes(
+            EntryChangeKind::Created,
+            ids,
+            &failed_ids(&results),
+            &HashMap::new(),
+        );
+        self.audit(caller, "import_snapshot", changes, None).await;
+
+        Ok(import_snapshot::Response { results })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use catalog::{AgentBans, Entries, EntryPruner};
+    use core_objects::RegistrationEntry;
+    use server_config::{CatalogConfig, KeyStoreConfig};
+
+    use crate::{
+        info_api::{catalog_backend, key_store_backend},
+        test_key_manager, test_trust_bundle_builder,
+    };
+
+    use super::*;
+
+    async fn init() -> (Api, Arc<catalog::inmemory::Catalog>) {
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+
+        let api = Api {
+            catalog: catalog.clone(),
+            trust_bundle_builder: test_trust_bundle_builder(catalog.clone()),
+            entry_pruner: Arc::new(EntryPruner::new(catalog.clone())),
+            agent_bans: Arc::new(AgentBans::new(catalog.clone())),
+            key_manager: test_key_manager(Arc::new(catalog::inmemory::Catalog::new())).await,
+            svid_audit: None,
+            admin_audit: None,
+            trust_domain: "trust_domain".to_string(),
+            catalog_backend: catalog_backend(&CatalogConfig::Memory),
+            key_store_backend: key_store_backend(&KeyStoreConfig::Memory()),
+            faults: None,
+            build: Default::default(),
+        };
+
+        (api, catalog)
+    }
+
+    #[tokio::test]
+    async fn export_import_snapshot_happy_path() {
+        let (api, catalog) = init().await;
+        catalog
+            .batch_create(vec![
+                RegistrationEntry::test_entry("id1"),
+                RegistrationEntry::test_entry("id2"),
+            ])
+            .await
+            .unwrap();
+
+        let snapshot = api.export_snapshot().await.unwrap();
+        assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+        assert_eq!(snapshot.entries.len(), 2);
+
+        let (restored_api, restored_catalog) = init().await;
+        let res = restored_api
+            .import_snapshot(snapshot, &Caller::default())
+            .await
+            .unwrap();
+        res.results.unwrap();
+
+        let (entries, _page_token) = restored_catalog.list_all(None, 10).await.unwrap();
+        assert_eq!(entries.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn import_snapshot_unsupported_version() {
+        let (api, _catalog) = init().await;
+
+        let req = import_snapshot::Request {
+            version: SNAPSHOT_VERSION + 1,
+            created_at: 0,
+            entries: vec![RegistrationEntry::test_entry("id1")],
+        };
+        let error = api
+            .import_snapshot(req, &Caller::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::UnsupportedSnapshotVersion(_)));
+    }
+}
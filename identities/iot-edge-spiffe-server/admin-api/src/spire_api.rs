@@ -0,0 +1,772 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Entries in the JSON entry format of SPIRE, to move entries between SPIRE and E4K. A SPIRE selector is the type
+// of its plugin and a `<name>:<value>` value, an E4K selector is `<NAME>:<value>` with the plugin set on the
+// entry. SPIRE entries attested by the server have the SPIRE server as parent, the parent of the others is the
+// SPIFFE ID of the node entry of their agent.
+
+use std::collections::HashMap;
+
+use core_objects::{
+    spiffe_id, AttestationConfig, EntryChangeKind, EntryNodeAttestation, EntryWorkloadAttestation,
+    NodeAttestationPlugin, RegistrationEntry, SpiffeId, WorkloadAttestationPlugin,
+    SPIFFE_ID_PREFIX,
+};
+use server_admin_api::{
+    operation,
+    spire_entries::{self, Selector},
+};
+use thiserror::Error;
+
+use crate::{
+    admin_audit_api::{entry_changes, failed_ids},
+    error::Error as ApiError,
+    tenancy::Caller,
+    Api,
+};
+
+// Path of the SPIFFE ID of the SPIRE server, the parent of the node entries.
+const SPIRE_SERVER_PATH: &str = "spire/server";
+
+// (SPIRE selector type, SPIRE selector name, E4K selector name). The selectors without an equivalent are not
+// translated.
+const SELECTORS: &[(&str, &str, &str)] = &[
+    ("k8s", "ns", "NAMESPACE"),
+    ("k8s", "sa", "SERVICEACCOUNT"),
+    ("k8s", "pod-name", "PODNAME"),
+    ("k8s", "pod-uid", "PODUID"),
+    ("k8s", "node-name", "NODENAME"),
+    ("k8s", "pod-label", "PODLABELS"),
+    ("k8s", "container-name", "CONTAINERNAME"),
+    ("k8s", "container-image", "CONTAINERIMAGE"),
+    ("k8s", "pod-owner", "PODOWNERS"),
+    ("k8s", "pod-owner-uid", "PODOWNERUIDS"),
+    ("k8s", "pod-image", "PODIMAGES"),
+    ("k8s", "pod-image-count", "PODIMAGECOUNT"),
+    ("k8s", "pod-init-image", "PODINITIMAGES"),
+    ("k8s", "pod-init-image-count", "PODINITIMAGECOUNT"),
+    ("unix", "uid", "UNIXUID"),
+    ("unix", "gid", "UNIXGID"),
+    ("unix", "path", "UNIXPATH"),
+    ("unix", "sha256", "UNIXSHA256"),
+    ("systemd", "id", "SYSTEMDUNIT"),
+    ("k8s_psat", "cluster", "CLUSTER"),
+    ("k8s_psat", "agent_ns", "AGENTNAMESPACE"),
+    ("k8s_psat", "agent_sa", "AGENTSERVICEACCOUNT"),
+    ("k8s_psat", "agent_pod_name", "AGENTPODNAME"),
+    ("k8s_psat", "agent_pod_uid", "AGENTPODUID"),
+    ("k8s_psat", "agent_node_ip", "AGENTNODEIP"),
+    ("k8s_psat", "agent_node_name", "AGENTNODENAME"),
+    ("k8s_psat", "agent_node_uid", "AGENTNODEUID"),
+    ("k8s_psat", "agent_node_label", "AGENTNODELABELS"),
+    ("k8s_psat", "agent_pod_label", "AGENTPODLABELS"),
+    ("k8s_sat", "cluster", "CLUSTER"),
+    ("k8s_sat", "agent_ns", "AGENTNAMESPACE"),
+    ("k8s_sat", "agent_sa", "AGENTSERVICEACCOUNT"),
+    ("x509pop", "subject:cn", "X509POPSUBJECTCN"),
+];
+
+#[derive(Debug, Error)]
+pub(crate) enum TranslationError {
+    #[error("Plugin {0} has no SPIRE equivalent")]
+    UnsupportedPlugin(String),
+    #[error("Selector {0} has no SPIRE equivalent")]
+    UnsupportedSelector(String),
+    #[error("SPIRE selector {0}:{1} has no E4K equivalent")]
+    UnsupportedSpireSelector(String, String),
+    #[error("Entry has no selectors")]
+    NoSelectors,
+    #[error("Entry has selectors of both {0} and {1}")]
+    MixedSelectorTypes(String, String),
+    #[error("{0} is not in trust domain {1}")]
+    OtherTrustDomain(String, String),
+    #[error("Invalid SPIFFE ID {0}: {1}")]
+    InvalidSpiffeId(String, spiffe_id::Error),
+    #[error("Invalid SPIFFE ID path {0}: {1}")]
+    InvalidSpiffeIdPath(String, spiffe_id::Error),
+    #[error("Parent entry {0} does not exist")]
+    ParentNotFound(String),
+    #[error("Parent {0} is not the SPIFFE ID of a node entry")]
+    ParentNotNodeEntry(String),
+    #[error("Parent {0} is the SPIFFE ID of several node entries")]
+    AmbiguousParent(String),
+}
+
+impl Api {
+    // Entries that SPIRE cannot express, like the ones of the DPS plugin, are left out and listed in `skipped`.
+    pub async fn export_spire_entries(&self) -> Result<spire_entries::Entries, ApiError> {
+        let entries = self
+            .catalog
+            .export_snapshot()
+            .await
+            .map_err(|err| ApiError::ExportSpireEntries(err))?;
+
+        let spiffe_ids: HashMap<&str, String> = entries
+            .iter()
+            .filter_map(|entry| {
+                Some((
+                    entry.id.as_str(),
+                    self.spiffe_id(&entry.spiffe_id_path).ok()?,
+                ))
+            })
+            .collect();
+
+        let mut res = spire_entries::Entries::default();
+        for entry in &entries {
+            match self.to_spire_entry(entry, &spiffe_ids) {
+                Ok(spire_entry) => res.entries.push(spire_entry),
+                Err(err) => res.skipped.push(translation_error(&entry.id, &err)),
+            }
+        }
+
+        log::info!(
+            "Exported {} SPIRE entries, skipped {}",
+            res.entries.len(),
+            res.skipped.len()
+        );
+
+        Ok(res)
+    }
+
+    // The parent of a workload entry is looked up among the imported node entries and the node entries already
+    // in the catalog. Entries that cannot be translated are reported in the results with the creation errors.
+    pub async fn import_spire_entries(
+        &self,
+        req: spire_entries::Entries,
+        caller: &Caller,
+    ) -> Result<spire_entries::ImportResponse, ApiError> {
+        let mut node_ids = self
+            .spire_node_ids()
+            .await
+            .map_err(|err| ApiError::ExportSpireEntries(err))?;
+
+        let (node_entries, workload_entries): (Vec<_>, Vec<_>) = req
+            .entries
+            .into_iter()
+            .partition(|entry| self.is_spire_node_entry(entry));
+
+        let mut entries = Vec::new();
+        let mut errors = Vec::new();
+
+        for spire_entry in node_entries.iter().chain(&workload_entries) {
+            let id = entry_id(spire_entry);
+            match self.from_spire_entry(id.clone(), spire_entry, &mut node_ids) {
+                Ok(entry) => entries.push(entry),
+                Err(err) => errors.push(translation_error(&id, &err)),
+            }
+        }
+
+        log::info!(
+            "Importing {} SPIRE entries, {} cannot be translated",
+            entries.len(),
+            errors.len()
+        );
+
+        let ids: Vec<String> = entries.iter().map(|entry| entry.id.clone()).collect();
+        if let Err(err) = self.catalog.batch_create(entries).await {
+            errors.extend(err.into_iter().map(operation::Error::from));
+        }
+
+        let results = if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        };
+
+        let changes = entry_changes(
+            EntryChangeKind::Created,
+            ids,
+            &failed_ids(&results),
+            &HashMap::new(),
+        );
+        self.audit(caller, "import_spire_entries", changes, None)
+            .await;
+
+        Ok(spire_entries::ImportResponse { results })
+    }
+
+    // Ids of the node entries of the catalog by SPIFFE ID, to look up the parents of the SPIRE workload entries.
+    pub(crate) async fn spire_node_ids(
+        &self,
+    ) -> Result<HashMap<String, Vec<String>>, Box<dyn std::error::Error + Send>> {
+        let entries = self.catalog.export_snapshot().await?;
+
+        let mut node_ids: HashMap<String, Vec<String>> = HashMap::new();
+        for entry in entries {
+            if let AttestationConfig::Node(_) = entry.attestation_config {
+                if let Ok(spiffe_id) = self.spiffe_id(&entry.spiffe_id_path) {
+                    node_ids.entry(spiffe_id).or_default().push(entry.id);
+                }
+            }
+        }
+
+        Ok(node_ids)
+    }
+
+    // The SPIFFE IDs of the parents of the workload entries are looked up in the catalog when the parents are
+    // not part of `entries`.
+    pub(crate) async fn to_spire_entries(
+        &self,
+        entries: &[RegistrationEntry],
+    ) -> Vec<Result<spire_entries::Entry, TranslationError>> {
+        let mut spiffe_ids: HashMap<&str, String> = entries
+            .iter()
+            .filter_map(|entry| {
+                Some((
+                    entry.id.as_str(),
+                    self.spiffe_id(&entry.spiffe_id_path).ok()?,
+                ))
+            })
+            .collect();
+
+        let mut parent_ids: Vec<String> = entries
+            .iter()
+            .filter_map(|entry| match &entry.attestation_config {
+                AttestationConfig::Workload(attestation)
+                    if !spiffe_ids.contains_key(attestation.parent_id.as_str()) =>
+                {
+                    Some(attestation.parent_id.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        parent_ids.sort_unstable();
+        parent_ids.dedup();
+
+        let parents: Vec<(String, String)> = self
+            .catalog
+            .batch_get(&parent_ids)
+            .await
+            .into_iter()
+            .filter_map(|(id, result)| {
+                let spiffe_id = self.spiffe_id(&result.ok()?.spiffe_id_path).ok()?;
+
+                Some((id, spiffe_id))
+            })
+            .collect();
+        spiffe_ids.extend(
+            parents
+                .iter()
+                .map(|(id, spiffe_id)| (id.as_str(), spiffe_id.clone())),
+        );
+
+        entries
+            .iter()
+            .map(|entry| self.to_spire_entry(entry, &spiffe_ids))
+            .collect()
+    }
+
+    // Node entries have the SPIRE server as parent.
+    pub(crate) fn is_spire_node_entry(&self, spire_entry: &spire_entries::Entry) -> bool {
+        self.spiffe_id(SPIRE_SERVER_PATH)
+            .map_or(false, |spire_server| {
+                normalized(&spire_entry.parent_id) == spire_server
+            })
+    }
+
+    // Node entries are added to `node_ids`, so the next entries can have them as parent.
+    pub(crate) fn from_spire_entry(
+        &self,
+        id: String,
+        spire_entry: &spire_entries::Entry,
+        node_ids: &mut HashMap<String, Vec<String>>,
+    ) -> Result<RegistrationEntry, TranslationError> {
+        if self.is_spire_node_entry(spire_entry) {
+            let entry = self.to_node_entry(id.clone(), spire_entry)?;
+            node_ids
+                .entry(normalized(&spire_entry.spiffe_id))
+                .or_default()
+                .push(id);
+
+            Ok(entry)
+        } else {
+            self.to_workload_entry(id, spire_entry, node_ids)
+        }
+    }
+
+    fn spiffe_id(&self, spiffe_id_path: &str) -> Result<String, TranslationError> {
+        SpiffeId::from_entry_path(&self.trust_domain, spiffe_id_path)
+            .map(String::from)
+            .map_err(|err| TranslationError::InvalidSpiffeIdPath(spiffe_id_path.to_string(), err))
+    }
+
+    // SPIFFE IDs without a path, the one of the trust domain, cannot be the SPIFFE ID of an entry.
+    fn spiffe_id_path(&self, spiffe_id: &str) -> Result<String, TranslationError> {
+        let parsed = SpiffeId::try_from(spiffe_id)
+            .map_err(|err| TranslationError::InvalidSpiffeId(spiffe_id.to_string(), err))?;
+        if !parsed
+            .trust_domain()
+            .eq_ignore_ascii_case(&self.trust_domain)
+            || parsed.path().is_empty()
+        {
+            return Err(TranslationError::OtherTrustDomain(
+                spiffe_id.to_string(),
+                self.trust_domain.clone(),
+            ));
+        }
+
+        Ok(parsed.entry_path().to_string())
+    }
+
+    fn to_spire_entry(
+        &self,
+        entry: &RegistrationEntry,
+        spiffe_ids: &HashMap<&str, String>,
+    ) -> Result<spire_entries::Entry, TranslationError> {
+        let spiffe_id = self.spiffe_id(&entry.spiffe_id_path)?;
+        let (parent_id, selector_type, selectors) = match &entry.attestation_config {
+            AttestationConfig::Node(attestation) => (
+                self.spiffe_id(SPIRE_SERVER_PATH)?,
+                node_selector_type(&attestation.plugin)?,
+                &attestation.value,
+            ),
+            AttestationConfig::Workload(attestation) => (
+                spiffe_ids
+                    .get(attestation.parent_id.as_str())
+                    .cloned()
+                    .ok_or_else(|| {
+                        TranslationError::ParentNotFound(attestation.parent_id.clone())
+                    })?,
+                workload_selector_type(&attestation.plugin)?,
+                &attestation.value,
+            ),
+        };
+
+        let selectors = selectors
+            .iter()
+            .map(|selector| to_spire_selector(selector_type, selector))
+            .collect::<Result<_, _>>()?;
+
+        Ok(spire_entries::Entry {
+            entry_id: Some(entry.id.clone()),
+            spiffe_id,
+            parent_id,
+            selectors,
+            dns_names: entry.dns_names.clone(),
+            admin: entry.admin,
+            expires_at: entry.expires_at,
+            jwt_svid_ttl: entry
+                .ttl
+                .map_or(0, |ttl| u32::try_from(ttl).unwrap_or(u32::MAX)),
+            federates_with: entry.federates_with.clone(),
+            ..Default::default()
+        })
+    }
+
+    fn to_node_entry(
+        &self,
+        id: String,
+        spire_entry: &spire_entries::Entry,
+    ) -> Result<RegistrationEntry, TranslationError> {
+        let (selector_type, value) = from_spire_selectors(&spire_entry.selectors)?;
+        let plugin = match selector_type {
+            "k8s_psat" => NodeAttestationPlugin::Psat,
+            "k8s_sat" => NodeAttestationPlugin::Sat,
+            "x509pop" => NodeAttestationPlugin::X509Pop,
+            other => return Err(TranslationError::UnsupportedPlugin(other.to_string())),
+        };
+
+        self.to_entry(
+            id,
+            spire_entry,
+            AttestationConfig::Node(EntryNodeAttestation { value, plugin }),
+        )
+    }
+
+    fn to_workload_entry(
+        &self,
+        id: String,
+        spire_entry: &spire_entries::Entry,
+        node_ids: &HashMap<String, Vec<String>>,
+    ) -> Result<RegistrationEntry, TranslationError> {
+        let parent_id = match node_ids
+            .get(&normalized(&spire_entry.parent_id))
+            .map(Vec::as_slice)
+        {
+            Some([parent_id]) => parent_id.clone(),
+            Some([]) | None => {
+                return Err(TranslationError::ParentNotNodeEntry(
+                    spire_entry.parent_id.clone(),
+                ))
+            }
+            Some(_) => {
+                return Err(TranslationError::AmbiguousParent(
+                    spire_entry.parent_id.clone(),
+                ))
+            }
+        };
+
+        let (selector_type, value) = from_spire_selectors(&spire_entry.selectors)?;
+        let plugin = match selector_type {
+            "k8s" => WorkloadAttestationPlugin::K8s,
+            "unix" => WorkloadAttestationPlugin::Unix,
+            "systemd" => WorkloadAttestationPlugin::Systemd,
+            other => return Err(TranslationError::UnsupportedPlugin(other.to_string())),
+        };
+
+        self.to_entry(
+            id,
+            spire_entry,
+            AttestationConfig::Workload(EntryWorkloadAttestation {
+                parent_id,
+                value,
+                plugin,
+            }),
+        )
+    }
+
+    fn to_entry(
+        &self,
+        id: String,
+        spire_entry: &spire_entries::Entry,
+        attestation_config: AttestationConfig,
+    ) -> Result<RegistrationEntry, TranslationError> {
+        Ok(RegistrationEntry {
+            id,
+            other_identities: Vec::new(),
+            spiffe_id_path: self.spiffe_id_path(&spire_entry.spiffe_id)?,
+            attestation_config,
+            admin: spire_entry.admin,
+            expires_at: spire_entry.expires_at,
+            dns_names: spire_entry.dns_names.clone(),
+            revision_number: 0,
+            store_svid: false,
+            prefetch: false,
+            // A TTL of 0 is the default of the server in SPIRE.
+            ttl: (spire_entry.jwt_svid_ttl > 0).then(|| u64::from(spire_entry.jwt_svid_ttl)),
+            custom_claims: Default::default(),
+            federates_with: spire_entry
+                .federates_with
+                .iter()
+                .map(|trust_domain| federated_trust_domain(trust_domain))
+                .collect(),
+        })
+    }
+}
+
+// The entry files of older SPIRE versions name the federated trust domains with their SPIFFE ID, the entry API with
+// their name. E4K keeps the name.
+fn federated_trust_domain(trust_domain: &str) -> String {
+    trust_domain
+        .strip_prefix(SPIFFE_ID_PREFIX)
+        .unwrap_or(trust_domain)
+        .trim_end_matches('/')
+        .to_string()
+}
+
+// SPIFFE IDs of SPIRE entries are compared normalized, their trust domain may be in upper case. Invalid ones are
+// kept as they are, they never match.
+fn normalized(spiffe_id: &str) -> String {
+    SpiffeId::try_from(spiffe_id).map_or_else(|_| spiffe_id.to_string(), String::from)
+}
+
+pub(crate) fn entry_id(spire_entry: &spire_entries::Entry) -> String {
+    spire_entry
+        .entry_id
+        .clone()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+fn translation_error(id: &str, err: &TranslationError) -> operation::Error {
+    operation::Error {
+        id: id.to_string(),
+        error: err.to_string(),
+        kind: operation::ErrorKind::Other,
+    }
+}
+
+fn node_selector_type(plugin: &NodeAttestationPlugin) -> Result<&'static str, TranslationError> {
+    match plugin {
+        NodeAttestationPlugin::Psat => Ok("k8s_psat"),
+        NodeAttestationPlugin::Sat => Ok("k8s_sat"),
+        NodeAttestationPlugin::X509Pop => Ok("x509pop"),
+        NodeAttestationPlugin::Dps => {
+            Err(TranslationError::UnsupportedPlugin(format!("{:?}", plugin)))
+        }
+    }
+}
+
+fn workload_selector_type(
+    plugin: &WorkloadAttestationPlugin,
+) -> Result<&'static str, TranslationError> {
+    match plugin {
+        WorkloadAttestationPlugin::K8s => Ok("k8s"),
+        WorkloadAttestationPlugin::Unix => Ok("unix"),
+        WorkloadAttestationPlugin::Systemd => Ok("systemd"),
+        WorkloadAttestationPlugin::Docker => {
+            Err(TranslationError::UnsupportedPlugin(format!("{:?}", plugin)))
+        }
+    }
+}
+
+fn to_spire_selector(selector_type: &str, selector: &str) -> Result<Selector, TranslationError> {
+    let (name, value) = selector
+        .split_once(':')
+        .ok_or_else(|| TranslationError::UnsupportedSelector(selector.to_string()))?;
+
+    SELECTORS
+        .iter()
+        .find(|(spire_type, _, e4k_name)| *spire_type == selector_type && *e4k_name == name)
+        .map(|(_, spire_name, _)| Selector {
+            selector_type: selector_type.to_string(),
+            value: format!("{}:{}", spire_name, value),
+        })
+        .ok_or_else(|| TranslationError::UnsupportedSelector(selector.to_string()))
+}
+
+// All the selectors of an entry are of the same plugin, returned with the E4K selectors.
+fn from_spire_selectors(selectors: &[Selector]) -> Result<(&str, Vec<String>), TranslationError> {
+    let selector_type = match selectors.first() {
+        Some(selector) => selector.selector_type.as_str(),
+        None => return Err(TranslationError::NoSelectors),
+    };
+
+    let value = selectors
+        .iter()
+        .map(|selector| {
+            if selector.selector_type != selector_type {
+                return Err(TranslationError::MixedSelectorTypes(
+                    selector_type.to_string(),
+                    selector.selector_type.clone(),
+                ));
+            }
+
+            SELECTORS
+                .iter()
+                .filter(|(spire_type, _, _)| *spire_type == selector_type)
+                .find_map(|(_, spire_name, e4k_name)| {
+                    selector
+                        .value
+                        .strip_prefix(spire_name)
+                        .and_then(|value| value.strip_prefix(':'))
+                        .map(|value| format!("{}:{}", e4k_name, value))
+                })
+                .ok_or_else(|| {
+                    TranslationError::UnsupportedSpireSelector(
+                        selector.selector_type.clone(),
+                        selector.value.clone(),
+                    )
+                })
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok((selector_type, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use catalog::{AgentBans, Entries, EntryPruner};
+    use core_objects::{NodeSelectorType, WorkloadSelectorType};
+    use server_config::{CatalogConfig, KeyStoreConfig};
+
+    use crate::{
+        info_api::{catalog_backend, key_store_backend},
+        test_key_manager, test_trust_bundle_builder,
+    };
+
+    use super::*;
+
+    async fn init() -> (Api, Arc<catalog::inmemory::Catalog>) {
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+
+        let api = Api {
+            catalog: catalog.clone(),
+            trust_bundle_builder: test_trust_bundle_builder(catalog.clone()),
+            entry_pruner: Arc::new(EntryPruner::new(catalog.clone())),
+            agent_bans: Arc::new(AgentBans::new(catalog.clone())),
+            key_manager: test_key_manager(Arc::new(catalog::inmemory::Catalog::new())).await,
+            svid_audit: None,
+            admin_audit: None,
+            trust_domain: "trust_domain".to_string(),
+            catalog_backend: catalog_backend(&CatalogConfig::Memory),
+            key_store_backend: key_store_backend(&KeyStoreConfig::Memory()),
+            faults: None,
+            build: Default::default(),
+        };
+
+        (api, catalog)
+    }
+
+    fn entry(id: &str, attestation_config: AttestationConfig) -> RegistrationEntry {
+        RegistrationEntry {
+            attestation_config,
+            ..RegistrationEntry::test_entry(id)
+        }
+    }
+
+    fn entries() -> Vec<RegistrationEntry> {
+        vec![
+            entry(
+                "node",
+                AttestationConfig::Node(EntryNodeAttestation {
+                    value: vec!["AGENTNODENAME:node1".to_string()],
+                    plugin: NodeAttestationPlugin::Psat,
+                }),
+            ),
+            RegistrationEntry {
+                ttl: Some(60),
+                ..entry(
+                    "workload",
+                    AttestationConfig::Workload(EntryWorkloadAttestation {
+                        parent_id: "node".to_string(),
+                        value: vec![
+                            "NAMESPACE:default".to_string(),
+                            "PODLABELS:app:web".to_string(),
+                        ],
+                        plugin: WorkloadAttestationPlugin::K8s,
+                    }),
+                )
+            },
+            entry(
+                "dps",
+                AttestationConfig::Node(EntryNodeAttestation {
+                    value: vec!["IOTHUBNAME:hub".to_string()],
+                    plugin: NodeAttestationPlugin::Dps,
+                }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn selector_names_match_selector_types() {
+        let names: Vec<String> = vec![
+            WorkloadSelectorType::Namespace.to_string(),
+            WorkloadSelectorType::ServiceAccount.to_string(),
+            WorkloadSelectorType::PodLabels.to_string(),
+            WorkloadSelectorType::PodOwnerUIDs.to_string(),
+            WorkloadSelectorType::UnixSHA256.to_string(),
+            WorkloadSelectorType::SystemdUnit.to_string(),
+            NodeSelectorType::AgentNameSpace.to_string(),
+            NodeSelectorType::AgentNodeLabels.to_string(),
+            NodeSelectorType::X509PopSubjectCN.to_string(),
+        ];
+
+        for name in names {
+            assert!(
+                SELECTORS.iter().any(|(_, _, e4k_name)| *e4k_name == name),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn federated_trust_domain_name() {
+        assert_eq!(
+            federated_trust_domain("spiffe://cloud.contoso.com/"),
+            "cloud.contoso.com"
+        );
+        assert_eq!(
+            federated_trust_domain("cloud.contoso.com"),
+            "cloud.contoso.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn export_import_spire_entries() {
+        let (api, catalog) = init().await;
+        catalog.batch_create(entries()).await.unwrap();
+
+        let export = api.export_spire_entries().await.unwrap();
+        assert_eq!(export.entries.len(), 2);
+        assert_eq!(export.skipped.len(), 1);
+        assert_eq!(export.skipped[0].id, "dps");
+
+        let workload = export
+            .entries
+            .iter()
+            .find(|entry| entry.entry_id.as_deref() == Some("workload"))
+            .unwrap();
+        assert_eq!(workload.spiffe_id, "spiffe://trust_domain/workload");
+        assert_eq!(workload.parent_id, "spiffe://trust_domain/node");
+        assert_eq!(workload.jwt_svid_ttl, 60);
+        assert_eq!(
+            workload.selectors,
+            vec![
+                Selector {
+                    selector_type: "k8s".to_string(),
+                    value: "ns:default".to_string(),
+                },
+                Selector {
+                    selector_type: "k8s".to_string(),
+                    value: "pod-label:app:web".to_string(),
+                },
+            ]
+        );
+
+        let (restored_api, restored_catalog) = init().await;
+        let res = restored_api
+            .import_spire_entries(export, &Caller::default())
+            .await
+            .unwrap();
+        res.results.unwrap();
+
+        let (restored, _page_token) = restored_catalog.list_all(None, 10).await.unwrap();
+        assert_eq!(restored.len(), 2);
+        for entry in restored {
+            let original = entries().into_iter().find(|e| e.id == entry.id).unwrap();
+            assert_eq!(entry.spiffe_id_path, original.spiffe_id_path);
+            assert_eq!(entry.attestation_config, original.attestation_config);
+            assert_eq!(entry.ttl, original.ttl);
+        }
+    }
+
+    #[tokio::test]
+    async fn import_spire_entries_errors() {
+        let (api, _catalog) = init().await;
+
+        let spire_entry =
+            |id: &str, parent_id: &str, selector_type: &str, value: &str| spire_entries::Entry {
+                entry_id: Some(id.to_string()),
+                spiffe_id: format!("spiffe://trust_domain/{}", id),
+                parent_id: parent_id.to_string(),
+                selectors: vec![Selector {
+                    selector_type: selector_type.to_string(),
+                    value: value.to_string(),
+                }],
+                ..Default::default()
+            };
+        let req = spire_entries::Entries {
+            entries: vec![
+                spire_entry(
+                    "aws",
+                    "spiffe://trust_domain/spire/server",
+                    "aws_iid",
+                    "tag:name:agent",
+                ),
+                spire_entry(
+                    "orphan",
+                    "spiffe://trust_domain/missing",
+                    "k8s",
+                    "ns:default",
+                ),
+                spire_entry(
+                    "other",
+                    "spiffe://other_domain/spire/server",
+                    "k8s_psat",
+                    "cluster:demo",
+                ),
+                spire_entries::Entry {
+                    spiffe_id: "spiffe://trust_domain/ns/../web".to_string(),
+                    ..spire_entry(
+                        "dot",
+                        "spiffe://trust_domain/spire/server",
+                        "k8s_psat",
+                        "cluster:demo",
+                    )
+                },
+            ],
+            skipped: Vec::new(),
+        };
+
+        let res = api
+            .import_spire_entries(req, &Caller::default())
+            .await
+            .unwrap();
+        let mut ids: Vec<String> = res.results.unwrap_err().into_iter().map(|e| e.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["aws", "dot", "orphan", "other"]);
+    }
+}
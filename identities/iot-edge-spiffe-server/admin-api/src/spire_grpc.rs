@@ -0,0 +1,915 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Entry API of SPIRE over gRPC, so the SPIRE clients such as the registrars manage the entries of E4K. It is
+// served on its own socket, and the entries are translated like the ones of the SPIRE entry format: entries
+// that SPIRE cannot express are left out of the lists, and the fields E4K does not support (X.509-SVID TTL,
+// federation, downstream) are ignored. The output masks are ignored too, entries are always returned whole.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    io,
+    sync::Arc,
+    time::Duration,
+};
+
+use catalog::scan_entries;
+use core_objects::{EntryChangeKind, RegistrationEntry, SpiffeId, SPIFFE_ID_PREFIX};
+use futures_util::{future, TryStreamExt};
+use server_admin_api::spire_entries::{self, Selector};
+use spire_entry_api::{
+    entry::{
+        batch_create_entry_response, batch_delete_entry_response, batch_update_entry_response,
+        entry_server::{Entry, EntryServer},
+        list_entries_request::Filter,
+        BatchCreateEntryRequest, BatchCreateEntryResponse, BatchDeleteEntryRequest,
+        BatchDeleteEntryResponse, BatchUpdateEntryRequest, BatchUpdateEntryResponse,
+        CountEntriesRequest, CountEntriesResponse, GetEntryRequest, ListEntriesRequest,
+        ListEntriesResponse,
+    },
+    types::{self, selector_match::MatchBehavior, EntryMask, SelectorMatch, Spiffeid},
+};
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::UnixListenerStream;
+use tonic::{
+    transport::{server::UdsConnectInfo, Server},
+    Code, Request, Response, Status,
+};
+
+use crate::{
+    admin_audit_api::entry_changes,
+    authorization::{self, Authorization, Connection, PeerCredentials},
+    spire_api::{entry_id, TranslationError},
+    tenancy::Caller,
+    Api, SOCKET_DEFAULT_PERMISSION,
+};
+
+// Page size of the lists of the clients that do not set one.
+const DEFAULT_PAGE_SIZE: usize = 100;
+const COUNT_SCAN_PAGE_SIZE: usize = 100;
+
+type CatalogErrors = Vec<(String, Box<dyn std::error::Error + Send>)>;
+
+#[derive(Clone)]
+struct SpireEntryApi {
+    api: Api,
+    // Only the UIDs and GIDs of the admin authorization are checked, neither the tenants nor the JWT-SVIDs are
+    // accepted on this socket.
+    authorization: Option<Arc<Authorization>>,
+}
+
+pub(crate) fn start(
+    api: Api,
+    authorization: Option<Arc<Authorization>>,
+    socket_path: &str,
+    timeout: Duration,
+) -> io::Result<JoinHandle<()>> {
+    let listener = authorization::bind(socket_path, SOCKET_DEFAULT_PERMISSION)?;
+    let service = EntryServer::new(SpireEntryApi { api, authorization });
+    let socket_path = socket_path.to_string();
+
+    Ok(tokio::spawn(async move {
+        log::info!("Starting SPIRE entry API on {}", socket_path);
+        let res = Server::builder()
+            .timeout(timeout)
+            .add_service(service)
+            .serve_with_incoming(UnixListenerStream::new(listener))
+            .await;
+        if let Err(err) = res {
+            log::error!("Closing SPIRE entry API: {:?}", err);
+        } else {
+            log::info!("Closing SPIRE entry API");
+        }
+    }))
+}
+
+impl SpireEntryApi {
+    // Returns the caller of an allowed request, for the audit log of the changes.
+    fn authorize<T>(&self, request: &Request<T>) -> Result<Caller, Status> {
+        let peer = request
+            .extensions()
+            .get::<UdsConnectInfo>()
+            .and_then(|info| info.peer_cred)
+            .map(|cred| PeerCredentials {
+                uid: cred.uid(),
+                gid: cred.gid(),
+            });
+
+        match &self.authorization {
+            Some(authorization) if !authorization.allows_peer(peer) => Err(
+                Status::permission_denied("The caller is not allowed to use the admin API"),
+            ),
+            _ => Ok(Caller::connection(Connection::Unix(peer))),
+        }
+    }
+
+    // Node entries are translated first, so the workload entries of the batch can have them as parent.
+    async fn translate_entries(
+        &self,
+        entries: &[spire_entries::Entry],
+    ) -> Result<Vec<Result<RegistrationEntry, TranslationError>>, Status> {
+        let mut node_ids = self
+            .api
+            .spire_node_ids()
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?;
+
+        let mut results: Vec<_> = entries.iter().map(|_| None).collect();
+        for node_entries in [true, false] {
+            for (spire_entry, result) in entries.iter().zip(&mut results) {
+                if self.api.is_spire_node_entry(spire_entry) == node_entries {
+                    *result = Some(self.api.from_spire_entry(
+                        entry_id(spire_entry),
+                        spire_entry,
+                        &mut node_ids,
+                    ));
+                }
+            }
+        }
+
+        Ok(results.into_iter().flatten().collect())
+    }
+
+    // Entries as they are in the catalog after a batch, the ones that failed get the status of their error.
+    async fn written_entries(
+        &self,
+        prepared: Vec<Result<RegistrationEntry, Option<types::Status>>>,
+        errors: CatalogErrors,
+    ) -> Vec<(Option<types::Status>, Option<types::Entry>)> {
+        let errors: HashMap<String, Box<dyn std::error::Error + Send>> =
+            errors.into_iter().collect();
+
+        let ids: Vec<String> = prepared
+            .iter()
+            .filter_map(|entry| entry.as_ref().ok())
+            .filter(|entry| !errors.contains_key(&entry.id))
+            .map(|entry| entry.id.clone())
+            .collect();
+        let written: Vec<RegistrationEntry> = self
+            .api
+            .catalog
+            .batch_get(&ids)
+            .await
+            .into_iter()
+            .filter_map(|(_, result)| result.ok())
+            .collect();
+        let mut written: HashMap<String, types::Entry> = self
+            .api
+            .to_spire_entries(&written)
+            .await
+            .into_iter()
+            .zip(&written)
+            .filter_map(|(spire_entry, entry)| {
+                spire_entry
+                    .ok()
+                    .map(|spire_entry| (entry.id.clone(), to_grpc_entry(spire_entry, entry)))
+            })
+            .collect();
+
+        prepared
+            .into_iter()
+            .map(|entry| match entry {
+                Err(status) => (status, None),
+                Ok(entry) => match errors.get(&entry.id) {
+                    Some(err) => (error_status(catalog_code(&**err), err), None),
+                    None => (ok_status(), written.remove(&entry.id)),
+                },
+            })
+            .collect()
+    }
+}
+
+#[tonic::async_trait]
+impl Entry for SpireEntryApi {
+    // Counts all the entries of the catalog, including the ones SPIRE cannot express.
+    async fn count_entries(
+        &self,
+        request: Request<CountEntriesRequest>,
+    ) -> Result<Response<CountEntriesResponse>, Status> {
+        self.authorize(&request)?;
+
+        let count = scan_entries(&*self.api.catalog, COUNT_SCAN_PAGE_SIZE)
+            .try_fold(0_i32, |count, _entry| future::ok(count.saturating_add(1)))
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?;
+
+        Ok(Response::new(CountEntriesResponse { count }))
+    }
+
+    // The filters are applied to the page read from the catalog, so a page may be shorter than its size.
+    async fn list_entries(
+        &self,
+        request: Request<ListEntriesRequest>,
+    ) -> Result<Response<ListEntriesResponse>, Status> {
+        self.authorize(&request)?;
+        let request = request.into_inner();
+
+        let page_size = match usize::try_from(request.page_size) {
+            Ok(page_size) if page_size > 0 => page_size,
+            _ => DEFAULT_PAGE_SIZE,
+        };
+        let page_token = if request.page_token.is_empty() {
+            None
+        } else {
+            Some(request.page_token)
+        };
+
+        let (entries, next_page_token) = self
+            .api
+            .catalog
+            .list_all(page_token, page_size)
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?;
+
+        let filter = request.filter.unwrap_or_default();
+        let entries = self
+            .api
+            .to_spire_entries(&entries)
+            .await
+            .into_iter()
+            .zip(&entries)
+            .filter_map(|(spire_entry, entry)| {
+                spire_entry.ok().map(|spire_entry| (spire_entry, entry))
+            })
+            .filter(|(spire_entry, _)| matches_filter(&filter, spire_entry))
+            .map(|(spire_entry, entry)| to_grpc_entry(spire_entry, entry))
+            .collect();
+
+        Ok(Response::new(ListEntriesResponse {
+            entries,
+            next_page_token: next_page_token.unwrap_or_default(),
+        }))
+    }
+
+    // The catalog backends do not tell a missing entry from other errors, a failed get is reported as not found.
+    async fn get_entry(
+        &self,
+        request: Request<GetEntryRequest>,
+    ) -> Result<Response<types::Entry>, Status> {
+        self.authorize(&request)?;
+        let id = request.into_inner().id;
+
+        let entry = match self.api.catalog.batch_get(&[id.clone()]).await.pop() {
+            Some((_, Ok(entry))) => entry,
+            Some((_, Err(err))) => return Err(Status::not_found(err.to_string())),
+            None => return Err(Status::not_found(format!("Entry {} does not exist", id))),
+        };
+
+        let spire_entry = self
+            .api
+            .to_spire_entries(std::slice::from_ref(&entry))
+            .await
+            .pop()
+            .ok_or_else(|| Status::not_found(format!("Entry {} does not exist", id)))?
+            .map_err(|err| Status::failed_precondition(err.to_string()))?;
+
+        Ok(Response::new(to_grpc_entry(spire_entry, &entry)))
+    }
+
+    async fn batch_create_entry(
+        &self,
+        request: Request<BatchCreateEntryRequest>,
+    ) -> Result<Response<BatchCreateEntryResponse>, Status> {
+        let caller = self.authorize(&request)?;
+        let request = request.into_inner();
+
+        let store_svids: Vec<bool> = request
+            .entries
+            .iter()
+            .map(|entry| entry.store_svid)
+            .collect();
+        let spire_entries: Vec<spire_entries::Entry> =
+            request.entries.into_iter().map(from_grpc_entry).collect();
+
+        let prepared: Vec<_> = self
+            .translate_entries(&spire_entries)
+            .await?
+            .into_iter()
+            .zip(store_svids)
+            .map(|(entry, store_svid)| match entry {
+                Ok(entry) => Ok(RegistrationEntry {
+                    store_svid,
+                    ..entry
+                }),
+                Err(err) => Err(error_status(Code::InvalidArgument, &err)),
+            })
+            .collect();
+
+        let entries: Vec<RegistrationEntry> = prepared
+            .iter()
+            .filter_map(|entry| entry.as_ref().ok().cloned())
+            .collect();
+        let ids: Vec<String> = entries.iter().map(|entry| entry.id.clone()).collect();
+        let errors = self
+            .api
+            .catalog
+            .batch_create(entries)
+            .await
+            .err()
+            .unwrap_or_default();
+
+        let changes = entry_changes(
+            EntryChangeKind::Created,
+            ids,
+            &failed_ids(&errors),
+            &HashMap::new(),
+        );
+        self.api
+            .audit(&caller, "batch_create_entry", changes, None)
+            .await;
+
+        let results = self
+            .written_entries(prepared, errors)
+            .await
+            .into_iter()
+            .map(|(status, entry)| batch_create_entry_response::Result { status, entry })
+            .collect();
+
+        Ok(Response::new(BatchCreateEntryResponse { results }))
+    }
+
+    // Only the fields of the input mask are changed, all of them when it is not set. Entries changed by
+    // another caller during the update fail with `ABORTED`.
+    async fn batch_update_entry(
+        &self,
+        request: Request<BatchUpdateEntryRequest>,
+    ) -> Result<Response<BatchUpdateEntryResponse>, Status> {
+        let caller = self.authorize(&request)?;
+        let request = request.into_inner();
+        let mask = request.input_mask.unwrap_or_else(full_mask);
+
+        let ids: Vec<String> = request
+            .entries
+            .iter()
+            .map(|entry| entry.id.clone())
+            .collect();
+        let current: Vec<RegistrationEntry> = self
+            .api
+            .catalog
+            .batch_get(&ids)
+            .await
+            .into_iter()
+            .filter_map(|(_, result)| result.ok())
+            .collect();
+        let mut current_spire_entries: HashMap<String, _> = self
+            .api
+            .to_spire_entries(&current)
+            .await
+            .into_iter()
+            .zip(&current)
+            .map(|(spire_entry, entry)| (entry.id.clone(), spire_entry))
+            .collect();
+        let current: HashMap<String, RegistrationEntry> = current
+            .into_iter()
+            .map(|entry| (entry.id.clone(), entry))
+            .collect();
+
+        let mut updates = Vec::new();
+        let mut spire_entries = Vec::new();
+        for update in request.entries {
+            let store_svid = update.store_svid;
+            let mut spire_entry = match current_spire_entries.remove(&update.id) {
+                Some(Ok(spire_entry)) => spire_entry,
+                Some(Err(err)) => {
+                    updates.push(Err(error_status(Code::FailedPrecondition, &err)));
+                    spire_entries.push(spire_entries::Entry::default());
+                    continue;
+                }
+                None => {
+                    let message = format!("Entry {} does not exist", update.id);
+                    updates.push(Err(error_status(Code::NotFound, &message)));
+                    spire_entries.push(spire_entries::Entry::default());
+                    continue;
+                }
+            };
+
+            apply_mask(&mut spire_entry, from_grpc_entry(update), &mask);
+            updates.push(Ok(store_svid));
+            spire_entries.push(spire_entry);
+        }
+
+        let translated = self.translate_entries(&spire_entries).await?;
+        let prepared: Vec<_> = updates
+            .into_iter()
+            .zip(translated)
+            .map(|(update, entry)| -> Result<_, Option<types::Status>> {
+                let store_svid = update?;
+                let entry = entry.map_err(|err| error_status(Code::InvalidArgument, &err))?;
+                let current = &current[&entry.id];
+
+                Ok(RegistrationEntry {
+                    other_identities: current.other_identities.clone(),
+                    revision_number: current.revision_number,
+                    store_svid: if mask.store_svid {
+                        store_svid
+                    } else {
+                        current.store_svid
+                    },
+                    prefetch: current.prefetch,
+                    custom_claims: current.custom_claims.clone(),
+                    ..entry
+                })
+            })
+            .collect();
+
+        let entries: Vec<RegistrationEntry> = prepared
+            .iter()
+            .filter_map(|entry| entry.as_ref().ok().cloned())
+            .collect();
+        let ids: Vec<String> = entries.iter().map(|entry| entry.id.clone()).collect();
+        let errors = self
+            .api
+            .catalog
+            .batch_update(entries)
+            .await
+            .err()
+            .unwrap_or_default();
+
+        let revisions = current
+            .iter()
+            .map(|(id, entry)| (id.clone(), entry.revision_number))
+            .collect();
+        let changes = entry_changes(
+            EntryChangeKind::Updated,
+            ids,
+            &failed_ids(&errors),
+            &revisions,
+        );
+        self.api
+            .audit(&caller, "batch_update_entry", changes, None)
+            .await;
+
+        let results = self
+            .written_entries(prepared, errors)
+            .await
+            .into_iter()
+            .map(|(status, entry)| batch_update_entry_response::Result { status, entry })
+            .collect();
+
+        Ok(Response::new(BatchUpdateEntryResponse { results }))
+    }
+
+    async fn batch_delete_entry(
+        &self,
+        request: Request<BatchDeleteEntryRequest>,
+    ) -> Result<Response<BatchDeleteEntryResponse>, Status> {
+        let caller = self.authorize(&request)?;
+        let ids = request.into_inner().ids;
+
+        let revisions = self.api.revisions(&ids).await;
+        let errors = self
+            .api
+            .catalog
+            .batch_delete(&ids)
+            .await
+            .err()
+            .unwrap_or_default();
+
+        let changes = entry_changes(
+            EntryChangeKind::Deleted,
+            ids.clone(),
+            &failed_ids(&errors),
+            &revisions,
+        );
+        self.api
+            .audit(&caller, "batch_delete_entry", changes, None)
+            .await;
+
+        let errors: HashMap<String, Box<dyn std::error::Error + Send>> =
+            errors.into_iter().collect();
+
+        let results = ids
+            .into_iter()
+            .map(|id| batch_delete_entry_response::Result {
+                status: match errors.get(&id) {
+                    Some(err) => error_status(catalog_code(&**err), err),
+                    None => ok_status(),
+                },
+                id,
+            })
+            .collect();
+
+        Ok(Response::new(BatchDeleteEntryResponse { results }))
+    }
+}
+
+fn failed_ids(errors: &CatalogErrors) -> BTreeSet<&str> {
+    errors.iter().map(|(id, _)| id.as_str()).collect()
+}
+
+fn ok_status() -> Option<types::Status> {
+    Some(types::Status {
+        code: Code::Ok as i32,
+        message: "OK".to_string(),
+    })
+}
+
+fn error_status(code: Code, err: &dyn std::fmt::Display) -> Option<types::Status> {
+    Some(types::Status {
+        code: code as i32,
+        message: err.to_string(),
+    })
+}
+
+fn catalog_code(err: &(dyn std::error::Error + Send + 'static)) -> Code {
+    match err.downcast_ref::<catalog::Error>() {
+        Some(catalog::Error::RevisionConflict { .. }) => Code::Aborted,
+        _ => Code::Internal,
+    }
+}
+
+fn full_mask() -> EntryMask {
+    EntryMask {
+        spiffe_id: true,
+        parent_id: true,
+        selectors: true,
+        x509_svid_ttl: true,
+        federates_with: true,
+        admin: true,
+        downstream: true,
+        expires_at: true,
+        dns_names: true,
+        revision_number: true,
+        store_svid: true,
+        jwt_svid_ttl: true,
+    }
+}
+
+// Only the fields E4K supports, the others are ignored whatever the mask.
+fn apply_mask(
+    spire_entry: &mut spire_entries::Entry,
+    update: spire_entries::Entry,
+    mask: &EntryMask,
+) {
+    if mask.spiffe_id {
+        spire_entry.spiffe_id = update.spiffe_id;
+    }
+    if mask.parent_id {
+        spire_entry.parent_id = update.parent_id;
+    }
+    if mask.selectors {
+        spire_entry.selectors = update.selectors;
+    }
+    if mask.admin {
+        spire_entry.admin = update.admin;
+    }
+    if mask.expires_at {
+        spire_entry.expires_at = update.expires_at;
+    }
+    if mask.dns_names {
+        spire_entry.dns_names = update.dns_names;
+    }
+    if mask.jwt_svid_ttl {
+        spire_entry.jwt_svid_ttl = update.jwt_svid_ttl;
+    }
+    if mask.federates_with {
+        spire_entry.federates_with = update.federates_with;
+    }
+}
+
+fn matches_filter(filter: &Filter, spire_entry: &spire_entries::Entry) -> bool {
+    if let Some(spiffe_id) = &filter.by_spiffe_id {
+        if to_spiffe_id(Some(spiffe_id)) != spire_entry.spiffe_id {
+            return false;
+        }
+    }
+    if let Some(parent_id) = &filter.by_parent_id {
+        if to_spiffe_id(Some(parent_id)) != spire_entry.parent_id {
+            return false;
+        }
+    }
+
+    match &filter.by_selectors {
+        Some(selector_match) => matches_selectors(selector_match, &spire_entry.selectors),
+        None => true,
+    }
+}
+
+fn matches_selectors(selector_match: &SelectorMatch, selectors: &[Selector]) -> bool {
+    let wanted: BTreeSet<(&str, &str)> = selector_match
+        .selectors
+        .iter()
+        .map(|selector| (selector.r#type.as_str(), selector.value.as_str()))
+        .collect();
+    let selectors: BTreeSet<(&str, &str)> = selectors
+        .iter()
+        .map(|selector| (selector.selector_type.as_str(), selector.value.as_str()))
+        .collect();
+
+    match MatchBehavior::from_i32(selector_match.r#match) {
+        Some(MatchBehavior::MatchExact) => selectors == wanted,
+        Some(MatchBehavior::MatchSubset) => selectors.is_subset(&wanted),
+        Some(MatchBehavior::MatchSuperset) => selectors.is_superset(&wanted),
+        Some(MatchBehavior::MatchAny) => !selectors.is_disjoint(&wanted),
+        None => false,
+    }
+}
+
+fn to_spiffe_id(spiffe_id: Option<&Spiffeid>) -> String {
+    spiffe_id
+        .map(|spiffe_id| {
+            format!(
+                "{}{}{}",
+                SPIFFE_ID_PREFIX, spiffe_id.trust_domain, spiffe_id.path
+            )
+        })
+        .unwrap_or_default()
+}
+
+fn from_spiffe_id(spiffe_id: &str) -> Option<Spiffeid> {
+    let spiffe_id = SpiffeId::try_from(spiffe_id).ok()?;
+
+    Some(Spiffeid {
+        trust_domain: spiffe_id.trust_domain().to_string(),
+        path: spiffe_id.path().to_string(),
+    })
+}
+
+fn from_grpc_entry(entry: types::Entry) -> spire_entries::Entry {
+    spire_entries::Entry {
+        entry_id: if entry.id.is_empty() {
+            None
+        } else {
+            Some(entry.id)
+        },
+        spiffe_id: to_spiffe_id(entry.spiffe_id.as_ref()),
+        parent_id: to_spiffe_id(entry.parent_id.as_ref()),
+        selectors: entry
+            .selectors
+            .into_iter()
+            .map(|selector| Selector {
+                selector_type: selector.r#type,
+                value: selector.value,
+            })
+            .collect(),
+        dns_names: entry.dns_names,
+        admin: entry.admin,
+        expires_at: u64::try_from(entry.expires_at).unwrap_or_default(),
+        x509_svid_ttl: u32::try_from(entry.x509_svid_ttl).unwrap_or_default(),
+        jwt_svid_ttl: u32::try_from(entry.jwt_svid_ttl).unwrap_or_default(),
+        downstream: entry.downstream,
+        federates_with: entry.federates_with,
+    }
+}
+
+fn to_grpc_entry(spire_entry: spire_entries::Entry, entry: &RegistrationEntry) -> types::Entry {
+    types::Entry {
+        id: entry.id.clone(),
+        spiffe_id: from_spiffe_id(&spire_entry.spiffe_id),
+        parent_id: from_spiffe_id(&spire_entry.parent_id),
+        selectors: spire_entry
+            .selectors
+            .into_iter()
+            .map(|selector| types::Selector {
+                r#type: selector.selector_type,
+                value: selector.value,
+            })
+            .collect(),
+        admin: spire_entry.admin,
+        expires_at: i64::try_from(spire_entry.expires_at).unwrap_or(i64::MAX),
+        dns_names: spire_entry.dns_names,
+        revision_number: i64::try_from(entry.revision_number).unwrap_or(i64::MAX),
+        store_svid: entry.store_svid,
+        jwt_svid_ttl: i32::try_from(spire_entry.jwt_svid_ttl).unwrap_or(i32::MAX),
+        federates_with: spire_entry.federates_with,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use catalog::{AgentBans, EntryPruner};
+    use server_config::{CatalogConfig, KeyStoreConfig};
+
+    use crate::{
+        info_api::{catalog_backend, key_store_backend},
+        test_key_manager, test_trust_bundle_builder,
+    };
+
+    use super::*;
+
+    async fn init() -> SpireEntryApi {
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+
+        let api = Api {
+            catalog: catalog.clone(),
+            trust_bundle_builder: test_trust_bundle_builder(catalog.clone()),
+            entry_pruner: Arc::new(EntryPruner::new(catalog.clone())),
+            agent_bans: Arc::new(AgentBans::new(catalog)),
+            key_manager: test_key_manager(Arc::new(catalog::inmemory::Catalog::new())).await,
+            svid_audit: None,
+            admin_audit: None,
+            trust_domain: "trust_domain".to_string(),
+            catalog_backend: catalog_backend(&CatalogConfig::Memory),
+            key_store_backend: key_store_backend(&KeyStoreConfig::Memory()),
+            faults: None,
+            build: Default::default(),
+        };
+
+        SpireEntryApi {
+            api,
+            authorization: None,
+        }
+    }
+
+    fn spiffe_id(path: &str) -> Option<Spiffeid> {
+        Some(Spiffeid {
+            trust_domain: "trust_domain".to_string(),
+            path: path.to_string(),
+        })
+    }
+
+    fn selector(selector_type: &str, value: &str) -> types::Selector {
+        types::Selector {
+            r#type: selector_type.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    fn grpc_entry(id: &str, parent_id: &str, selectors: Vec<types::Selector>) -> types::Entry {
+        types::Entry {
+            id: id.to_string(),
+            spiffe_id: spiffe_id(&format!("/{}", id)),
+            parent_id: spiffe_id(parent_id),
+            selectors,
+            ..Default::default()
+        }
+    }
+
+    fn entries() -> Vec<types::Entry> {
+        // The workload entry comes first, its parent is created in the same batch.
+        vec![
+            grpc_entry(
+                "workload",
+                "/node",
+                vec![selector("k8s", "ns:default"), selector("k8s", "sa:web")],
+            ),
+            grpc_entry(
+                "node",
+                "/spire/server",
+                vec![selector("k8s_psat", "agent_node_name:node1")],
+            ),
+            grpc_entry("aws", "/spire/server", vec![selector("aws_iid", "tag:a")]),
+        ]
+    }
+
+    fn code(status: &Option<types::Status>) -> i32 {
+        status.as_ref().unwrap().code
+    }
+
+    #[tokio::test]
+    async fn create_list_get_entries() {
+        let api = init().await;
+
+        let res = api
+            .batch_create_entry(Request::new(BatchCreateEntryRequest {
+                entries: entries(),
+                output_mask: None,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            res.results
+                .iter()
+                .map(|result| code(&result.status))
+                .collect::<Vec<_>>(),
+            vec![
+                Code::Ok as i32,
+                Code::Ok as i32,
+                Code::InvalidArgument as i32
+            ]
+        );
+        assert_eq!(
+            res.results[0].entry.as_ref().unwrap().parent_id,
+            spiffe_id("/node")
+        );
+
+        let res = api
+            .list_entries(Request::new(ListEntriesRequest {
+                filter: Some(Filter {
+                    by_spiffe_id: None,
+                    by_parent_id: None,
+                    by_selectors: Some(SelectorMatch {
+                        selectors: vec![selector("k8s", "ns:default")],
+                        r#match: MatchBehavior::MatchSuperset as i32,
+                    }),
+                }),
+                ..Default::default()
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(res.entries.len(), 1);
+        assert_eq!(res.entries[0].id, "workload");
+        assert_eq!(res.next_page_token, "");
+
+        let entry = api
+            .get_entry(Request::new(GetEntryRequest {
+                id: "node".to_string(),
+                output_mask: None,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(entry.parent_id, spiffe_id("/spire/server"));
+        assert_eq!(
+            entry.selectors,
+            vec![selector("k8s_psat", "agent_node_name:node1")]
+        );
+
+        let count = api
+            .count_entries(Request::new(CountEntriesRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .count;
+        assert_eq!(count, 2);
+    }
+
+    #[tokio::test]
+    async fn update_delete_entries() {
+        let api = init().await;
+        api.batch_create_entry(Request::new(BatchCreateEntryRequest {
+            entries: entries(),
+            output_mask: None,
+        }))
+        .await
+        .unwrap();
+
+        // Only the admin flag is updated, the selectors of the update are ignored.
+        let mut update = grpc_entry("workload", "/node", Vec::new());
+        update.admin = true;
+        let res = api
+            .batch_update_entry(Request::new(BatchUpdateEntryRequest {
+                entries: vec![update, grpc_entry("missing", "/node", Vec::new())],
+                input_mask: Some(EntryMask {
+                    admin: true,
+                    ..Default::default()
+                }),
+                output_mask: None,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            res.results
+                .iter()
+                .map(|result| code(&result.status))
+                .collect::<Vec<_>>(),
+            vec![Code::Ok as i32, Code::NotFound as i32]
+        );
+        let updated = res.results[0].entry.as_ref().unwrap();
+        assert!(updated.admin);
+        assert_eq!(updated.selectors.len(), 2);
+
+        let res = api
+            .batch_delete_entry(Request::new(BatchDeleteEntryRequest {
+                ids: vec!["workload".to_string(), "missing".to_string()],
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            res.results
+                .iter()
+                .map(|result| code(&result.status))
+                .collect::<Vec<_>>(),
+            vec![Code::Ok as i32, Code::Internal as i32]
+        );
+    }
+
+    #[test]
+    fn selector_match_behaviors() {
+        let selectors = vec![
+            Selector {
+                selector_type: "k8s".to_string(),
+                value: "ns:default".to_string(),
+            },
+            Selector {
+                selector_type: "k8s".to_string(),
+                value: "sa:web".to_string(),
+            },
+        ];
+        let selector_match = |behavior: MatchBehavior, values: &[&str]| SelectorMatch {
+            selectors: values.iter().map(|value| selector("k8s", value)).collect(),
+            r#match: behavior as i32,
+        };
+
+        let matches = |behavior, values: &[&str]| {
+            matches_selectors(&selector_match(behavior, values), &selectors)
+        };
+        assert!(matches(
+            MatchBehavior::MatchExact,
+            &["sa:web", "ns:default"]
+        ));
+        assert!(!matches(MatchBehavior::MatchExact, &["ns:default"]));
+        assert!(matches(
+            MatchBehavior::MatchSubset,
+            &["ns:default", "sa:web", "sa:db"]
+        ));
+        assert!(!matches(MatchBehavior::MatchSubset, &["ns:default"]));
+        assert!(matches(MatchBehavior::MatchSuperset, &["ns:default"]));
+        assert!(matches(MatchBehavior::MatchAny, &["sa:web", "sa:db"]));
+        assert!(!matches(MatchBehavior::MatchAny, &["sa:db"]));
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use catalog::IssuedSvidFilter;
+use server_admin_api::list_issued_svids;
+
+use crate::{error::Error, Api};
+
+impl Api {
+    pub async fn list_issued_svids(
+        &self,
+        params: list_issued_svids::Params,
+    ) -> Result<list_issued_svids::Response, Error> {
+        let svid_audit = self.svid_audit.as_ref().ok_or(Error::SvidAuditDisabled)?;
+        let page_size: usize = params
+            .page_size
+            .try_into()
+            .map_err(|err| Error::InvalidPageSize(Box::new(err)))?;
+
+        let filter = IssuedSvidFilter {
+            spiffe_id: params.spiffe_id,
+            entry_id: params.entry_id,
+            agent_id: params.agent_id,
+            since: params.since,
+            until: params.until,
+        };
+        let (svids, next_page_token) = svid_audit
+            .list(&filter, params.page_token, page_size)
+            .await
+            .map_err(|err| Error::ListIssuedSvids(err))?;
+
+        Ok(list_issued_svids::Response {
+            svids,
+            next_page_token,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, sync::Arc};
+
+    use catalog::{AgentBans, EntryPruner, SvidAudit};
+    use core_objects::{IssuedSvid, KeyUse};
+    use server_config::{AuditLogConfig, CatalogConfig, KeyStoreConfig};
+
+    use crate::{
+        info_api::{catalog_backend, key_store_backend},
+        test_key_manager, test_trust_bundle_builder,
+    };
+
+    use super::*;
+
+    async fn init(svid_audit: Option<Arc<SvidAudit>>) -> Api {
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+
+        Api {
+            catalog: catalog.clone(),
+            trust_bundle_builder: test_trust_bundle_builder(catalog.clone()),
+            entry_pruner: Arc::new(EntryPruner::new(catalog.clone())),
+            agent_bans: Arc::new(AgentBans::new(catalog)),
+            key_manager: test_key_manager(Arc::new(catalog::inmemory::Catalog::new())).await,
+            svid_audit,
+            admin_audit: None,
+            trust_domain: "trust_domain".to_string(),
+            catalog_backend: catalog_backend(&CatalogConfig::Memory),
+            key_store_backend: key_store_backend(&KeyStoreConfig::Memory()),
+            faults: None,
+            build: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn list_issued_svids_filters() {
+        let svid_audit = Arc::new(SvidAudit::new(
+            &AuditLogConfig::Catalog,
+            Arc::new(catalog::inmemory::Catalog::new()),
+        ));
+        let svids = ["web", "web", "db"]
+            .iter()
+            .map(|path| IssuedSvid {
+                id: SvidAudit::record_id(1000),
+                svid_type: KeyUse::JWTSVID,
+                entry_id: path.to_string(),
+                spiffe_id: format!("spiffe://trust_domain/{}", path),
+                audiences: Vec::new(),
+                selectors: Vec::new(),
+                agent_id: "agent".to_string(),
+                agent_selectors: BTreeSet::new(),
+                issued_at: 1000,
+                expiry: 1300,
+            })
+            .collect();
+        svid_audit.record(svids).await.unwrap();
+        let api = init(Some(svid_audit)).await;
+
+        let res = api
+            .list_issued_svids(list_issued_svids::Params {
+                page_size: 10,
+                spiffe_id: Some("spiffe://trust_domain/web".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(res.svids.len(), 2);
+        assert!(res.next_page_token.is_none());
+
+        let res = api
+            .list_issued_svids(list_issued_svids::Params {
+                page_size: 10,
+                since: Some(1001),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(res.svids.is_empty());
+    }
+
+    #[tokio::test]
+    async fn list_issued_svids_disabled() {
+        let api = init(None).await;
+
+        let error = api
+            .list_issued_svids(list_issued_svids::Params {
+                page_size: 10,
+                ..Default::default()
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::SvidAuditDisabled));
+    }
+}
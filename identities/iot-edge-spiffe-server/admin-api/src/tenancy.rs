@@ -0,0 +1,714 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Tenants of the admin API. A caller presenting the bearer token of a tenant only sees and manages the
+// workload entries scoped to the kubernetes namespaces and the SPIFFE ID paths of the tenant, and only
+// reaches the entry endpoints.
+// Callers without a token have full access, once allowed by the permissions of the socket and by
+// `authorization` when it is configured, or by their client certificate over TLS.
+
+use std::{
+    convert::Infallible,
+    fs,
+    future::Future,
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use core_objects::{AttestationConfig, RegistrationEntry, WorkloadSelectorType};
+use http::{header, HeaderValue, Request, Response, StatusCode};
+use hyper::{service::Service, Body};
+use server_admin_api::operation;
+use server_config::AdminTenantConfig;
+use zeroize::Zeroizing;
+
+use crate::{
+    authorization::{Authorization, Connection},
+    http::uri,
+};
+
+const BEARER_PREFIX: &str = "Bearer ";
+
+#[derive(Debug)]
+pub(crate) struct Tenant {
+    name: String,
+    namespaces: Vec<String>,
+    spiffe_id_path_prefixes: Vec<String>,
+    federates_with: Vec<String>,
+    dns_names: Vec<String>,
+}
+
+impl Tenant {
+    pub(crate) fn new(config: &AdminTenantConfig) -> Self {
+        Tenant {
+            name: config.name.clone(),
+            namespaces: config.namespaces.clone(),
+            spiffe_id_path_prefixes: config.spiffe_id_path_prefixes.clone(),
+            federates_with: config.federates_with.clone(),
+            dns_names: config.dns_names.clone(),
+        }
+    }
+
+    fn allows_namespace(&self, namespace: &str) -> bool {
+        self.namespaces
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => namespace.starts_with(prefix),
+                None => namespace == pattern,
+            })
+    }
+
+    // Path segments are compared whole, so a prefix does not reach the paths of another tenant starting with
+    // the same characters.
+    fn allows_spiffe_id_path(&self, spiffe_id_path: &str) -> bool {
+        self.spiffe_id_path_prefixes.iter().any(|prefix| {
+            let prefix = prefix.trim_matches('/');
+
+            match spiffe_id_path.strip_prefix(prefix) {
+                Some(rest) => rest.is_empty() || rest.starts_with('/'),
+                None => false,
+            }
+        })
+    }
+
+    // DNS names are case insensitive.
+    fn allows_dns_name(&self, dns_name: &str) -> bool {
+        let dns_name = dns_name.to_ascii_lowercase();
+
+        self.dns_names.iter().any(|pattern| {
+            let pattern = pattern.to_ascii_lowercase();
+
+            match pattern.strip_prefix("*.") {
+                Some(domain) => dns_name.strip_suffix(domain).map_or(false, |subdomain| {
+                    subdomain.len() > 1 && subdomain.ends_with('.')
+                }),
+                None => dns_name == pattern,
+            }
+        })
+    }
+
+    // An entry without a namespace selector matches workloads of every namespace, it is never in scope.
+    // Node and admin entries are left to the operators of the server, as are the other identities, e.g.
+    // the IoT Hub identities of the devices.
+    fn allows(&self, entry: &RegistrationEntry) -> bool {
+        let workload_attestation = match &entry.attestation_config {
+            AttestationConfig::Workload(workload_attestation) if !entry.admin => {
+                workload_attestation
+            }
+            _ => return false,
+        };
+
+        let prefix = format!("{}:", WorkloadSelectorType::Namespace);
+        let mut namespaces = workload_attestation
+            .value
+            .iter()
+            .filter_map(|selector| selector.strip_prefix(&prefix))
+            .peekable();
+
+        namespaces.peek().is_some()
+            && namespaces.all(|namespace| self.allows_namespace(namespace))
+            && self.allows_spiffe_id_path(&entry.spiffe_id_path)
+            && entry.other_identities.is_empty()
+            && entry
+                .federates_with
+                .iter()
+                .all(|trust_domain| self.federates_with.contains(trust_domain))
+            && entry
+                .dns_names
+                .iter()
+                .all(|dns_name| self.allows_dns_name(dns_name))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Scope {
+    Admin,
+    Tenant(Arc<Tenant>),
+}
+
+impl Scope {
+    pub(crate) fn allows(&self, entry: &RegistrationEntry) -> bool {
+        match self {
+            Scope::Admin => true,
+            Scope::Tenant(tenant) => tenant.allows(entry),
+        }
+    }
+
+    pub(crate) fn forbidden(&self, id: String) -> operation::Error {
+        let tenant = match self {
+            Scope::Admin => "",
+            Scope::Tenant(tenant) => &tenant.name,
+        };
+
+        operation::Error {
+            id,
+            error: format!("Entry is outside of the scope of tenant {}", tenant),
+            kind: operation::ErrorKind::Forbidden,
+        }
+    }
+}
+
+// Who made a request, as recorded in the admin audit log: the tenant, the SPIFFE ID of the JWT-SVID, or the
+// connection of a caller without a token.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Caller(String);
+
+impl Caller {
+    fn tenant(tenant: &Tenant) -> Self {
+        Caller(format!("tenant:{}", tenant.name))
+    }
+
+    pub(crate) fn connection(connection: Connection) -> Self {
+        match connection {
+            Connection::Unix(Some(peer)) => Caller(format!("uid:{},gid:{}", peer.uid, peer.gid)),
+            Connection::Unix(None) => Caller::default(),
+            Connection::Tls { .. } => Caller("tls-client".to_string()),
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+// A local process whose credentials could not be read.
+impl Default for Caller {
+    fn default() -> Self {
+        Caller("local".to_string())
+    }
+}
+
+// The tokens are wiped from memory once the tenants are dropped.
+#[derive(Clone, Default)]
+pub(crate) struct Tenants {
+    tokens: Arc<Vec<(Zeroizing<Vec<u8>>, Arc<Tenant>)>>,
+}
+
+impl Tenants {
+    pub(crate) fn load(config: &[AdminTenantConfig]) -> io::Result<Self> {
+        let mut tokens = Vec::with_capacity(config.len());
+
+        for tenant in config {
+            let token = read_token(&tenant.token_file)?;
+            let tenant = Tenant::new(tenant);
+
+            tokens.push((token, Arc::new(tenant)));
+        }
+
+        Ok(Tenants {
+            tokens: Arc::new(tokens),
+        })
+    }
+
+    fn tenant(&self, token: &[u8]) -> Option<Arc<Tenant>> {
+        self.tokens
+            .iter()
+            .find(|(tenant_token, _)| constant_time_eq(tenant_token, token))
+            .map(|(_, tenant)| tenant.clone())
+    }
+}
+
+fn scope_without_token(
+    authorization: Option<&Authorization>,
+    connection: Connection,
+) -> Result<Scope, Response<Body>> {
+    match connection {
+        Connection::Unix(peer) => match authorization {
+            Some(authorization) if !authorization.allows_peer(peer) => {
+                log::warn!("Rejected admin API request from {:?}", peer);
+                let message = "Caller is not allowed to use the admin API";
+                Err(error_response(StatusCode::FORBIDDEN, message))
+            }
+            _ => Ok(Scope::Admin),
+        },
+        Connection::Tls { client_certificate } => {
+            if client_certificate {
+                Ok(Scope::Admin)
+            } else {
+                let message = "A client certificate or a token is required";
+                Err(error_response(StatusCode::UNAUTHORIZED, message))
+            }
+        }
+    }
+}
+
+// No token is the admin scope, for the local callers allowed by `authorization` and the TLS clients with a
+// certificate. A token is the one of a tenant, or a JWT-SVID of an admin entry.
+async fn resolve_scope(
+    tenants: &Tenants,
+    authorization: Option<&Authorization>,
+    connection: Connection,
+    req: &Request<Body>,
+) -> Result<(Scope, Caller), Response<Body>> {
+    let token = match req.headers().get(header::AUTHORIZATION) {
+        Some(token) => token.as_bytes().strip_prefix(BEARER_PREFIX.as_bytes()),
+        None => {
+            let scope = scope_without_token(authorization, connection)?;
+            return Ok((scope, Caller::connection(connection)));
+        }
+    };
+
+    let unknown_token = || error_response(StatusCode::UNAUTHORIZED, "Unknown tenant token");
+    let token = token.ok_or_else(unknown_token)?;
+    if let Some(tenant) = tenants.tenant(token) {
+        let caller = Caller::tenant(&tenant);
+        return Ok((Scope::Tenant(tenant), caller));
+    }
+
+    let authorization = authorization.ok_or_else(unknown_token)?;
+    let jwt_svid = std::str::from_utf8(token).map_err(|_| unknown_token())?;
+    match authorization.check_jwt_svid(jwt_svid).await {
+        Ok(spiffe_id) => Ok((Scope::Admin, Caller(spiffe_id))),
+        Err(err) => {
+            log::warn!("Rejected admin API request from {:?}: {}", connection, err);
+            Err(error_response(StatusCode::FORBIDDEN, &err.to_string()))
+        }
+    }
+}
+
+fn read_token(path: &str) -> io::Result<Zeroizing<Vec<u8>>> {
+    let mut token = Zeroizing::new(fs::read(path).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("Could not read the tenant token file {}: {}", path, err),
+        )
+    })?);
+
+    while matches!(token.last(), Some(b'\n' | b'\r')) {
+        token.pop();
+    }
+
+    if token.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("The tenant token file {} is empty", path),
+        ));
+    }
+
+    Ok(token)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+// Tenants manage their entries, everything else (trust bundle, snapshots, faults, info) is server wide.
+fn tenant_path(path: &str) -> bool {
+    matches!(
+        path,
+        uri::CREATE_DELETE_UPDATE_REGISTRATION_ENTRIES
+            | uri::SELECT_GET_REGISTRATION_ENTRIES
+            | uri::HEALTH
+    )
+}
+
+fn error_response(status_code: StatusCode, message: &str) -> Response<Body> {
+    let body = serde_json::json!({ "message": message }).to_string();
+
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status_code;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+
+    response
+}
+
+// Resolves the scope of the caller before the request is read, the routes get it and the caller from the
+// request extensions.
+#[derive(Clone)]
+pub(crate) struct TenantService<S> {
+    inner: S,
+    tenants: Tenants,
+    authorization: Option<Arc<Authorization>>,
+    connection: Connection,
+}
+
+impl<S> TenantService<S> {
+    pub(crate) fn new(inner: S, tenants: Tenants) -> Self {
+        TenantService {
+            inner,
+            tenants,
+            authorization: None,
+            connection: Connection::Unix(None),
+        }
+    }
+
+    pub(crate) fn with_authorization(mut self, authorization: Option<Arc<Authorization>>) -> Self {
+        self.authorization = authorization;
+
+        self
+    }
+
+    // The service of a connection accepted by a listener.
+    pub(crate) fn with_connection(mut self, connection: Connection) -> Self {
+        self.connection = connection;
+
+        self
+    }
+}
+
+impl<S> Service<Request<Body>> for TenantService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+{
+    type Response = Response<Body>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<Body>, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let tenants = self.tenants.clone();
+        let authorization = self.authorization.clone();
+        let connection = self.connection;
+
+        // The service polled ready handles this request, the clone is kept for the next one.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            let (scope, caller) =
+                match resolve_scope(&tenants, authorization.as_deref(), connection, &req).await {
+                    Ok(scope) => scope,
+                    Err(response) => return Ok(response),
+                };
+
+            if let Scope::Tenant(tenant) = &scope {
+                if !tenant_path(req.uri().path()) {
+                    let message =
+                        format!("Tenant {} cannot access {}", tenant.name, req.uri().path());
+                    return Ok(error_response(StatusCode::FORBIDDEN, &message));
+                }
+            }
+
+            req.extensions_mut().insert(scope);
+            req.extensions_mut().insert(caller);
+
+            inner.call(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use core_objects::{
+        build_selector_string, EntryNodeAttestation, IdentityTypes, NodeAttestationPlugin,
+        NodeSelectorType,
+    };
+    use hyper::service::service_fn;
+
+    use crate::authorization::{self, tests::config, PeerCredentials};
+
+    use super::*;
+
+    // The SPIFFE ID paths of the tenant are below its name.
+    pub(crate) fn tenant_config(name: &str, namespaces: &[&str]) -> AdminTenantConfig {
+        AdminTenantConfig {
+            name: name.to_string(),
+            token_file: String::new(),
+            namespaces: namespaces.iter().map(ToString::to_string).collect(),
+            spiffe_id_path_prefixes: vec![name.to_string()],
+            federates_with: Vec::new(),
+            dns_names: Vec::new(),
+        }
+    }
+
+    fn tenant(namespaces: &[&str]) -> Scope {
+        Scope::Tenant(Arc::new(Tenant::new(&tenant_config("tenant", namespaces))))
+    }
+
+    fn workload_entry(selectors: &[(WorkloadSelectorType, &str)]) -> RegistrationEntry {
+        let selectors = selectors
+            .iter()
+            .map(|(selector, value)| build_selector_string(selector, value))
+            .collect();
+
+        RegistrationEntry {
+            spiffe_id_path: "tenant/web".to_string(),
+            ..RegistrationEntry::test_workload_entry("id", "parent", selectors)
+        }
+    }
+
+    #[test]
+    fn tenant_allows_its_namespaces() {
+        let scope = tenant(&["team-a", "team-a-*"]);
+
+        let entry = workload_entry(&[(WorkloadSelectorType::Namespace, "team-a")]);
+        assert!(scope.allows(&entry));
+        let entry = workload_entry(&[
+            (WorkloadSelectorType::Namespace, "team-a-dev"),
+            (WorkloadSelectorType::PodName, "pod"),
+        ]);
+        assert!(scope.allows(&entry));
+
+        let entry = workload_entry(&[(WorkloadSelectorType::Namespace, "team-ab")]);
+        assert!(!scope.allows(&entry));
+        let entry = workload_entry(&[
+            (WorkloadSelectorType::Namespace, "team-a"),
+            (WorkloadSelectorType::Namespace, "team-b"),
+        ]);
+        assert!(!scope.allows(&entry));
+    }
+
+    #[test]
+    fn tenant_allows_its_identities() {
+        let scope = Scope::Tenant(Arc::new(Tenant::new(&AdminTenantConfig {
+            federates_with: vec!["partner.example.org".to_string()],
+            dns_names: vec![
+                "web.example.org".to_string(),
+                "*.tenant.example.org".to_string(),
+            ],
+            ..tenant_config("tenant", &["team-a"])
+        })));
+        let entry = || workload_entry(&[(WorkloadSelectorType::Namespace, "team-a")]);
+
+        for spiffe_id_path in ["tenant", "tenant/web", "tenant/web/api"] {
+            let entry = RegistrationEntry {
+                spiffe_id_path: spiffe_id_path.to_string(),
+                ..entry()
+            };
+            assert!(scope.allows(&entry), "{}", spiffe_id_path);
+        }
+        for spiffe_id_path in ["tenant-b", "tenantweb", "other/tenant", "admin"] {
+            let entry = RegistrationEntry {
+                spiffe_id_path: spiffe_id_path.to_string(),
+                ..entry()
+            };
+            assert!(!scope.allows(&entry), "{}", spiffe_id_path);
+        }
+
+        let entry = RegistrationEntry {
+            federates_with: vec!["partner.example.org".to_string()],
+            dns_names: vec![
+                "web.example.org".to_string(),
+                "API.Tenant.Example.org".to_string(),
+            ],
+            ..entry()
+        };
+        assert!(scope.allows(&entry));
+
+        let entry = RegistrationEntry {
+            federates_with: vec!["other.example.org".to_string()],
+            ..entry()
+        };
+        assert!(!scope.allows(&entry));
+        for dns_name in [
+            "other.example.org",
+            "tenant.example.org",
+            "api.other-tenant.example.org",
+        ] {
+            let entry = RegistrationEntry {
+                dns_names: vec![dns_name.to_string()],
+                ..entry()
+            };
+            assert!(!scope.allows(&entry), "{}", dns_name);
+        }
+
+        let entry = RegistrationEntry {
+            other_identities: vec![IdentityTypes::Custom("custom".to_string())],
+            ..entry()
+        };
+        assert!(!scope.allows(&entry));
+    }
+
+    #[test]
+    fn tenant_denies_unscoped_entries() {
+        let scope = tenant(&["*"]);
+
+        let entry = workload_entry(&[(WorkloadSelectorType::PodName, "pod")]);
+        assert!(!scope.allows(&entry));
+
+        let mut entry = workload_entry(&[(WorkloadSelectorType::Namespace, "team-a")]);
+        entry.admin = true;
+        assert!(!scope.allows(&entry));
+
+        entry.attestation_config = AttestationConfig::Node(EntryNodeAttestation {
+            value: vec![build_selector_string(
+                &NodeSelectorType::AgentNameSpace,
+                "team-a",
+            )],
+            plugin: NodeAttestationPlugin::Psat,
+        });
+        entry.admin = false;
+        assert!(!scope.allows(&entry));
+
+        assert!(Scope::Admin.allows(&entry));
+    }
+
+    #[test]
+    fn caller_of_connection() {
+        let peer = PeerCredentials { uid: 5, gid: 6 };
+
+        assert_eq!(
+            Caller::connection(Connection::Unix(Some(peer))).as_str(),
+            "uid:5,gid:6"
+        );
+        assert_eq!(Caller::connection(Connection::Unix(None)).as_str(), "local");
+        assert_eq!(
+            Caller::connection(Connection::Tls {
+                client_certificate: true
+            })
+            .as_str(),
+            "tls-client"
+        );
+    }
+
+    async fn call(path: &str, authorization: Option<&str>) -> StatusCode {
+        call_as(path, authorization, None, Connection::Unix(None)).await
+    }
+
+    async fn call_as(
+        path: &str,
+        token: Option<&str>,
+        authorization: Option<Authorization>,
+        connection: Connection,
+    ) -> StatusCode {
+        let tenants = Tenants {
+            tokens: Arc::new(vec![(
+                Zeroizing::new(b"token".to_vec()),
+                Arc::new(Tenant::new(&tenant_config("tenant", &[]))),
+            )]),
+        };
+        let echo_scope = service_fn(|req: Request<Body>| async move {
+            let status_code = match req.extensions().get::<Scope>() {
+                Some(Scope::Admin) => StatusCode::OK,
+                Some(Scope::Tenant(_)) => StatusCode::ACCEPTED,
+                None => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = status_code;
+            Ok::<_, Infallible>(response)
+        });
+        let mut service = TenantService::new(echo_scope, tenants)
+            .with_authorization(authorization.map(Arc::new))
+            .with_connection(connection);
+
+        let mut req = Request::get(path);
+        if let Some(token) = token {
+            req = req.header(header::AUTHORIZATION, token);
+        }
+
+        service
+            .call(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn service_resolves_scope() {
+        assert_eq!(call(uri::SNAPSHOT, None).await, StatusCode::OK);
+        assert_eq!(
+            call(
+                uri::CREATE_DELETE_UPDATE_REGISTRATION_ENTRIES,
+                Some("Bearer token")
+            )
+            .await,
+            StatusCode::ACCEPTED
+        );
+        assert_eq!(
+            call(uri::SNAPSHOT, Some("Bearer token")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            call(uri::HEALTH, Some("Bearer other")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            call(uri::HEALTH, Some("token")).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn service_checks_authorization() {
+        let config = config();
+        let admin = || authorization::tests::init("spiffe://trust_domain/admin", &config);
+        let workload = || authorization::tests::init("spiffe://trust_domain/workload", &config);
+        let allowed = Connection::Unix(Some(PeerCredentials { uid: 0, gid: 0 }));
+        let other = Connection::Unix(Some(PeerCredentials { uid: 5, gid: 5 }));
+
+        assert_eq!(
+            call_as(uri::SNAPSHOT, None, Some(admin().await), allowed).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            call_as(uri::SNAPSHOT, None, Some(admin().await), other).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            call_as(
+                uri::SNAPSHOT,
+                None,
+                Some(admin().await),
+                Connection::Unix(None)
+            )
+            .await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            call_as(
+                uri::SNAPSHOT,
+                Some("Bearer jwt"),
+                Some(admin().await),
+                other
+            )
+            .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            call_as(
+                uri::SNAPSHOT,
+                Some("Bearer jwt"),
+                Some(workload().await),
+                other
+            )
+            .await,
+            StatusCode::FORBIDDEN
+        );
+        // Tenants keep their tokens.
+        assert_eq!(
+            call_as(
+                uri::HEALTH,
+                Some("Bearer token"),
+                Some(admin().await),
+                other
+            )
+            .await,
+            StatusCode::ACCEPTED
+        );
+    }
+
+    #[tokio::test]
+    async fn service_checks_client_certificate() {
+        let verified = Connection::Tls {
+            client_certificate: true,
+        };
+        let anonymous = Connection::Tls {
+            client_certificate: false,
+        };
+
+        assert_eq!(
+            call_as(uri::SNAPSHOT, None, None, verified).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            call_as(uri::SNAPSHOT, None, None, anonymous).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            call_as(uri::HEALTH, Some("Bearer token"), None, anonymous).await,
+            StatusCode::ACCEPTED
+        );
+    }
+}
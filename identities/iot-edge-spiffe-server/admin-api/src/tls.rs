@@ -0,0 +1,82 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// TCP listener of the admin API, for the clients outside of the server pod. The server authenticates with its
+// certificate. A client with a certificate of the client CAs is an admin, the others need a tenant token or
+// the JWT-SVID of an admin entry.
+
+use std::{convert::Infallible, io};
+
+use hyper::{server::conn::Http, service::Service, Body, Request, Response};
+use openssl::{
+    ssl::{SslAcceptor, SslVerifyMode},
+    x509::{X509Name, X509VerifyResult},
+};
+use server_config::AdminTlsListenerConfig;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_openssl::SslStream;
+
+use crate::{authorization::Connection, tenancy::TenantService};
+
+pub(crate) fn acceptor(config: &AdminTlsListenerConfig) -> io::Result<SslAcceptor> {
+    tls_listener::acceptor(&config.cert_path, &config.key_path, |builder| {
+        if let Some(client_ca_path) = &config.client_ca_path {
+            builder.set_ca_file(client_ca_path)?;
+            builder.set_client_ca_list(X509Name::load_client_ca_file(client_ca_path)?);
+            // Clients without a certificate still connect, they authenticate with a token.
+            builder.set_verify(SslVerifyMode::PEER);
+        }
+
+        Ok(())
+    })
+    .map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid admin TLS listener configuration: {}", err),
+        )
+    })
+}
+
+pub(crate) async fn bind(config: &AdminTlsListenerConfig) -> io::Result<TcpListener> {
+    TcpListener::bind((config.bind_address.as_str(), config.bind_port)).await
+}
+
+pub(crate) async fn serve<S>(
+    listener: TcpListener,
+    acceptor: SslAcceptor,
+    service: TenantService<S>,
+) where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+{
+    tls_listener::serve(
+        listener,
+        acceptor,
+        "admin TLS listener",
+        move |stream, _| serve_connection(stream, service.clone()),
+    )
+    .await;
+}
+
+async fn serve_connection<S>(stream: SslStream<TcpStream>, service: TenantService<S>)
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+{
+    let client_certificate = stream.ssl().peer_certificate().is_some()
+        && stream.ssl().verify_result() == X509VerifyResult::OK;
+    let service = service.with_connection(Connection::Tls { client_certificate });
+
+    if let Err(err) = Http::new()
+        .http1_only(true)
+        .serve_connection(stream, service)
+        .await
+    {
+        log::error!("Error serving the admin TLS listener: {}", err);
+    }
+}
@@ -0,0 +1,277 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use crate::{error::Error, tenancy::Caller, Api};
+use server_admin_api::{
+    get_trust_bundle, get_trust_bundle_history, revoke_signing_key, rollback_trust_bundle,
+};
+
+impl Api {
+    // The trust bundle as the agents get it, e.g. for services that validate JWT-SVIDs without an agent.
+    pub async fn get_trust_bundle(&self) -> Result<get_trust_bundle::Response, Error> {
+        let trust_bundle = self
+            .trust_bundle_builder
+            .build_trust_bundle(true, false)
+            .await
+            .map_err(|err| Error::TrustBundle(Box::new(err)))?;
+
+        let jwks = get_trust_bundle::JWKS {
+            keys: trust_bundle.jwt_key_set.keys.clone(),
+        };
+
+        Ok(get_trust_bundle::Response { trust_bundle, jwks })
+    }
+
+    pub async fn get_trust_bundle_history(
+        &self,
+    ) -> Result<get_trust_bundle_history::Response, Error> {
+        let versions = self
+            .catalog
+            .get_jwk_history(&self.trust_domain)
+            .await
+            .map_err(|err| Error::TrustBundleHistory(err))?;
+
+        Ok(get_trust_bundle_history::Response { versions })
+    }
+
+    // Re-publish a previous version of the trust bundle, e.g. after a malformed key was published.
+    // The rollback is published with a new sequence number so agents pick it up. The key manager rejects the
+    // versions without the current signing key or with a revoked key.
+    pub async fn rollback_trust_bundle(
+        &self,
+        req: rollback_trust_bundle::Request,
+        caller: &Caller,
+    ) -> Result<rollback_trust_bundle::Response, Error> {
+        log::warn!("Rolling back trust bundle to version {}", req.version);
+
+        let version = self
+            .key_manager
+            .rollback_trust_bundle(req.version)
+            .await
+            .map_err(|err| Error::TrustBundleRollback(Box::new(err)))?;
+
+        let detail = format!(
+            "Rolled back the trust bundle to version {}, published as version {}",
+            req.version, version
+        );
+        self.audit(caller, "rollback_trust_bundle", Vec::new(), Some(detail))
+            .await;
+
+        Ok(rollback_trust_bundle::Response { version })
+    }
+
+    // Replace the current signing key and remove it from the trust bundle, e.g. when it may be compromised.
+    // The JWT-SVIDs it signed are no longer valid once the agents have the new trust bundle.
+    pub async fn revoke_signing_key(
+        &self,
+        caller: &Caller,
+    ) -> Result<revoke_signing_key::Response, Error> {
+        let revocation = self
+            .key_manager
+            .revoke_current_key()
+            .await
+            .map_err(|err| Error::RevokeSigningKey(Box::new(err)))?;
+
+        let (_keys, version) = self
+            .catalog
+            .get_jwk(&self.trust_domain)
+            .await
+            .map_err(|err| Error::RevokeSigningKey(err))?;
+
+        let detail = format!(
+            "Revoked signing key {}, {} is the current key",
+            revocation.revoked_key_id, revocation.current_key_id
+        );
+        self.audit(caller, "revoke_signing_key", Vec::new(), Some(detail))
+            .await;
+
+        Ok(revoke_signing_key::Response {
+            revoked_key_id: revocation.revoked_key_id,
+            current_key_id: revocation.current_key_id,
+            version,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use catalog::{AgentBans, EntryPruner, TrustBundleStore};
+    use core_objects::{Crv, KeyUse, Kty, JWK};
+    use server_config::{CatalogConfig, KeyStoreConfig};
+
+    use crate::{
+        info_api::{catalog_backend, key_store_backend},
+        test_key_manager, test_trust_bundle_builder,
+    };
+
+    use super::*;
+
+    fn jwk(kid: &str) -> JWK {
+        JWK {
+            kid: kid.to_string(),
+            x: "abc".to_string(),
+            y: "abc".to_string(),
+            kty: Kty::EC,
+            crv: Some(Crv::P256),
+            n: String::new(),
+            e: String::new(),
+            key_use: KeyUse::JWTSVID,
+        }
+    }
+
+    #[tokio::test]
+    async fn rollback_trust_bundle_happy_path() {
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+        let api = Api {
+            catalog: catalog.clone(),
+            trust_bundle_builder: test_trust_bundle_builder(catalog.clone()),
+            entry_pruner: Arc::new(EntryPruner::new(catalog.clone())),
+            agent_bans: Arc::new(AgentBans::new(catalog.clone())),
+            key_manager: test_key_manager(catalog.clone()).await,
+            svid_audit: None,
+            admin_audit: None,
+            trust_domain: "trust_domain".to_string(),
+            catalog_backend: catalog_backend(&CatalogConfig::Memory),
+            key_store_backend: key_store_backend(&KeyStoreConfig::Memory()),
+            faults: None,
+            build: Default::default(),
+        };
+        catalog.add_jwk("trust_domain", jwk("bad")).await.unwrap();
+
+        let history = api.get_trust_bundle_history().await.unwrap();
+        assert_eq!(history.versions.len(), 2);
+
+        let res = api
+            .rollback_trust_bundle(
+                rollback_trust_bundle::Request { version: 1 },
+                &Caller::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.version, 3);
+    }
+
+    #[tokio::test]
+    async fn revoke_signing_key_happy_path() {
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+        let api = Api {
+            catalog: catalog.clone(),
+            trust_bundle_builder: test_trust_bundle_builder(catalog.clone()),
+            entry_pruner: Arc::new(EntryPruner::new(catalog.clone())),
+            agent_bans: Arc::new(AgentBans::new(catalog.clone())),
+            key_manager: test_key_manager(catalog.clone()).await,
+            svid_audit: None,
+            admin_audit: None,
+            trust_domain: "trust_domain".to_string(),
+            catalog_backend: catalog_backend(&CatalogConfig::Memory),
+            key_store_backend: key_store_backend(&KeyStoreConfig::Memory()),
+            faults: None,
+            build: Default::default(),
+        };
+        let (keys, _version) = catalog.get_jwk("trust_domain").await.unwrap();
+        let revoked_key_id = keys[0].kid.clone();
+
+        let res = api.revoke_signing_key(&Caller::default()).await.unwrap();
+        assert_eq!(res.revoked_key_id, revoked_key_id);
+        assert_eq!(res.version, 3);
+
+        let (keys, _version) = catalog.get_jwk("trust_domain").await.unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].kid, res.current_key_id);
+    }
+
+    #[tokio::test]
+    async fn get_trust_bundle_happy_path() {
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+        let api = Api {
+            catalog: catalog.clone(),
+            trust_bundle_builder: test_trust_bundle_builder(catalog.clone()),
+            entry_pruner: Arc::new(EntryPruner::new(catalog.clone())),
+            agent_bans: Arc::new(AgentBans::new(catalog.clone())),
+            key_manager: test_key_manager(catalog.clone()).await,
+            svid_audit: None,
+            admin_audit: None,
+            trust_domain: "trust_domain".to_string(),
+            catalog_backend: catalog_backend(&CatalogConfig::Memory),
+            key_store_backend: key_store_backend(&KeyStoreConfig::Memory()),
+            faults: None,
+            build: Default::default(),
+        };
+        let (keys, version) = catalog.get_jwk("trust_domain").await.unwrap();
+
+        let res = api.get_trust_bundle().await.unwrap();
+        assert_eq!(res.trust_bundle.trust_domain, "trust_domain");
+        assert_eq!(res.trust_bundle.jwt_key_set.keys, keys);
+        assert_eq!(
+            res.trust_bundle.jwt_key_set.spiffe_sequence_number,
+            version as u64
+        );
+        assert_eq!(res.jwks.keys, keys);
+    }
+
+    #[tokio::test]
+    async fn rollback_trust_bundle_unknown_version() {
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+        let api = Api {
+            catalog: catalog.clone(),
+            trust_bundle_builder: test_trust_bundle_builder(catalog.clone()),
+            entry_pruner: Arc::new(EntryPruner::new(catalog.clone())),
+            agent_bans: Arc::new(AgentBans::new(catalog)),
+            key_manager: test_key_manager(Arc::new(catalog::inmemory::Catalog::new())).await,
+            svid_audit: None,
+            admin_audit: None,
+            trust_domain: "trust_domain".to_string(),
+            catalog_backend: catalog_backend(&CatalogConfig::Memory),
+            key_store_backend: key_store_backend(&KeyStoreConfig::Memory()),
+            faults: None,
+            build: Default::default(),
+        };
+
+        let error = api
+            .rollback_trust_bundle(
+                rollback_trust_bundle::Request { version: 7 },
+                &Caller::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::TrustBundleRollback(_)));
+    }
+
+    #[tokio::test]
+    async fn rollback_trust_bundle_after_revocation() {
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+        let api = Api {
+            catalog: catalog.clone(),
+            trust_bundle_builder: test_trust_bundle_builder(catalog.clone()),
+            entry_pruner: Arc::new(EntryPruner::new(catalog.clone())),
+            agent_bans: Arc::new(AgentBans::new(catalog.clone())),
+            key_manager: test_key_manager(catalog.clone()).await,
+            svid_audit: None,
+            admin_audit: None,
+            trust_domain: "trust_domain".to_string(),
+            catalog_backend: catalog_backend(&CatalogConfig::Memory),
+            key_store_backend: key_store_backend(&KeyStoreConfig::Memory()),
+            faults: None,
+            build: Default::default(),
+        };
+        let res = api.revoke_signing_key(&Caller::default()).await.unwrap();
+
+        // Version 1 only has the revoked key, version 2 has both keys.
+        for version in [1, 2] {
+            let error = api
+                .rollback_trust_bundle(
+                    rollback_trust_bundle::Request { version },
+                    &Caller::default(),
+                )
+                .await
+                .unwrap_err();
+            assert!(error.to_string().contains(&res.revoked_key_id));
+        }
+
+        let (keys, version) = catalog.get_jwk("trust_domain").await.unwrap();
+        assert_eq!(version, 3);
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].kid, res.current_key_id);
+    }
+}
@@ -0,0 +1,13 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Cannot build the trust bundle: {0}")]
+    TrustBundle(trust_bundle_builder::error::Error),
+    #[error("Cannot serialize the bundle: {0}")]
+    Serialize(serde_json::Error),
+    #[error("Cannot write the config map of the bundle: {0}")]
+    Patch(kube::Error),
+}
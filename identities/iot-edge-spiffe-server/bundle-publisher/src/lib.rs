@@ -0,0 +1,159 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::default_trait_access,
+    clippy::let_unit_value,
+    clippy::missing_errors_doc,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+// Writes the bundle of the trust domain to a config map every time it changes, like the k8sbundle notifier of
+// SPIRE, for the workloads and admission webhooks that cannot reach an agent. The trust bundle is checked as
+// often as the agents watch it, so a rotated key is published before it signs. Every replica of the server
+// writes the same bundle, the config map is applied so the writes never conflict.
+
+pub mod error;
+
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use federation::bundle::SpiffeBundle;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::api::{Patch, PatchParams};
+#[cfg(not(any(test, feature = "tests")))]
+use kube::{Api, Client};
+use log::{error, info};
+#[cfg(any(test, feature = "tests"))]
+use mock_kube::{Api, Client};
+use server_config::BundleConfigMapConfig;
+use tokio::time;
+use trust_bundle_builder::TrustBundleBuilder;
+
+use crate::error::Error;
+
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(5);
+const FIELD_MANAGER: &str = "iotedge-spiffe-server";
+
+pub struct ConfigMapPublisher {
+    api: Api<ConfigMap>,
+    name: String,
+    key: String,
+    trust_bundle_builder: Arc<TrustBundleBuilder>,
+    // Only kept in memory, the bundle is written once more when the server restarts.
+    last_published: Option<SpiffeBundle>,
+}
+
+impl ConfigMapPublisher {
+    #[must_use]
+    pub fn new(
+        config: &BundleConfigMapConfig,
+        trust_bundle_builder: Arc<TrustBundleBuilder>,
+        client: Client,
+    ) -> Self {
+        ConfigMapPublisher {
+            api: Api::namespaced(client, &config.namespace),
+            name: config.name.clone(),
+            key: config.key.clone(),
+            trust_bundle_builder,
+            last_published: None,
+        }
+    }
+
+    // Never returns. A failed write is tried again at the next check.
+    pub async fn run(mut self) {
+        info!("Publishing the trust bundle to config map {}", self.name);
+
+        let mut interval = time::interval(PUBLISH_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            if let Err(err) = self.publish().await {
+                error!("Cannot publish the trust bundle: {}", err);
+            }
+        }
+    }
+
+    // Returns whether the config map was written, it is not when the bundle did not change.
+    async fn publish(&mut self) -> Result<bool, Error> {
+        let trust_bundle = self
+            .trust_bundle_builder
+            .build_trust_bundle(true, true)
+            .await
+            .map_err(Error::TrustBundle)?;
+        let bundle = SpiffeBundle::from(&trust_bundle);
+        // The refresh hint shrinks as a rotation comes up, the bundle is only written again when its keys change.
+        if let Some(last_published) = &self.last_published {
+            if last_published.spiffe_sequence == bundle.spiffe_sequence
+                && last_published.keys == bundle.keys
+            {
+                return Ok(false);
+            }
+        }
+
+        let data = serde_json::to_string(&bundle).map_err(Error::Serialize)?;
+        // Only the key of the bundle is owned by the server, the other keys of the config map are kept.
+        let patch = serde_json::json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": self.name },
+            "data": BTreeMap::from([(self.key.clone(), data)]),
+        });
+
+        self.api
+            .patch(
+                &self.name,
+                &PatchParams::apply(FIELD_MANAGER).force(),
+                &Patch::Apply(&patch),
+            )
+            .await
+            .map_err(Error::Patch)?;
+        info!(
+            "Published bundle {} to config map {}",
+            bundle.spiffe_sequence, self.name
+        );
+        self.last_published = Some(bundle);
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use catalog::inmemory;
+    use core_objects::CONFIG_DEFAULT_PATH;
+    use key_manager::KeyManager;
+    use server_config::Config;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn publish_changed_bundle() {
+        let config = Config::load_config(CONFIG_DEFAULT_PATH).unwrap();
+        let catalog = Arc::new(inmemory::Catalog::new());
+        let key_store = Arc::new(key_store::inmemory::KeyStore::new());
+        let key_manager = KeyManager::new(&config, catalog.clone(), key_store, 0)
+            .await
+            .unwrap();
+        let trust_bundle_builder = TrustBundleBuilder::new(&config, catalog);
+
+        let mut client = Client::try_default().await.unwrap();
+        let publisher_config = BundleConfigMapConfig {
+            namespace: "iotedge".to_string(),
+            name: "bundle".to_string(),
+            key: "bundle.spiffe".to_string(),
+        };
+        let mut publisher =
+            ConfigMapPublisher::new(&publisher_config, trust_bundle_builder, client.clone());
+
+        client.queue_response(ConfigMap::default()).await;
+        assert!(publisher.publish().await.unwrap());
+        let bundle = publisher.last_published.clone().unwrap();
+        let slots = key_manager.slots.read().await;
+        assert_eq!(bundle.keys[0].kid, slots.current_jwt_key.kid);
+
+        // Nothing is queued, the config map is not written again.
+        assert!(!publisher.publish().await.unwrap());
+    }
+}
@@ -0,0 +1,168 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::Arc;
+
+use core_objects::AdminOperation;
+use server_config::AuditLogConfig;
+
+use crate::{
+    audit_log::{self, Sink},
+    Catalog,
+};
+
+/// Filter of the admin operations. An operation matches when it matches all the criteria that are set.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdminOperationFilter {
+    pub caller: Option<String>,
+    /// The operation changed this entry.
+    pub entry_id: Option<String>,
+    /// Made at or after this time.
+    pub since: Option<u64>,
+    /// Made before this time.
+    pub until: Option<u64>,
+}
+
+impl AdminOperationFilter {
+    #[must_use]
+    pub fn matches(&self, operation: &AdminOperation) -> bool {
+        self.caller
+            .as_ref()
+            .map_or(true, |caller| caller == &operation.caller)
+            && self.entry_id.as_ref().map_or(true, |entry_id| {
+                operation.entries.iter().any(|entry| &entry.id == entry_id)
+            })
+            && self.since.map_or(true, |since| operation.time >= since)
+            && self.until.map_or(true, |until| operation.time < until)
+    }
+}
+
+/// Append-only audit log of the changes made through the admin API, in a file of this replica or in the
+/// catalog.
+pub struct AdminAudit {
+    sink: Sink,
+}
+
+impl AdminAudit {
+    #[must_use]
+    pub fn new(config: &AuditLogConfig, catalog: Arc<dyn Catalog>) -> Self {
+        AdminAudit {
+            sink: Sink::new(config, catalog),
+        }
+    }
+
+    /// Id of an operation made at `time`: the time, zero padded so the ids sort by time, and random hex.
+    #[must_use]
+    pub fn record_id(time: u64) -> String {
+        audit_log::record_id(time)
+    }
+
+    pub async fn record(
+        &self,
+        operation: AdminOperation,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        match &self.sink {
+            Sink::File { path, lock } => audit_log::append(path, lock, &[operation]).await,
+            Sink::Catalog(catalog) => catalog.record_admin_operation(operation).await,
+        }
+    }
+
+    /// List the operations matching the filter, ordered by id. Pages work the same way as the ones of
+    /// `Entries::list_all`. A file is read whole for every page.
+    pub async fn list(
+        &self,
+        filter: &AdminOperationFilter,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<AdminOperation>, Option<String>), Box<dyn std::error::Error + Send>> {
+        match &self.sink {
+            Sink::File { path, .. } => {
+                audit_log::read_page(
+                    path,
+                    page_token,
+                    page_size,
+                    |operation: &AdminOperation| operation.id.as_str(),
+                    |operation| filter.matches(operation),
+                )
+                .await
+            }
+            Sink::Catalog(catalog) => {
+                catalog
+                    .list_admin_operations(filter, page_token, page_size)
+                    .await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core_objects::{EntryChange, EntryChangeKind};
+    use server_config::AuditLogConfigFile;
+
+    use crate::inmemory;
+
+    use super::*;
+
+    fn operation(time: u64, caller: &str, entry_id: &str) -> AdminOperation {
+        AdminOperation {
+            id: AdminAudit::record_id(time),
+            time,
+            caller: caller.to_string(),
+            operation: "update_registration_entries".to_string(),
+            entries: vec![EntryChange {
+                id: entry_id.to_string(),
+                change: EntryChangeKind::Updated,
+                previous_revision: Some(1),
+            }],
+            detail: None,
+        }
+    }
+
+    async fn record_and_list(audit: AdminAudit) {
+        audit.record(operation(1000, "uid:0", "web")).await.unwrap();
+        audit.record(operation(1000, "uid:0", "db")).await.unwrap();
+        audit
+            .record(operation(2000, "tenant:team-a", "web"))
+            .await
+            .unwrap();
+
+        let filter = AdminOperationFilter {
+            entry_id: Some("web".to_string()),
+            ..Default::default()
+        };
+        let (operations, page_token) = audit.list(&filter, None, 1).await.unwrap();
+        assert_eq!(operations.len(), 1);
+        assert_eq!(operations[0].time, 1000);
+        let (operations, page_token) = audit.list(&filter, page_token, 1).await.unwrap();
+        assert_eq!(operations.len(), 1);
+        assert_eq!(operations[0].caller, "tenant:team-a");
+        assert!(page_token.is_none());
+
+        let filter = AdminOperationFilter {
+            caller: Some("uid:0".to_string()),
+            until: Some(1500),
+            ..Default::default()
+        };
+        let (operations, _) = audit.list(&filter, None, 10).await.unwrap();
+        assert_eq!(operations.len(), 2);
+
+        audit.list(&filter, None, 0).await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn file_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AuditLogConfig::File(AuditLogConfigFile {
+            path: dir.path().join("admin.log").to_str().unwrap().to_string(),
+        });
+
+        record_and_list(AdminAudit::new(&config, Arc::new(inmemory::Catalog::new()))).await;
+    }
+
+    #[tokio::test]
+    async fn catalog_sink() {
+        let config = AuditLogConfig::Catalog;
+
+        record_and_list(AdminAudit::new(&config, Arc::new(inmemory::Catalog::new()))).await;
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use core_objects::AgentBan;
+use parking_lot::RwLock;
+
+use crate::Catalog;
+
+/// Bans of agents, stored in the catalog so they apply to every replica of the server. Agents are checked against
+/// a copy of the bans, which is updated by `refresh` and by the changes made through this replica.
+pub struct AgentBans {
+    catalog: Arc<dyn Catalog>,
+    bans: RwLock<Vec<AgentBan>>,
+}
+
+impl AgentBans {
+    #[must_use]
+    pub fn new(catalog: Arc<dyn Catalog>) -> Self {
+        AgentBans {
+            catalog,
+            bans: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Read the bans from the catalog again, so the bans added by other replicas apply.
+    pub async fn refresh(&self) -> Result<(), Box<dyn std::error::Error + Send>> {
+        let bans = self.catalog.get_agent_bans().await?;
+        *self.bans.write() = bans;
+
+        Ok(())
+    }
+
+    pub async fn ban(&self, ban: AgentBan) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.catalog.add_agent_ban(ban).await?;
+
+        self.refresh().await
+    }
+
+    /// ## Returns
+    /// * `Ok(bool)` - Whether there was a ban with this id
+    pub async fn unban(&self, id: &str) -> Result<bool, Box<dyn std::error::Error + Send>> {
+        let removed = self.catalog.remove_agent_ban(id).await?;
+        self.refresh().await?;
+
+        Ok(removed)
+    }
+
+    /// The ban of the agent with these selectors, if it is banned.
+    #[must_use]
+    pub fn banned(&self, agent_selectors: &BTreeSet<String>) -> Option<AgentBan> {
+        self.bans
+            .read()
+            .iter()
+            .find(|ban| ban.matches(agent_selectors))
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::inmemory;
+
+    use super::*;
+
+    fn ban(id: &str, selectors: &[&str]) -> AgentBan {
+        AgentBan {
+            id: id.to_string(),
+            selectors: selectors.iter().map(ToString::to_string).collect(),
+            reason: None,
+            created_at: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn bans_apply_after_refresh() {
+        let catalog = Arc::new(inmemory::Catalog::new());
+        let agent_bans = AgentBans::new(catalog.clone());
+        let agent = BTreeSet::from([
+            "AGENTNODENAME:node1".to_string(),
+            "CLUSTER:cluster".to_string(),
+        ]);
+
+        agent_bans
+            .ban(ban("node1", &["AGENTNODENAME:node1"]))
+            .await
+            .unwrap();
+        assert_eq!(agent_bans.banned(&agent).unwrap().id, "node1");
+
+        // Added by another replica.
+        catalog.add_agent_ban(ban("empty", &[])).await.unwrap();
+        catalog.remove_agent_ban("node1").await.unwrap();
+        assert!(agent_bans.banned(&agent).is_some());
+        agent_bans.refresh().await.unwrap();
+        assert!(agent_bans.banned(&agent).is_none());
+
+        assert!(agent_bans.unban("empty").await.unwrap());
+        assert!(!agent_bans.unban("empty").await.unwrap());
+    }
+}
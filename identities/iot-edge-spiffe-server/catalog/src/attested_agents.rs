@@ -0,0 +1,162 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use core_objects::AttestedAgent;
+use parking_lot::Mutex;
+
+use crate::{Catalog, Error};
+
+// Agents attest on every request, the catalog is only written when what it has is older than this.
+const RECORD_PERIOD_SECS: u64 = 60;
+
+#[derive(Clone, Copy)]
+struct Recorded {
+    last_seen: u64,
+    svid_expiry: u64,
+}
+
+/// Attested agents, stored in the catalog so the agents of every replica are listed. The `last_seen` and
+/// `svid_expiry` of an agent are at most a minute behind.
+pub struct AttestedAgents {
+    catalog: Arc<dyn Catalog>,
+    // What this replica last wrote for each agent.
+    recorded: Mutex<HashMap<String, Recorded>>,
+    unsupported: AtomicBool,
+}
+
+impl AttestedAgents {
+    #[must_use]
+    pub fn new(catalog: Arc<dyn Catalog>) -> Self {
+        AttestedAgents {
+            catalog,
+            recorded: Mutex::new(HashMap::new()),
+            unsupported: AtomicBool::new(false),
+        }
+    }
+
+    /// Id of the agent with these selectors: SHA-256 of the selectors, in hex.
+    #[must_use]
+    pub fn agent_id(selectors: &BTreeSet<String>) -> String {
+        let mut hasher = openssl::sha::Sha256::new();
+        for selector in selectors {
+            hasher.update(selector.as_bytes());
+            hasher.update(b"\n");
+        }
+
+        hasher
+            .finish()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Record that the agent with these selectors was seen at `now`. `svid_expiry` is the expiry of the
+    /// JWT-SVIDs just issued through the agent, 0 if none were issued. Catalogs without attested agents are
+    /// ignored.
+    pub async fn record(
+        &self,
+        selectors: &BTreeSet<String>,
+        svid_expiry: u64,
+        now: u64,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        if self.unsupported.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let id = Self::agent_id(selectors);
+        let (previous, svid_expiry) = {
+            let mut recorded = self.recorded.lock();
+            let previous = recorded.get(&id).copied();
+
+            if let Some(previous) = previous {
+                if now < previous.last_seen.saturating_add(RECORD_PERIOD_SECS)
+                    && svid_expiry < previous.svid_expiry.saturating_add(RECORD_PERIOD_SECS)
+                {
+                    return Ok(());
+                }
+            }
+
+            let svid_expiry = previous.map_or(svid_expiry, |previous| {
+                previous.svid_expiry.max(svid_expiry)
+            });
+            recorded.insert(
+                id.clone(),
+                Recorded {
+                    last_seen: now,
+                    svid_expiry,
+                },
+            );
+
+            (previous, svid_expiry)
+        };
+
+        let agent = AttestedAgent {
+            id: id.clone(),
+            selectors: selectors.clone(),
+            last_seen: now,
+            svid_expiry,
+        };
+
+        match self.catalog.record_attested_agent(agent).await {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                if let Some(Error::Unsupported(_)) = err.downcast_ref::<Error>() {
+                    log::info!("Attested agents are not supported by the catalog backend");
+                    self.unsupported.store(true, Ordering::Relaxed);
+                    return Ok(());
+                }
+
+                // Written again on the next attestation.
+                let mut recorded = self.recorded.lock();
+                match previous {
+                    Some(previous) => recorded.insert(id, previous),
+                    None => recorded.remove(&id),
+                };
+
+                Err(err)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::inmemory;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn records_are_throttled() {
+        let catalog = Arc::new(inmemory::Catalog::new());
+        let attested_agents = AttestedAgents::new(catalog.clone());
+        let selectors = BTreeSet::from(["AGENTNODENAME:node1".to_string()]);
+
+        attested_agents.record(&selectors, 0, 1000).await.unwrap();
+        attested_agents.record(&selectors, 0, 1010).await.unwrap();
+        let (agents, _) = catalog.list_attested_agents(None, 10).await.unwrap();
+        assert_eq!(agents.len(), 1);
+        assert_eq!(agents[0].id, AttestedAgents::agent_id(&selectors));
+        assert_eq!(agents[0].last_seen, 1000);
+
+        // A new SVID expiry is written right away.
+        attested_agents
+            .record(&selectors, 4600, 1020)
+            .await
+            .unwrap();
+        let (agents, _) = catalog.list_attested_agents(None, 10).await.unwrap();
+        assert_eq!(agents[0].last_seen, 1020);
+        assert_eq!(agents[0].svid_expiry, 4600);
+
+        attested_agents.record(&selectors, 0, 1080).await.unwrap();
+        let (agents, _) = catalog.list_attested_agents(None, 10).await.unwrap();
+        assert_eq!(agents[0].last_seen, 1080);
+        assert_eq!(agents[0].svid_expiry, 4600);
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Storage of the audit logs: JSON records appended to a file of this replica, or the catalog.
+
+use std::{io, path::PathBuf, sync::Arc};
+
+use serde::{de::DeserializeOwned, Serialize};
+use server_config::AuditLogConfig;
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
+
+use crate::{pagination::split_page, Catalog, Error};
+
+pub(crate) enum Sink {
+    // Appends of concurrent requests are serialized, so the lines are not interleaved.
+    File { path: PathBuf, lock: Mutex<()> },
+    Catalog(Arc<dyn Catalog>),
+}
+
+impl Sink {
+    pub(crate) fn new(config: &AuditLogConfig, catalog: Arc<dyn Catalog>) -> Self {
+        match config {
+            AuditLogConfig::File(config) => Sink::File {
+                path: PathBuf::from(&config.path),
+                lock: Mutex::new(()),
+            },
+            AuditLogConfig::Catalog => Sink::Catalog(catalog),
+        }
+    }
+}
+
+// Id of a record made at `time`: the time, zero padded so the ids sort by time, and random hex.
+pub(crate) fn record_id(time: u64) -> String {
+    let mut random = [0; 8];
+    openssl::rand::rand_bytes(&mut random).expect("cannot fail to generate random bytes");
+    let random: String = random.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+    format!("{:020}-{}", time, random)
+}
+
+// One JSON record per line.
+pub(crate) async fn append<T: Serialize>(
+    path: &PathBuf,
+    lock: &Mutex<()>,
+    records: &[T],
+) -> Result<(), Box<dyn std::error::Error + Send>> {
+    let mut lines = Vec::new();
+    for record in records {
+        serde_json::to_writer(&mut lines, record).map_err(|err| Box::new(err) as _)?;
+        lines.push(b'\n');
+    }
+
+    let _lock = lock.lock().await;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(|err| Box::new(err) as _)?;
+    file.write_all(&lines)
+        .await
+        .map_err(|err| Box::new(err) as _)?;
+
+    // The callers only go on once their records are on disk.
+    file.sync_data().await.map_err(|err| Box::new(err) as _)
+}
+
+// The page of the records of the file matching `filter`, ordered by id. The file is read whole for every page.
+pub(crate) async fn read_page<T: DeserializeOwned>(
+    path: &PathBuf,
+    page_token: Option<String>,
+    page_size: usize,
+    id: impl Fn(&T) -> &str,
+    filter: impl Fn(&T) -> bool,
+) -> Result<(Vec<T>, Option<String>), Box<dyn std::error::Error + Send>> {
+    if page_size == 0 {
+        return Err(Box::new(Error::InvalidPageSize));
+    }
+
+    let records = match fs::read_to_string(path).await {
+        Ok(records) => records,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(Box::new(err)),
+    };
+
+    let page_token = page_token.unwrap_or_default();
+    let mut rows = Vec::new();
+    for line in records.lines().filter(|line| !line.is_empty()) {
+        let record: T = serde_json::from_str(line).map_err(|err| Box::new(err) as _)?;
+        if id(&record) >= page_token.as_str() && filter(&record) {
+            rows.push((id(&record).to_string(), record));
+        }
+    }
+    rows.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let (rows, page_token) = split_page(rows, page_size);
+    Ok((
+        rows.into_iter().map(|(_id, record)| record).collect(),
+        page_token,
+    ))
+}
@@ -0,0 +1,49 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use core_objects::AgentBan;
+use etcd_client::GetOptions;
+
+use super::{error::Error, Catalog};
+
+impl Catalog {
+    pub(super) async fn add_agent_ban_inner(&self, ban: &AgentBan) -> Result<(), Error> {
+        let serialized_ban = serde_json::to_string(ban).map_err(Error::Serialize)?;
+        let mut client = self.client().await?;
+
+        client
+            .put(self.agent_ban_key(&ban.id), serialized_ban, None)
+            .await
+            .map_err(Error::Request)?;
+
+        Ok(())
+    }
+
+    pub(super) async fn remove_agent_ban_inner(&self, id: &str) -> Result<bool, Error> {
+        let mut client = self.client().await?;
+
+        let response = client
+            .delete(self.agent_ban_key(id), None)
+            .await
+            .map_err(Error::Request)?;
+
+        Ok(response.deleted() > 0)
+    }
+
+    pub(super) async fn get_agent_bans_inner(&self) -> Result<Vec<AgentBan>, Error> {
+        let mut client = self.client().await?;
+
+        let response = client
+            .get(
+                self.agent_bans_prefix(),
+                Some(GetOptions::new().with_prefix()),
+            )
+            .await
+            .map_err(Error::Request)?;
+
+        response
+            .kvs()
+            .iter()
+            .map(|kv| serde_json::from_slice(kv.value()).map_err(Error::Deserialize))
+            .collect()
+    }
+}
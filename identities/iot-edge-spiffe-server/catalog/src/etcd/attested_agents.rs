@@ -0,0 +1,93 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use core_objects::AttestedAgent;
+use etcd_client::{Compare, CompareOp, GetOptions, Txn, TxnOp};
+
+use crate::pagination::split_page;
+
+use super::{error::Error, prefix_range_end, Catalog};
+
+impl Catalog {
+    // Replicas may record the same agent concurrently, the write only applies if the agent was not changed
+    // since it was read so the latest SVID expiry is kept.
+    pub(super) async fn record_attested_agent_inner(
+        &self,
+        mut agent: AttestedAgent,
+    ) -> Result<(), Error> {
+        let mut client = self.client().await?;
+        let key = self.attested_agent_key(&agent.id);
+        let svid_expiry = agent.svid_expiry;
+
+        loop {
+            let response = client
+                .get(key.clone(), None)
+                .await
+                .map_err(Error::Request)?;
+
+            // A key that does not exist has a mod revision of 0.
+            let mod_revision = match response.kvs().first() {
+                Some(kv) => {
+                    let previous: AttestedAgent =
+                        serde_json::from_slice(kv.value()).map_err(Error::Deserialize)?;
+                    agent.svid_expiry = svid_expiry.max(previous.svid_expiry);
+
+                    kv.mod_revision()
+                }
+                None => 0,
+            };
+
+            let serialized_agent = serde_json::to_string(&agent).map_err(Error::Serialize)?;
+            let txn = Txn::new()
+                .when(vec![Compare::mod_revision(
+                    key.clone(),
+                    CompareOp::Equal,
+                    mod_revision,
+                )])
+                .and_then(vec![TxnOp::put(key.clone(), serialized_agent, None)]);
+
+            let response = client.txn(txn).await.map_err(Error::Request)?;
+            if response.succeeded() {
+                return Ok(());
+            }
+        }
+    }
+
+    pub(super) async fn list_attested_agents_inner(
+        &self,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<AttestedAgent>, Option<String>), Error> {
+        if page_size == 0 {
+            return Err(Error::InvalidPageSize());
+        }
+
+        let mut client = self.client().await?;
+        let agents_prefix = self.attested_agents_prefix();
+        let start = self.attested_agent_key(page_token.as_deref().unwrap_or_default());
+        let limit = i64::try_from(page_size)
+            .unwrap_or(i64::MAX)
+            .saturating_add(1);
+
+        let options = GetOptions::new()
+            .with_range(prefix_range_end(&agents_prefix))
+            .with_limit(limit);
+        let response = client
+            .get(start, Some(options))
+            .await
+            .map_err(Error::Request)?;
+
+        let rows = response
+            .kvs()
+            .iter()
+            .map(|kv| {
+                let agent: AttestedAgent =
+                    serde_json::from_slice(kv.value()).map_err(Error::Deserialize)?;
+                Ok((agent.id.clone(), agent))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let (rows, page_token) = split_page(rows, page_size);
+        let agents = rows.into_iter().map(|(_id, agent)| agent).collect();
+
+        Ok((agents, page_token))
+    }
+}
@@ -0,0 +1,651 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashSet;
+
+use core_objects::{get_epoch_time, RegistrationEntry};
+use etcd_client::{
+    Client, Compare, CompareOp, EventType, GetOptions, KeyValue, PutOptions, Txn, TxnOp,
+    WatchOptions, WatchResponse,
+};
+use futures_util::{stream, StreamExt};
+
+use crate::{
+    abort_transaction, pagination::split_page, Entries, EntryEvent, EntryEventStream,
+    Error as CatalogError,
+};
+
+use super::{error::Error, prefix_range_end, Catalog};
+
+type BatchErrors = Vec<(String, Box<dyn std::error::Error + Send>)>;
+type BoxedError = Box<dyn std::error::Error + Send>;
+
+#[async_trait::async_trait]
+impl Entries for Catalog {
+    async fn batch_create(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        let ids = entries
+            .iter()
+            .map(|entry| entry.id.clone())
+            .collect::<Vec<_>>();
+
+        // Create only if the key was never created (or was deleted since).
+        let puts = entries
+            .into_iter()
+            .map(|entry| {
+                let compare =
+                    Compare::create_revision(self.entry_key(&entry.id), CompareOp::Equal, 0);
+                let error = Box::new(Error::DuplicatedEntry(entry.id.clone())) as BoxedError;
+
+                (entry, compare, error)
+            })
+            .collect();
+
+        let errors = self
+            .batch_put(puts)
+            .await
+            .map_err(|err| abort_batch(&ids, &err))?;
+
+        errors.is_empty().then(|| ()).ok_or(errors)
+    }
+
+    async fn batch_update(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        let ids = entries
+            .iter()
+            .map(|entry| entry.id.clone())
+            .collect::<Vec<_>>();
+
+        let errors = self
+            .batch_update_inner(entries)
+            .await
+            .map_err(|err| abort_batch(&ids, &err))?;
+
+        errors.is_empty().then(|| ()).ok_or(errors)
+    }
+
+    async fn batch_delete(
+        &self,
+        ids: &[String],
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        let errors = self
+            .batch_delete_inner(ids)
+            .await
+            .map_err(|err| abort_batch(ids, &err))?;
+
+        errors.is_empty().then(|| ()).ok_or(errors)
+    }
+
+    async fn batch_create_transactional(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        let ids = entries
+            .iter()
+            .map(|entry| entry.id.clone())
+            .collect::<Vec<_>>();
+
+        let puts = entries
+            .into_iter()
+            .map(|entry| {
+                let compare =
+                    Compare::create_revision(self.entry_key(&entry.id), CompareOp::Equal, 0);
+                let error = Box::new(Error::DuplicatedEntry(entry.id.clone())) as BoxedError;
+
+                (entry, compare, error)
+            })
+            .collect();
+
+        let errors = self
+            .transactional_put(Vec::new(), puts)
+            .await
+            .map_err(|err| abort_batch(&ids, &err))?;
+
+        errors
+            .is_empty()
+            .then(|| ())
+            .ok_or_else(|| abort_transaction(&ids, errors))
+    }
+
+    async fn batch_update_transactional(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        let ids = entries
+            .iter()
+            .map(|entry| entry.id.clone())
+            .collect::<Vec<_>>();
+
+        let errors = async {
+            let (errors, puts) = self.prepare_updates(entries).await?;
+
+            self.transactional_put(errors, puts).await
+        }
+        .await
+        .map_err(|err| abort_batch(&ids, &err))?;
+
+        errors
+            .is_empty()
+            .then(|| ())
+            .ok_or_else(|| abort_transaction(&ids, errors))
+    }
+
+    async fn batch_delete_transactional(
+        &self,
+        ids: &[String],
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        let operations = ids
+            .iter()
+            .map(|id| {
+                let key = self.entry_key(id);
+                let compare = Compare::version(key.clone(), CompareOp::Greater, 0);
+                let error = Box::new(Error::EntryNotFound(id.clone())) as BoxedError;
+
+                (id.clone(), compare, TxnOp::delete(key, None), error)
+            })
+            .collect();
+
+        let errors = self
+            .commit_txn(operations)
+            .await
+            .map_err(|err| abort_batch(ids, &err))?;
+
+        errors
+            .is_empty()
+            .then(|| ())
+            .ok_or_else(|| abort_transaction(ids, errors))
+    }
+
+    async fn batch_get(
+        &self,
+        ids: &[String],
+    ) -> Vec<(
+        String,
+        Result<RegistrationEntry, Box<dyn std::error::Error + Send>>,
+    )> {
+        let mut results = Vec::new();
+
+        for id in ids {
+            let result = self
+                .get_entry_inner(id)
+                .await
+                .map_err(|err| Box::new(err) as _);
+
+            results.push((id.clone(), result));
+        }
+
+        results
+    }
+
+    async fn get_entry(
+        &self,
+        id: &str,
+    ) -> Result<RegistrationEntry, Box<dyn std::error::Error + Send>> {
+        self.get_entry_inner(id)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn list_all(
+        &self,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<RegistrationEntry>, Option<String>), Box<dyn std::error::Error + Send>> {
+        if page_size == 0 {
+            return Err(Box::new(Error::InvalidPageSize()));
+        }
+
+        self.list_all_inner(page_token, page_size)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn watch(&self) -> Result<EntryEventStream, Box<dyn std::error::Error + Send>> {
+        self.watch_inner().await.map_err(|err| Box::new(err) as _)
+    }
+}
+
+impl Catalog {
+    // Put each entry if its comparison holds, otherwise report the error paired with it.
+    async fn batch_put(
+        &self,
+        puts: Vec<(RegistrationEntry, Compare, BoxedError)>,
+    ) -> Result<BatchErrors, Error> {
+        let mut client = self.client().await?;
+        let mut errors = Vec::new();
+
+        for (entry, compare, precondition_error) in puts {
+            let serialized_entry = match serde_json::to_string(&entry) {
+                Ok(serialized_entry) => serialized_entry,
+                Err(err) => {
+                    errors.push((entry.id, Box::new(Error::Serialize(err)) as _));
+                    continue;
+                }
+            };
+
+            let put_options = match lease(&mut client, &entry).await {
+                Ok(put_options) => put_options,
+                Err(Error::EntryExpired(id)) => {
+                    errors.push((entry.id, Box::new(Error::EntryExpired(id)) as _));
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            let key = self.entry_key(&entry.id);
+            let txn = Txn::new().when(vec![compare]).and_then(vec![TxnOp::put(
+                key,
+                serialized_entry,
+                put_options,
+            )]);
+
+            // If the precondition fails, the lease granted above is not attached to anything and expires on its own.
+            let response = client.txn(txn).await.map_err(Error::Request)?;
+
+            if !response.succeeded() {
+                errors.push((entry.id, precondition_error));
+            }
+        }
+
+        Ok(errors)
+    }
+
+    async fn batch_update_inner(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<BatchErrors, Error> {
+        let (mut errors, puts) = self.prepare_updates(entries).await?;
+
+        errors.extend(self.batch_put(puts).await?);
+
+        Ok(errors)
+    }
+
+    // Check the revision of the entries to update. The entries that pass are returned with the comparison
+    // that guards their put.
+    async fn prepare_updates(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(BatchErrors, Vec<(RegistrationEntry, Compare, BoxedError)>), Error> {
+        let mut client = self.client().await?;
+        let mut errors = Vec::new();
+        let mut puts = Vec::new();
+
+        for mut entry in entries {
+            let key = self.entry_key(&entry.id);
+            let response = client
+                .get(key.clone(), None)
+                .await
+                .map_err(Error::Request)?;
+
+            let kv = if let Some(kv) = response.kvs().first() {
+                kv
+            } else {
+                let error = (
+                    entry.id.clone(),
+                    Box::new(Error::EntryNotFound(entry.id)) as _,
+                );
+
+                errors.push(error);
+                continue;
+            };
+
+            let current: RegistrationEntry = match serde_json::from_slice(kv.value()) {
+                Ok(current) => current,
+                Err(err) => {
+                    errors.push((entry.id, Box::new(Error::Deserialize(err)) as _));
+                    continue;
+                }
+            };
+
+            let conflict = Box::new(CatalogError::RevisionConflict {
+                id: entry.id.clone(),
+                revision_number: entry.revision_number,
+            }) as BoxedError;
+
+            if current.revision_number != entry.revision_number {
+                errors.push((entry.id, conflict));
+                continue;
+            }
+
+            // Another server may update the entry between the get and the put, the put only succeeds if the
+            // key was not modified since it was read.
+            entry.revision_number += 1;
+            let compare = Compare::mod_revision(key, CompareOp::Equal, kv.mod_revision());
+
+            puts.push((entry, compare, conflict));
+        }
+
+        Ok((errors, puts))
+    }
+
+    // Put all the entries in a single transaction, or none of them if an entry failed.
+    async fn transactional_put(
+        &self,
+        mut errors: BatchErrors,
+        puts: Vec<(RegistrationEntry, Compare, BoxedError)>,
+    ) -> Result<BatchErrors, Error> {
+        let mut client = self.client().await?;
+        let mut operations = Vec::new();
+
+        for (entry, compare, precondition_error) in puts {
+            let serialized_entry = match serde_json::to_string(&entry) {
+                Ok(serialized_entry) => serialized_entry,
+                Err(err) => {
+                    errors.push((entry.id, Box::new(Error::Serialize(err)) as _));
+                    continue;
+                }
+            };
+
+            let put_options = match lease(&mut client, &entry).await {
+                Ok(put_options) => put_options,
+                Err(Error::EntryExpired(id)) => {
+                    errors.push((entry.id, Box::new(Error::EntryExpired(id)) as _));
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            let key = self.entry_key(&entry.id);
+            let put = TxnOp::put(key, serialized_entry, put_options);
+
+            operations.push((entry.id, compare, put, precondition_error));
+        }
+
+        // The leases granted above are not attached to anything and expire on their own.
+        if !errors.is_empty() {
+            return Ok(errors);
+        }
+
+        self.commit_txn(operations).await
+    }
+
+    // Apply all the operations in one etcd transaction, guarded by all their comparisons. An id that appears
+    // twice fails with its precondition error, like its second operation would after the first one.
+    async fn commit_txn(
+        &self,
+        operations: Vec<(String, Compare, TxnOp, BoxedError)>,
+    ) -> Result<BatchErrors, Error> {
+        let mut errors = Vec::new();
+        let mut ids = HashSet::new();
+        let mut compares = Vec::new();
+        let mut ops = Vec::new();
+        let mut checks = Vec::new();
+
+        for (id, compare, op, precondition_error) in operations {
+            if !ids.insert(id.clone()) {
+                errors.push((id, precondition_error));
+                continue;
+            }
+
+            compares.push(compare.clone());
+            ops.push(op);
+            checks.push((id, compare, precondition_error));
+        }
+
+        if !errors.is_empty() {
+            return Ok(errors);
+        }
+
+        let mut client = self.client().await?;
+        let response = client
+            .txn(Txn::new().when(compares).and_then(ops))
+            .await
+            .map_err(Error::Request)?;
+
+        if response.succeeded() {
+            return Ok(errors);
+        }
+
+        // Nothing was applied. Check the comparisons one by one to report the entries that failed.
+        for (id, compare, precondition_error) in checks {
+            let response = client
+                .txn(Txn::new().when(vec![compare]))
+                .await
+                .map_err(Error::Request)?;
+
+            if !response.succeeded() {
+                errors.push((id, precondition_error));
+            }
+        }
+
+        // The entries were changed back since the transaction failed.
+        if errors.is_empty() {
+            return Err(Error::TransactionConflict);
+        }
+
+        Ok(errors)
+    }
+
+    async fn batch_delete_inner(&self, ids: &[String]) -> Result<BatchErrors, Error> {
+        let mut client = self.client().await?;
+        let mut errors = Vec::new();
+
+        for id in ids {
+            let response = client
+                .delete(self.entry_key(id), None)
+                .await
+                .map_err(Error::Request)?;
+
+            if response.deleted() == 0 {
+                let error = (
+                    id.clone(),
+                    Box::new(Error::EntryNotFound(id.to_string())) as _,
+                );
+
+                errors.push(error);
+            }
+        }
+
+        Ok(errors)
+    }
+
+    async fn get_entry_inner(&self, id: &str) -> Result<RegistrationEntry, Error> {
+        let mut client = self.client().await?;
+
+        let response = client
+            .get(self.entry_key(id), None)
+            .await
+            .map_err(Error::Request)?;
+
+        let kv = response
+            .kvs()
+            .first()
+            .ok_or_else(|| Error::EntryNotFound(id.to_string()))?;
+
+        serde_json::from_slice(kv.value()).map_err(Error::Deserialize)
+    }
+
+    async fn list_all_inner(
+        &self,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<RegistrationEntry>, Option<String>), Error> {
+        let mut client = self.client().await?;
+
+        let entries_prefix = self.entries_prefix();
+        let start = self.entry_key(page_token.as_deref().unwrap_or_default());
+        let limit = i64::try_from(page_size)
+            .unwrap_or(i64::MAX)
+            .saturating_add(1);
+
+        // etcd returns the range sorted by key, which matches the ordering of the in memory catalog.
+        let options = GetOptions::new()
+            .with_range(prefix_range_end(&entries_prefix))
+            .with_limit(limit);
+        let response = client
+            .get(start, Some(options))
+            .await
+            .map_err(Error::Request)?;
+
+        let mut rows = Vec::new();
+        for kv in response.kvs() {
+            let key = kv.key_str().map_err(Error::InvalidKey)?;
+            let id = entry_id(key, &entries_prefix)?.to_string();
+
+            rows.push((id, kv.value()));
+        }
+
+        let (rows, page_token) = split_page(rows, page_size);
+
+        let entries = rows
+            .into_iter()
+            .map(|(_id, entry)| serde_json::from_slice(entry).map_err(Error::Deserialize))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok((entries, page_token))
+    }
+
+    // Expired entries are reported as deleted, etcd deletes them when their lease expires.
+    async fn watch_inner(&self) -> Result<EntryEventStream, Error> {
+        let mut client = self.client().await?;
+        let entries_prefix = self.entries_prefix();
+
+        let (watcher, watch_stream) = client
+            .watch(
+                entries_prefix.clone(),
+                Some(WatchOptions::new().with_prefix()),
+            )
+            .await
+            .map_err(Error::Request)?;
+
+        let events = watch_stream
+            .map(move |response| {
+                // etcd cancels the watch when the watcher is dropped, it has to live as long as the stream.
+                let _watcher = &watcher;
+
+                match response {
+                    Ok(response) => entry_events(&response, &entries_prefix),
+                    Err(err) => vec![Err(Box::new(Error::Request(err)) as BoxedError)],
+                }
+            })
+            .flat_map(stream::iter);
+
+        Ok(Box::pin(events))
+    }
+}
+
+fn entry_events(
+    response: &WatchResponse,
+    entries_prefix: &str,
+) -> Vec<Result<EntryEvent, BoxedError>> {
+    // A canceled watch (e.g. the revision was compacted) ends without the events that were missed.
+    if response.canceled() {
+        let error = Error::WatchCanceled(response.cancel_reason().to_string());
+
+        return vec![Err(Box::new(error) as BoxedError)];
+    }
+
+    response
+        .events()
+        .iter()
+        .filter_map(|event| {
+            event
+                .kv()
+                .map(|kv| entry_event(event.event_type(), kv, entries_prefix))
+        })
+        .map(|event| event.map_err(|err| Box::new(err) as BoxedError))
+        .collect()
+}
+
+fn entry_event(
+    event_type: EventType,
+    kv: &KeyValue,
+    entries_prefix: &str,
+) -> Result<EntryEvent, Error> {
+    match event_type {
+        EventType::Put => {
+            let entry = serde_json::from_slice(kv.value()).map_err(Error::Deserialize)?;
+
+            // The create revision of a key is reset when it is deleted and put again.
+            if kv.create_revision() == kv.mod_revision() {
+                Ok(EntryEvent::Created(entry))
+            } else {
+                Ok(EntryEvent::Updated(entry))
+            }
+        }
+        EventType::Delete => {
+            let key = kv.key_str().map_err(Error::InvalidKey)?;
+
+            Ok(EntryEvent::Deleted(
+                entry_id(key, entries_prefix)?.to_string(),
+            ))
+        }
+    }
+}
+
+// Id of the entry of a key. Only the entries prefix is read, a key outside of it is an error.
+fn entry_id<'a>(key: &'a str, entries_prefix: &str) -> Result<&'a str, Error> {
+    key.strip_prefix(entries_prefix)
+        .ok_or_else(|| Error::ForeignKey(key.to_string()))
+}
+
+// Entries with an expiry are attached to a lease so etcd removes them when they expire.
+async fn lease(
+    client: &mut Client,
+    entry: &RegistrationEntry,
+) -> Result<Option<PutOptions>, Error> {
+    if entry.expires_at == 0 {
+        return Ok(None);
+    }
+
+    let ttl = lease_ttl(entry.expires_at, get_epoch_time())
+        .ok_or_else(|| Error::EntryExpired(entry.id.clone()))?;
+
+    let lease = client
+        .lease_grant(ttl, None)
+        .await
+        .map_err(Error::Request)?;
+
+    Ok(Some(PutOptions::new().with_lease(lease.id())))
+}
+
+fn lease_ttl(expires_at: u64, now: u64) -> Option<i64> {
+    expires_at
+        .checked_sub(now)
+        .filter(|ttl| *ttl > 0)
+        .map(|ttl| i64::try_from(ttl).unwrap_or(i64::MAX))
+}
+
+// The whole batch failed, report the cause against every id so the caller gets one result per input.
+fn abort_batch(ids: &[String], err: &Error) -> BatchErrors {
+    ids.iter()
+        .map(|id| {
+            (
+                id.clone(),
+                Box::new(Error::BatchAborted(err.to_string())) as _,
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_id_strips_prefix_once() {
+        assert_eq!(entry_id("e4k/entries/id", "e4k/entries/").unwrap(), "id");
+        assert_eq!(
+            entry_id("e4k/entries/e4k/entries/id", "e4k/entries/").unwrap(),
+            "e4k/entries/id"
+        );
+
+        let error = entry_id("e4k/jwks/id", "e4k/entries/").unwrap_err();
+        assert!(matches!(error, Error::ForeignKey(key) if key == "e4k/jwks/id"));
+    }
+
+    #[test]
+    fn lease_ttl_until_expiry() {
+        assert_eq!(lease_ttl(110, 100), Some(10));
+    }
+
+    #[test]
+    fn lease_ttl_expired_entry() {
+        assert_eq!(lease_ttl(100, 100), None);
+        assert_eq!(lease_ttl(90, 100), None);
+    }
+}
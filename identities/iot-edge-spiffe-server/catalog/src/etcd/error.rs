@@ -0,0 +1,39 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Entry {0} already exists")]
+    DuplicatedEntry(String),
+    #[error("Entry {0} does not exist")]
+    EntryNotFound(String),
+    #[error("Entry {0} is already expired")]
+    EntryExpired(String),
+    #[error("Key {0} already exists")]
+    DuplicatedKey(String),
+    #[error("Key {0} does not exist")]
+    KeyNotFound(String),
+    #[error("Invalid page size")]
+    InvalidPageSize(),
+    #[error("Could not connect to etcd {0}")]
+    Connect(etcd_client::Error),
+    #[error("Etcd request failed {0}")]
+    Request(etcd_client::Error),
+    #[error("Etcd returned a key that is not valid utf8 {0}")]
+    InvalidKey(etcd_client::Error),
+    #[error("Etcd returned key {0} outside of the entries")]
+    ForeignKey(String),
+    #[error("Could not serialize {0}")]
+    Serialize(serde_json::Error),
+    #[error("Could not deserialize {0}")]
+    Deserialize(serde_json::Error),
+    #[error("The entries of the transaction were modified concurrently")]
+    TransactionConflict,
+    #[error("Batch aborted {0}")]
+    BatchAborted(String),
+    #[error("Trust bundle version {0} is invalid")]
+    InvalidVersion(String),
+    #[error("Etcd canceled the watch {0}")]
+    WatchCanceled(String),
+}
@@ -0,0 +1,195 @@
+// Copyright (c) Microsoft. All rights reserved.
+mod agent_bans;
+mod attested_agents;
+mod entries;
+mod error;
+mod trust_bundle_store;
+
+use core_objects::{AgentBan, AttestedAgent};
+use etcd_client::Client;
+use server_config::CatalogConfigEtcd;
+use tokio::sync::OnceCell;
+
+use crate::Catalog as CatalogTrait;
+
+use error::Error;
+
+// Catalog backed by etcd, so several server replicas can share the same state inside the cluster.
+// Entries with an expiry are attached to an etcd lease and are removed by etcd when they expire.
+//
+// Layout of the keys under the configured prefix:
+// <prefix>/entries/<entry id> -> json registration entry
+// <prefix>/jwks/<trust domain>/<kid> -> json jwk
+// <prefix>/jwk_versions/<trust domain> -> version of the trust domain jwk set
+// <prefix>/key_slots/<trust domain> -> json signing keys of the key manager
+// <prefix>/agent_bans/<ban id> -> json agent ban
+// <prefix>/attested_agents/<agent id> -> json attested agent
+pub struct Catalog {
+    endpoints: Vec<String>,
+    key_prefix: String,
+    client: OnceCell<Client>,
+}
+
+impl Catalog {
+    #[must_use]
+    pub fn new(config: &CatalogConfigEtcd) -> Self {
+        Catalog {
+            endpoints: config.endpoints.clone(),
+            key_prefix: config.key_prefix.trim_end_matches('/').to_string(),
+            client: OnceCell::new(),
+        }
+    }
+
+    // The connection is established on first use. The client is cheap to clone and multiplexes requests.
+    async fn client(&self) -> Result<Client, Error> {
+        let client = self
+            .client
+            .get_or_try_init(|| async {
+                Client::connect(&self.endpoints, None)
+                    .await
+                    .map_err(Error::Connect)
+            })
+            .await?;
+
+        Ok(client.clone())
+    }
+
+    fn entries_prefix(&self) -> String {
+        format!("{}/entries/", self.key_prefix)
+    }
+
+    fn entry_key(&self, id: &str) -> String {
+        format!("{}{}", self.entries_prefix(), id)
+    }
+
+    fn jwks_prefix(&self, trust_domain: &str) -> String {
+        format!("{}/jwks/{}/", self.key_prefix, trust_domain)
+    }
+
+    fn jwk_key(&self, trust_domain: &str, kid: &str) -> String {
+        format!("{}{}", self.jwks_prefix(trust_domain), kid)
+    }
+
+    fn jwk_version_key(&self, trust_domain: &str) -> String {
+        format!("{}/jwk_versions/{}", self.key_prefix, trust_domain)
+    }
+
+    fn key_slots_key(&self, trust_domain: &str) -> String {
+        format!("{}/key_slots/{}", self.key_prefix, trust_domain)
+    }
+
+    fn federated_bundles_prefix(&self) -> String {
+        format!("{}/federated_bundles/", self.key_prefix)
+    }
+
+    fn federated_bundle_key(&self, trust_domain: &str) -> String {
+        format!("{}{}", self.federated_bundles_prefix(), trust_domain)
+    }
+
+    fn agent_bans_prefix(&self) -> String {
+        format!("{}/agent_bans/", self.key_prefix)
+    }
+
+    fn agent_ban_key(&self, id: &str) -> String {
+        format!("{}{}", self.agent_bans_prefix(), id)
+    }
+
+    fn attested_agents_prefix(&self) -> String {
+        format!("{}/attested_agents/", self.key_prefix)
+    }
+
+    fn attested_agent_key(&self, id: &str) -> String {
+        format!("{}{}", self.attested_agents_prefix(), id)
+    }
+}
+
+// Smallest key strictly greater than every key starting with prefix, used as the end of range requests.
+fn prefix_range_end(prefix: &str) -> Vec<u8> {
+    let mut end = prefix.as_bytes().to_vec();
+
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return end;
+        }
+    }
+
+    // Every byte was 0xff, range until the end of the key space.
+    vec![0]
+}
+
+#[async_trait::async_trait]
+impl CatalogTrait for Catalog {
+    async fn backend_version(&self) -> Result<Option<String>, Box<dyn std::error::Error + Send>> {
+        let mut client = self.client().await.map_err(|err| Box::new(err) as _)?;
+
+        let status = client
+            .status()
+            .await
+            .map_err(|err| Box::new(Error::Request(err)) as _)?;
+
+        Ok(Some(format!("etcd {}", status.version())))
+    }
+
+    async fn add_agent_ban(&self, ban: AgentBan) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.add_agent_ban_inner(&ban)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn remove_agent_ban(&self, id: &str) -> Result<bool, Box<dyn std::error::Error + Send>> {
+        self.remove_agent_ban_inner(id)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn get_agent_bans(&self) -> Result<Vec<AgentBan>, Box<dyn std::error::Error + Send>> {
+        self.get_agent_bans_inner()
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn record_attested_agent(
+        &self,
+        agent: AttestedAgent,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.record_attested_agent_inner(agent)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn list_attested_agents(
+        &self,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<AttestedAgent>, Option<String>), Box<dyn std::error::Error + Send>> {
+        self.list_attested_agents_inner(page_token, page_size)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_range_end_increments_last_byte() {
+        assert_eq!(prefix_range_end("/e4k/entries/"), b"/e4k/entries0".to_vec());
+    }
+
+    #[test]
+    fn keys_are_built_under_prefix() {
+        let catalog = Catalog::new(&CatalogConfigEtcd {
+            endpoints: vec!["http://localhost:2379".to_string()],
+            key_prefix: "/e4k/".to_string(),
+        });
+
+        assert_eq!(catalog.entry_key("id"), "/e4k/entries/id");
+        assert_eq!(catalog.jwk_key("td", "kid"), "/e4k/jwks/td/kid");
+        assert_eq!(catalog.jwk_version_key("td"), "/e4k/jwk_versions/td");
+        assert_eq!(catalog.key_slots_key("td"), "/e4k/key_slots/td");
+        assert_eq!(catalog.agent_ban_key("id"), "/e4k/agent_bans/id");
+        assert_eq!(catalog.attested_agent_key("id"), "/e4k/attested_agents/id");
+    }
+}
@@ -0,0 +1,279 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use core_objects::{KeySlots, TrustBundle, JWK};
+use etcd_client::{Compare, CompareOp, GetOptions, Txn, TxnOp, TxnOpResponse};
+
+use crate::TrustBundleStore;
+
+use super::{error::Error, Catalog};
+
+#[async_trait::async_trait]
+impl TrustBundleStore for Catalog {
+    async fn add_jwk(
+        &self,
+        trust_domain: &str,
+        jwk: JWK,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        let serialized_jwk =
+            serde_json::to_string(&jwk).map_err(|err| Box::new(Error::Serialize(err)) as _)?;
+        let jwk_key = self.jwk_key(trust_domain, &jwk.kid);
+
+        let added = self
+            .update_jwk_set(trust_domain, &jwk_key, Some(serialized_jwk))
+            .await
+            .map_err(|err| Box::new(err) as _)?;
+
+        added
+            .then(|| ())
+            .ok_or_else(|| Box::new(Error::DuplicatedKey(jwk.kid)) as _)
+    }
+
+    async fn remove_jwk(
+        &self,
+        trust_domain: &str,
+        kid: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        let jwk_key = self.jwk_key(trust_domain, kid);
+
+        let removed = self
+            .update_jwk_set(trust_domain, &jwk_key, None)
+            .await
+            .map_err(|err| Box::new(err) as _)?;
+
+        removed
+            .then(|| ())
+            .ok_or_else(|| Box::new(Error::KeyNotFound(kid.to_string())) as _)
+    }
+
+    async fn get_jwk(
+        &self,
+        trust_domain: &str,
+    ) -> Result<(Vec<JWK>, usize), Box<dyn std::error::Error + Send>> {
+        self.get_jwk_inner(trust_domain)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn get_key_slots(
+        &self,
+        trust_domain: &str,
+    ) -> Result<Option<KeySlots>, Box<dyn std::error::Error + Send>> {
+        self.get_key_slots_inner(trust_domain)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn set_key_slots(
+        &self,
+        trust_domain: &str,
+        key_slots: KeySlots,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.set_key_slots_inner(trust_domain, &key_slots)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn set_federated_bundle(
+        &self,
+        trust_bundle: TrustBundle,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.set_federated_bundle_inner(&trust_bundle)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn get_federated_bundles(
+        &self,
+    ) -> Result<Vec<TrustBundle>, Box<dyn std::error::Error + Send>> {
+        self.get_federated_bundles_inner()
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+}
+
+impl Catalog {
+    // Add the jwk (Some) or remove it (None) and bump the trust domain version atomically.
+    // Returns false if the jwk already exists when adding, or does not exist when removing.
+    async fn update_jwk_set(
+        &self,
+        trust_domain: &str,
+        jwk_key: &str,
+        jwk: Option<String>,
+    ) -> Result<bool, Error> {
+        let mut client = self.client().await?;
+        let version_key = self.jwk_version_key(trust_domain);
+
+        loop {
+            let response = client
+                .get(version_key.clone(), None)
+                .await
+                .map_err(Error::Request)?;
+
+            // A key that does not exist has a mod revision of 0.
+            let (version, mod_revision) = match response.kvs().first() {
+                Some(kv) => (parse_version(kv.value())?, kv.mod_revision()),
+                None => (0, 0),
+            };
+
+            let (jwk_precondition, jwk_op) = if let Some(jwk) = &jwk {
+                (
+                    Compare::create_revision(jwk_key, CompareOp::Equal, 0),
+                    TxnOp::put(jwk_key, jwk.as_str(), None),
+                )
+            } else {
+                (
+                    Compare::create_revision(jwk_key, CompareOp::Greater, 0),
+                    TxnOp::delete(jwk_key, None),
+                )
+            };
+
+            let txn = Txn::new()
+                .when(vec![
+                    jwk_precondition,
+                    Compare::mod_revision(version_key.clone(), CompareOp::Equal, mod_revision),
+                ])
+                .and_then(vec![
+                    jwk_op,
+                    TxnOp::put(version_key.clone(), (version + 1).to_string(), None),
+                ])
+                .or_else(vec![TxnOp::get(jwk_key, None)]);
+
+            let response = client.txn(txn).await.map_err(Error::Request)?;
+
+            if response.succeeded() {
+                return Ok(true);
+            }
+
+            let jwk_exists = response.op_responses().into_iter().any(
+                |op_response| matches!(op_response, TxnOpResponse::Get(get) if !get.kvs().is_empty()),
+            );
+            if jwk_exists == jwk.is_some() {
+                return Ok(false);
+            }
+
+            // Another replica changed the jwk set concurrently, retry with the new version.
+        }
+    }
+
+    async fn get_jwk_inner(&self, trust_domain: &str) -> Result<(Vec<JWK>, usize), Error> {
+        let mut client = self.client().await?;
+
+        let response = client
+            .get(self.jwk_version_key(trust_domain), None)
+            .await
+            .map_err(Error::Request)?;
+        let version = match response.kvs().first() {
+            Some(kv) => parse_version(kv.value())?,
+            None => 0,
+        };
+
+        // Read the keys at the same revision as the version so both are consistent.
+        let mut options = GetOptions::new().with_prefix();
+        if let Some(header) = response.header() {
+            options = options.with_revision(header.revision());
+        }
+
+        let response = client
+            .get(self.jwks_prefix(trust_domain), Some(options))
+            .await
+            .map_err(Error::Request)?;
+
+        let jwks = response
+            .kvs()
+            .iter()
+            .map(|kv| serde_json::from_slice(kv.value()).map_err(Error::Deserialize))
+            .collect::<Result<Vec<JWK>, _>>()?;
+
+        Ok((jwks, version))
+    }
+
+    async fn get_key_slots_inner(&self, trust_domain: &str) -> Result<Option<KeySlots>, Error> {
+        let mut client = self.client().await?;
+
+        let response = client
+            .get(self.key_slots_key(trust_domain), None)
+            .await
+            .map_err(Error::Request)?;
+
+        response
+            .kvs()
+            .first()
+            .map(|kv| serde_json::from_slice(kv.value()).map_err(Error::Deserialize))
+            .transpose()
+    }
+
+    async fn set_key_slots_inner(
+        &self,
+        trust_domain: &str,
+        key_slots: &KeySlots,
+    ) -> Result<(), Error> {
+        let serialized_key_slots = serde_json::to_string(key_slots).map_err(Error::Serialize)?;
+        let mut client = self.client().await?;
+
+        client
+            .put(self.key_slots_key(trust_domain), serialized_key_slots, None)
+            .await
+            .map_err(Error::Request)?;
+
+        Ok(())
+    }
+
+    async fn set_federated_bundle_inner(&self, trust_bundle: &TrustBundle) -> Result<(), Error> {
+        let serialized_bundle = serde_json::to_string(trust_bundle).map_err(Error::Serialize)?;
+        let mut client = self.client().await?;
+
+        client
+            .put(
+                self.federated_bundle_key(&trust_bundle.trust_domain),
+                serialized_bundle,
+                None,
+            )
+            .await
+            .map_err(Error::Request)?;
+
+        Ok(())
+    }
+
+    // Keys are returned sorted, so the bundles are sorted by trust domain.
+    async fn get_federated_bundles_inner(&self) -> Result<Vec<TrustBundle>, Error> {
+        let mut client = self.client().await?;
+
+        let response = client
+            .get(
+                self.federated_bundles_prefix(),
+                Some(GetOptions::new().with_prefix()),
+            )
+            .await
+            .map_err(Error::Request)?;
+
+        response
+            .kvs()
+            .iter()
+            .map(|kv| serde_json::from_slice(kv.value()).map_err(Error::Deserialize))
+            .collect()
+    }
+}
+
+fn parse_version(value: &[u8]) -> Result<usize, Error> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|version| version.parse().ok())
+        .ok_or_else(|| Error::InvalidVersion(String::from_utf8_lossy(value).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::*;
+
+    #[test]
+    fn parse_version_happy_path() {
+        assert_eq!(parse_version(b"42").unwrap(), 42);
+    }
+
+    #[test]
+    fn parse_version_error_path() {
+        assert_matches!(parse_version(b"abc"), Err(Error::InvalidVersion(_)));
+    }
+}
@@ -0,0 +1,459 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Catalog wrapper injecting the faults set through the admin API, for resilience tests.
+// Only built with the `chaos` feature.
+
+use std::{collections::BTreeSet, sync::Arc};
+
+use ::chaos::Faults;
+use core_objects::{
+    AdminOperation, AgentBan, AttestedAgent, IssuedSvid, JWKSetVersion, KeySlots,
+    RegistrationEntry, TrustBundle, JWK,
+};
+
+use crate::{
+    AdminOperationFilter, Catalog as CatalogTrait, Entries, EntryEventStream, EntryFilter,
+    IssuedSvidFilter, TrustBundleStore,
+};
+
+type BatchErrors = Vec<(String, Box<dyn std::error::Error + Send>)>;
+
+pub struct Catalog {
+    catalog: Arc<dyn CatalogTrait>,
+    faults: Arc<Faults>,
+}
+
+impl Catalog {
+    #[must_use]
+    pub fn new(catalog: Arc<dyn CatalogTrait>, faults: Arc<Faults>) -> Self {
+        Catalog { catalog, faults }
+    }
+
+    async fn inject(&self) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.faults.inject().await.map_err(|err| Box::new(err) as _)
+    }
+
+    fn fail_all<'a>(&self, ids: impl Iterator<Item = &'a String>) -> BatchErrors {
+        ids.map(|id| (id.clone(), Box::new(self.faults.fault()) as _))
+            .collect()
+    }
+
+    // Split a batch between the items forwarded to the catalog and the ones failed by a partial
+    // failure.
+    fn split_batch<T>(&self, items: Vec<T>, id: impl Fn(&T) -> &String) -> (Vec<T>, BatchErrors) {
+        let mut forwarded = Vec::new();
+        let mut errors = Vec::new();
+
+        for (index, item) in items.into_iter().enumerate() {
+            if self.faults.fail_item(index) {
+                errors.push((id(&item).clone(), Box::new(self.faults.fault()) as _));
+            } else {
+                forwarded.push(item);
+            }
+        }
+
+        (forwarded, errors)
+    }
+}
+
+#[async_trait::async_trait]
+impl CatalogTrait for Catalog {
+    async fn backend_version(&self) -> Result<Option<String>, Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.backend_version().await
+    }
+
+    async fn add_agent_ban(&self, ban: AgentBan) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.add_agent_ban(ban).await
+    }
+
+    async fn remove_agent_ban(&self, id: &str) -> Result<bool, Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.remove_agent_ban(id).await
+    }
+
+    async fn get_agent_bans(&self) -> Result<Vec<AgentBan>, Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.get_agent_bans().await
+    }
+
+    async fn record_attested_agent(
+        &self,
+        agent: AttestedAgent,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.record_attested_agent(agent).await
+    }
+
+    async fn list_attested_agents(
+        &self,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<AttestedAgent>, Option<String>), Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog
+            .list_attested_agents(page_token, page_size)
+            .await
+    }
+
+    async fn record_issued_svids(
+        &self,
+        svids: Vec<IssuedSvid>,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.record_issued_svids(svids).await
+    }
+
+    async fn list_issued_svids(
+        &self,
+        filter: &IssuedSvidFilter,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<IssuedSvid>, Option<String>), Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog
+            .list_issued_svids(filter, page_token, page_size)
+            .await
+    }
+
+    async fn record_admin_operation(
+        &self,
+        operation: AdminOperation,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.record_admin_operation(operation).await
+    }
+
+    async fn list_admin_operations(
+        &self,
+        filter: &AdminOperationFilter,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<AdminOperation>, Option<String>), Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog
+            .list_admin_operations(filter, page_token, page_size)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl Entries for Catalog {
+    // Partial failures do not apply to transactional batches, they fail as a whole.
+    async fn batch_create_transactional(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), BatchErrors> {
+        if self.inject().await.is_err() {
+            return Err(self.fail_all(entries.iter().map(|entry| &entry.id)));
+        }
+
+        self.catalog.batch_create_transactional(entries).await
+    }
+
+    async fn batch_update_transactional(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), BatchErrors> {
+        if self.inject().await.is_err() {
+            return Err(self.fail_all(entries.iter().map(|entry| &entry.id)));
+        }
+
+        self.catalog.batch_update_transactional(entries).await
+    }
+
+    async fn batch_delete_transactional(&self, ids: &[String]) -> Result<(), BatchErrors> {
+        if self.inject().await.is_err() {
+            return Err(self.fail_all(ids.iter()));
+        }
+
+        self.catalog.batch_delete_transactional(ids).await
+    }
+
+    async fn batch_get(
+        &self,
+        ids: &[String],
+    ) -> Vec<(
+        String,
+        Result<RegistrationEntry, Box<dyn std::error::Error + Send>>,
+    )> {
+        if self.inject().await.is_err() {
+            return self
+                .fail_all(ids.iter())
+                .into_iter()
+                .map(|(id, err)| (id, Err(err)))
+                .collect();
+        }
+
+        let (ids, errors) = self.split_batch(ids.to_vec(), |id| id);
+        let mut results = self.catalog.batch_get(&ids).await;
+        results.extend(errors.into_iter().map(|(id, err)| (id, Err(err))));
+
+        results
+    }
+
+    async fn batch_create(&self, entries: Vec<RegistrationEntry>) -> Result<(), BatchErrors> {
+        if self.inject().await.is_err() {
+            return Err(self.fail_all(entries.iter().map(|entry| &entry.id)));
+        }
+
+        let (entries, mut errors) = self.split_batch(entries, |entry| &entry.id);
+        if let Err(catalog_errors) = self.catalog.batch_create(entries).await {
+            errors.extend(catalog_errors);
+        }
+
+        errors.is_empty().then(|| ()).ok_or(errors)
+    }
+
+    async fn batch_update(&self, entries: Vec<RegistrationEntry>) -> Result<(), BatchErrors> {
+        if self.inject().await.is_err() {
+            return Err(self.fail_all(entries.iter().map(|entry| &entry.id)));
+        }
+
+        let (entries, mut errors) = self.split_batch(entries, |entry| &entry.id);
+        if let Err(catalog_errors) = self.catalog.batch_update(entries).await {
+            errors.extend(catalog_errors);
+        }
+
+        errors.is_empty().then(|| ()).ok_or(errors)
+    }
+
+    async fn batch_delete(&self, ids: &[String]) -> Result<(), BatchErrors> {
+        if self.inject().await.is_err() {
+            return Err(self.fail_all(ids.iter()));
+        }
+
+        let (ids, mut errors) = self.split_batch(ids.to_vec(), |id| id);
+        if let Err(catalog_errors) = self.catalog.batch_delete(&ids).await {
+            errors.extend(catalog_errors);
+        }
+
+        errors.is_empty().then(|| ()).ok_or(errors)
+    }
+
+    async fn list_all(
+        &self,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<RegistrationEntry>, Option<String>), Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.list_all(page_token, page_size).await
+    }
+
+    async fn list_filtered(
+        &self,
+        filter: &EntryFilter,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<RegistrationEntry>, Option<String>), Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog
+            .list_filtered(filter, page_token, page_size)
+            .await
+    }
+
+    async fn get_entry(
+        &self,
+        id: &str,
+    ) -> Result<RegistrationEntry, Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.get_entry(id).await
+    }
+
+    async fn get_entries_by_selectors(
+        &self,
+        selectors: &BTreeSet<String>,
+    ) -> Result<Vec<RegistrationEntry>, Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.get_entries_by_selectors(selectors).await
+    }
+
+    async fn export_snapshot(
+        &self,
+    ) -> Result<Vec<RegistrationEntry>, Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.export_snapshot().await
+    }
+
+    async fn import_snapshot(&self, entries: Vec<RegistrationEntry>) -> Result<(), BatchErrors> {
+        if self.inject().await.is_err() {
+            return Err(self.fail_all(entries.iter().map(|entry| &entry.id)));
+        }
+
+        let (entries, mut errors) = self.split_batch(entries, |entry| &entry.id);
+        if let Err(catalog_errors) = self.catalog.import_snapshot(entries).await {
+            errors.extend(catalog_errors);
+        }
+
+        errors.is_empty().then(|| ()).ok_or(errors)
+    }
+
+    async fn watch(&self) -> Result<EntryEventStream, Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.watch().await
+    }
+}
+
+#[async_trait::async_trait]
+impl TrustBundleStore for Catalog {
+    async fn add_jwk(
+        &self,
+        trust_domain: &str,
+        jwk: JWK,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.add_jwk(trust_domain, jwk).await
+    }
+
+    async fn remove_jwk(
+        &self,
+        trust_domain: &str,
+        kid: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.remove_jwk(trust_domain, kid).await
+    }
+
+    async fn get_jwk(
+        &self,
+        trust_domain: &str,
+    ) -> Result<(Vec<JWK>, usize), Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.get_jwk(trust_domain).await
+    }
+
+    async fn get_jwk_history(
+        &self,
+        trust_domain: &str,
+    ) -> Result<Vec<JWKSetVersion>, Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.get_jwk_history(trust_domain).await
+    }
+
+    async fn rollback_jwk(
+        &self,
+        trust_domain: &str,
+        version: usize,
+    ) -> Result<usize, Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.rollback_jwk(trust_domain, version).await
+    }
+
+    async fn get_key_slots(
+        &self,
+        trust_domain: &str,
+    ) -> Result<Option<KeySlots>, Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.get_key_slots(trust_domain).await
+    }
+
+    async fn set_key_slots(
+        &self,
+        trust_domain: &str,
+        key_slots: KeySlots,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.set_key_slots(trust_domain, key_slots).await
+    }
+
+    async fn set_federated_bundle(
+        &self,
+        trust_bundle: TrustBundle,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.set_federated_bundle(trust_bundle).await
+    }
+
+    async fn get_federated_bundles(
+        &self,
+    ) -> Result<Vec<TrustBundle>, Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.catalog.get_federated_bundles().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::chaos::FaultConfig;
+
+    use crate::inmemory;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn fail_every_call() {
+        let faults = Arc::new(Faults::new("catalog"));
+        let catalog = Catalog::new(Arc::new(inmemory::Catalog::new()), faults.clone());
+        faults.set(FaultConfig {
+            fail_every: 1,
+            ..Default::default()
+        });
+
+        let errors = catalog
+            .batch_create(vec![
+                RegistrationEntry::test_entry("id1"),
+                RegistrationEntry::test_entry("id2"),
+            ])
+            .await
+            .unwrap_err();
+        assert_eq!(errors.len(), 2);
+        catalog.get_entry("id1").await.unwrap_err();
+
+        faults.set(FaultConfig::default());
+        catalog
+            .batch_create(vec![RegistrationEntry::test_entry("id1")])
+            .await
+            .unwrap();
+        catalog.get_entry("id1").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn partial_failure() {
+        let faults = Arc::new(Faults::new("catalog"));
+        let catalog = Catalog::new(Arc::new(inmemory::Catalog::new()), faults.clone());
+        faults.set(FaultConfig {
+            partial_failure: true,
+            ..Default::default()
+        });
+
+        let errors = catalog
+            .batch_create(vec![
+                RegistrationEntry::test_entry("id1"),
+                RegistrationEntry::test_entry("id2"),
+                RegistrationEntry::test_entry("id3"),
+            ])
+            .await
+            .unwrap_err();
+        let ids = errors.into_iter().map(|(id, _err)| id).collect::<Vec<_>>();
+        assert_eq!(ids, vec!["id2".to_string()]);
+
+        let (entries, _page_token) = catalog.list_all(None, 10).await.unwrap();
+        assert_eq!(entries.len(), 2);
+    }
+}
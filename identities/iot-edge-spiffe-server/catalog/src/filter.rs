@@ -0,0 +1,127 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::str::FromStr;
+
+use core_objects::{
+    AttestationConfig, NodeAttestationPlugin, RegistrationEntry, WorkloadAttestationPlugin,
+};
+
+use crate::entry_selectors;
+
+/// Filter of `Entries::list_filtered`. An entry matches when it matches all the criteria that are set.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EntryFilter {
+    /// Parent id of a workload entry. Node entries have no parent.
+    pub parent_id: Option<String>,
+    /// Selector the entry must have, e.g. `NAMESPACE:default`.
+    pub selector: Option<String>,
+    /// Start of the SPIFFE ID path of the entry, without the trust domain.
+    pub spiffe_id_path_prefix: Option<String>,
+    pub plugin: Option<AttestationPlugin>,
+}
+
+/// Attestation plugin of an entry. The names of the node and workload plugins do not overlap.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttestationPlugin {
+    Node(NodeAttestationPlugin),
+    Workload(WorkloadAttestationPlugin),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown attestation plugin {0}")]
+pub struct UnknownPlugin(String);
+
+impl EntryFilter {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == EntryFilter::default()
+    }
+
+    #[must_use]
+    pub fn matches(&self, entry: &RegistrationEntry) -> bool {
+        let parent_id = match &entry.attestation_config {
+            AttestationConfig::Workload(attestation) => Some(&attestation.parent_id),
+            AttestationConfig::Node(_) => None,
+        };
+
+        self.parent_id
+            .as_ref()
+            .map_or(true, |filter| Some(filter) == parent_id)
+            && self
+                .selector
+                .as_ref()
+                .map_or(true, |selector| entry_selectors(entry).contains(selector))
+            && self
+                .spiffe_id_path_prefix
+                .as_ref()
+                .map_or(true, |prefix| entry.spiffe_id_path.starts_with(prefix))
+            && self
+                .plugin
+                .as_ref()
+                .map_or(true, |plugin| plugin.matches(&entry.attestation_config))
+    }
+}
+
+impl AttestationPlugin {
+    fn matches(&self, attestation_config: &AttestationConfig) -> bool {
+        match (self, attestation_config) {
+            (AttestationPlugin::Node(plugin), AttestationConfig::Node(attestation)) => {
+                *plugin == attestation.plugin
+            }
+            (AttestationPlugin::Workload(plugin), AttestationConfig::Workload(attestation)) => {
+                *plugin == attestation.plugin
+            }
+            _ => false,
+        }
+    }
+
+    /// Name of the plugin, as it is serialized in the entries.
+    #[must_use]
+    pub fn name(&self) -> String {
+        let name = match self {
+            AttestationPlugin::Node(plugin) => serde_json::to_value(plugin),
+            AttestationPlugin::Workload(plugin) => serde_json::to_value(plugin),
+        };
+
+        name.ok()
+            .and_then(|name| name.as_str().map(ToString::to_string))
+            .unwrap_or_default()
+    }
+}
+
+impl FromStr for AttestationPlugin {
+    type Err = UnknownPlugin;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let value = || serde_json::Value::String(name.to_string());
+
+        if let Ok(plugin) = serde_json::from_value(value()) {
+            return Ok(AttestationPlugin::Node(plugin));
+        }
+
+        serde_json::from_value(value())
+            .map(AttestationPlugin::Workload)
+            .map_err(|_| UnknownPlugin(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_plugin() {
+        let plugin: AttestationPlugin = "PSAT".parse().unwrap();
+        assert_eq!(plugin, AttestationPlugin::Node(NodeAttestationPlugin::Psat));
+        assert_eq!(plugin.name(), "PSAT");
+
+        let plugin: AttestationPlugin = "K8S".parse().unwrap();
+        assert_eq!(
+            plugin,
+            AttestationPlugin::Workload(WorkloadAttestationPlugin::K8s)
+        );
+        assert_eq!(plugin.name(), "K8S");
+
+        "k8s".parse::<AttestationPlugin>().unwrap_err();
+    }
+}
@@ -1,10 +1,35 @@
 // Copyright (c) Microsoft. All rights reserved.
 
+use std::collections::BTreeSet;
+
 use core_objects::RegistrationEntry;
+use futures_util::stream;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{Entries, EntryEvent, EntryEventStream, EntryFilter, Error as CatalogError};
+
+use super::{error::Error, transaction::Transaction, Catalog};
+
+impl Catalog {
+    // The locks are held from staging to applying, so the transaction sees a consistent catalog.
+    fn apply_transaction(
+        &self,
+        stage: impl FnOnce(&mut Transaction<'_>),
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        let mut entries_list = self.entries_list.write();
+        let mut selector_index = self.selector_index.write();
 
-use crate::Entries;
+        let mut transaction = Transaction::new(&entries_list);
+        stage(&mut transaction);
+        let changes = transaction.commit()?;
 
-use super::{error::Error, Catalog};
+        for event in changes.apply(&mut entries_list, &mut selector_index) {
+            let _result = self.events.send(event);
+        }
+
+        Ok(())
+    }
+}
 
 #[async_trait::async_trait]
 impl Entries for Catalog {
@@ -13,6 +38,7 @@ impl Entries for Catalog {
         entries: Vec<RegistrationEntry>,
     ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
         let mut entries_list = self.entries_list.write();
+        let mut selector_index = self.selector_index.write();
         let mut errors = Vec::new();
 
         for entry in entries {
@@ -24,6 +50,8 @@ impl Entries for Catalog {
 
                 errors.push(error);
             } else {
+                selector_index.insert(&entry);
+                let _result = self.events.send(EntryEvent::Created(entry.clone()));
                 entries_list.insert(entry.id.clone(), entry);
             };
         }
@@ -36,10 +64,28 @@ impl Entries for Catalog {
         entries: Vec<RegistrationEntry>,
     ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
         let mut entries_list = self.entries_list.write();
+        let mut selector_index = self.selector_index.write();
         let mut errors = Vec::new();
 
-        for entry in entries {
+        for mut entry in entries {
             if let Some(entry_ptr) = entries_list.get_mut(&entry.id) {
+                if entry_ptr.revision_number != entry.revision_number {
+                    let error = (
+                        entry.id.clone(),
+                        Box::new(CatalogError::RevisionConflict {
+                            id: entry.id,
+                            revision_number: entry.revision_number,
+                        }) as _,
+                    );
+
+                    errors.push(error);
+                    continue;
+                }
+
+                entry.revision_number += 1;
+                selector_index.remove(entry_ptr);
+                selector_index.insert(&entry);
+                let _result = self.events.send(EntryEvent::Updated(entry.clone()));
                 *entry_ptr = entry;
             } else {
                 let error = (
@@ -59,10 +105,14 @@ impl Entries for Catalog {
         ids: &[String],
     ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
         let mut entries_list = self.entries_list.write();
+        let mut selector_index = self.selector_index.write();
         let mut errors = Vec::new();
 
         for id in ids {
-            if entries_list.remove(id).is_none() {
+            if let Some(entry) = entries_list.remove(id) {
+                selector_index.remove(&entry);
+                let _result = self.events.send(EntryEvent::Deleted(entry.id));
+            } else {
                 let error = (
                     id.clone(),
                     Box::new(Error::EntryNotFound(id.to_string())) as _,
@@ -75,6 +125,39 @@ impl Entries for Catalog {
         errors.is_empty().then(|| ()).ok_or(errors)
     }
 
+    async fn batch_create_transactional(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        self.apply_transaction(|transaction| {
+            for entry in entries {
+                transaction.create(entry);
+            }
+        })
+    }
+
+    async fn batch_update_transactional(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        self.apply_transaction(|transaction| {
+            for entry in entries {
+                transaction.update(entry);
+            }
+        })
+    }
+
+    async fn batch_delete_transactional(
+        &self,
+        ids: &[String],
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        self.apply_transaction(|transaction| {
+            for id in ids {
+                transaction.delete(id);
+            }
+        })
+    }
+
     async fn batch_get(
         &self,
         ids: &[String],
@@ -150,6 +233,71 @@ impl Entries for Catalog {
 
         Ok((response, page_token))
     }
+
+    async fn list_filtered(
+        &self,
+        filter: &EntryFilter,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<RegistrationEntry>, Option<String>), Box<dyn std::error::Error + Send>> {
+        let entries_list = self.entries_list.read();
+
+        if page_size == 0 {
+            return Err(Box::new(Error::InvalidPageSize()));
+        }
+
+        let iterator: Box<dyn Iterator<Item = (&String, &RegistrationEntry)>> =
+            if let Some(page_token) = page_token {
+                Box::new(entries_list.range(page_token..))
+            } else {
+                Box::new(entries_list.iter())
+            };
+        let mut matching = iterator.filter(|(_id, entry)| filter.matches(entry));
+
+        let response = (&mut matching)
+            .take(page_size)
+            .map(|(_id, entry)| entry.clone())
+            .collect();
+        let page_token = matching.next().map(|x| x.0.clone());
+
+        Ok((response, page_token))
+    }
+
+    async fn get_entries_by_selectors(
+        &self,
+        selectors: &BTreeSet<String>,
+    ) -> Result<Vec<RegistrationEntry>, Box<dyn std::error::Error + Send>> {
+        let entries_list = self.entries_list.read();
+        let selector_index = self.selector_index.read();
+
+        let entries = selector_index
+            .matching_ids(selectors)
+            .iter()
+            .filter_map(|id| entries_list.get(id).cloned())
+            .collect();
+
+        Ok(entries)
+    }
+
+    async fn watch(&self) -> Result<EntryEventStream, Box<dyn std::error::Error + Send>> {
+        let receiver = self.events.subscribe();
+
+        // The stream ends when the catalog is dropped.
+        let events = stream::unfold(receiver, |mut receiver| async move {
+            match receiver.recv().await {
+                Ok(event) => Some((Ok(event), receiver)),
+                Err(RecvError::Lagged(missed)) => {
+                    let error: Box<dyn std::error::Error + Send> =
+                        Box::new(Error::WatchLagged(missed));
+
+                    Some((Err(error), receiver))
+                }
+                Err(RecvError::Closed) => None,
+            }
+        });
+
+        Ok(Box::pin(events))
+    }
 }
 
 #[cfg(test)]
@@ -158,14 +306,15 @@ mod tests {
     use core_objects::{
         AttestationConfig, EntryNodeAttestation, NodeAttestationPlugin, NodeSelectorType,
     };
+    use futures_util::StreamExt;
     use matches::assert_matches;
 
+    use crate::inmemory::WATCH_CHANNEL_CAPACITY;
+
     use super::*;
 
     fn init_entry_test() -> (Catalog, RegistrationEntry, RegistrationEntry) {
         let entry1 = RegistrationEntry {
-            id: String::from("id"),
-            other_identities: Vec::new(),
             spiffe_id_path: "path".to_string(),
             attestation_config: AttestationConfig::Node(EntryNodeAttestation {
                 value: vec![
@@ -174,11 +323,7 @@ mod tests {
                 ],
                 plugin: NodeAttestationPlugin::Sat,
             }),
-            admin: false,
-            expires_at: 0,
-            dns_names: Vec::new(),
-            revision_number: 0,
-            store_svid: false,
+            ..RegistrationEntry::test_entry("id")
         };
 
         let mut entry2 = entry1.clone();
@@ -239,6 +384,33 @@ mod tests {
         catalog.batch_update(entries).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn update_registration_entry_test_revision_conflict() {
+        let (catalog, entry1, _entry2) = init_entry_test();
+
+        catalog.batch_create(vec![entry1.clone()]).await.unwrap();
+        catalog.batch_update(vec![entry1.clone()]).await.unwrap();
+
+        let entry = catalog.get_entry(&entry1.id).await.unwrap();
+        assert_eq!(entry.revision_number, 1);
+
+        // entry1 still carries revision 0, the update must be rejected.
+        let results = catalog.batch_update(vec![entry1]).await.unwrap_err();
+        for (_id, result) in results {
+            let result = *result.downcast::<CatalogError>().unwrap();
+
+            assert_matches!(
+                result,
+                CatalogError::RevisionConflict {
+                    revision_number: 0,
+                    ..
+                }
+            );
+        }
+
+        catalog.batch_update(vec![entry]).await.unwrap();
+    }
+
     #[tokio::test]
     async fn update_registration_entry_test_entry_not_exist() {
         let (catalog, entry1, entry2) = init_entry_test();
@@ -276,6 +448,94 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn export_import_snapshot() {
+        let (catalog, entry1, entry2) = init_entry_test();
+        catalog.batch_create(vec![entry1, entry2]).await.unwrap();
+        let entry = catalog.get_entry("id").await.unwrap();
+        catalog.batch_update(vec![entry]).await.unwrap();
+
+        let snapshot = catalog.export_snapshot().await.unwrap();
+        let ids = snapshot
+            .iter()
+            .map(|entry| entry.id.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["id".to_string(), "id2".to_string()]);
+
+        let restored = Catalog::new();
+        restored.import_snapshot(snapshot.clone()).await.unwrap();
+        let entry = restored.get_entry("id").await.unwrap();
+        assert_eq!(entry.revision_number, 1);
+
+        let results = restored.import_snapshot(snapshot).await.unwrap_err();
+        assert_eq!(results.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn transactional_batch_rolls_back() {
+        let (catalog, entry1, entry2) = init_entry_test();
+        catalog.batch_create(vec![entry1.clone()]).await.unwrap();
+
+        // entry1 already exists, entry2 must not be created either.
+        let results = catalog
+            .batch_create_transactional(vec![entry2.clone(), entry1.clone()])
+            .await
+            .unwrap_err();
+        for (id, result) in results {
+            if id == entry1.id {
+                assert_matches!(
+                    *result.downcast::<Error>().unwrap(),
+                    Error::DuplicatedEntry(_)
+                );
+            } else {
+                assert_matches!(
+                    *result.downcast::<CatalogError>().unwrap(),
+                    CatalogError::TransactionAborted(_)
+                );
+            }
+        }
+        catalog.get_entry(&entry2.id).await.unwrap_err();
+
+        // The second update of the same entry conflicts with the first one.
+        catalog
+            .batch_update_transactional(vec![entry1.clone(), entry1.clone()])
+            .await
+            .unwrap_err();
+        let entry = catalog.get_entry(&entry1.id).await.unwrap();
+        assert_eq!(entry.revision_number, 0);
+
+        let ids = vec![entry1.id.clone(), entry2.id.clone()];
+        catalog.batch_delete_transactional(&ids).await.unwrap_err();
+        catalog.get_entry(&entry1.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn transactional_batch_happy_path() {
+        let (catalog, entry1, entry2) = init_entry_test();
+        let mut events = catalog.watch().await.unwrap();
+
+        catalog
+            .batch_create_transactional(vec![entry1.clone(), entry2.clone()])
+            .await
+            .unwrap();
+        catalog
+            .batch_update_transactional(vec![entry1.clone()])
+            .await
+            .unwrap();
+        let entry = catalog.get_entry(&entry1.id).await.unwrap();
+        assert_eq!(entry.revision_number, 1);
+
+        catalog
+            .batch_delete_transactional(&[entry1.id.clone(), entry2.id.clone()])
+            .await
+            .unwrap();
+        let (entries, _page_token) = catalog.list_all(None, 10).await.unwrap();
+        assert!(entries.is_empty());
+
+        let event = events.next().await.unwrap().unwrap();
+        assert_matches!(event, EntryEvent::Created(_));
+    }
+
     #[tokio::test]
     async fn get_registration_entry_test_happy_path() {
         let (catalog, entry1, entry2) = init_entry_test();
@@ -302,4 +562,119 @@ mod tests {
             assert_matches!(result, Error::EntryNotFound(_));
         }
     }
+
+    #[tokio::test]
+    async fn get_entries_by_selectors_follows_updates() {
+        let (catalog, entry1, mut entry2) = init_entry_test();
+        let selectors = [
+            NodeSelectorType::Cluster.to_string(),
+            NodeSelectorType::AgentNameSpace.to_string(),
+        ]
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+
+        catalog
+            .batch_create(vec![entry1.clone(), entry2.clone()])
+            .await
+            .unwrap();
+        let entries = catalog.get_entries_by_selectors(&selectors).await.unwrap();
+        let ids = entries
+            .into_iter()
+            .map(|entry| entry.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![entry1.id.clone(), entry2.id.clone()]);
+
+        entry2.attestation_config = AttestationConfig::Node(EntryNodeAttestation {
+            value: vec![NodeSelectorType::AgentServiceAccount.to_string()],
+            plugin: NodeAttestationPlugin::Sat,
+        });
+        catalog.batch_update(vec![entry2]).await.unwrap();
+        let entries = catalog.get_entries_by_selectors(&selectors).await.unwrap();
+        let ids = entries
+            .into_iter()
+            .map(|entry| entry.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![entry1.id.clone()]);
+
+        catalog.batch_delete(&[entry1.id]).await.unwrap();
+        let entries = catalog.get_entries_by_selectors(&selectors).await.unwrap();
+        assert!(entries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn list_filtered_pages() {
+        let (catalog, entry1, entry2) = init_entry_test();
+        let mut entry3 = entry2.clone();
+        entry3.id = "id3".to_string();
+        entry3.spiffe_id_path = "other".to_string();
+        catalog
+            .batch_create(vec![entry1.clone(), entry2.clone(), entry3.clone()])
+            .await
+            .unwrap();
+
+        let filter = EntryFilter {
+            spiffe_id_path_prefix: Some("pa".to_string()),
+            ..Default::default()
+        };
+        let (entries, page_token) = catalog.list_filtered(&filter, None, 1).await.unwrap();
+        assert_eq!(entries[0].id, entry1.id);
+        assert_eq!(page_token, Some(entry2.id.clone()));
+        let (entries, page_token) = catalog.list_filtered(&filter, page_token, 1).await.unwrap();
+        assert_eq!(entries[0].id, entry2.id);
+        assert_eq!(page_token, None);
+
+        let filter = EntryFilter {
+            selector: Some(NodeSelectorType::Cluster.to_string()),
+            plugin: Some(crate::AttestationPlugin::Node(NodeAttestationPlugin::Sat)),
+            ..Default::default()
+        };
+        let (entries, _page_token) = catalog.list_filtered(&filter, None, 10).await.unwrap();
+        assert_eq!(entries.len(), 3);
+
+        let filter = EntryFilter {
+            parent_id: Some(entry1.id),
+            ..Default::default()
+        };
+        let (entries, page_token) = catalog.list_filtered(&filter, None, 10).await.unwrap();
+        assert!(entries.is_empty());
+        assert_eq!(page_token, None);
+    }
+
+    #[tokio::test]
+    async fn watch_entry_events() {
+        let (catalog, entry1, entry2) = init_entry_test();
+        let mut events = catalog.watch().await.unwrap();
+
+        catalog
+            .batch_create(vec![entry1.clone(), entry2.clone()])
+            .await
+            .unwrap();
+        catalog.batch_update(vec![entry1.clone()]).await.unwrap();
+        catalog.batch_delete(&[entry2.id.clone()]).await.unwrap();
+
+        let event = events.next().await.unwrap().unwrap();
+        assert_matches!(event, EntryEvent::Created(entry) if entry.id == entry1.id);
+        let event = events.next().await.unwrap().unwrap();
+        assert_matches!(event, EntryEvent::Created(entry) if entry.id == entry2.id);
+        let event = events.next().await.unwrap().unwrap();
+        assert_matches!(event, EntryEvent::Updated(entry) if entry.id == entry1.id);
+        let event = events.next().await.unwrap().unwrap();
+        assert_matches!(event, EntryEvent::Deleted(id) if id == entry2.id);
+    }
+
+    #[tokio::test]
+    async fn watch_lagged() {
+        let (catalog, entry1, _entry2) = init_entry_test();
+        let mut events = catalog.watch().await.unwrap();
+
+        for i in 0..=WATCH_CHANNEL_CAPACITY {
+            let mut entry = entry1.clone();
+            entry.id = format!("id{}", i);
+            catalog.batch_create(vec![entry]).await.unwrap();
+        }
+
+        let error = events.next().await.unwrap().unwrap_err();
+        let error = *error.downcast::<Error>().unwrap();
+        assert_matches!(error, Error::WatchLagged(1));
+    }
 }
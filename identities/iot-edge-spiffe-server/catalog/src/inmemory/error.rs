@@ -12,6 +12,10 @@ pub enum Error {
     DuplicatedKey(String),
     #[error("Key {0} does not exist")]
     KeyNotFound(String),
+    #[error("Version {0} is not in the trust bundle history")]
+    VersionNotFound(usize),
     #[error("Invalid page size")]
     InvalidPageSize(),
+    #[error("Watcher fell behind, {0} events were missed")]
+    WatchLagged(u64),
 }
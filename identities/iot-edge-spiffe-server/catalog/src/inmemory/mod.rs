@@ -1,20 +1,44 @@
 // Copyright (c) Microsoft. All rights reserved.
 mod entries;
 mod error;
+mod selector_index;
+mod transaction;
 mod trust_bundle_store;
 
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, VecDeque},
     sync::Arc,
 };
 
-use crate::Catalog as CatalogTrait;
-use core_objects::{RegistrationEntry, JWK};
+use crate::{
+    pagination::split_page, AdminOperationFilter, Catalog as CatalogTrait, EntryEvent,
+    IssuedSvidFilter, JWK_SET_HISTORY_SIZE,
+};
+use core_objects::{
+    get_epoch_time, AdminOperation, AgentBan, AttestedAgent, IssuedSvid, JWKSetVersion, KeySlots,
+    RegistrationEntry, TrustBundle, JWK,
+};
+use error::Error;
 use parking_lot::{const_rwlock, RwLock};
+use selector_index::SelectorIndex;
+use tokio::sync::broadcast;
+
+// Number of events a watcher can fall behind before it misses some.
+const WATCH_CHANNEL_CAPACITY: usize = 1024;
 
 pub struct Catalog {
     entries_list: Arc<RwLock<BTreeMap<String, RegistrationEntry>>>,
+    // Always locked after entries_list, so both are updated together.
+    selector_index: Arc<RwLock<SelectorIndex>>,
+    // Events are sent while holding the entries_list lock, so watchers see them in order.
+    events: broadcast::Sender<EntryEvent>,
     jwt_trust_domain: Arc<RwLock<JWTTrustDomain>>,
+    // Bundles of the federated trust domains, by trust domain.
+    federated_bundles: Arc<RwLock<BTreeMap<String, TrustBundle>>>,
+    agent_bans: Arc<RwLock<BTreeMap<String, AgentBan>>>,
+    attested_agents: Arc<RwLock<BTreeMap<String, AttestedAgent>>>,
+    issued_svids: Arc<RwLock<BTreeMap<String, IssuedSvid>>>,
+    admin_operations: Arc<RwLock<BTreeMap<String, AdminOperation>>>,
 }
 
 pub struct JWTTrustDomain {
@@ -23,6 +47,20 @@ pub struct JWTTrustDomain {
     // The trust domain string will be ignored in the calls related to the trust domain key store
     // That one hashmap contains all the public keys for the only trust domain.
     store: HashMap<String, JWK>,
+    // Most recent version first.
+    history: VecDeque<JWKSetVersion>,
+    key_slots: Option<KeySlots>,
+}
+
+impl JWTTrustDomain {
+    fn record_version(&mut self) {
+        self.history.push_front(JWKSetVersion {
+            version: self.version,
+            created_at: get_epoch_time(),
+            keys: self.store.values().cloned().collect(),
+        });
+        self.history.truncate(JWK_SET_HISTORY_SIZE);
+    }
 }
 
 impl Catalog {
@@ -30,10 +68,19 @@ impl Catalog {
     pub fn new() -> Self {
         Catalog {
             entries_list: Arc::new(const_rwlock(BTreeMap::new())),
+            selector_index: Arc::new(const_rwlock(SelectorIndex::default())),
+            events: broadcast::channel(WATCH_CHANNEL_CAPACITY).0,
             jwt_trust_domain: Arc::new(const_rwlock(JWTTrustDomain {
                 version: 0,
                 store: HashMap::new(),
+                history: VecDeque::new(),
+                key_slots: None,
             })),
+            federated_bundles: Arc::new(const_rwlock(BTreeMap::new())),
+            agent_bans: Arc::new(const_rwlock(BTreeMap::new())),
+            attested_agents: Arc::new(const_rwlock(BTreeMap::new())),
+            issued_svids: Arc::new(const_rwlock(BTreeMap::new())),
+            admin_operations: Arc::new(const_rwlock(BTreeMap::new())),
         }
     }
 }
@@ -45,4 +92,123 @@ impl Default for Catalog {
 }
 
 #[async_trait::async_trait]
-impl CatalogTrait for Catalog {}
+impl CatalogTrait for Catalog {
+    async fn add_agent_ban(&self, ban: AgentBan) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.agent_bans.write().insert(ban.id.clone(), ban);
+
+        Ok(())
+    }
+
+    async fn remove_agent_ban(&self, id: &str) -> Result<bool, Box<dyn std::error::Error + Send>> {
+        Ok(self.agent_bans.write().remove(id).is_some())
+    }
+
+    async fn get_agent_bans(&self) -> Result<Vec<AgentBan>, Box<dyn std::error::Error + Send>> {
+        Ok(self.agent_bans.read().values().cloned().collect())
+    }
+
+    async fn record_attested_agent(
+        &self,
+        mut agent: AttestedAgent,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        let mut attested_agents = self.attested_agents.write();
+
+        if let Some(previous) = attested_agents.get(&agent.id) {
+            agent.svid_expiry = agent.svid_expiry.max(previous.svid_expiry);
+        }
+        attested_agents.insert(agent.id.clone(), agent);
+
+        Ok(())
+    }
+
+    async fn list_attested_agents(
+        &self,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<AttestedAgent>, Option<String>), Box<dyn std::error::Error + Send>> {
+        if page_size == 0 {
+            return Err(Box::new(Error::InvalidPageSize()));
+        }
+
+        let attested_agents = self.attested_agents.read();
+        let rows = attested_agents
+            .range(page_token.unwrap_or_default()..)
+            .take(page_size.saturating_add(1))
+            .map(|(id, agent)| (id.clone(), agent.clone()))
+            .collect();
+        let (rows, page_token) = split_page(rows, page_size);
+        let agents = rows.into_iter().map(|(_id, agent)| agent).collect();
+
+        Ok((agents, page_token))
+    }
+
+    async fn record_issued_svids(
+        &self,
+        svids: Vec<IssuedSvid>,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        let mut issued_svids = self.issued_svids.write();
+        for svid in svids {
+            issued_svids.entry(svid.id.clone()).or_insert(svid);
+        }
+
+        Ok(())
+    }
+
+    async fn list_issued_svids(
+        &self,
+        filter: &IssuedSvidFilter,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<IssuedSvid>, Option<String>), Box<dyn std::error::Error + Send>> {
+        if page_size == 0 {
+            return Err(Box::new(Error::InvalidPageSize()));
+        }
+
+        let issued_svids = self.issued_svids.read();
+        let rows = issued_svids
+            .range(page_token.unwrap_or_default()..)
+            .filter(|(_id, svid)| filter.matches(svid))
+            .take(page_size.saturating_add(1))
+            .map(|(id, svid)| (id.clone(), svid.clone()))
+            .collect();
+        let (rows, page_token) = split_page(rows, page_size);
+        let svids = rows.into_iter().map(|(_id, svid)| svid).collect();
+
+        Ok((svids, page_token))
+    }
+
+    async fn record_admin_operation(
+        &self,
+        operation: AdminOperation,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.admin_operations
+            .write()
+            .entry(operation.id.clone())
+            .or_insert(operation);
+
+        Ok(())
+    }
+
+    async fn list_admin_operations(
+        &self,
+        filter: &AdminOperationFilter,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<AdminOperation>, Option<String>), Box<dyn std::error::Error + Send>> {
+        if page_size == 0 {
+            return Err(Box::new(Error::InvalidPageSize()));
+        }
+
+        let admin_operations = self.admin_operations.read();
+        let rows = admin_operations
+            .range(page_token.unwrap_or_default()..)
+            .filter(|(_id, operation)| filter.matches(operation))
+            .take(page_size.saturating_add(1))
+            .map(|(id, operation)| (id.clone(), operation.clone()))
+            .collect();
+        let (rows, page_token) = split_page(rows, page_size);
+        let operations = rows.into_iter().map(|(_id, operation)| operation).collect();
+
+        Ok((operations, page_token))
+    }
+}
@@ -0,0 +1,125 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use core_objects::RegistrationEntry;
+
+use crate::entry_selectors;
+
+// Inverted index from selector strings to the ids of the entries requiring them.
+#[derive(Default)]
+pub struct SelectorIndex {
+    entry_ids: HashMap<String, BTreeSet<String>>,
+    // Number of distinct selectors of each entry, an entry matches once all of them are found.
+    selector_counts: HashMap<String, usize>,
+    // Entries without selectors match any set of selectors.
+    without_selectors: BTreeSet<String>,
+}
+
+impl SelectorIndex {
+    pub fn insert(&mut self, entry: &RegistrationEntry) {
+        let selectors = entry_selectors(entry).iter().collect::<BTreeSet<_>>();
+
+        for selector in &selectors {
+            self.entry_ids
+                .entry((*selector).clone())
+                .or_default()
+                .insert(entry.id.clone());
+        }
+
+        if selectors.is_empty() {
+            self.without_selectors.insert(entry.id.clone());
+        }
+
+        self.selector_counts
+            .insert(entry.id.clone(), selectors.len());
+    }
+
+    pub fn remove(&mut self, entry: &RegistrationEntry) {
+        for selector in entry_selectors(entry) {
+            if let Some(entry_ids) = self.entry_ids.get_mut(selector) {
+                entry_ids.remove(&entry.id);
+
+                if entry_ids.is_empty() {
+                    self.entry_ids.remove(selector);
+                }
+            }
+        }
+
+        self.without_selectors.remove(&entry.id);
+        self.selector_counts.remove(&entry.id);
+    }
+
+    // Ids of the entries whose selectors are all contained in the given selectors, sorted.
+    pub fn matching_ids(&self, selectors: &BTreeSet<String>) -> Vec<String> {
+        let mut hits: BTreeMap<&String, usize> = BTreeMap::new();
+
+        for selector in selectors {
+            for id in self.entry_ids.get(selector).into_iter().flatten() {
+                *hits.entry(id).or_default() += 1;
+            }
+        }
+
+        for id in &self.without_selectors {
+            hits.insert(id, 0);
+        }
+
+        hits.into_iter()
+            .filter(|(id, hits)| self.selector_counts.get(*id) == Some(hits))
+            .map(|(id, _hits)| id.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, selectors: &[&str]) -> RegistrationEntry {
+        let selectors = selectors.iter().map(ToString::to_string).collect();
+
+        RegistrationEntry::test_workload_entry(id, "parent", selectors)
+    }
+
+    fn selectors(selectors: &[&str]) -> BTreeSet<String> {
+        selectors.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn matching_ids_requires_all_entry_selectors() {
+        let mut index = SelectorIndex::default();
+        index.insert(&entry("a", &["s1"]));
+        index.insert(&entry("b", &["s1", "s2"]));
+        index.insert(&entry("c", &["s3"]));
+
+        assert_eq!(index.matching_ids(&selectors(&["s1"])), vec!["a"]);
+        assert_eq!(
+            index.matching_ids(&selectors(&["s1", "s2", "s4"])),
+            vec!["a", "b"]
+        );
+        assert!(index.matching_ids(&selectors(&["s4"])).is_empty());
+    }
+
+    #[test]
+    fn matching_ids_entry_without_selectors() {
+        let mut index = SelectorIndex::default();
+        index.insert(&entry("a", &[]));
+        index.insert(&entry("b", &["s1", "s1"]));
+
+        assert_eq!(index.matching_ids(&selectors(&[])), vec!["a"]);
+        assert_eq!(index.matching_ids(&selectors(&["s1"])), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn remove_entry() {
+        let mut index = SelectorIndex::default();
+        let entry1 = entry("a", &["s1"]);
+        index.insert(&entry1);
+        index.remove(&entry1);
+
+        assert!(index.matching_ids(&selectors(&["s1"])).is_empty());
+        assert!(index.entry_ids.is_empty());
+        assert!(index.selector_counts.is_empty());
+        assert!(index.without_selectors.is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Transactional batches are staged on top of the entries, then applied once every entry succeeded.
+// Dropping the transaction rolls the whole batch back. Staging also catches conflicts inside the
+// batch, e.g. the same id created twice.
+
+use std::collections::BTreeMap;
+
+use core_objects::RegistrationEntry;
+
+use crate::{abort_transaction, EntryEvent, Error as CatalogError};
+
+use super::{error::Error, selector_index::SelectorIndex};
+
+type BatchErrors = Vec<(String, Box<dyn std::error::Error + Send>)>;
+
+pub(super) struct Transaction<'a> {
+    entries_list: &'a BTreeMap<String, RegistrationEntry>,
+    // None when the entry is deleted by the transaction.
+    staged: BTreeMap<String, Option<RegistrationEntry>>,
+    events: Vec<EntryEvent>,
+    failed: BatchErrors,
+    ids: Vec<String>,
+}
+
+// Changes of a transaction that succeeded, to apply to the catalog.
+pub(super) struct Changes {
+    staged: BTreeMap<String, Option<RegistrationEntry>>,
+    events: Vec<EntryEvent>,
+}
+
+impl<'a> Transaction<'a> {
+    pub(super) fn new(entries_list: &'a BTreeMap<String, RegistrationEntry>) -> Self {
+        Transaction {
+            entries_list,
+            staged: BTreeMap::new(),
+            events: Vec::new(),
+            failed: Vec::new(),
+            ids: Vec::new(),
+        }
+    }
+
+    fn get(&self, id: &str) -> Option<&RegistrationEntry> {
+        match self.staged.get(id) {
+            Some(staged) => staged.as_ref(),
+            None => self.entries_list.get(id),
+        }
+    }
+
+    fn fail(&mut self, id: String, error: Box<dyn std::error::Error + Send>) {
+        self.failed.push((id, error));
+    }
+
+    pub(super) fn create(&mut self, entry: RegistrationEntry) {
+        self.ids.push(entry.id.clone());
+
+        if self.get(&entry.id).is_some() {
+            let id = entry.id.clone();
+            self.fail(id, Box::new(Error::DuplicatedEntry(entry.id)));
+            return;
+        }
+
+        self.events.push(EntryEvent::Created(entry.clone()));
+        self.staged.insert(entry.id.clone(), Some(entry));
+    }
+
+    pub(super) fn update(&mut self, mut entry: RegistrationEntry) {
+        self.ids.push(entry.id.clone());
+
+        let revision_number = match self.get(&entry.id) {
+            Some(current) => current.revision_number,
+            None => {
+                let id = entry.id.clone();
+                self.fail(id, Box::new(Error::EntryNotFound(entry.id)));
+                return;
+            }
+        };
+
+        if revision_number != entry.revision_number {
+            let id = entry.id.clone();
+            self.fail(
+                id,
+                Box::new(CatalogError::RevisionConflict {
+                    id: entry.id,
+                    revision_number: entry.revision_number,
+                }),
+            );
+            return;
+        }
+
+        entry.revision_number += 1;
+        self.events.push(EntryEvent::Updated(entry.clone()));
+        self.staged.insert(entry.id.clone(), Some(entry));
+    }
+
+    pub(super) fn delete(&mut self, id: &str) {
+        self.ids.push(id.to_string());
+
+        if self.get(id).is_none() {
+            self.fail(
+                id.to_string(),
+                Box::new(Error::EntryNotFound(id.to_string())),
+            );
+            return;
+        }
+
+        self.events.push(EntryEvent::Deleted(id.to_string()));
+        self.staged.insert(id.to_string(), None);
+    }
+
+    // Nothing is applied if an entry failed. The other entries of the batch are reported aborted.
+    pub(super) fn commit(self) -> Result<Changes, BatchErrors> {
+        if self.failed.is_empty() {
+            return Ok(Changes {
+                staged: self.staged,
+                events: self.events,
+            });
+        }
+
+        Err(abort_transaction(&self.ids, self.failed))
+    }
+}
+
+impl Changes {
+    pub(super) fn apply(
+        self,
+        entries_list: &mut BTreeMap<String, RegistrationEntry>,
+        selector_index: &mut SelectorIndex,
+    ) -> Vec<EntryEvent> {
+        for (id, entry) in self.staged {
+            if let Some(previous) = entries_list.remove(&id) {
+                selector_index.remove(&previous);
+            }
+
+            if let Some(entry) = entry {
+                selector_index.insert(&entry);
+                entries_list.insert(id, entry);
+            }
+        }
+
+        self.events
+    }
+}
@@ -1,6 +1,6 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use core_objects::JWK;
+use core_objects::{JWKSetVersion, KeySlots, TrustBundle, JWK};
 
 use crate::TrustBundleStore;
 
@@ -21,6 +21,7 @@ impl TrustBundleStore for Catalog {
 
         jwt_trust_domain.version += 1;
         jwt_trust_domain.store.insert(jwk.kid.clone(), jwk);
+        jwt_trust_domain.record_version();
 
         Ok(())
     }
@@ -39,6 +40,7 @@ impl TrustBundleStore for Catalog {
             .map(|_| ())?;
 
         jwt_trust_domain.version += 1;
+        jwt_trust_domain.record_version();
 
         Ok(())
     }
@@ -58,11 +60,80 @@ impl TrustBundleStore for Catalog {
             jwt_trust_domain.version,
         ))
     }
+
+    async fn get_jwk_history(
+        &self,
+        _trust_domain: &str,
+    ) -> Result<Vec<JWKSetVersion>, Box<dyn std::error::Error + Send>> {
+        let jwt_trust_domain = self.jwt_trust_domain.read();
+
+        Ok(jwt_trust_domain.history.iter().cloned().collect())
+    }
+
+    async fn rollback_jwk(
+        &self,
+        _trust_domain: &str,
+        version: usize,
+    ) -> Result<usize, Box<dyn std::error::Error + Send>> {
+        let mut jwt_trust_domain = self.jwt_trust_domain.write();
+
+        let keys = jwt_trust_domain
+            .history
+            .iter()
+            .find(|jwk_set| jwk_set.version == version)
+            .map(|jwk_set| jwk_set.keys.clone())
+            .ok_or_else(|| Box::new(Error::VersionNotFound(version)) as _)?;
+
+        jwt_trust_domain.store = keys.into_iter().map(|jwk| (jwk.kid.clone(), jwk)).collect();
+        jwt_trust_domain.version += 1;
+        jwt_trust_domain.record_version();
+
+        Ok(jwt_trust_domain.version)
+    }
+
+    async fn get_key_slots(
+        &self,
+        _trust_domain: &str,
+    ) -> Result<Option<KeySlots>, Box<dyn std::error::Error + Send>> {
+        let jwt_trust_domain = self.jwt_trust_domain.read();
+
+        Ok(jwt_trust_domain.key_slots.clone())
+    }
+
+    async fn set_key_slots(
+        &self,
+        _trust_domain: &str,
+        key_slots: KeySlots,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        let mut jwt_trust_domain = self.jwt_trust_domain.write();
+
+        jwt_trust_domain.key_slots = Some(key_slots);
+
+        Ok(())
+    }
+
+    async fn set_federated_bundle(
+        &self,
+        trust_bundle: TrustBundle,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.federated_bundles
+            .write()
+            .insert(trust_bundle.trust_domain.clone(), trust_bundle);
+
+        Ok(())
+    }
+
+    async fn get_federated_bundles(
+        &self,
+    ) -> Result<Vec<TrustBundle>, Box<dyn std::error::Error + Send>> {
+        Ok(self.federated_bundles.read().values().cloned().collect())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use core_objects::{Crv, KeyUse, Kty};
+    use crate::JWK_SET_HISTORY_SIZE;
+    use core_objects::{Crv, JWKSet, KeySlot, KeyUse, Kty};
 
     use matches::assert_matches;
 
@@ -77,7 +148,9 @@ mod tests {
             x: "abc".to_string(),
             y: "abc".to_string(),
             kty: Kty::EC,
-            crv: Crv::P256,
+            crv: Some(Crv::P256),
+            n: String::new(),
+            e: String::new(),
             key_use: KeyUse::JWTSVID,
         };
 
@@ -93,7 +166,9 @@ mod tests {
             x: "abc".to_string(),
             y: "abc".to_string(),
             kty: Kty::EC,
-            crv: Crv::P256,
+            crv: Some(Crv::P256),
+            n: String::new(),
+            e: String::new(),
             key_use: KeyUse::JWTSVID,
         };
 
@@ -117,7 +192,9 @@ mod tests {
             x: "abc".to_string(),
             y: "abc".to_string(),
             kty: Kty::EC,
-            crv: Crv::P256,
+            crv: Some(Crv::P256),
+            n: String::new(),
+            e: String::new(),
             key_use: KeyUse::JWTSVID,
         };
 
@@ -134,7 +211,9 @@ mod tests {
             x: "abc".to_string(),
             y: "abc".to_string(),
             kty: Kty::EC,
-            crv: Crv::P256,
+            crv: Some(Crv::P256),
+            n: String::new(),
+            e: String::new(),
             key_use: KeyUse::JWTSVID,
         };
 
@@ -158,7 +237,9 @@ mod tests {
             x: "abc".to_string(),
             y: "abc".to_string(),
             kty: Kty::EC,
-            crv: Crv::P256,
+            crv: Some(Crv::P256),
+            n: String::new(),
+            e: String::new(),
             key_use: KeyUse::JWTSVID,
         };
         catalog.add_jwk("dummy", jwk.clone()).await.unwrap();
@@ -168,7 +249,9 @@ mod tests {
             x: "abc".to_string(),
             y: "abc".to_string(),
             kty: Kty::EC,
-            crv: Crv::P256,
+            crv: Some(Crv::P256),
+            n: String::new(),
+            e: String::new(),
             key_use: KeyUse::JWTSVID,
         };
         catalog.add_jwk("dummy", jwk).await.unwrap();
@@ -178,4 +261,153 @@ mod tests {
         assert_eq!(keys.len(), 2);
         assert_eq!(version, 2);
     }
+
+    #[tokio::test]
+    async fn rollback_jwk_test_happy_path() {
+        let catalog = Catalog::new();
+
+        let jwk = JWK {
+            kid: "my_key".to_string(),
+            x: "abc".to_string(),
+            y: "abc".to_string(),
+            kty: Kty::EC,
+            crv: Some(Crv::P256),
+            n: String::new(),
+            e: String::new(),
+            key_use: KeyUse::JWTSVID,
+        };
+        catalog.add_jwk("dummy", jwk).await.unwrap();
+
+        let jwk = JWK {
+            kid: "malformed".to_string(),
+            x: "".to_string(),
+            y: "".to_string(),
+            kty: Kty::EC,
+            crv: Some(Crv::P256),
+            n: String::new(),
+            e: String::new(),
+            key_use: KeyUse::JWTSVID,
+        };
+        catalog.add_jwk("dummy", jwk).await.unwrap();
+
+        let history = catalog.get_jwk_history("dummy").await.unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].version, 2);
+
+        let version = catalog.rollback_jwk("dummy", 1).await.unwrap();
+        assert_eq!(version, 3);
+
+        let (keys, version) = catalog.get_jwk("dummy").await.unwrap();
+        assert_eq!(version, 3);
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].kid, "my_key");
+    }
+
+    #[tokio::test]
+    async fn rollback_jwk_test_version_not_found() {
+        let catalog = Catalog::new();
+
+        let res = *catalog
+            .rollback_jwk("dummy", 5)
+            .await
+            .unwrap_err()
+            .downcast::<Error>()
+            .unwrap();
+
+        assert_matches!(res, Error::VersionNotFound(5));
+    }
+
+    #[tokio::test]
+    async fn jwk_history_is_bounded() {
+        let catalog = Catalog::new();
+
+        for i in 0..JWK_SET_HISTORY_SIZE + 2 {
+            let jwk = JWK {
+                kid: format!("my_key{}", i),
+                x: "abc".to_string(),
+                y: "abc".to_string(),
+                kty: Kty::EC,
+                crv: Some(Crv::P256),
+                n: String::new(),
+                e: String::new(),
+                key_use: KeyUse::JWTSVID,
+            };
+            catalog.add_jwk("dummy", jwk).await.unwrap();
+        }
+
+        let history = catalog.get_jwk_history("dummy").await.unwrap();
+        assert_eq!(history.len(), JWK_SET_HISTORY_SIZE);
+    }
+
+    #[tokio::test]
+    async fn key_slots_test_happy_path() {
+        let catalog = Catalog::new();
+
+        assert_eq!(catalog.get_key_slots("dummy").await.unwrap(), None);
+
+        let key_slots = KeySlots {
+            previous: None,
+            current: KeySlot {
+                id: "current".to_string(),
+                kid: "current-thumbprint".to_string(),
+                expiry: 10,
+            },
+            next: Some(KeySlot {
+                id: "next".to_string(),
+                kid: String::new(),
+                expiry: 20,
+            }),
+            revoked: vec!["revoked-thumbprint".to_string()],
+        };
+        catalog
+            .set_key_slots("dummy", key_slots.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            catalog.get_key_slots("dummy").await.unwrap(),
+            Some(key_slots)
+        );
+    }
+
+    #[tokio::test]
+    async fn federated_bundles_test_happy_path() {
+        let catalog = Catalog::new();
+
+        assert!(catalog.get_federated_bundles().await.unwrap().is_empty());
+
+        let trust_bundle = |trust_domain: &str, sequence_number| TrustBundle {
+            trust_domain: trust_domain.to_string(),
+            jwt_key_set: JWKSet {
+                keys: Vec::new(),
+                spiffe_refresh_hint: 300,
+                spiffe_sequence_number: sequence_number,
+            },
+            x509_key_set: JWKSet {
+                keys: Vec::new(),
+                spiffe_refresh_hint: 300,
+                spiffe_sequence_number: sequence_number,
+            },
+        };
+        catalog
+            .set_federated_bundle(trust_bundle("cloud.contoso.com", 1))
+            .await
+            .unwrap();
+        catalog
+            .set_federated_bundle(trust_bundle("azure.contoso.com", 1))
+            .await
+            .unwrap();
+        catalog
+            .set_federated_bundle(trust_bundle("cloud.contoso.com", 2))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            catalog.get_federated_bundles().await.unwrap(),
+            vec![
+                trust_bundle("azure.contoso.com", 1),
+                trust_bundle("cloud.contoso.com", 2)
+            ]
+        );
+    }
 }
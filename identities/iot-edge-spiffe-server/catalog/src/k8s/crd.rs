@@ -0,0 +1,153 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+
+use core_objects::{AttestationConfig, IdentityTypes, RegistrationEntry};
+use kube::{api::ObjectMeta, CustomResource};
+use serde::{Deserialize, Serialize};
+
+// The entry id is the name of the custom resource, the rest of the entry is the spec.
+// The schema is not generated from the types, see k8s-deployments/server/crd.yaml.
+#[derive(CustomResource, Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[kube(
+    group = "iotedge.azure.com",
+    version = "v1alpha1",
+    kind = "SpiffeRegistrationEntry",
+    namespaced,
+    schema = "disabled"
+)]
+#[serde(rename_all = "camelCase")]
+pub struct SpiffeRegistrationEntrySpec {
+    #[serde(default)]
+    pub other_identities: Vec<IdentityTypes>,
+    pub spiffe_id_path: String,
+    pub attestation_config: AttestationConfig,
+    #[serde(default)]
+    pub admin: bool,
+    #[serde(default)]
+    pub expires_at: u64,
+    #[serde(default)]
+    pub dns_names: Vec<String>,
+    #[serde(default)]
+    pub revision_number: u64,
+    #[serde(default)]
+    pub store_svid: bool,
+    #[serde(default)]
+    pub prefetch: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom_claims: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub federates_with: Vec<String>,
+}
+
+impl From<RegistrationEntry> for SpiffeRegistrationEntry {
+    fn from(entry: RegistrationEntry) -> Self {
+        let spec = SpiffeRegistrationEntrySpec {
+            other_identities: entry.other_identities,
+            spiffe_id_path: entry.spiffe_id_path,
+            attestation_config: entry.attestation_config,
+            admin: entry.admin,
+            expires_at: entry.expires_at,
+            dns_names: entry.dns_names,
+            revision_number: entry.revision_number,
+            store_svid: entry.store_svid,
+            prefetch: entry.prefetch,
+            ttl: entry.ttl,
+            custom_claims: entry.custom_claims,
+            federates_with: entry.federates_with,
+        };
+
+        SpiffeRegistrationEntry {
+            metadata: ObjectMeta {
+                name: Some(entry.id),
+                ..ObjectMeta::default()
+            },
+            spec,
+        }
+    }
+}
+
+impl From<SpiffeRegistrationEntry> for RegistrationEntry {
+    fn from(resource: SpiffeRegistrationEntry) -> Self {
+        let spec = resource.spec;
+
+        RegistrationEntry {
+            id: resource.metadata.name.unwrap_or_default(),
+            other_identities: spec.other_identities,
+            spiffe_id_path: spec.spiffe_id_path,
+            attestation_config: spec.attestation_config,
+            admin: spec.admin,
+            expires_at: spec.expires_at,
+            dns_names: spec.dns_names,
+            revision_number: spec.revision_number,
+            store_svid: spec.store_svid,
+            prefetch: spec.prefetch,
+            ttl: spec.ttl,
+            custom_claims: spec.custom_claims,
+            federates_with: spec.federates_with,
+        }
+    }
+}
+
+// Custom resource names must be lowercase RFC 1123 subdomains.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 253
+        && name.split('.').all(|label| {
+            !label.is_empty()
+                && label
+                    .bytes()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == b'-')
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use core_objects::{EntryNodeAttestation, NodeAttestationPlugin, NodeSelectorType};
+
+    use super::*;
+
+    #[test]
+    fn registration_entry_round_trip() {
+        let entry = RegistrationEntry {
+            id: "entry-1".to_string(),
+            other_identities: vec![IdentityTypes::Custom("custom".to_string())],
+            spiffe_id_path: "path".to_string(),
+            attestation_config: AttestationConfig::Node(EntryNodeAttestation {
+                value: vec![NodeSelectorType::Cluster.to_string()],
+                plugin: NodeAttestationPlugin::Sat,
+            }),
+            admin: true,
+            expires_at: 10,
+            dns_names: vec!["dns".to_string()],
+            revision_number: 2,
+            store_svid: true,
+            prefetch: false,
+            ttl: Some(60),
+            custom_claims: Default::default(),
+            federates_with: Vec::new(),
+        };
+
+        let resource = SpiffeRegistrationEntry::from(entry.clone());
+        assert_eq!(resource.metadata.name.as_deref(), Some("entry-1"));
+
+        let round_trip = RegistrationEntry::from(resource);
+        assert_eq!(
+            serde_json::to_value(&round_trip).unwrap(),
+            serde_json::to_value(&entry).unwrap()
+        );
+    }
+
+    #[test]
+    fn is_valid_name_test() {
+        assert!(is_valid_name("entry-1.iotedge"));
+        assert!(!is_valid_name("Entry"));
+        assert!(!is_valid_name("-entry"));
+        assert!(!is_valid_name("entry_1"));
+        assert!(!is_valid_name(""));
+    }
+}
@@ -0,0 +1,300 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+
+use core_objects::RegistrationEntry;
+use kube::{
+    api::{DeleteParams, ListParams, PostParams},
+    Api,
+};
+
+use crate::{pagination::split_page, Entries, EntryEventStream, Error as CatalogError};
+
+use super::{
+    crd::{is_valid_name, SpiffeRegistrationEntry},
+    error::Error,
+    is_status, Catalog,
+};
+
+// Page size used when listing the custom resources from the API server.
+const LIST_CHUNK_SIZE: u32 = 500;
+
+type BatchErrors = Vec<(String, Box<dyn std::error::Error + Send>)>;
+type BoxedError = Box<dyn std::error::Error + Send>;
+
+#[async_trait::async_trait]
+impl Entries for Catalog {
+    async fn batch_create(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        let ids = entries
+            .iter()
+            .map(|entry| entry.id.clone())
+            .collect::<Vec<_>>();
+        let api = self.api().await.map_err(|err| abort_batch(&ids, &err))?;
+        let mut errors = Vec::new();
+
+        for entry in entries {
+            let id = entry.id.clone();
+
+            if let Err(err) = create_entry(&api, entry).await {
+                errors.push((id, Box::new(err) as _));
+            }
+        }
+
+        errors.is_empty().then(|| ()).ok_or(errors)
+    }
+
+    async fn batch_update(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        let ids = entries
+            .iter()
+            .map(|entry| entry.id.clone())
+            .collect::<Vec<_>>();
+        let api = self.api().await.map_err(|err| abort_batch(&ids, &err))?;
+        let mut errors = Vec::new();
+
+        for entry in entries {
+            let id = entry.id.clone();
+
+            if let Err(err) = update_entry(&api, entry).await {
+                errors.push((id, err));
+            }
+        }
+
+        errors.is_empty().then(|| ()).ok_or(errors)
+    }
+
+    async fn batch_delete(
+        &self,
+        ids: &[String],
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        let api = self.api().await.map_err(|err| abort_batch(ids, &err))?;
+        let mut errors = Vec::new();
+
+        for id in ids {
+            if let Err(err) = delete_entry(&api, id).await {
+                errors.push((id.clone(), Box::new(err) as _));
+            }
+        }
+
+        errors.is_empty().then(|| ()).ok_or(errors)
+    }
+
+    async fn batch_get(
+        &self,
+        ids: &[String],
+    ) -> Vec<(
+        String,
+        Result<RegistrationEntry, Box<dyn std::error::Error + Send>>,
+    )> {
+        let api = match self.api().await {
+            Ok(api) => api,
+            Err(err) => {
+                return abort_batch(ids, &err)
+                    .into_iter()
+                    .map(|(id, err)| (id, Err(err)))
+                    .collect();
+            }
+        };
+
+        let mut results = Vec::new();
+
+        for id in ids {
+            let result = get_entry(&api, id).await.map_err(|err| Box::new(err) as _);
+
+            results.push((id.clone(), result));
+        }
+
+        results
+    }
+
+    async fn get_entry(
+        &self,
+        id: &str,
+    ) -> Result<RegistrationEntry, Box<dyn std::error::Error + Send>> {
+        let api = self.api().await.map_err(|err| Box::new(err) as _)?;
+
+        get_entry(&api, id).await.map_err(|err| Box::new(err) as _)
+    }
+
+    async fn list_all(
+        &self,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<RegistrationEntry>, Option<String>), Box<dyn std::error::Error + Send>> {
+        if page_size == 0 {
+            return Err(Box::new(Error::InvalidPageSize()));
+        }
+
+        self.list_all_inner(page_token, page_size)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn watch(&self) -> Result<EntryEventStream, Box<dyn std::error::Error + Send>> {
+        self.watch_inner().await.map_err(|err| Box::new(err) as _)
+    }
+}
+
+impl Catalog {
+    pub(super) async fn api(&self) -> Result<Api<SpiffeRegistrationEntry>, Error> {
+        let client = self.client().await?;
+
+        Ok(Api::namespaced(client, &self.namespace))
+    }
+
+    // Kubernetes continue tokens are opaque and cannot start from an arbitrary entry id, so the whole
+    // collection is read and sorted by id. The page token is the id of the first entry of the next page.
+    async fn list_all_inner(
+        &self,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<RegistrationEntry>, Option<String>), Error> {
+        let api = self.api().await?;
+
+        let entries = list_resources(&api)
+            .await?
+            .into_iter()
+            .map(|resource| {
+                let entry = RegistrationEntry::from(resource);
+                (entry.id.clone(), entry)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let rows = if let Some(page_token) = page_token {
+            entries.range(page_token..).take(page_size + 1)
+        } else {
+            entries.range::<String, _>(..).take(page_size + 1)
+        }
+        .map(|(id, entry)| (id.clone(), entry.clone()))
+        .collect::<Vec<_>>();
+
+        let (rows, page_token) = split_page(rows, page_size);
+
+        Ok((
+            rows.into_iter().map(|(_id, entry)| entry).collect(),
+            page_token,
+        ))
+    }
+}
+
+pub(super) async fn list_resources(
+    api: &Api<SpiffeRegistrationEntry>,
+) -> Result<Vec<SpiffeRegistrationEntry>, Error> {
+    let mut resources = Vec::new();
+    let mut continue_token: Option<String> = None;
+
+    loop {
+        let mut list_params = ListParams::default().limit(LIST_CHUNK_SIZE);
+        if let Some(continue_token) = &continue_token {
+            list_params = list_params.continue_token(continue_token);
+        }
+
+        let list = api.list(&list_params).await.map_err(Error::Request)?;
+        resources.extend(list.items);
+
+        continue_token = list.metadata.continue_.filter(|token| !token.is_empty());
+        if continue_token.is_none() {
+            return Ok(resources);
+        }
+    }
+}
+
+async fn create_entry(
+    api: &Api<SpiffeRegistrationEntry>,
+    entry: RegistrationEntry,
+) -> Result<(), Error> {
+    if !is_valid_name(&entry.id) {
+        return Err(Error::InvalidEntryId(entry.id));
+    }
+
+    let id = entry.id.clone();
+    let resource = SpiffeRegistrationEntry::from(entry);
+
+    match api.create(&PostParams::default(), &resource).await {
+        Ok(_) => Ok(()),
+        Err(err) if is_status(&err, 409) => Err(Error::DuplicatedEntry(id)),
+        Err(err) => Err(Error::Request(err)),
+    }
+}
+
+// The update carries the resource version that was read, the API server rejects it with a conflict if the
+// resource was modified in between.
+async fn update_entry(
+    api: &Api<SpiffeRegistrationEntry>,
+    mut entry: RegistrationEntry,
+) -> Result<(), BoxedError> {
+    if !is_valid_name(&entry.id) {
+        return Err(Box::new(Error::EntryNotFound(entry.id)));
+    }
+
+    let id = entry.id.clone();
+    let conflict = CatalogError::RevisionConflict {
+        id: id.clone(),
+        revision_number: entry.revision_number,
+    };
+
+    let current = match api.get(&id).await {
+        Ok(current) => current,
+        Err(err) if is_status(&err, 404) => return Err(Box::new(Error::EntryNotFound(id))),
+        Err(err) => return Err(Box::new(Error::Request(err))),
+    };
+
+    if current.spec.revision_number != entry.revision_number {
+        return Err(Box::new(conflict));
+    }
+
+    entry.revision_number += 1;
+    let mut resource = SpiffeRegistrationEntry::from(entry);
+    resource.metadata = current.metadata;
+
+    match api.replace(&id, &PostParams::default(), &resource).await {
+        Ok(_) => Ok(()),
+        Err(err) if is_status(&err, 404) => Err(Box::new(Error::EntryNotFound(id))),
+        Err(err) if is_status(&err, 409) => Err(Box::new(conflict)),
+        Err(err) => Err(Box::new(Error::Request(err))),
+    }
+}
+
+async fn delete_entry(api: &Api<SpiffeRegistrationEntry>, id: &str) -> Result<(), Error> {
+    if !is_valid_name(id) {
+        return Err(Error::EntryNotFound(id.to_string()));
+    }
+
+    match api.delete(id, &DeleteParams::default()).await {
+        Ok(_) => Ok(()),
+        Err(err) if is_status(&err, 404) => Err(Error::EntryNotFound(id.to_string())),
+        Err(err) => Err(Error::Request(err)),
+    }
+}
+
+async fn get_entry(
+    api: &Api<SpiffeRegistrationEntry>,
+    id: &str,
+) -> Result<RegistrationEntry, Error> {
+    if !is_valid_name(id) {
+        return Err(Error::EntryNotFound(id.to_string()));
+    }
+
+    match api.get(id).await {
+        Ok(resource) => Ok(RegistrationEntry::from(resource)),
+        Err(err) if is_status(&err, 404) => Err(Error::EntryNotFound(id.to_string())),
+        Err(err) => Err(Error::Request(err)),
+    }
+}
+
+// The whole batch failed, report the cause against every id so the caller gets one result per input.
+fn abort_batch(ids: &[String], err: &Error) -> BatchErrors {
+    ids.iter()
+        .map(|id| {
+            (
+                id.clone(),
+                Box::new(Error::BatchAborted(err.to_string())) as _,
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,35 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Entry {0} already exists")]
+    DuplicatedEntry(String),
+    #[error("Entry {0} does not exist")]
+    EntryNotFound(String),
+    #[error("Entry id {0} is not a valid kubernetes resource name")]
+    InvalidEntryId(String),
+    #[error("Trust domain {0} is not a valid kubernetes resource name")]
+    InvalidTrustDomain(String),
+    #[error("Key {0} already exists")]
+    DuplicatedKey(String),
+    #[error("Key {0} does not exist")]
+    KeyNotFound(String),
+    #[error("Invalid page size")]
+    InvalidPageSize(),
+    #[error("Could not create kubernetes client {0}")]
+    Client(kube::Error),
+    #[error("Kubernetes API request failed {0}")]
+    Request(kube::Error),
+    #[error("Could not serialize {0}")]
+    Serialize(serde_json::Error),
+    #[error("Could not deserialize {0}")]
+    Deserialize(serde_json::Error),
+    #[error("Batch aborted {0}")]
+    BatchAborted(String),
+    #[error("Trust bundle version {0} is invalid")]
+    InvalidVersion(String),
+    #[error("Watching the registration entries failed {0}")]
+    Watch(kube::runtime::watcher::Error),
+}
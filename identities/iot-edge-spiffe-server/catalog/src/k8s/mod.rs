@@ -0,0 +1,59 @@
+// Copyright (c) Microsoft. All rights reserved.
+pub mod crd;
+mod entries;
+mod error;
+mod trust_bundle_store;
+mod watch;
+
+use kube::Client;
+use server_config::CatalogConfigK8s;
+use tokio::sync::OnceCell;
+
+use crate::Catalog as CatalogTrait;
+
+use error::Error;
+
+// Catalog storing registration entries as SpiffeRegistrationEntry custom resources, so operators can manage
+// them with kubectl or GitOps tools. The JWKs of each trust domain are stored in a config map.
+pub struct Catalog {
+    namespace: String,
+    client: OnceCell<Client>,
+}
+
+impl Catalog {
+    #[must_use]
+    pub fn new(config: &CatalogConfigK8s) -> Self {
+        Catalog {
+            namespace: config.namespace.clone(),
+            client: OnceCell::new(),
+        }
+    }
+
+    // Infer the runtime environment and create the client on first use.
+    async fn client(&self) -> Result<Client, Error> {
+        let client = self
+            .client
+            .get_or_try_init(|| async { Client::try_default().await.map_err(Error::Client) })
+            .await?;
+
+        Ok(client.clone())
+    }
+}
+
+fn is_status(err: &kube::Error, code: u16) -> bool {
+    matches!(err, kube::Error::Api(response) if response.code == code)
+}
+
+#[async_trait::async_trait]
+impl CatalogTrait for Catalog {
+    async fn backend_version(&self) -> Result<Option<String>, Box<dyn std::error::Error + Send>> {
+        let client = self.client().await.map_err(|err| Box::new(err) as _)?;
+
+        let info = client
+            .apiserver_version()
+            .await
+            .map_err(|err| Box::new(Error::Request(err)) as _)?;
+
+        Ok(Some(format!("Kubernetes {}", info.git_version)))
+    }
+}
@@ -0,0 +1,390 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::BTreeMap;
+
+use core_objects::{KeySlots, TrustBundle, JWK};
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::{
+    api::{ObjectMeta, PostParams},
+    Api,
+};
+
+use crate::TrustBundleStore;
+
+use super::{crd::is_valid_name, error::Error, is_status, Catalog};
+
+const JWKS_CONFIG_MAP_PREFIX: &str = "iotedge-spiffe-server-jwks";
+const VERSION_ANNOTATION: &str = "iotedge.azure.com/jwk-set-version";
+// The key slots are kept apart from the jwks, every value of the jwks config map is a key.
+const KEY_SLOTS_CONFIG_MAP_PREFIX: &str = "iotedge-spiffe-server-key-slots";
+const KEY_SLOTS_KEY: &str = "key_slots";
+// One config map for the bundles of all the federated trust domains, keyed by trust domain.
+const FEDERATED_BUNDLES_CONFIG_MAP: &str = "iotedge-spiffe-server-federated-bundles";
+
+#[async_trait::async_trait]
+impl TrustBundleStore for Catalog {
+    async fn add_jwk(
+        &self,
+        trust_domain: &str,
+        jwk: JWK,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        let serialized_jwk =
+            serde_json::to_string(&jwk).map_err(|err| Box::new(Error::Serialize(err)) as _)?;
+
+        let added = self
+            .update_jwk_set(trust_domain, &jwk.kid, Some(serialized_jwk))
+            .await
+            .map_err(|err| Box::new(err) as _)?;
+
+        added
+            .then(|| ())
+            .ok_or_else(|| Box::new(Error::DuplicatedKey(jwk.kid)) as _)
+    }
+
+    async fn remove_jwk(
+        &self,
+        trust_domain: &str,
+        kid: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        let removed = self
+            .update_jwk_set(trust_domain, kid, None)
+            .await
+            .map_err(|err| Box::new(err) as _)?;
+
+        removed
+            .then(|| ())
+            .ok_or_else(|| Box::new(Error::KeyNotFound(kid.to_string())) as _)
+    }
+
+    async fn get_jwk(
+        &self,
+        trust_domain: &str,
+    ) -> Result<(Vec<JWK>, usize), Box<dyn std::error::Error + Send>> {
+        self.get_jwk_inner(trust_domain)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn get_key_slots(
+        &self,
+        trust_domain: &str,
+    ) -> Result<Option<KeySlots>, Box<dyn std::error::Error + Send>> {
+        self.get_key_slots_inner(trust_domain)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn set_key_slots(
+        &self,
+        trust_domain: &str,
+        key_slots: KeySlots,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.set_key_slots_inner(trust_domain, &key_slots)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn set_federated_bundle(
+        &self,
+        trust_bundle: TrustBundle,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.set_federated_bundle_inner(&trust_bundle)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn get_federated_bundles(
+        &self,
+    ) -> Result<Vec<TrustBundle>, Box<dyn std::error::Error + Send>> {
+        self.get_federated_bundles_inner()
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+}
+
+impl Catalog {
+    async fn config_map_api(&self) -> Result<Api<ConfigMap>, Error> {
+        let client = self.client().await?;
+
+        Ok(Api::namespaced(client, &self.namespace))
+    }
+
+    // Add the jwk (Some) or remove it (None) and bump the trust domain version in the same write.
+    // Returns false if the jwk already exists when adding, or does not exist when removing.
+    async fn update_jwk_set(
+        &self,
+        trust_domain: &str,
+        kid: &str,
+        jwk: Option<String>,
+    ) -> Result<bool, Error> {
+        let api = self.config_map_api().await?;
+        let name = config_map_name(trust_domain)?;
+
+        loop {
+            let (mut config_map, exists) = match api.get(&name).await {
+                Ok(config_map) => (config_map, true),
+                Err(err) if is_status(&err, 404) => {
+                    let config_map = ConfigMap {
+                        metadata: ObjectMeta {
+                            name: Some(name.clone()),
+                            ..ObjectMeta::default()
+                        },
+                        ..ConfigMap::default()
+                    };
+
+                    (config_map, false)
+                }
+                Err(err) => return Err(Error::Request(err)),
+            };
+
+            let version = version(&config_map)?;
+
+            let data = config_map.data.get_or_insert_with(BTreeMap::new);
+            match &jwk {
+                Some(jwk) => {
+                    if data.contains_key(kid) {
+                        return Ok(false);
+                    }
+                    data.insert(kid.to_string(), jwk.clone());
+                }
+                None => {
+                    if data.remove(kid).is_none() {
+                        return Ok(false);
+                    }
+                }
+            }
+
+            config_map
+                .metadata
+                .annotations
+                .get_or_insert_with(BTreeMap::new)
+                .insert(VERSION_ANNOTATION.to_string(), (version + 1).to_string());
+
+            // The replace carries the resource version read above, the API server rejects it if another
+            // replica wrote the config map in between.
+            let result = if exists {
+                api.replace(&name, &PostParams::default(), &config_map)
+                    .await
+            } else {
+                api.create(&PostParams::default(), &config_map).await
+            };
+
+            match result {
+                Ok(_) => return Ok(true),
+                Err(err) if is_status(&err, 409) => continue,
+                Err(err) => return Err(Error::Request(err)),
+            }
+        }
+    }
+
+    async fn get_jwk_inner(&self, trust_domain: &str) -> Result<(Vec<JWK>, usize), Error> {
+        let api = self.config_map_api().await?;
+        let name = config_map_name(trust_domain)?;
+
+        let config_map = match api.get(&name).await {
+            Ok(config_map) => config_map,
+            Err(err) if is_status(&err, 404) => return Ok((Vec::new(), 0)),
+            Err(err) => return Err(Error::Request(err)),
+        };
+
+        let version = version(&config_map)?;
+        let jwks = config_map
+            .data
+            .unwrap_or_default()
+            .values()
+            .map(|jwk| serde_json::from_str(jwk).map_err(Error::Deserialize))
+            .collect::<Result<Vec<JWK>, _>>()?;
+
+        Ok((jwks, version))
+    }
+
+    async fn get_key_slots_inner(&self, trust_domain: &str) -> Result<Option<KeySlots>, Error> {
+        let api = self.config_map_api().await?;
+        let name = key_slots_config_map_name(trust_domain)?;
+
+        let config_map = match api.get(&name).await {
+            Ok(config_map) => config_map,
+            Err(err) if is_status(&err, 404) => return Ok(None),
+            Err(err) => return Err(Error::Request(err)),
+        };
+
+        config_map
+            .data
+            .unwrap_or_default()
+            .get(KEY_SLOTS_KEY)
+            .map(|key_slots| serde_json::from_str(key_slots).map_err(Error::Deserialize))
+            .transpose()
+    }
+
+    async fn set_key_slots_inner(
+        &self,
+        trust_domain: &str,
+        key_slots: &KeySlots,
+    ) -> Result<(), Error> {
+        let api = self.config_map_api().await?;
+        let name = key_slots_config_map_name(trust_domain)?;
+        let serialized_key_slots = serde_json::to_string(key_slots).map_err(Error::Serialize)?;
+
+        let mut config_map = ConfigMap {
+            metadata: ObjectMeta {
+                name: Some(name.clone()),
+                ..ObjectMeta::default()
+            },
+            data: Some(BTreeMap::from([(
+                KEY_SLOTS_KEY.to_string(),
+                serialized_key_slots,
+            )])),
+            ..ConfigMap::default()
+        };
+
+        loop {
+            // The saved slots are replaced whatever they were, only the resource version of the config map
+            // is needed to write it.
+            let result = match api.get(&name).await {
+                Ok(existing) => {
+                    config_map.metadata.resource_version = existing.metadata.resource_version;
+                    api.replace(&name, &PostParams::default(), &config_map)
+                        .await
+                }
+                Err(err) if is_status(&err, 404) => {
+                    config_map.metadata.resource_version = None;
+                    api.create(&PostParams::default(), &config_map).await
+                }
+                Err(err) => return Err(Error::Request(err)),
+            };
+
+            match result {
+                Ok(_) => return Ok(()),
+                Err(err) if is_status(&err, 409) => continue,
+                Err(err) => return Err(Error::Request(err)),
+            }
+        }
+    }
+
+    async fn set_federated_bundle_inner(&self, trust_bundle: &TrustBundle) -> Result<(), Error> {
+        let api = self.config_map_api().await?;
+        let serialized_bundle = serde_json::to_string(trust_bundle).map_err(Error::Serialize)?;
+
+        loop {
+            // The bundles of the other trust domains are kept, the resource version makes sure none was
+            // written since the config map was read.
+            let result = match api.get(FEDERATED_BUNDLES_CONFIG_MAP).await {
+                Ok(mut config_map) => {
+                    config_map
+                        .data
+                        .get_or_insert_with(BTreeMap::new)
+                        .insert(trust_bundle.trust_domain.clone(), serialized_bundle.clone());
+                    api.replace(
+                        FEDERATED_BUNDLES_CONFIG_MAP,
+                        &PostParams::default(),
+                        &config_map,
+                    )
+                    .await
+                }
+                Err(err) if is_status(&err, 404) => {
+                    let config_map = ConfigMap {
+                        metadata: ObjectMeta {
+                            name: Some(FEDERATED_BUNDLES_CONFIG_MAP.to_string()),
+                            ..ObjectMeta::default()
+                        },
+                        data: Some(BTreeMap::from([(
+                            trust_bundle.trust_domain.clone(),
+                            serialized_bundle.clone(),
+                        )])),
+                        ..ConfigMap::default()
+                    };
+                    api.create(&PostParams::default(), &config_map).await
+                }
+                Err(err) => return Err(Error::Request(err)),
+            };
+
+            match result {
+                Ok(_) => return Ok(()),
+                Err(err) if is_status(&err, 409) => continue,
+                Err(err) => return Err(Error::Request(err)),
+            }
+        }
+    }
+
+    async fn get_federated_bundles_inner(&self) -> Result<Vec<TrustBundle>, Error> {
+        let api = self.config_map_api().await?;
+
+        let config_map = match api.get(FEDERATED_BUNDLES_CONFIG_MAP).await {
+            Ok(config_map) => config_map,
+            Err(err) if is_status(&err, 404) => return Ok(Vec::new()),
+            Err(err) => return Err(Error::Request(err)),
+        };
+
+        config_map
+            .data
+            .unwrap_or_default()
+            .values()
+            .map(|bundle| serde_json::from_str(bundle).map_err(Error::Deserialize))
+            .collect()
+    }
+}
+
+fn config_map_name(trust_domain: &str) -> Result<String, Error> {
+    let name = format!("{}-{}", JWKS_CONFIG_MAP_PREFIX, trust_domain);
+
+    is_valid_name(&name)
+        .then(|| name)
+        .ok_or_else(|| Error::InvalidTrustDomain(trust_domain.to_string()))
+}
+
+fn key_slots_config_map_name(trust_domain: &str) -> Result<String, Error> {
+    let name = format!("{}-{}", KEY_SLOTS_CONFIG_MAP_PREFIX, trust_domain);
+
+    is_valid_name(&name)
+        .then(|| name)
+        .ok_or_else(|| Error::InvalidTrustDomain(trust_domain.to_string()))
+}
+
+fn version(config_map: &ConfigMap) -> Result<usize, Error> {
+    let version = config_map
+        .metadata
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(VERSION_ANNOTATION));
+
+    match version {
+        Some(version) => version
+            .parse()
+            .map_err(|_| Error::InvalidVersion(version.clone())),
+        None => Ok(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::*;
+
+    #[test]
+    fn config_map_name_test() {
+        assert_eq!(
+            config_map_name("iotedge").unwrap(),
+            "iotedge-spiffe-server-jwks-iotedge"
+        );
+        assert_matches!(
+            config_map_name("Not_Valid"),
+            Err(Error::InvalidTrustDomain(_))
+        );
+        assert_eq!(
+            key_slots_config_map_name("iotedge").unwrap(),
+            "iotedge-spiffe-server-key-slots-iotedge"
+        );
+    }
+
+    #[test]
+    fn version_from_annotation() {
+        let mut config_map = ConfigMap::default();
+        assert_eq!(version(&config_map).unwrap(), 0);
+
+        let mut annotations = BTreeMap::new();
+        annotations.insert(VERSION_ANNOTATION.to_string(), "3".to_string());
+        config_map.metadata.annotations = Some(annotations);
+        assert_eq!(version(&config_map).unwrap(), 3);
+    }
+}
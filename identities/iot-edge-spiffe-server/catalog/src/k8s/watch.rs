@@ -0,0 +1,149 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::{HashMap, HashSet};
+
+use core_objects::RegistrationEntry;
+use futures_util::{stream, StreamExt};
+use kube::{
+    api::ListParams,
+    runtime::watcher::{self, Event},
+};
+
+use crate::{EntryEvent, EntryEventStream};
+
+use super::{
+    crd::{SpiffeRegistrationEntry, SpiffeRegistrationEntrySpec},
+    entries::list_resources,
+    error::Error,
+    Catalog,
+};
+
+type BoxedError = Box<dyn std::error::Error + Send>;
+
+impl Catalog {
+    // The watcher only reports the current state of the resources, not whether they were created or updated,
+    // so the last known spec of every resource is kept to tell the events apart and skip resyncs.
+    pub(super) async fn watch_inner(&self) -> Result<EntryEventStream, Error> {
+        let api = self.api().await?;
+
+        // Changes made between the call and the first list of the watcher are reported by the first restart.
+        let mut state = WatchState::new(list_resources(&api).await?);
+
+        let events = watcher::watcher(api, ListParams::default())
+            .map(move |event| match event {
+                Ok(event) => state.apply(event).into_iter().map(Ok).collect::<Vec<_>>(),
+                Err(err) => vec![Err(Box::new(Error::Watch(err)) as BoxedError)],
+            })
+            .flat_map(stream::iter);
+
+        Ok(Box::pin(events))
+    }
+}
+
+struct WatchState {
+    known: HashMap<String, SpiffeRegistrationEntrySpec>,
+}
+
+impl WatchState {
+    fn new(resources: Vec<SpiffeRegistrationEntry>) -> Self {
+        let known = resources
+            .into_iter()
+            .map(|resource| (resource.metadata.name.unwrap_or_default(), resource.spec))
+            .collect();
+
+        WatchState { known }
+    }
+
+    fn apply(&mut self, event: Event<SpiffeRegistrationEntry>) -> Vec<EntryEvent> {
+        match event {
+            Event::Applied(resource) => self.applied(resource).into_iter().collect(),
+            Event::Deleted(resource) => {
+                let id = resource.metadata.name.unwrap_or_default();
+
+                self.known
+                    .remove(&id)
+                    .map(|_spec| EntryEvent::Deleted(id))
+                    .into_iter()
+                    .collect()
+            }
+            Event::Restarted(resources) => {
+                let mut events = Vec::new();
+                let mut deleted = self.known.keys().cloned().collect::<HashSet<_>>();
+
+                for resource in resources {
+                    if let Some(id) = &resource.metadata.name {
+                        deleted.remove(id);
+                    }
+
+                    events.extend(self.applied(resource));
+                }
+
+                for id in deleted {
+                    self.known.remove(&id);
+                    events.push(EntryEvent::Deleted(id));
+                }
+
+                events
+            }
+        }
+    }
+
+    fn applied(&mut self, resource: SpiffeRegistrationEntry) -> Option<EntryEvent> {
+        let id = resource.metadata.name.clone().unwrap_or_default();
+
+        match self.known.insert(id, resource.spec.clone()) {
+            None => Some(EntryEvent::Created(RegistrationEntry::from(resource))),
+            Some(spec) if spec != resource.spec => {
+                Some(EntryEvent::Updated(RegistrationEntry::from(resource)))
+            }
+            Some(_spec) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::*;
+
+    fn resource(id: &str, spiffe_id_path: &str) -> SpiffeRegistrationEntry {
+        SpiffeRegistrationEntry::from(RegistrationEntry {
+            spiffe_id_path: spiffe_id_path.to_string(),
+            ..RegistrationEntry::test_entry(id)
+        })
+    }
+
+    #[test]
+    fn apply_events() {
+        let mut state = WatchState::new(vec![resource("a", "path")]);
+
+        // Resync of an entry that did not change.
+        let events = state.apply(Event::Applied(resource("a", "path")));
+        assert!(events.is_empty());
+
+        let events = state.apply(Event::Applied(resource("a", "other_path")));
+        assert_matches!(events.as_slice(), [EntryEvent::Updated(entry)] if entry.id == "a");
+
+        let events = state.apply(Event::Applied(resource("b", "path")));
+        assert_matches!(events.as_slice(), [EntryEvent::Created(entry)] if entry.id == "b");
+
+        let events = state.apply(Event::Deleted(resource("b", "path")));
+        assert_matches!(events.as_slice(), [EntryEvent::Deleted(id)] if id == "b");
+    }
+
+    #[test]
+    fn apply_restart() {
+        let mut state = WatchState::new(vec![resource("a", "path"), resource("b", "path")]);
+
+        let events = state.apply(Event::Restarted(vec![
+            resource("a", "path"),
+            resource("c", "path"),
+        ]));
+
+        assert_matches!(
+            events.as_slice(),
+            [EntryEvent::Created(entry), EntryEvent::Deleted(id)] if entry.id == "c" && id == "b"
+        );
+    }
+}
@@ -11,26 +11,228 @@
     clippy::missing_panics_doc
 )]
 
-use std::sync::Arc;
+use std::{collections::BTreeSet, pin::Pin, sync::Arc};
 
-use core_objects::{RegistrationEntry, JWK};
+use core_objects::{
+    AdminOperation, AgentBan, AttestationConfig, AttestedAgent, IssuedSvid, JWKSetVersion,
+    KeySlots, RegistrationEntry, TrustBundle, JWK,
+};
+use futures_util::{future, Stream, StreamExt, TryStreamExt};
 use server_config::CatalogConfig;
 
+mod admin_audit;
+mod agent_bans;
+mod attested_agents;
+mod audit_log;
+#[cfg(feature = "etcd")]
+pub mod etcd;
+#[cfg(feature = "chaos")]
+pub mod fault_injection;
+mod filter;
 pub mod inmemory;
+#[cfg(feature = "k8s")]
+pub mod k8s;
+mod pagination;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+mod pruning;
+mod svid_audit;
+
+pub use admin_audit::{AdminAudit, AdminOperationFilter};
+pub use agent_bans::AgentBans;
+pub use attested_agents::AttestedAgents;
+pub use filter::{AttestationPlugin, EntryFilter, UnknownPlugin};
+pub use pagination::scan_entries;
+use pagination::scan_entries_from;
+pub use pruning::EntryPruner;
+pub use svid_audit::{IssuedSvidFilter, SvidAudit};
+
+// Page size used to scan the catalog when a backend has no selector index.
+const SELECTOR_SCAN_PAGE_SIZE: usize = 100;
+
+// Page size used to scan the catalog when a backend cannot filter the entries itself.
+const FILTER_SCAN_PAGE_SIZE: usize = 100;
+
+// Page size used to scan the catalog when exporting a snapshot.
+const SNAPSHOT_SCAN_PAGE_SIZE: usize = 100;
+
+// Number of JWK set versions kept in the trust bundle history.
+pub const JWK_SET_HISTORY_SIZE: usize = 10;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0} is not supported by this catalog")]
+    Unsupported(&'static str),
+    #[error("The {0} catalog backend is not enabled in this build")]
+    BackendDisabled(&'static str),
+    #[error("Cannot create the {0} catalog: {1}")]
+    Backend(&'static str, Box<dyn std::error::Error + Send>),
+    #[error("Entry {id} was modified since revision {revision_number}")]
+    RevisionConflict { id: String, revision_number: u64 },
+    #[error("Entry {0} was not applied, another entry of the transaction failed")]
+    TransactionAborted(String),
+    #[error("The page size must be greater than 0")]
+    InvalidPageSize,
+}
+
+/// Change to a registration entry, as returned by `Entries::watch`.
+#[derive(Clone, Debug)]
+pub enum EntryEvent {
+    Created(RegistrationEntry),
+    Updated(RegistrationEntry),
+    Deleted(String),
+}
+
+pub type EntryEventStream =
+    Pin<Box<dyn Stream<Item = Result<EntryEvent, Box<dyn std::error::Error + Send>>> + Send>>;
 
 pub struct CatalogFactory {}
 
 impl CatalogFactory {
-    #[must_use]
-    pub fn get(config: &CatalogConfig) -> Arc<dyn Catalog> {
+    // Backends are behind cargo features so a server build can leave out the clients it does not use.
+    pub fn get(config: &CatalogConfig) -> Result<Arc<dyn Catalog>, Error> {
         match config {
             CatalogConfig::Disk => unimplemented!(),
-            CatalogConfig::Memory => Arc::new(inmemory::Catalog::new()),
+            CatalogConfig::Memory => Ok(Arc::new(inmemory::Catalog::new())),
+            #[cfg(feature = "etcd")]
+            CatalogConfig::Etcd(config) => Ok(Arc::new(etcd::Catalog::new(config))),
+            #[cfg(not(feature = "etcd"))]
+            CatalogConfig::Etcd(_) => Err(Error::BackendDisabled("etcd")),
+            #[cfg(feature = "k8s")]
+            CatalogConfig::K8s(config) => Ok(Arc::new(k8s::Catalog::new(config))),
+            #[cfg(not(feature = "k8s"))]
+            CatalogConfig::K8s(_) => Err(Error::BackendDisabled("k8s")),
+            #[cfg(feature = "postgres")]
+            CatalogConfig::Postgres(config) => {
+                Ok(Arc::new(postgres::Catalog::new(config).map_err(|err| {
+                    Error::Backend("postgres", Box::new(err))
+                })?))
+            }
+            #[cfg(not(feature = "postgres"))]
+            CatalogConfig::Postgres(_) => Err(Error::BackendDisabled("postgres")),
         }
     }
 }
 
-pub trait Catalog: Entries + TrustBundleStore {}
+#[async_trait::async_trait]
+pub trait Catalog: Entries + TrustBundleStore {
+    /// Version reported by the backend storing the catalog, reaching it also checks it is available.
+    ///
+    /// ## Returns
+    /// * `Ok(Some(version))` - The version of the backend
+    /// * `Ok(None)` - The catalog is stored in the server process
+    /// * `Err(e)` - the backend could not be reached
+    async fn backend_version(&self) -> Result<Option<String>, Box<dyn std::error::Error + Send>> {
+        Ok(None)
+    }
+
+    /// Add a ban of agents, replacing the ban with the same id if there is one.
+    ///
+    /// ## Returns
+    /// * `Ok(())` - Successfully added the ban
+    /// * `Err(e)` - an error occurred while adding the ban
+    async fn add_agent_ban(&self, _ban: AgentBan) -> Result<(), Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::Unsupported("Agent bans")))
+    }
+
+    /// Remove a ban of agents.
+    ///
+    /// ## Returns
+    /// * `Ok(bool)` - Whether there was a ban with this id
+    /// * `Err(e)` - an error occurred while removing the ban
+    async fn remove_agent_ban(&self, _id: &str) -> Result<bool, Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::Unsupported("Agent bans")))
+    }
+
+    /// Get all the bans of agents.
+    ///
+    /// ## Returns
+    /// * `Ok(Vec<AgentBan>)` - The bans, sorted by id
+    /// * `Err(e)` - an error occurred while getting the bans
+    async fn get_agent_bans(&self) -> Result<Vec<AgentBan>, Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::Unsupported("Agent bans")))
+    }
+
+    /// Add an attested agent, or update it if it is already known. The `svid_expiry` of an agent is never
+    /// moved back.
+    ///
+    /// ## Returns
+    /// * `Ok(())` - Successfully recorded the agent
+    /// * `Err(e)` - an error occurred while recording the agent
+    async fn record_attested_agent(
+        &self,
+        _agent: AttestedAgent,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::Unsupported("Attested agents")))
+    }
+
+    /// List the attested agents, ordered by id. Pages work the same way as the ones of `Entries::list_all`.
+    ///
+    /// ## Returns
+    /// * `Ok((Vec<AttestedAgent>, Option<String>))` - The agents of the page with the token of the next page, if any
+    /// * `Err(e)` - an error occurred while listing the agents
+    async fn list_attested_agents(
+        &self,
+        _page_token: Option<String>,
+        _page_size: usize,
+    ) -> Result<(Vec<AttestedAgent>, Option<String>), Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::Unsupported("Attested agents")))
+    }
+
+    /// Append issued SVIDs to the audit log. Records are never updated nor deleted.
+    ///
+    /// ## Returns
+    /// * `Ok(())` - Successfully recorded the SVIDs
+    /// * `Err(e)` - an error occurred while recording the SVIDs
+    async fn record_issued_svids(
+        &self,
+        _svids: Vec<IssuedSvid>,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::Unsupported("SVID audit")))
+    }
+
+    /// List the issued SVIDs matching the filter, ordered by id. Pages work the same way as the ones of
+    /// `Entries::list_all`.
+    ///
+    /// ## Returns
+    /// * `Ok((Vec<IssuedSvid>, Option<String>))` - The SVIDs of the page with the token of the next page, if any
+    /// * `Err(e)` - an error occurred while listing the SVIDs
+    async fn list_issued_svids(
+        &self,
+        _filter: &IssuedSvidFilter,
+        _page_token: Option<String>,
+        _page_size: usize,
+    ) -> Result<(Vec<IssuedSvid>, Option<String>), Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::Unsupported("SVID audit")))
+    }
+
+    /// Append a change made through the admin API to the audit log. Records are never updated nor deleted.
+    ///
+    /// ## Returns
+    /// * `Ok(())` - Successfully recorded the operation
+    /// * `Err(e)` - an error occurred while recording the operation
+    async fn record_admin_operation(
+        &self,
+        _operation: AdminOperation,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::Unsupported("Admin audit")))
+    }
+
+    /// List the admin operations matching the filter, ordered by id. Pages work the same way as the ones of
+    /// `Entries::list_all`.
+    ///
+    /// ## Returns
+    /// * `Ok((Vec<AdminOperation>, Option<String>))` - The operations of the page with the token of the next page, if any
+    /// * `Err(e)` - an error occurred while listing the operations
+    async fn list_admin_operations(
+        &self,
+        _filter: &AdminOperationFilter,
+        _page_token: Option<String>,
+        _page_size: usize,
+    ) -> Result<(Vec<AdminOperation>, Option<String>), Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::Unsupported("Admin audit")))
+    }
+}
 
 /// Entries are writen from the identity manager into the server. Entries contains all the necessary information
 /// to identify a workload and issue a new about a SPIFFE identity to it.
@@ -69,12 +271,16 @@ pub trait Entries: Sync + Send {
 
     /// Batch update registration entries
     ///
+    /// An entry is only updated if its revision number matches the revision number in the catalog. The catalog
+    /// then stores the entry with the next revision number.
+    ///
     /// ## Arguments
     /// * `Vec<RegistrationEntry>` -Vector containing all the ids to update.
     ///
     /// ## Returns
     /// * `Vec<(String, Result<(), Error)>` - A vector the size of the input "entries". The first parameter
-    /// of the tuple is the entryId, the second parameter is () if successful or an error
+    /// of the tuple is the entryId, the second parameter is () if successful or an error. `Error::RevisionConflict`
+    /// if the entry was modified since the given revision.
     async fn batch_update(
         &self,
         entries: Vec<RegistrationEntry>,
@@ -93,6 +299,34 @@ pub trait Entries: Sync + Send {
         ids: &[String],
     ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>>;
 
+    /// Transactional batch create: either all the entries are created or none of them.
+    ///
+    /// ## Returns
+    /// * `Err(Vec<(String, Error)>)` - Nothing was created. The entries that failed are reported with their error,
+    /// the other entries of the batch with `Error::TransactionAborted`.
+    async fn batch_create_transactional(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        Err(unsupported_batch(entries.iter().map(|entry| &entry.id)))
+    }
+
+    /// Transactional batch update, see `batch_create_transactional` and `batch_update`.
+    async fn batch_update_transactional(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        Err(unsupported_batch(entries.iter().map(|entry| &entry.id)))
+    }
+
+    /// Transactional batch delete, see `batch_create_transactional`.
+    async fn batch_delete_transactional(
+        &self,
+        ids: &[String],
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        Err(unsupported_batch(ids.iter()))
+    }
+
     /// List all resgitration entries
     ///
     /// ## Arguments
@@ -108,6 +342,48 @@ pub trait Entries: Sync + Send {
         page_size: usize,
     ) -> Result<(Vec<RegistrationEntry>, Option<String>), Box<dyn std::error::Error + Send>>;
 
+    /// List the registration entries matching a filter. Pages are the same as the ones of `list_all`, only
+    /// with the matching entries. The default implementation scans the catalog from the page token, backends
+    /// should override it with a query.
+    ///
+    /// ## Arguments
+    /// * `filter` - criteria the entries must match.
+    /// * `page_token` - page token, was returned from previous list_filtered(_) call with the same filter.
+    /// * `page_size` - how many matching entries in the page.
+    ///
+    /// ## Returns
+    /// * `Ok((Vec<RegistrationEntry>, Option<String>))` - The matching entries in the requested page with the page token of the next page. If no more page, page_token is None.
+    /// * `Err(e)` - an error occurred while trying to list the entries
+    async fn list_filtered(
+        &self,
+        filter: &EntryFilter,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<RegistrationEntry>, Option<String>), Box<dyn std::error::Error + Send>> {
+        if filter.is_empty() {
+            return self.list_all(page_token, page_size).await;
+        }
+        if page_size == 0 {
+            return Err(Box::new(Error::InvalidPageSize));
+        }
+
+        // One more entry than the page size: if it exists, it starts the next page.
+        let mut entries: Vec<RegistrationEntry> =
+            scan_entries_from(self, page_token, FILTER_SCAN_PAGE_SIZE)
+                .try_filter(|entry| future::ready(filter.matches(entry)))
+                .take(page_size + 1)
+                .try_collect()
+                .await?;
+
+        let page_token = if entries.len() > page_size {
+            entries.pop().map(|entry| entry.id)
+        } else {
+            None
+        };
+
+        Ok((entries, page_token))
+    }
+
     /// Batch get registration entries
     ///
     /// ## Arguments
@@ -119,6 +395,104 @@ pub trait Entries: Sync + Send {
         &self,
         id: &str,
     ) -> Result<RegistrationEntry, Box<dyn std::error::Error + Send>>;
+
+    /// Get all the registration entries whose selectors are all contained in the given selectors.
+    /// The default implementation scans the whole catalog, backends should override it with an index.
+    ///
+    /// ## Arguments
+    /// * `selectors` - selectors of the workload or node.
+    ///
+    /// ## Returns
+    /// * `Ok(Vec<RegistrationEntry>)` - The matching entries, sorted by id
+    /// * `Err(e)` - an error occurred while getting the entries
+    async fn get_entries_by_selectors(
+        &self,
+        selectors: &BTreeSet<String>,
+    ) -> Result<Vec<RegistrationEntry>, Box<dyn std::error::Error + Send>> {
+        scan_entries(self, SELECTOR_SCAN_PAGE_SIZE)
+            .try_filter(|entry| {
+                future::ready(
+                    entry_selectors(entry)
+                        .iter()
+                        .all(|selector| selectors.contains(selector)),
+                )
+            })
+            .try_collect()
+            .await
+    }
+
+    /// Export all the registration entries, e.g. to back them up before moving to a new server.
+    /// The entries are read page by page, entries changed during the export may or may not be included.
+    ///
+    /// ## Returns
+    /// * `Ok(Vec<RegistrationEntry>)` - All the entries, sorted by id
+    /// * `Err(e)` - an error occurred while reading the entries
+    async fn export_snapshot(
+        &self,
+    ) -> Result<Vec<RegistrationEntry>, Box<dyn std::error::Error + Send>> {
+        scan_entries(self, SNAPSHOT_SCAN_PAGE_SIZE)
+            .try_collect()
+            .await
+    }
+
+    /// Import registration entries exported by `export_snapshot`. The entries keep their id and revision number.
+    /// An entry that already exists in the catalog is not overwritten and is reported as an error.
+    ///
+    /// ## Arguments
+    /// * `entries` - entries of the snapshot.
+    ///
+    /// ## Returns
+    /// * `Vec<(String, Result<(), Error)>` - The first parameter of the tuple is the entryId, the second
+    /// parameter is () if successful or an error
+    async fn import_snapshot(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        self.batch_create(entries).await
+    }
+
+    /// Watch the changes to the registration entries made after the call.
+    /// An error in the stream means events may have been missed, the watcher should list the entries again.
+    ///
+    /// ## Returns
+    /// * `Ok(EntryEventStream)` - Stream of the create, update and delete events, in the order they were applied
+    /// * `Err(e)` - an error occurred while starting the watch
+    async fn watch(&self) -> Result<EntryEventStream, Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::Unsupported("watch")))
+    }
+}
+
+fn unsupported_batch<'a>(
+    ids: impl Iterator<Item = &'a String>,
+) -> Vec<(String, Box<dyn std::error::Error + Send>)> {
+    ids.map(|id| {
+        (
+            id.clone(),
+            Box::new(Error::Unsupported("Transactional batch")) as _,
+        )
+    })
+    .collect()
+}
+
+// A transactional batch failed: the entries that did not fail themselves are reported aborted.
+pub(crate) fn abort_transaction(
+    ids: &[String],
+    mut errors: Vec<(String, Box<dyn std::error::Error + Send>)>,
+) -> Vec<(String, Box<dyn std::error::Error + Send>)> {
+    for id in ids {
+        if !errors.iter().any(|(failed_id, _)| failed_id == id) {
+            errors.push((id.clone(), Box::new(Error::TransactionAborted(id.clone()))));
+        }
+    }
+
+    errors
+}
+
+fn entry_selectors(entry: &RegistrationEntry) -> &[String] {
+    match &entry.attestation_config {
+        AttestationConfig::Workload(workload_attestation) => &workload_attestation.value,
+        AttestationConfig::Node(node_attestation) => &node_attestation.value,
+    }
 }
 
 /// The trust bundle store contains all the public keys necessary to validate  JWT tokens or trust certificates.
@@ -169,4 +543,97 @@ pub trait TrustBundleStore: Sync + Send {
         &self,
         trust_domain: &str,
     ) -> Result<(Vec<JWK>, usize), Box<dyn std::error::Error + Send>>;
+
+    /// get the last versions of the jwk set for given trust domain, most recent first.
+    /// A version is recorded every time a key is added or removed, at most JWK_SET_HISTORY_SIZE are kept.
+    ///
+    /// ## Arguments
+    /// * `trust_domain` - trust domain for the key.
+    ///
+    /// ## Returns
+    /// * `Ok(Vec<JWKSetVersion>)` - The versions of the jwk set with their creation time
+    /// * `Err(e)` - an error occurred while getting the history
+    async fn get_jwk_history(
+        &self,
+        _trust_domain: &str,
+    ) -> Result<Vec<JWKSetVersion>, Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::Unsupported("Trust bundle history")))
+    }
+
+    /// re-publish the keys of a previous version of the jwk set. The rollback is a new version of the set.
+    /// The keys are not checked, the key manager checks them against its signing keys before the rollback.
+    ///
+    /// ## Arguments
+    /// * `trust_domain` - trust domain for the key.
+    /// * `version` - version to roll back to, must still be in the history.
+    ///
+    /// ## Returns
+    /// * `Ok(usize)` - The new version number
+    /// * `Err(e)` - an error occurred while rolling back
+    async fn rollback_jwk(
+        &self,
+        _trust_domain: &str,
+        _version: usize,
+    ) -> Result<usize, Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::Unsupported("Trust bundle rollback")))
+    }
+
+    /// get the signing keys of the key manager saved for given trust domain.
+    ///
+    /// ## Arguments
+    /// * `trust_domain` - trust domain for the keys.
+    ///
+    /// ## Returns
+    /// * `Ok(Some(KeySlots))` - The keys saved by the last key manager
+    /// * `Ok(None)` - No keys were saved for the trust domain
+    /// * `Err(e)` - an error occurred while getting the keys
+    async fn get_key_slots(
+        &self,
+        _trust_domain: &str,
+    ) -> Result<Option<KeySlots>, Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::Unsupported("Key slots")))
+    }
+
+    /// save the signing keys of the key manager for given trust domain, replacing the saved ones.
+    ///
+    /// ## Arguments
+    /// * `trust_domain` - trust domain for the keys.
+    /// * `key_slots` - the keys in the slots of the key manager.
+    ///
+    /// ## Returns
+    /// * `Ok(())` - Successfully saved the keys
+    /// * `Err(e)` - an error occurred while saving the keys
+    async fn set_key_slots(
+        &self,
+        _trust_domain: &str,
+        _key_slots: KeySlots,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::Unsupported("Key slots")))
+    }
+
+    /// save the bundle of a federated trust domain, fetched from its bundle endpoint, replacing the saved one.
+    ///
+    /// ## Arguments
+    /// * `trust_bundle` - the bundle, with the name of the federated trust domain.
+    ///
+    /// ## Returns
+    /// * `Ok(())` - Successfully saved the bundle
+    /// * `Err(e)` - an error occurred while saving the bundle
+    async fn set_federated_bundle(
+        &self,
+        _trust_bundle: TrustBundle,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::Unsupported("Federated bundles")))
+    }
+
+    /// get the saved bundles of the federated trust domains.
+    ///
+    /// ## Returns
+    /// * `Ok(Vec<TrustBundle>)` - The bundles, sorted by trust domain
+    /// * `Err(e)` - an error occurred while getting the bundles
+    async fn get_federated_bundles(
+        &self,
+    ) -> Result<Vec<TrustBundle>, Box<dyn std::error::Error + Send>> {
+        Err(Box::new(Error::Unsupported("Federated bundles")))
+    }
 }
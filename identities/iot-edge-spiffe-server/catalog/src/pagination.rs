@@ -0,0 +1,110 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use core_objects::RegistrationEntry;
+use futures_util::{stream, Stream, TryStreamExt};
+
+use crate::Entries;
+
+/// Stream all the registration entries of the catalog, fetching them one page at a time.
+/// Following the page tokens is done here so callers cannot get stuck on the first page.
+pub fn scan_entries<E: Entries + ?Sized>(
+    entries: &E,
+    page_size: usize,
+) -> impl Stream<Item = Result<RegistrationEntry, Box<dyn std::error::Error + Send>>> + Send + '_ {
+    scan_entries_from(entries, None, page_size)
+}
+
+// Same as `scan_entries`, starting with the page of `page_token`.
+pub(crate) fn scan_entries_from<E: Entries + ?Sized>(
+    entries: &E,
+    page_token: Option<String>,
+    page_size: usize,
+) -> impl Stream<Item = Result<RegistrationEntry, Box<dyn std::error::Error + Send>>> + Send + '_ {
+    // The state is the token of the next page to fetch, None once the last page was fetched.
+    stream::try_unfold(
+        Some(page_token),
+        move |page_token: Option<Option<String>>| async move {
+            let page_token = match page_token {
+                Some(page_token) => page_token,
+                None => return Ok(None),
+            };
+
+            let (page, next_page_token) = entries.list_all(page_token, page_size).await?;
+            let page = stream::iter(page.into_iter().map(Ok));
+
+            Ok(Some((page, next_page_token.map(Some))))
+        },
+    )
+    .try_flatten()
+}
+
+// Backends fetch one more row than the requested page size: if it exists, its id is the token of the next page.
+// The token is inclusive, list_all(Some(token), _) starts with that entry, same as the in memory catalog.
+pub(crate) fn split_page<T>(
+    mut rows: Vec<(String, T)>,
+    page_size: usize,
+) -> (Vec<(String, T)>, Option<String>) {
+    let page_token = if rows.len() > page_size {
+        rows.truncate(page_size + 1);
+        rows.pop().map(|(id, _)| id)
+    } else {
+        None
+    };
+
+    (rows, page_token)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::inmemory;
+
+    use super::*;
+
+    fn rows(ids: &[&str]) -> Vec<(String, ())> {
+        ids.iter().map(|id| ((*id).to_string(), ())).collect()
+    }
+
+    #[test]
+    fn split_page_with_next_page() {
+        let (page, page_token) = split_page(rows(&["a", "b", "c"]), 2);
+
+        assert_eq!(page.len(), 2);
+        assert_eq!(page_token, Some("c".to_string()));
+    }
+
+    #[test]
+    fn split_page_last_page() {
+        let (page, page_token) = split_page(rows(&["a", "b"]), 2);
+
+        assert_eq!(page.len(), 2);
+        assert_eq!(page_token, None);
+    }
+
+    #[tokio::test]
+    async fn scan_entries_follows_page_tokens() {
+        let catalog = inmemory::Catalog::new();
+        let entries = (0..250)
+            .map(|i| RegistrationEntry::test_entry(&format!("id{:03}", i)))
+            .collect::<Vec<_>>();
+        catalog.batch_create(entries).await.unwrap();
+
+        let ids = scan_entries(&catalog, 100)
+            .map_ok(|entry| entry.id)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(ids.len(), 250);
+        assert_eq!(ids.first().unwrap(), "id000");
+        assert_eq!(ids.last().unwrap(), "id249");
+    }
+
+    #[tokio::test]
+    async fn scan_entries_invalid_page_size() {
+        let catalog = inmemory::Catalog::new();
+
+        let result = scan_entries(&catalog, 0).try_collect::<Vec<_>>().await;
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use core_objects::AdminOperation;
+
+use crate::{pagination::split_page, AdminOperationFilter};
+
+use super::{error::Error, Catalog};
+
+impl Catalog {
+    // The filtered fields have their own columns, the whole record is kept as JSON.
+    pub(super) async fn record_admin_operation_inner(
+        &self,
+        operation: &AdminOperation,
+    ) -> Result<(), Error> {
+        let connection = self.connection().await?;
+        let record = serde_json::to_string(operation).map_err(Error::Serialize)?;
+        let entry_ids: Vec<&str> = operation
+            .entries
+            .iter()
+            .map(|entry| entry.id.as_str())
+            .collect();
+        let time = i64::try_from(operation.time).unwrap_or(i64::MAX);
+
+        connection
+            .execute(
+                "INSERT INTO admin_operations (id, caller, entry_ids, time, record) \
+                VALUES ($1, $2, $3, $4, $5) ON CONFLICT (id) DO NOTHING",
+                &[&operation.id, &operation.caller, &entry_ids, &time, &record],
+            )
+            .await
+            .map_err(Error::Query)?;
+
+        Ok(())
+    }
+
+    pub(super) async fn list_admin_operations_inner(
+        &self,
+        filter: &AdminOperationFilter,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<AdminOperation>, Option<String>), Error> {
+        if page_size == 0 {
+            return Err(Error::InvalidPageSize());
+        }
+
+        let connection = self.connection().await?;
+        let limit = i64::try_from(page_size)
+            .unwrap_or(i64::MAX)
+            .saturating_add(1);
+        let since = filter
+            .since
+            .map(|since| i64::try_from(since).unwrap_or(i64::MAX));
+        let until = filter
+            .until
+            .map(|until| i64::try_from(until).unwrap_or(i64::MAX));
+
+        let rows = connection
+            .query(
+                "SELECT id, record FROM admin_operations WHERE id >= $1 \
+                AND ($2::TEXT IS NULL OR caller = $2) \
+                AND ($3::TEXT IS NULL OR $3 = ANY(entry_ids)) \
+                AND ($4::BIGINT IS NULL OR time >= $4) \
+                AND ($5::BIGINT IS NULL OR time < $5) \
+                ORDER BY id LIMIT $6",
+                &[
+                    &page_token.unwrap_or_default(),
+                    &filter.caller,
+                    &filter.entry_id,
+                    &since,
+                    &until,
+                    &limit,
+                ],
+            )
+            .await
+            .map_err(Error::Query)?;
+
+        let rows = rows
+            .iter()
+            .map(|row| {
+                let id: String = row.get(0);
+                let operation: AdminOperation =
+                    serde_json::from_str(row.get(1)).map_err(Error::Deserialize)?;
+                Ok((id, operation))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let (rows, page_token) = split_page(rows, page_size);
+        let operations = rows.into_iter().map(|(_id, operation)| operation).collect();
+
+        Ok((operations, page_token))
+    }
+}
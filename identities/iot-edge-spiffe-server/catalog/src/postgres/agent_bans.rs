@@ -0,0 +1,47 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use core_objects::AgentBan;
+
+use super::{error::Error, Catalog};
+
+impl Catalog {
+    pub(super) async fn add_agent_ban_inner(&self, ban: &AgentBan) -> Result<(), Error> {
+        let serialized_ban = serde_json::to_string(ban).map_err(Error::Serialize)?;
+        let connection = self.connection().await?;
+
+        connection
+            .execute(
+                "INSERT INTO agent_bans (id, ban) VALUES ($1, $2) \
+                ON CONFLICT (id) DO UPDATE SET ban = EXCLUDED.ban",
+                &[&ban.id, &serialized_ban],
+            )
+            .await
+            .map_err(Error::Query)?;
+
+        Ok(())
+    }
+
+    pub(super) async fn remove_agent_ban_inner(&self, id: &str) -> Result<bool, Error> {
+        let connection = self.connection().await?;
+
+        let removed = connection
+            .execute("DELETE FROM agent_bans WHERE id = $1", &[&id])
+            .await
+            .map_err(Error::Query)?;
+
+        Ok(removed > 0)
+    }
+
+    pub(super) async fn get_agent_bans_inner(&self) -> Result<Vec<AgentBan>, Error> {
+        let connection = self.connection().await?;
+
+        let rows = connection
+            .query("SELECT ban FROM agent_bans ORDER BY id", &[])
+            .await
+            .map_err(Error::Query)?;
+
+        rows.iter()
+            .map(|row| serde_json::from_str(row.get(0)).map_err(Error::Deserialize))
+            .collect()
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use core_objects::AttestedAgent;
+use tokio_postgres::Row;
+
+use crate::pagination::split_page;
+
+use super::{error::Error, Catalog};
+
+impl Catalog {
+    // The expiry is kept in its own column, so the database keeps the latest one when replicas race.
+    pub(super) async fn record_attested_agent_inner(
+        &self,
+        agent: &AttestedAgent,
+    ) -> Result<(), Error> {
+        let selectors = serde_json::to_string(&agent.selectors).map_err(Error::Serialize)?;
+        let last_seen = i64::try_from(agent.last_seen).unwrap_or(i64::MAX);
+        let svid_expiry = i64::try_from(agent.svid_expiry).unwrap_or(i64::MAX);
+        let connection = self.connection().await?;
+
+        connection
+            .execute(
+                "INSERT INTO attested_agents (id, selectors, last_seen, svid_expiry) VALUES ($1, $2, $3, $4) \
+                ON CONFLICT (id) DO UPDATE SET selectors = EXCLUDED.selectors, last_seen = EXCLUDED.last_seen, \
+                svid_expiry = GREATEST(attested_agents.svid_expiry, EXCLUDED.svid_expiry)",
+                &[&agent.id, &selectors, &last_seen, &svid_expiry],
+            )
+            .await
+            .map_err(Error::Query)?;
+
+        Ok(())
+    }
+
+    pub(super) async fn list_attested_agents_inner(
+        &self,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<AttestedAgent>, Option<String>), Error> {
+        if page_size == 0 {
+            return Err(Error::InvalidPageSize());
+        }
+
+        let connection = self.connection().await?;
+        let limit = i64::try_from(page_size)
+            .unwrap_or(i64::MAX)
+            .saturating_add(1);
+
+        let rows = connection
+            .query(
+                "SELECT id, selectors, last_seen, svid_expiry FROM attested_agents \
+                WHERE id >= $1 ORDER BY id LIMIT $2",
+                &[&page_token.unwrap_or_default(), &limit],
+            )
+            .await
+            .map_err(Error::Query)?;
+
+        let rows = rows
+            .iter()
+            .map(|row| parse_agent(row).map(|agent| (agent.id.clone(), agent)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let (rows, page_token) = split_page(rows, page_size);
+        let agents = rows.into_iter().map(|(_id, agent)| agent).collect();
+
+        Ok((agents, page_token))
+    }
+}
+
+fn parse_agent(row: &Row) -> Result<AttestedAgent, Error> {
+    let last_seen: i64 = row.get(2);
+    let svid_expiry: i64 = row.get(3);
+
+    Ok(AttestedAgent {
+        id: row.get(0),
+        selectors: serde_json::from_str(row.get(1)).map_err(Error::Deserialize)?,
+        last_seen: u64::try_from(last_seen).unwrap_or_default(),
+        svid_expiry: u64::try_from(svid_expiry).unwrap_or_default(),
+    })
+}
@@ -0,0 +1,492 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::collections::HashMap;
+
+use core_objects::RegistrationEntry;
+use tokio_postgres::{Row, Transaction};
+
+use crate::{
+    abort_transaction, pagination::split_page, AttestationPlugin, Entries, EntryFilter,
+    Error as CatalogError,
+};
+
+use super::{error::Error, Catalog};
+
+type BatchErrors = Vec<(String, Box<dyn std::error::Error + Send>)>;
+
+#[async_trait::async_trait]
+impl Entries for Catalog {
+    async fn batch_create(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        let ids = entries
+            .iter()
+            .map(|entry| entry.id.clone())
+            .collect::<Vec<_>>();
+
+        let errors = self
+            .batch_create_inner(entries, false)
+            .await
+            .map_err(|err| abort_batch(&ids, &err))?;
+
+        errors.is_empty().then(|| ()).ok_or(errors)
+    }
+
+    async fn batch_update(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        let ids = entries
+            .iter()
+            .map(|entry| entry.id.clone())
+            .collect::<Vec<_>>();
+
+        let errors = self
+            .batch_update_inner(entries, false)
+            .await
+            .map_err(|err| abort_batch(&ids, &err))?;
+
+        errors.is_empty().then(|| ()).ok_or(errors)
+    }
+
+    async fn batch_delete(
+        &self,
+        ids: &[String],
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        let errors = self
+            .batch_delete_inner(ids, false)
+            .await
+            .map_err(|err| abort_batch(ids, &err))?;
+
+        errors.is_empty().then(|| ()).ok_or(errors)
+    }
+
+    async fn batch_create_transactional(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        let ids = entries
+            .iter()
+            .map(|entry| entry.id.clone())
+            .collect::<Vec<_>>();
+
+        let errors = self
+            .batch_create_inner(entries, true)
+            .await
+            .map_err(|err| abort_batch(&ids, &err))?;
+
+        errors
+            .is_empty()
+            .then(|| ())
+            .ok_or_else(|| abort_transaction(&ids, errors))
+    }
+
+    async fn batch_update_transactional(
+        &self,
+        entries: Vec<RegistrationEntry>,
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        let ids = entries
+            .iter()
+            .map(|entry| entry.id.clone())
+            .collect::<Vec<_>>();
+
+        let errors = self
+            .batch_update_inner(entries, true)
+            .await
+            .map_err(|err| abort_batch(&ids, &err))?;
+
+        errors
+            .is_empty()
+            .then(|| ())
+            .ok_or_else(|| abort_transaction(&ids, errors))
+    }
+
+    async fn batch_delete_transactional(
+        &self,
+        ids: &[String],
+    ) -> Result<(), Vec<(String, Box<dyn std::error::Error + Send>)>> {
+        let errors = self
+            .batch_delete_inner(ids, true)
+            .await
+            .map_err(|err| abort_batch(ids, &err))?;
+
+        errors
+            .is_empty()
+            .then(|| ())
+            .ok_or_else(|| abort_transaction(ids, errors))
+    }
+
+    async fn batch_get(
+        &self,
+        ids: &[String],
+    ) -> Vec<(
+        String,
+        Result<RegistrationEntry, Box<dyn std::error::Error + Send>>,
+    )> {
+        let mut entries = match self.batch_get_inner(ids).await {
+            Ok(entries) => entries,
+            Err(err) => {
+                return abort_batch(ids, &err)
+                    .into_iter()
+                    .map(|(id, err)| (id, Err(err)))
+                    .collect();
+            }
+        };
+
+        let mut results = Vec::new();
+
+        for id in ids {
+            let result = if let Some(entry) = entries.remove(id) {
+                (
+                    id.clone(),
+                    parse_entry(&entry).map_err(|err| Box::new(err) as _),
+                )
+            } else {
+                (
+                    id.clone(),
+                    Err(Box::new(Error::EntryNotFound(id.to_string())) as _),
+                )
+            };
+
+            results.push(result);
+        }
+
+        results
+    }
+
+    async fn get_entry(
+        &self,
+        id: &str,
+    ) -> Result<RegistrationEntry, Box<dyn std::error::Error + Send>> {
+        let connection = self.connection().await.map_err(|err| Box::new(err) as _)?;
+
+        let row = connection
+            .query_opt(
+                "SELECT entry FROM registration_entries WHERE id = $1",
+                &[&id],
+            )
+            .await
+            .map_err(|err| Box::new(Error::Query(err)) as _)?
+            .ok_or_else(|| Box::new(Error::EntryNotFound(id.to_string())) as _)?;
+
+        parse_entry(row.get(0)).map_err(|err| Box::new(err) as _)
+    }
+
+    async fn list_all(
+        &self,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<RegistrationEntry>, Option<String>), Box<dyn std::error::Error + Send>> {
+        if page_size == 0 {
+            return Err(Box::new(Error::InvalidPageSize()));
+        }
+
+        let connection = self.connection().await.map_err(|err| Box::new(err) as _)?;
+
+        let limit = i64::try_from(page_size)
+            .unwrap_or(i64::MAX)
+            .saturating_add(1);
+
+        let rows = if let Some(page_token) = page_token {
+            connection
+                .query(
+                    "SELECT id, entry FROM registration_entries WHERE id >= $1 ORDER BY id LIMIT $2",
+                    &[&page_token, &limit],
+                )
+                .await
+        } else {
+            connection
+                .query(
+                    "SELECT id, entry FROM registration_entries ORDER BY id LIMIT $1",
+                    &[&limit],
+                )
+                .await
+        }
+        .map_err(|err| Box::new(Error::Query(err)) as _)?;
+
+        parse_page(&rows, page_size).map_err(|err| Box::new(err) as _)
+    }
+
+    // The filter is evaluated on the JSON of the entries by the database, only the page is sent back.
+    async fn list_filtered(
+        &self,
+        filter: &EntryFilter,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<RegistrationEntry>, Option<String>), Box<dyn std::error::Error + Send>> {
+        if page_size == 0 {
+            return Err(Box::new(Error::InvalidPageSize()));
+        }
+
+        let connection = self.connection().await.map_err(|err| Box::new(err) as _)?;
+
+        let limit = i64::try_from(page_size)
+            .unwrap_or(i64::MAX)
+            .saturating_add(1);
+        let plugin = filter.plugin.as_ref().map(AttestationPlugin::name);
+
+        let rows = connection
+            .query(
+                "SELECT id, entry FROM registration_entries \
+                 WHERE ($1::TEXT IS NULL OR id >= $1) \
+                 AND ($2::TEXT IS NULL OR entry::jsonb #>> '{attestation_config,content,parent_id}' = $2) \
+                 AND ($3::TEXT IS NULL OR entry::jsonb #> '{attestation_config,content,value}' ? $3) \
+                 AND ($4::TEXT IS NULL OR starts_with(entry::jsonb ->> 'spiffe_id_path', $4)) \
+                 AND ($5::TEXT IS NULL OR entry::jsonb #>> '{attestation_config,content,plugin}' = $5) \
+                 ORDER BY id LIMIT $6",
+                &[
+                    &page_token,
+                    &filter.parent_id,
+                    &filter.selector,
+                    &filter.spiffe_id_path_prefix,
+                    &plugin,
+                    &limit,
+                ],
+            )
+            .await
+            .map_err(|err| Box::new(Error::Query(err)) as _)?;
+
+        parse_page(&rows, page_size).map_err(|err| Box::new(err) as _)
+    }
+}
+
+impl Catalog {
+    // Each batch runs in a transaction. A transactional batch is only committed if no entry failed.
+    async fn batch_create_inner(
+        &self,
+        entries: Vec<RegistrationEntry>,
+        transactional: bool,
+    ) -> Result<BatchErrors, Error> {
+        let mut connection = self.connection().await?;
+        let transaction = connection.transaction().await.map_err(Error::Query)?;
+        let statement = transaction
+            .prepare(
+                "INSERT INTO registration_entries (id, entry) VALUES ($1, $2) ON CONFLICT (id) DO NOTHING",
+            )
+            .await
+            .map_err(Error::Query)?;
+
+        let mut errors = Vec::new();
+
+        for entry in entries {
+            let serialized_entry = match serde_json::to_string(&entry) {
+                Ok(serialized_entry) => serialized_entry,
+                Err(err) => {
+                    errors.push((entry.id, Box::new(Error::Serialize(err)) as _));
+                    continue;
+                }
+            };
+
+            let inserted = transaction
+                .execute(&statement, &[&entry.id, &serialized_entry])
+                .await
+                .map_err(Error::Query)?;
+
+            if inserted == 0 {
+                let error = (
+                    entry.id.clone(),
+                    Box::new(Error::DuplicatedEntry(entry.id)) as _,
+                );
+
+                errors.push(error);
+            }
+        }
+
+        commit_batch(transaction, transactional, &errors).await?;
+
+        Ok(errors)
+    }
+
+    async fn batch_update_inner(
+        &self,
+        entries: Vec<RegistrationEntry>,
+        transactional: bool,
+    ) -> Result<BatchErrors, Error> {
+        let mut connection = self.connection().await?;
+        let transaction = connection.transaction().await.map_err(Error::Query)?;
+        // The row stays locked until the transaction commits, so the revision cannot change between the
+        // check and the update.
+        let select_statement = transaction
+            .prepare("SELECT entry FROM registration_entries WHERE id = $1 FOR UPDATE")
+            .await
+            .map_err(Error::Query)?;
+        let update_statement = transaction
+            .prepare("UPDATE registration_entries SET entry = $2 WHERE id = $1")
+            .await
+            .map_err(Error::Query)?;
+
+        let mut errors = Vec::new();
+
+        for mut entry in entries {
+            let row = transaction
+                .query_opt(&select_statement, &[&entry.id])
+                .await
+                .map_err(Error::Query)?;
+
+            let current = if let Some(row) = row {
+                row.get::<_, String>(0)
+            } else {
+                let error = (
+                    entry.id.clone(),
+                    Box::new(Error::EntryNotFound(entry.id)) as _,
+                );
+
+                errors.push(error);
+                continue;
+            };
+
+            match parse_entry(&current) {
+                Ok(current) if current.revision_number == entry.revision_number => {}
+                Ok(_) => {
+                    let error = CatalogError::RevisionConflict {
+                        id: entry.id.clone(),
+                        revision_number: entry.revision_number,
+                    };
+
+                    errors.push((entry.id, Box::new(error) as _));
+                    continue;
+                }
+                Err(err) => {
+                    errors.push((entry.id, Box::new(err) as _));
+                    continue;
+                }
+            }
+
+            entry.revision_number += 1;
+            let serialized_entry = match serde_json::to_string(&entry) {
+                Ok(serialized_entry) => serialized_entry,
+                Err(err) => {
+                    errors.push((entry.id, Box::new(Error::Serialize(err)) as _));
+                    continue;
+                }
+            };
+
+            transaction
+                .execute(&update_statement, &[&entry.id, &serialized_entry])
+                .await
+                .map_err(Error::Query)?;
+        }
+
+        commit_batch(transaction, transactional, &errors).await?;
+
+        Ok(errors)
+    }
+
+    async fn batch_delete_inner(
+        &self,
+        ids: &[String],
+        transactional: bool,
+    ) -> Result<BatchErrors, Error> {
+        let mut connection = self.connection().await?;
+        let transaction = connection.transaction().await.map_err(Error::Query)?;
+        let statement = transaction
+            .prepare("DELETE FROM registration_entries WHERE id = $1")
+            .await
+            .map_err(Error::Query)?;
+
+        let mut errors = Vec::new();
+
+        for id in ids {
+            let deleted = transaction
+                .execute(&statement, &[id])
+                .await
+                .map_err(Error::Query)?;
+
+            if deleted == 0 {
+                let error = (
+                    id.clone(),
+                    Box::new(Error::EntryNotFound(id.to_string())) as _,
+                );
+
+                errors.push(error);
+            }
+        }
+
+        commit_batch(transaction, transactional, &errors).await?;
+
+        Ok(errors)
+    }
+
+    async fn batch_get_inner(&self, ids: &[String]) -> Result<HashMap<String, String>, Error> {
+        let connection = self.connection().await?;
+
+        let rows = connection
+            .query(
+                "SELECT id, entry FROM registration_entries WHERE id = ANY($1)",
+                &[&ids],
+            )
+            .await
+            .map_err(Error::Query)?;
+
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+}
+
+// Dropping the transaction rolls it back, nothing of a failed transactional batch is applied.
+async fn commit_batch(
+    transaction: Transaction<'_>,
+    transactional: bool,
+    errors: &BatchErrors,
+) -> Result<(), Error> {
+    if transactional && !errors.is_empty() {
+        return Ok(());
+    }
+
+    transaction.commit().await.map_err(Error::Query)
+}
+
+fn parse_entry(entry: &str) -> Result<RegistrationEntry, Error> {
+    serde_json::from_str(entry).map_err(Error::Deserialize)
+}
+
+// Rows of a query for one more entry than the page size, see `split_page`.
+fn parse_page(
+    rows: &[Row],
+    page_size: usize,
+) -> Result<(Vec<RegistrationEntry>, Option<String>), Error> {
+    let rows = rows
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect::<Vec<(String, String)>>();
+    let (rows, page_token) = split_page(rows, page_size);
+
+    let entries = rows
+        .iter()
+        .map(|(_id, entry)| parse_entry(entry))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok((entries, page_token))
+}
+
+// The whole batch failed, report the cause against every id so the caller gets one result per input.
+fn abort_batch(ids: &[String], err: &Error) -> BatchErrors {
+    ids.iter()
+        .map(|id| {
+            (
+                id.clone(),
+                Box::new(Error::BatchAborted(err.to_string())) as _,
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use matches::assert_matches;
+
+    use super::*;
+
+    #[test]
+    fn abort_batch_reports_every_id() {
+        let ids = vec!["id1".to_string(), "id2".to_string()];
+        let errors = abort_batch(&ids, &Error::InvalidPageSize());
+
+        assert_eq!(errors.len(), 2);
+        for ((id, error), expected_id) in errors.into_iter().zip(ids) {
+            assert_eq!(id, expected_id);
+            let error = *error.downcast::<Error>().unwrap();
+            assert_matches!(error, Error::BatchAborted(_));
+        }
+    }
+}
@@ -0,0 +1,35 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Entry {0} already exists")]
+    DuplicatedEntry(String),
+    #[error("Entry {0} does not exist")]
+    EntryNotFound(String),
+    #[error("Key {0} already exists")]
+    DuplicatedKey(String),
+    #[error("Key {0} does not exist")]
+    KeyNotFound(String),
+    #[error("Version {0} is not in the trust bundle history")]
+    VersionNotFound(usize),
+    #[error("Invalid page size")]
+    InvalidPageSize(),
+    #[error("Invalid TLS configuration {0}")]
+    Tls(openssl::error::ErrorStack),
+    #[error("Could not get a connection from the pool {0}")]
+    Pool(bb8::RunError<tokio_postgres::Error>),
+    #[error("Error while running database migrations {0}")]
+    Migration(tokio_postgres::Error),
+    #[error("Database query failed {0}")]
+    Query(tokio_postgres::Error),
+    #[error("Could not serialize {0}")]
+    Serialize(serde_json::Error),
+    #[error("Could not deserialize {0}")]
+    Deserialize(serde_json::Error),
+    #[error("Batch aborted {0}")]
+    BatchAborted(String),
+    #[error("Trust bundle version {0} is out of range")]
+    InvalidVersion(i64),
+}
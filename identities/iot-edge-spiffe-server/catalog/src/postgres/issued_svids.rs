@@ -0,0 +1,99 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use core_objects::IssuedSvid;
+
+use crate::{pagination::split_page, IssuedSvidFilter};
+
+use super::{error::Error, Catalog};
+
+impl Catalog {
+    // The filtered fields have their own columns, the whole record is kept as JSON.
+    pub(super) async fn record_issued_svids_inner(
+        &self,
+        svids: &[IssuedSvid],
+    ) -> Result<(), Error> {
+        let mut connection = self.connection().await?;
+        let transaction = connection.transaction().await.map_err(Error::Query)?;
+
+        for svid in svids {
+            let record = serde_json::to_string(svid).map_err(Error::Serialize)?;
+            let issued_at = i64::try_from(svid.issued_at).unwrap_or(i64::MAX);
+
+            transaction
+                .execute(
+                    "INSERT INTO issued_svids (id, spiffe_id, entry_id, agent_id, issued_at, record) \
+                    VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (id) DO NOTHING",
+                    &[
+                        &svid.id,
+                        &svid.spiffe_id,
+                        &svid.entry_id,
+                        &svid.agent_id,
+                        &issued_at,
+                        &record,
+                    ],
+                )
+                .await
+                .map_err(Error::Query)?;
+        }
+
+        transaction.commit().await.map_err(Error::Query)
+    }
+
+    pub(super) async fn list_issued_svids_inner(
+        &self,
+        filter: &IssuedSvidFilter,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<IssuedSvid>, Option<String>), Error> {
+        if page_size == 0 {
+            return Err(Error::InvalidPageSize());
+        }
+
+        let connection = self.connection().await?;
+        let limit = i64::try_from(page_size)
+            .unwrap_or(i64::MAX)
+            .saturating_add(1);
+        let since = filter
+            .since
+            .map(|since| i64::try_from(since).unwrap_or(i64::MAX));
+        let until = filter
+            .until
+            .map(|until| i64::try_from(until).unwrap_or(i64::MAX));
+
+        let rows = connection
+            .query(
+                "SELECT id, record FROM issued_svids WHERE id >= $1 \
+                AND ($2::TEXT IS NULL OR spiffe_id = $2) \
+                AND ($3::TEXT IS NULL OR entry_id = $3) \
+                AND ($4::TEXT IS NULL OR agent_id = $4) \
+                AND ($5::BIGINT IS NULL OR issued_at >= $5) \
+                AND ($6::BIGINT IS NULL OR issued_at < $6) \
+                ORDER BY id LIMIT $7",
+                &[
+                    &page_token.unwrap_or_default(),
+                    &filter.spiffe_id,
+                    &filter.entry_id,
+                    &filter.agent_id,
+                    &since,
+                    &until,
+                    &limit,
+                ],
+            )
+            .await
+            .map_err(Error::Query)?;
+
+        let rows = rows
+            .iter()
+            .map(|row| {
+                let id: String = row.get(0);
+                let svid: IssuedSvid =
+                    serde_json::from_str(row.get(1)).map_err(Error::Deserialize)?;
+                Ok((id, svid))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let (rows, page_token) = split_page(rows, page_size);
+        let svids = rows.into_iter().map(|(_id, svid)| svid).collect();
+
+        Ok((svids, page_token))
+    }
+}
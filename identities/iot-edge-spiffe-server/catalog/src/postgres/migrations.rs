@@ -0,0 +1,154 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use tokio_postgres::Client;
+
+use super::error::Error;
+
+// Arbitrary key for the advisory lock serializing migrations between server replicas.
+const MIGRATION_LOCK_KEY: i64 = 0x4534_4b43_4154;
+
+// Migrations are applied in order and never modified once released. To change the schema,
+// append a new migration. The index in the array (plus one) is the schema version.
+const MIGRATIONS: &[&str] = &[
+    // Ids use the "C" collation so the ordering matches the byte ordering of the in memory catalog.
+    // list_all page tokens depend on it.
+    r#"
+    CREATE TABLE registration_entries (
+        id TEXT COLLATE "C" PRIMARY KEY,
+        entry TEXT NOT NULL
+    );
+
+    CREATE TABLE jwt_keys (
+        trust_domain TEXT NOT NULL,
+        kid TEXT NOT NULL,
+        jwk TEXT NOT NULL,
+        PRIMARY KEY (trust_domain, kid)
+    );
+
+    CREATE TABLE trust_domains (
+        trust_domain TEXT PRIMARY KEY,
+        version BIGINT NOT NULL
+    );
+    "#,
+    r#"
+    CREATE TABLE jwk_set_history (
+        trust_domain TEXT NOT NULL,
+        version BIGINT NOT NULL,
+        created_at BIGINT NOT NULL,
+        keys TEXT NOT NULL,
+        PRIMARY KEY (trust_domain, version)
+    );
+    "#,
+    r#"
+    CREATE TABLE key_slots (
+        trust_domain TEXT PRIMARY KEY,
+        key_slots TEXT NOT NULL
+    );
+    "#,
+    r#"
+    CREATE TABLE agent_bans (
+        id TEXT PRIMARY KEY,
+        ban TEXT NOT NULL
+    );
+    "#,
+    r#"
+    CREATE TABLE attested_agents (
+        id TEXT COLLATE "C" PRIMARY KEY,
+        selectors TEXT NOT NULL,
+        last_seen BIGINT NOT NULL,
+        svid_expiry BIGINT NOT NULL
+    );
+    "#,
+    r#"
+    CREATE TABLE issued_svids (
+        id TEXT COLLATE "C" PRIMARY KEY,
+        spiffe_id TEXT NOT NULL,
+        entry_id TEXT NOT NULL,
+        agent_id TEXT NOT NULL,
+        issued_at BIGINT NOT NULL,
+        record TEXT NOT NULL
+    );
+    CREATE INDEX issued_svids_spiffe_id ON issued_svids (spiffe_id);
+    "#,
+    r#"
+    CREATE TABLE admin_operations (
+        id TEXT COLLATE "C" PRIMARY KEY,
+        caller TEXT NOT NULL,
+        entry_ids TEXT[] NOT NULL,
+        time BIGINT NOT NULL,
+        record TEXT NOT NULL
+    );
+    CREATE INDEX admin_operations_entry_ids ON admin_operations USING GIN (entry_ids);
+    "#,
+    r#"
+    CREATE TABLE federated_bundles (
+        trust_domain TEXT COLLATE "C" PRIMARY KEY,
+        bundle TEXT NOT NULL
+    );
+    "#,
+];
+
+pub async fn run(client: &mut Client) -> Result<(), Error> {
+    let transaction = client.transaction().await.map_err(Error::Migration)?;
+
+    transaction
+        .execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK_KEY])
+        .await
+        .map_err(Error::Migration)?;
+
+    transaction
+        .batch_execute("CREATE TABLE IF NOT EXISTS schema_migrations (version BIGINT PRIMARY KEY)")
+        .await
+        .map_err(Error::Migration)?;
+
+    let current_version: i64 = transaction
+        .query_one(
+            "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+            &[],
+        )
+        .await
+        .map_err(Error::Migration)?
+        .get(0);
+
+    for (version, migration) in pending(current_version) {
+        transaction
+            .batch_execute(migration)
+            .await
+            .map_err(Error::Migration)?;
+        transaction
+            .execute(
+                "INSERT INTO schema_migrations (version) VALUES ($1)",
+                &[&version],
+            )
+            .await
+            .map_err(Error::Migration)?;
+    }
+
+    transaction.commit().await.map_err(Error::Migration)
+}
+
+fn pending(current_version: i64) -> impl Iterator<Item = (i64, &'static str)> {
+    (1_i64..)
+        .zip(MIGRATIONS.iter().copied())
+        .filter(move |(version, _)| *version > current_version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending_from_empty_database() {
+        let versions = pending(0).map(|(version, _)| version).collect::<Vec<_>>();
+
+        assert_eq!(versions.len(), MIGRATIONS.len());
+        assert_eq!(versions[0], 1);
+    }
+
+    #[test]
+    fn pending_up_to_date() {
+        let current_version = i64::try_from(MIGRATIONS.len()).unwrap();
+
+        assert_eq!(pending(current_version).count(), 0);
+    }
+}
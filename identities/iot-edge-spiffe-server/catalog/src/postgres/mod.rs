@@ -0,0 +1,170 @@
+// Copyright (c) Microsoft. All rights reserved.
+mod admin_operations;
+mod agent_bans;
+mod attested_agents;
+mod entries;
+mod error;
+mod issued_svids;
+mod migrations;
+mod trust_bundle_store;
+
+use bb8::{Pool, PooledConnection};
+use bb8_postgres::PostgresConnectionManager;
+use core_objects::{AdminOperation, AgentBan, AttestedAgent, IssuedSvid};
+use openssl::ssl::{SslConnector, SslMethod};
+use postgres_openssl::MakeTlsConnector;
+use server_config::{CatalogConfigPostgres, PostgresSslMode};
+use tokio::sync::OnceCell;
+use tokio_postgres::config::SslMode;
+
+use crate::{AdminOperationFilter, Catalog as CatalogTrait, IssuedSvidFilter};
+
+use error::Error;
+
+type Connection<'a> = PooledConnection<'a, PostgresConnectionManager<MakeTlsConnector>>;
+
+// Catalog backed by a Postgres database. Several server replicas can share the same database.
+// Connections are established lazily, the schema is migrated the first time a connection is used.
+pub struct Catalog {
+    pool: Pool<PostgresConnectionManager<MakeTlsConnector>>,
+    migrated: OnceCell<()>,
+}
+
+impl Catalog {
+    pub fn new(config: &CatalogConfigPostgres) -> Result<Self, Error> {
+        let mut pg_config = tokio_postgres::Config::new();
+        pg_config
+            .host(&config.host)
+            .port(config.port)
+            .user(&config.user)
+            .dbname(&config.dbname);
+        if let Some(password) = &config.password {
+            pg_config.password(password);
+        }
+        pg_config.ssl_mode(match config.ssl_mode {
+            PostgresSslMode::Disable => SslMode::Disable,
+            PostgresSslMode::Prefer => SslMode::Prefer,
+            PostgresSslMode::Require => SslMode::Require,
+        });
+
+        // Verifies the certificate chain and the host of the database.
+        let mut connector = SslConnector::builder(SslMethod::tls_client()).map_err(Error::Tls)?;
+        if let Some(ca_path) = &config.ssl_ca_path {
+            connector.set_ca_file(ca_path).map_err(Error::Tls)?;
+        }
+
+        let manager =
+            PostgresConnectionManager::new(pg_config, MakeTlsConnector::new(connector.build()));
+        let pool = Pool::builder()
+            .max_size(config.max_connections)
+            .build_unchecked(manager);
+
+        Ok(Catalog {
+            pool,
+            migrated: OnceCell::new(),
+        })
+    }
+
+    async fn connection(&self) -> Result<Connection<'_>, Error> {
+        self.migrated
+            .get_or_try_init(|| async {
+                let mut connection = self.pool.get().await.map_err(Error::Pool)?;
+                migrations::run(&mut connection).await
+            })
+            .await?;
+
+        self.pool.get().await.map_err(Error::Pool)
+    }
+}
+
+#[async_trait::async_trait]
+impl CatalogTrait for Catalog {
+    async fn backend_version(&self) -> Result<Option<String>, Box<dyn std::error::Error + Send>> {
+        let connection = self.connection().await.map_err(|err| Box::new(err) as _)?;
+
+        let version: String = connection
+            .query_one("SHOW server_version", &[])
+            .await
+            .map_err(|err| Box::new(Error::Query(err)) as _)?
+            .get(0);
+
+        Ok(Some(format!("PostgreSQL {}", version)))
+    }
+
+    async fn add_agent_ban(&self, ban: AgentBan) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.add_agent_ban_inner(&ban)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn remove_agent_ban(&self, id: &str) -> Result<bool, Box<dyn std::error::Error + Send>> {
+        self.remove_agent_ban_inner(id)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn get_agent_bans(&self) -> Result<Vec<AgentBan>, Box<dyn std::error::Error + Send>> {
+        self.get_agent_bans_inner()
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn record_attested_agent(
+        &self,
+        agent: AttestedAgent,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.record_attested_agent_inner(&agent)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn list_attested_agents(
+        &self,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<AttestedAgent>, Option<String>), Box<dyn std::error::Error + Send>> {
+        self.list_attested_agents_inner(page_token, page_size)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn record_issued_svids(
+        &self,
+        svids: Vec<IssuedSvid>,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.record_issued_svids_inner(&svids)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn list_issued_svids(
+        &self,
+        filter: &IssuedSvidFilter,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<IssuedSvid>, Option<String>), Box<dyn std::error::Error + Send>> {
+        self.list_issued_svids_inner(filter, page_token, page_size)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn record_admin_operation(
+        &self,
+        operation: AdminOperation,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.record_admin_operation_inner(&operation)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn list_admin_operations(
+        &self,
+        filter: &AdminOperationFilter,
+        page_token: Option<String>,
+        page_size: usize,
+    ) -> Result<(Vec<AdminOperation>, Option<String>), Box<dyn std::error::Error + Send>> {
+        self.list_admin_operations_inner(filter, page_token, page_size)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+}
@@ -0,0 +1,364 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use core_objects::{get_epoch_time, JWKSetVersion, KeySlots, TrustBundle, JWK};
+use tokio_postgres::{IsolationLevel, Transaction};
+
+use crate::{TrustBundleStore, JWK_SET_HISTORY_SIZE};
+
+use super::{error::Error, Catalog};
+
+#[async_trait::async_trait]
+impl TrustBundleStore for Catalog {
+    async fn add_jwk(
+        &self,
+        trust_domain: &str,
+        jwk: JWK,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.add_jwk_inner(trust_domain, jwk)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn remove_jwk(
+        &self,
+        trust_domain: &str,
+        kid: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.remove_jwk_inner(trust_domain, kid)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn get_jwk(
+        &self,
+        trust_domain: &str,
+    ) -> Result<(Vec<JWK>, usize), Box<dyn std::error::Error + Send>> {
+        self.get_jwk_inner(trust_domain)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn get_jwk_history(
+        &self,
+        trust_domain: &str,
+    ) -> Result<Vec<JWKSetVersion>, Box<dyn std::error::Error + Send>> {
+        self.get_jwk_history_inner(trust_domain)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn rollback_jwk(
+        &self,
+        trust_domain: &str,
+        version: usize,
+    ) -> Result<usize, Box<dyn std::error::Error + Send>> {
+        self.rollback_jwk_inner(trust_domain, version)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn get_key_slots(
+        &self,
+        trust_domain: &str,
+    ) -> Result<Option<KeySlots>, Box<dyn std::error::Error + Send>> {
+        self.get_key_slots_inner(trust_domain)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn set_key_slots(
+        &self,
+        trust_domain: &str,
+        key_slots: KeySlots,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.set_key_slots_inner(trust_domain, &key_slots)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn set_federated_bundle(
+        &self,
+        trust_bundle: TrustBundle,
+    ) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.set_federated_bundle_inner(&trust_bundle)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+
+    async fn get_federated_bundles(
+        &self,
+    ) -> Result<Vec<TrustBundle>, Box<dyn std::error::Error + Send>> {
+        self.get_federated_bundles_inner()
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+}
+
+impl Catalog {
+    async fn add_jwk_inner(&self, trust_domain: &str, jwk: JWK) -> Result<(), Error> {
+        let serialized_jwk = serde_json::to_string(&jwk).map_err(Error::Serialize)?;
+
+        let mut connection = self.connection().await?;
+        let transaction = connection.transaction().await.map_err(Error::Query)?;
+
+        let inserted = transaction
+            .execute(
+                "INSERT INTO jwt_keys (trust_domain, kid, jwk) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+                &[&trust_domain, &jwk.kid, &serialized_jwk],
+            )
+            .await
+            .map_err(Error::Query)?;
+
+        if inserted == 0 {
+            return Err(Error::DuplicatedKey(jwk.kid));
+        }
+
+        publish_version(&transaction, trust_domain).await?;
+
+        transaction.commit().await.map_err(Error::Query)
+    }
+
+    async fn remove_jwk_inner(&self, trust_domain: &str, kid: &str) -> Result<(), Error> {
+        let mut connection = self.connection().await?;
+        let transaction = connection.transaction().await.map_err(Error::Query)?;
+
+        let deleted = transaction
+            .execute(
+                "DELETE FROM jwt_keys WHERE trust_domain = $1 AND kid = $2",
+                &[&trust_domain, &kid],
+            )
+            .await
+            .map_err(Error::Query)?;
+
+        if deleted == 0 {
+            return Err(Error::KeyNotFound(kid.to_string()));
+        }
+
+        publish_version(&transaction, trust_domain).await?;
+
+        transaction.commit().await.map_err(Error::Query)
+    }
+
+    async fn get_jwk_inner(&self, trust_domain: &str) -> Result<(Vec<JWK>, usize), Error> {
+        let mut connection = self.connection().await?;
+
+        // Keys and version must come from the same snapshot, another replica may be rotating keys.
+        let transaction = connection
+            .build_transaction()
+            .isolation_level(IsolationLevel::RepeatableRead)
+            .read_only(true)
+            .start()
+            .await
+            .map_err(Error::Query)?;
+
+        let jwks = transaction
+            .query(
+                "SELECT jwk FROM jwt_keys WHERE trust_domain = $1",
+                &[&trust_domain],
+            )
+            .await
+            .map_err(Error::Query)?
+            .iter()
+            .map(|row| serde_json::from_str(row.get(0)).map_err(Error::Deserialize))
+            .collect::<Result<Vec<JWK>, _>>()?;
+
+        let version: i64 = transaction
+            .query_opt(
+                "SELECT version FROM trust_domains WHERE trust_domain = $1",
+                &[&trust_domain],
+            )
+            .await
+            .map_err(Error::Query)?
+            .map_or(0, |row| row.get(0));
+
+        transaction.commit().await.map_err(Error::Query)?;
+
+        let version = usize::try_from(version).map_err(|_| Error::InvalidVersion(version))?;
+
+        Ok((jwks, version))
+    }
+
+    async fn get_jwk_history_inner(&self, trust_domain: &str) -> Result<Vec<JWKSetVersion>, Error> {
+        let connection = self.connection().await?;
+
+        let rows = connection
+            .query(
+                "SELECT version, created_at, keys FROM jwk_set_history WHERE trust_domain = $1 ORDER BY version DESC",
+                &[&trust_domain],
+            )
+            .await
+            .map_err(Error::Query)?;
+
+        let mut history = Vec::new();
+
+        for row in rows {
+            let version: i64 = row.get(0);
+            let created_at: i64 = row.get(1);
+
+            history.push(JWKSetVersion {
+                version: usize::try_from(version).map_err(|_| Error::InvalidVersion(version))?,
+                created_at: u64::try_from(created_at).unwrap_or_default(),
+                keys: serde_json::from_str(row.get(2)).map_err(Error::Deserialize)?,
+            });
+        }
+
+        Ok(history)
+    }
+
+    async fn rollback_jwk_inner(&self, trust_domain: &str, version: usize) -> Result<usize, Error> {
+        let mut connection = self.connection().await?;
+        let transaction = connection.transaction().await.map_err(Error::Query)?;
+
+        let history_version =
+            i64::try_from(version).map_err(|_| Error::VersionNotFound(version))?;
+        let keys = transaction
+            .query_opt(
+                "SELECT keys FROM jwk_set_history WHERE trust_domain = $1 AND version = $2",
+                &[&trust_domain, &history_version],
+            )
+            .await
+            .map_err(Error::Query)?
+            .ok_or(Error::VersionNotFound(version))?;
+        let keys: Vec<JWK> = serde_json::from_str(keys.get(0)).map_err(Error::Deserialize)?;
+
+        transaction
+            .execute(
+                "DELETE FROM jwt_keys WHERE trust_domain = $1",
+                &[&trust_domain],
+            )
+            .await
+            .map_err(Error::Query)?;
+
+        for jwk in keys {
+            let serialized_jwk = serde_json::to_string(&jwk).map_err(Error::Serialize)?;
+
+            transaction
+                .execute(
+                    "INSERT INTO jwt_keys (trust_domain, kid, jwk) VALUES ($1, $2, $3)",
+                    &[&trust_domain, &jwk.kid, &serialized_jwk],
+                )
+                .await
+                .map_err(Error::Query)?;
+        }
+
+        let version = publish_version(&transaction, trust_domain).await?;
+
+        transaction.commit().await.map_err(Error::Query)?;
+
+        Ok(version)
+    }
+
+    async fn get_key_slots_inner(&self, trust_domain: &str) -> Result<Option<KeySlots>, Error> {
+        let connection = self.connection().await?;
+
+        connection
+            .query_opt(
+                "SELECT key_slots FROM key_slots WHERE trust_domain = $1",
+                &[&trust_domain],
+            )
+            .await
+            .map_err(Error::Query)?
+            .map(|row| serde_json::from_str(row.get(0)).map_err(Error::Deserialize))
+            .transpose()
+    }
+
+    async fn set_key_slots_inner(
+        &self,
+        trust_domain: &str,
+        key_slots: &KeySlots,
+    ) -> Result<(), Error> {
+        let serialized_key_slots = serde_json::to_string(key_slots).map_err(Error::Serialize)?;
+        let connection = self.connection().await?;
+
+        connection
+            .execute(
+                "INSERT INTO key_slots (trust_domain, key_slots) VALUES ($1, $2) \
+                ON CONFLICT (trust_domain) DO UPDATE SET key_slots = EXCLUDED.key_slots",
+                &[&trust_domain, &serialized_key_slots],
+            )
+            .await
+            .map_err(Error::Query)?;
+
+        Ok(())
+    }
+
+    async fn set_federated_bundle_inner(&self, trust_bundle: &TrustBundle) -> Result<(), Error> {
+        let serialized_bundle = serde_json::to_string(trust_bundle).map_err(Error::Serialize)?;
+        let connection = self.connection().await?;
+
+        connection
+            .execute(
+                "INSERT INTO federated_bundles (trust_domain, bundle) VALUES ($1, $2) \
+                ON CONFLICT (trust_domain) DO UPDATE SET bundle = EXCLUDED.bundle",
+                &[&trust_bundle.trust_domain, &serialized_bundle],
+            )
+            .await
+            .map_err(Error::Query)?;
+
+        Ok(())
+    }
+
+    async fn get_federated_bundles_inner(&self) -> Result<Vec<TrustBundle>, Error> {
+        let connection = self.connection().await?;
+
+        connection
+            .query(
+                "SELECT bundle FROM federated_bundles ORDER BY trust_domain",
+                &[],
+            )
+            .await
+            .map_err(Error::Query)?
+            .iter()
+            .map(|row| serde_json::from_str(row.get(0)).map_err(Error::Deserialize))
+            .collect()
+    }
+}
+
+// Bump the version of the trust domain jwk set and record the new set in the history.
+async fn publish_version(
+    transaction: &Transaction<'_>,
+    trust_domain: &str,
+) -> Result<usize, Error> {
+    let version: i64 = transaction
+        .query_one(
+            "INSERT INTO trust_domains (trust_domain, version) VALUES ($1, 1) \
+            ON CONFLICT (trust_domain) DO UPDATE SET version = trust_domains.version + 1 \
+            RETURNING version",
+            &[&trust_domain],
+        )
+        .await
+        .map_err(Error::Query)?
+        .get(0);
+
+    let keys = transaction
+        .query(
+            "SELECT jwk FROM jwt_keys WHERE trust_domain = $1",
+            &[&trust_domain],
+        )
+        .await
+        .map_err(Error::Query)?
+        .iter()
+        .map(|row| serde_json::from_str(row.get(0)).map_err(Error::Deserialize))
+        .collect::<Result<Vec<JWK>, _>>()?;
+    let keys = serde_json::to_string(&keys).map_err(Error::Serialize)?;
+    let created_at = i64::try_from(get_epoch_time()).unwrap_or(i64::MAX);
+
+    transaction
+        .execute(
+            "INSERT INTO jwk_set_history (trust_domain, version, created_at, keys) VALUES ($1, $2, $3, $4)",
+            &[&trust_domain, &version, &created_at, &keys],
+        )
+        .await
+        .map_err(Error::Query)?;
+
+    let history_size = i64::try_from(JWK_SET_HISTORY_SIZE).unwrap_or(i64::MAX);
+    transaction
+        .execute(
+            "DELETE FROM jwk_set_history WHERE trust_domain = $1 AND version <= $2 - $3",
+            &[&trust_domain, &version, &history_size],
+        )
+        .await
+        .map_err(Error::Query)?;
+
+    usize::try_from(version).map_err(|_| Error::InvalidVersion(version))
+}
@@ -0,0 +1,120 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use core_objects::RegistrationEntry;
+use futures_util::{future, TryStreamExt};
+
+use crate::{scan_entries, Catalog};
+
+// Page size used to scan the catalog for expired entries.
+const PRUNING_SCAN_PAGE_SIZE: usize = 100;
+
+/// Deletes the registration entries past their expiry. Entries with `expires_at` set to 0 never expire.
+pub struct EntryPruner {
+    catalog: Arc<dyn Catalog>,
+    pruned_entries: AtomicU64,
+}
+
+impl EntryPruner {
+    #[must_use]
+    pub fn new(catalog: Arc<dyn Catalog>) -> Self {
+        EntryPruner {
+            catalog,
+            pruned_entries: AtomicU64::new(0),
+        }
+    }
+
+    /// Delete the entries expired at `now` (seconds since Unix epoch).
+    ///
+    /// ## Returns
+    /// * `Ok(usize)` - The number of entries deleted
+    /// * `Err(e)` - The catalog could not be scanned
+    pub async fn prune_expired(
+        &self,
+        now: u64,
+    ) -> Result<usize, Box<dyn std::error::Error + Send>> {
+        let expired: Vec<String> = scan_entries(&*self.catalog, PRUNING_SCAN_PAGE_SIZE)
+            .try_filter_map(|entry| future::ok(is_expired(&entry, now).then(|| entry.id)))
+            .try_collect()
+            .await?;
+
+        if expired.is_empty() {
+            return Ok(0);
+        }
+
+        // An entry can be deleted in between by an administrator or by another server, it is not counted.
+        let pruned = match self.catalog.batch_delete(&expired).await {
+            Ok(()) => expired.len(),
+            Err(errors) => {
+                for (id, err) in &errors {
+                    log::warn!("Could not prune expired entry {}: {}", id, err);
+                }
+
+                expired.len().saturating_sub(errors.len())
+            }
+        };
+
+        self.pruned_entries
+            .fetch_add(u64::try_from(pruned).unwrap_or(u64::MAX), Ordering::Relaxed);
+
+        Ok(pruned)
+    }
+
+    /// Number of entries deleted since the server started.
+    #[must_use]
+    pub fn pruned_entries(&self) -> u64 {
+        self.pruned_entries.load(Ordering::Relaxed)
+    }
+}
+
+fn is_expired(entry: &RegistrationEntry, now: u64) -> bool {
+    entry.expires_at != 0 && entry.expires_at <= now
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{inmemory, Entries};
+
+    use super::*;
+
+    fn entry(id: &str, expires_at: u64) -> RegistrationEntry {
+        RegistrationEntry {
+            expires_at,
+            ..RegistrationEntry::test_entry(id)
+        }
+    }
+
+    #[tokio::test]
+    async fn prune_expired_entries() {
+        let catalog = Arc::new(inmemory::Catalog::new());
+        catalog
+            .batch_create(vec![
+                entry("never", 0),
+                entry("expired", 100),
+                entry("expires_now", 200),
+                entry("valid", 300),
+            ])
+            .await
+            .unwrap();
+        let pruner = EntryPruner::new(catalog.clone());
+
+        let pruned = pruner.prune_expired(200).await.unwrap();
+        assert_eq!(pruned, 2);
+        assert_eq!(pruner.pruned_entries(), 2);
+
+        let (entries, _page_token) = catalog.list_all(None, 10).await.unwrap();
+        let ids = entries
+            .into_iter()
+            .map(|entry| entry.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["never".to_string(), "valid".to_string()]);
+
+        let pruned = pruner.prune_expired(200).await.unwrap();
+        assert_eq!(pruned, 0);
+        assert_eq!(pruner.pruned_entries(), 2);
+    }
+}
@@ -0,0 +1,60 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Checks done before the server starts in FIPS mode. The signing keys are restricted to the P-256
+// and P-384 curves and to RSA, which also limits the digests to SHA-256, SHA-384 and SHA-512.
+
+use core_objects::KeyType;
+
+use crate::Config;
+
+pub const APPROVED_KEY_TYPES: &[KeyType] = &[
+    KeyType::ES256,
+    KeyType::ES384,
+    KeyType::RS256,
+    KeyType::RS384,
+    KeyType::RS512,
+    KeyType::PS256,
+    KeyType::PS384,
+    KeyType::PS512,
+];
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum Error {
+    #[error("JWT key type {0:?} is not allowed in FIPS mode")]
+    KeyType(KeyType),
+}
+
+pub fn check(config: &Config) -> Result<(), Error> {
+    if !APPROVED_KEY_TYPES.contains(&config.jwt.key_type) {
+        return Err(Error::KeyType(config.jwt.key_type));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(key_type: KeyType) -> Config {
+        let config = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/Config_fips.toml");
+        let mut config = Config::load_config(config).unwrap();
+        config.jwt.key_type = key_type;
+
+        config
+    }
+
+    #[test]
+    fn check_approved_key_type() {
+        assert_eq!(check(&config(KeyType::ES256)), Ok(()));
+        assert_eq!(check(&config(KeyType::ES384)), Ok(()));
+    }
+
+    #[test]
+    fn check_p521_key_type() {
+        assert_eq!(
+            check(&config(KeyType::ES512)),
+            Err(Error::KeyType(KeyType::ES512))
+        );
+    }
+}
@@ -10,9 +10,12 @@
     clippy::too_many_lines
 )]
 
-use std::{collections::BTreeSet, fs, io, path::Path};
+use std::{cmp::min, collections::BTreeSet, fs, io, path::Path};
 
-use core_objects::KeyType;
+use core_objects::{KeyType, SpiffeId, RESERVED_CLAIMS};
+use request_limits::EndpointLimits;
+
+pub mod fips;
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct Config {
@@ -30,6 +33,71 @@ pub struct Config {
     pub catalog: CatalogConfig,
     #[serde(alias = "node-attestation-config")]
     pub node_attestation_config: NodeAttestationConfig,
+    #[serde(default, alias = "issuance-policy")]
+    pub issuance_policy: IssuancePolicyConfig,
+    #[serde(default, alias = "entry-pruning")]
+    pub entry_pruning: EntryPruningConfig,
+    // Body size and processing time limits of the requests to the admin and server-agent APIs.
+    #[serde(default, alias = "request-limits")]
+    pub request_limits: EndpointLimits,
+    // Add the build reported by the agents to their selectors, so entries can require approved builds.
+    // Requires `agent_build_public_key_path`.
+    #[serde(default)]
+    pub agent_build_selectors: bool,
+    // PEM public key of the release key signing the agent builds. Builds without a valid signature get no
+    // build selectors.
+    #[serde(default)]
+    pub agent_build_public_key_path: Option<String>,
+    // Restrict the key types to the FIPS approved ones and OpenSSL to its FIPS provider.
+    #[serde(default)]
+    pub fips: bool,
+    // Callers of the admin API presenting the token of a tenant only manage the entries of its namespaces.
+    #[serde(default, alias = "admin-tenants")]
+    pub admin_tenants: Vec<AdminTenantConfig>,
+    // Callers of the admin API without a tenant token. Any caller let through by the permissions of the
+    // socket has full access when not set.
+    #[serde(default, alias = "admin-authorization")]
+    pub admin_authorization: Option<AdminAuthorizationConfig>,
+    // Also serve the admin API over TCP with TLS, e.g. for a central identity controller outside of the
+    // pod of the server. Only the socket is served when not set.
+    #[serde(default, alias = "admin-tls-listener")]
+    pub admin_tls_listener: Option<AdminTlsListenerConfig>,
+    // Socket of the entry API of SPIRE over gRPC, for the SPIRE clients such as the registrars. Not served
+    // when not set.
+    #[serde(default, alias = "admin-grpc-socket-path")]
+    pub admin_grpc_socket_path: Option<String>,
+    // Endpoint serving the bundle of the trust domain to the servers of other trust domains, in the SPIFFE
+    // bundle format. Not served when not set.
+    #[serde(default, alias = "federation-bundle-endpoint")]
+    pub federation_bundle_endpoint: Option<BundleEndpointConfig>,
+    // Foreign trust domains whose bundles are fetched from their bundle endpoint and sent to the agents, so the
+    // JWT-SVIDs they issue are accepted.
+    #[serde(default)]
+    pub federates_with: Vec<FederatedTrustDomainConfig>,
+    // OpenID Connect discovery document and JWKS of the JWT-SVIDs, for the services that validate them as OIDC
+    // tokens, e.g. Azure AD workload identity federation. Needs `jwt.issuer`. Not served when not set.
+    #[serde(default, alias = "oidc-discovery")]
+    pub oidc_discovery: Option<OidcDiscoveryConfig>,
+    // Config map the bundle of the trust domain is written to every time it changes, for the workloads and
+    // admission webhooks that cannot reach an agent. Not published when not set.
+    #[serde(default, alias = "bundle-config-map")]
+    pub bundle_config_map: Option<BundleConfigMapConfig>,
+    // Webhook notified of the changes of the registration entries, disabled when not set.
+    #[serde(default, alias = "entry-webhook")]
+    pub entry_webhook: Option<EntryWebhookConfig>,
+    // Append-only audit log of the issued SVIDs, disabled when not set.
+    #[serde(default, alias = "svid-audit")]
+    pub svid_audit: Option<AuditLogConfig>,
+    // Append-only audit log of the changes made through the admin API, disabled when not set. The changes are
+    // logged either way.
+    #[serde(default, alias = "admin-audit")]
+    pub admin_audit: Option<AuditLogConfig>,
+    // Prometheus `/metrics` endpoint, disabled when not set.
+    #[serde(default)]
+    pub metrics: Option<metrics::MetricsConfig>,
+    // Format of the logs, "text" or "json" for log collectors. Changing it needs a restart of the server.
+    #[serde(default, alias = "log-format")]
+    pub log_format: logging::LogFormat,
 }
 
 fn default_server_spiffe_id() -> String {
@@ -43,6 +111,8 @@ fn default_server_spiffe_id() -> String {
 pub enum NodeAttestationConfig {
     Sat(NodeAttestationConfigSat),
     Psat(NodeAttestationConfigPsat),
+    Dps(NodeAttestationConfigDps),
+    X509Pop(NodeAttestationConfigX509Pop),
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
@@ -54,15 +124,84 @@ pub struct NodeAttestationConfigPsat {
     pub allowed_node_label_keys: BTreeSet<String>,
     #[serde(default)]
     pub allowed_pod_label_keys: BTreeSet<String>,
+    // When set, a token is only accepted once until it expires, so a captured token cannot be replayed. The
+    // agents must then request a new token for every attestation, see `token_request` in their config.
+    #[serde(default)]
+    pub reject_token_replays: bool,
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct NodeAttestationConfigSat {}
 
+// Agents on Azure IoT devices, attested with the credentials of their DPS enrollment.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct NodeAttestationConfigDps {
+    pub id_scope: String,
+    // Host of the service API of the provisioning service, where the registrations are looked up.
+    pub service_host: String,
+    pub service_policy_name: String,
+    // File holding the base64 key of the shared access policy. The key files are read on every
+    // attestation, so rotated keys are picked up without a restart.
+    pub service_policy_key_path: String,
+    // Files holding the base64 keys of the symmetric key enrollment groups.
+    #[serde(default)]
+    pub enrollment_group_key_paths: Vec<String>,
+    // PEM file of the CA certificates of the X.509 enrollments.
+    #[serde(default)]
+    pub x509_ca_cert_path: Option<String>,
+    // Only agents on devices assigned to these IoT hubs are attested, any hub when empty.
+    #[serde(default)]
+    pub allowed_iot_hubs: BTreeSet<String>,
+    #[serde(default = "default_dps_max_token_ttl_secs")]
+    pub max_token_ttl_secs: u64,
+}
+
+fn default_dps_max_token_ttl_secs() -> u64 {
+    3600
+}
+
+// Agents with a pre-provisioned certificate, attested by signing a nonce of the server with its key.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct NodeAttestationConfigX509Pop {
+    // PEM file of the CA certificates the agent certificates must chain to.
+    pub ca_bundle_path: String,
+    #[serde(default = "default_nonce_ttl_secs")]
+    pub nonce_ttl_secs: u64,
+}
+
+fn default_nonce_ttl_secs() -> u64 {
+    60
+}
+
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct ServerAgentAPI {
     pub bind_address: String,
     pub bind_port: u16,
+    // Also serve the gRPC variant of the API on this port. Agents keep using the HTTP API unless
+    // they are configured for gRPC.
+    #[serde(default)]
+    pub grpc_bind_port: Option<u16>,
+    // Lifetime of the attestation sessions of the gRPC API, the agents renew them with a new token.
+    #[serde(default = "default_session_ttl_secs")]
+    pub session_ttl_secs: u64,
+    // Serve the HTTP and gRPC APIs over TLS, plain HTTP and gRPC when not set.
+    #[serde(default)]
+    pub tls: Option<ServerAgentApiTlsConfig>,
+}
+
+// Agents over TLS send their attestation token with the request, it is checked before the request is handled.
+// With `client_ca_path`, they must also present a certificate issued by one of its CAs.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ServerAgentApiTlsConfig {
+    // PEM certificate chain of the server, then its private key.
+    pub cert_path: String,
+    pub key_path: String,
+    #[serde(default)]
+    pub client_ca_path: Option<String>,
+}
+
+fn default_session_ttl_secs() -> u64 {
+    300
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
@@ -70,6 +209,324 @@ pub struct JWTConfig {
     pub key_type: KeyType,
     pub key_ttl: u64,
     pub ttl: u64,
+    // Fraction of key_ttl left on the current key when the next key is created and published.
+    #[serde(default = "default_prepare_rotation_fraction")]
+    pub prepare_rotation_fraction: f64,
+    // Fraction of key_ttl left on the current key when the next key replaces it for signing. The
+    // replaced key stays published for that time.
+    #[serde(default = "default_activate_rotation_fraction")]
+    pub activate_rotation_fraction: f64,
+    // Cap of the ttl of the entries. Entries can only shorten the lifetime of their JWT-SVIDs when not set.
+    #[serde(default)]
+    pub max_entry_ttl: Option<u64>,
+    // Give every JWT-SVID a unique jti claim, so the services it is presented to can reject replays.
+    #[serde(default = "default_jwt_id")]
+    pub jwt_id: bool,
+    // When set, JWT-SVIDs get a nbf claim this many seconds before they are issued, so they are accepted by
+    // verifiers whose clock is a little behind.
+    #[serde(default)]
+    pub not_before_skew: Option<u64>,
+    // When set, JWT-SVIDs get this iss claim, the URL of the issuer served by the OIDC discovery endpoint.
+    #[serde(default)]
+    pub issuer: Option<String>,
+}
+
+fn default_jwt_id() -> bool {
+    true
+}
+
+fn default_prepare_rotation_fraction() -> f64 {
+    1.0 / 2.0
+}
+
+fn default_activate_rotation_fraction() -> f64 {
+    1.0 / 6.0
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum JWTConfigError {
+    #[error("JWT rotation fractions must satisfy 0 < activate_rotation_fraction ({activate}) < prepare_rotation_fraction ({prepare}) <= 1")]
+    RotationFractions { prepare: f64, activate: f64 },
+}
+
+impl JWTConfig {
+    // The next key is published before it signs, and replaces the current key before it expires.
+    pub fn validate(&self) -> Result<(), JWTConfigError> {
+        let prepare = self.prepare_rotation_fraction;
+        let activate = self.activate_rotation_fraction;
+
+        if 0.0 < activate && activate < prepare && prepare <= 1.0 {
+            Ok(())
+        } else {
+            Err(JWTConfigError::RotationFractions { prepare, activate })
+        }
+    }
+
+    // Seconds left on the current key when the next key is created.
+    #[must_use]
+    pub fn prepare_rotation_margin(&self) -> u64 {
+        fraction_of(self.key_ttl, self.prepare_rotation_fraction)
+    }
+
+    // Seconds left on the current key when the next key replaces it.
+    #[must_use]
+    pub fn activate_rotation_margin(&self) -> u64 {
+        fraction_of(self.key_ttl, self.activate_rotation_fraction)
+    }
+
+    // Lifetime of the JWT-SVIDs of an entry with this ttl.
+    #[must_use]
+    pub fn entry_ttl(&self, ttl: Option<u64>) -> u64 {
+        match ttl {
+            Some(ttl) => min(ttl, self.max_entry_ttl.unwrap_or(self.ttl)),
+            None => self.ttl,
+        }
+    }
+}
+
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+fn fraction_of(value: u64, fraction: f64) -> u64 {
+    (value as f64 * fraction).round() as u64
+}
+
+// Rules applied to entries that matched a workload, before a JWT-SVID is issued.
+// Everything is allowed by default but custom claims.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct IssuancePolicyConfig {
+    // SPIFFE ID paths an administrator has disabled without deleting the entry.
+    #[serde(default)]
+    pub disabled_spiffe_id_paths: BTreeSet<String>,
+    // If not empty, every requested audience must be part of this list.
+    #[serde(default)]
+    pub allowed_audiences: BTreeSet<String>,
+    // Maximum number of JWT-SVIDs issued for a single request.
+    #[serde(default)]
+    pub max_svids_per_request: Option<usize>,
+    // Names of the custom claims entries may add to their JWT-SVIDs. Entries with other custom claims are
+    // denied.
+    #[serde(default)]
+    pub allowed_custom_claims: BTreeSet<String>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum IssuancePolicyConfigError {
+    #[error("Custom claim {0} is set by the server, it cannot be allowed")]
+    ReservedCustomClaim(String),
+}
+
+impl IssuancePolicyConfig {
+    pub fn validate(&self) -> Result<(), IssuancePolicyConfigError> {
+        match self
+            .allowed_custom_claims
+            .iter()
+            .find(|claim| RESERVED_CLAIMS.contains(&claim.as_str()))
+        {
+            Some(claim) => Err(IssuancePolicyConfigError::ReservedCustomClaim(
+                claim.clone(),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct AdminTenantConfig {
+    pub name: String,
+    // File holding the bearer token of the tenant, a trailing line ending is ignored.
+    pub token_file: String,
+    // Kubernetes namespaces of the workloads of the tenant. A trailing `*` matches any namespace starting
+    // with the rest of the name.
+    pub namespaces: Vec<String>,
+    // SPIFFE ID paths of the entries of the tenant: a prefix matches the path itself and the paths below it,
+    // "team-a" matches "team-a" and "team-a/web" but not "team-ab". The tenant has no paths when not set.
+    #[serde(default)]
+    pub spiffe_id_path_prefixes: Vec<String>,
+    // Trust domains the entries of the tenant may federate with.
+    #[serde(default)]
+    pub federates_with: Vec<String>,
+    // DNS names of the entries of the tenant. A leading `*.` matches the subdomains, "*.team-a.example.com"
+    // matches "web.team-a.example.com".
+    #[serde(default)]
+    pub dns_names: Vec<String>,
+}
+
+// A caller is allowed when it runs as one of `uids` or one of `gids`, or when it presents a JWT-SVID of an
+// admin entry issued for `jwt_svid_audience`. JWT-SVIDs are not accepted when no audience is set.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct AdminAuthorizationConfig {
+    #[serde(default)]
+    pub uids: BTreeSet<u32>,
+    #[serde(default)]
+    pub gids: BTreeSet<u32>,
+    #[serde(default)]
+    pub jwt_svid_audience: Option<String>,
+}
+
+// Callers over TLS present a client certificate issued by a CA of `client_ca_path`, a tenant token, or a
+// JWT-SVID of an admin entry when `admin-authorization` accepts them.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct AdminTlsListenerConfig {
+    pub bind_address: String,
+    pub bind_port: u16,
+    // PEM certificate chain of the server, then its private key.
+    pub cert_path: String,
+    pub key_path: String,
+    // PEM bundle of the CAs of the client certificates. Client certificates are not requested when not set.
+    #[serde(default)]
+    pub client_ca_path: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct BundleEndpointConfig {
+    pub bind_address: String,
+    pub bind_port: u16,
+    pub profile: BundleEndpointProfile,
+}
+
+// How the endpoint authenticates to the servers fetching the bundle. The files are read when the server starts.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BundleEndpointProfile {
+    // PEM certificate chain of the web PKI, e.g. from an ACME CA, then its private key.
+    HttpsWeb { cert_path: String, key_path: String },
+    // PEM X.509-SVID of the server, then its private key. The server does not issue X.509-SVIDs, it is issued
+    // by the CA of the trust domain, e.g. the upstream CA. Its SPIFFE ID must be in the trust domain.
+    HttpsSpiffe { svid_path: String, key_path: String },
+}
+
+// The bundle is fetched again after its refresh hint.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct FederatedTrustDomainConfig {
+    pub trust_domain: String,
+    pub bundle_endpoint_url: String,
+    pub bundle_endpoint_profile: BundleEndpointClientProfile,
+}
+
+const HTTPS_SCHEME: &str = "https://";
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum FederatedTrustDomainConfigError {
+    #[error("The bundle endpoint URL {0} of {1} is not an https URL")]
+    InsecureBundleEndpointUrl(String, String),
+}
+
+impl FederatedTrustDomainConfig {
+    // Both profiles authenticate the endpoint over TLS.
+    pub fn validate(&self) -> Result<(), FederatedTrustDomainConfigError> {
+        let is_https = self
+            .bundle_endpoint_url
+            .get(..HTTPS_SCHEME.len())
+            .map_or(false, |scheme| scheme.eq_ignore_ascii_case(HTTPS_SCHEME));
+        if is_https {
+            Ok(())
+        } else {
+            Err(FederatedTrustDomainConfigError::InsecureBundleEndpointUrl(
+                self.bundle_endpoint_url.clone(),
+                self.trust_domain.clone(),
+            ))
+        }
+    }
+}
+
+// How the bundle endpoint of a foreign trust domain is authenticated.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BundleEndpointClientProfile {
+    // Certificate of the web PKI, checked with the CAs of the system and the host of the URL.
+    HttpsWeb,
+    // X.509-SVID with `endpoint_spiffe_id`, issued by a CA of the PEM bundle in `ca_path`.
+    HttpsSpiffe {
+        endpoint_spiffe_id: String,
+        ca_path: String,
+    },
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct OidcDiscoveryConfig {
+    pub bind_address: String,
+    pub bind_port: u16,
+    // Served over TLS with the certificate chain of `cert_path` and its key, e.g. of the web PKI. Plain HTTP
+    // when not set, e.g. behind an ingress terminating TLS.
+    #[serde(default)]
+    pub tls: Option<OidcDiscoveryTlsConfig>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct OidcDiscoveryTlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+// The bundle is written in the SPIFFE bundle format, under `key` in the data of the config map.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct BundleConfigMapConfig {
+    pub namespace: String,
+    pub name: String,
+    #[serde(default = "default_bundle_config_map_key")]
+    pub key: String,
+}
+
+fn default_bundle_config_map_key() -> String {
+    "bundle.spiffe".to_string()
+}
+
+// The create, update and delete events of the entries are POSTed to `url`, http or https. A failed event is
+// retried up to `max_attempts` times, then dropped. Needs a catalog backend that can watch the entries, not postgres.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct EntryWebhookConfig {
+    pub url: String,
+    // File with a token sent as `Authorization: Bearer <token>`, read for every event so it can be rotated.
+    #[serde(default)]
+    pub token_path: Option<String>,
+    #[serde(default = "default_entry_webhook_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_entry_webhook_max_attempts")]
+    pub max_attempts: u32,
+}
+
+fn default_entry_webhook_timeout_secs() -> u64 {
+    10
+}
+
+fn default_entry_webhook_max_attempts() -> u32 {
+    5
+}
+
+// A file is written by its replica only, the catalog has the records of all the replicas.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type")]
+pub enum AuditLogConfig {
+    File(AuditLogConfigFile),
+    Catalog,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct AuditLogConfigFile {
+    // One JSON record per line, only ever appended to.
+    pub path: String,
+}
+
+// Expired registration entries are deleted from the catalog every `interval` seconds. 0 disables the pruning.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct EntryPruningConfig {
+    #[serde(default = "default_entry_pruning_interval")]
+    pub interval: u64,
+}
+
+impl Default for EntryPruningConfig {
+    fn default() -> Self {
+        EntryPruningConfig {
+            interval: default_entry_pruning_interval(),
+        }
+    }
+}
+
+fn default_entry_pruning_interval() -> u64 {
+    60
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
@@ -82,6 +539,7 @@ pub struct TrustBundleConfig {
 pub enum KeyStoreConfig {
     Disk(KeyStoreConfigDisk),
     Memory(),
+    Tpm(KeyStoreConfigTpm),
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
@@ -89,18 +547,152 @@ pub enum KeyStoreConfig {
 pub enum CatalogConfig {
     Disk,
     Memory,
+    Postgres(CatalogConfigPostgres),
+    Etcd(CatalogConfigEtcd),
+    K8s(CatalogConfigK8s),
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct CatalogConfigK8s {
+    pub namespace: String,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct CatalogConfigPostgres {
+    pub host: String,
+    #[serde(default = "default_postgres_port")]
+    pub port: u16,
+    pub user: String,
+    #[serde(default)]
+    pub password: Option<String>,
+    pub dbname: String,
+    #[serde(default = "default_postgres_max_connections")]
+    pub max_connections: u32,
+    // TLS of the connections, as the sslmode of libpq but the certificate of the database is always verified:
+    // with the CAs of the PEM bundle in `ssl_ca_path`, of the system when not set, and the host.
+    #[serde(default = "default_postgres_ssl_mode")]
+    pub ssl_mode: PostgresSslMode,
+    #[serde(default)]
+    pub ssl_ca_path: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PostgresSslMode {
+    Disable,
+    // TLS when the database supports it.
+    Prefer,
+    Require,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct CatalogConfigEtcd {
+    pub endpoints: Vec<String>,
+    #[serde(default = "default_etcd_key_prefix")]
+    pub key_prefix: String,
+}
+
+fn default_etcd_key_prefix() -> String {
+    "/iotedge-spiffe-server".to_string()
+}
+
+fn default_postgres_port() -> u16 {
+    5432
+}
+
+fn default_postgres_max_connections() -> u32 {
+    16
+}
+
+fn default_postgres_ssl_mode() -> PostgresSslMode {
+    PostgresSslMode::Disable
 }
 
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 pub struct KeyStoreConfigDisk {
     pub key_base_path: String,
+    // The private keys are stored unencrypted without it.
+    #[serde(default)]
+    pub encryption: Option<KeyStoreConfigDiskEncryption>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct KeyStoreConfigDiskEncryption {
+    // File holding the passphrase, or key encryption key, the private keys are encrypted with.
+    pub passphrase_file: String,
+    // Passphrase in use before a passphrase change. The keys still encrypted with it are
+    // re-wrapped with the current passphrase when the key store is opened.
+    #[serde(default)]
+    pub previous_passphrase_file: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct KeyStoreConfigTpm {
+    // TCTI of the TPM: "device:/dev/tpmrm0", or "mssim:host=localhost,port=2321" for a simulator.
+    #[serde(default = "default_tpm_tcti")]
+    pub tcti: String,
+    // The keys are persisted at the `max_keys` handles starting at `handle_base`. The file maps
+    // the key ids to their handle, it must be kept across restarts.
+    pub handle_map_path: String,
+    #[serde(default = "default_tpm_handle_base")]
+    pub handle_base: u32,
+    #[serde(default = "default_tpm_max_keys")]
+    pub max_keys: u32,
+}
+
+fn default_tpm_tcti() -> String {
+    "device:/dev/tpmrm0".to_string()
+}
+
+fn default_tpm_handle_base() -> u32 {
+    0x8100_0100
+}
+
+fn default_tpm_max_keys() -> u32 {
+    16
 }
 
 impl Config {
     pub fn load_config(filename: impl AsRef<Path>) -> Result<Config, io::Error> {
         let config = fs::read_to_string(&filename)?;
 
-        let config = toml::from_str(&config)?;
+        let mut config: Config = toml::from_str(&config)?;
+        // Trust domains are case insensitive, they are kept in lower case as in the SPIFFE IDs.
+        config.trust_domain = SpiffeId::new(&config.trust_domain, "")
+            .map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid trust domain {}: {}", config.trust_domain, err),
+                )
+            })?
+            .trust_domain()
+            .to_string();
+        config
+            .jwt
+            .validate()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        config
+            .issuance_policy
+            .validate()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        for federated_trust_domain in &config.federates_with {
+            federated_trust_domain
+                .validate()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        }
+        if config.oidc_discovery.is_some() && config.jwt.issuer.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "oidc-discovery needs the issuer of the JWT-SVIDs in jwt.issuer",
+            ));
+        }
+        // The webhook watches the entries of the catalog, the postgres catalog cannot watch them.
+        if config.entry_webhook.is_some() && matches!(config.catalog, CatalogConfig::Postgres(_)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "entry-webhook is not supported with the postgres catalog",
+            ));
+        }
 
         Ok(config)
     }
@@ -129,7 +721,114 @@ mod tests {
             let mut buf = Vec::new();
             raw_config.read_to_end(&mut buf).unwrap();
 
-            let _config: Config = toml::from_slice(&buf).unwrap();
+            let config: Config = toml::from_slice(&buf).unwrap();
+            config.jwt.validate().unwrap();
+            config.issuance_policy.validate().unwrap();
+            for federated_trust_domain in &config.federates_with {
+                federated_trust_domain.validate().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn federated_trust_domain_https_url() {
+        let mut config = FederatedTrustDomainConfig {
+            trust_domain: "cloud.contoso.com".to_string(),
+            bundle_endpoint_url: "HTTPS://spire.contoso.com:8443".to_string(),
+            bundle_endpoint_profile: BundleEndpointClientProfile::HttpsWeb,
+        };
+        config.validate().unwrap();
+
+        config.bundle_endpoint_url = "http://spire.contoso.com:8443".to_string();
+        assert_eq!(
+            config.validate(),
+            Err(FederatedTrustDomainConfigError::InsecureBundleEndpointUrl(
+                "http://spire.contoso.com:8443".to_string(),
+                "cloud.contoso.com".to_string()
+            ))
+        );
+    }
+
+    fn jwt_config(prepare: f64, activate: f64) -> JWTConfig {
+        JWTConfig {
+            key_type: KeyType::ES256,
+            key_ttl: 300,
+            ttl: 10,
+            prepare_rotation_fraction: prepare,
+            activate_rotation_fraction: activate,
+            max_entry_ttl: None,
+            jwt_id: default_jwt_id(),
+            not_before_skew: None,
+            issuer: None,
         }
     }
+
+    #[test]
+    fn jwt_rotation_fractions() {
+        let config = jwt_config(
+            default_prepare_rotation_fraction(),
+            default_activate_rotation_fraction(),
+        );
+        config.validate().unwrap();
+        assert_eq!(config.prepare_rotation_margin(), 150);
+        assert_eq!(config.activate_rotation_margin(), 50);
+
+        jwt_config(1.0, 0.1).validate().unwrap();
+        assert!(jwt_config(0.5, 0.5).validate().is_err());
+        assert!(jwt_config(0.5, 0.0).validate().is_err());
+        assert!(jwt_config(1.5, 0.1).validate().is_err());
+        assert!(jwt_config(f64::NAN, 0.1).validate().is_err());
+    }
+
+    #[test]
+    fn issuance_policy_custom_claims() {
+        let mut config = IssuancePolicyConfig::default();
+        config.allowed_custom_claims.insert("ring".to_string());
+        config.validate().unwrap();
+
+        config.allowed_custom_claims.insert("sub".to_string());
+        assert_eq!(
+            config.validate(),
+            Err(IssuancePolicyConfigError::ReservedCustomClaim(
+                "sub".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn jwt_entry_ttl() {
+        let mut config = jwt_config(
+            default_prepare_rotation_fraction(),
+            default_activate_rotation_fraction(),
+        );
+        assert_eq!(config.entry_ttl(None), 10);
+        assert_eq!(config.entry_ttl(Some(5)), 5);
+        assert_eq!(config.entry_ttl(Some(60)), 10);
+
+        config.max_entry_ttl = Some(30);
+        assert_eq!(config.entry_ttl(None), 10);
+        assert_eq!(config.entry_ttl(Some(60)), 30);
+    }
+
+    #[test]
+    fn trust_domain_lower_case() {
+        let config = Config::load_config(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/Config_trust_domain_case.toml"
+        ))
+        .unwrap();
+
+        assert_eq!(config.trust_domain, "iotedge");
+    }
+
+    #[test]
+    fn entry_webhook_postgres_catalog() {
+        let error = Config::load_config(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/invalid/Config_postgres_entry_webhook.toml"
+        ))
+        .unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
 }
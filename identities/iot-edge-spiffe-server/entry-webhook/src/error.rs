@@ -0,0 +1,21 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Cannot create the HTTPS connector: {0}")]
+    Connector(openssl::error::ErrorStack),
+    #[error("Cannot read the webhook token: {0}")]
+    ReadToken(std::io::Error),
+    #[error("Invalid webhook request: {0}")]
+    Request(hyper::http::Error),
+    #[error("Cannot serialize the event: {0}")]
+    Serialize(serde_json::Error),
+    #[error("Webhook request failed: {0}")]
+    Send(hyper::Error),
+    #[error("Webhook request timed out")]
+    Timeout,
+    #[error("Webhook answered {0}")]
+    Status(hyper::StatusCode),
+}
@@ -0,0 +1,325 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::default_trait_access,
+    clippy::let_unit_value,
+    clippy::missing_errors_doc,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+// Publisher of the changes of the registration entries to a webhook of the operator. Events are sent one at a
+// time, in the order of the catalog watch. Every replica of the server watches the catalog and sends every
+// event, receivers deduplicate them with the entry id and revision number. Changes made while the watch
+// restarts, e.g. after the webhook was too slow and the watch fell behind, are not sent.
+
+use std::{sync::Arc, time::Duration};
+
+use catalog::{Catalog, EntryEvent};
+use error::Error;
+use futures_util::{Stream, StreamExt};
+use hyper::{
+    client::HttpConnector,
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    Body, Client, Method, Request,
+};
+use hyper_openssl::HttpsConnector;
+use log::{error, info, warn};
+use server_config::EntryWebhookConfig;
+use tokio::{fs, time};
+
+pub mod error;
+
+const WATCH_RESTART_DELAY: Duration = Duration::from_secs(5);
+// Doubled after every failed attempt of an event.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+// Body POSTed to the webhook. Events of deleted entries have no revision number.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Notification {
+    pub event: EventKind,
+    pub entry_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision_number: Option<u64>,
+}
+
+impl From<EntryEvent> for Notification {
+    fn from(event: EntryEvent) -> Self {
+        let (event, entry_id, revision_number) = match event {
+            EntryEvent::Created(entry) => {
+                (EventKind::Created, entry.id, Some(entry.revision_number))
+            }
+            EntryEvent::Updated(entry) => {
+                (EventKind::Updated, entry.id, Some(entry.revision_number))
+            }
+            EntryEvent::Deleted(id) => (EventKind::Deleted, id, None),
+        };
+
+        Notification {
+            event,
+            entry_id,
+            revision_number,
+        }
+    }
+}
+
+pub struct EntryWebhook {
+    url: String,
+    token_path: Option<String>,
+    timeout: Duration,
+    max_attempts: u32,
+    catalog: Arc<dyn Catalog>,
+    client: Client<HttpsConnector<HttpConnector>, Body>,
+}
+
+impl EntryWebhook {
+    pub fn new(config: &EntryWebhookConfig, catalog: Arc<dyn Catalog>) -> Result<Self, Error> {
+        let connector = HttpsConnector::new().map_err(Error::Connector)?;
+
+        Ok(EntryWebhook {
+            url: config.url.clone(),
+            token_path: config.token_path.clone(),
+            timeout: Duration::from_secs(config.timeout_secs),
+            max_attempts: config.max_attempts.max(1),
+            catalog,
+            client: Client::builder().build(connector),
+        })
+    }
+
+    // Only returns when the catalog backend cannot watch the entries.
+    pub async fn run(&self) {
+        info!("Starting entry webhook to {}", self.url);
+
+        loop {
+            match self.catalog.watch().await {
+                Ok(events) => {
+                    self.publish(events).await;
+                    warn!("The watch of the entries for the webhook ended, restarting it");
+                }
+                Err(err) => {
+                    if let Some(catalog::Error::Unsupported(_)) =
+                        err.downcast_ref::<catalog::Error>()
+                    {
+                        warn!("The catalog backend cannot watch the entries, the entry webhook is disabled");
+                        return;
+                    }
+                    error!("Cannot watch the entries for the webhook: {}", err);
+                }
+            }
+
+            time::sleep(WATCH_RESTART_DELAY).await;
+        }
+    }
+
+    // Until the stream ends or fails.
+    async fn publish<S>(&self, mut events: S)
+    where
+        S: Stream<Item = Result<EntryEvent, Box<dyn std::error::Error + Send>>> + Unpin,
+    {
+        while let Some(event) = events.next().await {
+            match event {
+                Ok(event) => self.deliver(&Notification::from(event)).await,
+                Err(err) => {
+                    error!("Error watching the entries for the webhook: {}", err);
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn deliver(&self, notification: &Notification) {
+        let mut delay = RETRY_DELAY;
+
+        for attempt in 1..=self.max_attempts {
+            match self.send(notification).await {
+                Ok(()) => return,
+                Err(err) if attempt < self.max_attempts => {
+                    warn!(
+                        "Attempt {} to send the {:?} event of entry {} failed: {}",
+                        attempt, notification.event, notification.entry_id, err
+                    );
+                    time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RETRY_DELAY);
+                }
+                Err(err) => error!(
+                    "Dropping the {:?} event of entry {} after {} attempts: {}",
+                    notification.event, notification.entry_id, attempt, err
+                ),
+            }
+        }
+    }
+
+    async fn send(&self, notification: &Notification) -> Result<(), Error> {
+        let body = serde_json::to_vec(notification).map_err(Error::Serialize)?;
+
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(&self.url)
+            .header(CONTENT_TYPE, "application/json");
+        if let Some(token_path) = &self.token_path {
+            let token = fs::read_to_string(token_path)
+                .await
+                .map_err(Error::ReadToken)?;
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token.trim()));
+        }
+        let request = request.body(Body::from(body)).map_err(Error::Request)?;
+
+        let response = time::timeout(self.timeout, self.client.request(request))
+            .await
+            .map_err(|_| Error::Timeout)?
+            .map_err(Error::Send)?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(Error::Status(response.status()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        io::Write,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use catalog::Entries;
+    use core_objects::RegistrationEntry;
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Response, Server, StatusCode,
+    };
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    type Received = mpsc::UnboundedReceiver<(Option<String>, Notification)>;
+
+    // Answers 500 to the first `failures` requests.
+    fn webhook_server(failures: usize) -> (String, Received) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let failures = Arc::new(AtomicUsize::new(failures));
+
+        let make_service = make_service_fn(move |_| {
+            let tx = tx.clone();
+            let failures = failures.clone();
+
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let tx = tx.clone();
+                    let failures = failures.clone();
+
+                    async move {
+                        let authorization = request
+                            .headers()
+                            .get(AUTHORIZATION)
+                            .map(|value| value.to_str().unwrap().to_string());
+                        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                        tx.send((authorization, serde_json::from_slice(&body).unwrap()))
+                            .unwrap();
+
+                        let status = match failures.fetch_update(
+                            Ordering::SeqCst,
+                            Ordering::SeqCst,
+                            |failures| failures.checked_sub(1),
+                        ) {
+                            Ok(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                            Err(_) => StatusCode::OK,
+                        };
+                        let mut response = Response::new(Body::empty());
+                        *response.status_mut() = status;
+
+                        Ok::<_, Infallible>(response)
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let url = format!("http://{}/events", server.local_addr());
+        tokio::spawn(server);
+
+        (url, rx)
+    }
+
+    fn config(url: String, token_path: Option<String>) -> EntryWebhookConfig {
+        EntryWebhookConfig {
+            url,
+            token_path,
+            timeout_secs: 5,
+            max_attempts: 2,
+        }
+    }
+
+    fn notification(event: EventKind, revision_number: Option<u64>) -> Notification {
+        Notification {
+            event,
+            entry_id: "entry1".to_string(),
+            revision_number,
+        }
+    }
+
+    #[tokio::test]
+    async fn publishes_entry_events() {
+        let (url, mut received) = webhook_server(0);
+        let mut token = tempfile::NamedTempFile::new().unwrap();
+        writeln!(token, "token").unwrap();
+        let token_path = token.path().to_str().unwrap().to_string();
+
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+        let webhook = EntryWebhook::new(&config(url, Some(token_path)), catalog.clone()).unwrap();
+        let events = catalog.watch().await.unwrap();
+
+        catalog
+            .batch_create(vec![RegistrationEntry::test_entry("entry1")])
+            .await
+            .unwrap();
+        catalog
+            .batch_update(vec![RegistrationEntry::test_entry("entry1")])
+            .await
+            .unwrap();
+        catalog.batch_delete(&["entry1".to_string()]).await.unwrap();
+        webhook.publish(events.take(3)).await;
+
+        let expected = [
+            notification(EventKind::Created, Some(0)),
+            notification(EventKind::Updated, Some(1)),
+            notification(EventKind::Deleted, None),
+        ];
+        for expected in expected {
+            let (authorization, notification) = received.recv().await.unwrap();
+            assert_eq!(authorization.as_deref(), Some("Bearer token"));
+            assert_eq!(notification, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_failed_events() {
+        let (url, mut received) = webhook_server(1);
+        let catalog = Arc::new(catalog::inmemory::Catalog::new());
+        let webhook = EntryWebhook::new(&config(url, None), catalog).unwrap();
+
+        let created = notification(EventKind::Created, Some(0));
+        webhook.deliver(&created).await;
+
+        for _ in 0..2 {
+            let (authorization, notification) = received.recv().await.unwrap();
+            assert_eq!(authorization, None);
+            assert_eq!(notification, created);
+        }
+        assert!(received.try_recv().is_err());
+    }
+}
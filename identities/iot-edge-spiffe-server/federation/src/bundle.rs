@@ -0,0 +1,151 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// SPIFFE bundle format of the SPIFFE Trust Domain and Bundle specification: a JWK set with the keys of every
+// SVID type, each with its `use`, and the sequence number and refresh hint of the bundle. The key members are
+// base64url, like in the trust bundles of the server.
+
+use core_objects::{JWKSet, KeyUse, TrustBundle, JWK};
+use log::warn;
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct SpiffeBundle {
+    pub keys: Vec<JWK>,
+    pub spiffe_sequence: u64,
+    pub spiffe_refresh_hint: u64,
+}
+
+impl From<&TrustBundle> for SpiffeBundle {
+    fn from(trust_bundle: &TrustBundle) -> Self {
+        let keys = trust_bundle
+            .jwt_key_set
+            .keys
+            .iter()
+            .chain(&trust_bundle.x509_key_set.keys)
+            .cloned()
+            .collect();
+
+        SpiffeBundle {
+            keys,
+            spiffe_sequence: trust_bundle.jwt_key_set.spiffe_sequence_number,
+            spiffe_refresh_hint: trust_bundle.jwt_key_set.spiffe_refresh_hint,
+        }
+    }
+}
+
+impl SpiffeBundle {
+    // Bundle of a foreign trust domain, as served by its bundle endpoint. Only the JWT authorities are kept, the
+    // server does not handle X.509-SVIDs. Keys that cannot be parsed, e.g. of an unknown key type, are skipped
+    // as the specification requires, the other keys of the bundle are still used.
+    pub fn parse(
+        trust_domain: &str,
+        bundle: &[u8],
+        default_refresh_hint: u64,
+    ) -> Result<TrustBundle, serde_json::Error> {
+        let bundle: ForeignBundle = serde_json::from_slice(bundle)?;
+
+        let keys = bundle
+            .keys
+            .into_iter()
+            .filter_map(|key| match serde_json::from_value::<JWK>(key) {
+                Ok(jwk) => Some(jwk),
+                Err(err) => {
+                    warn!("Skipping a key of the bundle of {}: {}", trust_domain, err);
+                    None
+                }
+            })
+            .filter(|jwk| jwk.key_use == KeyUse::JWTSVID)
+            .collect();
+        let refresh_hint = bundle.spiffe_refresh_hint.unwrap_or(default_refresh_hint);
+
+        Ok(TrustBundle {
+            trust_domain: trust_domain.to_string(),
+            jwt_key_set: JWKSet {
+                keys,
+                spiffe_refresh_hint: refresh_hint,
+                spiffe_sequence_number: bundle.spiffe_sequence,
+            },
+            x509_key_set: JWKSet {
+                keys: Vec::new(),
+                spiffe_refresh_hint: refresh_hint,
+                spiffe_sequence_number: bundle.spiffe_sequence,
+            },
+        })
+    }
+}
+
+// The keys are parsed one by one, so a key of the foreign trust domain we cannot parse does not fail the bundle.
+#[derive(serde::Deserialize)]
+struct ForeignBundle {
+    keys: Vec<serde_json::Value>,
+    #[serde(default)]
+    spiffe_sequence: u64,
+    #[serde(default)]
+    spiffe_refresh_hint: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use core_objects::{Crv, JWKSet, KeyUse, Kty};
+
+    use super::*;
+
+    #[test]
+    fn from_trust_bundle() {
+        let jwk = JWK {
+            x: "a-b_c".to_string(),
+            y: "d-e".to_string(),
+            kty: Kty::EC,
+            crv: Some(Crv::P256),
+            n: String::new(),
+            e: String::new(),
+            kid: "kid".to_string(),
+            key_use: KeyUse::JWTSVID,
+        };
+        let trust_bundle = TrustBundle {
+            trust_domain: "iotedge".to_string(),
+            jwt_key_set: JWKSet {
+                keys: vec![jwk.clone()],
+                spiffe_refresh_hint: 300,
+                spiffe_sequence_number: 2,
+            },
+            x509_key_set: JWKSet {
+                keys: Vec::new(),
+                spiffe_refresh_hint: 300,
+                spiffe_sequence_number: 2,
+            },
+        };
+
+        let bundle = SpiffeBundle::from(&trust_bundle);
+        assert_eq!(bundle.spiffe_sequence, 2);
+        assert_eq!(bundle.spiffe_refresh_hint, 300);
+        assert_eq!(bundle.keys, vec![jwk]);
+
+        let bundle = serde_json::to_value(&bundle).unwrap();
+        assert_eq!(bundle["keys"][0]["use"], "jwt-svid");
+        assert_eq!(bundle["keys"][0]["kty"], "EC");
+        assert_eq!(bundle["spiffe_sequence"], 2);
+    }
+
+    #[test]
+    fn parse_foreign_bundle() {
+        let bundle = br#"{
+            "keys": [
+                {"use": "jwt-svid", "kty": "EC", "kid": "a", "crv": "P-256", "x": "a-b_c", "y": "d-e"},
+                {"use": "x509-svid", "kty": "EC", "crv": "P-256", "x": "x", "y": "y", "x5c": ["MIIB"]},
+                {"use": "jwt-svid", "kty": "EC", "kid": "b", "crv": "secp256k1", "x": "x", "y": "y"}
+            ],
+            "spiffe_sequence": 7
+        }"#;
+
+        let trust_bundle = SpiffeBundle::parse("cloud.contoso.com", bundle, 300).unwrap();
+        assert_eq!(trust_bundle.trust_domain, "cloud.contoso.com");
+        assert_eq!(trust_bundle.jwt_key_set.spiffe_sequence_number, 7);
+        assert_eq!(trust_bundle.jwt_key_set.spiffe_refresh_hint, 300);
+        assert_eq!(trust_bundle.jwt_key_set.keys.len(), 1);
+        assert_eq!(trust_bundle.jwt_key_set.keys[0].kid, "a");
+        assert_eq!(trust_bundle.jwt_key_set.keys[0].x, "a-b_c");
+        assert!(trust_bundle.x509_key_set.keys.is_empty());
+
+        SpiffeBundle::parse("cloud.contoso.com", b"{}", 300).unwrap_err();
+    }
+}
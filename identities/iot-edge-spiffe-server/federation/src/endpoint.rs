@@ -0,0 +1,276 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Bundle endpoint of SPIFFE Federation. GET / answers the current bundle of the trust domain in the SPIFFE
+// bundle format, over TLS with the https_web or the https_spiffe profile. The bundle is public, callers are
+// not authenticated.
+
+use std::{convert::Infallible, sync::Arc};
+
+use hyper::{
+    header::{HeaderValue, CONTENT_TYPE},
+    server::conn::Http,
+    service::service_fn,
+    Body, Method, Request, Response, StatusCode,
+};
+use log::{error, info};
+use openssl::{
+    ssl::SslAcceptor,
+    x509::{X509Ref, X509},
+};
+use server_config::{BundleEndpointConfig, BundleEndpointProfile};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+use tokio_openssl::SslStream;
+use trust_bundle_builder::TrustBundleBuilder;
+
+use crate::{bundle::SpiffeBundle, error::Error};
+
+const BUNDLE_PATH: &str = "/";
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+pub async fn start(
+    config: &BundleEndpointConfig,
+    trust_domain: &str,
+    trust_bundle_builder: Arc<TrustBundleBuilder>,
+) -> Result<JoinHandle<()>, Error> {
+    let acceptor = acceptor(&config.profile, trust_domain)?;
+    let listener = TcpListener::bind((config.bind_address.as_str(), config.bind_port))
+        .await
+        .map_err(Error::Bind)?;
+
+    info!(
+        "Starting bundle endpoint on {}:{}",
+        config.bind_address, config.bind_port
+    );
+    Ok(tokio::spawn(serve(
+        listener,
+        acceptor,
+        trust_bundle_builder,
+    )))
+}
+
+fn acceptor(profile: &BundleEndpointProfile, trust_domain: &str) -> Result<SslAcceptor, Error> {
+    let (cert_path, key_path) = match profile {
+        BundleEndpointProfile::HttpsWeb {
+            cert_path,
+            key_path,
+        } => (cert_path, key_path),
+        BundleEndpointProfile::HttpsSpiffe {
+            svid_path,
+            key_path,
+        } => {
+            let svid = std::fs::read(svid_path).map_err(Error::ReadSvid)?;
+            let svid = X509::from_pem(&svid).map_err(Error::Tls)?;
+            check_spiffe_id(&svid, trust_domain)?;
+
+            (svid_path, key_path)
+        }
+    };
+
+    tls_listener::acceptor(cert_path, key_path, |_| Ok(())).map_err(Error::Tls)
+}
+
+fn check_spiffe_id(svid: &X509Ref, trust_domain: &str) -> Result<(), Error> {
+    let spiffe_id = crate::spiffe_id(svid).ok_or(Error::SvidWithoutSpiffeId)?;
+
+    let svid_trust_domain = spiffe_id
+        .strip_prefix(core_objects::SPIFFE_ID_PREFIX)
+        .map(|rest| rest.split('/').next().unwrap_or_default());
+    match svid_trust_domain {
+        Some(svid_trust_domain) if svid_trust_domain.eq_ignore_ascii_case(trust_domain) => Ok(()),
+        _ => Err(Error::SvidNotInTrustDomain(
+            spiffe_id,
+            trust_domain.to_string(),
+        )),
+    }
+}
+
+async fn serve(
+    listener: TcpListener,
+    acceptor: SslAcceptor,
+    trust_bundle_builder: Arc<TrustBundleBuilder>,
+) {
+    tls_listener::serve(listener, acceptor, "bundle endpoint", move |stream, _| {
+        serve_connection(stream, trust_bundle_builder.clone())
+    })
+    .await;
+}
+
+async fn serve_connection(
+    stream: SslStream<TcpStream>,
+    trust_bundle_builder: Arc<TrustBundleBuilder>,
+) {
+    let service = service_fn(move |request| {
+        let trust_bundle_builder = trust_bundle_builder.clone();
+
+        async move { Ok::<_, Infallible>(handle(&request, &trust_bundle_builder).await) }
+    });
+
+    if let Err(err) = Http::new()
+        .http1_only(true)
+        .serve_connection(stream, service)
+        .await
+    {
+        error!("Error serving the bundle endpoint: {}", err);
+    }
+}
+
+async fn handle(
+    request: &Request<Body>,
+    trust_bundle_builder: &TrustBundleBuilder,
+) -> Response<Body> {
+    if request.method() != Method::GET || request.uri().path() != BUNDLE_PATH {
+        return status(StatusCode::NOT_FOUND);
+    }
+
+    let bundle = match trust_bundle_builder.build_trust_bundle(true, true).await {
+        Ok(trust_bundle) => SpiffeBundle::from(&trust_bundle),
+        Err(err) => {
+            error!("Cannot build the bundle of the bundle endpoint: {}", err);
+            return status(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let body = match serde_json::to_vec(&bundle) {
+        Ok(body) => body,
+        Err(err) => {
+            error!(
+                "Cannot serialize the bundle of the bundle endpoint: {}",
+                err
+            );
+            return status(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let mut response = Response::new(Body::from(body));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(JSON_CONTENT_TYPE));
+
+    response
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use catalog::inmemory;
+    use core_objects::CONFIG_DEFAULT_PATH;
+    use key_manager::KeyManager;
+    use openssl::{
+        asn1::Asn1Time,
+        ec::{EcGroup, EcKey},
+        hash::MessageDigest,
+        nid::Nid,
+        pkey::PKey,
+        x509::extension::SubjectAlternativeName,
+    };
+    use server_config::Config;
+
+    use super::*;
+
+    // Self-signed, with a URI SAN when set.
+    fn svid(uri: Option<&str>) -> X509 {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        if let Some(uri) = uri {
+            let subject_alt_name = SubjectAlternativeName::new()
+                .uri(uri)
+                .build(&builder.x509v3_context(None, None))
+                .unwrap();
+            builder.append_extension(subject_alt_name).unwrap();
+        }
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+
+        builder.build()
+    }
+
+    #[test]
+    fn spiffe_id_in_trust_domain() {
+        check_spiffe_id(&svid(Some("spiffe://iotedge/bundle-endpoint")), "iotedge").unwrap();
+        check_spiffe_id(&svid(Some("spiffe://IoTEdge/bundle-endpoint")), "iotedge").unwrap();
+
+        let error =
+            check_spiffe_id(&svid(Some("spiffe://iotedge.org/server")), "iotedge").unwrap_err();
+        assert!(matches!(error, Error::SvidNotInTrustDomain(_, _)));
+        let error = check_spiffe_id(&svid(Some("https://iotedge/")), "iotedge").unwrap_err();
+        assert!(matches!(error, Error::SvidNotInTrustDomain(_, _)));
+        let error = check_spiffe_id(&svid(None), "iotedge").unwrap_err();
+        assert!(matches!(error, Error::SvidWithoutSpiffeId));
+    }
+
+    #[tokio::test]
+    async fn handle_bundle_request() {
+        let config = Config::load_config(CONFIG_DEFAULT_PATH).unwrap();
+        let catalog = Arc::new(inmemory::Catalog::new());
+        let key_store = Arc::new(key_store::inmemory::KeyStore::new());
+        let key_manager = KeyManager::new(&config, catalog.clone(), key_store, 0)
+            .await
+            .unwrap();
+        let trust_bundle_builder = TrustBundleBuilder::new(&config, catalog);
+
+        let request = Request::get(BUNDLE_PATH).body(Body::empty()).unwrap();
+        let response = handle(&request, &trust_bundle_builder).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], JSON_CONTENT_TYPE);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let bundle: SpiffeBundle = serde_json::from_slice(&body).unwrap();
+        let slots = key_manager.slots.read().await;
+        assert_eq!(bundle.keys.len(), 1);
+        assert_eq!(bundle.keys[0].kid, slots.current_jwt_key.kid);
+        assert_eq!(bundle.spiffe_refresh_hint, config.trust_bundle.refresh_hint);
+
+        let request = Request::post(BUNDLE_PATH).body(Body::empty()).unwrap();
+        let response = handle(&request, &trust_bundle_builder).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    // The kid is the RFC 7638 thumbprint, over the base64url members, of the key as served. Keys are rotated
+    // until one has a coordinate where base64url and standard base64 differ.
+    #[tokio::test]
+    async fn served_kid_is_thumbprint() {
+        let config = Config::load_config(CONFIG_DEFAULT_PATH).unwrap();
+        let catalog = Arc::new(inmemory::Catalog::new());
+        let key_store = Arc::new(key_store::inmemory::KeyStore::new());
+        let key_manager = KeyManager::new(&config, catalog.clone(), key_store, 0)
+            .await
+            .unwrap();
+        let trust_bundle_builder = TrustBundleBuilder::new(&config, catalog);
+
+        for _ in 0..100 {
+            let request = Request::get(BUNDLE_PATH).body(Body::empty()).unwrap();
+            let response = handle(&request, &trust_bundle_builder).await;
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let bundle: SpiffeBundle = serde_json::from_slice(&body).unwrap();
+            let jwk = &bundle.keys[0];
+
+            let coordinates = format!("{}{}", jwk.x, jwk.y);
+            assert!(!coordinates.contains(['+', '/']));
+            if coordinates.contains(['-', '_']) {
+                assert_eq!(jwk.kid, jwk.thumbprint().unwrap());
+                return;
+            }
+
+            key_manager.revoke_current_key().await.unwrap();
+        }
+
+        panic!("No key with a '-' or '_' in its coordinates");
+    }
+}
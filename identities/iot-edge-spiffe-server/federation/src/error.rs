@@ -0,0 +1,35 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Cannot bind the bundle endpoint: {0}")]
+    Bind(std::io::Error),
+    #[error("Invalid bundle endpoint TLS configuration: {0}")]
+    Tls(openssl::error::ErrorStack),
+    #[error("Cannot read the X.509-SVID of the bundle endpoint: {0}")]
+    ReadSvid(std::io::Error),
+    #[error("The X.509-SVID of the bundle endpoint has no SPIFFE ID")]
+    SvidWithoutSpiffeId,
+    #[error("SPIFFE ID {0} of the bundle endpoint is not in trust domain {1}")]
+    SvidNotInTrustDomain(String, String),
+    #[error("Invalid federated trust domain: {0}")]
+    Config(server_config::FederatedTrustDomainConfigError),
+    #[error("Cannot create the HTTPS connector of the bundle endpoint of {0}: {1}")]
+    Connector(String, openssl::error::ErrorStack),
+    #[error("Invalid bundle endpoint URL: {0}")]
+    Request(hyper::http::Error),
+    #[error("Bundle endpoint request failed: {0}")]
+    Fetch(hyper::Error),
+    #[error("Bundle endpoint request timed out")]
+    Timeout,
+    #[error("Bundle endpoint answered {0}")]
+    Status(hyper::StatusCode),
+    #[error("Bundle larger than {0} bytes")]
+    BundleTooLarge(usize),
+    #[error("Invalid bundle: {0}")]
+    Parse(serde_json::Error),
+    #[error("Cannot save the bundle: {0}")]
+    Catalog(Box<dyn std::error::Error + Send>),
+}
@@ -0,0 +1,30 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::default_trait_access,
+    clippy::let_unit_value,
+    clippy::missing_errors_doc,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+// SPIFFE Federation: the bundle of the trust domain is served to the servers of other trust domains, e.g. a
+// SPIRE deployment in the cloud, so they accept the JWT-SVIDs issued here. The bundles of the other trust
+// domains are fetched the same way and sent to the agents, so the JWT-SVIDs issued there are accepted here.
+
+use openssl::x509::X509Ref;
+
+pub mod bundle;
+pub mod endpoint;
+pub mod error;
+pub mod manager;
+
+// An X.509-SVID has exactly one URI SAN, its SPIFFE ID.
+pub(crate) fn spiffe_id(svid: &X509Ref) -> Option<String> {
+    svid.subject_alt_names()
+        .iter()
+        .flatten()
+        .find_map(|san| san.uri().map(ToString::to_string))
+}
@@ -0,0 +1,341 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Fetches the bundles of the federated trust domains from their bundle endpoint and saves them in the catalog,
+// where the trust bundle builder reads them for the agents. Every trust domain is refreshed on its own, after the
+// refresh hint of its last bundle. The saved bundle is kept when a fetch fails, until a later fetch succeeds.
+
+use std::{sync::Arc, time::Duration};
+
+use catalog::Catalog;
+use core_objects::TrustBundle;
+use futures_util::future;
+use hyper::{body::HttpBody, client::HttpConnector, Body, Client, Method, Request};
+use hyper_openssl::HttpsConnector;
+use log::{error, info};
+use openssl::{
+    error::ErrorStack,
+    ssl::{SslConnector, SslMethod, SslVerifyMode},
+};
+use server_config::{BundleEndpointClientProfile, FederatedTrustDomainConfig};
+use tokio::time;
+
+use crate::{bundle::SpiffeBundle, error::Error};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+// Bundles hold a few keys, a larger body is not read into memory.
+const MAX_BUNDLE_SIZE: usize = 1024 * 1024;
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+// Refresh hint of the bundles without one. The hints of the foreign trust domains are clamped, so a bundle
+// endpoint is not polled in a loop, and keys are not missed for days.
+const DEFAULT_REFRESH_HINT_SECS: u64 = 300;
+const MIN_REFRESH_HINT: Duration = Duration::from_secs(30);
+const MAX_REFRESH_HINT: Duration = Duration::from_secs(3600);
+
+struct FederatedTrustDomain {
+    trust_domain: String,
+    url: String,
+    client: Client<HttpsConnector<HttpConnector>, Body>,
+}
+
+pub struct FederationManager {
+    trust_domains: Vec<FederatedTrustDomain>,
+    catalog: Arc<dyn Catalog>,
+}
+
+impl FederationManager {
+    pub fn new(
+        config: &[FederatedTrustDomainConfig],
+        catalog: Arc<dyn Catalog>,
+    ) -> Result<Self, Error> {
+        let trust_domains = config
+            .iter()
+            .map(|config| {
+                config.validate().map_err(Error::Config)?;
+                let connector = connector(&config.bundle_endpoint_profile)
+                    .map_err(|err| Error::Connector(config.trust_domain.clone(), err))?;
+
+                Ok(FederatedTrustDomain {
+                    trust_domain: config.trust_domain.clone(),
+                    url: config.bundle_endpoint_url.clone(),
+                    client: Client::builder().build(connector),
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(FederationManager {
+            trust_domains,
+            catalog,
+        })
+    }
+
+    // Never returns.
+    pub async fn run(&self) {
+        future::join_all(
+            self.trust_domains
+                .iter()
+                .map(|trust_domain| self.refresh_loop(trust_domain)),
+        )
+        .await;
+    }
+
+    async fn refresh_loop(&self, trust_domain: &FederatedTrustDomain) {
+        info!(
+            "Fetching the bundle of {} from {}",
+            trust_domain.trust_domain, trust_domain.url
+        );
+
+        loop {
+            let delay = match self.refresh(trust_domain).await {
+                Ok(trust_bundle) => {
+                    Duration::from_secs(trust_bundle.jwt_key_set.spiffe_refresh_hint)
+                        .clamp(MIN_REFRESH_HINT, MAX_REFRESH_HINT)
+                }
+                Err(err) => {
+                    error!(
+                        "Cannot refresh the bundle of {}: {}",
+                        trust_domain.trust_domain, err
+                    );
+                    RETRY_DELAY
+                }
+            };
+
+            time::sleep(delay).await;
+        }
+    }
+
+    async fn refresh(&self, trust_domain: &FederatedTrustDomain) -> Result<TrustBundle, Error> {
+        let trust_bundle = fetch(trust_domain).await?;
+        self.catalog
+            .set_federated_bundle(trust_bundle.clone())
+            .await
+            .map_err(Error::Catalog)?;
+
+        Ok(trust_bundle)
+    }
+}
+
+async fn fetch(trust_domain: &FederatedTrustDomain) -> Result<TrustBundle, Error> {
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(&trust_domain.url)
+        .body(Body::empty())
+        .map_err(Error::Request)?;
+
+    let response = time::timeout(FETCH_TIMEOUT, trust_domain.client.request(request))
+        .await
+        .map_err(|_| Error::Timeout)?
+        .map_err(Error::Fetch)?;
+    if !response.status().is_success() {
+        return Err(Error::Status(response.status()));
+    }
+    let body = time::timeout(FETCH_TIMEOUT, read_body(response.into_body()))
+        .await
+        .map_err(|_| Error::Timeout)??;
+
+    SpiffeBundle::parse(&trust_domain.trust_domain, &body, DEFAULT_REFRESH_HINT_SECS)
+        .map_err(Error::Parse)
+}
+
+async fn read_body(mut body: Body) -> Result<Vec<u8>, Error> {
+    // The Content-Length of the response, when it has one.
+    if body.size_hint().lower() > MAX_BUNDLE_SIZE as u64 {
+        return Err(Error::BundleTooLarge(MAX_BUNDLE_SIZE));
+    }
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(Error::Fetch)?;
+        if bytes.len() + chunk.len() > MAX_BUNDLE_SIZE {
+            return Err(Error::BundleTooLarge(MAX_BUNDLE_SIZE));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok(bytes)
+}
+
+// https_spiffe endpoints have no DNS SAN to match the host of the URL with, their SPIFFE ID is checked instead.
+fn connector(
+    profile: &BundleEndpointClientProfile,
+) -> Result<HttpsConnector<HttpConnector>, ErrorStack> {
+    match profile {
+        BundleEndpointClientProfile::HttpsWeb => HttpsConnector::new(),
+        BundleEndpointClientProfile::HttpsSpiffe {
+            endpoint_spiffe_id,
+            ca_path,
+        } => {
+            let mut http = HttpConnector::new();
+            http.enforce_http(false);
+
+            let mut builder = SslConnector::builder(SslMethod::tls_client())?;
+            builder.set_ca_file(ca_path)?;
+            let endpoint_spiffe_id = endpoint_spiffe_id.clone();
+            builder.set_verify_callback(SslVerifyMode::PEER, move |preverified, context| {
+                if !preverified || context.error_depth() != 0 {
+                    return preverified;
+                }
+
+                context
+                    .current_cert()
+                    .and_then(crate::spiffe_id)
+                    .map_or(false, |spiffe_id| spiffe_id == endpoint_spiffe_id)
+            });
+
+            let mut connector = HttpsConnector::with_connector(http, builder)?;
+            connector.set_callback(|config, _uri| {
+                config.set_verify_hostname(false);
+                Ok(())
+            });
+
+            Ok(connector)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, pin::Pin};
+
+    use catalog::{inmemory, TrustBundleStore};
+    use hyper::{server::conn::Http, service::service_fn, Response};
+    use openssl::{
+        asn1::Asn1Time,
+        ec::{EcGroup, EcKey},
+        hash::MessageDigest,
+        nid::Nid,
+        pkey::PKey,
+        ssl::SslAcceptor,
+        x509::{extension::SubjectAlternativeName, X509},
+    };
+    use tokio::net::TcpListener;
+    use tokio_openssl::SslStream;
+
+    use super::*;
+
+    const ENDPOINT_SPIFFE_ID: &str = "spiffe://cloud.contoso.com/bundle-endpoint";
+
+    // Serves the bundle over TLS with a self-signed X.509-SVID, its CA file is written in `dir`.
+    async fn bundle_server(bundle: String, dir: &tempfile::TempDir) -> (String, String) {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        let subject_alt_name = SubjectAlternativeName::new()
+            .uri(ENDPOINT_SPIFFE_ID)
+            .build(&builder.x509v3_context(None, None))
+            .unwrap();
+        builder.append_extension(subject_alt_name).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        let svid = builder.build();
+
+        let ca_path = dir.path().join("ca.pem");
+        std::fs::write(&ca_path, svid.to_pem().unwrap()).unwrap();
+
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls_server()).unwrap();
+        acceptor.set_certificate(&svid).unwrap();
+        acceptor.set_private_key(&key).unwrap();
+        let acceptor = acceptor.build();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("https://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let ssl = openssl::ssl::Ssl::new(acceptor.context()).unwrap();
+                let mut stream = SslStream::new(ssl, stream).unwrap();
+                let bundle = bundle.clone();
+
+                tokio::spawn(async move {
+                    if Pin::new(&mut stream).accept().await.is_err() {
+                        return;
+                    }
+                    let service = service_fn(move |_request: Request<Body>| {
+                        let bundle = bundle.clone();
+                        async move { Ok::<_, Infallible>(Response::new(Body::from(bundle))) }
+                    });
+                    let _ = Http::new().serve_connection(stream, service).await;
+                });
+            }
+        });
+
+        (url, ca_path.to_str().unwrap().to_string())
+    }
+
+    fn config(url: String, ca_path: String) -> FederatedTrustDomainConfig {
+        FederatedTrustDomainConfig {
+            trust_domain: "cloud.contoso.com".to_string(),
+            bundle_endpoint_url: url,
+            bundle_endpoint_profile: BundleEndpointClientProfile::HttpsSpiffe {
+                endpoint_spiffe_id: ENDPOINT_SPIFFE_ID.to_string(),
+                ca_path,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn refresh_saves_the_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, ca_path) = bundle_server(
+            r#"{"keys":[{"use":"jwt-svid","kty":"EC","kid":"a","crv":"P-256","x":"x","y":"y"}],"spiffe_sequence":1,"spiffe_refresh_hint":60}"#.to_string(),
+            &dir,
+        )
+        .await;
+        let catalog = Arc::new(inmemory::Catalog::new());
+        let manager = FederationManager::new(&[config(url, ca_path)], catalog.clone()).unwrap();
+
+        let trust_bundle = manager.refresh(&manager.trust_domains[0]).await.unwrap();
+        assert_eq!(trust_bundle.jwt_key_set.spiffe_refresh_hint, 60);
+
+        let federated_bundles = catalog.get_federated_bundles().await.unwrap();
+        assert_eq!(federated_bundles, vec![trust_bundle]);
+        assert_eq!(federated_bundles[0].trust_domain, "cloud.contoso.com");
+        assert_eq!(federated_bundles[0].jwt_key_set.keys[0].kid, "a");
+    }
+
+    #[tokio::test]
+    async fn invalid_bundle_is_not_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, ca_path) = bundle_server("not a bundle".to_string(), &dir).await;
+        let catalog = Arc::new(inmemory::Catalog::new());
+        let manager = FederationManager::new(&[config(url, ca_path)], catalog.clone()).unwrap();
+
+        let error = manager
+            .refresh(&manager.trust_domains[0])
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::Parse(_)));
+        assert!(catalog.get_federated_bundles().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn large_bundle_is_not_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let (url, ca_path) = bundle_server(" ".repeat(MAX_BUNDLE_SIZE + 1), &dir).await;
+        let catalog = Arc::new(inmemory::Catalog::new());
+        let manager = FederationManager::new(&[config(url, ca_path)], catalog.clone()).unwrap();
+
+        let error = manager
+            .refresh(&manager.trust_domains[0])
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::BundleTooLarge(_)));
+    }
+
+    #[test]
+    fn http_bundle_endpoint_is_rejected() {
+        let catalog = Arc::new(inmemory::Catalog::new());
+        let mut config = config("http://127.0.0.1:8443/".to_string(), String::new());
+        config.bundle_endpoint_profile = BundleEndpointClientProfile::HttpsWeb;
+
+        let error = FederationManager::new(&[config], catalog).err().unwrap();
+        assert!(matches!(error, Error::Config(_)));
+    }
+}
@@ -15,11 +15,9 @@ pub mod error;
 use std::{collections::BTreeSet, sync::Arc};
 
 use catalog::Catalog;
-use core_objects::{AttestationConfig, RegistrationEntry};
+use core_objects::{spiffe_id, AttestationConfig, NodeSelector, RegistrationEntry};
 use error::Error;
 
-const PAGE_SIZE: usize = 100;
-
 pub struct IdentityMatcher {
     catalog: Arc<dyn Catalog>,
 }
@@ -37,31 +35,26 @@ impl IdentityMatcher {
     ) -> Result<Vec<RegistrationEntry>, Error> {
         let mut identities = Vec::new();
 
-        loop {
-            let (entries, token) = self
-                .catalog
-                .list_all(None, PAGE_SIZE)
-                .await
-                .map_err(Error::CatalogGetEntries)?;
+        // Only the entries whose selectors are all present in the workload selectors can match.
+        let entries = self
+            .catalog
+            .get_entries_by_selectors(workload_selectors)
+            .await
+            .map_err(Error::CatalogGetEntries)?;
 
-            // Go over all the entries. For each entry, we check if the workload that just came up is matching any of the entries we have.
-            // For each matching entry, we will extract the SPIFFE identity and match it with the workload.
-            for entry in entries {
-                // Check if the workload selectors are matching with the entry.
-                let result = self
-                    .match_entry(workload_selectors, &entry, parent_selectors)
-                    .await?;
-
-                // If we have a match add the ID to the list
-                if result {
-                    identities.push(entry);
-                }
-            }
+        // For each candidate entry, check the parent entry is matching the agent making the request on behalf of the workload.
+        for entry in entries {
+            let result = self
+                .match_entry(workload_selectors, &entry, parent_selectors)
+                .await?;
 
-            if token.is_none() {
-                return Ok(identities);
+            // If we have a match add the ID to the list
+            if result {
+                identities.push(entry);
             }
         }
+
+        Ok(identities)
     }
 
     async fn match_entry(
@@ -70,6 +63,12 @@ impl IdentityMatcher {
         entry: &RegistrationEntry,
         parent_selectors: &BTreeSet<String>,
     ) -> Result<bool, Error> {
+        // Entries saved before their SPIFFE ID was checked may have a path no SVID can be issued for.
+        if let Err(err) = spiffe_id::check_path(&entry.spiffe_id_path) {
+            log::error!("Entry {} has an invalid SPIFFE ID path: {}", entry.id, err);
+            return Ok(false);
+        }
+
         // Get the selectors for the entry and the parent entry. Those selectors will be checked againt the selectors of
         // the workload and the parent making the request on behalf of the workload.
         // To have a match, all the entry selectors need to be present in node/workload selector set.
@@ -81,10 +80,20 @@ impl IdentityMatcher {
                 .map_err(Error::CatalogGetEntries)?;
 
             if let AttestationConfig::Node(node_attestation) = &parent_entry.attestation_config {
-                Ok(
-                    match_selectors(&workload_attestation.value, workload_selectors)
-                        & match_selectors(&node_attestation.value, parent_selectors),
-                )
+                if !match_selectors(&workload_attestation.value, workload_selectors) {
+                    return Ok(false);
+                }
+                if !match_selectors(&node_attestation.value, parent_selectors) {
+                    log_parent_mismatch(
+                        entry,
+                        &parent_entry.id,
+                        &node_attestation.value,
+                        parent_selectors,
+                    );
+                    return Ok(false);
+                }
+
+                Ok(true)
             } else {
                 // This error is when a regular workload is parented to another workload.
                 // This error should be filtered out when entries are created, not here.
@@ -109,6 +118,36 @@ fn match_selectors(entry_selectors: &[String], selectors: &BTreeSet<String>) ->
     true
 }
 
+// The workload matched but the agent did not match the parent entry. Tells which node selectors the agent is
+// missing, so a wrong value can be told apart from a selector type the agent never reports.
+fn log_parent_mismatch(
+    entry: &RegistrationEntry,
+    parent_id: &str,
+    parent_entry_selectors: &[String],
+    parent_selectors: &BTreeSet<String>,
+) {
+    for expected_selector in parent_entry_selectors
+        .iter()
+        .filter(|expected_selector| !parent_selectors.contains(*expected_selector))
+    {
+        match expected_selector.parse::<NodeSelector>() {
+            Ok(selector) => log::debug!(
+                "Entry {} not matched, the agent has no {} selector {:?} of parent {}",
+                entry.id,
+                selector.selector_type,
+                selector.value,
+                parent_id
+            ),
+            Err(err) => log::warn!(
+                "Parent {} of entry {} has an invalid selector: {}",
+                parent_id,
+                entry.id,
+                err
+            ),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use catalog::{inmemory, Entries};
@@ -140,9 +179,6 @@ mod tests {
 
         // Add parent
         let parent = RegistrationEntry {
-            id: PARENT_NAME.to_string(),
-            other_identities: Vec::new(),
-            spiffe_id_path: PARENT_NAME.to_string(),
             attestation_config: AttestationConfig::Node(EntryNodeAttestation {
                 value: vec![
                     build_selector_string(&NodeSelectorType::Cluster, CLUSTER_NAME),
@@ -150,11 +186,7 @@ mod tests {
                 ],
                 plugin: NodeAttestationPlugin::Sat,
             }),
-            admin: false,
-            expires_at: 0,
-            dns_names: Vec::new(),
-            revision_number: 0,
-            store_svid: false,
+            ..RegistrationEntry::test_entry(PARENT_NAME)
         };
         catalog.batch_create(vec![parent.clone()]).await.unwrap();
 
@@ -289,6 +321,30 @@ mod tests {
         assert!(check_if_entry_id_in_response(entries, &group.id));
     }
 
+    #[tokio::test]
+    async fn invalid_spiffe_id_path_not_matched() {
+        let (identity_matcher, parent, entry1, _entry2, _group) = init_test().await;
+
+        let mut invalid = entry1.clone();
+        invalid.id = "invalid".to_string();
+        invalid.spiffe_id_path = "ns/../pod1".to_string();
+        identity_matcher
+            .catalog
+            .batch_create(vec![invalid])
+            .await
+            .unwrap();
+
+        let entries = identity_matcher
+            .get_entry_id_from_selectors(
+                &get_workload_selectors(&entry1),
+                &get_node_selectors(&parent),
+            )
+            .await
+            .unwrap();
+        assert_eq!(1, entries.len());
+        assert!(check_if_entry_id_in_response(entries, &entry1.id));
+    }
+
     #[tokio::test]
     async fn get_entry_id_from_selectors_error_match_test() {
         let (identity_matcher, parent, entry1, _entry2, _group) = init_test().await;
@@ -323,6 +379,34 @@ mod tests {
         assert_matches!(error, Error::CatalogGetEntries(_));
     }
 
+    #[tokio::test]
+    async fn get_entry_id_from_selectors_more_entries_than_page_size() {
+        let (identity_matcher, parent, entry1, _entry2, _group) = init_test().await;
+
+        // More matching entries than a catalog page, all of them must be returned.
+        let entries = (0..250)
+            .map(|i| {
+                let mut entry = entry1.clone();
+                entry.id = format!("{}-{}", POD_NAME1, i);
+                entry
+            })
+            .collect::<Vec<_>>();
+        identity_matcher
+            .catalog
+            .batch_create(entries)
+            .await
+            .unwrap();
+
+        let workload_selectors = get_workload_selectors(&entry1);
+        let parent_selectors = get_node_selectors(&parent);
+        let entries = identity_matcher
+            .get_entry_id_from_selectors(&workload_selectors, &parent_selectors)
+            .await
+            .unwrap();
+
+        assert_eq!(251, entries.len());
+    }
+
     #[tokio::test]
     async fn match_entry_happy_path() {
         let (identity_matcher, parent, entry1, _entry2, _group) = init_test().await;
@@ -9,6 +9,14 @@ pub enum Error {
     CreatingNewKey(#[from] Box<dyn std::error::Error>),
     #[error("Converting key to ec key {0}")]
     ECkeyConvertion(ErrorStack),
+    #[error("Converting key to rsa key {0}")]
+    RSAkeyConvertion(ErrorStack),
+    #[error("Converting key to okp key {0}")]
+    OKPkeyConvertion(ErrorStack),
+    #[error("Public key is not an Ed25519 key")]
+    NotEd25519Key,
+    #[error("Public key has no JWK thumbprint")]
+    NoThumbprint,
     #[error("Error creating big num object {0}")]
     BigNumGeneration(ErrorStack),
     #[error("Error while generating X and Y {0}")]
@@ -23,6 +31,20 @@ pub enum Error {
     GettingPulicKey(Box<dyn std::error::Error>),
     #[error("Error while adding public into the catalog {0}")]
     AddingPulicKey(Box<dyn std::error::Error>),
+    #[error("Error while getting the saved key slots from the catalog {0}")]
+    GettingKeySlots(Box<dyn std::error::Error>),
+    #[error("Error while saving the key slots into the catalog {0}")]
+    SavingKeySlots(Box<dyn std::error::Error>),
     #[error("Tried to rotate but there is not next jwt key to replace the current one")]
     NextJwtKeyMissing(),
+    #[error("Error while getting the trust bundle history from the catalog {0}")]
+    GettingTrustBundleHistory(Box<dyn std::error::Error>),
+    #[error("Version {0} of the trust bundle is not in its history")]
+    UnknownTrustBundleVersion(usize),
+    #[error("Version {0} of the trust bundle has the revoked key {1}")]
+    RevokedKeyInTrustBundle(usize, String),
+    #[error("Version {0} of the trust bundle does not have the current signing key {1}")]
+    CurrentKeyNotInTrustBundle(usize, String),
+    #[error("Error while rolling back the trust bundle {0}")]
+    RollingBackTrustBundle(Box<dyn std::error::Error>),
 }
@@ -11,34 +11,79 @@
 )]
 
 mod error;
+pub mod scheduler;
 
-use catalog::Catalog;
-use core_objects::{get_epoch_time, KeyType, KeyUse, JWK};
+use catalog::{Catalog, JWK_SET_HISTORY_SIZE};
+use core_objects::{
+    get_epoch_time, KeySlot, KeySlots, KeyType, KeyUse, Kty, ED25519_PUBLIC_KEY_DER_PREFIX, JWK,
+};
 use error::Error;
 use key_store::KeyStore;
 use log::info;
+use scheduler::RotationMetrics;
 use server_config::Config;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
-// This is a divisor, so a higher divisor results in smaller margin
-// This is the percentage of the lifetime of the current key left when the next key is created
-const PREPARE_NEXT_KEY_FOR_ROTATION_MARGIN: u64 = 2;
-// This is a divisor, so a higher divisor results in smaller margin
-// This is the percentage of the lifetime of the current key left when the next key replaces the current key
-const ROTATE_CURRENT_KEY_MARGIN: u64 = 6;
-
 #[derive(Clone)]
 pub struct JWTKeyEntry {
+    // Id of the key in the key store.
     pub id: String,
+    // Key id of the JWK and of the JWT-SVIDs the key signs, the RFC 7638 thumbprint of the public key.
+    pub kid: String,
     pub expiry: u64,
 }
 
+impl From<KeySlot> for JWTKeyEntry {
+    fn from(key_slot: KeySlot) -> Self {
+        // Keys saved before the thumbprints were published with their key store id.
+        let kid = if key_slot.kid.is_empty() {
+            key_slot.id.clone()
+        } else {
+            key_slot.kid
+        };
+
+        JWTKeyEntry {
+            id: key_slot.id,
+            kid,
+            expiry: key_slot.expiry,
+        }
+    }
+}
+
+impl From<JWTKeyEntry> for KeySlot {
+    fn from(jwt_key: JWTKeyEntry) -> Self {
+        KeySlot {
+            id: jwt_key.id,
+            kid: jwt_key.kid,
+            expiry: jwt_key.expiry,
+        }
+    }
+}
+
 pub struct Slots {
     previous_jwt_key: Option<JWTKeyEntry>,
     pub current_jwt_key: JWTKeyEntry,
     next_jwt_key: Option<JWTKeyEntry>,
+    // Each revocation publishes a new version of the trust bundle, so older revoked keys are out of its history.
+    revoked_kids: Vec<String>,
+}
+
+impl From<&Slots> for KeySlots {
+    fn from(slots: &Slots) -> Self {
+        KeySlots {
+            previous: slots.previous_jwt_key.clone().map(Into::into),
+            current: slots.current_jwt_key.clone().into(),
+            next: slots.next_jwt_key.clone().map(Into::into),
+            revoked: slots.revoked_kids.clone(),
+        }
+    }
+}
+
+pub struct Revocation {
+    pub revoked_key_id: String,
+    pub current_key_id: String,
 }
 
 pub struct KeyManager {
@@ -47,7 +92,11 @@ pub struct KeyManager {
     pub key_store: Arc<dyn KeyStore>,
     pub jwt_key_type: KeyType,
     pub jwt_key_ttl: u64,
+    // Time left on the current key when the next key is created, then when it replaces the current key.
+    prepare_rotation_margin: u64,
+    activate_rotation_margin: u64,
     pub slots: RwLock<Slots>,
+    pub rotation_metrics: RotationMetrics,
 }
 
 impl KeyManager {
@@ -60,8 +109,10 @@ impl KeyManager {
         let id = Uuid::new_v4().to_string();
         let expiry = current_time + config.jwt.key_ttl;
 
+        // The kid is known once the key is created.
         let jwt_key = JWTKeyEntry {
             id: id.clone(),
+            kid: String::new(),
             expiry,
         };
 
@@ -69,6 +120,7 @@ impl KeyManager {
             previous_jwt_key: None,
             current_jwt_key: jwt_key,
             next_jwt_key: None,
+            revoked_kids: Vec::new(),
         };
 
         let key_manager = KeyManager {
@@ -77,17 +129,118 @@ impl KeyManager {
             key_store,
             jwt_key_type: config.jwt.key_type,
             jwt_key_ttl: config.jwt.key_ttl,
+            prepare_rotation_margin: config.jwt.prepare_rotation_margin(),
+            activate_rotation_margin: config.jwt.activate_rotation_margin(),
             slots: RwLock::new(slots),
+            rotation_metrics: RotationMetrics::default(),
         };
 
-        key_manager.create_key_and_add_to_catalog(&id).await?;
+        let saved_slots = key_manager
+            .catalog
+            .get_key_slots(&key_manager.trust_domain)
+            .await
+            .map_err(|err| Error::GettingKeySlots(err))?;
+
+        let mut slots = key_manager.slots.write().await;
+        // Kept even when the saved keys cannot be restored, the trust bundle history may still have them.
+        if let Some(saved_slots) = &saved_slots {
+            slots.revoked_kids = saved_slots.revoked.clone();
+        }
+        match saved_slots {
+            Some(saved_slots) if key_manager.can_restore(&saved_slots, current_time).await => {
+                info!("Key manager: Restoring the keys of the previous run");
+                key_manager.restore(&mut slots, saved_slots).await;
+            }
+            saved_slots => {
+                // The saved keys cannot sign anymore, they are removed so they are not published forever.
+                if let Some(saved_slots) = saved_slots {
+                    let jwt_keys = saved_slots
+                        .previous
+                        .into_iter()
+                        .chain(Some(saved_slots.current))
+                        .chain(saved_slots.next)
+                        .map(JWTKeyEntry::from);
+                    for jwt_key in jwt_keys {
+                        key_manager.remove_saved_key(&jwt_key).await;
+                    }
+                }
+
+                slots.current_jwt_key.kid = key_manager.create_key_and_add_to_catalog(&id).await?;
+            }
+        }
+        key_manager.save_slots(&slots).await?;
+        drop(slots);
 
         Ok(key_manager)
     }
 
+    // The current key of the previous run is used again if it has not expired and the key store still has it.
+    async fn can_restore(&self, saved_slots: &KeySlots, current_time: u64) -> bool {
+        saved_slots.current.expiry > current_time && self.has_key(&saved_slots.current.id).await
+    }
+
+    async fn restore(&self, slots: &mut Slots, saved_slots: KeySlots) {
+        slots.current_jwt_key = saved_slots.current.into();
+        slots.previous_jwt_key = self.restore_slot(saved_slots.previous).await;
+        // Without its private key, the next key could never sign. The rotation creates another one.
+        slots.next_jwt_key = self.restore_slot(saved_slots.next).await;
+    }
+
+    async fn restore_slot(&self, key_slot: Option<KeySlot>) -> Option<JWTKeyEntry> {
+        let jwt_key: JWTKeyEntry = key_slot?.into();
+
+        if self.has_key(&jwt_key.id).await {
+            Some(jwt_key)
+        } else {
+            self.remove_saved_key(&jwt_key).await;
+            None
+        }
+    }
+
+    async fn has_key(&self, id: &str) -> bool {
+        self.key_store.get_public_key(id).await.is_ok()
+    }
+
+    // Either part of the key may already be gone, the other one is removed anyway.
+    async fn remove_saved_key(&self, jwt_key: &JWTKeyEntry) {
+        if let Err(err) = self.key_store.delete_key_pair(&jwt_key.id).await {
+            log::warn!(
+                "Key manager: Could not delete the saved private key {}: {}",
+                jwt_key.id,
+                err
+            );
+        }
+        if let Err(err) = self
+            .catalog
+            .remove_jwk(&self.trust_domain, &jwt_key.kid)
+            .await
+        {
+            log::warn!(
+                "Key manager: Could not remove the saved public key {}: {}",
+                jwt_key.kid,
+                err
+            );
+        }
+    }
+
+    async fn save_slots(&self, slots: &Slots) -> Result<(), Error> {
+        self.catalog
+            .set_key_slots(&self.trust_domain, slots.into())
+            .await
+            .map_err(|err| Error::SavingKeySlots(err))
+    }
+
     pub async fn rotate_periodic(&self) -> Result<(), Error> {
         let current_time = get_epoch_time();
-        self.rotate_periodic_inner(current_time).await
+        let result = self.rotate_periodic_inner(current_time).await;
+
+        if result.is_ok() {
+            self.rotation_metrics.success(current_time);
+        } else {
+            self.rotation_metrics.failure();
+        }
+
+        result
     }
 
     // Separated logic from rotate_periodic to be able to unit test it
@@ -98,24 +251,25 @@ impl KeyManager {
     // Then some more time later, when the previous key expire, it is destroyed.
     async fn rotate_periodic_inner(&self, current_time: u64) -> Result<(), Error> {
         let slots = &mut *self.slots.write().await;
+        let mut changed = false;
 
-        let threshold =
-            slots.current_jwt_key.expiry - self.jwt_key_ttl / PREPARE_NEXT_KEY_FOR_ROTATION_MARGIN;
+        let threshold = slots.current_jwt_key.expiry - self.prepare_rotation_margin;
 
         // Create new key in the next slot. The pulic part of the key is added to the catalog.
         if slots.next_jwt_key.is_none() && (current_time > threshold) {
             info!("Key manager: Filling next_key slot");
             let id = Uuid::new_v4().to_string();
+            let kid = self.create_key_and_add_to_catalog(&id).await?;
 
             slots.next_jwt_key = Some(JWTKeyEntry {
-                id: id.clone(),
+                id,
+                kid,
                 expiry: current_time + self.jwt_key_ttl,
             });
-
-            self.create_key_and_add_to_catalog(&id).await?;
+            changed = true;
         }
 
-        let threshold = slots.current_jwt_key.expiry - self.jwt_key_ttl / ROTATE_CURRENT_KEY_MARGIN;
+        let threshold = slots.current_jwt_key.expiry - self.activate_rotation_margin;
 
         if current_time > threshold {
             let jwt_key = slots
@@ -127,84 +281,215 @@ impl KeyManager {
             // This should never happen, the key should have expired a long time ago. But we clean up nonetheless and raise an error.
             if let Some(jwt_key) = &slots.previous_jwt_key {
                 log::error!("Request of key current slot deprecation while key in previous slot has not expired yet");
-                self.remove_jwk_from_catalog_and_store(&jwt_key.id).await?;
+                self.remove_jwk_from_catalog_and_store(jwt_key).await?;
             }
             info!("Key manager: Rotating keys");
             slots.previous_jwt_key = Some(slots.current_jwt_key.clone());
             slots.current_jwt_key = jwt_key;
             slots.next_jwt_key = None;
+            changed = true;
+            self.rotation_metrics.rotation(current_time);
         }
 
         // Remove old key when it expires
         if let Some(jwt_key) = &slots.previous_jwt_key {
             if current_time > jwt_key.expiry {
                 info!("Key manager: Removing old key");
-                self.remove_jwk_from_catalog_and_store(&jwt_key.id).await?;
+                self.remove_jwk_from_catalog_and_store(jwt_key).await?;
                 slots.previous_jwt_key = None;
+                changed = true;
             }
         }
 
+        // Saved after every change, so a restarted server signs with the keys it published.
+        if changed {
+            self.save_slots(slots).await?;
+        }
+
         Ok(())
     }
 
-    async fn remove_jwk_from_catalog_and_store(&self, id: &str) -> Result<(), Error> {
+    // Emergency rotation, when the current key may be compromised. A new key signs right away and the current
+    // key is removed from the trust bundle, so the JWT-SVIDs it signed are no longer valid. The next key is
+    // removed too, it would expire before the new key.
+    pub async fn revoke_current_key(&self) -> Result<Revocation, Error> {
+        let current_time = get_epoch_time();
+        self.revoke_current_key_inner(current_time).await
+    }
+
+    async fn revoke_current_key_inner(&self, current_time: u64) -> Result<Revocation, Error> {
+        let slots = &mut *self.slots.write().await;
+
+        let id = Uuid::new_v4().to_string();
+        let kid = self.create_key_and_add_to_catalog(&id).await?;
+
+        let revoked = std::mem::replace(
+            &mut slots.current_jwt_key,
+            JWTKeyEntry {
+                id,
+                kid: kid.clone(),
+                expiry: current_time + self.jwt_key_ttl,
+            },
+        );
+        let next = slots.next_jwt_key.take();
+        slots.revoked_kids.push(revoked.kid.clone());
+        if slots.revoked_kids.len() > JWK_SET_HISTORY_SIZE {
+            slots.revoked_kids.remove(0);
+        }
+        self.rotation_metrics.rotation(current_time);
+        // Saved first, a restarted server must never sign with the revoked key again.
+        self.save_slots(slots).await?;
+
+        log::warn!("Key manager: Revoking key {}", revoked.kid);
+        if let Some(next) = next {
+            self.remove_jwk_from_catalog_and_store(&next).await?;
+        }
+        self.remove_jwk_from_catalog_and_store(&revoked).await?;
+
+        Ok(Revocation {
+            revoked_key_id: revoked.kid,
+            current_key_id: kid,
+        })
+    }
+
+    // Re-publish a previous version of the trust bundle. It must have the current key, or the JWT-SVIDs signed
+    // from now on would not validate, and none of the revoked keys. Returns the new version.
+    pub async fn rollback_trust_bundle(&self, version: usize) -> Result<usize, Error> {
+        // Held so the keys do not change between the check and the rollback.
+        let slots = self.slots.read().await;
+
+        let keys = self
+            .catalog
+            .get_jwk_history(&self.trust_domain)
+            .await
+            .map_err(|err| Error::GettingTrustBundleHistory(err))?
+            .into_iter()
+            .find(|jwk_set| jwk_set.version == version)
+            .ok_or(Error::UnknownTrustBundleVersion(version))?
+            .keys;
+
+        if let Some(jwk) = keys
+            .iter()
+            .find(|jwk| slots.revoked_kids.contains(&jwk.kid))
+        {
+            return Err(Error::RevokedKeyInTrustBundle(version, jwk.kid.clone()));
+        }
+        if !keys.iter().any(|jwk| jwk.kid == slots.current_jwt_key.kid) {
+            return Err(Error::CurrentKeyNotInTrustBundle(
+                version,
+                slots.current_jwt_key.kid.clone(),
+            ));
+        }
+
+        self.catalog
+            .rollback_jwk(&self.trust_domain, version)
+            .await
+            .map_err(|err| Error::RollingBackTrustBundle(err))
+    }
+
+    async fn remove_jwk_from_catalog_and_store(&self, jwt_key: &JWTKeyEntry) -> Result<(), Error> {
         // Delete the old private key
         self.key_store
-            .delete_key_pair(id)
+            .delete_key_pair(&jwt_key.id)
             .await
             .map_err(|err| Error::DeletingPrivateKey(err))?;
 
         // Remove from catalog
         self.catalog
-            .remove_jwk(&self.trust_domain, id)
+            .remove_jwk(&self.trust_domain, &jwt_key.kid)
             .await
             .map_err(|err| Error::DeletingPublicKey(err))
     }
 
-    async fn create_key_and_add_to_catalog(&self, id: &str) -> Result<(), Error> {
-        let mut x = openssl::bn::BigNum::new().map_err(Error::BigNumGeneration)?;
-
-        let mut y = openssl::bn::BigNum::new().map_err(Error::BigNumGeneration)?;
-        let mut ctx = openssl::bn::BigNumContext::new().map_err(Error::BigNumGeneration)?;
-        let ec_key = self
+    // The kid of the JWK is the thumbprint of the public key, so verifiers can pin the key and a key is never
+    // published under the kid of another one. Returns the kid.
+    async fn create_key_and_add_to_catalog(&self, id: &str) -> Result<String, Error> {
+        let public_key = self
             .key_store
             .create_key_pair_if_not_exists(id, self.jwt_key_type)
             .await
-            .map_err(|err| Error::CreatingNewKey(err))?
-            .ec_key()
-            .map_err(Error::ECkeyConvertion)?;
-
-        let group = ec_key.group();
-        ec_key
-            .public_key()
-            .affine_coordinates_gfp(group, &mut x, &mut y, &mut ctx)
-            .map_err(Error::GenerateXandY)?;
-
-        let x_b64 = base64::encode_config(x.to_vec(), base64::STANDARD_NO_PAD);
-        let y_b64 = base64::encode_config(y.to_vec(), base64::STANDARD_NO_PAD);
+            .map_err(|err| Error::CreatingNewKey(err))?;
         let (kty, crv) = self.jwt_key_type.into();
 
+        let jwk = match kty {
+            Kty::RSA => {
+                let rsa = public_key.rsa().map_err(Error::RSAkeyConvertion)?;
+
+                JWK {
+                    x: String::new(),
+                    y: String::new(),
+                    kty,
+                    crv,
+                    n: base64::encode_config(rsa.n().to_vec(), base64::URL_SAFE_NO_PAD),
+                    e: base64::encode_config(rsa.e().to_vec(), base64::URL_SAFE_NO_PAD),
+                    kid: String::new(),
+                    key_use: KeyUse::JWTSVID,
+                }
+            }
+            Kty::OKP => {
+                let public_key_der = public_key
+                    .public_key_to_der()
+                    .map_err(Error::OKPkeyConvertion)?;
+                let x = public_key_der
+                    .strip_prefix(&ED25519_PUBLIC_KEY_DER_PREFIX[..])
+                    .ok_or(Error::NotEd25519Key)?;
+
+                JWK {
+                    x: base64::encode_config(x, base64::URL_SAFE_NO_PAD),
+                    y: String::new(),
+                    kty,
+                    crv,
+                    n: String::new(),
+                    e: String::new(),
+                    kid: String::new(),
+                    key_use: KeyUse::JWTSVID,
+                }
+            }
+            _ => {
+                let mut x = openssl::bn::BigNum::new().map_err(Error::BigNumGeneration)?;
+
+                let mut y = openssl::bn::BigNum::new().map_err(Error::BigNumGeneration)?;
+                let mut ctx = openssl::bn::BigNumContext::new().map_err(Error::BigNumGeneration)?;
+                let ec_key = public_key.ec_key().map_err(Error::ECkeyConvertion)?;
+
+                let group = ec_key.group();
+                ec_key
+                    .public_key()
+                    .affine_coordinates_gfp(group, &mut x, &mut y, &mut ctx)
+                    .map_err(Error::GenerateXandY)?;
+
+                JWK {
+                    x: base64::encode_config(x.to_vec(), base64::URL_SAFE_NO_PAD),
+                    y: base64::encode_config(y.to_vec(), base64::URL_SAFE_NO_PAD),
+                    kty,
+                    crv,
+                    n: String::new(),
+                    e: String::new(),
+                    kid: String::new(),
+                    key_use: KeyUse::JWTSVID,
+                }
+            }
+        };
+        let kid = jwk.thumbprint().ok_or(Error::NoThumbprint)?;
         let jwk = JWK {
-            x: x_b64,
-            y: y_b64,
-            kty,
-            crv,
-            kid: id.to_string(),
-            key_use: KeyUse::JWTSVID,
+            kid: kid.clone(),
+            ..jwk
         };
 
         self.catalog
             .add_jwk(&self.trust_domain, jwk)
             .await
-            .map_err(|err| Error::AddingPulicKey(err))
+            .map_err(|err| Error::AddingPulicKey(err))?;
+
+        Ok(kid)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::KeyManager;
+    use crate::{Error, JWTKeyEntry, KeyManager};
     use catalog::{inmemory, Catalog};
-    use core_objects::CONFIG_DEFAULT_PATH;
+    use core_objects::{KeySlot, CONFIG_DEFAULT_PATH};
     use key_store::{disk, KeyStore};
     use server_config::{Config, KeyStoreConfig, KeyStoreConfigDisk};
     use std::sync::Arc;
@@ -212,7 +497,10 @@ mod tests {
     async fn init(dir: &tempfile::TempDir) -> KeyManager {
         let mut config = Config::load_config(CONFIG_DEFAULT_PATH).unwrap();
         let key_base_path = dir.path().to_str().unwrap().to_string();
-        let key_plugin = KeyStoreConfigDisk { key_base_path };
+        let key_plugin = KeyStoreConfigDisk {
+            key_base_path,
+            encryption: None,
+        };
 
         // Change key disk plugin path to write in tempdir
         config.key_store = KeyStoreConfig::Disk(key_plugin.clone());
@@ -220,25 +508,99 @@ mod tests {
         config.jwt.key_ttl = 300;
 
         let catalog = Arc::new(inmemory::Catalog::new());
-        let key_store = Arc::new(disk::KeyStore::new(&key_plugin));
+        let key_store = Arc::new(disk::KeyStore::new(&key_plugin).unwrap());
 
         KeyManager::new(&config, catalog.clone(), key_store.clone(), 0)
             .await
             .unwrap()
     }
 
+    async fn restart(manager: &KeyManager, current_time: u64) -> KeyManager {
+        let mut config = Config::load_config(CONFIG_DEFAULT_PATH).unwrap();
+        config.jwt.key_ttl = manager.jwt_key_ttl;
+
+        KeyManager::new(
+            &config,
+            manager.catalog.clone(),
+            manager.key_store.clone(),
+            current_time,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn restart_test_restores_keys() {
+        let tmp = tempfile::tempdir().unwrap();
+        let manager = init(&tmp).await;
+        manager
+            .rotate_periodic_inner(manager.jwt_key_ttl / 2 + 1)
+            .await
+            .unwrap();
+
+        let restarted = restart(&manager, manager.jwt_key_ttl / 2 + 2).await;
+
+        let slots = manager.slots.read().await;
+        let restarted_slots = restarted.slots.read().await;
+        assert_eq!(restarted_slots.current_jwt_key.id, slots.current_jwt_key.id);
+        assert_eq!(
+            restarted_slots.current_jwt_key.kid,
+            slots.current_jwt_key.kid
+        );
+        assert_eq!(
+            restarted_slots.next_jwt_key.as_ref().unwrap().id,
+            slots.next_jwt_key.as_ref().unwrap().id
+        );
+
+        // No key was added to the trust bundle
+        let (res, _version) = restarted.catalog.get_jwk("dummy").await.unwrap();
+        assert_eq!(res.len(), 2);
+    }
+
+    #[test]
+    fn saved_slot_without_kid() {
+        let jwt_key: JWTKeyEntry = KeySlot {
+            id: "id".to_string(),
+            kid: String::new(),
+            expiry: 10,
+        }
+        .into();
+        assert_eq!(jwt_key.kid, "id");
+    }
+
+    #[tokio::test]
+    async fn restart_test_expired_keys() {
+        let tmp = tempfile::tempdir().unwrap();
+        let manager = init(&tmp).await;
+        let old_jwt_key_id = manager.slots.read().await.current_jwt_key.id.clone();
+
+        let restarted = restart(&manager, manager.jwt_key_ttl + 1).await;
+
+        // The expired key was replaced and removed from the catalog
+        let current_jwt_key = restarted.slots.read().await.current_jwt_key.clone();
+        assert_ne!(current_jwt_key.id, old_jwt_key_id);
+        let (res, _version) = restarted.catalog.get_jwk("dummy").await.unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].kid, current_jwt_key.kid);
+
+        let key_slots = restarted.catalog.get_key_slots("dummy").await.unwrap();
+        assert_eq!(key_slots.unwrap().current.id, current_jwt_key.id);
+    }
+
     #[tokio::test]
     async fn initialize_test_happy_path() {
         let tmp = tempfile::tempdir().unwrap();
         let manager = init(&tmp).await;
 
-        // Check the public key has been uploaded
+        // Check the public key has been uploaded, under its thumbprint
         let (res, version) = manager.catalog.get_jwk("dummy").await.unwrap();
         assert_eq!(res.len(), 1);
         assert_eq!(version, 1);
+        let current_jwt_key = &manager.slots.write().await.current_jwt_key;
+        assert_eq!(res[0].kid, current_jwt_key.kid);
+        assert_eq!(res[0].thumbprint().unwrap(), current_jwt_key.kid);
 
         // Check private key is in the store
-        let current_jwt_key = &manager.slots.write().await.current_jwt_key;
         let _key = manager
             .key_store
             .get_public_key(&current_jwt_key.id)
@@ -246,6 +608,93 @@ mod tests {
             .unwrap();
     }
 
+    #[tokio::test]
+    async fn revoke_current_key_test_happy_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let manager = init(&tmp).await;
+        manager
+            .rotate_periodic_inner(manager.jwt_key_ttl / 2 + 1)
+            .await
+            .unwrap();
+        let revoked_key = manager.slots.read().await.current_jwt_key.clone();
+
+        let revocation = manager
+            .revoke_current_key_inner(manager.jwt_key_ttl / 2 + 2)
+            .await
+            .unwrap();
+        assert_eq!(revocation.revoked_key_id, revoked_key.kid);
+
+        let slots = manager.slots.read().await;
+        assert_eq!(slots.current_jwt_key.kid, revocation.current_key_id);
+        assert_eq!(
+            slots.current_jwt_key.expiry,
+            manager.jwt_key_ttl * 3 / 2 + 2
+        );
+        assert!(slots.next_jwt_key.is_none());
+
+        // Only the new key is published, the revoked and next keys are gone
+        let (res, _version) = manager.catalog.get_jwk("dummy").await.unwrap();
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].kid, revocation.current_key_id);
+        assert!(manager
+            .key_store
+            .get_public_key(&revoked_key.id)
+            .await
+            .is_err());
+
+        let key_slots = manager.catalog.get_key_slots("dummy").await.unwrap();
+        assert_eq!(key_slots.unwrap().current.kid, revocation.current_key_id);
+    }
+
+    #[tokio::test]
+    async fn rollback_trust_bundle_test_after_revocation() {
+        let tmp = tempfile::tempdir().unwrap();
+        let manager = init(&tmp).await;
+        // Version 1 has the first key, version 2 the next key too.
+        manager
+            .rotate_periodic_inner(manager.jwt_key_ttl / 2 + 1)
+            .await
+            .unwrap();
+
+        // Versions 3 to 5 add the new key, then remove the next and the revoked keys.
+        let revocation = manager
+            .revoke_current_key_inner(manager.jwt_key_ttl / 2 + 2)
+            .await
+            .unwrap();
+
+        let error = manager.rollback_trust_bundle(2).await.unwrap_err();
+        assert!(
+            matches!(error, Error::RevokedKeyInTrustBundle(2, kid) if kid == revocation.revoked_key_id)
+        );
+        let error = manager.rollback_trust_bundle(4).await.unwrap_err();
+        assert!(matches!(error, Error::RevokedKeyInTrustBundle(4, _)));
+        assert_eq!(manager.rollback_trust_bundle(5).await.unwrap(), 6);
+
+        // The revoked keys are still known after a restart.
+        let restarted = restart(&manager, manager.jwt_key_ttl / 2 + 3).await;
+        let error = restarted.rollback_trust_bundle(2).await.unwrap_err();
+        assert!(matches!(error, Error::RevokedKeyInTrustBundle(2, _)));
+    }
+
+    #[tokio::test]
+    async fn rollback_trust_bundle_test_without_current_key() {
+        let tmp = tempfile::tempdir().unwrap();
+        let manager = init(&tmp).await;
+        let current_jwt_key = manager.slots.read().await.current_jwt_key.clone();
+        manager
+            .catalog
+            .remove_jwk("dummy", &current_jwt_key.kid)
+            .await
+            .unwrap();
+
+        let error = manager.rollback_trust_bundle(2).await.unwrap_err();
+        assert!(
+            matches!(error, Error::CurrentKeyNotInTrustBundle(2, kid) if kid == current_jwt_key.kid)
+        );
+        let error = manager.rollback_trust_bundle(5).await.unwrap_err();
+        assert!(matches!(error, Error::UnknownTrustBundleVersion(5)));
+    }
+
     #[tokio::test]
     async fn remove_jwk_from_catalog_and_store_test_happy_path() {
         let tmp = tempfile::tempdir().unwrap();
@@ -253,7 +702,7 @@ mod tests {
 
         let current_jwt_key = &manager.slots.write().await.current_jwt_key;
         manager
-            .remove_jwk_from_catalog_and_store(&current_jwt_key.id)
+            .remove_jwk_from_catalog_and_store(current_jwt_key)
             .await
             .unwrap();
 
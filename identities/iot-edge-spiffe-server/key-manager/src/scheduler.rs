@@ -0,0 +1,143 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Schedule of the rotation checks of the key manager. A random jitter spreads the checks of the replicas
+// started together, and failed checks are retried with an exponential backoff so an unavailable catalog or
+// key store is not hammered.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+pub const ROTATION_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+// Added to every interval, up to this much.
+pub const ROTATION_CHECK_JITTER: Duration = Duration::from_secs(5);
+pub const MAX_ROTATION_BACKOFF: Duration = Duration::from_secs(300);
+
+#[derive(Default, Debug)]
+pub struct RotationMetrics {
+    checks: AtomicU64,
+    failures: AtomicU64,
+    consecutive_failures: AtomicU64,
+    rotations: AtomicU64,
+    last_success_at: AtomicU64,
+    last_rotation_at: AtomicU64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RotationMetricsSnapshot {
+    pub checks: u64,
+    pub failures: u64,
+    pub consecutive_failures: u64,
+    // Number of times the next key replaced the current key.
+    pub rotations: u64,
+    // Seconds since epoch, 0 until the first one.
+    pub last_success_at: u64,
+    pub last_rotation_at: u64,
+}
+
+impl RotationMetrics {
+    pub(crate) fn success(&self, now: u64) {
+        self.checks.fetch_add(1, Ordering::Relaxed);
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        self.last_success_at.store(now, Ordering::Relaxed);
+    }
+
+    pub(crate) fn failure(&self) {
+        self.checks.fetch_add(1, Ordering::Relaxed);
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        self.consecutive_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn rotation(&self, now: u64) {
+        self.rotations.fetch_add(1, Ordering::Relaxed);
+        self.last_rotation_at.store(now, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn snapshot(&self) -> RotationMetricsSnapshot {
+        RotationMetricsSnapshot {
+            checks: self.checks.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            consecutive_failures: self.consecutive_failures.load(Ordering::Relaxed),
+            rotations: self.rotations.load(Ordering::Relaxed),
+            last_success_at: self.last_success_at.load(Ordering::Relaxed),
+            last_rotation_at: self.last_rotation_at.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// Time to wait before the next check. The interval doubles with every consecutive failure, up to
+// MAX_ROTATION_BACKOFF. `jitter` is the fraction of ROTATION_CHECK_JITTER added, between 0 and 1.
+#[must_use]
+pub fn next_delay(consecutive_failures: u64, jitter: f64) -> Duration {
+    let exponent = u32::try_from(consecutive_failures.min(16)).unwrap_or(16);
+    let backoff = ROTATION_CHECK_INTERVAL
+        .checked_mul(1 << exponent)
+        .map_or(MAX_ROTATION_BACKOFF, |backoff| {
+            backoff.min(MAX_ROTATION_BACKOFF)
+        });
+
+    let jitter = if jitter > 0.0 { jitter.min(1.0) } else { 0.0 };
+
+    backoff + ROTATION_CHECK_JITTER.mul_f64(jitter)
+}
+
+// Random fraction of the jitter. Without randomness, the checks still run but are not spread.
+#[must_use]
+pub fn random_jitter() -> f64 {
+    let mut bytes = [0; 4];
+    if openssl::rand::rand_bytes(&mut bytes).is_err() {
+        return 0.0;
+    }
+
+    f64::from(u32::from_le_bytes(bytes)) / f64::from(u32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_delay_backs_off() {
+        assert_eq!(next_delay(0, 0.0), ROTATION_CHECK_INTERVAL);
+        assert_eq!(next_delay(1, 0.0), ROTATION_CHECK_INTERVAL * 2);
+        assert_eq!(next_delay(3, 0.0), ROTATION_CHECK_INTERVAL * 8);
+        assert_eq!(next_delay(10, 0.0), MAX_ROTATION_BACKOFF);
+        assert_eq!(next_delay(u64::MAX, 0.0), MAX_ROTATION_BACKOFF);
+    }
+
+    #[test]
+    fn next_delay_adds_jitter() {
+        assert_eq!(
+            next_delay(0, 1.0),
+            ROTATION_CHECK_INTERVAL + ROTATION_CHECK_JITTER
+        );
+        assert_eq!(
+            next_delay(0, 2.0),
+            ROTATION_CHECK_INTERVAL + ROTATION_CHECK_JITTER
+        );
+
+        let jitter = random_jitter();
+        assert!((0.0..=1.0).contains(&jitter));
+    }
+
+    #[test]
+    fn metrics_track_failures() {
+        let metrics = RotationMetrics::default();
+
+        metrics.failure();
+        metrics.failure();
+        assert_eq!(metrics.snapshot().consecutive_failures, 2);
+
+        metrics.success(10);
+        metrics.rotation(10);
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.checks, 3);
+        assert_eq!(snapshot.failures, 2);
+        assert_eq!(snapshot.consecutive_failures, 0);
+        assert_eq!(snapshot.rotations, 1);
+        assert_eq!(snapshot.last_success_at, 10);
+        assert_eq!(snapshot.last_rotation_at, 10);
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// OpenSSL operations (key generation, signing) are CPU bound and can take several milliseconds for big keys.
+// Running them directly on the async runtime blocks the reactor, and with it the workload and agent APIs.
+// They are offloaded to tokio blocking threads. A semaphore bounds how many of those threads the key store
+// can use at the same time so a signing burst cannot starve the rest of the blocking pool (file system, etc...).
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+use tokio::sync::{AcquireError, Semaphore};
+
+pub const MAX_CONCURRENT_CRYPTO_OPERATIONS: usize = 4;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Crypto pool is closed {0}")]
+    PoolClosed(AcquireError),
+    #[error("Crypto task panicked or was cancelled {0}")]
+    TaskFailed(tokio::task::JoinError),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Operation {
+    Sign,
+    KeyGeneration,
+}
+
+#[derive(Default, Debug)]
+struct LatencyMetrics {
+    count: AtomicU64,
+    total_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+impl LatencyMetrics {
+    fn record(&self, latency: Duration) {
+        let latency_us = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_us.fetch_add(latency_us, Ordering::Relaxed);
+        self.max_us.fetch_max(latency_us, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> LatencySnapshot {
+        LatencySnapshot {
+            count: self.count.load(Ordering::Relaxed),
+            total_us: self.total_us.load(Ordering::Relaxed),
+            max_us: self.max_us.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Latency of one type of operation, including the time spent waiting for a slot in the pool.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LatencySnapshot {
+    pub count: u64,
+    pub total_us: u64,
+    pub max_us: u64,
+}
+
+impl LatencySnapshot {
+    #[must_use]
+    pub fn average_us(&self) -> u64 {
+        if self.count == 0 {
+            0
+        } else {
+            self.total_us / self.count
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CryptoMetricsSnapshot {
+    pub sign: LatencySnapshot,
+    pub key_generation: LatencySnapshot,
+}
+
+pub struct CryptoPool {
+    permits: Semaphore,
+    sign: LatencyMetrics,
+    key_generation: LatencyMetrics,
+}
+
+impl CryptoPool {
+    #[must_use]
+    pub fn new(max_concurrent_operations: usize) -> Self {
+        CryptoPool {
+            permits: Semaphore::new(max_concurrent_operations),
+            sign: LatencyMetrics::default(),
+            key_generation: LatencyMetrics::default(),
+        }
+    }
+
+    pub async fn run<F, T>(&self, operation: Operation, f: F) -> Result<T, Error>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let start = Instant::now();
+
+        let _permit = self.permits.acquire().await.map_err(Error::PoolClosed)?;
+        let result = tokio::task::spawn_blocking(f)
+            .await
+            .map_err(Error::TaskFailed)?;
+
+        let metrics = match operation {
+            Operation::Sign => &self.sign,
+            Operation::KeyGeneration => &self.key_generation,
+        };
+        metrics.record(start.elapsed());
+
+        Ok(result)
+    }
+
+    #[must_use]
+    pub fn metrics(&self) -> CryptoMetricsSnapshot {
+        CryptoMetricsSnapshot {
+            sign: self.sign.snapshot(),
+            key_generation: self.key_generation.snapshot(),
+        }
+    }
+}
+
+impl Default for CryptoPool {
+    fn default() -> Self {
+        Self::new(MAX_CONCURRENT_CRYPTO_OPERATIONS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn run_records_latency_per_operation() {
+        let pool = CryptoPool::new(1);
+
+        let result = pool.run(Operation::Sign, || 40 + 2).await.unwrap();
+        assert_eq!(result, 42);
+        pool.run(Operation::Sign, || ()).await.unwrap();
+        pool.run(Operation::KeyGeneration, || ()).await.unwrap();
+
+        let metrics = pool.metrics();
+        assert_eq!(metrics.sign.count, 2);
+        assert_eq!(metrics.key_generation.count, 1);
+        assert!(metrics.sign.max_us >= metrics.sign.average_us());
+    }
+}
@@ -27,6 +27,16 @@ pub enum Error {
     UnsupportedMechanismType(),
     #[error("Unimplemented KeyType {0:?}")]
     UnimplementedKeyType(KeyType),
+    #[error("Could not run crypto operation {0}")]
+    CryptoPool(crate::blocking::Error),
+    #[error("Could not read the passphrase file {0}: {1}")]
+    PassphraseFile(String, io::Error),
+    #[error("The passphrase file {0} is empty")]
+    EmptyPassphrase(String),
+    #[error("Key {0} is encrypted but no passphrase is configured")]
+    KeyEncrypted(String),
+    #[error("Could not decrypt key {0} with the configured passphrases: {1}")]
+    Decrypt(String, openssl::error::ErrorStack),
 }
 
 impl From<openssl::error::Error> for Error {
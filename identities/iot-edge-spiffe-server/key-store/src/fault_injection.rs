@@ -0,0 +1,84 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Key store wrapper injecting the faults set through the admin API, e.g. to test key rotation when
+// signing fails. Only built with the `chaos` feature.
+
+use std::sync::Arc;
+
+use ::chaos::Faults;
+use core_objects::KeyType;
+use openssl::pkey::{PKey, Public};
+
+use crate::{blocking::CryptoMetricsSnapshot, KeyStore as KeyStoreTrait};
+
+pub struct KeyStore {
+    key_store: Arc<dyn KeyStoreTrait>,
+    faults: Arc<Faults>,
+}
+
+impl KeyStore {
+    #[must_use]
+    pub fn new(key_store: Arc<dyn KeyStoreTrait>, faults: Arc<Faults>) -> Self {
+        KeyStore { key_store, faults }
+    }
+
+    async fn inject(&self) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.faults.inject().await.map_err(|err| Box::new(err) as _)
+    }
+}
+
+#[async_trait::async_trait]
+impl KeyStoreTrait for KeyStore {
+    async fn create_key_pair_if_not_exists(
+        &self,
+        id: &str,
+        key_type: KeyType,
+    ) -> Result<PKey<Public>, Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.key_store
+            .create_key_pair_if_not_exists(id, key_type)
+            .await
+    }
+
+    async fn sign(
+        &self,
+        id: &str,
+        key_type: KeyType,
+        digest: &[u8],
+    ) -> Result<(usize, Vec<u8>), Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.key_store.sign(id, key_type, digest).await
+    }
+
+    async fn sign_batch(
+        &self,
+        id: &str,
+        key_type: KeyType,
+        digests: &[Vec<u8>],
+    ) -> Result<Vec<(usize, Vec<u8>)>, Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.key_store.sign_batch(id, key_type, digests).await
+    }
+
+    async fn delete_key_pair(&self, id: &str) -> Result<(), Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.key_store.delete_key_pair(id).await
+    }
+
+    async fn get_public_key(
+        &self,
+        id: &str,
+    ) -> Result<PKey<Public>, Box<dyn std::error::Error + Send>> {
+        self.inject().await?;
+
+        self.key_store.get_public_key(id).await
+    }
+
+    fn crypto_metrics(&self) -> CryptoMetricsSnapshot {
+        self.key_store.crypto_metrics()
+    }
+}
@@ -0,0 +1,18 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Routes every OpenSSL operation of the process through the FIPS provider of OpenSSL 3. Loading
+// fails if the provider is not installed, or if its self tests fail.
+
+use openssl::{error::ErrorStack, provider::Provider};
+
+pub(crate) fn enable() -> Result<(), ErrorStack> {
+    let provider = Provider::load(None, "fips")?;
+    // The provider must stay loaded for the lifetime of the process.
+    std::mem::forget(provider);
+
+    if unsafe { openssl_sys::EVP_default_properties_enable_fips(std::ptr::null_mut(), 1) } != 1 {
+        return Err(ErrorStack::get());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,13 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Key {0} does not exist")]
+    KeyNotFound(String),
+    #[error("Openssl Error: {0}")]
+    OpenSSL(openssl::error::ErrorStack),
+    #[error("Could not run crypto operation {0}")]
+    CryptoPool(crate::blocking::Error),
+}
@@ -0,0 +1,240 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Keys are only kept in memory and are lost when the server restarts. Meant for tests and ephemeral
+// deployments, where the trust bundle is rebuilt on every start anyway.
+
+use std::collections::HashMap;
+
+use core_objects::KeyType;
+use openssl::pkey::{PKey, Private, Public};
+use parking_lot::{const_rwlock, RwLock};
+
+pub mod error;
+
+use error::Error;
+
+use crate::{
+    blocking::{CryptoMetricsSnapshot, CryptoPool, Operation},
+    disk::{generate_private_key, sign_batch_inner, sign_inner},
+    KeyStore as KeyPluginTrait,
+};
+
+struct KeyPair {
+    public_key: PKey<Public>,
+    private_key: PKey<Private>,
+}
+
+pub struct KeyStore {
+    keys: RwLock<HashMap<String, KeyPair>>,
+    crypto_pool: CryptoPool,
+}
+
+impl KeyStore {
+    #[must_use]
+    pub fn new() -> Self {
+        KeyStore {
+            keys: const_rwlock(HashMap::new()),
+            crypto_pool: CryptoPool::default(),
+        }
+    }
+
+    fn get_key_pair<T>(
+        &self,
+        id: &str,
+        f: impl FnOnce(&KeyPair) -> T,
+    ) -> Result<T, Box<dyn std::error::Error + Send>> {
+        let keys = self.keys.read();
+
+        keys.get(id)
+            .map(f)
+            .ok_or_else(|| Box::new(Error::KeyNotFound(id.to_string())) as _)
+    }
+}
+
+impl Default for KeyStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl KeyPluginTrait for KeyStore {
+    async fn create_key_pair_if_not_exists(
+        &self,
+        id: &str,
+        key_type: KeyType,
+    ) -> Result<PKey<Public>, Box<dyn std::error::Error + Send>> {
+        if let Ok(public_key) = self.get_key_pair(id, |key_pair| key_pair.public_key.clone()) {
+            return Ok(public_key);
+        }
+
+        let private_key = self
+            .crypto_pool
+            .run(Operation::KeyGeneration, move || {
+                generate_private_key(key_type)
+            })
+            .await
+            .map_err(|err| {
+                Box::new(Error::CryptoPool(err)) as Box<dyn std::error::Error + Send>
+            })??;
+
+        // Copy private_key's public parameters into a new public key
+        let public_key_der = private_key
+            .public_key_to_der()
+            .map_err(|err| Box::new(Error::OpenSSL(err)) as _)?;
+        let public_key = PKey::public_key_from_der(&public_key_der)
+            .map_err(|err| Box::new(Error::OpenSSL(err)) as _)?;
+
+        // Another caller may have created the key while this one was generated, keep the first one.
+        let mut keys = self.keys.write();
+        let key_pair = keys.entry(id.to_string()).or_insert(KeyPair {
+            public_key,
+            private_key,
+        });
+
+        Ok(key_pair.public_key.clone())
+    }
+
+    async fn sign(
+        &self,
+        id: &str,
+        key_type: KeyType,
+        digest: &[u8],
+    ) -> Result<(usize, Vec<u8>), Box<dyn std::error::Error + Send>> {
+        let private_key = self.get_key_pair(id, |key_pair| key_pair.private_key.clone())?;
+        let digest = digest.to_vec();
+
+        self.crypto_pool
+            .run(Operation::Sign, move || {
+                sign_inner(&private_key, key_type, &digest)
+            })
+            .await
+            .map_err(|err| Box::new(Error::CryptoPool(err)) as Box<dyn std::error::Error + Send>)?
+    }
+
+    async fn sign_batch(
+        &self,
+        id: &str,
+        key_type: KeyType,
+        digests: &[Vec<u8>],
+    ) -> Result<Vec<(usize, Vec<u8>)>, Box<dyn std::error::Error + Send>> {
+        let private_key = self.get_key_pair(id, |key_pair| key_pair.private_key.clone())?;
+        let digests = digests.to_vec();
+
+        self.crypto_pool
+            .run(Operation::Sign, move || {
+                sign_batch_inner(&private_key, key_type, &digests)
+            })
+            .await
+            .map_err(|err| Box::new(Error::CryptoPool(err)) as Box<dyn std::error::Error + Send>)?
+    }
+
+    async fn get_public_key(
+        &self,
+        id: &str,
+    ) -> Result<PKey<Public>, Box<dyn std::error::Error + Send>> {
+        self.get_key_pair(id, |key_pair| key_pair.public_key.clone())
+    }
+
+    async fn delete_key_pair(&self, id: &str) -> Result<(), Box<dyn std::error::Error + Send>> {
+        let mut keys = self.keys.write();
+
+        keys.remove(id)
+            .map(|_key_pair| ())
+            .ok_or_else(|| Box::new(Error::KeyNotFound(id.to_string())) as _)
+    }
+
+    fn crypto_metrics(&self) -> CryptoMetricsSnapshot {
+        self.crypto_pool.metrics()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matches::assert_matches;
+
+    #[tokio::test]
+    async fn create_key_pair_happy_path() {
+        let key_store = KeyStore::new();
+
+        let public_key = key_store
+            .create_key_pair_if_not_exists("id", KeyType::ES256)
+            .await
+            .unwrap();
+
+        // The existing key is returned instead of being overwritten
+        let public_key2 = key_store
+            .create_key_pair_if_not_exists("id", KeyType::ES256)
+            .await
+            .unwrap();
+        assert!(public_key.public_eq(&public_key2));
+
+        let public_key3 = key_store.get_public_key("id").await.unwrap();
+        assert!(public_key.public_eq(&public_key3));
+        assert_eq!(key_store.crypto_metrics().key_generation.count, 1);
+    }
+
+    #[tokio::test]
+    async fn delete_key_pair_happy_path() {
+        let key_store = KeyStore::new();
+
+        key_store
+            .create_key_pair_if_not_exists("id", KeyType::ES256)
+            .await
+            .unwrap();
+        key_store.delete_key_pair("id").await.unwrap();
+
+        let error = *key_store
+            .get_public_key("id")
+            .await
+            .unwrap_err()
+            .downcast::<Error>()
+            .unwrap();
+        assert_matches!(error, Error::KeyNotFound(_));
+    }
+
+    #[tokio::test]
+    async fn delete_key_pair_error_path() {
+        let key_store = KeyStore::new();
+
+        let error = *key_store
+            .delete_key_pair("id")
+            .await
+            .unwrap_err()
+            .downcast::<Error>()
+            .unwrap();
+        assert_matches!(error, Error::KeyNotFound(_));
+    }
+
+    #[tokio::test]
+    async fn sign_happy_path() {
+        let key_store = KeyStore::new();
+
+        let public_key = key_store
+            .create_key_pair_if_not_exists("id", KeyType::ES256)
+            .await
+            .unwrap();
+
+        let digest = openssl::sha::sha256("hello world".as_bytes());
+        let (_signature_len, signature) =
+            key_store.sign("id", KeyType::ES256, &digest).await.unwrap();
+
+        let signature = openssl::ecdsa::EcdsaSig::from_der(&signature).unwrap();
+        let ec_key = public_key.ec_key().unwrap();
+        assert!(signature.verify(&digest, &ec_key).unwrap());
+    }
+
+    #[tokio::test]
+    async fn sign_error_path() {
+        let key_store = KeyStore::new();
+
+        let error = *key_store
+            .sign("id", KeyType::ES256, "hello world".as_bytes())
+            .await
+            .unwrap_err()
+            .downcast::<Error>()
+            .unwrap();
+        assert_matches!(error, Error::KeyNotFound(_));
+    }
+}
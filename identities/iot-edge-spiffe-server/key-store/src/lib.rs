@@ -14,20 +14,67 @@
 
 use std::sync::Arc;
 
+use blocking::CryptoMetricsSnapshot;
 use core_objects::KeyType;
 use openssl::pkey::{PKey, Public};
 use server_config::KeyStoreConfig;
 
+pub mod blocking;
 pub mod disk;
+#[cfg(feature = "chaos")]
+pub mod fault_injection;
+#[cfg(feature = "fips")]
+mod fips;
+pub mod inmemory;
+#[cfg(feature = "tpm")]
+pub mod tpm;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("The {0} key store is not enabled in this build")]
+    BackendDisabled(&'static str),
+    #[error("Could not open the disk key store: {0}")]
+    Disk(disk::error::Error),
+    #[cfg(feature = "tpm")]
+    #[error("Could not open the TPM key store: {0}")]
+    Tpm(tpm::error::Error),
+    #[error("FIPS mode is not enabled in this build")]
+    FipsDisabled,
+    #[cfg(feature = "fips")]
+    #[error("Could not enable the OpenSSL FIPS provider: {0}")]
+    Fips(openssl::error::ErrorStack),
+}
+
+// FIPS mode is behind a cargo feature since it needs OpenSSL 3 with its FIPS provider installed.
+pub fn enable_fips() -> Result<(), Error> {
+    #[cfg(feature = "fips")]
+    {
+        fips::enable().map_err(Error::Fips)
+    }
+    #[cfg(not(feature = "fips"))]
+    {
+        Err(Error::FipsDisabled)
+    }
+}
 
 pub struct KeyStoreFactory {}
 
 impl KeyStoreFactory {
-    #[must_use]
-    pub fn get(config: &KeyStoreConfig) -> Arc<dyn KeyStore> {
+    // The TPM key store is behind a cargo feature since it needs the TSS libraries of the device.
+    pub fn get(config: &KeyStoreConfig) -> Result<Arc<dyn KeyStore>, Error> {
         match config {
-            KeyStoreConfig::Disk(config) => Arc::new(disk::KeyStore::new(config)),
-            KeyStoreConfig::Memory() => unimplemented!(),
+            KeyStoreConfig::Disk(config) => {
+                let key_store = disk::KeyStore::new(config).map_err(Error::Disk)?;
+                Ok(Arc::new(key_store))
+            }
+            KeyStoreConfig::Memory() => Ok(Arc::new(inmemory::KeyStore::new())),
+            #[cfg(feature = "tpm")]
+            KeyStoreConfig::Tpm(config) => {
+                let key_store = tpm::KeyStore::new(config).map_err(Error::Tpm)?;
+                Ok(Arc::new(key_store))
+            }
+            #[cfg(not(feature = "tpm"))]
+            KeyStoreConfig::Tpm(_) => Err(Error::BackendDisabled("tpm")),
         }
     }
 }
@@ -45,9 +92,29 @@ pub trait KeyStore: Sync + Send {
         key_type: KeyType,
         digest: &[u8],
     ) -> Result<(usize, Vec<u8>), Box<dyn std::error::Error + Send>>;
+    /// Signs several digests with the same key, in order. Stores override it to look up the key
+    /// once for the whole batch.
+    async fn sign_batch(
+        &self,
+        id: &str,
+        key_type: KeyType,
+        digests: &[Vec<u8>],
+    ) -> Result<Vec<(usize, Vec<u8>)>, Box<dyn std::error::Error + Send>> {
+        let mut signatures = Vec::with_capacity(digests.len());
+        for digest in digests {
+            signatures.push(self.sign(id, key_type, digest).await?);
+        }
+
+        Ok(signatures)
+    }
     async fn delete_key_pair(&self, id: &str) -> Result<(), Box<dyn std::error::Error + Send>>;
     async fn get_public_key(
         &self,
         id: &str,
     ) -> Result<PKey<Public>, Box<dyn std::error::Error + Send>>;
+
+    /// Latency of the crypto operations (signing, key generation) done by the store.
+    fn crypto_metrics(&self) -> CryptoMetricsSnapshot {
+        CryptoMetricsSnapshot::default()
+    }
 }
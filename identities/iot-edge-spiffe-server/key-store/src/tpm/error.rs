@@ -0,0 +1,42 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::io;
+
+use core_objects::KeyType;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Key could not be found: {0}")]
+    KeyNotFound(String),
+    #[error("TPM error: {0}")]
+    Tpm(tss_esapi::Error),
+    #[error("Openssl Error: {0}")]
+    OpenSSL(openssl::error::ErrorStack),
+    #[error("Could not read the TPM handle map: {0}")]
+    ReadHandleMap(io::Error),
+    #[error("Could not write the TPM handle map: {0}")]
+    WriteHandleMap(io::Error),
+    #[error("Invalid TPM handle map: {0}")]
+    ParseHandleMap(serde_json::Error),
+    #[error("All the {0} persistent handles of the key store are used")]
+    NoFreeHandle(u32),
+    #[error("Unexpected {0} returned by the TPM")]
+    UnexpectedResponse(&'static str),
+    #[error("Unimplemented KeyType {0:?}")]
+    UnimplementedKeyType(KeyType),
+    #[error("The TPM worker thread stopped")]
+    WorkerStopped,
+}
+
+impl From<tss_esapi::Error> for Error {
+    fn from(err: tss_esapi::Error) -> Self {
+        Error::Tpm(err)
+    }
+}
+
+impl From<openssl::error::ErrorStack> for Error {
+    fn from(err: openssl::error::ErrorStack) -> Self {
+        Error::OpenSSL(err)
+    }
+}
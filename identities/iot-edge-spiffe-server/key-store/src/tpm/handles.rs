@@ -0,0 +1,114 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Persistent handles of the keys, by key id. The TPM only knows the handles, the map is kept in a
+// file so the keys are found again after a restart. A key is persisted in the TPM before being added
+// to the file and removed from the file before being evicted: a crash in between leaves an orphan
+// key in the TPM, never an entry of the file without a key. Orphans are evicted when their handle
+// is allocated again.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::error::Error;
+
+pub struct HandleMap {
+    path: PathBuf,
+    handles: BTreeMap<String, u32>,
+    handle_base: u32,
+    max_keys: u32,
+}
+
+impl HandleMap {
+    pub fn load(path: &Path, handle_base: u32, max_keys: u32) -> Result<Self, Error> {
+        let handles = match fs::read(path) {
+            Ok(handles) => serde_json::from_slice(&handles).map_err(Error::ParseHandleMap)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(Error::ReadHandleMap(err)),
+        };
+
+        Ok(HandleMap {
+            path: path.to_path_buf(),
+            handles,
+            handle_base,
+            max_keys,
+        })
+    }
+
+    pub fn get(&self, id: &str) -> Option<u32> {
+        self.handles.get(id).copied()
+    }
+
+    // First handle of the range not used by a key.
+    pub fn allocate(&self) -> Result<u32, Error> {
+        (self.handle_base..self.handle_base.saturating_add(self.max_keys))
+            .find(|handle| !self.handles.values().any(|used| used == handle))
+            .ok_or(Error::NoFreeHandle(self.max_keys))
+    }
+
+    pub fn insert(&mut self, id: &str, handle: u32) -> Result<(), Error> {
+        self.handles.insert(id.to_string(), handle);
+
+        self.save()
+    }
+
+    pub fn remove(&mut self, id: &str) -> Result<Option<u32>, Error> {
+        let handle = self.handles.remove(id);
+        if handle.is_some() {
+            self.save()?;
+        }
+
+        Ok(handle)
+    }
+
+    // Written to a temporary file first so a crash cannot leave a truncated map.
+    fn save(&self) -> Result<(), Error> {
+        let handles = serde_json::to_vec(&self.handles).map_err(Error::ParseHandleMap)?;
+
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, handles).map_err(Error::WriteHandleMap)?;
+        fs::rename(&tmp_path, &self.path).map_err(Error::WriteHandleMap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use matches::assert_matches;
+
+    const HANDLE_BASE: u32 = 0x8100_0100;
+
+    #[test]
+    fn insert_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("handles.json");
+
+        let mut handles = HandleMap::load(&path, HANDLE_BASE, 2).unwrap();
+        let handle = handles.allocate().unwrap();
+        assert_eq!(handle, HANDLE_BASE);
+        handles.insert("key1", handle).unwrap();
+
+        // The keys are found again after a restart.
+        let handles = HandleMap::load(&path, HANDLE_BASE, 2).unwrap();
+        assert_eq!(handles.get("key1"), Some(HANDLE_BASE));
+        assert_eq!(handles.get("key2"), None);
+    }
+
+    #[test]
+    fn allocate_reuses_removed_handles() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("handles.json");
+
+        let mut handles = HandleMap::load(&path, HANDLE_BASE, 2).unwrap();
+        handles.insert("key1", HANDLE_BASE).unwrap();
+        handles.insert("key2", HANDLE_BASE + 1).unwrap();
+        assert_matches!(handles.allocate(), Err(Error::NoFreeHandle(2)));
+
+        assert_eq!(handles.remove("key1").unwrap(), Some(HANDLE_BASE));
+        assert_eq!(handles.remove("key1").unwrap(), None);
+        assert_eq!(handles.allocate().unwrap(), HANDLE_BASE);
+    }
+}
@@ -0,0 +1,399 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Keys are generated in the TPM and never leave it. Each key is created under a primary key of the
+// owner hierarchy, then persisted at a handle of the configured range so it survives restarts. The
+// primary key is created again from the same template on every start, giving back the same key.
+// Only built with the `tpm` feature, it needs the TSS libraries of the device.
+//
+// The TSS context can only be used by one thread at a time, and calls to the TPM block. Every
+// operation is sent to a worker thread owning the context.
+
+use std::{convert::TryFrom, path::Path, str::FromStr, thread};
+
+use core_objects::KeyType;
+use log::warn;
+use openssl::{
+    bn::BigNum,
+    ec::{Asn1Flag, EcGroup, EcKey},
+    ecdsa::EcdsaSig,
+    nid::Nid,
+    pkey::{PKey, Public},
+};
+use server_config::KeyStoreConfigTpm;
+use tokio::sync::{mpsc, oneshot};
+use tss_esapi::{
+    attributes::ObjectAttributesBuilder,
+    constants::tss::{TPM2_RH_NULL, TPM2_ST_HASHCHECK},
+    handles::{KeyHandle, PersistentTpmHandle, TpmHandle},
+    interface_types::{
+        algorithm::{HashingAlgorithm, PublicAlgorithm},
+        dynamic_handles::Persistent,
+        ecc::EccCurve,
+        resource_handles::{Hierarchy, Provision},
+    },
+    structures::{
+        Digest, EccPoint, EccScheme, HashScheme, HashcheckTicket, KeyDerivationFunctionScheme,
+        Public as TpmPublic, PublicBuilder, PublicEccParametersBuilder, Signature, SignatureScheme,
+        SymmetricDefinitionObject,
+    },
+    tss2_esys::TPMT_TK_HASHCHECK,
+    Context, TctiNameConf,
+};
+
+pub mod error;
+mod handles;
+
+use error::Error;
+use handles::HandleMap;
+
+use crate::KeyStore as KeyPluginTrait;
+
+type Job = Box<dyn FnOnce(&mut Tpm) + Send>;
+
+pub struct KeyStore {
+    jobs: mpsc::UnboundedSender<Job>,
+}
+
+impl KeyStore {
+    // Opens the TPM and reads the handles of the existing keys, fails if the TPM cannot be used.
+    pub fn new(config: &KeyStoreConfigTpm) -> Result<Self, Error> {
+        let config = config.clone();
+        let (jobs, mut jobs_rx) = mpsc::unbounded_channel::<Job>();
+        let (opened_tx, opened_rx) = std::sync::mpsc::sync_channel(1);
+
+        thread::Builder::new()
+            .name("tpm-key-store".to_string())
+            .spawn(move || {
+                let mut tpm = match Tpm::open(&config) {
+                    Ok(tpm) => {
+                        let _result = opened_tx.send(Ok(()));
+                        tpm
+                    }
+                    Err(err) => {
+                        let _result = opened_tx.send(Err(err));
+                        return;
+                    }
+                };
+
+                // Stops once the key store is dropped.
+                while let Some(job) = jobs_rx.blocking_recv() {
+                    job(&mut tpm);
+                }
+            })
+            .map_err(|_| Error::WorkerStopped)?;
+
+        opened_rx.recv().map_err(|_| Error::WorkerStopped)??;
+
+        Ok(KeyStore { jobs })
+    }
+
+    async fn run<T, F>(&self, f: F) -> Result<T, Box<dyn std::error::Error + Send>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Tpm) -> Result<T, Error> + Send + 'static,
+    {
+        let (result_tx, result_rx) = oneshot::channel();
+        self.jobs
+            .send(Box::new(move |tpm| {
+                let _result = result_tx.send(f(tpm));
+            }))
+            .map_err(|_| Box::new(Error::WorkerStopped) as _)?;
+
+        result_rx
+            .await
+            .map_err(|_| Box::new(Error::WorkerStopped) as Box<dyn std::error::Error + Send>)?
+            .map_err(|err| Box::new(err) as _)
+    }
+}
+
+#[async_trait::async_trait]
+impl KeyPluginTrait for KeyStore {
+    async fn create_key_pair_if_not_exists(
+        &self,
+        id: &str,
+        key_type: KeyType,
+    ) -> Result<PKey<Public>, Box<dyn std::error::Error + Send>> {
+        let id = id.to_string();
+
+        self.run(move |tpm| tpm.create_key_pair_if_not_exists(&id, key_type))
+            .await
+    }
+
+    async fn sign(
+        &self,
+        id: &str,
+        key_type: KeyType,
+        digest: &[u8],
+    ) -> Result<(usize, Vec<u8>), Box<dyn std::error::Error + Send>> {
+        let id = id.to_string();
+        let digest = digest.to_vec();
+
+        self.run(move |tpm| tpm.sign(&id, key_type, digest)).await
+    }
+
+    async fn get_public_key(
+        &self,
+        id: &str,
+    ) -> Result<PKey<Public>, Box<dyn std::error::Error + Send>> {
+        let id = id.to_string();
+
+        self.run(move |tpm| {
+            let handle = tpm.get_handle(&id)?;
+            tpm.public_key(handle)
+        })
+        .await
+    }
+
+    async fn delete_key_pair(&self, id: &str) -> Result<(), Box<dyn std::error::Error + Send>> {
+        let id = id.to_string();
+
+        self.run(move |tpm| tpm.delete_key_pair(&id)).await
+    }
+}
+
+struct Tpm {
+    context: Context,
+    primary: KeyHandle,
+    handles: HandleMap,
+}
+
+impl Tpm {
+    fn open(config: &KeyStoreConfigTpm) -> Result<Self, Error> {
+        let tcti = TctiNameConf::from_str(&config.tcti)?;
+        let mut context = Context::new(tcti)?;
+
+        let primary = context
+            .execute_with_nullauth_session(|context| {
+                context.create_primary(
+                    Hierarchy::Owner,
+                    primary_key_template()?,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+            })?
+            .key_handle;
+
+        let handles = HandleMap::load(
+            Path::new(&config.handle_map_path),
+            config.handle_base,
+            config.max_keys,
+        )?;
+
+        Ok(Tpm {
+            context,
+            primary,
+            handles,
+        })
+    }
+
+    fn create_key_pair_if_not_exists(
+        &mut self,
+        id: &str,
+        key_type: KeyType,
+    ) -> Result<PKey<Public>, Error> {
+        if let Some(handle) = self.handles.get(id) {
+            return self.public_key(handle);
+        }
+
+        if key_type != KeyType::ES256 {
+            return Err(Error::UnimplementedKeyType(key_type));
+        }
+
+        let handle = self.handles.allocate()?;
+        self.evict_orphan(handle)?;
+
+        let primary = self.primary;
+        let key = self.context.execute_with_nullauth_session(|context| {
+            let key = context.create(primary, signing_key_template()?, None, None, None, None)?;
+            context.load(primary, key.out_private, key.out_public)
+        })?;
+
+        let persistent = Persistent::Persistent(PersistentTpmHandle::new(handle)?);
+        let persisted = self.context.execute_with_nullauth_session(|context| {
+            context.evict_control(Provision::Owner, key.into(), persistent)
+        });
+        // The transient copy of the key is not needed anymore, whether it was persisted or not.
+        self.context.flush_context(key.into())?;
+        persisted?;
+
+        self.handles.insert(id, handle)?;
+
+        self.public_key(handle)
+    }
+
+    fn sign(
+        &mut self,
+        id: &str,
+        key_type: KeyType,
+        digest: Vec<u8>,
+    ) -> Result<(usize, Vec<u8>), Error> {
+        if key_type != KeyType::ES256 {
+            return Err(Error::UnimplementedKeyType(key_type));
+        }
+
+        let handle = self.get_handle(id)?;
+        let digest = Digest::try_from(digest)?;
+
+        // Keys that are not restricted can sign any digest, the ticket is a null one.
+        let validation = HashcheckTicket::try_from(TPMT_TK_HASHCHECK {
+            tag: TPM2_ST_HASHCHECK,
+            hierarchy: TPM2_RH_NULL,
+            digest: Default::default(),
+        })?;
+
+        let signature = self.with_key(handle, |context, key| {
+            context.execute_with_nullauth_session(|context| {
+                context.sign(key, digest, SignatureScheme::Null, validation)
+            })
+        })?;
+
+        match signature {
+            Signature::EcDsa(signature) => {
+                let r = BigNum::from_slice(signature.signature_r().value())?;
+                let s = BigNum::from_slice(signature.signature_s().value())?;
+                let signature = EcdsaSig::from_private_components(r, s)?.to_der()?;
+
+                Ok((signature.len(), signature))
+            }
+            _ => Err(Error::UnexpectedResponse("signature scheme")),
+        }
+    }
+
+    fn delete_key_pair(&mut self, id: &str) -> Result<(), Error> {
+        let handle = self
+            .handles
+            .remove(id)?
+            .ok_or_else(|| Error::KeyNotFound(id.to_string()))?;
+
+        self.evict(handle)
+    }
+
+    fn get_handle(&self, id: &str) -> Result<u32, Error> {
+        self.handles
+            .get(id)
+            .ok_or_else(|| Error::KeyNotFound(id.to_string()))
+    }
+
+    fn public_key(&mut self, handle: u32) -> Result<PKey<Public>, Error> {
+        let (public, _, _) = self.with_key(handle, |context, key| context.read_public(key))?;
+
+        match public {
+            TpmPublic::Ecc { unique, .. } => {
+                let mut group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+                group.set_asn1_flag(Asn1Flag::NAMED_CURVE);
+                let x = BigNum::from_slice(unique.x().value())?;
+                let y = BigNum::from_slice(unique.y().value())?;
+                let ec_key = EcKey::from_public_key_affine_coordinates(&group, &x, &y)?;
+
+                Ok(PKey::from_ec_key(ec_key)?)
+            }
+            _ => Err(Error::UnexpectedResponse("public key type")),
+        }
+    }
+
+    fn with_key<T>(
+        &mut self,
+        handle: u32,
+        f: impl FnOnce(&mut Context, KeyHandle) -> Result<T, tss_esapi::Error>,
+    ) -> Result<T, Error> {
+        let handle = PersistentTpmHandle::new(handle)?;
+        let mut object = self
+            .context
+            .tr_from_tpm_public(TpmHandle::Persistent(handle))?;
+
+        let result = f(&mut self.context, object.into());
+        self.context.tr_close(&mut object)?;
+
+        Ok(result?)
+    }
+
+    fn evict(&mut self, handle: u32) -> Result<(), Error> {
+        let handle = PersistentTpmHandle::new(handle)?;
+        let object = self
+            .context
+            .tr_from_tpm_public(TpmHandle::Persistent(handle))?;
+
+        self.context.execute_with_nullauth_session(|context| {
+            context.evict_control(Provision::Owner, object, Persistent::Persistent(handle))
+        })?;
+
+        Ok(())
+    }
+
+    // A key is left at its handle without being in the map when the server stopped while creating
+    // or deleting it.
+    fn evict_orphan(&mut self, handle: u32) -> Result<(), Error> {
+        let persistent = PersistentTpmHandle::new(handle)?;
+        let mut object = match self
+            .context
+            .tr_from_tpm_public(TpmHandle::Persistent(persistent))
+        {
+            Ok(object) => object,
+            // Nothing is persisted at this handle.
+            Err(_) => return Ok(()),
+        };
+        self.context.tr_close(&mut object)?;
+
+        warn!("Evicting orphan key at TPM handle {:#x}", handle);
+        self.evict(handle)
+    }
+}
+
+fn primary_key_template() -> Result<TpmPublic, tss_esapi::Error> {
+    let object_attributes = ObjectAttributesBuilder::new()
+        .with_fixed_tpm(true)
+        .with_fixed_parent(true)
+        .with_sensitive_data_origin(true)
+        .with_user_with_auth(true)
+        .with_restricted(true)
+        .with_decrypt(true)
+        .build()?;
+
+    let ecc_parameters = PublicEccParametersBuilder::new()
+        .with_ecc_scheme(EccScheme::Null)
+        .with_curve(EccCurve::NistP256)
+        .with_is_signing_key(false)
+        .with_is_decryption_key(true)
+        .with_restricted(true)
+        .with_symmetric(SymmetricDefinitionObject::AES_128_CFB)
+        .with_key_derivation_function_scheme(KeyDerivationFunctionScheme::Null)
+        .build()?;
+
+    PublicBuilder::new()
+        .with_public_algorithm(PublicAlgorithm::Ecc)
+        .with_name_hashing_algorithm(HashingAlgorithm::Sha256)
+        .with_object_attributes(object_attributes)
+        .with_ecc_parameters(ecc_parameters)
+        .with_ecc_unique_identifier(EccPoint::default())
+        .build()
+}
+
+fn signing_key_template() -> Result<TpmPublic, tss_esapi::Error> {
+    let object_attributes = ObjectAttributesBuilder::new()
+        .with_fixed_tpm(true)
+        .with_fixed_parent(true)
+        .with_sensitive_data_origin(true)
+        .with_user_with_auth(true)
+        .with_sign_encrypt(true)
+        .build()?;
+
+    let ecc_parameters = PublicEccParametersBuilder::new()
+        .with_ecc_scheme(EccScheme::EcDsa(HashScheme::new(HashingAlgorithm::Sha256)))
+        .with_curve(EccCurve::NistP256)
+        .with_is_signing_key(true)
+        .with_is_decryption_key(false)
+        .with_restricted(false)
+        .with_symmetric(SymmetricDefinitionObject::Null)
+        .with_key_derivation_function_scheme(KeyDerivationFunctionScheme::Null)
+        .build()?;
+
+    PublicBuilder::new()
+        .with_public_algorithm(PublicAlgorithm::Ecc)
+        .with_name_hashing_algorithm(HashingAlgorithm::Sha256)
+        .with_object_attributes(object_attributes)
+        .with_ecc_parameters(ecc_parameters)
+        .with_ecc_unique_identifier(EccPoint::default())
+        .build()
+}
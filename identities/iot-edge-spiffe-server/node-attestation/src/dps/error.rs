@@ -0,0 +1,43 @@
+// Copyright (c) Microsoft. All rights reserved.
+use openssl::error::ErrorStack;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Invalid DPS attestation token {0}")]
+    InvalidToken(serde_json::Error),
+    #[error("DPS attestation token expired")]
+    TokenExpired,
+    #[error("DPS attestation token lives longer than {0} seconds")]
+    TokenLifetimeTooLong(u64),
+    #[error("Unable to read key file {0}: {1}")]
+    ReadingKeyFile(String, std::io::Error),
+    #[error("Invalid base64 value {0}")]
+    InvalidBase64(base64::DecodeError),
+    #[error("Symmetric key enrollments are not configured")]
+    SymmetricKeyNotConfigured,
+    #[error("X.509 enrollments are not configured")]
+    X509NotConfigured,
+    #[error("Invalid signature of the DPS attestation token")]
+    InvalidSignature,
+    #[error("Invalid device certificate {0}")]
+    Certificate(crate::x509::Error),
+    #[error("Certificate common name does not match registration {0}")]
+    RegistrationIdMismatch(String),
+    #[error("Cryptographic operation failed {0}")]
+    Crypto(ErrorStack),
+    #[error("Error while building the DPS service request {0}")]
+    ServiceRequest(hyper::http::Error),
+    #[error("Error while calling the DPS service API {0}")]
+    ServiceAPI(hyper::Error),
+    #[error("DPS service API responded with {0}")]
+    ServiceResponse(hyper::StatusCode),
+    #[error("Error while reading the DPS registration {0}")]
+    DeserializingRegistration(serde_json::Error),
+    #[error("Registration {0} not found in DPS")]
+    NotRegistered(String),
+    #[error("Registration {0} is {1}, not assigned to an IoT hub")]
+    NotAssigned(String, String),
+    #[error("IoT hub {0} not allowed")]
+    IoTHubNotAllowed(String),
+}
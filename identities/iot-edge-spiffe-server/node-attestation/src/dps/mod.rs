@@ -0,0 +1,428 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Attestation of the agents on Azure IoT devices provisioned by DPS. The agent signs its token with the
+// credentials of the DPS enrollment of its device, the server checks them against the enrollment keys or
+// CAs, then looks up the registration in DPS to get the IoT hub and device ID the device was assigned.
+
+pub mod error;
+pub mod registrations;
+
+use std::{collections::BTreeSet, fs};
+
+use core_objects::{build_selector_string, get_epoch_time, NodeSelectorType};
+use log::{debug, info};
+use server_agent_api::dps_attestation::{signed_data, Token};
+use server_config::NodeAttestationConfigDps;
+use zeroize::Zeroizing;
+
+use crate::{x509, AgentAttributes, NodeAttestation as NodeAttestationTrait};
+
+use error::Error;
+use registrations::{hmac_sha256, Registrations, ServiceClient};
+
+const ENROLLMENT_SYMMETRIC_KEY: &str = "symmetric_key";
+const ENROLLMENT_X509: &str = "x509";
+const REGISTRATION_ASSIGNED: &str = "assigned";
+
+pub struct NodeAttestation {
+    config: NodeAttestationConfigDps,
+    registrations: Box<dyn Registrations>,
+}
+
+impl NodeAttestation {
+    #[must_use]
+    pub fn new(config: &NodeAttestationConfigDps) -> Self {
+        NodeAttestation {
+            config: config.clone(),
+            registrations: Box::new(ServiceClient::new(config)),
+        }
+    }
+
+    fn check_expiry(&self, expiry: u64) -> Result<(), Error> {
+        let now = get_epoch_time();
+
+        if expiry <= now {
+            return Err(Error::TokenExpired);
+        }
+        if expiry - now > self.config.max_token_ttl_secs {
+            return Err(Error::TokenLifetimeTooLong(self.config.max_token_ttl_secs));
+        }
+
+        Ok(())
+    }
+
+    // The device key of a group enrollment is derived from the group key and the registration ID.
+    fn verify_symmetric_key(
+        &self,
+        registration_id: &str,
+        data: &[u8],
+        signature: &str,
+    ) -> Result<(), Error> {
+        if self.config.enrollment_group_key_paths.is_empty() {
+            return Err(Error::SymmetricKeyNotConfigured);
+        }
+
+        let signature = base64::decode(signature).map_err(Error::InvalidBase64)?;
+
+        for group_key_path in &self.config.enrollment_group_key_paths {
+            let group_key = read_key(group_key_path)?;
+            let device_key = Zeroizing::new(hmac_sha256(&group_key, registration_id.as_bytes())?);
+            let expected = hmac_sha256(&device_key, data)?;
+
+            if expected.len() == signature.len() && openssl::memcmp::eq(&expected, &signature) {
+                return Ok(());
+            }
+        }
+
+        Err(Error::InvalidSignature)
+    }
+
+    // DPS requires the common name of the device certificate to be the registration ID.
+    fn verify_x509(
+        &self,
+        registration_id: &str,
+        data: &[u8],
+        certificate_chain: &[String],
+        signature: &str,
+    ) -> Result<(), Error> {
+        let ca_cert_path = self
+            .config
+            .x509_ca_cert_path
+            .as_ref()
+            .ok_or(Error::X509NotConfigured)?;
+        let device_cert =
+            x509::verify_chain(ca_cert_path, certificate_chain).map_err(Error::Certificate)?;
+
+        if x509::common_name(&device_cert).as_deref() != Some(registration_id) {
+            return Err(Error::RegistrationIdMismatch(registration_id.to_string()));
+        }
+
+        x509::verify_signature(&device_cert, data, signature).map_err(Error::Certificate)
+    }
+
+    async fn auth_agent(&self, token: &str) -> Result<AgentAttributes, Error> {
+        let token: Token = serde_json::from_str(token).map_err(Error::InvalidToken)?;
+        let registration_id = token.registration_id().to_string();
+        self.check_expiry(token.expiry())?;
+
+        let data = signed_data(&self.config.id_scope, &registration_id, token.expiry());
+        let enrollment_type = match &token {
+            Token::SymmetricKey { signature, .. } => {
+                self.verify_symmetric_key(&registration_id, data.as_bytes(), signature)?;
+                ENROLLMENT_SYMMETRIC_KEY
+            }
+            Token::X509 {
+                certificate_chain,
+                signature,
+                ..
+            } => {
+                self.verify_x509(
+                    &registration_id,
+                    data.as_bytes(),
+                    certificate_chain,
+                    signature,
+                )?;
+                ENROLLMENT_X509
+            }
+        };
+
+        let registration = self
+            .registrations
+            .get_registration(&registration_id)
+            .await?
+            .ok_or_else(|| Error::NotRegistered(registration_id.clone()))?;
+
+        let (assigned_hub, device_id) = match (
+            registration.status.as_str(),
+            registration.assigned_hub,
+            registration.device_id,
+        ) {
+            (REGISTRATION_ASSIGNED, Some(assigned_hub), Some(device_id)) => {
+                (assigned_hub, device_id)
+            }
+            (status, _, _) => {
+                return Err(Error::NotAssigned(registration_id, status.to_string()));
+            }
+        };
+
+        let iot_hub_name = iot_hub_name(&assigned_hub);
+        if !self.config.allowed_iot_hubs.is_empty()
+            && !self.config.allowed_iot_hubs.contains(iot_hub_name)
+        {
+            return Err(Error::IoTHubNotAllowed(iot_hub_name.to_string()));
+        }
+
+        let mut selectors = BTreeSet::new();
+        selectors.insert(build_selector_string(
+            &NodeSelectorType::IoTHubName,
+            iot_hub_name,
+        ));
+        selectors.insert(build_selector_string(
+            &NodeSelectorType::IoTHubDeviceId,
+            &device_id,
+        ));
+        selectors.insert(build_selector_string(
+            &NodeSelectorType::DpsRegistrationId,
+            &registration_id,
+        ));
+        selectors.insert(build_selector_string(
+            &NodeSelectorType::DpsEnrollmentType,
+            enrollment_type,
+        ));
+
+        info!(
+            "IoTEdge SPIFFE Agent on device {} of IoT hub {} was attested successfully",
+            device_id, iot_hub_name
+        );
+        debug!("Found the following selectors for workload {:?}", selectors);
+
+        Ok(AgentAttributes { selectors })
+    }
+}
+
+// DPS reports the host name of the assigned hub, the selectors use its name.
+fn iot_hub_name(assigned_hub: &str) -> &str {
+    assigned_hub.split('.').next().unwrap_or(assigned_hub)
+}
+
+// The keys are read for every use and wiped from memory right after.
+pub(crate) fn read_key(path: &str) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let key = Zeroizing::new(
+        fs::read_to_string(path).map_err(|err| Error::ReadingKeyFile(path.to_string(), err))?,
+    );
+
+    base64::decode(key.trim())
+        .map(Zeroizing::new)
+        .map_err(Error::InvalidBase64)
+}
+
+#[async_trait::async_trait]
+impl NodeAttestationTrait for NodeAttestation {
+    async fn attest_agent(
+        &self,
+        token: &str,
+    ) -> Result<AgentAttributes, Box<dyn std::error::Error + Send>> {
+        self.auth_agent(token)
+            .await
+            .map_err(|err| Box::new(err) as _)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use matches::assert_matches;
+    use openssl::{hash::MessageDigest, sign::Signer};
+    use registrations::RegistrationState;
+
+    use super::*;
+    use crate::x509::test_certs::{certificate, key};
+
+    const ID_SCOPE: &str = "0ne00000000";
+    const REGISTRATION_ID: &str = "device-registration";
+    const GROUP_KEY: &[u8] = b"enrollment group key";
+
+    struct TestRegistrations(Option<RegistrationState>);
+
+    #[async_trait::async_trait]
+    impl Registrations for TestRegistrations {
+        async fn get_registration(
+            &self,
+            _registration_id: &str,
+        ) -> Result<Option<RegistrationState>, Error> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn assigned() -> RegistrationState {
+        RegistrationState {
+            registration_id: REGISTRATION_ID.to_string(),
+            status: REGISTRATION_ASSIGNED.to_string(),
+            assigned_hub: Some("iotedge-hub.azure-devices.net".to_string()),
+            device_id: Some("device".to_string()),
+        }
+    }
+
+    fn init(base_path: &Path, registration: Option<RegistrationState>) -> NodeAttestation {
+        let group_key_path = base_path.join("group_key");
+        fs::write(&group_key_path, base64::encode(GROUP_KEY)).unwrap();
+
+        let config = NodeAttestationConfigDps {
+            id_scope: ID_SCOPE.to_string(),
+            service_host: "iotedge-dps.azure-devices-provisioning.net".to_string(),
+            service_policy_name: "provisioningserviceowner".to_string(),
+            service_policy_key_path: base_path.join("policy_key").to_str().unwrap().to_string(),
+            enrollment_group_key_paths: vec![group_key_path.to_str().unwrap().to_string()],
+            x509_ca_cert_path: Some(base_path.join("ca.pem").to_str().unwrap().to_string()),
+            allowed_iot_hubs: BTreeSet::new(),
+            max_token_ttl_secs: 3600,
+        };
+
+        NodeAttestation {
+            config,
+            registrations: Box::new(TestRegistrations(registration)),
+        }
+    }
+
+    fn symmetric_key_token(group_key: &[u8], expiry: u64) -> String {
+        let device_key = hmac_sha256(group_key, REGISTRATION_ID.as_bytes()).unwrap();
+        let data = signed_data(ID_SCOPE, REGISTRATION_ID, expiry);
+        let signature = hmac_sha256(&device_key, data.as_bytes()).unwrap();
+
+        serde_json::to_string(&Token::SymmetricKey {
+            registration_id: REGISTRATION_ID.to_string(),
+            expiry,
+            signature: base64::encode(signature),
+        })
+        .unwrap()
+    }
+
+    // Writes the CA of the enrollment and returns the token of a device certificate it issued.
+    fn x509_token(base_path: &Path, common_name: &str, expiry: u64) -> String {
+        let ca_key = key();
+        let ca = certificate("enrollment-ca", &ca_key, None, &[]);
+        fs::write(base_path.join("ca.pem"), ca.to_pem().unwrap()).unwrap();
+
+        let device_key = key();
+        let device_cert = certificate(common_name, &device_key, Some((&ca, &ca_key)), &[]);
+
+        let data = signed_data(ID_SCOPE, REGISTRATION_ID, expiry);
+        let mut signer = Signer::new(MessageDigest::sha256(), &device_key).unwrap();
+        signer.update(data.as_bytes()).unwrap();
+
+        serde_json::to_string(&Token::X509 {
+            registration_id: REGISTRATION_ID.to_string(),
+            expiry,
+            certificate_chain: vec![base64::encode(device_cert.to_der().unwrap())],
+            signature: base64::encode(signer.sign_to_vec().unwrap()),
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn auth_agent_symmetric_key_happy_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let node_attestation = init(dir.path(), Some(assigned()));
+
+        let token = symmetric_key_token(GROUP_KEY, get_epoch_time() + 60);
+        let attributes = node_attestation.auth_agent(&token).await.unwrap();
+
+        let expected: BTreeSet<String> = [
+            build_selector_string(&NodeSelectorType::IoTHubName, "iotedge-hub"),
+            build_selector_string(&NodeSelectorType::IoTHubDeviceId, "device"),
+            build_selector_string(&NodeSelectorType::DpsRegistrationId, REGISTRATION_ID),
+            build_selector_string(&NodeSelectorType::DpsEnrollmentType, "symmetric_key"),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(attributes.selectors, expected);
+        assert!(attributes.selectors.contains("IOTHUBNAME:iotedge-hub"));
+    }
+
+    #[tokio::test]
+    async fn auth_agent_symmetric_key_invalid_signature_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let node_attestation = init(dir.path(), Some(assigned()));
+
+        let token = symmetric_key_token(b"other group key", get_epoch_time() + 60);
+        let error = node_attestation.auth_agent(&token).await.unwrap_err();
+
+        assert_matches!(error, Error::InvalidSignature);
+    }
+
+    #[tokio::test]
+    async fn auth_agent_token_expiry_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let node_attestation = init(dir.path(), Some(assigned()));
+
+        let token = symmetric_key_token(GROUP_KEY, get_epoch_time() - 1);
+        let error = node_attestation.auth_agent(&token).await.unwrap_err();
+        assert_matches!(error, Error::TokenExpired);
+
+        let token = symmetric_key_token(GROUP_KEY, get_epoch_time() + 7200);
+        let error = node_attestation.auth_agent(&token).await.unwrap_err();
+        assert_matches!(error, Error::TokenLifetimeTooLong(3600));
+
+        let error = node_attestation.auth_agent("token").await.unwrap_err();
+        assert_matches!(error, Error::InvalidToken(_));
+    }
+
+    #[tokio::test]
+    async fn auth_agent_x509_happy_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let node_attestation = init(dir.path(), Some(assigned()));
+
+        let token = x509_token(dir.path(), REGISTRATION_ID, get_epoch_time() + 60);
+        let attributes = node_attestation.auth_agent(&token).await.unwrap();
+
+        assert!(attributes.selectors.contains(&build_selector_string(
+            &NodeSelectorType::DpsEnrollmentType,
+            "x509"
+        )));
+        assert!(attributes.selectors.contains(&build_selector_string(
+            &NodeSelectorType::IoTHubDeviceId,
+            "device"
+        )));
+    }
+
+    #[tokio::test]
+    async fn auth_agent_x509_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let node_attestation = init(dir.path(), Some(assigned()));
+
+        let token = x509_token(dir.path(), "other-registration", get_epoch_time() + 60);
+        let error = node_attestation.auth_agent(&token).await.unwrap_err();
+        assert_matches!(error, Error::RegistrationIdMismatch(_));
+
+        // The device certificate was issued by another CA.
+        let token = x509_token(dir.path(), REGISTRATION_ID, get_epoch_time() + 60);
+        let other_ca = certificate("other-ca", &key(), None, &[]);
+        fs::write(dir.path().join("ca.pem"), other_ca.to_pem().unwrap()).unwrap();
+        let error = node_attestation.auth_agent(&token).await.unwrap_err();
+        assert_matches!(
+            error,
+            Error::Certificate(x509::Error::CertificateNotTrusted(_))
+        );
+    }
+
+    #[tokio::test]
+    async fn auth_agent_registration_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let token = symmetric_key_token(GROUP_KEY, get_epoch_time() + 60);
+
+        let node_attestation = init(dir.path(), None);
+        let error = node_attestation.auth_agent(&token).await.unwrap_err();
+        assert_matches!(error, Error::NotRegistered(_));
+
+        let mut registration = assigned();
+        registration.status = "disabled".to_string();
+        let node_attestation = init(dir.path(), Some(registration));
+        let error = node_attestation.auth_agent(&token).await.unwrap_err();
+        assert_matches!(error, Error::NotAssigned(_, _));
+
+        let mut node_attestation = init(dir.path(), Some(assigned()));
+        node_attestation
+            .config
+            .allowed_iot_hubs
+            .insert("other-hub".to_string());
+        let error = node_attestation.auth_agent(&token).await.unwrap_err();
+        assert_matches!(error, Error::IoTHubNotAllowed(_));
+    }
+
+    #[test]
+    fn service_sas_token() {
+        let token = registrations::sas_token(
+            "iotedge-dps.azure-devices-provisioning.net",
+            "provisioningserviceowner",
+            b"key",
+            1000,
+        )
+        .unwrap();
+
+        assert!(token.starts_with(
+            "SharedAccessSignature sr=iotedge%2Ddps%2Eazure%2Ddevices%2Dprovisioning%2Enet&sig="
+        ));
+        assert!(token.ends_with("&se=1000&skn=provisioningserviceowner"));
+    }
+}
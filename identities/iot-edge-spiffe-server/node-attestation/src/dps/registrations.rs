@@ -0,0 +1,126 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Lookup of the device registrations with the service API of DPS, authenticated with a shared access
+// policy of the provisioning service.
+
+use core_objects::get_epoch_time;
+use hyper::{body, Body, Client, Method, Request, StatusCode};
+use hyper_openssl::HttpsConnector;
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use server_config::NodeAttestationConfigDps;
+
+use super::{error::Error, read_key};
+
+const API_VERSION: &str = "2021-10-01";
+const SAS_TOKEN_TTL_SECS: u64 = 300;
+
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistrationState {
+    pub registration_id: String,
+    // "unassigned", "assigning", "assigned", "failed" or "disabled".
+    pub status: String,
+    pub assigned_hub: Option<String>,
+    pub device_id: Option<String>,
+}
+
+#[async_trait::async_trait]
+pub trait Registrations: Sync + Send {
+    // None when DPS has no registration for this ID.
+    async fn get_registration(
+        &self,
+        registration_id: &str,
+    ) -> Result<Option<RegistrationState>, Error>;
+}
+
+pub struct ServiceClient {
+    service_host: String,
+    policy_name: String,
+    policy_key_path: String,
+}
+
+impl ServiceClient {
+    #[must_use]
+    pub fn new(config: &NodeAttestationConfigDps) -> Self {
+        ServiceClient {
+            service_host: config.service_host.clone(),
+            policy_name: config.service_policy_name.clone(),
+            policy_key_path: config.service_policy_key_path.clone(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Registrations for ServiceClient {
+    async fn get_registration(
+        &self,
+        registration_id: &str,
+    ) -> Result<Option<RegistrationState>, Error> {
+        let key = read_key(&self.policy_key_path)?;
+        let authorization = sas_token(
+            &self.service_host,
+            &self.policy_name,
+            &key,
+            get_epoch_time() + SAS_TOKEN_TTL_SECS,
+        )?;
+
+        let uri = format!(
+            "https://{}/registrations/{}?api-version={}",
+            self.service_host,
+            utf8_percent_encode(registration_id, NON_ALPHANUMERIC),
+            API_VERSION
+        );
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(uri)
+            .header(hyper::header::AUTHORIZATION, authorization)
+            .body(Body::empty())
+            .map_err(Error::ServiceRequest)?;
+
+        let connector = HttpsConnector::new().map_err(Error::Crypto)?;
+        let client: Client<_, Body> = Client::builder().build(connector);
+
+        let response = client.request(request).await.map_err(Error::ServiceAPI)?;
+        match response.status() {
+            StatusCode::OK => (),
+            StatusCode::NOT_FOUND => return Ok(None),
+            status => return Err(Error::ServiceResponse(status)),
+        }
+
+        let body = body::to_bytes(response.into_body())
+            .await
+            .map_err(Error::ServiceAPI)?;
+
+        serde_json::from_slice(&body)
+            .map(Some)
+            .map_err(Error::DeserializingRegistration)
+    }
+}
+
+// Shared access signature of the policy, scoped to the whole provisioning service.
+pub(crate) fn sas_token(
+    service_host: &str,
+    policy_name: &str,
+    key: &[u8],
+    expiry: u64,
+) -> Result<String, Error> {
+    let resource = utf8_percent_encode(service_host, NON_ALPHANUMERIC).to_string();
+    let signature = hmac_sha256(key, format!("{}\n{}", resource, expiry).as_bytes())?;
+
+    Ok(format!(
+        "SharedAccessSignature sr={}&sig={}&se={}&skn={}",
+        resource,
+        utf8_percent_encode(&base64::encode(signature), NON_ALPHANUMERIC),
+        expiry,
+        policy_name
+    ))
+}
+
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+    let key = PKey::hmac(key).map_err(Error::Crypto)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).map_err(Error::Crypto)?;
+    signer.update(data).map_err(Error::Crypto)?;
+
+    signer.sign_to_vec().map_err(Error::Crypto)
+}
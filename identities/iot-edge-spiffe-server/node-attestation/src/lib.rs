@@ -11,7 +11,10 @@
     clippy::missing_panics_doc
 )]
 
+pub mod dps;
 pub mod psat;
+pub mod x509;
+pub mod x509pop;
 
 #[cfg(not(any(test, feature = "tests")))]
 use kube::Client;
@@ -20,6 +23,7 @@ use mock_kube::Client;
 
 use std::{collections::BTreeSet, sync::Arc};
 
+use server_agent_api::get_attestation_nonce;
 use server_config::NodeAttestationConfig;
 
 #[derive(Clone, Debug)]
@@ -37,6 +41,10 @@ impl NodeAttestatorFactory {
                 Arc::new(psat::NodeAttestation::new(config, client))
             }
             NodeAttestationConfig::Sat(_config) => unimplemented!(),
+            NodeAttestationConfig::Dps(config) => Arc::new(dps::NodeAttestation::new(config)),
+            NodeAttestationConfig::X509Pop(config) => {
+                Arc::new(x509pop::NodeAttestation::new(config))
+            }
         }
     }
 }
@@ -47,4 +55,11 @@ pub trait NodeAttestation: Sync + Send {
         &self,
         token: &str,
     ) -> Result<AgentAttributes, Box<dyn std::error::Error + Send>>;
+
+    // Nonce for the agent to sign in its next token. None when the attestation does not use nonces.
+    async fn create_nonce(
+        &self,
+    ) -> Result<Option<get_attestation_nonce::Response>, Box<dyn std::error::Error + Send>> {
+        Ok(None)
+    }
 }
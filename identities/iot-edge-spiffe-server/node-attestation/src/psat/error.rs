@@ -6,6 +6,12 @@ use thiserror::Error;
 pub enum Error {
     #[error("Unable to create Kube Client {0}")]
     UnableToCreateKubeClient(kube::Error),
+    #[error("Token already used")]
+    TokenReplayed,
+    #[error("Too many tokens used recently to track them")]
+    TooManyUsedTokens,
+    #[error("Token not bound to the running pod {0}")]
+    TokenNotBoundToPod(String),
     #[error("Service account not allowed {0}")]
     ServiceAccountNotAllowed(String),
     #[error("Error while creating token review request {0}")]
@@ -36,6 +42,10 @@ pub enum MissingField {
     PodName,
     #[error("Pod Uid")]
     PodUid,
+    #[error("Pod Uid of the token")]
+    TokenPodUid,
+    #[error("Expiry of the token")]
+    TokenExpiry,
     #[error("Cluster name")]
     ClusterName,
     #[error("Namespace")]
@@ -2,9 +2,12 @@
 
 pub mod error;
 
-use std::collections::{BTreeMap, BTreeSet};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    sync::Mutex,
+};
 
-use core_objects::{build_selector_string, NodeSelectorType};
+use core_objects::{build_selector_string, get_epoch_time, NodeSelectorType};
 use k8s_openapi::api::{
     authentication::v1::{TokenReview, TokenReviewStatus},
     core::v1::{Node, Pod},
@@ -22,6 +25,13 @@ use crate::{psat::error::MissingField, AgentAttributes, NodeAttestation as NodeA
 
 use error::Error;
 
+// Bounds the memory taken by the IDs of the used tokens.
+const MAX_USED_TOKENS: usize = 100_000;
+// Set by Kubernetes 1.29 and later to the ID of the token, "JTI=<jti>".
+const CREDENTIAL_ID_EXTRA: &str = "authentication.kubernetes.io/credential-id";
+const POD_NAME_EXTRA: &str = "authentication.kubernetes.io/pod-name";
+const POD_UID_EXTRA: &str = "authentication.kubernetes.io/pod-uid";
+
 #[derive(Clone, Debug, Default)]
 struct SelectorInfo {
     cluster_name: String,
@@ -42,9 +52,25 @@ pub struct NodeAttestation {
     allowed_node_label_keys: BTreeSet<String>,
     allowed_pod_label_keys: BTreeSet<String>,
     cluster_name: String,
+    reject_token_replays: bool,
+    used_tokens: Mutex<UsedTokens>,
     client: Client,
 }
 
+// IDs of the tokens already used, when replays are rejected. A token is kept until it expires, the API server
+// rejects it after that. The expired ones are dropped from the front of the ordered expiries.
+#[derive(Default)]
+struct UsedTokens {
+    ids: HashSet<String>,
+    expiries: BTreeSet<(u64, String)>,
+}
+
+// Claims of the token read for the replay checks, once the API server authenticated it.
+#[derive(serde::Deserialize)]
+struct TokenClaims {
+    exp: Option<u64>,
+}
+
 impl NodeAttestation {
     #[must_use]
     pub fn new(config: &NodeAttestationConfigPsat, client: Client) -> Self {
@@ -54,6 +80,8 @@ impl NodeAttestation {
             allowed_node_label_keys: config.allowed_node_label_keys.clone(),
             allowed_pod_label_keys: config.allowed_pod_label_keys.clone(),
             cluster_name: config.cluster_name.clone(),
+            reject_token_replays: config.reject_token_replays,
+            used_tokens: Mutex::new(UsedTokens::default()),
             client,
         }
     }
@@ -91,6 +119,29 @@ impl NodeAttestation {
         Ok(token_review_status)
     }
 
+    // Records the token as used until it expires, or fails if it was already used.
+    fn use_token(&self, token_id: String, expires_at: u64, now: u64) -> Result<(), Error> {
+        let mut used_tokens = self.used_tokens.lock().unwrap();
+        let UsedTokens { ids, expiries } = &mut *used_tokens;
+        while let Some(expired) = expiries.iter().next().cloned() {
+            if expired.0 > now {
+                break;
+            }
+            expiries.remove(&expired);
+            ids.remove(&expired.1);
+        }
+        if ids.contains(&token_id) {
+            return Err(Error::TokenReplayed);
+        }
+        if ids.len() >= MAX_USED_TOKENS {
+            return Err(Error::TooManyUsedTokens);
+        }
+        ids.insert(token_id.clone());
+        expiries.insert((expires_at, token_id));
+
+        Ok(())
+    }
+
     async fn get_selector_info(
         &self,
         token_review_status: TokenReviewStatus,
@@ -102,15 +153,29 @@ impl NodeAttestation {
             .ok_or(Error::MissingField(MissingField::Extra))?;
 
         let pod_name = extras
-            .get("authentication.kubernetes.io/pod-name")
+            .get(POD_NAME_EXTRA)
             .ok_or(Error::MissingField(MissingField::PodName))?
             .first()
             .ok_or(Error::MissingField(MissingField::PodName))?
             .clone();
+        let token_pod_uid = extras
+            .get(POD_UID_EXTRA)
+            .and_then(|values| values.first())
+            .ok_or(Error::MissingField(MissingField::TokenPodUid))?;
 
         let pods: Api<Pod> = Api::default_namespaced(self.client.clone());
 
         let pod = pods.get(&pod_name).await.map_err(Error::GettingPodInfo)?;
+        let pod_uid = pod
+            .metadata
+            .uid
+            .ok_or(Error::MissingField(MissingField::PodUid))?;
+
+        // The token is bound to the pod it was issued to, a token of a deleted pod is not accepted for
+        // another pod that got the same name.
+        if *token_pod_uid != pod_uid {
+            return Err(Error::TokenNotBoundToPod(pod_name));
+        }
 
         let pod_spec = pod.spec.ok_or(Error::MissingField(MissingField::PodSpec))?;
         let pod_status = pod
@@ -130,10 +195,7 @@ impl NodeAttestation {
         let selector_info = SelectorInfo {
             cluster_name: self.cluster_name.clone(),
             pod_name,
-            pod_uid: pod
-                .metadata
-                .uid
-                .ok_or(Error::MissingField(MissingField::PodUid))?,
+            pod_uid,
             namespace: pod
                 .metadata
                 .namespace
@@ -178,9 +240,16 @@ impl NodeAttestation {
 
     async fn auth_agent(&self, token: &str) -> Result<AgentAttributes, Error> {
         let token_review_status = self.review_token(token).await?;
+        let token_id = token_id(&token_review_status, token);
 
         let selector_info = self.get_selector_info(token_review_status).await?;
 
+        // Only the tokens of attested agents are recorded, a token that failed can be presented again once the
+        // cause is fixed, e.g. the service account is allowed.
+        if self.reject_token_replays {
+            self.use_token(token_id, token_expiry(token)?, get_epoch_time())?;
+        }
+
         let mut selectors = BTreeSet::new();
         selectors.insert(build_selector_string(
             &NodeSelectorType::Cluster,
@@ -236,6 +305,40 @@ impl NodeAttestation {
     }
 }
 
+// The ID of the token when the API server reports it, a hash of the token otherwise.
+fn token_id(token_review_status: &TokenReviewStatus, token: &str) -> String {
+    let credential_id = token_review_status
+        .user
+        .as_ref()
+        .and_then(|user| user.extra.as_ref())
+        .and_then(|extra| extra.get(CREDENTIAL_ID_EXTRA))
+        .and_then(|values| values.first());
+
+    match credential_id {
+        Some(credential_id) => credential_id.clone(),
+        None => openssl::sha::sha256(token.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect(),
+    }
+}
+
+// Expiry of the token, from its payload. The signature was checked by the API server when reviewing it.
+fn token_expiry(token: &str) -> Result<u64, Error> {
+    let payload = token
+        .split('.')
+        .nth(1)
+        .ok_or(Error::MissingField(MissingField::TokenExpiry))?;
+    let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD)
+        .map_err(|_| Error::MissingField(MissingField::TokenExpiry))?;
+    let claims: TokenClaims = serde_json::from_slice(&payload)
+        .map_err(|_| Error::MissingField(MissingField::TokenExpiry))?;
+
+    claims
+        .exp
+        .ok_or(Error::MissingField(MissingField::TokenExpiry))
+}
+
 fn push_map_into_selectors<'a, A>(
     selectors: &mut BTreeSet<String>,
     map: &BTreeMap<String, String>,
@@ -275,8 +378,8 @@ mod tests {
         let config = Config::load_config(CONFIG_DEFAULT_PATH).unwrap();
 
         let node_attestation_config = match config.node_attestation_config {
-            server_config::NodeAttestationConfig::Sat(_) => panic!("Unexpected type"),
             server_config::NodeAttestationConfig::Psat(psat) => psat,
+            _ => panic!("Unexpected type"),
         };
 
         let client = Client::try_default().await.unwrap();
@@ -687,6 +790,49 @@ mod tests {
         assert_matches!(error, Error::MissingField(MissingField::PodUid));
     }
 
+    #[tokio::test]
+    async fn get_selector_token_pod_uid_error() {
+        let mut node_attestation = init_selector_test().await;
+
+        let pod = get_pods();
+        let mut token_review_status = get_token_review_status();
+        if let Some(extra) = token_review_status
+            .user
+            .as_mut()
+            .and_then(|user| user.extra.as_mut())
+        {
+            extra.remove(POD_UID_EXTRA).unwrap();
+        }
+
+        node_attestation.client.queue_response(pod).await;
+
+        let error = node_attestation
+            .get_selector_info(token_review_status)
+            .await
+            .unwrap_err();
+
+        assert_matches!(error, Error::MissingField(MissingField::TokenPodUid));
+    }
+
+    #[tokio::test]
+    async fn get_selector_token_not_bound_to_pod_error() {
+        let mut node_attestation = init_selector_test().await;
+
+        // The pod was deleted and created again with the same name.
+        let mut pod = get_pods();
+        pod.metadata.uid = Some("0b8f4f5e-2c3a-4d8e-9a3e-5f0c1d2e3f4a".to_string());
+        let token_review_status = get_token_review_status();
+
+        node_attestation.client.queue_response(pod).await;
+
+        let error = node_attestation
+            .get_selector_info(token_review_status)
+            .await
+            .unwrap_err();
+
+        assert_matches!(error, Error::TokenNotBoundToPod(_));
+    }
+
     #[tokio::test]
     async fn review_token_test_happy_path() {
         let mut node_attestation = init_selector_test().await;
@@ -750,4 +896,146 @@ mod tests {
 
         assert_matches!(error, Error::InvalidToken(_));
     }
+
+    // Unsigned token with the given expiry, the signature is checked by the mocked API server.
+    fn jwt_token(exp: u64) -> String {
+        let payload = base64::encode_config(
+            serde_json::json!({ "exp": exp }).to_string(),
+            base64::URL_SAFE_NO_PAD,
+        );
+
+        format!("eyJhbGciOiJSUzI1NiJ9.{}.signature", payload)
+    }
+
+    async fn queue_attestation(node_attestation: &mut NodeAttestation) {
+        node_attestation
+            .client
+            .queue_response(get_token_review())
+            .await;
+        node_attestation.client.queue_response(get_pods()).await;
+        node_attestation.client.queue_response(get_nodes()).await;
+    }
+
+    #[tokio::test]
+    async fn auth_agent_token_replayed_error() {
+        let mut node_attestation = init_selector_test().await;
+        let token = jwt_token(get_epoch_time() + 600);
+
+        // Without replay checks, tokens can be presented again.
+        queue_attestation(&mut node_attestation).await;
+        node_attestation.auth_agent(&token).await.unwrap();
+        queue_attestation(&mut node_attestation).await;
+        node_attestation.auth_agent(&token).await.unwrap();
+
+        node_attestation.reject_token_replays = true;
+        queue_attestation(&mut node_attestation).await;
+        node_attestation.auth_agent(&token).await.unwrap();
+        queue_attestation(&mut node_attestation).await;
+        let error = node_attestation.auth_agent(&token).await.unwrap_err();
+
+        assert_matches!(error, Error::TokenReplayed);
+    }
+
+    #[tokio::test]
+    async fn auth_agent_failed_token_not_used() {
+        let mut node_attestation = init_selector_test().await;
+        node_attestation.reject_token_replays = true;
+        let token = jwt_token(get_epoch_time() + 600);
+
+        // The service account of the agent is not allowed yet.
+        let mut pod = get_pods();
+        if let Some(spec) = pod.spec.as_mut() {
+            spec.service_account_name = Some("other".to_string());
+        }
+        node_attestation
+            .client
+            .queue_response(get_token_review())
+            .await;
+        node_attestation.client.queue_response(pod).await;
+        node_attestation.client.queue_response(get_nodes()).await;
+        let error = node_attestation.auth_agent(&token).await.unwrap_err();
+        assert_matches!(error, Error::ServiceAccountNotAllowed(_));
+
+        queue_attestation(&mut node_attestation).await;
+        node_attestation.auth_agent(&token).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn auth_agent_token_without_expiry_error() {
+        let mut node_attestation = init_selector_test().await;
+        node_attestation.reject_token_replays = true;
+
+        queue_attestation(&mut node_attestation).await;
+        let error = node_attestation
+            .auth_agent("dummy token")
+            .await
+            .unwrap_err();
+
+        assert_matches!(error, Error::MissingField(MissingField::TokenExpiry));
+    }
+
+    #[tokio::test]
+    async fn use_token_expiry() {
+        let node_attestation = init_selector_test().await;
+
+        node_attestation
+            .use_token("token".to_string(), 160, 100)
+            .unwrap();
+        let error = node_attestation
+            .use_token("token".to_string(), 160, 159)
+            .unwrap_err();
+        assert_matches!(error, Error::TokenReplayed);
+        node_attestation
+            .use_token("other token".to_string(), 190, 130)
+            .unwrap();
+        node_attestation
+            .use_token("short token".to_string(), 140, 130)
+            .unwrap();
+
+        // Each token is forgotten once it expired, whatever the order they were used in.
+        node_attestation
+            .use_token("short token".to_string(), 200, 140)
+            .unwrap();
+        node_attestation
+            .use_token("token".to_string(), 220, 160)
+            .unwrap();
+        let error = node_attestation
+            .use_token("other token".to_string(), 190, 160)
+            .unwrap_err();
+        assert_matches!(error, Error::TokenReplayed);
+        assert_eq!(node_attestation.used_tokens.lock().unwrap().ids.len(), 3);
+    }
+
+    #[test]
+    fn token_expiry_from_payload() {
+        assert_eq!(token_expiry(&jwt_token(1234)).unwrap(), 1234);
+
+        let error = token_expiry("dummy token").unwrap_err();
+        assert_matches!(error, Error::MissingField(MissingField::TokenExpiry));
+    }
+
+    #[test]
+    fn token_id_from_credential_id() {
+        let mut token_review_status = get_token_review_status();
+        assert_eq!(
+            token_id(&token_review_status, "token"),
+            token_id(&token_review_status, "token")
+        );
+        assert_ne!(
+            token_id(&token_review_status, "token"),
+            token_id(&token_review_status, "other token")
+        );
+
+        if let Some(extra) = token_review_status
+            .user
+            .as_mut()
+            .and_then(|user| user.extra.as_mut())
+        {
+            extra.insert(
+                CREDENTIAL_ID_EXTRA.to_string(),
+                vec!["JTI=2f4b2c6e".to_string()],
+            );
+        }
+        assert_eq!(token_id(&token_review_status, "token"), "JTI=2f4b2c6e");
+    }
 }
@@ -0,0 +1,170 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Checks of the certificate chains sent by the agents in their attestation tokens.
+
+use openssl::{
+    error::ErrorStack,
+    hash::MessageDigest,
+    nid::Nid,
+    sign::Verifier,
+    stack::Stack,
+    x509::{store::X509StoreBuilder, X509StoreContext, X509},
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Unable to read CA certificates {0}: {1}")]
+    ReadingCACerts(String, std::io::Error),
+    #[error("Invalid base64 value {0}")]
+    InvalidBase64(base64::DecodeError),
+    #[error("Empty certificate chain")]
+    EmptyCertificateChain,
+    #[error("Certificate not trusted: {0}")]
+    CertificateNotTrusted(String),
+    #[error("Invalid signature of the attestation token")]
+    InvalidSignature,
+    #[error("Cryptographic operation failed {0}")]
+    Crypto(ErrorStack),
+}
+
+// Returns the first certificate of the chain, once checked that the chain leads to one of the CA
+// certificates of the PEM file.
+pub(crate) fn verify_chain(
+    ca_cert_path: &str,
+    certificate_chain: &[String],
+) -> Result<X509, Error> {
+    let ca_certs = std::fs::read(ca_cert_path)
+        .map_err(|err| Error::ReadingCACerts(ca_cert_path.to_string(), err))?;
+
+    let mut store = X509StoreBuilder::new().map_err(Error::Crypto)?;
+    for ca_cert in X509::stack_from_pem(&ca_certs).map_err(Error::Crypto)? {
+        store.add_cert(ca_cert).map_err(Error::Crypto)?;
+    }
+    let store = store.build();
+
+    let mut certs = certificate_chain.iter().map(|cert| {
+        let der = base64::decode(cert).map_err(Error::InvalidBase64)?;
+        X509::from_der(&der).map_err(Error::Crypto)
+    });
+    let cert = certs.next().ok_or(Error::EmptyCertificateChain)??;
+    let mut intermediates = Stack::new().map_err(Error::Crypto)?;
+    for intermediate in certs {
+        intermediates.push(intermediate?).map_err(Error::Crypto)?;
+    }
+
+    let mut context = X509StoreContext::new().map_err(Error::Crypto)?;
+    let error = context
+        .init(&store, &cert, &intermediates, |context| {
+            context.verify_cert()?;
+            Ok(context.error())
+        })
+        .map_err(Error::Crypto)?;
+    if error.as_raw() != 0 {
+        return Err(Error::CertificateNotTrusted(
+            error.error_string().to_string(),
+        ));
+    }
+
+    Ok(cert)
+}
+
+pub(crate) fn verify_signature(cert: &X509, data: &[u8], signature: &str) -> Result<(), Error> {
+    let signature = base64::decode(signature).map_err(Error::InvalidBase64)?;
+    let public_key = cert.public_key().map_err(Error::Crypto)?;
+    let mut verifier =
+        Verifier::new(MessageDigest::sha256(), &public_key).map_err(Error::Crypto)?;
+    verifier.update(data).map_err(Error::Crypto)?;
+
+    // A signature that does not parse is as invalid as one that does not match.
+    if verifier.verify(&signature).unwrap_or(false) {
+        Ok(())
+    } else {
+        Err(Error::InvalidSignature)
+    }
+}
+
+pub(crate) fn common_name(cert: &X509) -> Option<String> {
+    cert.subject_name()
+        .entries_by_nid(Nid::COMMONNAME)
+        .next()
+        .and_then(|entry| entry.data().as_utf8().ok())
+        .map(|common_name| common_name.to_string())
+}
+
+// Certificates for the tests of the attestations.
+#[cfg(test)]
+pub(crate) mod test_certs {
+    use openssl::{
+        asn1::Asn1Time,
+        bn::BigNum,
+        ec::{EcGroup, EcKey},
+        hash::MessageDigest,
+        nid::Nid,
+        pkey::{PKey, Private},
+        x509::{extension::SubjectAlternativeName, X509Name, X509NameBuilder, X509},
+    };
+
+    fn name(common_name: &str) -> X509Name {
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, common_name)
+            .unwrap();
+        name.append_entry_by_nid(Nid::ORGANIZATIONNAME, "Contoso")
+            .unwrap();
+        name.build()
+    }
+
+    pub(crate) fn key() -> PKey<Private> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    // Self-signed without issuer. The SANs are DNS names, or URIs when they have a scheme.
+    pub(crate) fn certificate(
+        common_name: &str,
+        key: &PKey<Private>,
+        issuer: Option<(&X509, &PKey<Private>)>,
+        sans: &[&str],
+    ) -> X509 {
+        let mut builder = X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        let serial_number = BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap();
+        builder.set_serial_number(&serial_number).unwrap();
+        builder.set_subject_name(&name(common_name)).unwrap();
+        builder.set_pubkey(key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+
+        if !sans.is_empty() {
+            let mut subject_alt_name = SubjectAlternativeName::new();
+            for san in sans {
+                if san.contains("://") {
+                    subject_alt_name.uri(san);
+                } else {
+                    subject_alt_name.dns(san);
+                }
+            }
+            let subject_alt_name = subject_alt_name
+                .build(&builder.x509v3_context(issuer.map(|(issuer, _)| &**issuer), None))
+                .unwrap();
+            builder.append_extension(subject_alt_name).unwrap();
+        }
+
+        match issuer {
+            Some((issuer, issuer_key)) => {
+                builder.set_issuer_name(issuer.subject_name()).unwrap();
+                builder.sign(issuer_key, MessageDigest::sha256()).unwrap();
+            }
+            None => {
+                builder.set_issuer_name(&name(common_name)).unwrap();
+                builder.sign(key, MessageDigest::sha256()).unwrap();
+            }
+        }
+
+        builder.build()
+    }
+}
@@ -0,0 +1,15 @@
+// Copyright (c) Microsoft. All rights reserved.
+use openssl::error::ErrorStack;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Invalid x509pop attestation token {0}")]
+    InvalidToken(serde_json::Error),
+    #[error("Unknown, expired or already used nonce")]
+    UnknownNonce,
+    #[error("Invalid agent certificate {0}")]
+    Certificate(crate::x509::Error),
+    #[error("Cryptographic operation failed {0}")]
+    Crypto(ErrorStack),
+}
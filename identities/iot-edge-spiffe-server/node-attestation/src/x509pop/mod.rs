@@ -0,0 +1,390 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Attestation of the agents with a pre-provisioned certificate. The agent gets a nonce from the server and
+// signs it with the private key of its certificate, so a token cannot be replayed. The nonces are not stored:
+// a nonce carries its expiry and is authenticated with a key of the replica, only the nonces already used are
+// kept in memory until they expire. The agent must send its token to the replica that issued the nonce.
+
+pub mod error;
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Mutex,
+};
+
+use core_objects::{build_selector_string, get_epoch_time, NodeSelectorType};
+use log::{debug, info};
+use openssl::{
+    hash::MessageDigest,
+    memcmp,
+    pkey::{PKey, Private},
+    sign::Signer,
+    x509::X509,
+};
+use server_agent_api::{
+    get_attestation_nonce,
+    x509pop_attestation::{signed_data, Token},
+};
+use server_config::NodeAttestationConfigX509Pop;
+
+use crate::{x509, AgentAttributes, NodeAttestation as NodeAttestationTrait};
+
+use error::Error;
+
+const NONCE_KEY_BYTES: usize = 32;
+const NONCE_RANDOM_BYTES: usize = 16;
+const NONCE_EXPIRY_BYTES: usize = 8;
+const NONCE_MAC_BYTES: usize = 32;
+
+pub struct NodeAttestation {
+    ca_bundle_path: String,
+    nonce_ttl_secs: u64,
+    // HMAC key of the nonces issued by this replica.
+    nonce_key: PKey<Private>,
+    // Expiry of the nonces already used. Only attested agents use a nonce, so it does not grow with the nonce
+    // requests, which need no authentication.
+    used_nonces: Mutex<HashMap<String, u64>>,
+}
+
+impl NodeAttestation {
+    /// # Panics
+    ///
+    /// Panics if OpenSSL fails to generate the key of the nonces.
+    #[must_use]
+    pub fn new(config: &NodeAttestationConfigX509Pop) -> Self {
+        let mut nonce_key = [0; NONCE_KEY_BYTES];
+        openssl::rand::rand_bytes(&mut nonce_key).expect("Could not generate the nonce key");
+        let nonce_key = PKey::hmac(&nonce_key).expect("Could not create the nonce key");
+
+        NodeAttestation {
+            ca_bundle_path: config.ca_bundle_path.clone(),
+            nonce_ttl_secs: config.nonce_ttl_secs,
+            nonce_key,
+            used_nonces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // The nonce is the random bytes, followed by the expiry and the HMAC of both.
+    fn issue_nonce(&self, now: u64) -> Result<get_attestation_nonce::Response, Error> {
+        let expires_at = now + self.nonce_ttl_secs;
+
+        let mut nonce = vec![0; NONCE_RANDOM_BYTES];
+        openssl::rand::rand_bytes(&mut nonce).map_err(Error::Crypto)?;
+        nonce.extend_from_slice(&expires_at.to_be_bytes());
+        let mac = self.nonce_mac(&nonce)?;
+        nonce.extend_from_slice(&mac);
+
+        Ok(get_attestation_nonce::Response {
+            nonce: base64::encode(nonce),
+            expires_at,
+        })
+    }
+
+    fn nonce_mac(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut signer =
+            Signer::new(MessageDigest::sha256(), &self.nonce_key).map_err(Error::Crypto)?;
+        signer.update(data).map_err(Error::Crypto)?;
+
+        signer.sign_to_vec().map_err(Error::Crypto)
+    }
+
+    // Returns the expiry of a nonce issued by this replica and not expired yet.
+    fn verify_nonce(&self, nonce: &str, now: u64) -> Result<u64, Error> {
+        let nonce = base64::decode(nonce).map_err(|_err| Error::UnknownNonce)?;
+        if nonce.len() != NONCE_RANDOM_BYTES + NONCE_EXPIRY_BYTES + NONCE_MAC_BYTES {
+            return Err(Error::UnknownNonce);
+        }
+
+        let (data, mac) = nonce.split_at(NONCE_RANDOM_BYTES + NONCE_EXPIRY_BYTES);
+        if !memcmp::eq(&self.nonce_mac(data)?, mac) {
+            return Err(Error::UnknownNonce);
+        }
+
+        let expires_at = data[NONCE_RANDOM_BYTES..]
+            .try_into()
+            .map(u64::from_be_bytes)
+            .map_err(|_err| Error::UnknownNonce)?;
+        if expires_at <= now {
+            return Err(Error::UnknownNonce);
+        }
+
+        Ok(expires_at)
+    }
+
+    // A nonce is used once, the used nonces are forgotten when they expire.
+    fn use_nonce(&self, nonce: &str, expires_at: u64, now: u64) -> Result<(), Error> {
+        let mut used_nonces = self.used_nonces.lock().unwrap();
+        used_nonces.retain(|_, expires_at| *expires_at > now);
+
+        if used_nonces.insert(nonce.to_string(), expires_at).is_some() {
+            return Err(Error::UnknownNonce);
+        }
+
+        Ok(())
+    }
+
+    fn auth_agent(&self, token: &str) -> Result<AgentAttributes, Error> {
+        let token: Token = serde_json::from_str(token).map_err(Error::InvalidToken)?;
+        let now = get_epoch_time();
+        let expires_at = self.verify_nonce(&token.nonce, now)?;
+
+        let cert = x509::verify_chain(&self.ca_bundle_path, &token.certificate_chain)
+            .map_err(Error::Certificate)?;
+        x509::verify_signature(
+            &cert,
+            signed_data(&token.nonce).as_bytes(),
+            &token.signature,
+        )
+        .map_err(Error::Certificate)?;
+        // Only once the token is verified, so the used nonces are bounded by the attested agents.
+        self.use_nonce(&token.nonce, expires_at, now)?;
+
+        let selectors = selectors(&cert)?;
+
+        info!(
+            "IoTEdge SPIFFE Agent with certificate {} was attested successfully",
+            x509::common_name(&cert).unwrap_or_default()
+        );
+        debug!("Found the following selectors for workload {:?}", selectors);
+
+        Ok(AgentAttributes { selectors })
+    }
+}
+
+fn selectors(cert: &X509) -> Result<BTreeSet<String>, Error> {
+    let mut selectors = BTreeSet::new();
+
+    if let Some(common_name) = x509::common_name(cert) {
+        selectors.insert(build_selector_string(
+            &NodeSelectorType::X509PopSubjectCN,
+            common_name,
+        ));
+    }
+
+    // Every attribute of the subject, by its short name, e.g. "O:Contoso".
+    for entry in cert.subject_name().entries() {
+        let name = entry.object().nid().short_name();
+        let value = entry.data().as_utf8();
+        if let (Ok(name), Ok(value)) = (name, value) {
+            selectors.insert(build_selector_string(
+                &NodeSelectorType::X509PopSubject,
+                format!("{}:{}", name, value),
+            ));
+        }
+    }
+
+    for san in cert.subject_alt_names().iter().flatten() {
+        if let Some(dns_name) = san.dnsname() {
+            selectors.insert(build_selector_string(
+                &NodeSelectorType::X509PopSanDNS,
+                dns_name,
+            ));
+        }
+        if let Some(uri) = san.uri() {
+            selectors.insert(build_selector_string(&NodeSelectorType::X509PopSanURI, uri));
+        }
+    }
+
+    let fingerprint = cert
+        .digest(MessageDigest::sha256())
+        .map_err(Error::Crypto)?
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    selectors.insert(build_selector_string(
+        &NodeSelectorType::X509PopFingerprint,
+        fingerprint,
+    ));
+
+    Ok(selectors)
+}
+
+#[async_trait::async_trait]
+impl NodeAttestationTrait for NodeAttestation {
+    async fn attest_agent(
+        &self,
+        token: &str,
+    ) -> Result<AgentAttributes, Box<dyn std::error::Error + Send>> {
+        self.auth_agent(token).map_err(|err| Box::new(err) as _)
+    }
+
+    async fn create_nonce(
+        &self,
+    ) -> Result<Option<get_attestation_nonce::Response>, Box<dyn std::error::Error + Send>> {
+        self.issue_nonce(get_epoch_time())
+            .map(Some)
+            .map_err(|err| Box::new(err) as _)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use matches::assert_matches;
+    use openssl::{
+        pkey::{PKey, Private},
+        sign::Signer,
+    };
+
+    use super::*;
+    use crate::x509::test_certs::{certificate, key};
+
+    // Writes the CA bundle and returns the attestation of the agents with a certificate it issued.
+    fn init(base_path: &Path) -> (NodeAttestation, X509, PKey<Private>) {
+        let ca_key = key();
+        let ca = certificate("agent-ca", &ca_key, None, &[]);
+        let ca_bundle_path = base_path.join("ca.pem");
+        fs::write(&ca_bundle_path, ca.to_pem().unwrap()).unwrap();
+
+        let agent_key = key();
+        let agent_cert = certificate(
+            "agent",
+            &agent_key,
+            Some((&ca, &ca_key)),
+            &["agent.contoso.com", "spiffe://iotedge/agent"],
+        );
+
+        let node_attestation = NodeAttestation::new(&NodeAttestationConfigX509Pop {
+            ca_bundle_path: ca_bundle_path.to_str().unwrap().to_string(),
+            nonce_ttl_secs: 60,
+        });
+
+        (node_attestation, agent_cert, agent_key)
+    }
+
+    fn token(nonce: &str, cert: &X509, key: &PKey<Private>) -> String {
+        let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+        signer.update(signed_data(nonce).as_bytes()).unwrap();
+
+        serde_json::to_string(&Token {
+            nonce: nonce.to_string(),
+            certificate_chain: vec![base64::encode(cert.to_der().unwrap())],
+            signature: base64::encode(signer.sign_to_vec().unwrap()),
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn attest_agent_happy_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let (node_attestation, cert, key) = init(dir.path());
+
+        let nonce = node_attestation.create_nonce().await.unwrap().unwrap();
+        let attributes = node_attestation
+            .attest_agent(&token(&nonce.nonce, &cert, &key))
+            .await
+            .unwrap();
+
+        let selectors = attributes.selectors;
+        assert!(selectors.contains("X509POPSUBJECTCN:agent"));
+        assert!(selectors.contains("X509POPSUBJECT:CN:agent"));
+        assert!(selectors.contains("X509POPSUBJECT:O:Contoso"));
+        assert!(selectors.contains("X509POPSANDNS:agent.contoso.com"));
+        assert!(selectors.contains("X509POPSANURI:spiffe://iotedge/agent"));
+        assert!(selectors
+            .iter()
+            .any(|selector| selector.starts_with("X509POPFINGERPRINT:")));
+    }
+
+    #[tokio::test]
+    async fn attest_agent_nonce_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let (node_attestation, cert, key) = init(dir.path());
+
+        // Not issued by the server.
+        let error = node_attestation
+            .auth_agent(&token("nonce", &cert, &key))
+            .unwrap_err();
+        assert_matches!(error, Error::UnknownNonce);
+
+        // Already used.
+        let nonce = node_attestation.issue_nonce(get_epoch_time()).unwrap();
+        let token = token(&nonce.nonce, &cert, &key);
+        node_attestation.auth_agent(&token).unwrap();
+        let error = node_attestation.auth_agent(&token).unwrap_err();
+        assert_matches!(error, Error::UnknownNonce);
+
+        // Expired.
+        let nonce = node_attestation.issue_nonce(0).unwrap();
+        let error = node_attestation
+            .verify_nonce(&nonce.nonce, get_epoch_time())
+            .unwrap_err();
+        assert_matches!(error, Error::UnknownNonce);
+
+        // Issued by another replica.
+        let (other_replica, _cert, _key) = init(dir.path());
+        let nonce = other_replica.issue_nonce(get_epoch_time()).unwrap();
+        let error = node_attestation
+            .auth_agent(&token(&nonce.nonce, &cert, &key))
+            .unwrap_err();
+        assert_matches!(error, Error::UnknownNonce);
+
+        // Expiry changed by the agent.
+        let nonce = node_attestation.issue_nonce(0).unwrap();
+        let mut forged_nonce = base64::decode(&nonce.nonce).unwrap();
+        forged_nonce[NONCE_RANDOM_BYTES..NONCE_RANDOM_BYTES + NONCE_EXPIRY_BYTES]
+            .copy_from_slice(&u64::MAX.to_be_bytes());
+        let error = node_attestation
+            .auth_agent(&token(&base64::encode(forged_nonce), &cert, &key))
+            .unwrap_err();
+        assert_matches!(error, Error::UnknownNonce);
+    }
+
+    #[tokio::test]
+    async fn attest_agent_certificate_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let (node_attestation, cert, _key) = init(dir.path());
+
+        // Signed with another key than the key of the certificate.
+        let nonce = node_attestation.issue_nonce(get_epoch_time()).unwrap();
+        let error = node_attestation
+            .auth_agent(&token(&nonce.nonce, &cert, &key()))
+            .unwrap_err();
+        assert_matches!(error, Error::Certificate(x509::Error::InvalidSignature));
+
+        // Issued by another CA.
+        let other_key = key();
+        let other_cert = certificate("agent", &other_key, None, &[]);
+        let nonce = node_attestation.issue_nonce(get_epoch_time()).unwrap();
+        let error = node_attestation
+            .auth_agent(&token(&nonce.nonce, &other_cert, &other_key))
+            .unwrap_err();
+        assert_matches!(
+            error,
+            Error::Certificate(x509::Error::CertificateNotTrusted(_))
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_attestation_does_not_use_nonce() {
+        let dir = tempfile::tempdir().unwrap();
+        let (node_attestation, cert, agent_key) = init(dir.path());
+
+        // Signed with another key than the key of the certificate.
+        let nonce = node_attestation.issue_nonce(get_epoch_time()).unwrap();
+        node_attestation
+            .auth_agent(&token(&nonce.nonce, &cert, &key()))
+            .unwrap_err();
+        assert!(node_attestation.used_nonces.lock().unwrap().is_empty());
+
+        node_attestation
+            .auth_agent(&token(&nonce.nonce, &cert, &agent_key))
+            .unwrap();
+    }
+
+    #[test]
+    fn used_nonces_expire() {
+        let dir = tempfile::tempdir().unwrap();
+        let (node_attestation, _cert, _key) = init(dir.path());
+
+        node_attestation.use_nonce("nonce1", 200, 100).unwrap();
+        let error = node_attestation.use_nonce("nonce1", 200, 150).unwrap_err();
+        assert_matches!(error, Error::UnknownNonce);
+
+        node_attestation.use_nonce("nonce2", 300, 200).unwrap();
+        let used_nonces = node_attestation.used_nonces.lock().unwrap();
+        assert_eq!(used_nonces.len(), 1);
+        assert!(used_nonces.contains_key("nonce2"));
+    }
+}
@@ -0,0 +1,11 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Cannot bind the OIDC discovery endpoint: {0}")]
+    Bind(std::io::Error),
+    #[error("Invalid OIDC discovery endpoint TLS configuration: {0}")]
+    Tls(openssl::error::ErrorStack),
+}
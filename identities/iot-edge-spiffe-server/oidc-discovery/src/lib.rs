@@ -0,0 +1,299 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::default_trait_access,
+    clippy::let_unit_value,
+    clippy::missing_errors_doc,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+// OpenID Connect discovery of the JWT-SVIDs, like the OIDC discovery provider of SPIRE. The discovery document at
+// /.well-known/openid-configuration points to the JWKS at /keys, the signing keys of the trust bundle. Services
+// validating OIDC tokens, e.g. Azure AD workload identity federation, then accept the JWT-SVIDs whose iss claim
+// is the issuer. Both are public, callers are not authenticated.
+
+pub mod error;
+
+use std::{convert::Infallible, sync::Arc};
+
+use core_objects::{Crv, KeyType, Kty, TrustBundle};
+use hyper::{
+    header::{HeaderValue, CONTENT_TYPE},
+    server::conn::Http,
+    service::service_fn,
+    Body, Method, Request, Response, StatusCode,
+};
+use log::{error, info};
+use openssl::{error::ErrorStack, ssl::SslAcceptor};
+use serde::Serialize;
+use server_config::{OidcDiscoveryConfig, OidcDiscoveryTlsConfig};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    task::JoinHandle,
+};
+use trust_bundle_builder::TrustBundleBuilder;
+
+use crate::error::Error;
+
+const DISCOVERY_PATH: &str = "/.well-known/openid-configuration";
+const JWKS_PATH: &str = "/keys";
+const JSON_CONTENT_TYPE: &str = "application/json";
+const LISTENER_NAME: &str = "OIDC discovery endpoint";
+
+struct Provider {
+    issuer: String,
+    key_type: KeyType,
+    trust_bundle_builder: Arc<TrustBundleBuilder>,
+}
+
+// Only the members OIDC clients look at. The JWT-SVIDs are no ID tokens, there is no authorization endpoint.
+#[derive(Serialize)]
+struct DiscoveryDocument<'a> {
+    issuer: &'a str,
+    jwks_uri: String,
+    authorization_endpoint: &'a str,
+    response_types_supported: [&'a str; 1],
+    subject_types_supported: [&'a str; 1],
+    id_token_signing_alg_values_supported: [KeyType; 1],
+}
+
+#[derive(Serialize)]
+struct Jwks {
+    keys: Vec<OidcJwk>,
+}
+
+// Signature keys of RFC 7517, the members in base64url like in the trust bundles.
+#[derive(Serialize)]
+struct OidcJwk {
+    kty: Kty,
+    kid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crv: Option<Crv>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    x: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    y: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    n: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    e: String,
+    #[serde(rename = "use")]
+    key_use: &'static str,
+    alg: KeyType,
+}
+
+pub async fn start(
+    config: &OidcDiscoveryConfig,
+    issuer: &str,
+    key_type: KeyType,
+    trust_bundle_builder: Arc<TrustBundleBuilder>,
+) -> Result<JoinHandle<()>, Error> {
+    let acceptor = config
+        .tls
+        .as_ref()
+        .map(acceptor)
+        .transpose()
+        .map_err(Error::Tls)?;
+    let listener = TcpListener::bind((config.bind_address.as_str(), config.bind_port))
+        .await
+        .map_err(Error::Bind)?;
+
+    info!(
+        "Starting OIDC discovery endpoint of {} on {}:{}",
+        issuer, config.bind_address, config.bind_port
+    );
+    let provider = Arc::new(Provider {
+        issuer: issuer.to_string(),
+        key_type,
+        trust_bundle_builder,
+    });
+
+    Ok(tokio::spawn(serve(listener, acceptor, provider)))
+}
+
+fn acceptor(tls: &OidcDiscoveryTlsConfig) -> Result<SslAcceptor, ErrorStack> {
+    tls_listener::acceptor(&tls.cert_path, &tls.key_path, |_| Ok(()))
+}
+
+async fn serve(listener: TcpListener, acceptor: Option<SslAcceptor>, provider: Arc<Provider>) {
+    let acceptor = match acceptor {
+        Some(acceptor) => acceptor,
+        None => {
+            serve_plain(listener, provider).await;
+            return;
+        }
+    };
+
+    tls_listener::serve(listener, acceptor, LISTENER_NAME, move |stream, _| {
+        serve_http(stream, provider.clone())
+    })
+    .await;
+}
+
+// Without TLS, e.g. behind an ingress that terminates it.
+async fn serve_plain(listener: TcpListener, provider: Arc<Provider>) {
+    loop {
+        let (stream, _) = tls_listener::accept(&listener, LISTENER_NAME).await;
+
+        tokio::spawn(serve_http(stream, provider.clone()));
+    }
+}
+
+async fn serve_http<S>(stream: S, provider: Arc<Provider>)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = service_fn(move |request| {
+        let provider = provider.clone();
+
+        async move { Ok::<_, Infallible>(handle(&request, &provider).await) }
+    });
+
+    if let Err(err) = Http::new()
+        .http1_only(true)
+        .serve_connection(stream, service)
+        .await
+    {
+        error!("Error serving the OIDC discovery endpoint: {}", err);
+    }
+}
+
+async fn handle(request: &Request<Body>, provider: &Provider) -> Response<Body> {
+    if request.method() != Method::GET {
+        return status(StatusCode::NOT_FOUND);
+    }
+
+    let body = match request.uri().path() {
+        DISCOVERY_PATH => serde_json::to_vec(&discovery_document(provider)),
+        JWKS_PATH => match provider
+            .trust_bundle_builder
+            .build_trust_bundle(true, false)
+            .await
+        {
+            Ok(trust_bundle) => serde_json::to_vec(&jwks(&trust_bundle, provider.key_type)),
+            Err(err) => {
+                error!("Cannot build the trust bundle of the JWKS: {}", err);
+                return status(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        },
+        _ => return status(StatusCode::NOT_FOUND),
+    };
+    let body = match body {
+        Ok(body) => body,
+        Err(err) => {
+            error!("Cannot serialize the OIDC discovery response: {}", err);
+            return status(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let mut response = Response::new(Body::from(body));
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static(JSON_CONTENT_TYPE));
+
+    response
+}
+
+fn discovery_document(provider: &Provider) -> DiscoveryDocument<'_> {
+    DiscoveryDocument {
+        issuer: &provider.issuer,
+        jwks_uri: format!("{}{}", provider.issuer.trim_end_matches('/'), JWKS_PATH),
+        authorization_endpoint: "",
+        response_types_supported: ["id_token"],
+        subject_types_supported: ["public"],
+        id_token_signing_alg_values_supported: [provider.key_type],
+    }
+}
+
+// The trust bundle holds the keys of the configured key type only.
+fn jwks(trust_bundle: &TrustBundle, key_type: KeyType) -> Jwks {
+    let keys = trust_bundle
+        .jwt_key_set
+        .keys
+        .iter()
+        .map(|jwk| OidcJwk {
+            kty: jwk.kty.clone(),
+            kid: jwk.kid.clone(),
+            crv: jwk.crv.clone(),
+            x: jwk.x.clone(),
+            y: jwk.y.clone(),
+            n: jwk.n.clone(),
+            e: jwk.e.clone(),
+            key_use: "sig",
+            alg: key_type,
+        })
+        .collect();
+
+    Jwks { keys }
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use catalog::inmemory;
+    use core_objects::CONFIG_DEFAULT_PATH;
+    use key_manager::KeyManager;
+    use server_config::Config;
+
+    use super::*;
+
+    async fn get(provider: &Provider, path: &str) -> (StatusCode, serde_json::Value) {
+        let request = Request::get(path).body(Body::empty()).unwrap();
+        let response = handle(&request, provider).await;
+        let status = response.status();
+        if status != StatusCode::OK {
+            return (status, serde_json::Value::Null);
+        }
+        assert_eq!(response.headers()[CONTENT_TYPE], JSON_CONTENT_TYPE);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn handle_discovery_requests() {
+        let config = Config::load_config(CONFIG_DEFAULT_PATH).unwrap();
+        let catalog = Arc::new(inmemory::Catalog::new());
+        let key_store = Arc::new(key_store::inmemory::KeyStore::new());
+        let key_manager = KeyManager::new(&config, catalog.clone(), key_store, 0)
+            .await
+            .unwrap();
+        let provider = Provider {
+            issuer: "https://oidc.contoso.com/".to_string(),
+            key_type: config.jwt.key_type,
+            trust_bundle_builder: TrustBundleBuilder::new(&config, catalog),
+        };
+
+        let (status, document) = get(&provider, DISCOVERY_PATH).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(document["issuer"], "https://oidc.contoso.com/");
+        assert_eq!(document["jwks_uri"], "https://oidc.contoso.com/keys");
+        assert_eq!(
+            document["id_token_signing_alg_values_supported"][0],
+            "ES256"
+        );
+
+        let (status, jwks) = get(&provider, JWKS_PATH).await;
+        assert_eq!(status, StatusCode::OK);
+        let slots = key_manager.slots.read().await;
+        let keys = jwks["keys"].as_array().unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0]["kid"], slots.current_jwt_key.kid.as_str());
+        assert_eq!(keys[0]["use"], "sig");
+        assert_eq!(keys[0]["alg"], "ES256");
+        assert!(!keys[0]["x"].as_str().unwrap().contains(['+', '/']));
+
+        let (status, _) = get(&provider, "/other").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
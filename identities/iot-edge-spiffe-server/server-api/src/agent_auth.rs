@@ -0,0 +1,155 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Authenticates the agents connecting over TLS before their request is handled. An agent sends its attestation
+// token as a bearer token, the selectors of the attested agent are passed to the route in the request extensions, so
+// the token of the body is not attested again. The nonces are needed to make some attestation tokens, and the trust
+// bundle only has public keys, their endpoints do not need a token. The request limits are applied first, so an
+// attestation is bound by the timeout of the request.
+
+use std::{
+    collections::BTreeSet,
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use http::{header, HeaderValue, Request, Response, StatusCode};
+use hyper::{service::Service, Body};
+use tokio::sync::Semaphore;
+
+use crate::{error::Error, http::uri, Api};
+
+const BEARER_PREFIX: &str = "Bearer ";
+// Attestations call the Kubernetes API or check certificate chains. The others wait for their turn, up to the
+// timeout of their request.
+const MAX_CONCURRENT_ATTESTATIONS: usize = 32;
+
+// Selectors of the agent authenticated by the token of the request.
+#[derive(Clone, Debug)]
+pub(crate) struct AttestedAgent(pub(crate) BTreeSet<String>);
+
+#[derive(Clone)]
+pub(crate) struct AgentAuthService<S> {
+    inner: S,
+    api: Api,
+    attestations: Arc<Semaphore>,
+}
+
+impl<S> AgentAuthService<S> {
+    pub(crate) fn new(inner: S, api: Api) -> Self {
+        AgentAuthService {
+            inner,
+            api,
+            attestations: Arc::new(Semaphore::new(MAX_CONCURRENT_ATTESTATIONS)),
+        }
+    }
+}
+
+impl<S> Service<Request<Body>> for AgentAuthService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+{
+    type Response = Response<Body>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<Body>, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let api = self.api.clone();
+        let attestations = self.attestations.clone();
+
+        // The service polled ready handles this request, the clone is kept for the next one.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            if req.uri().path() == uri::CREATE_WORKLOAD_JTWS {
+                let attestation_token = match bearer_token(&req) {
+                    Some(attestation_token) => attestation_token,
+                    None => {
+                        let message = "An attestation token is required";
+                        return Ok(error_response(StatusCode::UNAUTHORIZED, message));
+                    }
+                };
+
+                // The semaphore is never closed.
+                let _permit = attestations.acquire().await;
+                let agent_selectors = match api.attest_agent(&attestation_token).await {
+                    Ok(agent_selectors) => agent_selectors,
+                    Err(err) => return Ok(attestation_error_response(&err)),
+                };
+                req.extensions_mut().insert(AttestedAgent(agent_selectors));
+            }
+
+            inner.call(req).await
+        })
+    }
+}
+
+fn bearer_token(req: &Request<Body>) -> Option<String> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix(BEARER_PREFIX)?;
+
+    (!token.is_empty()).then(|| token.to_string())
+}
+
+fn attestation_error_response(err: &Error) -> Response<Body> {
+    log::warn!("Rejected agent request: {}", err);
+    let status_code = match err {
+        Error::AttestAgent(_) | Error::AgentBanned(_) => StatusCode::FORBIDDEN,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    error_response(status_code, &err.to_string())
+}
+
+fn error_response(status_code: StatusCode, message: &str) -> Response<Body> {
+    let body = serde_json::json!({ "message": message }).to_string();
+
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status_code;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(authorization: Option<&str>) -> Request<Body> {
+        let mut req = Request::builder().uri(uri::CREATE_WORKLOAD_JTWS);
+        if let Some(authorization) = authorization {
+            req = req.header(header::AUTHORIZATION, authorization);
+        }
+
+        req.body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn bearer_tokens() {
+        assert_eq!(
+            bearer_token(&request(Some("Bearer token"))),
+            Some("token".to_string())
+        );
+        assert_eq!(bearer_token(&request(None)), None);
+        assert_eq!(bearer_token(&request(Some("Bearer "))), None);
+        assert_eq!(bearer_token(&request(Some("Basic token"))), None);
+    }
+}
@@ -1,7 +1,18 @@
 // Copyright (c) Microsoft. All rights reserved.
 
-use core_objects::SPIFFE_ID_PREFIX;
-use server_agent_api::{create_workload_jwts, get_trust_bundle};
+use std::{collections::BTreeSet, time::Instant};
+
+use build_info::BuildInfo;
+use catalog::{AttestedAgents, SvidAudit};
+use core_objects::{
+    build_selector_string, get_epoch_time, AttestationConfig, IssuedSvid, KeyUse, NodeSelectorType,
+    SPIFFE_ID_PREFIX,
+};
+use openssl::pkey::{PKey, Public};
+use server_agent_api::{
+    create_workload_jwts::{self, DeniedIdentity},
+    get_attestation_nonce, get_trust_bundle,
+};
 use svid_factory::JWTSVIDParams;
 
 use crate::{error::Error, Api};
@@ -15,19 +26,96 @@ impl Api {
         // only create jwt svid for that specific spiffe id
         let spiffe_id_path = get_spiffe_id_path(&req.workload_spiffe_id, &self.trust_domain)?;
 
+        let agent_selectors = self.attest_agent(&req.attestation_token).await?;
+
+        self.issue_workload_jwts(req, spiffe_id_path, agent_selectors)
+            .await
+    }
+
+    // Agents with an attestation session are not attested again, their selectors are kept with the
+    // session.
+    pub(crate) async fn create_workload_jwts_for_agent(
+        &self,
+        req: create_workload_jwts::Request,
+        agent_selectors: BTreeSet<String>,
+    ) -> Result<create_workload_jwts::Response, Error> {
+        let spiffe_id_path = get_spiffe_id_path(&req.workload_spiffe_id, &self.trust_domain)?;
+        // The agent may have been banned since it opened its session.
+        self.check_agent_ban(&agent_selectors)?;
+
+        self.issue_workload_jwts(req, spiffe_id_path, agent_selectors)
+            .await
+    }
+
+    pub(crate) async fn attest_agent(
+        &self,
+        attestation_token: &str,
+    ) -> Result<BTreeSet<String>, Error> {
         let agent_attributes = self
             .node_attestation
-            .attest_agent(&req.attestation_token)
+            .attest_agent(attestation_token)
             .await
-            .map_err(Error::AttestAgent)?;
+            .map_err(|err| {
+                self.metrics.node_attestation_failures.inc();
+                Error::AttestAgent(err)
+            })?;
+        self.check_agent_ban(&agent_attributes.selectors)?;
+
+        Ok(agent_attributes.selectors)
+    }
+
+    fn check_agent_ban(&self, agent_selectors: &BTreeSet<String>) -> Result<(), Error> {
+        if let Some(ban) = self.agent_bans.banned(agent_selectors) {
+            log::warn!(
+                target: "audit",
+                ban_id = ban.id.as_str();
+                "Rejected agent {:?}: banned by ban {}",
+                agent_selectors,
+                ban.id
+            );
+            return Err(Error::AgentBanned(ban.id));
+        }
+
+        Ok(())
+    }
+
+    // Failing to record the agent does not fail its request.
+    pub(crate) async fn record_agent(&self, agent_selectors: &BTreeSet<String>, svid_expiry: u64) {
+        if let Err(err) = self
+            .attested_agents
+            .record(agent_selectors, svid_expiry, get_epoch_time())
+            .await
+        {
+            log::warn!("Could not record the attested agent: {}", err);
+        }
+    }
+
+    async fn issue_workload_jwts(
+        &self,
+        req: create_workload_jwts::Request,
+        spiffe_id_path: Option<String>,
+        attested_selectors: BTreeSet<String>,
+    ) -> Result<create_workload_jwts::Response, Error> {
+        // The build of the agent is not part of its identity, it is recorded with its attested selectors.
+        let mut agent_selectors = attested_selectors.clone();
+        if let (Some(agent_build_key), Some(agent_build)) =
+            (&self.agent_build_key, &req.agent_build)
+        {
+            agent_selectors.extend(agent_build_selectors(agent_build, agent_build_key));
+        }
 
         let entries = self
             .identity_matcher
-            .get_entry_id_from_selectors(&req.selectors, &agent_attributes.selectors)
+            .get_entry_id_from_selectors(&req.selectors, &agent_selectors)
             .await
             .map_err(Error::MatchIdentity)?;
 
-        let mut jwt_svids = Vec::new();
+        // Ties the logs of the request together.
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let mut jwt_svid_params = Vec::new();
+        let mut issued_entries = Vec::new();
+        let mut denied = Vec::new();
+        let mut federates_with = BTreeSet::new();
 
         for entry in entries {
             // If user is requesting for specific spiffe ID. Skip all unconcerned identities.
@@ -37,22 +125,130 @@ impl Api {
                 }
             }
 
-            let jwt_svid_params = JWTSVIDParams {
+            // The workload gets the bundles of the entries it matches, whether their JWT-SVIDs are issued or not.
+            federates_with.extend(entry.federates_with.iter().cloned());
+            if req.bundles_only {
+                continue;
+            }
+
+            if req.prefetch_only && !entry.prefetch {
+                continue;
+            }
+
+            if let Err(denial) =
+                self.issuance_policy
+                    .check(&entry, &req.audiences, jwt_svid_params.len())
+            {
+                let spiffe_id = format!(
+                    "{}{}/{}",
+                    SPIFFE_ID_PREFIX, self.trust_domain, entry.spiffe_id_path
+                );
+                log::warn!(
+                    target: "audit",
+                    request_id = request_id.as_str(),
+                    entry_id = entry.id.as_str(),
+                    spiffe_id = spiffe_id.as_str();
+                    "Denied JWT-SVID issuance for entry {} ({}): {}: {}",
+                    entry.id,
+                    spiffe_id,
+                    denial.reason,
+                    denial.detail
+                );
+                denied.push(DeniedIdentity {
+                    spiffe_id,
+                    reason: denial.reason,
+                });
+                continue;
+            }
+
+            jwt_svid_params.push(JWTSVIDParams {
                 spiffe_id_path: entry.spiffe_id_path.clone(),
                 audiences: req.audiences.clone(),
                 other_identities: entry.other_identities,
+                pod_uid: req.pod_uid.clone(),
+                ttl: entry.ttl,
+                custom_claims: entry.custom_claims.clone(),
+            });
+            let selectors = match entry.attestation_config {
+                AttestationConfig::Workload(attestation) => attestation.value,
+                AttestationConfig::Node(attestation) => attestation.value,
             };
+            issued_entries.push((entry.id, selectors));
+        }
 
-            let jwt_svid = self
-                .svid_factory
-                .create_jwt_svid(jwt_svid_params)
-                .await
-                .map_err(Error::CreateWorkloadJWT)?;
+        if req.bundles_only {
+            return Ok(create_workload_jwts::Response {
+                jwt_svids: Vec::new(),
+                denied: Vec::new(),
+                federates_with,
+            });
+        }
 
-            jwt_svids.push(jwt_svid);
+        // Signed together, the key is only looked up once for all the matched entries.
+        let signing_started = Instant::now();
+        let jwt_svids = self
+            .svid_factory
+            .create_jwt_svids(jwt_svid_params)
+            .await
+            .map_err(Error::CreateWorkloadJWT)?;
+        self.metrics
+            .signing_duration
+            .observe(signing_started.elapsed());
+        self.metrics.jwt_svids_issued.inc_by(jwt_svids.len() as u64);
+
+        // The JWT-SVIDs are in the order of their entries.
+        for (jwt_svid, (entry_id, _selectors)) in jwt_svids.iter().zip(&issued_entries) {
+            let spiffe_id = jwt_svid.spiffe_id.to_string();
+            log::debug!(
+                target: "audit",
+                request_id = request_id.as_str(),
+                entry_id = entry_id.as_str(),
+                spiffe_id = spiffe_id.as_str();
+                "Issued JWT-SVID for entry {} ({})",
+                entry_id,
+                spiffe_id
+            );
+        }
+        self.metrics.jwt_svids_denied.inc_by(denied.len() as u64);
+
+        if let Some(svid_audit) = &self.svid_audit {
+            let agent_id = AttestedAgents::agent_id(&attested_selectors);
+            let records = jwt_svids
+                .iter()
+                .zip(issued_entries)
+                .map(|(jwt_svid, (entry_id, selectors))| IssuedSvid {
+                    id: SvidAudit::record_id(jwt_svid.issued_at),
+                    svid_type: KeyUse::JWTSVID,
+                    entry_id,
+                    spiffe_id: jwt_svid.spiffe_id.clone(),
+                    audiences: req.audiences.clone(),
+                    selectors,
+                    agent_id: agent_id.clone(),
+                    agent_selectors: attested_selectors.clone(),
+                    issued_at: jwt_svid.issued_at,
+                    expiry: jwt_svid.expiry,
+                })
+                .collect();
+
+            // A JWT-SVID that is not in the audit log is not returned.
+            svid_audit
+                .record(records)
+                .await
+                .map_err(Error::RecordIssuedSvids)?;
         }
 
-        Ok(create_workload_jwts::Response { jwt_svids })
+        let svid_expiry = jwt_svids
+            .iter()
+            .map(|jwt_svid| jwt_svid.expiry)
+            .max()
+            .unwrap_or_default();
+        self.record_agent(&attested_selectors, svid_expiry).await;
+
+        Ok(create_workload_jwts::Response {
+            jwt_svids,
+            denied,
+            federates_with,
+        })
     }
 
     pub async fn get_trust_bundle(
@@ -64,9 +260,54 @@ impl Api {
             .build_trust_bundle(params.jwt_keys, params.x509_cas)
             .await
             .map_err(Error::BuildTrustBundle)?;
+        // Only JWT-SVIDs of the federated trust domains are validated, with their JWT keys.
+        let federated_bundles = if params.jwt_keys {
+            self.trust_bundle_builder
+                .build_federated_bundles()
+                .await
+                .map_err(Error::BuildTrustBundle)?
+        } else {
+            Vec::new()
+        };
 
-        Ok(get_trust_bundle::Response { trust_bundle })
+        Ok(get_trust_bundle::Response {
+            trust_bundle,
+            federated_bundles,
+        })
     }
+
+    pub async fn get_attestation_nonce(&self) -> Result<get_attestation_nonce::Response, Error> {
+        self.node_attestation
+            .create_nonce()
+            .await
+            .map_err(Error::CreateNonce)?
+            .ok_or(Error::NoncesNotUsed)
+    }
+}
+
+// The build reported by the agent is only trusted when it is signed by the release key. Agents of an
+// unsigned or modified build get no build selectors, so they do not match the entries requiring a build.
+fn agent_build_selectors(agent_build: &BuildInfo, agent_build_key: &PKey<Public>) -> Vec<String> {
+    if !agent_build.verify(agent_build_key) {
+        log::warn!(
+            "Ignoring the build of an agent without a valid signature: {}",
+            agent_build
+        );
+        return Vec::new();
+    }
+
+    let mut selectors = vec![
+        build_selector_string(&NodeSelectorType::AgentBuildVersion, &agent_build.version),
+        build_selector_string(&NodeSelectorType::AgentBuildGitSHA, &agent_build.git_sha),
+    ];
+    selectors.extend(
+        agent_build
+            .features
+            .iter()
+            .map(|feature| build_selector_string(&NodeSelectorType::AgentBuildFeature, feature)),
+    );
+
+    selectors
 }
 
 fn get_spiffe_id_path(
@@ -100,10 +341,11 @@ fn get_spiffe_id_path(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use catalog::{inmemory, Catalog, Entries};
+    use crate::{issuance_policy::Policy, metrics::Metrics};
+    use catalog::{inmemory, AgentBans, Catalog, Entries, IssuedSvidFilter};
     use core_objects::{
-        AttestationConfig, EntryNodeAttestation, EntryWorkloadAttestation, NodeAttestationPlugin,
-        RegistrationEntry, WorkloadAttestationPlugin, CONFIG_DEFAULT_PATH, SPIFFE_ID_PREFIX,
+        AgentBan, AttestationConfig, EntryNodeAttestation, JWTClaims, NodeAttestationPlugin,
+        RegistrationEntry, CONFIG_DEFAULT_PATH, SPIFFE_ID_PREFIX,
     };
     use identity_matcher::IdentityMatcher;
     use key_manager::KeyManager;
@@ -111,7 +353,14 @@ mod tests {
     use matches::assert_matches;
     use mock_kube::{get_nodes, get_pods, get_token_review, Client};
     use node_attestation_server::NodeAttestatorFactory;
-    use server_config::{Config, KeyStoreConfig, KeyStoreConfigDisk};
+    use openssl::{
+        ec::{EcGroup, EcKey},
+        nid::Nid,
+    };
+    use server_agent_api::create_workload_jwts::DenyReason;
+    use server_config::{
+        AuditLogConfig, Config, IssuancePolicyConfig, KeyStoreConfig, KeyStoreConfigDisk,
+    };
     use svid_factory::SVIDFactory;
     use trust_bundle_builder::TrustBundleBuilder;
 
@@ -129,39 +378,28 @@ mod tests {
     ) {
         let mut config = Config::load_config(CONFIG_DEFAULT_PATH).unwrap();
         let key_base_path = dir.path().to_str().unwrap().to_string();
-        let key_plugin = KeyStoreConfigDisk { key_base_path };
+        let key_plugin = KeyStoreConfigDisk {
+            key_base_path,
+            encryption: None,
+        };
 
         // Create parent
         let entry1 = RegistrationEntry {
-            id: String::from("parent"),
-            other_identities: Vec::new(),
-            spiffe_id_path: "parent".to_string(),
             attestation_config: AttestationConfig::Node(EntryNodeAttestation {
                 value: vec!["AGENTSERVICEACCOUNT:iotedge-spiffe-agent".to_string()],
                 plugin: NodeAttestationPlugin::Psat,
             }),
-            admin: false,
-            expires_at: 0,
-            dns_names: Vec::new(),
-            revision_number: 0,
-            store_svid: false,
+            ..RegistrationEntry::test_entry("parent")
         };
 
         // Create child
         let entry2 = RegistrationEntry {
-            id: String::from("workload"),
-            other_identities: Vec::new(),
             spiffe_id_path: "generic".to_string(),
-            attestation_config: AttestationConfig::Workload(EntryWorkloadAttestation {
-                value: vec!["PODLABELS:app:genericnode".to_string()],
-                plugin: WorkloadAttestationPlugin::K8s,
-                parent_id: "parent".to_string(),
-            }),
-            admin: false,
-            expires_at: 0,
-            dns_names: Vec::new(),
-            revision_number: 0,
-            store_svid: false,
+            ..RegistrationEntry::test_workload_entry(
+                "workload",
+                "parent",
+                vec!["PODLABELS:app:genericnode".to_string()],
+            )
         };
         let entries = vec![entry1, entry2];
 
@@ -173,7 +411,7 @@ mod tests {
         let catalog = Arc::new(inmemory::Catalog::new());
         catalog.batch_create(entries.clone()).await.unwrap();
 
-        let key_store = Arc::new(disk::KeyStore::new(&key_plugin));
+        let key_store = Arc::new(disk::KeyStore::new(&key_plugin).unwrap());
 
         let key_manager = Arc::new(
             KeyManager::new(&config, catalog.clone(), key_store.clone(), 0)
@@ -194,7 +432,13 @@ mod tests {
             trust_bundle_builder,
             node_attestation,
             identity_matcher,
+            agent_bans: Arc::new(AgentBans::new(catalog.clone())),
+            attested_agents: Arc::new(AttestedAgents::new(catalog.clone())),
+            svid_audit: None,
+            issuance_policy: Arc::new(Policy::new(&config.issuance_policy)),
             trust_domain: Arc::new(config.trust_domain.clone()),
+            agent_build_key: None,
+            metrics: Arc::new(Metrics::default()),
         };
 
         (api, entries, key_manager, config, client, catalog)
@@ -203,7 +447,7 @@ mod tests {
     #[tokio::test]
     async fn create_new_jwts_happy_path() {
         let tmp = tempfile::tempdir().unwrap();
-        let (api, entries, _key_manager, _config, mut client, _catalog) = init(&tmp).await;
+        let (api, entries, _key_manager, _config, mut client, catalog) = init(&tmp).await;
 
         let entry = entries[1].clone();
 
@@ -222,6 +466,10 @@ mod tests {
             selectors: workload_selectors.clone(),
             attestation_token: "dummy".to_string(),
             workload_spiffe_id: None,
+            pod_uid: None,
+            agent_build: None,
+            prefetch_only: false,
+            bundles_only: false,
         };
 
         let pod = get_pods();
@@ -246,6 +494,213 @@ mod tests {
 
         let response = api.create_workload_jwts(req).await.unwrap();
         assert_eq!(response.jwt_svids.len(), 1);
+
+        assert_eq!(api.metrics.jwt_svids_issued.get(), 2);
+        assert_eq!(api.metrics.signing_duration.count(), 2);
+
+        let (agents, _) = catalog.list_attested_agents(None, 10).await.unwrap();
+        assert_eq!(agents.len(), 1);
+        assert_ne!(agents[0].svid_expiry, 0);
+    }
+
+    #[tokio::test]
+    async fn create_new_jwts_recorded_in_audit() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (mut api, entries, _key_manager, _config, mut client, catalog) = init(&tmp).await;
+        api.svid_audit = Some(Arc::new(SvidAudit::new(
+            &AuditLogConfig::Catalog,
+            catalog.clone(),
+        )));
+
+        let req = create_workload_jwts::Request {
+            audiences: vec!["audience".to_string()],
+            selectors: BTreeSet::from(["PODLABELS:app:genericnode".to_string()]),
+            attestation_token: "dummy".to_string(),
+            workload_spiffe_id: None,
+            pod_uid: None,
+            agent_build: None,
+            prefetch_only: false,
+            bundles_only: false,
+        };
+
+        client.queue_response(get_token_review()).await;
+        client.queue_response(get_pods()).await;
+        client.queue_response(get_nodes()).await;
+
+        let response = api.create_workload_jwts(req).await.unwrap();
+
+        let (svids, _) = catalog
+            .list_issued_svids(&IssuedSvidFilter::default(), None, 10)
+            .await
+            .unwrap();
+        assert_eq!(svids.len(), 1);
+        assert_eq!(svids[0].entry_id, entries[1].id);
+        assert_eq!(svids[0].spiffe_id, response.jwt_svids[0].spiffe_id);
+        assert_eq!(svids[0].audiences, vec!["audience".to_string()]);
+        assert_eq!(
+            svids[0].selectors,
+            vec!["PODLABELS:app:genericnode".to_string()]
+        );
+        assert_eq!(svids[0].expiry, response.jwt_svids[0].expiry);
+        let (agents, _) = catalog.list_attested_agents(None, 10).await.unwrap();
+        assert_eq!(svids[0].agent_id, agents[0].id);
+    }
+
+    #[tokio::test]
+    async fn create_new_jwts_audiences() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (api, _entries, _key_manager, _config, mut client, _catalog) = init(&tmp).await;
+
+        let mut workload_selectors = BTreeSet::new();
+        workload_selectors.insert("PODLABELS:app:genericnode".to_string());
+
+        let audiences = vec![
+            "spiffe://my trust domain/mqttbroker".to_string(),
+            "other audience".to_string(),
+        ];
+        let req = create_workload_jwts::Request {
+            audiences: audiences.clone(),
+            selectors: workload_selectors,
+            attestation_token: "dummy".to_string(),
+            workload_spiffe_id: None,
+            pod_uid: None,
+            agent_build: None,
+            prefetch_only: false,
+            bundles_only: false,
+        };
+
+        client.queue_response(get_token_review()).await;
+        client.queue_response(get_pods()).await;
+        client.queue_response(get_nodes()).await;
+
+        let response = api.create_workload_jwts(req).await.unwrap();
+        assert_eq!(response.jwt_svids.len(), 1);
+
+        // The audiences of the request are the ones of the issued JWT-SVID.
+        let claims = response.jwt_svids[0].token.split('.').nth(1).unwrap();
+        let claims = base64::decode_config(claims, base64::URL_SAFE_NO_PAD).unwrap();
+        let claims: JWTClaims = serde_json::from_slice(&claims).unwrap();
+        assert_eq!(claims.audience, audiences);
+    }
+
+    #[tokio::test]
+    async fn create_new_jwts_denied_by_policy() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (mut api, entries, _key_manager, _config, mut client, _catalog) = init(&tmp).await;
+
+        let entry = entries[1].clone();
+        let mut policy_config = IssuancePolicyConfig::default();
+        policy_config
+            .disabled_spiffe_id_paths
+            .insert(entry.spiffe_id_path.clone());
+        api.issuance_policy = Arc::new(Policy::new(&policy_config));
+
+        let mut workload_selectors = BTreeSet::new();
+        workload_selectors.insert("PODLABELS:app:genericnode".to_string());
+
+        let req = create_workload_jwts::Request {
+            audiences: vec!["my trust domain/audiences".to_string()],
+            selectors: workload_selectors,
+            attestation_token: "dummy".to_string(),
+            workload_spiffe_id: None,
+            pod_uid: None,
+            agent_build: None,
+            prefetch_only: false,
+            bundles_only: false,
+        };
+
+        client.queue_response(get_token_review()).await;
+        client.queue_response(get_pods()).await;
+        client.queue_response(get_nodes()).await;
+
+        let response = api.create_workload_jwts(req).await.unwrap();
+        assert!(response.jwt_svids.is_empty());
+        assert_eq!(response.denied.len(), 1);
+        assert_eq!(
+            response.denied[0].spiffe_id,
+            format!(
+                "{}{}/{}",
+                SPIFFE_ID_PREFIX, api.trust_domain, entry.spiffe_id_path
+            )
+        );
+        assert_eq!(response.denied[0].reason, DenyReason::AdminDisabled);
+    }
+
+    #[tokio::test]
+    async fn create_new_jwts_prefetch_only() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (api, entries, _key_manager, _config, mut client, catalog) = init(&tmp).await;
+
+        let mut workload_selectors = BTreeSet::new();
+        workload_selectors.insert("PODLABELS:app:genericnode".to_string());
+
+        let req = create_workload_jwts::Request {
+            audiences: vec!["my trust domain/audiences".to_string()],
+            selectors: workload_selectors,
+            attestation_token: "dummy".to_string(),
+            workload_spiffe_id: None,
+            pod_uid: None,
+            agent_build: None,
+            prefetch_only: true,
+            bundles_only: false,
+        };
+
+        client.queue_response(get_token_review()).await;
+        client.queue_response(get_pods()).await;
+        client.queue_response(get_nodes()).await;
+
+        // The entry is not marked for prefetch
+        let response = api.create_workload_jwts(req.clone()).await.unwrap();
+        assert!(response.jwt_svids.is_empty());
+        assert!(response.denied.is_empty());
+
+        let mut entry = entries[1].clone();
+        entry.prefetch = true;
+        catalog.batch_update(vec![entry]).await.unwrap();
+
+        client.queue_response(get_token_review()).await;
+        client.queue_response(get_pods()).await;
+        client.queue_response(get_nodes()).await;
+
+        let response = api.create_workload_jwts(req).await.unwrap();
+        assert_eq!(response.jwt_svids.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn create_new_jwts_bundles_only() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (api, entries, _key_manager, _config, mut client, catalog) = init(&tmp).await;
+
+        let mut entry = entries[1].clone();
+        entry.federates_with = vec!["cloud.contoso.com".to_string()];
+        catalog.batch_update(vec![entry]).await.unwrap();
+
+        let mut workload_selectors = BTreeSet::new();
+        workload_selectors.insert("PODLABELS:app:genericnode".to_string());
+
+        let req = create_workload_jwts::Request {
+            audiences: Vec::new(),
+            selectors: workload_selectors,
+            attestation_token: "dummy".to_string(),
+            workload_spiffe_id: None,
+            pod_uid: None,
+            agent_build: None,
+            prefetch_only: false,
+            bundles_only: true,
+        };
+
+        client.queue_response(get_token_review()).await;
+        client.queue_response(get_pods()).await;
+        client.queue_response(get_nodes()).await;
+
+        // No JWT-SVID is issued, the entry only gives its federated trust domains.
+        let response = api.create_workload_jwts(req).await.unwrap();
+        assert!(response.jwt_svids.is_empty());
+        assert!(response.denied.is_empty());
+        assert_eq!(
+            response.federates_with,
+            BTreeSet::from(["cloud.contoso.com".to_string()])
+        );
     }
 
     #[test]
@@ -307,6 +762,10 @@ mod tests {
             selectors: workload_selectors,
             attestation_token: "dummy".to_string(),
             workload_spiffe_id: None,
+            pod_uid: None,
+            agent_build: None,
+            prefetch_only: false,
+            bundles_only: false,
         };
 
         let pod = get_pods();
@@ -323,6 +782,50 @@ mod tests {
         let error = api.create_workload_jwts(req).await.unwrap_err();
 
         assert_matches!(error, Error::AttestAgent(_));
+        assert_eq!(api.metrics.node_attestation_failures.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn create_new_jwts_agent_banned() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (api, _entries, _key_manager, _config, mut client, _catalog) = init(&tmp).await;
+        let agent_selectors =
+            BTreeSet::from(["AGENTSERVICEACCOUNT:iotedge-spiffe-agent".to_string()]);
+
+        api.agent_bans
+            .ban(AgentBan {
+                id: "ban".to_string(),
+                selectors: agent_selectors.clone(),
+                reason: None,
+                created_at: 0,
+            })
+            .await
+            .unwrap();
+
+        let req = create_workload_jwts::Request {
+            audiences: vec!["my trust domain/audiences".to_string()],
+            selectors: BTreeSet::from(["PODLABELS:app:genericnode".to_string()]),
+            attestation_token: "dummy".to_string(),
+            workload_spiffe_id: None,
+            pod_uid: None,
+            agent_build: None,
+            prefetch_only: false,
+            bundles_only: false,
+        };
+
+        client.queue_response(get_token_review()).await;
+        client.queue_response(get_pods()).await;
+        client.queue_response(get_nodes()).await;
+
+        let error = api.create_workload_jwts(req.clone()).await.unwrap_err();
+        assert_matches!(error, Error::AgentBanned(id) if id == "ban");
+
+        // Agents attested before the ban are rejected too.
+        let error = api
+            .create_workload_jwts_for_agent(req, agent_selectors)
+            .await
+            .unwrap_err();
+        assert_matches!(error, Error::AgentBanned(_));
     }
 
     #[tokio::test]
@@ -335,6 +838,10 @@ mod tests {
             selectors: BTreeSet::new(),
             attestation_token: "dummy".to_string(),
             workload_spiffe_id: None,
+            pod_uid: None,
+            agent_build: None,
+            prefetch_only: false,
+            bundles_only: false,
         };
 
         // Delete the parent, this will cause an error during matching since workload won't have any parent attached to it.
@@ -353,6 +860,70 @@ mod tests {
         assert_matches!(error, Error::MatchIdentity(_));
     }
 
+    #[tokio::test]
+    async fn create_new_jwts_agent_build_selectors() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (mut api, entries, _key_manager, _config, mut client, catalog) = init(&tmp).await;
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let release_key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let public_key = release_key.public_key_to_pem().unwrap();
+        api.agent_build_key = Some(PKey::public_key_from_pem(&public_key).unwrap());
+
+        // Only agents of an approved build can get identities for the children of the parent.
+        let mut parent = entries[0].clone();
+        if let AttestationConfig::Node(node_attestation) = &mut parent.attestation_config {
+            node_attestation
+                .value
+                .push("AGENTBUILDGITSHA:0123abcd".to_string());
+        }
+        catalog.batch_update(vec![parent]).await.unwrap();
+
+        let mut workload_selectors = BTreeSet::new();
+        workload_selectors.insert("PODLABELS:app:genericnode".to_string());
+
+        let mut req = create_workload_jwts::Request {
+            audiences: vec!["my trust domain/audiences".to_string()],
+            selectors: workload_selectors,
+            attestation_token: "dummy".to_string(),
+            workload_spiffe_id: None,
+            pod_uid: None,
+            agent_build: None,
+            prefetch_only: false,
+            bundles_only: false,
+        };
+
+        client.queue_response(get_token_review()).await;
+        client.queue_response(get_pods()).await;
+        client.queue_response(get_nodes()).await;
+
+        let response = api.create_workload_jwts(req.clone()).await.unwrap();
+        assert!(response.jwt_svids.is_empty());
+
+        // The build must be signed by the release key.
+        let mut agent_build = BuildInfo {
+            git_sha: "0123abcd".to_string(),
+            ..Default::default()
+        };
+        req.agent_build = Some(agent_build.clone());
+
+        client.queue_response(get_token_review()).await;
+        client.queue_response(get_pods()).await;
+        client.queue_response(get_nodes()).await;
+
+        let response = api.create_workload_jwts(req.clone()).await.unwrap();
+        assert!(response.jwt_svids.is_empty());
+
+        agent_build.signature = Some(agent_build.sign(&release_key).unwrap());
+        req.agent_build = Some(agent_build);
+
+        client.queue_response(get_token_review()).await;
+        client.queue_response(get_pods()).await;
+        client.queue_response(get_nodes()).await;
+
+        let response = api.create_workload_jwts(req).await.unwrap();
+        assert_eq!(response.jwt_svids.len(), 1);
+    }
+
     #[tokio::test]
     async fn create_new_jwts_jwt_factory_error() {
         let tmp = tempfile::tempdir().unwrap();
@@ -366,6 +937,10 @@ mod tests {
             selectors: workload_selectors,
             attestation_token: "dummy".to_string(),
             workload_spiffe_id: None,
+            pod_uid: None,
+            agent_build: None,
+            prefetch_only: false,
+            bundles_only: false,
         };
 
         let pod = get_pods();
@@ -407,4 +982,15 @@ mod tests {
         );
         assert_eq!(1, trust_bundle.jwt_key_set.spiffe_sequence_number);
     }
+
+    #[tokio::test]
+    async fn get_attestation_nonce_not_used_test() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (api, _entries, _key_manager, _config, _client, _catalog) = init(&tmp).await;
+
+        // The PSAT attestation of the test config does not use nonces.
+        let error = api.get_attestation_nonce().await.unwrap_err();
+
+        assert_matches!(error, Error::NoncesNotUsed);
+    }
 }
@@ -5,16 +5,24 @@ use thiserror::Error;
 pub enum Error {
     #[error("Unable to create new workload JWT-SVID {0}")]
     CreateWorkloadJWT(svid_factory::error::Error),
+    #[error("Unable to record the issued SVIDs in the audit log {0}")]
+    RecordIssuedSvids(Box<dyn std::error::Error + Send>),
     #[error("Unable to build the trust bundle {0}")]
     BuildTrustBundle(trust_bundle_builder::error::Error),
     #[error("Could not match identity {0}")]
     MatchIdentity(identity_matcher::error::Error),
     #[error("Unable to attest new agent {0}")]
     AttestAgent(Box<dyn std::error::Error + Send>),
+    #[error("The agent is banned by ban {0}")]
+    AgentBanned(String),
     #[error(
         "The server can only create svid for {expected:?} trust domain, request was {actual:?}"
     )]
     InvalidTrustDomain { expected: String, actual: String },
     #[error("Malformed spiffe id in request {0}")]
     MalformedSPIFFEID(String),
+    #[error("Unable to create an attestation nonce {0}")]
+    CreateNonce(Box<dyn std::error::Error + Send>),
+    #[error("The node attestation of the server does not use nonces")]
+    NoncesNotUsed,
 }
@@ -0,0 +1,348 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// gRPC variant of the server-agent API, served next to the HTTP API for the agents configured to use it.
+// An agent can open an attestation session: it is attested once, then sends its session with the
+// requests instead of a token. The session lasts until it expires without being renewed, or until the
+// stream that opened it is closed. The sessions are bearer credentials, the API is served over TLS like the HTTP
+// API when it is configured.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use core_objects::get_epoch_time;
+use futures_util::{Stream, StreamExt};
+use server_agent_api::grpc::{
+    self,
+    generated::{
+        server_agent_server::{ServerAgent, ServerAgentServer},
+        AttestRequest, AttestResponse, CreateWorkloadJwtsRequest, CreateWorkloadJwtsResponse,
+        GetAttestationNonceRequest, GetAttestationNonceResponse, GetTrustBundleRequest,
+        GetTrustBundleResponse,
+    },
+    Credential,
+};
+use server_config::Config;
+use tokio::{net::TcpListener, task::JoinHandle, time};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{transport::Server, Request, Response, Status, Streaming};
+
+use crate::{error::Error, tls, Api};
+
+// The trust bundle is built from the catalog, which does not notify its changes.
+const TRUST_BUNDLE_WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+struct Session {
+    agent_selectors: BTreeSet<String>,
+    expires_at: u64,
+}
+
+#[derive(Default)]
+struct Sessions {
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl Sessions {
+    fn get(&self, id: &str, now: u64) -> Option<BTreeSet<String>> {
+        let mut sessions = self.sessions.lock().unwrap();
+
+        match sessions.get(id) {
+            Some(session) if session.expires_at > now => Some(session.agent_selectors.clone()),
+            Some(_) => {
+                sessions.remove(id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, id: String, session: Session) {
+        let mut sessions = self.sessions.lock().unwrap();
+
+        sessions.insert(id, session);
+    }
+
+    fn remove(&self, id: &str) {
+        let mut sessions = self.sessions.lock().unwrap();
+
+        sessions.remove(id);
+    }
+}
+
+// Ends the session when its stream is dropped, whether the agent closed it or went away.
+struct SessionGuard {
+    sessions: Arc<Sessions>,
+    id: String,
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.sessions.remove(&self.id);
+    }
+}
+
+#[derive(Clone)]
+struct GrpcApi {
+    api: Api,
+    sessions: Arc<Sessions>,
+    session_ttl_secs: u64,
+}
+
+pub(crate) async fn start(api: Api, config: &Config, port: u16) -> io::Result<JoinHandle<()>> {
+    let acceptor = config
+        .server_agent_api
+        .tls
+        .as_ref()
+        .map(tls::acceptor)
+        .transpose()?;
+    let listener = TcpListener::bind((config.server_agent_api.bind_address.as_str(), port)).await?;
+
+    let service = ServerAgentServer::new(GrpcApi {
+        api,
+        sessions: Arc::new(Sessions::default()),
+        session_ttl_secs: config.server_agent_api.session_ttl_secs,
+    });
+    // Only the time to answer is bound, the streams opened by the agents stay open.
+    let timeout = config.request_limits.default.timeout();
+
+    Ok(tokio::spawn(async move {
+        log::info!("Starting gRPC SVID & trust bundle server");
+        let server = Server::builder().timeout(timeout).add_service(service);
+        let res = match acceptor {
+            Some(acceptor) => {
+                server
+                    .serve_with_incoming(tls::incoming(listener, acceptor))
+                    .await
+            }
+            None => {
+                server
+                    .serve_with_incoming(TcpListenerStream::new(listener))
+                    .await
+            }
+        };
+        if let Err(err) = res {
+            log::error!("Closing gRPC SVID & trust bundle server: {:?}", err);
+        } else {
+            log::info!("Closing gRPC SVID & trust bundle server");
+        }
+    }))
+}
+
+fn to_status(err: &Error) -> Status {
+    match err {
+        Error::AttestAgent(_) => Status::unauthenticated(err.to_string()),
+        Error::AgentBanned(_) => Status::permission_denied(err.to_string()),
+        Error::InvalidTrustDomain { .. } | Error::MalformedSPIFFEID(_) => {
+            Status::invalid_argument(err.to_string())
+        }
+        Error::NoncesNotUsed => Status::unimplemented(err.to_string()),
+        _ => Status::internal(err.to_string()),
+    }
+}
+
+#[tonic::async_trait]
+impl ServerAgent for GrpcApi {
+    async fn create_workload_jwts(
+        &self,
+        request: Request<CreateWorkloadJwtsRequest>,
+    ) -> Result<Response<CreateWorkloadJwtsResponse>, Status> {
+        let (credential, request) = grpc::from_grpc_request(request.into_inner());
+
+        let response = match credential {
+            Some(Credential::AttestationToken(_)) => self.api.create_workload_jwts(request).await,
+            Some(Credential::SessionId(session_id)) => {
+                let agent_selectors = self
+                    .sessions
+                    .get(&session_id, get_epoch_time())
+                    .ok_or_else(|| {
+                        Status::unauthenticated("Unknown or expired attestation session")
+                    })?;
+
+                let response = self
+                    .api
+                    .create_workload_jwts_for_agent(request, agent_selectors)
+                    .await;
+                // The session of a banned agent is not renewed, it must attest again.
+                if let Err(Error::AgentBanned(_)) = &response {
+                    self.sessions.remove(&session_id);
+                }
+
+                response
+            }
+            None => {
+                return Err(Status::unauthenticated(
+                    "Missing attestation token or session",
+                ))
+            }
+        };
+        let response = response.map_err(|err| to_status(&err))?;
+
+        Ok(Response::new(response.into()))
+    }
+
+    async fn get_trust_bundle(
+        &self,
+        request: Request<GetTrustBundleRequest>,
+    ) -> Result<Response<GetTrustBundleResponse>, Status> {
+        let response = self
+            .api
+            .get_trust_bundle(request.into_inner().into())
+            .await
+            .map_err(|err| to_status(&err))?;
+
+        let response = grpc::to_grpc_trust_bundle(&response)
+            .map_err(|err| Status::internal(err.to_string()))?;
+
+        Ok(Response::new(response))
+    }
+
+    type WatchTrustBundleStream = ResponseStream<GetTrustBundleResponse>;
+
+    async fn watch_trust_bundle(
+        &self,
+        request: Request<GetTrustBundleRequest>,
+    ) -> Result<Response<Self::WatchTrustBundleStream>, Status> {
+        let request = request.into_inner();
+        let api = self.api.clone();
+
+        let stream = async_stream::stream! {
+            let mut interval = time::interval(TRUST_BUNDLE_WATCH_INTERVAL);
+            let mut last_sent = None;
+
+            loop {
+                interval.tick().await;
+
+                // The catalog may only be unavailable for a moment, the agent keeps its trust bundle.
+                let response = match api.get_trust_bundle(request.clone().into()).await {
+                    Ok(response) => response,
+                    Err(err) => {
+                        log::warn!("Could not build the trust bundle to send to an agent: {}", err);
+                        continue;
+                    }
+                };
+
+                match grpc::to_grpc_trust_bundle(&response) {
+                    Ok(response) if last_sent.as_ref() == Some(&response) => (),
+                    Ok(response) => {
+                        last_sent = Some(response.clone());
+                        yield Ok(response);
+                    }
+                    Err(err) => {
+                        yield Err(Status::internal(err.to_string()));
+                        break;
+                    }
+                }
+            }
+        };
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    type AttestStream = ResponseStream<AttestResponse>;
+
+    async fn attest(
+        &self,
+        request: Request<Streaming<AttestRequest>>,
+    ) -> Result<Response<Self::AttestStream>, Status> {
+        let mut requests = request.into_inner();
+        let grpc_api = self.clone();
+
+        let stream = async_stream::stream! {
+            let session_id = uuid::Uuid::new_v4().to_string();
+            let _session = SessionGuard {
+                sessions: grpc_api.sessions.clone(),
+                id: session_id.clone(),
+            };
+
+            // Each token opens or renews the session, the first one that fails ends it.
+            while let Some(Ok(request)) = requests.next().await {
+                let agent_selectors = match grpc_api.api.attest_agent(&request.attestation_token).await {
+                    Ok(agent_selectors) => agent_selectors,
+                    Err(err) => {
+                        yield Err(to_status(&err));
+                        break;
+                    }
+                };
+
+                grpc_api.api.record_agent(&agent_selectors, 0).await;
+
+                let expires_at = get_epoch_time() + grpc_api.session_ttl_secs;
+                grpc_api.sessions.insert(
+                    session_id.clone(),
+                    Session {
+                        agent_selectors,
+                        expires_at,
+                    },
+                );
+
+                yield Ok(AttestResponse {
+                    session_id: session_id.clone(),
+                    expires_at,
+                });
+            }
+        };
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn get_attestation_nonce(
+        &self,
+        _request: Request<GetAttestationNonceRequest>,
+    ) -> Result<Response<GetAttestationNonceResponse>, Status> {
+        let response = self
+            .api
+            .get_attestation_nonce()
+            .await
+            .map_err(|err| to_status(&err))?;
+
+        Ok(Response::new(response.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(expires_at: u64) -> Session {
+        Session {
+            agent_selectors: BTreeSet::from(["AGENTSERVICEACCOUNT:agent".to_string()]),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn sessions_expire() {
+        let sessions = Sessions::default();
+
+        sessions.insert("session".to_string(), session(100));
+        assert!(sessions.get("session", 99).is_some());
+        assert!(sessions.get("other", 99).is_none());
+
+        assert!(sessions.get("session", 100).is_none());
+        // Expired sessions are dropped, renewing them opens them again.
+        assert!(sessions.get("session", 0).is_none());
+        sessions.insert("session".to_string(), session(200));
+        assert!(sessions.get("session", 100).is_some());
+    }
+
+    #[test]
+    fn session_ends_with_its_stream() {
+        let sessions = Arc::new(Sessions::default());
+
+        let guard = SessionGuard {
+            sessions: sessions.clone(),
+            id: "session".to_string(),
+        };
+        sessions.insert("session".to_string(), session(100));
+        assert!(sessions.get("session", 0).is_some());
+
+        drop(guard);
+        assert!(sessions.get("session", 0).is_none());
+    }
+}
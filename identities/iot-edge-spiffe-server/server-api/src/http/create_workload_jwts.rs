@@ -6,12 +6,14 @@ use serde::de::IgnoredAny;
 use server_agent_api::{create_workload_jwts, ApiVersion};
 use std::borrow::Cow;
 
-use crate::{error::Error, Api};
+use crate::{agent_auth::AttestedAgent, error::Error, Api};
 
 use super::uri;
 
 pub(super) struct Route {
     api: Api,
+    // Set when the agent was authenticated by its bearer token, the token of the body is then not attested.
+    attested_agent: Option<AttestedAgent>,
 }
 
 #[async_trait::async_trait]
@@ -30,13 +32,14 @@ impl server::Route for Route {
         service: &Self::Service,
         path: &str,
         _query: &[(Cow<'_, str>, Cow<'_, str>)],
-        _extensions: &Extensions,
+        extensions: &Extensions,
     ) -> Option<Self> {
         if path != uri::CREATE_WORKLOAD_JTWS {
             return None;
         }
         Some(Route {
             api: service.api.clone(),
+            attested_agent: extensions.get::<AttestedAgent>().cloned(),
         })
     }
 
@@ -46,11 +49,18 @@ impl server::Route for Route {
             message: "missing request body".into(),
         })?;
 
-        let res = self.api.create_workload_jwts(body).await;
+        let res = match self.attested_agent {
+            Some(AttestedAgent(agent_selectors)) => {
+                self.api
+                    .create_workload_jwts_for_agent(body, agent_selectors)
+                    .await
+            }
+            None => self.api.create_workload_jwts(body).await,
+        };
         let res = match res {
             Ok(res) => res,
             Err(err) => {
-                if let Error::AttestAgent(_) = err {
+                if let Error::AttestAgent(_) | Error::AgentBanned(_) = err {
                     return Err(server::Error {
                         status_code: StatusCode::FORBIDDEN,
                         message: format!("Error doing agent attestation: {}", err).into(),
@@ -0,0 +1,66 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::borrow::Cow;
+
+use http::{Extensions, StatusCode};
+use http_common::{server, DynRangeBounds};
+use serde::de::IgnoredAny;
+use server_agent_api::ApiVersion;
+
+use crate::{error::Error, Api};
+
+use super::uri;
+
+pub(super) struct Route {
+    api: Api,
+}
+
+#[async_trait::async_trait]
+impl server::Route for Route {
+    type ApiVersion = ApiVersion;
+    type Service = super::Service;
+    type DeleteBody = IgnoredAny;
+    type PostBody = IgnoredAny;
+    type PutBody = IgnoredAny;
+
+    fn api_version() -> &'static dyn DynRangeBounds<Self::ApiVersion> {
+        &((ApiVersion::V2022_06_01)..)
+    }
+
+    fn from_uri(
+        service: &Self::Service,
+        path: &str,
+        _query: &[(Cow<'_, str>, Cow<'_, str>)],
+        _extensions: &Extensions,
+    ) -> Option<Self> {
+        if path != uri::GET_ATTESTATION_NONCE {
+            return None;
+        }
+        Some(Route {
+            api: service.api.clone(),
+        })
+    }
+
+    // A POST, every call issues a new nonce.
+    async fn post(self, _body: Option<Self::PostBody>) -> server::RouteResponse {
+        let res = match self.api.get_attestation_nonce().await {
+            Ok(res) => res,
+            Err(err @ Error::NoncesNotUsed) => {
+                return Err(server::Error {
+                    status_code: StatusCode::NOT_IMPLEMENTED,
+                    message: err.to_string().into(),
+                });
+            }
+            Err(err) => {
+                return Err(server::Error {
+                    status_code: StatusCode::INTERNAL_SERVER_ERROR,
+                    message: format!("Error when creating the nonce: {}", err).into(),
+                });
+            }
+        };
+
+        let res = server::response::json(StatusCode::CREATED, &res);
+
+        Ok(res)
+    }
+}
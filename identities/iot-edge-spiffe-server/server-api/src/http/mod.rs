@@ -6,6 +6,7 @@ use server_agent_api::ApiVersion;
 use crate::Api;
 
 mod create_workload_jwts;
+mod get_attestation_nonce;
 mod get_trust_bundle;
 
 #[derive(Clone)]
@@ -16,6 +17,7 @@ pub struct Service {
 pub mod uri {
     pub const CREATE_WORKLOAD_JTWS: &str = "/workload-jwts";
     pub const GET_TRUST_BUNDLE: &str = "/trust-bundle";
+    pub const GET_ATTESTATION_NONCE: &str = "/attestation-nonce";
 }
 
 make_service! {
@@ -23,6 +25,7 @@ make_service! {
     api_version: ApiVersion,
     routes: [
         create_workload_jwts::Route,
+        get_attestation_nonce::Route,
         get_trust_bundle::Route,
     ],
 }
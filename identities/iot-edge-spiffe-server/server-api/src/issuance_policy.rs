@@ -0,0 +1,183 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use core_objects::RegistrationEntry;
+use server_agent_api::create_workload_jwts::DenyReason;
+use server_config::IssuancePolicyConfig;
+
+// Why an entry that matched the workload was not issued. The reason is sent back
+// to the agent, the detail is only written to the audit log.
+#[derive(Debug, PartialEq)]
+pub struct Denial {
+    pub reason: DenyReason,
+    pub detail: String,
+}
+
+pub struct Policy {
+    config: IssuancePolicyConfig,
+}
+
+impl Policy {
+    #[must_use]
+    pub fn new(config: &IssuancePolicyConfig) -> Self {
+        Policy {
+            config: config.clone(),
+        }
+    }
+
+    // Check a matched entry against the policy. `issued` is the number of
+    // JWT-SVIDs already issued for the same request.
+    pub fn check(
+        &self,
+        entry: &RegistrationEntry,
+        audiences: &[String],
+        issued: usize,
+    ) -> Result<(), Denial> {
+        if self
+            .config
+            .disabled_spiffe_id_paths
+            .contains(&entry.spiffe_id_path)
+        {
+            return Err(Denial {
+                reason: DenyReason::AdminDisabled,
+                detail: format!("entry {} is disabled by the administrator", entry.id),
+            });
+        }
+
+        if !self.config.allowed_audiences.is_empty() {
+            if let Some(audience) = audiences
+                .iter()
+                .find(|audience| !self.config.allowed_audiences.contains(*audience))
+            {
+                return Err(Denial {
+                    reason: DenyReason::PolicyDenied,
+                    detail: format!("audience {} is not allowed", audience),
+                });
+            }
+        }
+
+        if let Some(claim) = entry
+            .custom_claims
+            .keys()
+            .find(|claim| !self.config.allowed_custom_claims.contains(*claim))
+        {
+            return Err(Denial {
+                reason: DenyReason::PolicyDenied,
+                detail: format!(
+                    "custom claim {} of entry {} is not allowed",
+                    claim, entry.id
+                ),
+            });
+        }
+
+        if let Some(max_svids_per_request) = self.config.max_svids_per_request {
+            if issued >= max_svids_per_request {
+                return Err(Denial {
+                    reason: DenyReason::QuotaExceeded,
+                    detail: format!(
+                        "request already issued {} JWT-SVIDs, limit is {}",
+                        issued, max_svids_per_request
+                    ),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_default_allows_everything() {
+        let policy = Policy::new(&IssuancePolicyConfig::default());
+
+        policy
+            .check(
+                &RegistrationEntry::test_entry("workload"),
+                &["audience".to_string()],
+                100,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn check_admin_disabled() {
+        let mut config = IssuancePolicyConfig::default();
+        config
+            .disabled_spiffe_id_paths
+            .insert("workload".to_string());
+        let policy = Policy::new(&config);
+
+        let denial = policy
+            .check(&RegistrationEntry::test_entry("workload"), &[], 0)
+            .unwrap_err();
+        assert_eq!(denial.reason, DenyReason::AdminDisabled);
+
+        policy
+            .check(&RegistrationEntry::test_entry("other"), &[], 0)
+            .unwrap();
+    }
+
+    #[test]
+    fn check_audience_not_allowed() {
+        let mut config = IssuancePolicyConfig::default();
+        config.allowed_audiences.insert("broker".to_string());
+        let policy = Policy::new(&config);
+
+        policy
+            .check(
+                &RegistrationEntry::test_entry("workload"),
+                &["broker".to_string()],
+                0,
+            )
+            .unwrap();
+
+        let denial = policy
+            .check(
+                &RegistrationEntry::test_entry("workload"),
+                &["broker".to_string(), "other".to_string()],
+                0,
+            )
+            .unwrap_err();
+        assert_eq!(denial.reason, DenyReason::PolicyDenied);
+    }
+
+    #[test]
+    fn check_custom_claim_not_allowed() {
+        let mut config = IssuancePolicyConfig::default();
+        config.allowed_custom_claims.insert("ring".to_string());
+        let policy = Policy::new(&config);
+
+        let mut entry = RegistrationEntry::test_entry("workload");
+        entry
+            .custom_claims
+            .insert("ring".to_string(), "canary".to_string());
+        policy.check(&entry, &[], 0).unwrap();
+
+        entry
+            .custom_claims
+            .insert("tenant".to_string(), "contoso".to_string());
+        let denial = policy.check(&entry, &[], 0).unwrap_err();
+        assert_eq!(denial.reason, DenyReason::PolicyDenied);
+    }
+
+    #[test]
+    fn check_quota_exceeded() {
+        let config = IssuancePolicyConfig {
+            max_svids_per_request: Some(1),
+            ..Default::default()
+        };
+        let policy = Policy::new(&config);
+
+        policy
+            .check(&RegistrationEntry::test_entry("workload"), &[], 0)
+            .unwrap();
+
+        let denial = policy
+            .check(&RegistrationEntry::test_entry("workload"), &[], 1)
+            .unwrap_err();
+        assert_eq!(denial.reason, DenyReason::QuotaExceeded);
+    }
+}
@@ -10,67 +10,158 @@
     clippy::too_many_lines
 )]
 
+use agent_auth::AgentAuthService;
+use catalog::{AgentBans, AttestedAgents, SvidAudit};
+use futures_util::future::BoxFuture;
 use http_common::Connector;
 use identity_matcher::IdentityMatcher;
+use issuance_policy::Policy;
 use node_attestation_server::NodeAttestation;
+use openssl::pkey::{PKey, Public};
+use request_limits::{service::LimitedService, EndpointClass};
 use server_config::Config;
 use std::{io, sync::Arc};
 use svid_factory::SVIDFactory;
-use tokio::task::JoinHandle;
+use tokio::{net::TcpListener, task::JoinHandle};
 use trust_bundle_builder::TrustBundleBuilder;
 
+use crate::metrics::Metrics;
+
+mod agent_auth;
 pub mod create_workload_jwts;
 mod error;
+mod grpc;
 mod http;
+pub mod issuance_policy;
+pub mod metrics;
+mod tls;
 
 const SOCKET_DEFAULT_PERMISSION: u32 = 0o660;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn start_server_api(
     config: &Config,
     svid_factory: Arc<SVIDFactory>,
     trust_bundle_builder: Arc<TrustBundleBuilder>,
     node_attestation: Arc<dyn NodeAttestation>,
     identity_matcher: Arc<IdentityMatcher>,
+    agent_bans: Arc<AgentBans>,
+    attested_agents: Arc<AttestedAgents>,
+    svid_audit: Option<Arc<SvidAudit>>,
+    metrics: Arc<Metrics>,
 ) -> Result<JoinHandle<Result<(), std::io::Error>>, io::Error> {
     let api = Api {
         svid_factory,
         trust_bundle_builder,
         node_attestation,
         identity_matcher,
+        agent_bans,
+        attested_agents,
+        svid_audit,
+        issuance_policy: Arc::new(Policy::new(&config.issuance_policy)),
         trust_domain: Arc::new(config.trust_domain.clone()),
+        agent_build_key: agent_build_key(config)?,
+        metrics,
     };
 
-    let service = http::Service { api };
+    let service = http::Service { api: api.clone() };
     let uri: &str = &config.server_agent_api.bind_address;
 
-    let connector = Connector::Tcp {
-        host: uri.into(),
-        port: config.server_agent_api.bind_port,
+    let server: BoxFuture<'static, ()> = match &config.server_agent_api.tls {
+        Some(tls_config) => {
+            let acceptor = tls::acceptor(tls_config)?;
+            let listener = TcpListener::bind((uri, config.server_agent_api.bind_port)).await?;
+            // Agents are authenticated before their requests are handled, within the limits of the request.
+            let service = limited(AgentAuthService::new(service, api.clone()), config);
+
+            Box::pin(async move {
+                log::info!("Starting SVID & trust bundle server over TLS");
+                tls::serve(listener, acceptor, service).await;
+            })
+        }
+        None => {
+            let connector = Connector::Tcp {
+                host: uri.into(),
+                port: config.server_agent_api.bind_port,
+            };
+
+            let mut incoming = connector.incoming(SOCKET_DEFAULT_PERMISSION, None).await?;
+            let service = limited(service, config);
+
+            Box::pin(async move {
+                // Channel to gracefully shut down the server. It's currently not used.
+                let (_shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+
+                log::info!("Starting SVID & trust bundle server");
+                let res = incoming.serve(service, shutdown_rx).await;
+                if let Err(err) = res {
+                    log::error!("Closing SVID & trust bundle server: {:?}", err);
+                } else {
+                    log::info!("Closing SVID & trust bundle server");
+                };
+            })
+        }
     };
 
-    let mut incoming = connector.incoming(SOCKET_DEFAULT_PERMISSION, None).await?;
+    // Served next to the HTTP API, so the agents can move to gRPC one at a time.
+    let grpc_server = match config.server_agent_api.grpc_bind_port {
+        Some(port) => Some(grpc::start(api, config, port).await?),
+        None => None,
+    };
 
     Ok(tokio::spawn(async move {
-        // Channel to gracefully shut down the server. It's currently not used.
-        let (_shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        server.await;
 
-        log::info!("Starting SVID & trust bundle server");
-        let res = incoming.serve(service, shutdown_rx).await;
-        if let Err(err) = res {
-            log::error!("Closing SVID & trust bundle server: {:?}", err);
-        } else {
-            log::info!("Closing SVID & trust bundle server");
-        };
+        if let Some(grpc_server) = grpc_server {
+            let _wait = grpc_server.await;
+        }
 
         Ok(())
     }))
 }
 
+// Key checking the signature of the builds reported by the agents. None when the builds are not selectors.
+fn agent_build_key(config: &Config) -> io::Result<Option<PKey<Public>>> {
+    if !config.agent_build_selectors {
+        return Ok(None);
+    }
+
+    let path = config.agent_build_public_key_path.as_ref().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "agent_build_selectors requires agent_build_public_key_path",
+        )
+    })?;
+    let key = PKey::public_key_from_pem(&std::fs::read(path)?).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid agent build public key {}: {}", path, err),
+        )
+    })?;
+
+    Ok(Some(key))
+}
+
+// Agents only send small requests, every endpoint gets the default limits.
+fn limited<S>(service: S, config: &Config) -> LimitedService<S> {
+    LimitedService::new(service, config.request_limits.clone(), |_, _| {
+        EndpointClass::Default
+    })
+}
+
 #[derive(Clone)]
 struct Api {
     svid_factory: Arc<SVIDFactory>,
     trust_bundle_builder: Arc<TrustBundleBuilder>,
     node_attestation: Arc<dyn NodeAttestation>,
     identity_matcher: Arc<IdentityMatcher>,
+    agent_bans: Arc<AgentBans>,
+    attested_agents: Arc<AttestedAgents>,
+    // Issued SVIDs are recorded when set.
+    svid_audit: Option<Arc<SvidAudit>>,
+    issuance_policy: Arc<Policy>,
     trust_domain: Arc<String>,
+    // Builds signed by this key are added to the agent selectors, when set.
+    agent_build_key: Option<PKey<Public>>,
+    metrics: Arc<Metrics>,
 }
@@ -0,0 +1,41 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Metrics of the requests of the agents. The default metrics are not registered, for tests and servers
+// without a metrics endpoint.
+
+use std::sync::Arc;
+
+use metrics::{Counter, Histogram, Registry, LATENCY_BUCKETS};
+
+#[derive(Default)]
+pub struct Metrics {
+    pub(crate) jwt_svids_issued: Arc<Counter>,
+    pub(crate) jwt_svids_denied: Arc<Counter>,
+    pub(crate) node_attestation_failures: Arc<Counter>,
+    pub(crate) signing_duration: Arc<Histogram>,
+}
+
+impl Metrics {
+    #[must_use]
+    pub fn new(registry: &mut Registry) -> Self {
+        Metrics {
+            jwt_svids_issued: registry.counter(
+                "e4k_server_jwt_svids_issued_total",
+                "JWT-SVIDs issued to the workloads of the agents.",
+            ),
+            jwt_svids_denied: registry.counter(
+                "e4k_server_jwt_svids_denied_total",
+                "JWT-SVIDs of matching entries denied by the issuance policy.",
+            ),
+            node_attestation_failures: registry.counter(
+                "e4k_server_node_attestation_failures_total",
+                "Agents that failed node attestation.",
+            ),
+            signing_duration: registry.histogram(
+                "e4k_server_jwt_svid_signing_duration_seconds",
+                "Time to sign the JWT-SVIDs of a request.",
+                LATENCY_BUCKETS,
+            ),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// TLS listeners of the server-agent API. The server authenticates with its certificate, the agents with their
+// attestation token, see `agent_auth` and the sessions of the gRPC API, and with a certificate of the client CAs
+// when they are configured.
+
+use std::{
+    convert::Infallible,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use hyper::{server::conn::Http, service::Service, Body, Request, Response};
+use openssl::{
+    ssl::{SslAcceptor, SslVerifyMode},
+    x509::X509Name,
+};
+use server_config::ServerAgentApiTlsConfig;
+use tls_listener::{accept, handshake};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_openssl::SslStream;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::server::{Connected, TcpConnectInfo};
+
+const LISTENER_NAME: &str = "server-agent API";
+// Connections handshaken but not yet picked up by the gRPC server.
+const INCOMING_CAPACITY: usize = 64;
+
+pub(crate) fn acceptor(config: &ServerAgentApiTlsConfig) -> io::Result<SslAcceptor> {
+    tls_listener::acceptor(&config.cert_path, &config.key_path, |builder| {
+        if let Some(client_ca_path) = &config.client_ca_path {
+            builder.set_ca_file(client_ca_path)?;
+            builder.set_client_ca_list(X509Name::load_client_ca_file(client_ca_path)?);
+            builder.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        }
+
+        Ok(())
+    })
+    .map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid server-agent API TLS configuration: {}", err),
+        )
+    })
+}
+
+pub(crate) async fn serve<S>(listener: TcpListener, acceptor: SslAcceptor, service: S)
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+{
+    tls_listener::serve(listener, acceptor, LISTENER_NAME, move |stream, _| {
+        serve_connection(stream, service.clone())
+    })
+    .await;
+}
+
+async fn serve_connection<S>(stream: SslStream<TcpStream>, service: S)
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+{
+    if let Err(err) = Http::new()
+        .http1_only(true)
+        .serve_connection(stream, service)
+        .await
+    {
+        log::error!("Error serving the server-agent API over TLS: {}", err);
+    }
+}
+
+// Connections of the gRPC API. The handshakes run in their own tasks, a slow client does not hold the others.
+pub(crate) fn incoming(
+    listener: TcpListener,
+    acceptor: SslAcceptor,
+) -> ReceiverStream<io::Result<TlsConnection>> {
+    let (sender, receiver) = mpsc::channel(INCOMING_CAPACITY);
+
+    tokio::spawn(async move {
+        // Stops once the gRPC server is gone.
+        while !sender.is_closed() {
+            let (stream, remote) = accept(&listener, LISTENER_NAME).await;
+            let acceptor = acceptor.clone();
+            let sender = sender.clone();
+
+            tokio::spawn(async move {
+                if let Some(stream) = handshake(&acceptor, stream, remote).await {
+                    if sender.send(Ok(TlsConnection(stream))).await.is_err() {
+                        log::debug!(
+                            "Dropping the connection of {}, the server is closed",
+                            remote
+                        );
+                    }
+                }
+            });
+        }
+    });
+
+    ReceiverStream::new(receiver)
+}
+
+// A connection of the gRPC API, tonic needs the address of the agent.
+pub(crate) struct TlsConnection(SslStream<TcpStream>);
+
+impl Connected for TlsConnection {
+    type ConnectInfo = TcpConnectInfo;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.0.get_ref().connect_info()
+    }
+}
+
+impl AsyncRead for TlsConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
@@ -6,4 +6,8 @@ use thiserror::Error;
 pub enum Error {
     #[error("Error parsing config {0}")]
     ErrorParsingConfig(std::io::Error),
+    #[error("Cannot read the SVID audit log {0}")]
+    SvidAudit(Box<dyn std::error::Error + Send>),
+    #[error("Cannot read the admin audit log {0}")]
+    AdminAudit(Box<dyn std::error::Error + Send>),
 }
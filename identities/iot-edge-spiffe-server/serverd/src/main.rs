@@ -16,33 +16,56 @@ use kube::Client;
 #[cfg(any(test, feature = "tests"))]
 use mock_kube::Client;
 
-use catalog::{Catalog, CatalogFactory};
+use admin_api::info_api;
+use build_info::{build_info, BuildInfo};
+use bundle_publisher::ConfigMapPublisher;
+use catalog::{
+    scan_entries, AdminAudit, AdminOperationFilter, AgentBans, AttestedAgents, Catalog,
+    CatalogFactory, EntryPruner, IssuedSvidFilter, SvidAudit,
+};
+#[cfg(feature = "chaos")]
+use chaos_hooks::Faults;
 use core_objects::get_epoch_time;
+use entry_webhook::EntryWebhook;
 use error::Error;
-use futures_util::{future, pin_mut};
-use key_manager::KeyManager;
+use federation::manager::FederationManager;
+use futures_util::{future, pin_mut, TryStreamExt};
+use key_manager::{scheduler, KeyManager};
+#[cfg(feature = "chaos")]
+use key_store::KeyStore;
 use key_store::KeyStoreFactory;
-use log::{error, info};
+use log::{error, info, warn};
+use logging::LogFormat;
+use metrics::{Gauge, Registry};
 use node_attestation_server::NodeAttestatorFactory;
 use server_config::Config;
-use std::{error::Error as StdError, sync::Arc, time::Duration};
+use std::{error::Error as StdError, io, sync::Arc, time::Duration};
 use svid_factory::SVIDFactory;
 use tokio::{sync::Notify, time};
 use trust_bundle_builder::TrustBundleBuilder;
 
 const CONFIG_DEFAULT_PATH: &str = "/mnt/config/Config.toml";
-
-const KEY_MANAGER_ROTATION_POLL_INTERVAL_SECONDS: u64 = 10;
+// Counting the entries lists the whole catalog, it is not done on every scrape.
+const CATALOG_METRICS_PERIOD: Duration = Duration::from_secs(60);
+const CATALOG_METRICS_PAGE_SIZE: usize = 100;
+// Bans added through another replica apply here after at most this long.
+const AGENT_BANS_REFRESH_PERIOD: Duration = Duration::from_secs(10);
 
 mod error;
 
 #[tokio::main]
 async fn main() {
-    logger::try_init()
+    // Loaded first for the log format, a config that cannot be loaded is reported in the text format.
+    let config = Config::load_config(CONFIG_DEFAULT_PATH);
+    let (log_format, trust_domain) = match &config {
+        Ok(config) => (config.log_format, Some(config.trust_domain.as_str())),
+        Err(_) => (LogFormat::Text, None),
+    };
+    logging::try_init(log_format, trust_domain)
         .expect("cannot fail to initialize global logger from the process entrypoint");
 
     info!("Starting IoTEdge SPIFFE Server");
-    if let Err(err) = main_inner().await {
+    if let Err(err) = main_inner(config).await {
         error!("{}", err);
 
         let mut source = std::error::Error::source(&*err);
@@ -55,25 +78,68 @@ async fn main() {
     }
 }
 
-async fn main_inner() -> Result<(), Box<dyn StdError>> {
-    let config = Config::load_config(CONFIG_DEFAULT_PATH).map_err(Error::ErrorParsingConfig)?;
+async fn main_inner(config: Result<Config, io::Error>) -> Result<(), Box<dyn StdError>> {
+    let config = config.map_err(Error::ErrorParsingConfig)?;
+    let build = build_info!();
 
-    let catalog: Arc<dyn Catalog> = CatalogFactory::get(&config.catalog);
+    // Checked before any key is created or loaded.
+    if config.fips {
+        server_config::fips::check(&config)?;
+        key_store::enable_fips()?;
+        info!("FIPS mode enabled");
+    }
 
-    let identity_matcher = Arc::new(IdentityMatcher::new(catalog.clone()));
+    let catalog: Arc<dyn Catalog> = CatalogFactory::get(&config.catalog)?;
+    let key_store = KeyStoreFactory::get(&config.key_store)?;
+
+    // Resilience test builds wrap the catalog and the key store with faults set through the admin API.
+    #[cfg(feature = "chaos")]
+    let (catalog, key_store, faults) = {
+        let faults = admin_api::ServerFaults {
+            catalog: Arc::new(Faults::new("catalog")),
+            key_store: Arc::new(Faults::new("key store")),
+        };
+        warn!("Fault injection is enabled, do not use this build in production");
+
+        let catalog: Arc<dyn Catalog> = Arc::new(catalog::fault_injection::Catalog::new(
+            catalog,
+            faults.catalog.clone(),
+        ));
+        let key_store: Arc<dyn KeyStore> = Arc::new(key_store::fault_injection::KeyStore::new(
+            key_store,
+            faults.key_store.clone(),
+        ));
+
+        (catalog, key_store, Some(faults))
+    };
+    #[cfg(not(feature = "chaos"))]
+    let faults = None;
+
+    let catalog_version = catalog.backend_version().await.unwrap_or_else(|err| {
+        warn!("Cannot get catalog backend version: {}", err);
+        None
+    });
+    log_startup_banner(&config, &build, catalog_version);
 
-    let key_store = KeyStoreFactory::get(&config.key_store);
+    let identity_matcher = Arc::new(IdentityMatcher::new(catalog.clone()));
 
     let key_manager =
         KeyManager::new(&config, catalog.clone(), key_store, get_epoch_time()).await?;
     let key_manager = Arc::new(key_manager);
 
+    let mut registry = Registry::default();
+    let server_api_metrics = Arc::new(server_api::metrics::Metrics::new(&mut registry));
+    register_key_manager_metrics(&mut registry, &key_manager);
+
     let svid_factory = SVIDFactory::new(key_manager.clone(), &config);
     let svid_factory = Arc::new(svid_factory);
+    // The admin API revokes the signing key in an emergency.
+    let admin_key_manager = key_manager.clone();
 
     // Infer the runtime environment and try to create a Kubernetes Client
     let client = Client::try_default().await?;
-    let node_attestation = NodeAttestatorFactory::get(&config.node_attestation_config, client);
+    let node_attestation =
+        NodeAttestatorFactory::get(&config.node_attestation_config, client.clone());
 
     let trust_bundle_builder = TrustBundleBuilder::new(&config, catalog.clone());
 
@@ -81,13 +147,14 @@ async fn main_inner() -> Result<(), Box<dyn StdError>> {
     let key_manager_shutdown_signal_tx = key_manager_shutdown_signal_rx.clone();
     let key_manager_handle = tokio::spawn(async move {
         info!("Starting Key manager");
-        let mut interval = time::interval(Duration::from_secs(
-            KEY_MANAGER_ROTATION_POLL_INTERVAL_SECONDS,
-        ));
 
         loop {
+            // Failed checks back off, the next check is scheduled from the failures so far.
+            let consecutive_failures = key_manager.rotation_metrics.snapshot().consecutive_failures;
+            let delay = scheduler::next_delay(consecutive_failures, scheduler::random_jitter());
+
             let wait_shutdown = key_manager_shutdown_signal_rx.notified();
-            let wait_tick = interval.tick();
+            let wait_tick = time::sleep(delay);
 
             pin_mut!(wait_shutdown);
             pin_mut!(wait_tick);
@@ -99,20 +166,162 @@ async fn main_inner() -> Result<(), Box<dyn StdError>> {
                 }
                 future::Either::Right(_) => {
                     if let Err(err) = key_manager.rotate_periodic().await {
-                        error!("{}", err);
+                        error!(
+                            "Key rotation failed, {} consecutive failures: {}",
+                            key_manager.rotation_metrics.snapshot().consecutive_failures,
+                            err
+                        );
                     }
                 }
             };
         }
     });
 
-    let admin_api_handle = admin_api::start_admin_api(&config, catalog.clone()).await?;
+    let entry_pruner = Arc::new(EntryPruner::new(catalog.clone()));
+    registry.counter_fn(
+        "e4k_server_pruned_entries_total",
+        "Expired entries deleted from the catalog.",
+        {
+            let entry_pruner = entry_pruner.clone();
+            move || entry_pruner.pruned_entries()
+        },
+    );
+    let entry_pruner_shutdown_signal_rx = Arc::new(Notify::new());
+    let entry_pruner_shutdown_signal_tx = entry_pruner_shutdown_signal_rx.clone();
+    let entry_pruner_handle = tokio::spawn({
+        let entry_pruner = entry_pruner.clone();
+        let pruning_interval = config.entry_pruning.interval;
+
+        async move {
+            if pruning_interval == 0 {
+                info!("Pruning of expired entries is disabled");
+                return;
+            }
+
+            info!("Starting entry pruner");
+            let mut interval = time::interval(Duration::from_secs(pruning_interval));
+
+            loop {
+                let wait_shutdown = entry_pruner_shutdown_signal_rx.notified();
+                let wait_tick = interval.tick();
+
+                pin_mut!(wait_shutdown);
+                pin_mut!(wait_tick);
+
+                match future::select(wait_shutdown, wait_tick).await {
+                    future::Either::Left(_) => {
+                        info!("Closing entry pruner task");
+                        break;
+                    }
+                    future::Either::Right(_) => {
+                        match entry_pruner.prune_expired(get_epoch_time()).await {
+                            Ok(0) => (),
+                            Ok(pruned) => info!("Pruned {} expired entries", pruned),
+                            Err(err) => error!("Could not prune expired entries: {}", err),
+                        }
+                    }
+                };
+            }
+        }
+    });
+
+    let agent_bans = Arc::new(AgentBans::new(catalog.clone()));
+    start_agent_bans_refresh_task(agent_bans.clone());
+
+    if let Some(entry_webhook) = &config.entry_webhook {
+        let entry_webhook = EntryWebhook::new(entry_webhook, catalog.clone())?;
+        tokio::spawn(async move { entry_webhook.run().await });
+    }
+
+    if let Some(metrics_config) = &config.metrics {
+        let catalog_entries = registry.gauge(
+            "e4k_server_catalog_entries",
+            "Registration entries in the catalog.",
+        );
+        start_catalog_metrics_task(catalog.clone(), catalog_entries);
+
+        metrics::server::start(metrics_config.address, Arc::new(registry))?;
+    }
+
+    if let Some(bundle_endpoint) = &config.federation_bundle_endpoint {
+        federation::endpoint::start(
+            bundle_endpoint,
+            &config.trust_domain,
+            trust_bundle_builder.clone(),
+        )
+        .await?;
+    }
+
+    if !config.federates_with.is_empty() {
+        let federation_manager = FederationManager::new(&config.federates_with, catalog.clone())?;
+        tokio::spawn(async move { federation_manager.run().await });
+    }
+
+    if let Some(oidc_discovery) = &config.oidc_discovery {
+        // The config is not loaded without an issuer.
+        let issuer = config.jwt.issuer.as_deref().unwrap_or_default();
+        oidc_discovery::start(
+            oidc_discovery,
+            issuer,
+            config.jwt.key_type,
+            trust_bundle_builder.clone(),
+        )
+        .await?;
+    }
+
+    if let Some(bundle_config_map) = &config.bundle_config_map {
+        let publisher =
+            ConfigMapPublisher::new(bundle_config_map, trust_bundle_builder.clone(), client);
+        tokio::spawn(publisher.run());
+    }
+
+    // The audit log is read once before serving, no SVID is issued while it cannot be written.
+    let svid_audit = match &config.svid_audit {
+        Some(svid_audit) => {
+            let svid_audit = Arc::new(SvidAudit::new(svid_audit, catalog.clone()));
+            svid_audit
+                .list(&IssuedSvidFilter::default(), None, 1)
+                .await
+                .map_err(Error::SvidAudit)?;
+            Some(svid_audit)
+        }
+        None => None,
+    };
+
+    let admin_audit = match &config.admin_audit {
+        Some(admin_audit) => {
+            let admin_audit = Arc::new(AdminAudit::new(admin_audit, catalog.clone()));
+            admin_audit
+                .list(&AdminOperationFilter::default(), None, 1)
+                .await
+                .map_err(Error::AdminAudit)?;
+            Some(admin_audit)
+        }
+        None => None,
+    };
+
+    let admin_api_handle = admin_api::start_admin_api(
+        &config,
+        catalog.clone(),
+        entry_pruner,
+        agent_bans.clone(),
+        admin_key_manager,
+        svid_audit.clone(),
+        admin_audit,
+        faults,
+        build,
+    )
+    .await?;
     let server_api_handle = server_api::start_server_api(
         &config,
         svid_factory,
         trust_bundle_builder,
         node_attestation,
         identity_matcher,
+        agent_bans,
+        Arc::new(AttestedAgents::new(catalog)),
+        svid_audit,
+        server_api_metrics,
     )
     .await?;
 
@@ -122,5 +331,93 @@ async fn main_inner() -> Result<(), Box<dyn StdError>> {
     key_manager_shutdown_signal_tx.notify_one();
     let _wait = key_manager_handle.await;
 
+    entry_pruner_shutdown_signal_tx.notify_one();
+    let _wait = entry_pruner_handle.await;
+
     Ok(())
 }
+
+// Read from the rotation metrics the key manager already keeps.
+fn register_key_manager_metrics(registry: &mut Registry, key_manager: &Arc<KeyManager>) {
+    let key_manager_checks = key_manager.clone();
+    registry.counter_fn(
+        "e4k_server_key_rotation_checks_total",
+        "Periodic checks of the signing keys.",
+        move || key_manager_checks.rotation_metrics.snapshot().checks,
+    );
+
+    let key_manager_failures = key_manager.clone();
+    registry.counter_fn(
+        "e4k_server_key_rotation_failures_total",
+        "Periodic checks of the signing keys that failed.",
+        move || key_manager_failures.rotation_metrics.snapshot().failures,
+    );
+
+    let key_manager_rotations = key_manager.clone();
+    registry.counter_fn(
+        "e4k_server_key_rotations_total",
+        "Times the next signing key replaced the current one.",
+        move || key_manager_rotations.rotation_metrics.snapshot().rotations,
+    );
+}
+
+fn start_catalog_metrics_task(catalog: Arc<dyn Catalog>, catalog_entries: Arc<Gauge>) {
+    tokio::spawn(async move {
+        let mut interval = time::interval(CATALOG_METRICS_PERIOD);
+
+        loop {
+            interval.tick().await;
+            match count_entries(&*catalog).await {
+                Ok(count) => catalog_entries.set(count),
+                Err(err) => warn!("Could not count the catalog entries: {}", err),
+            }
+        }
+    });
+}
+
+fn start_agent_bans_refresh_task(agent_bans: Arc<AgentBans>) {
+    tokio::spawn(async move {
+        let mut interval = time::interval(AGENT_BANS_REFRESH_PERIOD);
+
+        loop {
+            interval.tick().await;
+            if let Err(err) = agent_bans.refresh().await {
+                if let Some(catalog::Error::Unsupported(_)) = err.downcast_ref::<catalog::Error>() {
+                    info!("Agent bans are not supported by the catalog backend");
+                    break;
+                }
+                warn!("Could not refresh the agent bans: {}", err);
+            }
+        }
+    });
+}
+
+async fn count_entries(catalog: &dyn Catalog) -> Result<u64, Box<dyn std::error::Error + Send>> {
+    scan_entries(catalog, CATALOG_METRICS_PAGE_SIZE)
+        .try_fold(0, |count, _entry| future::ok(count + 1))
+        .await
+}
+
+// One line per component, so the configuration of each server of a fleet can be compared from the logs.
+fn log_startup_banner(config: &Config, build: &BuildInfo, catalog_version: Option<String>) {
+    let catalog = info_api::catalog_backend(&config.catalog);
+    let key_store = info_api::key_store_backend(&config.key_store);
+
+    info!(
+        "server_version={} trust_domain={}",
+        env!("CARGO_PKG_VERSION"),
+        config.trust_domain
+    );
+    info!("build {}", build);
+    info!(
+        "catalog type={} location={} version={}",
+        catalog.backend_type,
+        catalog.location.as_deref().unwrap_or("-"),
+        catalog_version.as_deref().unwrap_or("-")
+    );
+    info!(
+        "key_store type={} location={}",
+        key_store.backend_type,
+        key_store.location.as_deref().unwrap_or("-")
+    );
+}
@@ -1,5 +1,5 @@
 // Copyright (c) Microsoft. All rights reserved.
-use core_objects::KeyType;
+use core_objects::{spiffe_id, KeyType};
 
 use thiserror::Error;
 
@@ -9,6 +9,10 @@ pub enum Error {
     ErrorJSONSerializing(serde_json::Error),
     #[error("Error while signing digest with current key {0}")]
     SigningDigest(Box<dyn std::error::Error + Send>),
+    #[error("Error converting the ECDSA signature to the JOSE format {0}")]
+    EcdsaSignatureToJose(openssl::error::ErrorStack),
+    #[error("Invalid SPIFFE ID path {0}: {1}")]
+    InvalidSpiffeId(String, spiffe_id::Error),
     #[error("Key type not implemented {0:?}")]
     UnimplementedKeyType(KeyType),
 }
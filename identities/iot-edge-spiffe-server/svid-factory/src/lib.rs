@@ -12,19 +12,20 @@
 
 pub mod error;
 
-use std::{cmp::min, sync::Arc};
+use std::{cmp::min, collections::BTreeMap, sync::Arc};
 
 use core_objects::{
-    get_epoch_time, IdentityTypes, JWTClaims, JWTHeader, JWTSVIDCompact, JWTType, SPIFFE_ID_PREFIX,
+    get_epoch_time, IdentityTypes, JWTClaims, JWTHeader, JWTSVIDCompact, JWTType, KeyType, SpiffeId,
 };
 use error::Error;
 use key_manager::KeyManager;
-use openssl::sha;
-use server_config::Config;
+use openssl::{ecdsa::EcdsaSig, rand, sha};
+use serde_json::Value;
+use server_config::{Config, JWTConfig};
 
 pub struct SVIDFactory {
     key_manager: Arc<KeyManager>,
-    jwt_ttl: u64,
+    jwt_config: JWTConfig,
     trust_domain: String,
 }
 
@@ -33,6 +34,11 @@ pub struct JWTSVIDParams {
     pub spiffe_id_path: String,
     pub audiences: Vec<String>,
     pub other_identities: Vec<IdentityTypes>,
+    pub pod_uid: Option<String>,
+    // TTL of the entry, the lifetime of the JWT-SVID is the TTL of the config when not set.
+    pub ttl: Option<u64>,
+    // Custom claims of the entry, the issuance policy already checked they are allowed.
+    pub custom_claims: BTreeMap<String, String>,
 }
 
 impl SVIDFactory {
@@ -40,7 +46,7 @@ impl SVIDFactory {
     pub fn new(key_manager: Arc<KeyManager>, config: &Config) -> Self {
         SVIDFactory {
             key_manager,
-            jwt_ttl: config.jwt.ttl,
+            jwt_config: config.jwt.clone(),
             trust_domain: config.trust_domain.clone(),
         }
     }
@@ -54,69 +60,168 @@ impl SVIDFactory {
         self.create_jwt_svid_inner(jwt_svid_params, issued_at).await
     }
 
+    // The JWT-SVIDs are signed in a single batch by the key store, the key is only looked up once.
+    pub async fn create_jwt_svids(
+        &self,
+        jwt_svid_params: Vec<JWTSVIDParams>,
+    ) -> Result<Vec<JWTSVIDCompact>, Error> {
+        let issued_at = get_epoch_time();
+
+        self.create_jwt_svids_inner(jwt_svid_params, issued_at)
+            .await
+    }
+
     async fn create_jwt_svid_inner(
         &self,
         jwt_svid_params: JWTSVIDParams,
         issued_at: u64,
     ) -> Result<JWTSVIDCompact, Error> {
+        let mut jwt_svids = self
+            .create_jwt_svids_inner(vec![jwt_svid_params], issued_at)
+            .await?;
+
+        Ok(jwt_svids.remove(0))
+    }
+
+    async fn create_jwt_svids_inner(
+        &self,
+        jwt_svid_params: Vec<JWTSVIDParams>,
+        issued_at: u64,
+    ) -> Result<Vec<JWTSVIDCompact>, Error> {
+        if jwt_svid_params.is_empty() {
+            return Ok(Vec::new());
+        }
+
         let slots = &*self.key_manager.slots.read().await;
         let jwt_key = &slots.current_jwt_key;
 
-        let expiry = issued_at + self.jwt_ttl;
-        // Do not generate an svid with a lifetime bigger than the private key.
-        let expiry = min(expiry, jwt_key.expiry);
-
         let header = JWTHeader {
             algorithm: self.key_manager.jwt_key_type,
-            key_id: jwt_key.id.clone(),
+            key_id: jwt_key.kid.clone(),
             jwt_type: JWTType::JWT,
         };
 
-        // Craft spiffe id by concatenating the trust domain and path.
-        let spiffe_id = format!(
-            "{}{}/{}",
-            SPIFFE_ID_PREFIX, self.trust_domain, jwt_svid_params.spiffe_id_path
-        );
-
-        let claims = JWTClaims {
-            subject: spiffe_id.clone(),
-            audience: jwt_svid_params.audiences,
-            expiry,
-            issued_at,
-            other_identities: jwt_svid_params.other_identities,
-        };
-
         let header_compact = serde_json::to_string(&header).map_err(Error::ErrorJSONSerializing)?;
         let header_compact =
-            base64::encode_config(header_compact.as_bytes(), base64::STANDARD_NO_PAD);
-
-        let claims_compact = serde_json::to_string(&claims).map_err(Error::ErrorJSONSerializing)?;
-        let claims_compact =
-            base64::encode_config(claims_compact.as_bytes(), base64::STANDARD_NO_PAD);
-
-        let signature = format!("{}.{}", header_compact, claims_compact);
-
-        let signature = match self.key_manager.jwt_key_type {
-            core_objects::KeyType::ES256 => sha::sha256(signature.as_bytes()),
-            _ => return Err(Error::UnimplementedKeyType(self.key_manager.jwt_key_type)),
-        };
+            base64::encode_config(header_compact.as_bytes(), base64::URL_SAFE_NO_PAD);
+
+        let not_before = self
+            .jwt_config
+            .not_before_skew
+            .map(|skew| issued_at.saturating_sub(skew));
+
+        let mut unsigned = Vec::with_capacity(jwt_svid_params.len());
+        let mut digests = Vec::with_capacity(jwt_svid_params.len());
+        for jwt_svid_params in jwt_svid_params {
+            let spiffe_id =
+                SpiffeId::from_entry_path(&self.trust_domain, &jwt_svid_params.spiffe_id_path)
+                    .map_err(|err| {
+                        Error::InvalidSpiffeId(jwt_svid_params.spiffe_id_path.clone(), err)
+                    })?
+                    .to_string();
+
+            let expiry = issued_at + self.jwt_config.entry_ttl(jwt_svid_params.ttl);
+            // Do not generate an svid with a lifetime bigger than the private key.
+            let expiry = min(expiry, jwt_key.expiry);
+
+            let claims = JWTClaims {
+                subject: spiffe_id.clone(),
+                audience: jwt_svid_params.audiences,
+                expiry,
+                issued_at,
+                jwt_id: self.jwt_config.jwt_id.then(jwt_id),
+                not_before,
+                issuer: self.jwt_config.issuer.clone(),
+                other_identities: jwt_svid_params.other_identities,
+                pod_uid: jwt_svid_params.pod_uid,
+                custom_claims: jwt_svid_params
+                    .custom_claims
+                    .into_iter()
+                    .map(|(name, value)| (name, Value::String(value)))
+                    .collect(),
+            };
+
+            let claims_compact =
+                serde_json::to_string(&claims).map_err(Error::ErrorJSONSerializing)?;
+            let claims_compact =
+                base64::encode_config(claims_compact.as_bytes(), base64::URL_SAFE_NO_PAD);
+
+            let signature = format!("{}.{}", header_compact, claims_compact);
+            digests.push(digest(self.key_manager.jwt_key_type, signature));
+            unsigned.push((spiffe_id, claims_compact, expiry));
+        }
 
-        let signature = self
+        let signatures = self
             .key_manager
             .key_store
-            .sign(&jwt_key.id, self.key_manager.jwt_key_type, &signature)
+            .sign_batch(&jwt_key.id, self.key_manager.jwt_key_type, &digests)
             .await
             .map_err(Error::SigningDigest)?;
 
-        let signature = base64::encode_config(signature.1, base64::STANDARD_NO_PAD);
-        let token = format!("{}.{}.{}", header_compact, claims_compact, signature);
+        let jwt_svids = unsigned
+            .into_iter()
+            .zip(signatures)
+            .map(|((spiffe_id, claims_compact, expiry), signature)| {
+                let signature = jose_signature(self.key_manager.jwt_key_type, signature.1)?;
+                let signature = base64::encode_config(signature, base64::URL_SAFE_NO_PAD);
+                let token = format!("{}.{}.{}", header_compact, claims_compact, signature);
+
+                Ok(JWTSVIDCompact {
+                    token,
+                    spiffe_id,
+                    expiry,
+                    issued_at,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(jwt_svids)
+    }
+}
+
+// 128 random bits, unique for all practical purposes.
+fn jwt_id() -> String {
+    let mut random = [0; 16];
+    rand::rand_bytes(&mut random).expect("cannot fail to generate random bytes");
 
-        Ok(JWTSVIDCompact {
-            token,
-            spiffe_id,
-            expiry,
-            issued_at,
-        })
+    random.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// The key stores return DER encoded ECDSA signatures, JWS wants R and S concatenated, each left padded to the
+// size of the curve order.
+fn jose_signature(key_type: KeyType, signature: Vec<u8>) -> Result<Vec<u8>, Error> {
+    let component_len = match key_type.ecdsa_component_len() {
+        Some(component_len) => component_len,
+        None => return Ok(signature),
+    };
+
+    let signature = EcdsaSig::from_der(&signature).map_err(Error::EcdsaSignatureToJose)?;
+    let mut jose = Vec::with_capacity(2 * component_len);
+    for component in [signature.r(), signature.s()] {
+        let component = component.to_vec();
+        jose.resize(
+            jose.len() + component_len.saturating_sub(component.len()),
+            0,
+        );
+        jose.extend_from_slice(&component);
+    }
+
+    Ok(jose)
+}
+
+fn digest(key_type: KeyType, signature: String) -> Vec<u8> {
+    match key_type {
+        KeyType::ES256 | KeyType::RS256 | KeyType::PS256 => {
+            sha::sha256(signature.as_bytes()).to_vec()
+        }
+        KeyType::ES384 | KeyType::RS384 | KeyType::PS384 => {
+            sha::sha384(signature.as_bytes()).to_vec()
+        }
+        KeyType::ES512 | KeyType::RS512 | KeyType::PS512 => {
+            sha::sha512(signature.as_bytes()).to_vec()
+        }
+        // EdDSA hashes the message itself.
+        KeyType::EdDSA => signature.into_bytes(),
     }
 }
 
@@ -124,17 +229,31 @@ impl SVIDFactory {
 mod tests {
     use super::*;
     use catalog::inmemory;
-    use core_objects::CONFIG_DEFAULT_PATH;
+    use core_objects::IoTHubId;
+    use core_objects::{CONFIG_DEFAULT_PATH, SPIFFE_ID_PREFIX};
     use key_manager::KeyManager;
     use key_store::disk;
     use matches::assert_matches;
+    use openssl::{
+        bn::BigNum,
+        ec::{EcGroup, EcKey},
+        nid::Nid,
+    };
+    use proptest::{
+        collection, option, prop_assert, prop_assert_eq, prop_oneof,
+        strategy::Strategy,
+        test_runner::{Config as ProptestConfig, TestRunner},
+    };
     use server_config::{Config, KeyStoreConfig, KeyStoreConfigDisk};
     use std::sync::Arc;
 
     async fn init(dir: &tempfile::TempDir) -> (SVIDFactory, Config) {
         let mut config = Config::load_config(CONFIG_DEFAULT_PATH).unwrap();
         let key_base_path = dir.path().to_str().unwrap().to_string();
-        let key_plugin = KeyStoreConfigDisk { key_base_path };
+        let key_plugin = KeyStoreConfigDisk {
+            key_base_path,
+            encryption: None,
+        };
 
         // Change key disk plugin path to write in tempdir
         config.key_store = KeyStoreConfig::Disk(key_plugin.clone());
@@ -142,7 +261,7 @@ mod tests {
         config.jwt.key_ttl = 300;
 
         let catalog = Arc::new(inmemory::Catalog::new());
-        let key_store = Arc::new(disk::KeyStore::new(&key_plugin));
+        let key_store = Arc::new(disk::KeyStore::new(&key_plugin).unwrap());
 
         let key_manager = Arc::new(
             KeyManager::new(&config, catalog.clone(), key_store.clone(), 0)
@@ -163,6 +282,9 @@ mod tests {
             spiffe_id_path: spiffe_id_path.clone(),
             audiences: vec!["my trust domain/audiences".to_string()],
             other_identities: Vec::new(),
+            pod_uid: None,
+            ttl: None,
+            custom_claims: Default::default(),
         };
 
         let jwt_svid = svid_factory
@@ -191,6 +313,9 @@ mod tests {
             spiffe_id_path,
             audiences: vec!["my trust domain/audiences".to_string()],
             other_identities: Vec::new(),
+            pod_uid: None,
+            ttl: None,
+            custom_claims: Default::default(),
         };
 
         // Generate an SVID close to the key expiration. The expiry time should not be after the expiration.
@@ -202,6 +327,158 @@ mod tests {
         assert_eq!(config.jwt.key_ttl, jwt_svid.expiry);
     }
 
+    #[tokio::test]
+    async fn jwt_id_and_not_before() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (mut svid_factory, _config) = init(&tmp).await;
+
+        let jwt_svid_params = JWTSVIDParams {
+            spiffe_id_path: "path".to_string(),
+            audiences: vec!["my trust domain/audiences".to_string()],
+            other_identities: Vec::new(),
+            pod_uid: None,
+            ttl: None,
+            custom_claims: Default::default(),
+        };
+
+        let claims = |jwt_svid: JWTSVIDCompact| -> JWTClaims {
+            let claims = jwt_svid.token.split('.').nth(1).unwrap();
+            let claims = base64::decode_config(claims, base64::URL_SAFE_NO_PAD).unwrap();
+            serde_json::from_slice(&claims).unwrap()
+        };
+
+        let jwt_svids = svid_factory
+            .create_jwt_svids_inner(vec![jwt_svid_params.clone(), jwt_svid_params.clone()], 100)
+            .await
+            .unwrap();
+        let jwt_ids: Vec<Option<String>> = jwt_svids
+            .into_iter()
+            .map(|jwt_svid| claims(jwt_svid).jwt_id)
+            .collect();
+        assert!(jwt_ids[0].is_some());
+        assert_ne!(jwt_ids[0], jwt_ids[1]);
+
+        svid_factory.jwt_config.issuer = Some("https://oidc.contoso.com".to_string());
+        let jwt_svid = svid_factory
+            .create_jwt_svid_inner(jwt_svid_params.clone(), 100)
+            .await
+            .unwrap();
+        assert_eq!(
+            claims(jwt_svid).issuer.as_deref(),
+            Some("https://oidc.contoso.com")
+        );
+
+        svid_factory.jwt_config.jwt_id = false;
+        svid_factory.jwt_config.not_before_skew = Some(30);
+        let jwt_svid = svid_factory
+            .create_jwt_svid_inner(jwt_svid_params, 100)
+            .await
+            .unwrap();
+        let claims = claims(jwt_svid);
+        assert_eq!(claims.jwt_id, None);
+        assert_eq!(claims.not_before, Some(70));
+    }
+
+    #[tokio::test]
+    async fn custom_claims() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (svid_factory, _config) = init(&tmp).await;
+
+        let jwt_svid_params = JWTSVIDParams {
+            spiffe_id_path: "path".to_string(),
+            audiences: vec!["my trust domain/audiences".to_string()],
+            other_identities: Vec::new(),
+            pod_uid: None,
+            ttl: None,
+            custom_claims: BTreeMap::from([("ring".to_string(), "canary".to_string())]),
+        };
+
+        let jwt_svid = svid_factory
+            .create_jwt_svid_inner(jwt_svid_params, 100)
+            .await
+            .unwrap();
+        let claims = jwt_svid.token.split('.').nth(1).unwrap();
+        let claims = base64::decode_config(claims, base64::URL_SAFE_NO_PAD).unwrap();
+        let claims: Value = serde_json::from_slice(&claims).unwrap();
+        assert_eq!(claims["ring"], "canary");
+    }
+
+    #[tokio::test]
+    async fn entry_ttl() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (svid_factory, config) = init(&tmp).await;
+
+        let jwt_svid_params = [Some(1), Some(config.jwt.key_ttl), None]
+            .iter()
+            .map(|ttl| JWTSVIDParams {
+                spiffe_id_path: "path".to_string(),
+                audiences: vec!["my trust domain/audiences".to_string()],
+                other_identities: Vec::new(),
+                pod_uid: None,
+                ttl: *ttl,
+                custom_claims: Default::default(),
+            })
+            .collect();
+
+        let jwt_svids = svid_factory
+            .create_jwt_svids_inner(jwt_svid_params, 0)
+            .await
+            .unwrap();
+
+        // The entry can shorten the lifetime, not make it longer than the max.
+        let expiries: Vec<u64> = jwt_svids.iter().map(|jwt_svid| jwt_svid.expiry).collect();
+        assert_eq!(
+            vec![
+                1,
+                config.jwt.max_entry_ttl.unwrap_or(config.jwt.ttl),
+                config.jwt.ttl
+            ],
+            expiries
+        );
+    }
+
+    #[tokio::test]
+    async fn create_jwt_svids_batch() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (svid_factory, config) = init(&tmp).await;
+
+        let jwt_svid_params = ["path1", "path2"]
+            .iter()
+            .map(|spiffe_id_path| JWTSVIDParams {
+                spiffe_id_path: spiffe_id_path.to_string(),
+                audiences: vec!["my trust domain/audiences".to_string()],
+                other_identities: Vec::new(),
+                pod_uid: None,
+                ttl: None,
+                custom_claims: Default::default(),
+            })
+            .collect();
+
+        let jwt_svids = svid_factory
+            .create_jwt_svids(jwt_svid_params)
+            .await
+            .unwrap();
+
+        let spiffe_ids = jwt_svids
+            .iter()
+            .map(|jwt_svid| jwt_svid.spiffe_id.clone())
+            .collect::<Vec<String>>();
+        assert_eq!(
+            spiffe_ids,
+            vec![
+                format!("{}{}/path1", SPIFFE_ID_PREFIX, config.trust_domain),
+                format!("{}{}/path2", SPIFFE_ID_PREFIX, config.trust_domain),
+            ]
+        );
+        assert_ne!(jwt_svids[0].token, jwt_svids[1].token);
+
+        assert!(svid_factory
+            .create_jwt_svids(Vec::new())
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
     #[tokio::test]
     async fn sign_digest_error_path() {
         let tmp = tempfile::tempdir().unwrap();
@@ -220,6 +497,9 @@ mod tests {
             spiffe_id_path,
             audiences: vec!["my trust domain/audiences".to_string()],
             other_identities: Vec::new(),
+            pod_uid: None,
+            ttl: None,
+            custom_claims: Default::default(),
         };
 
         let error = svid_factory
@@ -228,4 +508,157 @@ mod tests {
             .unwrap_err();
         assert_matches!(error, Error::SigningDigest(_));
     }
+
+    #[tokio::test]
+    async fn invalid_spiffe_id_path() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (svid_factory, _config) = init(&tmp).await;
+
+        for spiffe_id_path in ["", "ns//web", "ns/../web", "web?query"] {
+            let jwt_svid_params = JWTSVIDParams {
+                spiffe_id_path: spiffe_id_path.to_string(),
+                audiences: vec!["my trust domain/audiences".to_string()],
+                other_identities: Vec::new(),
+                pod_uid: None,
+                ttl: None,
+                custom_claims: Default::default(),
+            };
+
+            let error = svid_factory
+                .create_jwt_svid(jwt_svid_params)
+                .await
+                .unwrap_err();
+            assert_matches!(error, Error::InvalidSpiffeId(_, _));
+        }
+    }
+
+    fn identity_strategy() -> impl Strategy<Value = IdentityTypes> {
+        prop_oneof![
+            "\\PC{0,32}".prop_map(IdentityTypes::Custom),
+            ("\\PC{1,32}", "\\PC{1,32}", "\\PC{0,32}").prop_map(
+                |(iot_hub_hostname, device_id, module_id)| {
+                    IdentityTypes::IoTHub(IoTHubId {
+                        iot_hub_hostname,
+                        device_id,
+                        module_id,
+                    })
+                }
+            ),
+        ]
+    }
+
+    fn jwt_svid_params_strategy() -> impl Strategy<Value = JWTSVIDParams> {
+        (
+            // No "." or ".." segments, they are not allowed in SPIFFE IDs.
+            "[a-zA-Z0-9_-][a-zA-Z0-9._-]{0,15}(/[a-zA-Z0-9_-][a-zA-Z0-9._-]{0,15}){0,4}",
+            collection::vec("\\PC{1,48}", 1..4),
+            collection::vec(identity_strategy(), 0..3),
+            option::of("[0-9a-f]{8}(-[0-9a-f]{4}){3}-[0-9a-f]{12}"),
+        )
+            .prop_map(|(spiffe_id_path, audiences, other_identities, pod_uid)| {
+                JWTSVIDParams {
+                    spiffe_id_path,
+                    audiences,
+                    other_identities,
+                    pod_uid,
+                    ttl: None,
+                    custom_claims: Default::default(),
+                }
+            })
+    }
+
+    #[test]
+    fn jose_signature_fixed_length() {
+        for (key_type, curve) in [
+            (KeyType::ES256, Nid::X9_62_PRIME256V1),
+            (KeyType::ES384, Nid::SECP384R1),
+            (KeyType::ES512, Nid::SECP521R1),
+        ] {
+            let ec_key = EcKey::generate(&EcGroup::from_curve_name(curve).unwrap()).unwrap();
+            let component_len = key_type.ecdsa_component_len().unwrap();
+
+            // R and S are shorter than the curve order now and then, they must be padded.
+            for _ in 0..32 {
+                let signature = EcdsaSig::sign(&[1; 32], &ec_key).unwrap();
+                let jose = jose_signature(key_type, signature.to_der().unwrap()).unwrap();
+
+                assert_eq!(jose.len(), 2 * component_len);
+                let r = BigNum::from_slice(&jose[..component_len]).unwrap();
+                let s = BigNum::from_slice(&jose[component_len..]).unwrap();
+                assert_eq!(r.to_vec(), signature.r().to_vec());
+                assert_eq!(s.to_vec(), signature.s().to_vec());
+            }
+        }
+
+        assert_eq!(
+            jose_signature(KeyType::RS256, vec![1, 2, 3]).unwrap(),
+            vec![1, 2, 3]
+        );
+    }
+
+    fn is_base64url_no_pad(segment: &str) -> bool {
+        segment
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_')
+    }
+
+    #[test]
+    fn jwt_svid_compact_encoding_round_trip() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let tmp = tempfile::tempdir().unwrap();
+        // Key generation is expensive, share the same factory for all the cases.
+        let (svid_factory, config) = runtime.block_on(init(&tmp));
+
+        let mut runner = TestRunner::new(ProptestConfig {
+            cases: 128,
+            ..ProptestConfig::default()
+        });
+
+        runner
+            .run(&jwt_svid_params_strategy(), |jwt_svid_params| {
+                let jwt_svid = runtime
+                    .block_on(svid_factory.create_jwt_svid_inner(jwt_svid_params.clone(), 0))
+                    .unwrap();
+
+                let segments = jwt_svid.token.split('.').collect::<Vec<&str>>();
+                prop_assert_eq!(segments.len(), 3);
+
+                for segment in &segments {
+                    prop_assert!(!segment.is_empty());
+                    prop_assert!(
+                        is_base64url_no_pad(segment),
+                        "segment {} is not unpadded base64url",
+                        segment
+                    );
+                }
+
+                let header = base64::decode_config(segments[0], base64::URL_SAFE_NO_PAD).unwrap();
+                let header: JWTHeader = serde_json::from_slice(&header).unwrap();
+                prop_assert!(!header.key_id.is_empty());
+
+                let claims = base64::decode_config(segments[1], base64::URL_SAFE_NO_PAD).unwrap();
+                let claims: JWTClaims = serde_json::from_slice(&claims).unwrap();
+                let spiffe_id = format!(
+                    "{}{}/{}",
+                    SPIFFE_ID_PREFIX, config.trust_domain, jwt_svid_params.spiffe_id_path
+                );
+                prop_assert_eq!(&claims.subject, &spiffe_id);
+                prop_assert_eq!(&claims.audience, &jwt_svid_params.audiences);
+                prop_assert_eq!(&claims.other_identities, &jwt_svid_params.other_identities);
+                prop_assert_eq!(&claims.pod_uid, &jwt_svid_params.pod_uid);
+                prop_assert_eq!(claims.jwt_id.map(|jwt_id| jwt_id.len()), Some(32));
+                prop_assert_eq!(claims.not_before, None);
+
+                let signature =
+                    base64::decode_config(segments[2], base64::URL_SAFE_NO_PAD).unwrap();
+                // ES256 signatures are R and S, 32 bytes each.
+                prop_assert_eq!(signature.len(), 64);
+
+                Ok(())
+            })
+            .unwrap();
+    }
 }
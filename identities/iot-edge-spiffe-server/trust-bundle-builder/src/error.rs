@@ -5,4 +5,8 @@ use thiserror::Error;
 pub enum Error {
     #[error("Unable to get key from catalog {0}")]
     CatalogGetKeys(Box<dyn std::error::Error + Send>),
+    #[error("Unable to get the key slots from catalog {0}")]
+    CatalogGetKeySlots(Box<dyn std::error::Error + Send>),
+    #[error("Unable to get the federated bundles from catalog {0}")]
+    CatalogGetFederatedBundles(Box<dyn std::error::Error + Send>),
 }
@@ -10,18 +10,26 @@
     clippy::too_many_lines
 )]
 
-use std::sync::Arc;
+use std::{cmp, collections::BTreeSet, sync::Arc};
 
 use catalog::Catalog;
-use core_objects::{JWKSet, TrustBundle};
+use core_objects::{get_epoch_time, JWKSet, KeySlots, TrustBundle};
 use error::Error;
 use server_config::Config;
 
 pub mod error;
 
+// The keys are checked for rotation every 10 seconds, agents are not asked to refresh more often.
+const MIN_REFRESH_HINT: u64 = 10;
+
 pub struct TrustBundleBuilder {
     trust_domain: String,
+    // Maximum of the refresh hint, sent while no rotation is coming up.
     refresh_hint: u64,
+    prepare_rotation_margin: u64,
+    activate_rotation_margin: u64,
+    // Saved bundles of trust domains no longer in the configuration are not sent.
+    federated_trust_domains: BTreeSet<String>,
     catalog: Arc<dyn Catalog>,
 }
 
@@ -31,6 +39,13 @@ impl TrustBundleBuilder {
         Arc::new(TrustBundleBuilder {
             trust_domain: config.trust_domain.clone(),
             refresh_hint: config.trust_bundle.refresh_hint,
+            prepare_rotation_margin: config.jwt.prepare_rotation_margin(),
+            activate_rotation_margin: config.jwt.activate_rotation_margin(),
+            federated_trust_domains: config
+                .federates_with
+                .iter()
+                .map(|federated| federated.trust_domain.clone())
+                .collect(),
             catalog,
         })
     }
@@ -40,24 +55,35 @@ impl TrustBundleBuilder {
         jwt_keys: bool,
         _x509_cas: bool,
     ) -> Result<TrustBundle, Error> {
-        let (jwt_key, version) = if jwt_keys {
-            self.catalog
+        let (jwt_key, version, refresh_hint) = if jwt_keys {
+            let (jwt_key, version) = self
+                .catalog
                 .get_jwk(&self.trust_domain)
                 .await
-                .map_err(Error::CatalogGetKeys)?
+                .map_err(Error::CatalogGetKeys)?;
+            let key_slots = self
+                .catalog
+                .get_key_slots(&self.trust_domain)
+                .await
+                .map_err(Error::CatalogGetKeySlots)?;
+            let refresh_hint = key_slots.map_or(self.refresh_hint, |key_slots| {
+                self.refresh_hint(&key_slots, get_epoch_time())
+            });
+
+            (jwt_key, version, refresh_hint)
         } else {
-            (Vec::new(), 0)
+            (Vec::new(), 0, self.refresh_hint)
         };
 
         let jwt_key_set = JWKSet {
             keys: jwt_key,
-            spiffe_refresh_hint: self.refresh_hint,
+            spiffe_refresh_hint: refresh_hint,
             spiffe_sequence_number: version as u64,
         };
 
         let x509_key_set = JWKSet {
             keys: Vec::new(),
-            spiffe_refresh_hint: self.refresh_hint,
+            spiffe_refresh_hint: refresh_hint,
             spiffe_sequence_number: version as u64,
         };
 
@@ -67,16 +93,57 @@ impl TrustBundleBuilder {
             x509_key_set,
         })
     }
+
+    // Half of the time left until the key slots change, so that the agents poll more often as a rotation comes
+    // up and fetch the next key well before it signs. The next change is the creation of the next key, or its
+    // activation once it is published. Between the minimum and the configured refresh hint, the minimum once the
+    // rotation is due.
+    fn refresh_hint(&self, key_slots: &KeySlots, now: u64) -> u64 {
+        let rotation_margin = if key_slots.next.is_some() {
+            self.activate_rotation_margin
+        } else {
+            self.prepare_rotation_margin
+        };
+        let next_rotation = key_slots.current.expiry.saturating_sub(rotation_margin);
+        let refresh_hint = next_rotation.saturating_sub(now) / 2;
+
+        cmp::min(cmp::max(refresh_hint, MIN_REFRESH_HINT), self.refresh_hint)
+    }
+
+    // Bundles of the federated trust domains, sorted by trust domain. A trust domain whose bundle was not fetched
+    // yet has none.
+    pub async fn build_federated_bundles(&self) -> Result<Vec<TrustBundle>, Error> {
+        if self.federated_trust_domains.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let federated_bundles = self
+            .catalog
+            .get_federated_bundles()
+            .await
+            .map_err(Error::CatalogGetFederatedBundles)?;
+
+        Ok(federated_bundles
+            .into_iter()
+            .filter(|trust_bundle| {
+                self.federated_trust_domains
+                    .contains(&trust_bundle.trust_domain)
+            })
+            .collect())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use catalog::inmemory;
-    use core_objects::CONFIG_DEFAULT_PATH;
+    use catalog::{inmemory, TrustBundleStore};
+    use core_objects::{KeySlot, CONFIG_DEFAULT_PATH};
     use key_manager::KeyManager;
     use key_store::disk;
-    use server_config::{Config, KeyStoreConfig, KeyStoreConfigDisk};
+    use server_config::{
+        BundleEndpointClientProfile, Config, FederatedTrustDomainConfig, KeyStoreConfig,
+        KeyStoreConfigDisk,
+    };
 
     use std::sync::Arc;
 
@@ -84,7 +151,10 @@ mod tests {
         let mut config = Config::load_config(CONFIG_DEFAULT_PATH).unwrap();
         let dir = tempfile::tempdir().unwrap();
         let key_base_path = dir.path().to_str().unwrap().to_string();
-        let key_plugin = KeyStoreConfigDisk { key_base_path };
+        let key_plugin = KeyStoreConfigDisk {
+            key_base_path,
+            encryption: None,
+        };
 
         // Change key disk plugin path to write in tempdir
         config.key_store = KeyStoreConfig::Disk(key_plugin.clone());
@@ -92,7 +162,7 @@ mod tests {
         config.jwt.key_ttl = 300;
 
         let catalog = Arc::new(inmemory::Catalog::new());
-        let key_store = Arc::new(disk::KeyStore::new(&key_plugin));
+        let key_store = Arc::new(disk::KeyStore::new(&key_plugin).unwrap());
 
         let key_manager = KeyManager::new(&config, catalog.clone(), key_store.clone(), 0)
             .await
@@ -110,7 +180,7 @@ mod tests {
         let (trust_bundle_builder, config, key_manager) = init().await;
 
         let slots = key_manager.slots.read().await;
-        let id = slots.current_jwt_key.id.clone();
+        let kid = slots.current_jwt_key.kid.clone();
 
         let trust_bundle = trust_bundle_builder
             .build_trust_bundle(true, false)
@@ -125,7 +195,7 @@ mod tests {
             config.trust_bundle.refresh_hint,
             trust_bundle.jwt_key_set.spiffe_refresh_hint
         );
-        assert_eq!(id, jwk.kid);
+        assert_eq!(kid, jwk.kid);
 
         let trust_bundle = trust_bundle_builder
             .build_trust_bundle(false, false)
@@ -133,4 +203,88 @@ mod tests {
             .unwrap();
         assert_eq!(0, trust_bundle.jwt_key_set.keys.len());
     }
+
+    #[test]
+    fn refresh_hint_follows_rotation() {
+        let mut config = Config::load_config(CONFIG_DEFAULT_PATH).unwrap();
+        config.jwt.key_ttl = 3600;
+        config.jwt.prepare_rotation_fraction = 0.5;
+        config.jwt.activate_rotation_fraction = 0.25;
+        config.trust_bundle.refresh_hint = 300;
+        let catalog = Arc::new(inmemory::Catalog::new());
+        let trust_bundle_builder = TrustBundleBuilder::new(&config, catalog);
+
+        let key_slot = |expiry| KeySlot {
+            id: "key".to_string(),
+            kid: String::new(),
+            expiry,
+        };
+        let mut key_slots = KeySlots {
+            previous: None,
+            current: key_slot(3600),
+            next: None,
+            revoked: Vec::new(),
+        };
+
+        // The next key is created at 1800.
+        assert_eq!(300, trust_bundle_builder.refresh_hint(&key_slots, 0));
+        assert_eq!(200, trust_bundle_builder.refresh_hint(&key_slots, 1400));
+        assert_eq!(10, trust_bundle_builder.refresh_hint(&key_slots, 1790));
+        assert_eq!(10, trust_bundle_builder.refresh_hint(&key_slots, 1900));
+
+        // Published, it signs from 2700.
+        key_slots.next = Some(key_slot(7200));
+        assert_eq!(300, trust_bundle_builder.refresh_hint(&key_slots, 1800));
+        assert_eq!(100, trust_bundle_builder.refresh_hint(&key_slots, 2500));
+        assert_eq!(10, trust_bundle_builder.refresh_hint(&key_slots, 2800));
+    }
+
+    #[tokio::test]
+    async fn build_federated_bundles_happy_path() {
+        let mut config = Config::load_config(CONFIG_DEFAULT_PATH).unwrap();
+        let catalog = Arc::new(inmemory::Catalog::new());
+        let federated_bundle = |trust_domain: &str| TrustBundle {
+            trust_domain: trust_domain.to_string(),
+            jwt_key_set: JWKSet {
+                keys: Vec::new(),
+                spiffe_refresh_hint: 300,
+                spiffe_sequence_number: 1,
+            },
+            x509_key_set: JWKSet {
+                keys: Vec::new(),
+                spiffe_refresh_hint: 300,
+                spiffe_sequence_number: 1,
+            },
+        };
+        catalog
+            .set_federated_bundle(federated_bundle("cloud.contoso.com"))
+            .await
+            .unwrap();
+        catalog
+            .set_federated_bundle(federated_bundle("old.contoso.com"))
+            .await
+            .unwrap();
+
+        let trust_bundle_builder = TrustBundleBuilder::new(&config, catalog.clone());
+        let federated_bundles = trust_bundle_builder
+            .build_federated_bundles()
+            .await
+            .unwrap();
+        assert!(federated_bundles.is_empty());
+
+        config.federates_with = vec![FederatedTrustDomainConfig {
+            trust_domain: "cloud.contoso.com".to_string(),
+            bundle_endpoint_url: "https://spire.contoso.com:8443".to_string(),
+            bundle_endpoint_profile: BundleEndpointClientProfile::HttpsWeb,
+        }];
+        let trust_bundle_builder = TrustBundleBuilder::new(&config, catalog);
+        let federated_bundles = trust_bundle_builder
+            .build_federated_bundles()
+            .await
+            .unwrap();
+        assert_eq!(
+            federated_bundles,
+            vec![federated_bundle("cloud.contoso.com")]
+        );
+    }
 }
@@ -0,0 +1,200 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use std::str::FromStr;
+
+use agent_config::ServerProtocol;
+use thiserror::Error;
+
+pub const DEFAULT_FLEET_SIZES: &[usize] = &[100, 1000, 5000];
+pub const DEFAULT_WORKLOADS_PER_AGENT: usize = 10;
+pub const DEFAULT_REQUESTS_PER_AGENT: usize = 10;
+pub const DEFAULT_KEY_TTL_SECS: u64 = 30;
+pub const DEFAULT_REFRESH_SECS: u64 = 1;
+pub const DEFAULT_PORT: u16 = 18443;
+
+pub const USAGE: &str = "\
+Usage: fleet-sim [options]
+
+Simulates fleets of agents against an in-process server, with the real catalog, key manager and server
+API, and reports the issuance latency and the propagation time of the new signing keys per fleet size.
+
+Options:
+    --fleet-sizes <n,n,...>          Number of agents of each run [default: 100,1000,5000]
+    --workloads-per-agent <n>        Workloads on the node of each agent [default: 10]
+    --requests-per-agent <n>         JWT-SVID requests sent by each agent [default: 10]
+    --attestation-latency-ms <ms>    Time taken by each agent attestation, to stand for the token
+                                     review of the PSAT attestation [default: 0]
+    --key-ttl-secs <secs>            Lifetime of the JWT signing keys, a new key is published about
+                                     every half of it [default: 30]
+    --refresh-secs <secs>            Trust bundle refresh period of the HTTP agents [default: 1]
+    --protocol <http|grpc>           Protocol of the server-agent API [default: http]
+    --port <port>                    Local port of the server-agent API, gRPC uses the next one
+                                     [default: 18443]
+    --help                           Print this message";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Help,
+    Run(Options),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub fleet_sizes: Vec<usize>,
+    pub workloads_per_agent: usize,
+    pub requests_per_agent: usize,
+    pub attestation_latency_ms: u64,
+    pub key_ttl_secs: u64,
+    pub refresh_secs: u64,
+    pub protocol: ServerProtocol,
+    pub port: u16,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            fleet_sizes: DEFAULT_FLEET_SIZES.to_vec(),
+            workloads_per_agent: DEFAULT_WORKLOADS_PER_AGENT,
+            requests_per_agent: DEFAULT_REQUESTS_PER_AGENT,
+            attestation_latency_ms: 0,
+            key_ttl_secs: DEFAULT_KEY_TTL_SECS,
+            refresh_secs: DEFAULT_REFRESH_SECS,
+            protocol: ServerProtocol::Http,
+            port: DEFAULT_PORT,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum Error {
+    #[error("Unknown option {0}")]
+    UnknownOption(String),
+    #[error("Missing value for option {0}")]
+    MissingValue(String),
+    #[error("Invalid value {value} for option {option}")]
+    InvalidValue { option: String, value: String },
+}
+
+pub fn parse(args: &[String]) -> Result<Command, Error> {
+    let mut options = Options::default();
+
+    let mut args = args.iter();
+    while let Some(option) = args.next() {
+        if matches!(option.as_str(), "help" | "--help" | "-h") {
+            return Ok(Command::Help);
+        }
+
+        let value = args
+            .next()
+            .ok_or_else(|| Error::MissingValue(option.clone()))?;
+
+        match option.as_str() {
+            "--fleet-sizes" => {
+                options.fleet_sizes = value
+                    .split(',')
+                    .map(|size| parse_value(option, size))
+                    .collect::<Result<_, _>>()?;
+            }
+            "--workloads-per-agent" => options.workloads_per_agent = parse_value(option, value)?,
+            "--requests-per-agent" => options.requests_per_agent = parse_value(option, value)?,
+            "--attestation-latency-ms" => {
+                options.attestation_latency_ms = parse_value(option, value)?;
+            }
+            "--key-ttl-secs" => options.key_ttl_secs = parse_value(option, value)?,
+            "--refresh-secs" => options.refresh_secs = parse_value(option, value)?,
+            "--protocol" => {
+                options.protocol = match value.as_str() {
+                    "http" => ServerProtocol::Http,
+                    "grpc" => ServerProtocol::Grpc,
+                    _ => return Err(invalid_value(option, value)),
+                };
+            }
+            "--port" => options.port = parse_value(option, value)?,
+            _ => return Err(Error::UnknownOption(option.clone())),
+        }
+    }
+
+    // Every agent needs at least one workload to request JWT-SVIDs for.
+    if options.workloads_per_agent == 0 {
+        return Err(invalid_value("--workloads-per-agent", "0"));
+    }
+    if options.refresh_secs == 0 {
+        return Err(invalid_value("--refresh-secs", "0"));
+    }
+
+    Ok(Command::Run(options))
+}
+
+fn parse_value<T: FromStr>(option: &str, value: &str) -> Result<T, Error> {
+    value.parse().map_err(|_| invalid_value(option, value))
+}
+
+fn invalid_value(option: &str, value: &str) -> Error {
+    Error::InvalidValue {
+        option: option.to_string(),
+        value: value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn parse_defaults() {
+        assert_eq!(parse(&[]).unwrap(), Command::Run(Options::default()));
+        assert_eq!(parse(&args(&["--help"])).unwrap(), Command::Help);
+    }
+
+    #[test]
+    fn parse_options() {
+        let command = parse(&args(&[
+            "--fleet-sizes",
+            "10,20",
+            "--attestation-latency-ms",
+            "50",
+            "--protocol",
+            "grpc",
+        ]))
+        .unwrap();
+
+        let options = match command {
+            Command::Run(options) => options,
+            Command::Help => panic!("Expected run command"),
+        };
+        assert_eq!(options.fleet_sizes, vec![10, 20]);
+        assert_eq!(options.attestation_latency_ms, 50);
+        assert_eq!(options.protocol, ServerProtocol::Grpc);
+        assert_eq!(options.requests_per_agent, DEFAULT_REQUESTS_PER_AGENT);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            parse(&args(&["--verbose", "1"])).unwrap_err(),
+            Error::UnknownOption("--verbose".to_string())
+        );
+        assert_eq!(
+            parse(&args(&["--port"])).unwrap_err(),
+            Error::MissingValue("--port".to_string())
+        );
+        assert_eq!(
+            parse(&args(&["--fleet-sizes", "10,many"])).unwrap_err(),
+            Error::InvalidValue {
+                option: "--fleet-sizes".to_string(),
+                value: "many".to_string()
+            }
+        );
+        assert_eq!(
+            parse(&args(&["--protocol", "udp"])).unwrap_err(),
+            Error::InvalidValue {
+                option: "--protocol".to_string(),
+                value: "udp".to_string()
+            }
+        );
+    }
+}
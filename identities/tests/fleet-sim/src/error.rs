@@ -0,0 +1,17 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Error parsing config {0}")]
+    ParsingConfig(std::io::Error),
+    #[error("Could not register the simulated entries, {0} of them failed")]
+    CreateEntries(usize),
+    #[error("Could not start the server {0}")]
+    StartServer(Box<dyn std::error::Error>),
+    #[error("Could not build the trust bundle {0}")]
+    BuildTrustBundle(trust_bundle_builder::error::Error),
+    #[error("Could not create the client of agent {0}: {1}")]
+    CreateClient(usize, Box<dyn std::error::Error + Send>),
+}
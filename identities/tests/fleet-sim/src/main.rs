@@ -0,0 +1,57 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+#![deny(rust_2018_idioms)]
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::default_trait_access,
+    clippy::let_unit_value,
+    clippy::missing_errors_doc,
+    clippy::similar_names,
+    clippy::too_many_lines
+)]
+
+mod args;
+mod error;
+mod report;
+mod server;
+mod sim;
+
+use std::process;
+
+use args::{Command, USAGE};
+
+#[tokio::main]
+async fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+
+    let options = match args::parse(&args) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let server = match server::start(&options).await {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
+
+    println!("{}", report::HEADER);
+    for fleet_size in &options.fleet_sizes {
+        match sim::run(&server, &options, *fleet_size).await {
+            Ok(run) => println!("{}", report::row(run)),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// One row per fleet size, printed as soon as its run is over. The durations are in milliseconds.
+
+use std::time::Duration;
+
+use crate::sim::FleetRun;
+
+pub const HEADER: &str =
+    "agents   requests   errors   issuance p50/p95/p99/max   propagation p50/p95/p99/max   missed";
+
+#[derive(Debug, PartialEq)]
+pub struct Percentiles {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Percentiles {
+    #[must_use]
+    pub fn new(mut samples: Vec<Duration>) -> Option<Self> {
+        samples.sort_unstable();
+        let max = *samples.last()?;
+
+        let percentile = |percent: usize| samples[(samples.len() - 1) * percent / 100];
+
+        Some(Percentiles {
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99),
+            max,
+        })
+    }
+}
+
+#[must_use]
+pub fn row(run: FleetRun) -> String {
+    let requests = run.issuance.len() + run.issuance_errors;
+
+    format!(
+        "{:<8} {:<10} {:<8} {:<26} {:<29} {}",
+        run.fleet_size,
+        requests,
+        run.issuance_errors,
+        format_percentiles(Percentiles::new(run.issuance)),
+        format_percentiles(Percentiles::new(run.propagation)),
+        run.missed
+    )
+}
+
+fn format_percentiles(percentiles: Option<Percentiles>) -> String {
+    match percentiles {
+        Some(percentiles) => format!(
+            "{}/{}/{}/{}",
+            percentiles.p50.as_millis(),
+            percentiles.p95.as_millis(),
+            percentiles.p99.as_millis(),
+            percentiles.max.as_millis()
+        ),
+        None => "-".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles() {
+        let samples = (1..=100).rev().map(Duration::from_millis).collect();
+
+        assert_eq!(
+            Percentiles::new(samples).unwrap(),
+            Percentiles {
+                p50: Duration::from_millis(50),
+                p95: Duration::from_millis(95),
+                p99: Duration::from_millis(99),
+                max: Duration::from_millis(100),
+            }
+        );
+        assert_eq!(Percentiles::new(Vec::new()), None);
+    }
+
+    #[test]
+    fn row_columns() {
+        let row = row(FleetRun {
+            fleet_size: 2,
+            issuance: vec![Duration::from_millis(3), Duration::from_millis(5)],
+            issuance_errors: 1,
+            propagation: Vec::new(),
+            missed: 2,
+        });
+
+        let columns = row.split_whitespace().collect::<Vec<_>>();
+        assert_eq!(columns, vec!["2", "3", "1", "3/3/3/5", "-", "2"]);
+    }
+}
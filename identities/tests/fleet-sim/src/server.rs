@@ -0,0 +1,199 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// In-process server with the real catalog, key manager and server API. Only the node attestation is
+// simulated: the agents send their name as attestation token.
+
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+
+use agent_config::{ServerConfig, ServerProtocol};
+use catalog::{inmemory, AgentBans, AttestedAgents, Catalog};
+use core_objects::{
+    build_selector_string, get_epoch_time, AttestationConfig, EntryNodeAttestation,
+    EntryWorkloadAttestation, NodeAttestationPlugin, NodeSelectorType, RegistrationEntry,
+    WorkloadAttestationPlugin, WorkloadSelectorType,
+};
+use identity_matcher::IdentityMatcher;
+use key_manager::KeyManager;
+use node_attestation_server::{AgentAttributes, NodeAttestation};
+use server_config::{Config, KeyStoreConfig};
+use svid_factory::SVIDFactory;
+use tokio::{task::JoinHandle, time};
+use trust_bundle_builder::TrustBundleBuilder;
+
+use crate::{args::Options, error::Error};
+
+const BASE_CONFIG_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../../iot-edge-spiffe-server/config/tests/Config.toml"
+);
+const PARENT_ENTRY_ID: &str = "fleet-sim-agents";
+// Every simulated agent runs under this service account, so one parent entry covers the fleet.
+const AGENT_SERVICE_ACCOUNT: &str = "fleet-sim";
+// The server checks the keys to rotate every 10 seconds, the simulation polls more often to time the
+// publication of the new keys closely.
+const ROTATION_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+pub struct Server {
+    pub trust_bundle_builder: Arc<TrustBundleBuilder>,
+    pub agent_server_config: ServerConfig,
+    rotation: JoinHandle<()>,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.rotation.abort();
+    }
+}
+
+struct SimNodeAttestation {
+    latency: Duration,
+}
+
+#[async_trait::async_trait]
+impl NodeAttestation for SimNodeAttestation {
+    async fn attest_agent(
+        &self,
+        token: &str,
+    ) -> Result<AgentAttributes, Box<dyn std::error::Error + Send>> {
+        time::sleep(self.latency).await;
+
+        let selectors = BTreeSet::from([
+            build_selector_string(
+                &NodeSelectorType::AgentServiceAccount,
+                AGENT_SERVICE_ACCOUNT,
+            ),
+            build_selector_string(&NodeSelectorType::AgentNodeName, token),
+        ]);
+
+        Ok(AgentAttributes { selectors })
+    }
+}
+
+#[must_use]
+pub fn workload_selectors(workload: usize) -> BTreeSet<String> {
+    BTreeSet::from([build_selector_string(
+        &WorkloadSelectorType::PodLabels,
+        format!("app:workload-{}", workload),
+    )])
+}
+
+fn entries(workloads: usize) -> Vec<RegistrationEntry> {
+    let parent = RegistrationEntry {
+        id: PARENT_ENTRY_ID.to_string(),
+        other_identities: Vec::new(),
+        spiffe_id_path: PARENT_ENTRY_ID.to_string(),
+        attestation_config: AttestationConfig::Node(EntryNodeAttestation {
+            value: vec![build_selector_string(
+                &NodeSelectorType::AgentServiceAccount,
+                AGENT_SERVICE_ACCOUNT,
+            )],
+            plugin: NodeAttestationPlugin::Psat,
+        }),
+        admin: false,
+        expires_at: 0,
+        dns_names: Vec::new(),
+        revision_number: 0,
+        store_svid: false,
+        prefetch: false,
+        ttl: None,
+        custom_claims: Default::default(),
+        federates_with: Vec::new(),
+    };
+
+    let mut entries = vec![parent];
+    entries.extend((0..workloads).map(|workload| RegistrationEntry {
+        id: format!("workload-{}", workload),
+        other_identities: Vec::new(),
+        spiffe_id_path: format!("workload-{}", workload),
+        attestation_config: AttestationConfig::Workload(EntryWorkloadAttestation {
+            parent_id: PARENT_ENTRY_ID.to_string(),
+            value: workload_selectors(workload).into_iter().collect(),
+            plugin: WorkloadAttestationPlugin::K8s,
+        }),
+        admin: false,
+        expires_at: 0,
+        dns_names: Vec::new(),
+        revision_number: 0,
+        store_svid: false,
+        prefetch: false,
+        ttl: None,
+        custom_claims: Default::default(),
+        federates_with: Vec::new(),
+    }));
+
+    entries
+}
+
+pub async fn start(options: &Options) -> Result<Server, Error> {
+    let mut config = Config::load_config(BASE_CONFIG_PATH).map_err(Error::ParsingConfig)?;
+    config.key_store = KeyStoreConfig::Memory();
+    config.jwt.key_ttl = options.key_ttl_secs;
+    config.trust_bundle.refresh_hint = options.refresh_secs;
+    config.server_agent_api.bind_address = "127.0.0.1".to_string();
+    config.server_agent_api.bind_port = options.port;
+
+    let agent_port = match options.protocol {
+        ServerProtocol::Http => options.port,
+        ServerProtocol::Grpc => {
+            config.server_agent_api.grpc_bind_port = Some(options.port + 1);
+            options.port + 1
+        }
+    };
+
+    let catalog: Arc<dyn Catalog> = Arc::new(inmemory::Catalog::new());
+    catalog
+        .batch_create(entries(options.workloads_per_agent))
+        .await
+        .map_err(|errors| Error::CreateEntries(errors.len()))?;
+
+    let key_store = Arc::new(key_store::inmemory::KeyStore::new());
+    let key_manager = KeyManager::new(&config, catalog.clone(), key_store, get_epoch_time())
+        .await
+        .map_err(|err| Error::StartServer(Box::new(err)))?;
+    let key_manager = Arc::new(key_manager);
+
+    let svid_factory = Arc::new(SVIDFactory::new(key_manager.clone(), &config));
+    let trust_bundle_builder = TrustBundleBuilder::new(&config, catalog.clone());
+    let identity_matcher = Arc::new(IdentityMatcher::new(catalog.clone()));
+    let agent_bans = Arc::new(AgentBans::new(catalog.clone()));
+    let attested_agents = Arc::new(AttestedAgents::new(catalog));
+    let node_attestation = Arc::new(SimNodeAttestation {
+        latency: Duration::from_millis(options.attestation_latency_ms),
+    });
+
+    server_api::start_server_api(
+        &config,
+        svid_factory,
+        trust_bundle_builder.clone(),
+        node_attestation,
+        identity_matcher,
+        agent_bans,
+        attested_agents,
+        None,
+        Arc::new(server_api::metrics::Metrics::default()),
+    )
+    .await
+    .map_err(|err| Error::StartServer(Box::new(err)))?;
+
+    let rotation = tokio::spawn(async move {
+        let mut interval = time::interval(ROTATION_POLL_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            if let Err(err) = key_manager.rotate_periodic().await {
+                eprintln!("Could not rotate the signing keys: {}", err);
+            }
+        }
+    });
+
+    Ok(Server {
+        trust_bundle_builder,
+        agent_server_config: ServerConfig {
+            address: "127.0.0.1".to_string(),
+            port: agent_port,
+            protocol: options.protocol,
+            tls: None,
+        },
+        rotation,
+    })
+}
@@ -0,0 +1,232 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Simulated agents. Each one requests the JWT-SVIDs of the workloads on its node, as the workload API
+// does for the workloads, then follows the trust bundle of the server until it sees the next signing key.
+
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+
+use agent_config::ServerProtocol;
+use core_objects::TrustBundle;
+use futures_util::{future, StreamExt};
+use server_agent_api::{create_workload_jwts, get_trust_bundle};
+use spiffe_server_client::{Client, ServerClientFactory};
+use tokio::time::{self, Instant};
+use trust_bundle_builder::TrustBundleBuilder;
+
+use crate::{
+    args::Options,
+    error::Error,
+    server::{workload_selectors, Server},
+};
+
+const AUDIENCE: &str = "fleet-sim";
+// Publication of the new keys by the server, the reference of the propagation times.
+const PUBLICATION_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+pub struct FleetRun {
+    pub fleet_size: usize,
+    // Latencies of the requests that got their JWT-SVIDs.
+    pub issuance: Vec<Duration>,
+    pub issuance_errors: usize,
+    // Time between the publication of the next key and each agent seeing it.
+    pub propagation: Vec<Duration>,
+    // Agents that did not see the next key within a key lifetime.
+    pub missed: usize,
+}
+
+pub async fn run(server: &Server, options: &Options, fleet_size: usize) -> Result<FleetRun, Error> {
+    let clients = (0..fleet_size)
+        .map(|agent| {
+            ServerClientFactory::get(&server.agent_server_config)
+                .map_err(|err| Error::CreateClient(agent, err))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // The whole fleet asks at once, as after a restart of the cluster.
+    let issuance = clients.iter().enumerate().map(|(agent, client)| {
+        tokio::spawn(issue(
+            client.clone(),
+            agent,
+            options.requests_per_agent,
+            options.workloads_per_agent,
+        ))
+    });
+
+    let mut run = FleetRun {
+        fleet_size,
+        issuance: Vec::new(),
+        issuance_errors: 0,
+        propagation: Vec::new(),
+        missed: 0,
+    };
+    for result in future::join_all(issuance).await {
+        match result {
+            Ok((latencies, errors)) => {
+                run.issuance.extend(latencies);
+                run.issuance_errors += errors;
+            }
+            Err(_) => run.issuance_errors += options.requests_per_agent,
+        }
+    }
+
+    let known_keys = key_ids(
+        &server
+            .trust_bundle_builder
+            .build_trust_bundle(true, false)
+            .await
+            .map_err(Error::BuildTrustBundle)?,
+    );
+    let deadline = Duration::from_secs(options.key_ttl_secs);
+
+    let publication = tokio::spawn(wait_for_publication(
+        server.trust_bundle_builder.clone(),
+        known_keys.clone(),
+    ));
+    let propagation = clients.iter().enumerate().map(|(agent, client)| {
+        let follow = follow(
+            client.clone(),
+            agent,
+            fleet_size,
+            known_keys.clone(),
+            options.protocol,
+            Duration::from_secs(options.refresh_secs),
+        );
+        tokio::spawn(time::timeout(deadline, follow))
+    });
+    let seen = future::join_all(propagation).await;
+
+    // Without a publication within the deadline, every agent missed it.
+    let published = match time::timeout(deadline, publication).await {
+        Ok(Ok(published)) => published,
+        _ => {
+            run.missed = fleet_size;
+            return Ok(run);
+        }
+    };
+    for seen in seen {
+        match seen {
+            Ok(Ok(seen)) => run
+                .propagation
+                .push(seen.saturating_duration_since(published)),
+            _ => run.missed += 1,
+        }
+    }
+
+    Ok(run)
+}
+
+async fn issue(
+    client: Arc<dyn Client>,
+    agent: usize,
+    requests: usize,
+    workloads: usize,
+) -> (Vec<Duration>, usize) {
+    let mut latencies = Vec::new();
+    let mut errors = 0;
+
+    for request in 0..requests {
+        let request = create_workload_jwts::Request {
+            attestation_token: format!("agent-{}", agent),
+            workload_spiffe_id: None,
+            audiences: vec![AUDIENCE.to_string()],
+            selectors: workload_selectors((agent + request) % workloads),
+            pod_uid: None,
+            agent_build: None,
+            prefetch_only: false,
+            bundles_only: false,
+        };
+
+        let started = Instant::now();
+        match client.create_workload_jwts(request).await {
+            Ok(response) if !response.jwt_svids.is_empty() => latencies.push(started.elapsed()),
+            _ => errors += 1,
+        }
+    }
+
+    (latencies, errors)
+}
+
+async fn wait_for_publication(
+    trust_bundle_builder: Arc<TrustBundleBuilder>,
+    known_keys: BTreeSet<String>,
+) -> Instant {
+    let mut interval = time::interval(PUBLICATION_POLL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        if let Ok(trust_bundle) = trust_bundle_builder.build_trust_bundle(true, false).await {
+            if has_new_key(&trust_bundle, &known_keys) {
+                return Instant::now();
+            }
+        }
+    }
+}
+
+// Returns when the agent first sees a key that was not in the trust bundle when the run started.
+async fn follow(
+    client: Arc<dyn Client>,
+    agent: usize,
+    fleet_size: usize,
+    known_keys: BTreeSet<String>,
+    protocol: ServerProtocol,
+    refresh: Duration,
+) -> Instant {
+    match protocol {
+        ServerProtocol::Http => {
+            // The agents of a fleet are not started together, their refreshes are spread over the period.
+            let offset = refresh * u32::try_from(agent).unwrap_or(u32::MAX)
+                / u32::try_from(fleet_size).unwrap_or(u32::MAX);
+            time::sleep(offset).await;
+            let mut interval = time::interval(refresh);
+
+            loop {
+                interval.tick().await;
+                if let Ok(response) = client.get_trust_bundle(params()).await {
+                    if has_new_key(&response.trust_bundle, &known_keys) {
+                        return Instant::now();
+                    }
+                }
+            }
+        }
+        ServerProtocol::Grpc => loop {
+            if let Ok(mut updates) = client.watch_trust_bundle(params()).await {
+                while let Some(update) = updates.next().await {
+                    match update {
+                        Ok(response) if has_new_key(&response.trust_bundle, &known_keys) => {
+                            return Instant::now();
+                        }
+                        Ok(_) => (),
+                        Err(_) => break,
+                    }
+                }
+            }
+
+            // The watch is reopened like the agent does.
+            time::sleep(refresh).await;
+        },
+    }
+}
+
+fn params() -> get_trust_bundle::Params {
+    get_trust_bundle::Params {
+        jwt_keys: true,
+        x509_cas: false,
+    }
+}
+
+fn key_ids(trust_bundle: &TrustBundle) -> BTreeSet<String> {
+    trust_bundle
+        .jwt_key_set
+        .keys
+        .iter()
+        .map(|key| key.kid.clone())
+        .collect()
+}
+
+fn has_new_key(trust_bundle: &TrustBundle, known_keys: &BTreeSet<String>) -> bool {
+    trust_bundle
+        .jwt_key_set
+        .keys
+        .iter()
+        .any(|key| !known_keys.contains(&key.kid))
+}
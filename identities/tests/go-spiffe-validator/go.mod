@@ -0,0 +1,5 @@
+module github.com/azure/e4k/identities/tests/go-spiffe-validator
+
+go 1.17
+
+require github.com/spiffe/go-spiffe/v2 v2.1.0
@@ -0,0 +1,84 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Small wrapper around the official go-spiffe library used by the interop tests.
+// It validates JWT-SVIDs and trust bundles produced by the IoTEdge SPIFFE server
+// so that any JOSE incompatibility is caught before release.
+//
+// Usage:
+//   go-spiffe-validator bundle  -trust-domain <td> -bundle <path>
+//   go-spiffe-validator jwtsvid -trust-domain <td> -bundle <path> -token <path> -audience <aud>
+//
+// On success the program exits with 0. For jwtsvid, the SPIFFE ID of the token is printed on stdout.
+package main
+
+import (
+	"flag"
+	"fmt"
+	"io/ioutil"
+	"os"
+	"strings"
+
+	"github.com/spiffe/go-spiffe/v2/bundle/jwtbundle"
+	"github.com/spiffe/go-spiffe/v2/spiffeid"
+	"github.com/spiffe/go-spiffe/v2/svid/jwtsvid"
+)
+
+func main() {
+	if len(os.Args) < 2 {
+		fail("expected 'bundle' or 'jwtsvid' subcommand")
+	}
+
+	flags := flag.NewFlagSet(os.Args[1], flag.ExitOnError)
+	trustDomain := flags.String("trust-domain", "", "trust domain of the bundle")
+	bundlePath := flags.String("bundle", "", "path to the JWKS trust bundle")
+	tokenPath := flags.String("token", "", "path to the JWT-SVID")
+	audience := flags.String("audience", "", "expected audience")
+	if err := flags.Parse(os.Args[2:]); err != nil {
+		fail(err.Error())
+	}
+
+	bundle := loadBundle(*trustDomain, *bundlePath)
+
+	switch os.Args[1] {
+	case "bundle":
+		fmt.Printf("%d\n", len(bundle.JWTAuthorities()))
+	case "jwtsvid":
+		token, err := ioutil.ReadFile(*tokenPath)
+		if err != nil {
+			fail(fmt.Sprintf("cannot read token: %v", err))
+		}
+
+		svid, err := jwtsvid.ParseAndValidate(strings.TrimSpace(string(token)), bundle, []string{*audience})
+		if err != nil {
+			fail(fmt.Sprintf("cannot validate JWT-SVID: %v", err))
+		}
+
+		fmt.Println(svid.ID.String())
+	default:
+		fail(fmt.Sprintf("unknown subcommand %s", os.Args[1]))
+	}
+}
+
+func loadBundle(trustDomain string, bundlePath string) *jwtbundle.Bundle {
+	td, err := spiffeid.TrustDomainFromString(trustDomain)
+	if err != nil {
+		fail(fmt.Sprintf("invalid trust domain: %v", err))
+	}
+
+	raw, err := ioutil.ReadFile(bundlePath)
+	if err != nil {
+		fail(fmt.Sprintf("cannot read bundle: %v", err))
+	}
+
+	bundle, err := jwtbundle.Parse(td, raw)
+	if err != nil {
+		fail(fmt.Sprintf("cannot parse bundle: %v", err))
+	}
+
+	return bundle
+}
+
+func fail(message string) {
+	fmt.Fprintln(os.Stderr, message)
+	os.Exit(1)
+}
@@ -0,0 +1,160 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Interop tests: JWT-SVIDs and trust bundles issued by the server are validated with the official
+// go-spiffe library (see tests/go-spiffe-validator). SPIRE agents validate tokens with the same library,
+// so a failure here means a workload using go-spiffe or spire-agent would reject our tokens.
+// The validator binary path is read from the GO_SPIFFE_VALIDATOR env var. Build the fixture with:
+// docker build -f ./identities/tests/go-spiffe-validator/Dockerfile . -t local/interop-tests
+
+#[cfg(test)]
+mod tests {
+    use catalog::inmemory;
+    use core_objects::{CONFIG_DEFAULT_PATH, SPIFFE_ID_PREFIX};
+    use federation::bundle::SpiffeBundle;
+    use key_manager::KeyManager;
+    use key_store::disk;
+    use server_config::{Config, KeyStoreConfig, KeyStoreConfigDisk};
+    use std::{fs, path::Path, process::Command, sync::Arc};
+    use svid_factory::{JWTSVIDParams, SVIDFactory};
+    use trust_bundle_builder::TrustBundleBuilder;
+
+    const GO_SPIFFE_VALIDATOR_ENV: &str = "GO_SPIFFE_VALIDATOR";
+    const GO_SPIFFE_VALIDATOR_DEFAULT: &str = "go-spiffe-validator";
+    const AUDIENCE: &str = "spiffe://iotedge/interop-audience";
+    const SPIFFE_ID_PATH: &str = "interop/workload";
+
+    struct Fixture {
+        svid_factory: SVIDFactory,
+        trust_bundle_builder: Arc<TrustBundleBuilder>,
+        config: Config,
+    }
+
+    async fn init(dir: &tempfile::TempDir) -> Fixture {
+        let mut config = Config::load_config(CONFIG_DEFAULT_PATH).unwrap();
+        let key_base_path = dir.path().to_str().unwrap().to_string();
+        let key_plugin = KeyStoreConfigDisk {
+            key_base_path,
+            encryption: None,
+        };
+
+        // Change key disk plugin path to write in tempdir
+        config.key_store = KeyStoreConfig::Disk(key_plugin.clone());
+
+        let catalog = Arc::new(inmemory::Catalog::new());
+        let key_store = Arc::new(disk::KeyStore::new(&key_plugin).unwrap());
+
+        let key_manager = Arc::new(
+            KeyManager::new(
+                &config,
+                catalog.clone(),
+                key_store,
+                core_objects::get_epoch_time(),
+            )
+            .await
+            .unwrap(),
+        );
+
+        Fixture {
+            svid_factory: SVIDFactory::new(key_manager, &config),
+            trust_bundle_builder: TrustBundleBuilder::new(&config, catalog),
+            config,
+        }
+    }
+
+    async fn write_bundle(fixture: &Fixture, dir: &Path) -> String {
+        let trust_bundle = fixture
+            .trust_bundle_builder
+            .build_trust_bundle(true, false)
+            .await
+            .unwrap();
+
+        // As served by the bundle endpoint, in the SPIFFE bundle format.
+        let bundle_path = dir.join("bundle.json");
+        fs::write(
+            &bundle_path,
+            serde_json::to_vec(&SpiffeBundle::from(&trust_bundle)).unwrap(),
+        )
+        .unwrap();
+
+        bundle_path.to_str().unwrap().to_string()
+    }
+
+    fn run_validator(args: &[&str]) -> String {
+        let validator = std::env::var(GO_SPIFFE_VALIDATOR_ENV)
+            .unwrap_or_else(|_| GO_SPIFFE_VALIDATOR_DEFAULT.to_string());
+
+        let output = Command::new(&validator)
+            .args(args)
+            .output()
+            .unwrap_or_else(|err| panic!("Could not run {}: {}", validator, err));
+
+        assert!(
+            output.status.success(),
+            "go-spiffe rejected the input: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    #[tokio::test]
+    async fn go_spiffe_parses_trust_bundle() {
+        let tmp = tempfile::tempdir().unwrap();
+        let fixture = init(&tmp).await;
+
+        let bundle_path = write_bundle(&fixture, tmp.path()).await;
+
+        let key_count = run_validator(&[
+            "bundle",
+            "-trust-domain",
+            &fixture.config.trust_domain,
+            "-bundle",
+            &bundle_path,
+        ]);
+
+        assert_eq!(key_count, "1");
+    }
+
+    #[tokio::test]
+    async fn go_spiffe_validates_jwt_svid() {
+        let tmp = tempfile::tempdir().unwrap();
+        let fixture = init(&tmp).await;
+
+        let jwt_svid = fixture
+            .svid_factory
+            .create_jwt_svid(JWTSVIDParams {
+                spiffe_id_path: SPIFFE_ID_PATH.to_string(),
+                audiences: vec![AUDIENCE.to_string()],
+                other_identities: Vec::new(),
+                pod_uid: None,
+                ttl: None,
+                custom_claims: Default::default(),
+            })
+            .await
+            .unwrap();
+
+        let bundle_path = write_bundle(&fixture, tmp.path()).await;
+        let token_path = tmp.path().join("token");
+        fs::write(&token_path, &jwt_svid.token).unwrap();
+
+        let spiffe_id = run_validator(&[
+            "jwtsvid",
+            "-trust-domain",
+            &fixture.config.trust_domain,
+            "-bundle",
+            &bundle_path,
+            "-token",
+            token_path.to_str().unwrap(),
+            "-audience",
+            AUDIENCE,
+        ]);
+
+        assert_eq!(
+            spiffe_id,
+            format!(
+                "{}{}/{}",
+                SPIFFE_ID_PREFIX, fixture.config.trust_domain, SPIFFE_ID_PATH
+            )
+        );
+    }
+}
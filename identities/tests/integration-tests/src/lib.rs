@@ -11,4 +11,8 @@
     clippy::too_many_lines
 )]
 
+#[cfg(feature = "interop")]
+mod go_spiffe_interop;
+#[cfg(feature = "compat")]
+mod rolling_upgrade_compat;
 mod spiffe_server_admin_api;
@@ -0,0 +1,152 @@
+// Copyright (c) Microsoft. All rights reserved.
+
+// Rolling upgrade compatibility tests: during a staged upgrade an edge fleet runs agents and servers of
+// two consecutive releases side by side. These tests run against a deployed agent and server, one of them
+// from the previous release, see tests/rolling-upgrade/run-matrix.sh which deploys both mixed-version pairs.
+// They exercise what an upgrade must not break: node attestation of the agent, JWT-SVID issuance and the
+// trust bundle fetch, and check the current code can validate what the other release produced.
+//
+// The deployment is read from the env vars below. The workload running the tests must have a registration
+// entry issuing it a JWT-SVID for COMPAT_AUDIENCE.
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use agent_config::{ServerConfig, ServerProtocol};
+    use core_objects::{JWKSet, TrustBundle};
+    use jwt_svid_validator::{validate, JWTSVIDValidator};
+    use server_agent_api::get_trust_bundle;
+    use spiffe_server_client::Client;
+    use tokio::net::UnixStream;
+    use tonic::transport::{Channel, Endpoint, Uri};
+    use tower::service_fn;
+    use workload_api::generated::{
+        spiffe_workload_api_client::SpiffeWorkloadApiClient, JwtBundlesRequest, JwtsvidRequest,
+        ValidateJwtsvidRequest,
+    };
+
+    const SERVER_ADDRESS_ENV: &str = "COMPAT_SERVER_ADDRESS";
+    const SERVER_ADDRESS_DEFAULT: &str = "localhost";
+    const SERVER_PORT_ENV: &str = "COMPAT_SERVER_PORT";
+    const SERVER_PORT_DEFAULT: u16 = 8443;
+    const WORKLOAD_API_SOCKET_ENV: &str = "COMPAT_WORKLOAD_API_SOCKET";
+    const WORKLOAD_API_SOCKET_DEFAULT: &str = "/run/iotedge/sockets/workloadapi.sock";
+    const AUDIENCE_ENV: &str = "COMPAT_AUDIENCE";
+    const AUDIENCE_DEFAULT: &str = "spiffe://iotedge/mqttbroker";
+
+    fn env_or(name: &str, default: &str) -> String {
+        std::env::var(name).unwrap_or_else(|_| default.to_string())
+    }
+
+    async fn server_trust_bundle() -> TrustBundle {
+        let server_config = ServerConfig {
+            address: env_or(SERVER_ADDRESS_ENV, SERVER_ADDRESS_DEFAULT),
+            port: std::env::var(SERVER_PORT_ENV)
+                .map(|port| port.parse().unwrap())
+                .unwrap_or(SERVER_PORT_DEFAULT),
+            protocol: ServerProtocol::Http,
+            tls: None,
+        };
+        let client = spiffe_server_client::http::Client::new(&server_config).unwrap();
+
+        let params = get_trust_bundle::Params {
+            jwt_keys: true,
+            x509_cas: false,
+        };
+
+        client.get_trust_bundle(params).await.unwrap().trust_bundle
+    }
+
+    async fn workload_api_client() -> SpiffeWorkloadApiClient<Channel> {
+        let socket_path = env_or(WORKLOAD_API_SOCKET_ENV, WORKLOAD_API_SOCKET_DEFAULT);
+
+        // The uri is ignored, the connector always connects to the agent socket.
+        let channel = Endpoint::try_from("http://[::]:50051")
+            .unwrap()
+            .connect_with_connector(service_fn(move |_: Uri| {
+                UnixStream::connect(socket_path.clone())
+            }))
+            .await
+            .unwrap();
+
+        SpiffeWorkloadApiClient::new(channel)
+    }
+
+    // Current agent code fetching the trust bundle of the deployed server.
+    #[tokio::test]
+    async fn server_trust_bundle_is_readable() {
+        let trust_bundle = server_trust_bundle().await;
+
+        assert!(!trust_bundle.trust_domain.is_empty());
+        assert!(!trust_bundle.jwt_key_set.keys.is_empty());
+    }
+
+    // The agent attests to the server and relays the bundles it got from it.
+    #[tokio::test]
+    async fn agent_relays_server_trust_bundle() {
+        let server_trust_bundle = server_trust_bundle().await;
+        let mut client = workload_api_client().await;
+
+        let mut response = client
+            .fetch_jwt_bundles(workload_api::request(JwtBundlesRequest::default()))
+            .await
+            .unwrap();
+        let bundles = response.get_mut().message().await.unwrap().unwrap().bundles;
+
+        let jwk_set = bundles
+            .get(&server_trust_bundle.trust_domain)
+            .expect("agent did not return the server trust domain");
+        let jwk_set: JWKSet = serde_json::from_slice(jwk_set).unwrap();
+
+        for jwk in &server_trust_bundle.jwt_key_set.keys {
+            assert!(
+                jwk_set.keys.iter().any(|key| key.kid == jwk.kid),
+                "key {} of the server is missing from the agent bundle",
+                jwk.kid
+            );
+        }
+    }
+
+    // JWT-SVIDs issued through the agent are accepted by the agent and by the current validator.
+    #[tokio::test]
+    async fn agent_issues_valid_jwt_svid() {
+        let audience = env_or(AUDIENCE_ENV, AUDIENCE_DEFAULT);
+        let mut client = workload_api_client().await;
+
+        let request = JwtsvidRequest {
+            audience: vec![audience.clone()],
+            spiffe_id: String::new(),
+        };
+        let svids = client
+            .fetch_jwtsvid(workload_api::request(request))
+            .await
+            .unwrap()
+            .into_inner()
+            .svids;
+        assert!(!svids.is_empty(), "no JWT-SVID issued for {}", audience);
+
+        let trust_bundle = server_trust_bundle().await;
+        let trust_bundles = BTreeMap::from([(trust_bundle.trust_domain.clone(), trust_bundle)]);
+        let validator = validate::JWTSVIDValidator::default();
+
+        for svid in svids {
+            let request = ValidateJwtsvidRequest {
+                audience: audience.clone(),
+                svid: svid.svid.clone(),
+            };
+            let response = client
+                .validate_jwtsvid(workload_api::request(request))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(response.spiffe_id, svid.spiffe_id);
+
+            let jwt_svid = validator
+                .validate(&svid.svid, &trust_bundles, &audience)
+                .await
+                .unwrap();
+            assert_eq!(jwt_svid.claims.subject, svid.spiffe_id);
+        }
+    }
+}
@@ -32,18 +32,13 @@ mod tests {
         let identities_to_create = modules_to_create
             .iter()
             .map(|id| RegistrationEntry {
-                id: id.clone(),
-                other_identities: Vec::new(),
                 spiffe_id_path: "path".to_string(),
                 attestation_config: AttestationConfig::Node(EntryNodeAttestation {
                     value: Vec::new(),
                     plugin: NodeAttestationPlugin::Psat,
                 }),
-                admin: false,
                 expires_at: 1028,
-                dns_names: Vec::new(),
-                revision_number: 0,
-                store_svid: false,
+                ..RegistrationEntry::test_entry(id)
             })
             .collect();
 
@@ -89,18 +84,13 @@ mod tests {
         let identities_to_create = modules_to_create
             .iter()
             .map(|id| RegistrationEntry {
-                id: id.clone(),
-                other_identities: Vec::new(),
                 spiffe_id_path: "path".to_string(),
                 attestation_config: AttestationConfig::Node(EntryNodeAttestation {
                     value: Vec::new(),
                     plugin: NodeAttestationPlugin::Psat,
                 }),
-                admin: false,
                 expires_at: 1028,
-                dns_names: Vec::new(),
-                revision_number: 0,
-                store_svid: false,
+                ..RegistrationEntry::test_entry(id)
             })
             .collect();
         client
@@ -143,8 +133,31 @@ mod tests {
                 config.socket_path = socket;
 
                 let catalog = Arc::new(catalog::inmemory::Catalog::new());
-
-                admin_api::start_admin_api(&config, catalog).await.unwrap();
+                let entry_pruner = Arc::new(catalog::EntryPruner::new(catalog.clone()));
+                let agent_bans = Arc::new(catalog::AgentBans::new(catalog.clone()));
+                let key_store = Arc::new(key_store::inmemory::KeyStore::new());
+                let key_manager = key_manager::KeyManager::new(
+                    &config,
+                    catalog.clone(),
+                    key_store,
+                    core_objects::get_epoch_time(),
+                )
+                .await
+                .unwrap();
+
+                admin_api::start_admin_api(
+                    &config,
+                    catalog,
+                    entry_pruner,
+                    agent_bans,
+                    Arc::new(key_manager),
+                    None,
+                    None,
+                    None,
+                    Default::default(),
+                )
+                .await
+                .unwrap();
             }
         });
         sleep(Duration::from_millis(10)).await;
@@ -14,17 +14,22 @@
 )]
 
 use core::fmt::Debug;
+use futures_util::{stream, Stream};
 use http::Request;
 use k8s_openapi::{
     api::{
-        authentication::v1::{TokenReview, TokenReviewSpec, TokenReviewStatus, UserInfo},
+        authentication::v1::{
+            TokenRequest, TokenRequestStatus, TokenReview, TokenReviewSpec, TokenReviewStatus,
+            UserInfo,
+        },
         core::v1::{Container, ContainerStatus, Node, Pod, PodSpec, PodStatus},
     },
-    apimachinery::pkg::apis::meta::v1::OwnerReference,
+    apimachinery::pkg::apis::meta::v1::{OwnerReference, Time},
 };
 use kube::{
-    api::ListParams,
+    api::{ListParams, Patch, PatchParams},
     core::{ObjectList, ObjectMeta},
+    runtime::watcher::{self, Event},
     Error, Resource,
 };
 use serde::{de::DeserializeOwned, Serialize};
@@ -119,6 +124,24 @@ where
         let req = Request::default();
         self.client.request::<ObjectList<K>>(req).await
     }
+
+    pub async fn patch<P: Serialize + Debug>(
+        &self,
+        _name: &str,
+        _patch_params: &PatchParams,
+        _patch: &Patch<P>,
+    ) -> Result<K, Error> {
+        let req = Request::default();
+        self.client.request::<K>(req).await
+    }
+}
+
+// Watches are not mocked, the stream never returns any event.
+pub fn watcher<K: Send>(
+    _api: Api<K>,
+    _list_params: ListParams,
+) -> impl Stream<Item = Result<Event<K>, watcher::Error>> + Send {
+    stream::pending()
 }
 
 pub fn get_token_review_status() -> TokenReviewStatus {
@@ -157,6 +180,16 @@ pub fn get_token_review() -> TokenReview {
     token_review
 }
 
+pub fn get_token_request(token: &str) -> TokenRequest {
+    TokenRequest {
+        status: Some(TokenRequestStatus {
+            expiration_timestamp: Time(k8s_openapi::chrono::Utc::now()),
+            token: token.to_string(),
+        }),
+        ..Default::default()
+    }
+}
+
 pub fn get_pods() -> Pod {
     let mut pod = Pod::default();
     let mut pod_status = PodStatus::default();
@@ -0,0 +1,46 @@
+#!/bin/bash
+
+# Runs the rolling upgrade compatibility tests for both mixed-version pairs:
+# current agent with the previous server, then previous agent with the current server.
+#
+# Expects the server and agent of k8s-deployments to be deployed in the current kubectl context,
+# with the registration entries of the test workload, and to be run on a cluster node.
+#
+# REGISTRY      registry hosting the identity/server and identity/agent images
+# PREVIOUS_TAG  image tag of the previous release
+# CURRENT_TAG   image tag of the build under test
+
+set -euo pipefail
+
+: "${REGISTRY:?}"
+: "${PREVIOUS_TAG:?}"
+: "${CURRENT_TAG:?}"
+
+cd "$(dirname "$0")/../.."
+
+deploy() {
+    local kind="$1" name="$2" image="$3"
+
+    # The dev deployments run a shell, the release images have their own entrypoint.
+    kubectl patch "$kind" "$name" --type strategic --patch "{
+        \"spec\": {\"template\": {\"spec\": {\"containers\": [
+            {\"name\": \"$name\", \"image\": \"$image\", \"command\": null, \"args\": null}
+        ]}}}
+    }"
+    kubectl rollout status "$kind" "$name" --timeout 300s
+}
+
+run() {
+    local agent_tag="$1" server_tag="$2"
+
+    echo "Agent $agent_tag, server $server_tag"
+
+    deploy deployment iotedge-spiffe-server "$REGISTRY/identity/server:$server_tag"
+    deploy daemonset iotedge-spiffe-agent "$REGISTRY/identity/agent:$agent_tag"
+
+    COMPAT_SERVER_PORT="$(kubectl get service iotedge-spiffe-server -o jsonpath='{.spec.ports[0].nodePort}')" \
+        cargo test -p integration-tests --features compat -- --test-threads 1
+}
+
+run "$CURRENT_TAG" "$PREVIOUS_TAG"
+run "$PREVIOUS_TAG" "$CURRENT_TAG"
@@ -44,7 +44,10 @@ async fn main() {
 
     // Fetch trust bundle test
     let request = JwtBundlesRequest::default();
-    let mut response = client.fetch_jwt_bundles(request).await.unwrap();
+    let mut response = client
+        .fetch_jwt_bundles(workload_api::request(request))
+        .await
+        .unwrap();
     let trust_bundle = response.get_mut().message().await.unwrap().unwrap();
 
     for (trust_domain, jwk_set) in trust_bundle.bundles {
@@ -60,7 +63,10 @@ async fn main() {
         audience: vec!["spiffe://iotedge/mqttbroker".to_string()],
         spiffe_id: String::new(),
     };
-    let response = client.fetch_jwtsvid(request).await.unwrap();
+    let response = client
+        .fetch_jwtsvid(workload_api::request(request))
+        .await
+        .unwrap();
     let svids = response.into_inner().svids;
     info!("Got svids {:?}", svids);
 
@@ -69,7 +75,10 @@ async fn main() {
         audience: "spiffe://iotedge/mqttbroker".to_string(),
         svid: svids[0].svid.clone(),
     };
-    let response = client.validate_jwtsvid(request).await.unwrap();
+    let response = client
+        .validate_jwtsvid(workload_api::request(request))
+        .await
+        .unwrap();
     let claims = response.into_inner();
     info!("Got claims {:?}", claims);
 